@@ -0,0 +1,34 @@
+//! Container configs are written by anyone allowed to edit a container, and by hand. Parsing one,
+//! and reading the values the checks look at, must not panic whatever it holds.
+
+#![no_main]
+
+use std::str::FromStr;
+
+use libfuzzer_sys::fuzz_target;
+use pupman::lxc::config::{Config, MountPoint};
+use pupman::lxc::idmap::IdMap;
+use pupman::lxc::rootfs_volume;
+
+fuzz_target!(|content: &str| {
+    let Ok(config) = Config::from_str(content) else {
+        return;
+    };
+    let _ = config.to_string();
+    let _ = config.duplicate_keys();
+    let _ = config.key_lines(None);
+
+    let section = config.section(None);
+
+    if let Some(rootfs) = section.get_rootfs() {
+        let _ = rootfs_volume(rootfs);
+    }
+
+    for (key, value) in section.key_values() {
+        let _ = MountPoint::parse(key, value);
+    }
+
+    for idmap in section.get_lxc_idmaps() {
+        let _ = IdMap::from_str(idmap);
+    }
+});
@@ -0,0 +1,15 @@
+//! `lxc.idmap` values, which must either be rejected or round-trip through their display form.
+
+#![no_main]
+
+use std::str::FromStr;
+
+use libfuzzer_sys::fuzz_target;
+use pupman::lxc::idmap::IdMap;
+
+fuzz_target!(|value: &str| {
+    if let Ok(idmap) = IdMap::from_str(value) {
+        assert_eq!(IdMap::from_str(&idmap.to_string()), Ok(idmap));
+        assert!(idmap.container_end() > u64::from(idmap.container_id));
+    }
+});
@@ -0,0 +1,13 @@
+//! /etc/subuid and /etc/subgid, which are edited by hand as often as through usermod.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pupman::app::parse_subid_map;
+use pupman::fs::subid::{comment_lines, normalize};
+
+fuzz_target!(|content: &str| {
+    let _ = parse_subid_map(content);
+    let _ = comment_lines(content);
+    let _ = normalize(content);
+});
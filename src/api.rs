@@ -0,0 +1,344 @@
+//! The stable library surface of pupman, for tools which consume its findings without the TUI.
+//!
+//! Everything else the crate exports is shared with the binary and changes whenever the app needs
+//! it to. This module is the part with semver guarantees: its items only change incompatibly in a
+//! release which bumps the minor version while pupman is at 0.x, and the major version after. To
+//! leave room to grow without such a release, every struct and enum here is `#[non_exhaustive]`:
+//! new fields, severities and id kinds may be added at any time, so match with a wildcard arm and
+//! don't construct these types yourself. Checks are identified by their string ids, such as
+//! `idmap-present`, which are kept when checks are reworked since settings refer to them too.
+//!
+//! ```no_run
+//! let report = pupman::api::evaluate()?;
+//!
+//! for finding in report.findings.iter().filter(|f| f.severity.is_problem()) {
+//!     println!("{}: {}", finding.check, finding.message);
+//! }
+//! # Ok::<(), pupman::api::Error>(())
+//! ```
+
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::app::state::State;
+use crate::finding::{self, FindingKind};
+use crate::fs::subid::SubID;
+use crate::metadata::Metadata;
+use crate::settings::Settings;
+
+pub use crate::health::{Health, HealthStatus};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The host's files couldn't be located, with why.
+    #[error("couldn't inspect the host: {0}")]
+    Host(String),
+}
+
+/// How much a finding matters, from fine to broken.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
+pub enum Severity {
+    Good,
+    /// Worth knowing about, but nothing is wrong.
+    Info,
+    /// Suspicious but still works.
+    Warning,
+    /// Breaks the container, which won't start or can't use its files until it is fixed.
+    Bad,
+}
+
+impl Severity {
+    /// Whether something is wrong, rather than fine or only worth knowing about.
+    pub fn is_problem(self) -> bool {
+        matches!(self, Severity::Warning | Severity::Bad)
+    }
+}
+
+impl From<FindingKind> for Severity {
+    fn from(kind: FindingKind) -> Self {
+        match kind {
+            FindingKind::Good => Severity::Good,
+            FindingKind::Info => Severity::Info,
+            FindingKind::Warning => Severity::Warning,
+            FindingKind::Bad => Severity::Bad,
+        }
+    }
+}
+
+/// Which ids a range is made of.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum IdKind {
+    Uid,
+    Gid,
+}
+
+impl From<SubID> for IdKind {
+    fn from(sub_id: SubID) -> Self {
+        match sub_id {
+            SubID::UID => IdKind::Uid,
+            SubID::GID => IdKind::Gid,
+        }
+    }
+}
+
+/// A run of `count` consecutive ids starting at `start`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub struct IdRange {
+    pub start: u32,
+    pub count: u32,
+}
+
+impl IdRange {
+    pub fn new(start: u32, count: u32) -> Self {
+        IdRange { start, count }
+    }
+
+    /// One past the last id of the range, which may not fit in 32 bits.
+    pub fn end(&self) -> u64 {
+        crate::lxc::range_end(self.start, self.count)
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        id >= self.start && u64::from(id) < self.end()
+    }
+
+    pub fn overlaps(&self, other: &IdRange) -> bool {
+        u64::from(self.start) < other.end() && u64::from(other.start) < self.end()
+    }
+}
+
+/// An /etc/subuid or /etc/subgid entry, granting `user` a range of subordinate ids.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct HostRange {
+    pub user: String,
+    pub range: IdRange,
+    /// 1-based, counting blank lines too.
+    pub line: usize,
+}
+
+/// The subordinate id ranges of the host, in file order.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct HostMapping {
+    pub subuid: Vec<HostRange>,
+    pub subgid: Vec<HostRange>,
+}
+
+impl HostMapping {
+    pub fn ranges(&self, kind: IdKind) -> &[HostRange] {
+        match kind {
+            IdKind::Uid => &self.subuid,
+            IdKind::Gid => &self.subgid,
+        }
+    }
+}
+
+/// A line of a container config a finding is about.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ConfigLine {
+    /// The config's file name, such as `100.conf`.
+    pub container: String,
+    pub key: String,
+    /// 1-based, as shown by editors.
+    pub line: usize,
+}
+
+/// A line of /etc/subuid or /etc/subgid a finding is about, which isn't a valid entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct SubidLine {
+    /// `/etc/subuid` or `/etc/subgid`.
+    pub file: &'static str,
+    /// 1-based, as shown by editors.
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Finding {
+    /// Identifies the same finding across runs, for tracking when it first showed up.
+    pub id: String,
+    pub severity: Severity,
+    /// The id of the check which produced this finding, such as `idmap-present`.
+    pub check: &'static str,
+    pub message: String,
+    /// The file names of the container configs involved, sorted.
+    pub containers: Vec<String>,
+    /// The users whose subordinate id entries are involved, sorted.
+    pub host_users: Vec<String>,
+    /// The root filesystems involved.
+    pub rootfs: Vec<String>,
+    pub config_lines: Vec<ConfigLine>,
+    pub subid_lines: Vec<SubidLine>,
+    /// What pupman would change to fix this, if it knows.
+    pub fix: Option<String>,
+}
+
+impl From<&finding::Finding> for Finding {
+    fn from(finding: &finding::Finding) -> Self {
+        let mut containers: Vec<String> = finding
+            .lxc_config_mapping_highlights
+            .iter()
+            .map(|(filename, _)| filename.to_string())
+            .chain(
+                finding
+                    .config_line_highlights
+                    .iter()
+                    .map(|line| line.filename.to_string()),
+            )
+            .collect();
+        let mut host_users: Vec<String> = finding
+            .host_mapping_highlights
+            .iter()
+            .map(|(user, _)| user.to_string())
+            .collect();
+
+        containers.sort_unstable();
+        containers.dedup();
+        host_users.sort_unstable();
+        host_users.dedup();
+
+        Finding {
+            id: finding.id(),
+            severity: finding.kind.into(),
+            check: finding.check.id(),
+            message: finding.message.to_string(),
+            containers,
+            host_users,
+            rootfs: finding.rootfs_highlights.clone(),
+            config_lines: finding
+                .config_line_highlights
+                .iter()
+                .map(|line| ConfigLine {
+                    container: line.filename.to_string(),
+                    key: line.key.to_string(),
+                    line: line.line,
+                })
+                .collect(),
+            subid_lines: finding
+                .subid_line_highlights
+                .iter()
+                .map(|&(sub_id, line)| SubidLine {
+                    file: sub_id.path(),
+                    line,
+                })
+                .collect(),
+            fix: finding.fix.map(|fix| fix.description()),
+        }
+    }
+}
+
+/// The result of evaluating every enabled check once.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Report {
+    pub health: Health,
+    /// Most severe first.
+    pub findings: Vec<Finding>,
+    pub host_mapping: HostMapping,
+    /// Why each file which couldn't be loaded was skipped.
+    pub load_errors: Vec<String>,
+}
+
+impl Report {
+    fn from_state(state: &State, errors: &[color_eyre::Report]) -> Self {
+        let host_ranges = |entries: &[crate::app::ui::IdMapEntry]| {
+            entries
+                .iter()
+                .map(|entry| HostRange {
+                    user: entry.host_user_id.to_string(),
+                    range: IdRange::new(entry.host_sub_id, entry.host_sub_id_count),
+                    line: entry.line_number,
+                })
+                .collect()
+        };
+
+        Report {
+            health: Health::from_state(state, errors.len()),
+            findings: state.findings.iter().map(Finding::from).collect(),
+            host_mapping: HostMapping {
+                subuid: host_ranges(&state.host_mapping.subuid),
+                subgid: host_ranges(&state.host_mapping.subgid),
+            },
+            load_errors: errors.iter().map(|err| format!("{err:#}")).collect(),
+        }
+    }
+}
+
+/// Evaluates this host's container configs and subordinate ids with the user's settings.
+pub fn evaluate() -> Result<Report, Error> {
+    let metadata = Metadata::collect(None).map_err(|err| Error::Host(format!("{err:#}")))?;
+
+    Ok(evaluate_with(&metadata, Settings::load_default()))
+}
+
+/// Like [`evaluate`], but reads the files of another host copied under `root_prefix`, the way
+/// `--root-prefix` does.
+pub fn evaluate_at(root_prefix: impl Into<PathBuf>) -> Result<Report, Error> {
+    let metadata =
+        Metadata::with_root_prefix(root_prefix.into(), None).map_err(|err| Error::Host(format!("{err:#}")))?;
+
+    Ok(evaluate_with(&metadata, Settings::load_default()))
+}
+
+fn evaluate_with(metadata: &Metadata, settings: Settings) -> Report {
+    let (state, errors) = State::collect(metadata, settings);
+
+    Report::from_state(&state, &errors)
+}
+
+#[test]
+fn test_evaluate_at() -> color_eyre::Result<()> {
+    let root = tempfile::tempdir()?;
+    let lxc_dir = root.path().join("etc/pve/lxc");
+
+    std::fs::create_dir_all(&lxc_dir)?;
+    std::fs::write(root.path().join("etc/subuid"), "root:100000:65536\n")?;
+    std::fs::write(root.path().join("etc/subgid"), "root:100000:65536\n")?;
+    std::fs::write(lxc_dir.join("100.conf"), "unprivileged: 1\n")?;
+
+    let report = evaluate_with(
+        &Metadata::with_root_prefix(root.path().to_path_buf(), None)?,
+        Settings::default(),
+    );
+
+    assert_eq!(report.health.containers, 1);
+    assert_eq!(
+        report.host_mapping.ranges(IdKind::Uid),
+        [HostRange {
+            user: "root".into(),
+            range: IdRange::new(100000, 65536),
+            line: 1,
+        }]
+    );
+
+    let missing = report
+        .findings
+        .iter()
+        .find(|finding| finding.check == "idmap-present")
+        .expect("missing idmaps are found");
+
+    assert_eq!(missing.severity, Severity::Bad);
+    assert_eq!(missing.containers, ["100.conf"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_id_range() {
+    let range = IdRange::new(100000, 65536);
+
+    assert_eq!(range.end(), 165536);
+    assert!(range.contains(165535));
+    assert!(!range.contains(165536));
+    assert!(range.overlaps(&IdRange::new(165535, 10)));
+    assert!(!range.overlaps(&IdRange::new(165536, 10)));
+    assert_eq!(IdRange::new(u32::MAX, 1).end(), 1 << 32);
+}
@@ -0,0 +1,153 @@
+//! A small publish/subscribe bus shared by the app and its worker threads.
+//!
+//! Each [`Topic`] carries a single message type. Publishers don't need to know who is listening, so
+//! new subsystems can subscribe to existing topics without threading yet another channel around.
+
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use log::Level;
+
+use crate::app::event::FileSystemChangeKind;
+
+type Subscriber<T> = Arc<dyn Fn(T) -> bool + Send + Sync>;
+
+/// A typed channel with any number of subscribers.
+pub struct Topic<T> {
+    subscribers: Arc<Mutex<Vec<Subscriber<T>>>>,
+}
+
+impl<T> Clone for Topic<T> {
+    fn clone(&self) -> Self {
+        Self {
+            subscribers: Arc::clone(&self.subscribers),
+        }
+    }
+}
+
+impl<T> Default for Topic<T> {
+    fn default() -> Self {
+        Self {
+            subscribers: Arc::default(),
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Topic<T> {
+    /// Delivers `message` to every subscriber. Subscribers which have gone away are dropped.
+    pub fn publish(&self, message: T) {
+        // Delivered without holding the lock, so subscribers may publish and subscribe themselves
+        let subscribers = self.subscribers.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        let gone: Vec<_> = subscribers
+            .into_iter()
+            .filter(|deliver| !deliver(message.clone()))
+            .collect();
+
+        if !gone.is_empty() {
+            self.subscribers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .retain(|subscriber| !gone.iter().any(|deliver| Arc::ptr_eq(subscriber, deliver)));
+        }
+    }
+
+    /// Subscribes to all messages published from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let (tx, rx) = mpsc::channel();
+
+        self.subscribe_with(move |message| tx.send(message).is_ok());
+
+        rx
+    }
+
+    /// Subscribes with a callback, which should return `false` once it no longer wants messages.
+    /// Useful for forwarding messages into an existing channel.
+    pub fn subscribe_with(&self, deliver: impl Fn(T) -> bool + Send + Sync + 'static) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::new(deliver));
+    }
+
+    /// Drops every subscriber. Receivers from [`Topic::subscribe`] disconnect once their messages are
+    /// drained, and nothing published afterwards is delivered.
+    pub fn close(&self) {
+        self.subscribers.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
+
+/// A message meant for the user rather than for another subsystem.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+}
+
+/// All topics. Cloning is cheap and every clone publishes to the same subscribers.
+#[derive(Clone, Default)]
+pub struct Bus {
+    /// Files, rootfs directories and datasets which changed on disk.
+    pub fs_changes: Topic<FileSystemChangeKind>,
+    /// Files which need to be (re)read by the file reader thread.
+    pub file_reads: Topic<PathBuf>,
+    /// Rootfs values whose ownership and disk space should be monitored.
+    pub rootfs_watches: Topic<String>,
+    /// A config's path and the `lxc.include` files it pulled in, which are watched so the config is
+    /// read again when one of them changes. Replaces the files published for the config before.
+    pub include_watches: Topic<(PathBuf, Vec<PathBuf>)>,
+    /// Messages to surface to the user.
+    pub notifications: Topic<Notification>,
+}
+
+impl Bus {
+    /// Closes every topic, which stops the worker threads subscribed to them.
+    pub fn close(&self) {
+        self.fs_changes.close();
+        self.file_reads.close();
+        self.rootfs_watches.close();
+        self.include_watches.close();
+        self.notifications.close();
+    }
+}
+
+#[test]
+fn test_topic_fan_out() {
+    let topic = Topic::default();
+    let first = topic.subscribe();
+    let second = topic.subscribe();
+
+    topic.publish(1);
+    drop(second);
+    topic.publish(2);
+
+    assert_eq!(first.try_iter().collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(topic.subscribers.lock().unwrap().len(), 1);
+
+    topic.close();
+    topic.publish(3);
+
+    assert!(first.recv().is_err());
+}
+
+#[test]
+fn test_topic_reentrant_subscribers() {
+    let topic = Topic::default();
+    let inner = topic.clone();
+
+    // Republishes every message once, subscribing along the way
+    topic.subscribe_with(move |message: u32| {
+        if message == 1 {
+            drop(inner.subscribe());
+            inner.publish(2);
+        }
+
+        true
+    });
+
+    let received = topic.subscribe();
+
+    topic.publish(1);
+
+    assert_eq!(received.try_iter().collect::<Vec<_>>(), [2, 1]);
+}
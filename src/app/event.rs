@@ -2,10 +2,16 @@ use color_eyre::eyre::WrapErr;
 use ratatui::crossterm::event::{self, Event as CrosstermEvent};
 use std::fs::Metadata;
 use std::path::PathBuf;
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread;
 use std::time::{Duration, Instant};
 
+use crate::app::bus::Notification;
+use crate::fs::scan::OwnershipScan;
+use crate::linux::DiskSpace;
+use crate::lxc::RootfsLocation;
+use crate::server::rpc::RpcCall;
+
 /// The frequency at which tick events are emitted.
 const TICK_FPS: f64 = 30.0;
 
@@ -31,14 +37,38 @@ pub enum Event {
 #[derive(Clone, Debug)]
 pub enum FileSystemChangeKind {
     RemoveFile(PathBuf),
+    /// The reader started on a file, whose [`UpdateFile`](Self::UpdateFile) or
+    /// [`ReadFailed`](Self::ReadFailed) follows.
+    ReadStarted(PathBuf),
     UpdateFile(PathBuf, String),
-    UpdateDir(String, PathBuf, Metadata),
+    /// A file couldn't be read, with why.
+    ReadFailed(PathBuf, String),
+    UpdateDir(String, RootfsLocation, Box<Metadata>),
+    /// A rootfs or mount point value couldn't be resolved or looked at, with why.
+    DirUnavailable(String, String),
+    UpdateDiskSpace(String, DiskSpace),
+    /// An Incus container was read, under the config name and as the LXC config it was translated to.
+    UpdateIncusInstance(String, String),
+    /// A deep scan of the rootfs with the given value finished or was cancelled.
+    OwnershipScanned(String, Box<OwnershipScan>),
+    /// The container config directory went away, e.g. /etc/pve while pve-cluster restarts, and its
+    /// changes are no longer seen.
+    ConfigDirLost(PathBuf),
+    /// The container config directory is back and watched again. Its configs may have changed while
+    /// it was gone.
+    ConfigDirRestored(PathBuf),
 }
 
 /// Application events.
 #[derive(Clone, Debug)]
 pub enum AppEvent {
-    FileSystemChanged(FileSystemChangeKind),
+    /// A change read by the workers of the given [`generation`](crate::app::App), which is stale once
+    /// a hard refresh started a newer one.
+    FileSystemChanged(u64, FileSystemChangeKind),
+    /// Show a message to the user.
+    Notify(Notification),
+    /// A call to the API of `pupman serve`, answered through its reply channel.
+    Rpc(RpcCall),
     /// Quit the application.
     Quit,
 }
@@ -61,6 +91,13 @@ impl EventHandler {
         Self { sender, receiver }
     }
 
+    /// Constructs an [`EventHandler`] which only carries app events, with no terminal behind it and
+    /// no ticks. Used to drive the app from tests.
+    pub fn without_terminal() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { sender, receiver }
+    }
+
     /// Receives an event from the sender.
     ///
     /// This function blocks until an event is received.
@@ -74,6 +111,15 @@ impl EventHandler {
         Ok(self.receiver.recv()?)
     }
 
+    /// Like [`EventHandler::next`], but gives up once `timeout` passes without an event.
+    pub fn next_timeout(&self, timeout: Duration) -> color_eyre::Result<Option<Event>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(err @ RecvTimeoutError::Disconnected) => Err(err.into()),
+        }
+    }
+
     /// Queue an app event to be sent to the event receiver.
     ///
     /// This is useful for sending events to the event handler which will be processed by the next
@@ -89,6 +135,12 @@ impl EventHandler {
     }
 }
 
+impl Default for EventHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// A thread that handles reading crossterm events and emitting tick events on a regular schedule.
 struct EventThread {
     /// Event sender channel.
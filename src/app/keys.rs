@@ -0,0 +1,676 @@
+//! Key handling, split by the popup or page which has the keys. The first one shown, from the top
+//! of the screen down, takes them.
+
+use log::Level;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use tui_logger::TuiWidgetEvent;
+
+use super::App;
+use super::bus::Notification;
+use super::state::Focus;
+use super::state::conversion::ConversionStep;
+use super::state::import::SubidImport;
+use super::state::subid_edit::SubidEditor;
+use super::state::wizard::WizardField;
+use super::ui::{self, SettingOption};
+use crate::check::Check;
+use crate::finding::FindingKind;
+use crate::linux::passwd::Passwd;
+use crate::report::ReportFormat;
+
+impl App {
+    /// Handles the key events and updates the state of [`App`].
+    pub fn handle_key_event(&mut self, key_event: KeyEvent) -> color_eyre::Result<()> {
+        let code = key_event.code;
+
+        // The confirmation dialog is drawn over everything else, so it takes keys first
+        if self.state.confirmation.is_some() {
+            self.handle_confirmation_key(code);
+        } else if code == KeyCode::Char('c') && key_event.modifiers == KeyModifiers::CONTROL {
+            self.request_quit();
+        } else if self.state.follow_up.is_some() {
+            self.handle_follow_up_key(code);
+        } else if self.state.write_preview.is_some() {
+            self.handle_write_preview_key(code);
+        } else if self.state.ownership_shift.is_some() {
+            self.handle_ownership_shift_key(code);
+        } else if self.state.import.is_some() {
+            self.handle_import_key(code);
+        } else if self.state.subid_editor.is_some() {
+            self.handle_subid_editor_key(code);
+        } else if self.state.idmap_editor.is_some() {
+            self.handle_idmap_editor_key(code);
+        } else if self.state.idmap_wizard.is_some() {
+            self.handle_idmap_wizard_key(code);
+        } else if self.state.conversion.is_some() {
+            self.handle_conversion_key(code);
+        } else if self.state.show_changes_page {
+            self.handle_changes_page_key(code);
+        } else if self.state.show_fix_popup {
+            self.handle_fix_popup_key(code);
+        } else if self.state.show_explain_popup {
+            self.handle_explain_popup_key(code);
+        } else if self.state.show_report_popup {
+            self.handle_report_popup_key(code);
+        } else if self.state.show_settings_page {
+            self.handle_settings_page_key(code);
+        } else if self.state.source_view.is_some() {
+            self.handle_source_view_key(code);
+        } else if self.state.container_detail.is_some() {
+            if code == KeyCode::Esc {
+                self.state.container_detail = None;
+            }
+        } else if self.state.show_stats_page {
+            if code == KeyCode::Esc {
+                self.state.show_stats_page = false;
+            }
+        } else if self.state.show_checks_page {
+            self.handle_checks_page_key(code);
+        } else if self.state.show_logs_page {
+            self.handle_logs_page_key(code);
+        } else if self.state.filter_input {
+            self.handle_filter_key(code);
+        } else if !self.handle_panel_key(code) {
+            self.handle_main_key(key_event)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_confirmation_key(&mut self, code: KeyCode) {
+        let Some(confirmation) = &self.state.confirmation else {
+            return;
+        };
+
+        match code {
+            KeyCode::Enter | KeyCode::Char('y') => {
+                let action = confirmation.action;
+
+                self.state.confirmation = None;
+                self.run_confirmed(action);
+            },
+            KeyCode::Esc | KeyCode::Char('n') => self.state.confirmation = None,
+            _ => {},
+        }
+    }
+
+    fn handle_follow_up_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc | KeyCode::Enter => self.state.follow_up = None,
+            KeyCode::Char('x') => self.run_safe_follow_up_steps(),
+            _ => {},
+        }
+    }
+
+    /// Keys for the diff of files about to be written.
+    fn handle_write_preview_key(&mut self, code: KeyCode) {
+        let Some(preview) = &mut self.state.write_preview else {
+            return;
+        };
+        let max_scroll = ui::write_preview_popup_lines(preview, self.state.changes.len())
+            .len()
+            .saturating_sub(1);
+
+        match code {
+            KeyCode::Esc => self.state.write_preview = None,
+            KeyCode::Up => preview.scroll = preview.scroll.saturating_sub(1),
+            KeyCode::Down => preview.scroll = (preview.scroll + 1).min(max_scroll),
+            KeyCode::PageUp => preview.scroll = preview.scroll.saturating_sub(10),
+            KeyCode::PageDown => preview.scroll = (preview.scroll + 10).min(max_scroll),
+            KeyCode::Home => preview.scroll = 0,
+            KeyCode::Char('q') => {
+                if let Some(preview) = self.state.write_preview.take() {
+                    self.queue_preview(preview);
+                }
+            },
+            KeyCode::Enter | KeyCode::Char('y') => {
+                if let Some(preview) = self.state.write_preview.take() {
+                    // Once something is queued, previews are planned on top of it, so writing one
+                    // on its own would write the queued changes along with it
+                    if !self.state.changes.is_empty() && preview.action.queueable() {
+                        self.queue_preview(preview);
+                    } else {
+                        self.commit_preview(preview);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Keys for the rootfs shift dialog, of which only Esc is taken while the shift runs.
+    fn handle_ownership_shift_key(&mut self, code: KeyCode) {
+        let Some(shift) = &mut self.state.ownership_shift else {
+            return;
+        };
+
+        if shift.is_running() {
+            if code == KeyCode::Esc {
+                self.stop_ownership_shift();
+            }
+
+            return;
+        }
+
+        match code {
+            KeyCode::Esc => self.state.ownership_shift = None,
+            KeyCode::Tab | KeyCode::Down => shift.field = shift.field.cycle(1),
+            KeyCode::BackTab | KeyCode::Up => shift.field = shift.field.cycle(-1),
+            KeyCode::Backspace => {
+                shift.input().pop();
+            },
+            KeyCode::Char(c) if c.is_ascii_digit() => shift.input().push(c),
+            KeyCode::Char('d') => self.start_ownership_shift(true),
+            KeyCode::Char('q') => self.queue_ownership_shift(),
+            KeyCode::Enter => self.start_ownership_shift(false),
+            _ => {},
+        }
+    }
+
+    /// Keys for the import dialog, first while pasting entries and then while reviewing them.
+    fn handle_import_key(&mut self, code: KeyCode) {
+        let Some(import) = &mut self.state.import else {
+            return;
+        };
+        let host_mapping = || {
+            self.state
+                .subid_editor
+                .as_ref()
+                .map(SubidEditor::host_mapping)
+                .unwrap_or_default()
+        };
+        let no_users = Passwd::default();
+        let passwd = self.state.host_users.as_ref().unwrap_or(&no_users);
+
+        if !import.reviewing {
+            match code {
+                KeyCode::Esc => self.state.import = None,
+                KeyCode::Tab => import.review(&host_mapping(), passwd),
+                KeyCode::Enter => import.text.push('\n'),
+                KeyCode::Backspace => {
+                    import.text.pop();
+                },
+                KeyCode::Char(c) => import.text.push(c),
+                _ => {},
+            }
+
+            return;
+        }
+
+        match code {
+            KeyCode::Esc | KeyCode::Tab => import.reviewing = false,
+            KeyCode::Up => import.selected = import.selected.saturating_sub(1),
+            KeyCode::Down => import.selected = (import.selected + 1).min(import.entries.len().saturating_sub(1)),
+            KeyCode::Char(' ') => import.toggle_selected(),
+            KeyCode::Char('t') => {
+                import.target = import.target.next();
+                import.review(&host_mapping(), passwd);
+            },
+            KeyCode::Enter => self.import_into_editor(),
+            _ => {},
+        }
+    }
+
+    fn handle_subid_editor_key(&mut self, code: KeyCode) {
+        let unsaved = !self.state.unsaved_changes().is_empty();
+        let Some(editor) = &mut self.state.subid_editor else {
+            return;
+        };
+
+        if let Some(input) = &mut editor.input {
+            match code {
+                KeyCode::Esc => editor.input = None,
+                KeyCode::Enter => {
+                    if let Err(err) = editor.commit_input() {
+                        self.bus.notifications.publish(Notification {
+                            level: Level::Warn,
+                            message: err.to_string(),
+                        });
+                    }
+                },
+                KeyCode::Backspace => {
+                    input.pop();
+                },
+                KeyCode::Char(c) => input.push(c),
+                _ => {},
+            }
+
+            return;
+        }
+
+        match code {
+            KeyCode::Esc if unsaved => self.state.confirm_discard(),
+            KeyCode::Esc => self.state.subid_editor = None,
+            KeyCode::Up => editor.move_selection(-1),
+            KeyCode::Down => editor.move_selection(1),
+            KeyCode::Left | KeyCode::BackTab => editor.field = editor.field.prev(),
+            KeyCode::Right | KeyCode::Tab => editor.field = editor.field.next(),
+            KeyCode::Enter => editor.begin_input(),
+            KeyCode::Char('a') => editor.add_row(),
+            KeyCode::Char('d') => editor.delete_row(),
+            KeyCode::Char('k') => editor.toggle_kind(),
+            KeyCode::Char('i') => self.state.import = Some(SubidImport::default()),
+            KeyCode::Char('w') => self.preview_subid_edits(),
+            _ => {},
+        }
+    }
+
+    fn handle_idmap_editor_key(&mut self, code: KeyCode) {
+        let unsaved = !self.state.unsaved_changes().is_empty();
+        let Some(editor) = &mut self.state.idmap_editor else {
+            return;
+        };
+
+        if let Some(input) = &mut editor.input {
+            match code {
+                KeyCode::Esc => editor.input = None,
+                KeyCode::Enter => {
+                    if let Err(err) = editor.commit_input() {
+                        self.bus.notifications.publish(Notification {
+                            level: Level::Warn,
+                            message: err.to_string(),
+                        });
+                    }
+                },
+                KeyCode::Backspace => {
+                    input.pop();
+                },
+                KeyCode::Char(c) if c.is_ascii_digit() => input.push(c),
+                _ => {},
+            }
+
+            return;
+        }
+
+        match code {
+            KeyCode::Esc if unsaved => self.state.confirm_discard(),
+            KeyCode::Esc => self.state.idmap_editor = None,
+            KeyCode::Up => editor.move_selection(-1),
+            KeyCode::Down => editor.move_selection(1),
+            KeyCode::Left | KeyCode::BackTab => editor.field = editor.field.prev(),
+            KeyCode::Right | KeyCode::Tab => editor.field = editor.field.next(),
+            KeyCode::Enter => editor.begin_input(),
+            KeyCode::Char('a') => editor.add_row(),
+            KeyCode::Char('d') => editor.delete_row(),
+            KeyCode::Char('k') => editor.toggle_kind(),
+            KeyCode::Char('w') => self.preview_idmap_edits(),
+            KeyCode::Char('n') => {
+                if let Err(err) = self.state.next_idmap_editor(&self.metadata.lxc_config_dir) {
+                    self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: err.to_string(),
+                    });
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn handle_idmap_wizard_key(&mut self, code: KeyCode) {
+        let Some(wizard) = &mut self.state.idmap_wizard else {
+            return;
+        };
+
+        match (code, wizard.field) {
+            (KeyCode::Esc, _) => self.state.idmap_wizard = None,
+            (KeyCode::Tab, field) => wizard.field = field.cycle(1),
+            (KeyCode::BackTab, field) => wizard.field = field.cycle(-1),
+            (KeyCode::Char('i'), _) => wizard.intent = wizard.intent.next(),
+            (KeyCode::Up, WizardField::Container) => self.state.move_wizard_selection(-1),
+            (KeyCode::Down, WizardField::Container) => self.state.move_wizard_selection(1),
+            (KeyCode::Char(c), WizardField::Offset) if c.is_ascii_digit() => wizard.offset.push(c),
+            (KeyCode::Backspace, WizardField::Offset) => {
+                wizard.offset.pop();
+            },
+            (KeyCode::Char(c), WizardField::Passthrough) if c.is_ascii_digit() => wizard.passthrough.push(c),
+            (KeyCode::Backspace, WizardField::Passthrough) => {
+                wizard.passthrough.pop();
+            },
+            (KeyCode::Enter, _) => self.preview_generated_mapping(),
+            _ => {},
+        }
+    }
+
+    /// Keys for the conversion assistant's checklist.
+    fn handle_conversion_key(&mut self, code: KeyCode) {
+        let Some(conversion) = &mut self.state.conversion else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc => self.state.conversion = None,
+            KeyCode::Up => conversion.step = conversion.step.saturating_sub(1),
+            KeyCode::Down => conversion.step = (conversion.step + 1).min(ConversionStep::ALL.len() - 1),
+            KeyCode::Char('n') => self.state.next_conversion_container(),
+            KeyCode::Enter => {
+                if let Err(err) = self.start_conversion_step() {
+                    self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: err.to_string(),
+                    });
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Keys for the page of queued changes.
+    fn handle_changes_page_key(&mut self, code: KeyCode) {
+        let count = self.state.changes.len();
+        let max_scroll = self
+            .state
+            .changes
+            .changes()
+            .get(self.state.selected_change)
+            .map_or(0, |change| ui::change_diff_lines(change).len().saturating_sub(1));
+        let scroll = &mut self.state.changes_scroll;
+
+        match code {
+            KeyCode::Esc => {
+                self.state.show_changes_page = false;
+                *scroll = 0;
+            },
+            KeyCode::Up => {
+                self.state.selected_change = self.state.selected_change.saturating_sub(1);
+                *scroll = 0;
+            },
+            KeyCode::Down => {
+                self.state.selected_change = (self.state.selected_change + 1).min(count.saturating_sub(1));
+                *scroll = 0;
+            },
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::PageDown => *scroll = (*scroll + 10).min(max_scroll),
+            KeyCode::Char('d') => self.drop_queued_change(),
+            KeyCode::Char('A') => self.preview_transaction(),
+            _ => {},
+        }
+    }
+
+    fn handle_fix_popup_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.state.show_fix_popup = false,
+            KeyCode::Up => self.state.move_fix_selection(-1),
+            KeyCode::Down => self.state.move_fix_selection(1),
+            KeyCode::Enter => {
+                let strategy = self
+                    .selected_finding()
+                    .and_then(|f| self.state.fix_strategies(f).into_iter().nth(self.state.fix_selection));
+
+                if let Some(strategy) = strategy {
+                    self.run_fix_strategy(strategy);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn handle_explain_popup_key(&mut self, code: KeyCode) {
+        let max_scroll = self.explain_line_count().saturating_sub(1);
+        let scroll = &mut self.state.explain_scroll;
+
+        match code {
+            KeyCode::Esc => {
+                self.state.show_explain_popup = false;
+                *scroll = 0;
+            },
+            KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down => *scroll = (*scroll + 1).min(max_scroll),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::PageDown => *scroll = (*scroll + 10).min(max_scroll),
+            KeyCode::Home => *scroll = 0,
+            _ => {},
+        }
+    }
+
+    /// Keys for picking the format of a report.
+    fn handle_report_popup_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.state.show_report_popup = false,
+            KeyCode::Char('m') => self.write_report(ReportFormat::Markdown),
+            KeyCode::Char('h') => self.write_report(ReportFormat::Html),
+            _ => {},
+        }
+    }
+
+    fn handle_settings_page_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.state.show_settings_page = false,
+            KeyCode::Up => self.state.selected_setting = self.state.selected_setting.saturating_sub(1),
+            KeyCode::Down => {
+                self.state.selected_setting = (self.state.selected_setting + 1).min(SettingOption::row_count() - 1)
+            },
+            KeyCode::Char(' ') | KeyCode::Enter => match SettingOption::at_row(self.state.selected_setting) {
+                None => self.toggle_check(Check::ALL[self.state.selected_setting]),
+                Some(SettingOption::InspectRootfs) => self.toggle_inspect_rootfs(),
+                Some(SettingOption::CheckSnapshots) => self.toggle_check_snapshots(),
+                Some(SettingOption::ApplyMode) => self.toggle_apply_mode(),
+                Some(SettingOption::SortOrder) => self.cycle_sort_order(),
+                Some(SettingOption::Theme) => self.cycle_theme(),
+                Some(SettingOption::UndoLastChange) => self.undo_last_change(),
+            },
+            _ => {},
+        }
+    }
+
+    /// Keys for a file opened from a finding.
+    fn handle_source_view_key(&mut self, code: KeyCode) {
+        let Some(view) = &mut self.state.source_view else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc => self.state.source_view = None,
+            KeyCode::Up => view.move_cursor(-1),
+            KeyCode::Down => view.move_cursor(1),
+            KeyCode::PageUp => view.move_cursor(-10),
+            KeyCode::PageDown => view.move_cursor(10),
+            KeyCode::Char('n') => {
+                let next = view
+                    .targets
+                    .iter()
+                    .find(|&&line| line > view.cursor + 1)
+                    .or_else(|| view.targets.first());
+
+                if let Some(&line) = next {
+                    view.cursor = (line - 1).min(view.lines.len().saturating_sub(1));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn handle_checks_page_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.state.show_checks_page = false,
+            KeyCode::Up => self.state.selected_check = self.state.selected_check.saturating_sub(1),
+            KeyCode::Down => self.state.selected_check = (self.state.selected_check + 1).min(Check::ALL.len() - 1),
+            KeyCode::Char(' ') | KeyCode::Enter => self.toggle_check(Check::ALL[self.state.selected_check]),
+            _ => {},
+        }
+    }
+
+    fn handle_logs_page_key(&mut self, code: KeyCode) {
+        let state = &self.state.logger_page_state;
+
+        match code {
+            KeyCode::Esc => self.state.show_logs_page = false,
+            KeyCode::Char(' ') => state.transition(TuiWidgetEvent::SpaceKey),
+            KeyCode::Char('q') => state.transition(TuiWidgetEvent::EscapeKey),
+            KeyCode::PageUp => state.transition(TuiWidgetEvent::PrevPageKey),
+            KeyCode::PageDown => state.transition(TuiWidgetEvent::NextPageKey),
+            KeyCode::Up => state.transition(TuiWidgetEvent::UpKey),
+            KeyCode::Down => state.transition(TuiWidgetEvent::DownKey),
+            KeyCode::Left => state.transition(TuiWidgetEvent::LeftKey),
+            KeyCode::Right => state.transition(TuiWidgetEvent::RightKey),
+            KeyCode::Char('+') => state.transition(TuiWidgetEvent::PlusKey),
+            KeyCode::Char('-') => state.transition(TuiWidgetEvent::MinusKey),
+            KeyCode::Char('h') => state.transition(TuiWidgetEvent::HideKey),
+            KeyCode::Char('f') => state.transition(TuiWidgetEvent::FocusKey),
+            _ => {},
+        }
+    }
+
+    /// Keys for typing the findings filter.
+    fn handle_filter_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.state.clear_filter(),
+            KeyCode::Enter => self.state.filter_input = false,
+            KeyCode::Backspace => {
+                self.state.filter.pop();
+            },
+            KeyCode::Char(c) => self.state.filter.push(c),
+            _ => {},
+        }
+
+        self.state.reselect_visible();
+        // The container picked in the config panel may just have been filtered out
+        self.state.selected_container = self
+            .state
+            .selected_container
+            .map(|selected| selected.min(self.state.panel_containers().len().saturating_sub(1)));
+    }
+
+    /// Tab moves focus between the panels, and the arrow keys move through whichever has it. Returns
+    /// whether the key was taken, other keys fall through to the main application.
+    fn handle_panel_key(&mut self, code: KeyCode) -> bool {
+        let host_rows = self.state.host_mapping.row_count();
+        let rootfs_rows = self.state.rootfs_info.len();
+
+        match (self.state.focus, code) {
+            (_, KeyCode::Tab) => {
+                self.cycle_focus(1);
+                true
+            },
+            (_, KeyCode::BackTab) => {
+                self.cycle_focus(-1);
+                true
+            },
+            (Focus::Findings, _) => false,
+            (_, KeyCode::Esc) => {
+                self.focus_findings();
+                true
+            },
+            (Focus::LXCConfig, KeyCode::Up | KeyCode::Down | KeyCode::Enter) => {
+                let containers = self.state.panel_containers();
+
+                match (self.state.selected_container, code) {
+                    (Some(selected), KeyCode::Up) => self.state.selected_container = Some(selected.saturating_sub(1)),
+                    (Some(selected), KeyCode::Down) => {
+                        self.state.selected_container = Some((selected + 1).min(containers.len().saturating_sub(1)))
+                    },
+                    (Some(selected), _) => self.state.container_detail = containers.get(selected).map(|f| (*f).clone()),
+                    (None, _) => {},
+                }
+
+                true
+            },
+            (Focus::HostMapping, KeyCode::Up) => {
+                self.state.host_mapping_scroll = self.state.host_mapping_scroll.saturating_sub(1);
+                true
+            },
+            (Focus::HostMapping, KeyCode::Down) => {
+                self.state.host_mapping_scroll = (self.state.host_mapping_scroll + 1).min(host_rows.saturating_sub(1));
+                true
+            },
+            (Focus::RootFS, KeyCode::Up) => {
+                self.state.rootfs_scroll = self.state.rootfs_scroll.saturating_sub(1);
+                true
+            },
+            (Focus::RootFS, KeyCode::Down) => {
+                self.state.rootfs_scroll = (self.state.rootfs_scroll + 1).min(rootfs_rows.saturating_sub(1));
+                true
+            },
+            // Paging and jumping only apply to the findings list
+            (_, KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End) => true,
+            _ => false,
+        }
+    }
+
+    /// Keys for the main view, once no popup or page took them.
+    fn handle_main_key(&mut self, key_event: KeyEvent) -> color_eyre::Result<()> {
+        match key_event.code {
+            KeyCode::Esc if !self.state.filter.is_empty() => self.state.clear_filter(),
+            KeyCode::Esc if !self.state.marked_findings.is_empty() => self.state.marked_findings.clear(),
+            KeyCode::Esc => self.request_quit(),
+            KeyCode::Char('r' | 'R') if key_event.modifiers == KeyModifiers::CONTROL => self.hard_refresh()?,
+            KeyCode::Char('R') => self.rescan()?,
+            KeyCode::Char('f') if !self.state.show_fix_popup => {
+                if let Some(finding) = self.selected_finding()
+                    && self.state.fixable(finding)
+                {
+                    self.state.show_fix_popup = true;
+                    self.state.fix_selection = 0;
+                }
+            },
+            KeyCode::Char('e') if !self.state.show_explain_popup => {
+                if let Some(finding) = self.selected_finding()
+                    && finding.kind != FindingKind::Good
+                {
+                    self.state.show_explain_popup = true;
+                }
+            },
+            KeyCode::Enter => self.open_source(),
+            KeyCode::Char(' ') if self.state.focus == Focus::Findings => {
+                if let Err(err) = self.state.toggle_marked_finding() {
+                    self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: err.to_string(),
+                    });
+                }
+            },
+            KeyCode::Char('F') => self.preview_marked_fixes(),
+            KeyCode::Char('p') => {
+                self.state.show_changes_page = true;
+                self.state.selected_change = 0;
+                self.state.changes_scroll = 0;
+            },
+            KeyCode::Char('l') => {
+                self.state.show_logs_page = true;
+            },
+            KeyCode::Char('s') => {
+                self.state.show_settings_page = true;
+            },
+            KeyCode::Char('c') => {
+                self.state.show_checks_page = true;
+            },
+            KeyCode::Char('t') => {
+                self.state.show_stats_page = true;
+            },
+            KeyCode::Char('m') => self.open_subid_editor(),
+            KeyCode::Char('M') => {
+                if let Err(err) = self.state.open_idmap_editor(&self.metadata.lxc_config_dir) {
+                    self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: err.to_string(),
+                    });
+                }
+            },
+            KeyCode::Char('g') => {
+                if let Err(err) = self.state.open_idmap_wizard() {
+                    self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: err.to_string(),
+                    });
+                }
+            },
+            KeyCode::Char('u') => {
+                if let Err(err) = self.state.open_conversion() {
+                    self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: err.to_string(),
+                    });
+                }
+            },
+            KeyCode::Char('r') => self.state.show_report_popup = true,
+            KeyCode::Char('/') => self.state.filter_input = true,
+            KeyCode::Char('o') => self.cycle_sort_order(),
+            KeyCode::Char('D') => self.toggle_deep_scan(),
+            KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => {
+                self.move_finding_selection(key_event.code);
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+}
@@ -1,56 +1,146 @@
-use std::fs::read_dir;
+use std::collections::HashSet;
+use std::env::current_dir;
+use std::fmt::Write;
+use std::fs::{read_dir, read_to_string};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
 use std::thread;
+use std::time::Duration;
 
-use color_eyre::eyre::{OptionExt, eyre};
+use ahash::RandomState;
+use chrono::Utc;
+use color_eyre::eyre::{WrapErr, eyre};
 use compact_str::CompactString;
 use crossterm::event::Event as CrosstermEvent;
-use log::warn;
+use log::{Level, error, info, log};
+use nix::unistd::geteuid;
 use ratatui::DefaultTerminal;
-use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::crossterm::event::{KeyCode, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::{Position, Rect};
 
-pub(crate) mod event;
-mod state;
+pub(crate) mod bus;
+pub mod event;
+mod keys;
+pub mod recording;
+mod rpc;
+pub(crate) mod state;
 pub(crate) mod ui;
 
+use bus::{Bus, Notification};
 use event::{AppEvent, Event, EventHandler, FileSystemChangeKind};
-use state::State;
-use tui_logger::TuiWidgetEvent;
-use ui::{Finding, FindingKind, IdMapEntry};
+use recording::{Entry, Recorded, Recorder, RecordingError};
+use state::acl::AclPlan;
+use state::confirm::{ConfirmAction, Confirmation};
+use state::conversion::ConversionStep;
+use state::fix_options::FixStrategy;
+use state::preview::{PreviewAction, WritePreview};
+use state::shared_volume::{ContainerAccounts, ShareStrategy, SharedVolumePlan};
+use state::shift::{OwnershipShift, ShiftField};
+use state::source::{SourceFile, SourceView};
+use state::subid_edit::SubidEditor;
+use state::wizard::{CONTAINER_IDS, GeneratedMapping};
+use state::{Focus, State};
+use ui::IdMapEntry;
 
+use crate::changes::{QueuedChange, QueuedStep};
+use crate::check::Check;
+use crate::finding::{Finding, FindingKind};
+use crate::fix::{self, Fix};
+use crate::followup::{Change, checklist};
 use crate::fs;
-use crate::fs::monitor::{MonitorHandler, is_valid_file};
-use crate::fs::subid::{ETC_SUBGID, ETC_SUBUID, SubID};
-use crate::lxc::config::Config;
+use crate::fs::backup;
+use crate::fs::monitor::{MonitorHandler, is_container_config};
+use crate::fs::scan::DeepScan;
+use crate::fs::shift::ShiftJob;
+use crate::fs::subid::{
+    InvalidSubidLine, SubID, SubidError, append_entries, is_comment, read_shadow_backup, split_fields,
+};
+use crate::fs::writer::{PendingWrite, commit_together, write_atomic};
+use crate::history::FindingHistory;
+use crate::incus;
+use crate::linux::lxc_running;
 use crate::metadata::Metadata;
+use crate::report::{ReportFormat, report};
+use crate::settings::{ApplyMode, Settings, SortOrder};
+
+/// How many findings PageUp and PageDown move the selection by.
+const FINDINGS_PAGE: usize = 10;
 
 pub struct App {
     metadata: Metadata,
     // infra: Infrastructure,
     monitor: MonitorHandler,
     event_handler: EventHandler,
-    fs_reader_tx: Sender<PathBuf>,
+    bus: Bus,
+    /// Bumped by every hard refresh, so events from the workers it replaced can be told apart.
+    generation: u64,
+    history: FindingHistory,
+    /// Configs which were already there when everything was last loaded.
+    known_configs: HashSet<CompactString, RandomState>,
+    /// New configs of containers which were never started, waiting to be audited once their rootfs
+    /// is loaded.
+    readiness_probes: Vec<CompactString>,
+    /// The deep ownership scan of rootfs contents, once one was started.
+    deep_scan: Option<Arc<DeepScan>>,
+    /// Where the last frame was drawn, which mouse clicks are matched against.
+    frame_area: Rect,
+    /// Where handled events are recorded to, when asked to with `--record`.
+    recorder: Option<Recorder>,
     state: State,
 }
 
 impl App {
     /// Constructs a new instance of [`App`].
-    pub fn new(metadata: Metadata) -> Self {
-        let event_handler = EventHandler::new();
-        let (fs_tx, fs_rx) = mpsc::channel();
-        let app_tx = event_handler.sender();
+    pub fn new(metadata: Metadata, settings: Settings) -> color_eyre::Result<Self> {
+        Self::with_event_handler(metadata, settings, EventHandler::new())
+    }
 
-        thread::spawn(|| fs::reader::start(fs_rx, app_tx));
+    /// Constructs an [`App`] which takes its events from `event_handler`, e.g. one without a
+    /// terminal behind it. Fails only if files can't be watched for changes even by polling them.
+    pub fn with_event_handler(
+        metadata: Metadata,
+        settings: Settings,
+        event_handler: EventHandler,
+    ) -> color_eyre::Result<Self> {
+        let rootfs_checks = metadata.inspects_rootfs();
+        let dialect = metadata.dialect();
+        let root_prefix = metadata.root_prefix.clone();
+        let operator_uid = metadata.operator_uid();
+        let runs_as_root = geteuid().is_root();
+        let bus = start_workers(&event_handler, 0);
+        let monitor = MonitorHandler::new(bus.clone(), &metadata, settings.event_debounce())
+            .wrap_err("Failed to start the file system monitor")?;
+        let polling_files = monitor.is_polling();
 
-        Self {
-            fs_reader_tx: fs_tx.clone(),
-            monitor: MonitorHandler::new(event_handler.sender(), fs_tx, &metadata.lxc_config_dir).expect("Fixme"),
+        Ok(Self {
+            monitor,
+            bus,
+            generation: 0,
+            // Copied configs belong to another host, so they shouldn't show up in this one's history
+            history: if metadata.is_viewer_only() {
+                FindingHistory::default()
+            } else {
+                FindingHistory::load_default()
+            },
             metadata,
             event_handler,
-            state: State::default(),
-        }
+            known_configs: HashSet::with_hasher(RandomState::new()),
+            readiness_probes: Vec::new(),
+            deep_scan: None,
+            frame_area: Rect::default(),
+            recorder: None,
+            state: State {
+                settings,
+                rootfs_checks,
+                dialect,
+                root_prefix,
+                operator_uid,
+                runs_as_root,
+                polling_files,
+                ..State::default()
+            },
+        })
     }
 
     /// Run the application's main loop.
@@ -58,292 +148,2187 @@ impl App {
         self.initialize()?;
 
         while self.state.is_running {
-            terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
+            self.frame_area = terminal.draw(|frame| frame.render_widget(&self, frame.area()))?.area;
             self.handle_events()?;
         }
         Ok(())
     }
 
     pub fn handle_events(&mut self) -> color_eyre::Result<()> {
-        match self.event_handler.next()? {
+        let event = self.event_handler.next()?;
+
+        self.handle_event(event)
+    }
+
+    /// Handles the next event if one arrives within `timeout`, returning whether one did.
+    pub fn handle_events_timeout(&mut self, timeout: Duration) -> color_eyre::Result<bool> {
+        match self.event_handler.next_timeout(timeout)? {
+            Some(event) => self.handle_event(event).map(|()| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Records every event handled from now on to `path`, replacing what it held.
+    pub fn record_to(&mut self, path: &Path) -> Result<(), RecordingError> {
+        self.recorder = Some(Recorder::create(path)?);
+
+        Ok(())
+    }
+
+    /// Replays `recording` alongside the events which come in anyway, at the pace it was recorded.
+    pub fn replay(&self, recording: Vec<Recorded>) {
+        recording::replay(recording, self.event_handler.sender());
+    }
+
+    /// Adds `entry` to the recording, if one is being made. A recording which can't be written
+    /// to is given up on rather than failing the session.
+    fn record(&mut self, entry: Entry) {
+        if let Some(recorder) = &mut self.recorder
+            && let Err(err) = recorder.record(entry)
+        {
+            error!("Failed to record the session, stopped recording: {err}");
+            self.recorder = None;
+        }
+    }
+
+    /// The findings as of the last handled event.
+    pub fn findings(&self) -> &[Finding] {
+        &self.state.findings
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.is_running
+    }
+
+    /// Where events for the app can be sent from other threads, like API calls.
+    pub fn event_sender(&self) -> Sender<Event> {
+        self.event_handler.sender()
+    }
+
+    fn handle_event(&mut self, event: Event) -> color_eyre::Result<()> {
+        // Entries copy whole files, so they're only made while recording
+        if self.recorder.is_some()
+            && let Some(entry) = Entry::from_event(&event)
+        {
+            self.record(entry);
+        }
+
+        match event {
             Event::Tick => self.tick(),
-            Event::Crossterm(event) => {
-                if let CrosstermEvent::Key(key_event) = event {
-                    self.handle_key_event(key_event)?;
-                }
+            Event::Crossterm(event) => match event {
+                CrosstermEvent::Key(key_event) => self.handle_key_event(key_event)?,
+                CrosstermEvent::Paste(text) => {
+                    if let Some(import) = &mut self.state.import
+                        && !import.reviewing
+                    {
+                        import.text.push_str(&text.replace("\r\n", "\n").replace('\r', "\n"));
+                    }
+                },
+                CrosstermEvent::Mouse(mouse_event) => self.handle_mouse_event(mouse_event)?,
+                _ => {},
             },
             Event::App(app_event) => match app_event {
-                AppEvent::FileSystemChanged(change_kind) => {
+                // Read before the last hard refresh, and possibly since overwritten
+                AppEvent::FileSystemChanged(generation, _) if generation != self.generation => {},
+                AppEvent::FileSystemChanged(_, change_kind) => {
+                    if let FileSystemChangeKind::RemoveFile(path) = &change_kind {
+                        self.state.forget_load(path);
+                    }
+
                     match change_kind {
-                        // /etc/subuid and /etc/subgid are permanent and cannot be removed, so we assume it's a config
-                        FileSystemChangeKind::RemoveFile(path) => self.unload_container_id_map(&path)?,
+                        // Nothing arrived yet, so there is nothing to evaluate
+                        FileSystemChangeKind::ReadStarted(path) => {
+                            self.state.mark_loading(path);
+
+                            return Ok(());
+                        },
+                        FileSystemChangeKind::ReadFailed(path, err) => {
+                            error!("Failed to read {}: {err}", path.display());
+                            self.state.mark_failed(path, err);
+                        },
+                        FileSystemChangeKind::RemoveFile(path) if path == self.metadata.lxc_default_config => {
+                            self.state.unload_lxc_defaults();
+                        },
+                        FileSystemChangeKind::RemoveFile(path) if path == self.metadata.passwd_path => {
+                            self.state.unload_passwd();
+                        },
+                        FileSystemChangeKind::RemoveFile(path) if path == self.metadata.group_path => {
+                            self.state.unload_group();
+                        },
+                        FileSystemChangeKind::RemoveFile(path) => match self.metadata.subid_for_path(&path) {
+                            Some(sub_id) => self.state.unload_subid(sub_id),
+                            // Anything else removed is assumed to be a config
+                            None => {
+                                if let Some(filename) = path.file_name().and_then(|f| f.to_str()) {
+                                    self.known_configs.remove(filename);
+                                }
+
+                                self.state.unload_config(&path)?;
+                                self.bus.include_watches.publish((path, Vec::new()));
+                            },
+                        },
                         FileSystemChangeKind::UpdateFile(path, content) => {
+                            self.state.mark_loaded(path.clone());
+
                             if path.starts_with(&self.metadata.lxc_config_dir) {
-                                self.load_container_id_map(&path, &content)?;
-                            } else if path == Path::new(ETC_SUBUID) {
-                                self.load_subid(&content, SubID::UID)?;
-                            } else if path == Path::new(ETC_SUBGID) {
-                                self.load_subid(&content, SubID::GID)?;
+                                self.queue_readiness_probe(&path);
+
+                                let inspects_rootfs = self.state.inspects_rootfs();
+
+                                if let Some(rootfs_value) = self.state.load_config(&path, &content)?
+                                    && inspects_rootfs
+                                {
+                                    self.bus.rootfs_watches.publish(rootfs_value.to_owned());
+                                }
+
+                                if inspects_rootfs {
+                                    for value in self.state.mount_point_values(&path) {
+                                        self.bus.rootfs_watches.publish(value);
+                                    }
+                                }
+
+                                let includes = self.state.include_paths(&path);
+
+                                self.bus.include_watches.publish((path, includes));
+                            } else if let Some(sub_id) = self.metadata.subid_for_path(&path) {
+                                self.state.load_subid(&content, sub_id)?;
+                                self.state.load_shadow_backup(sub_id, read_shadow_backup(&path));
+                            } else if path == self.metadata.lxc_default_config {
+                                self.state.load_lxc_defaults(&path, &content)?;
+                            } else if path == self.metadata.passwd_path {
+                                self.state.load_passwd(&content);
+                            } else if path == self.metadata.group_path {
+                                self.state.load_group(&content);
                             }
                         },
-                        FileSystemChangeKind::UpdateDir(rootfs_value, path, metadata) => {
-                            self.load_rootfs_metadata(rootfs_value, path, metadata);
+                        FileSystemChangeKind::UpdateIncusInstance(filename, content) => {
+                            let inspects_rootfs = self.state.inspects_rootfs();
+
+                            if let Some(rootfs_value) = self.state.load_config(Path::new(&filename), &content)?
+                                && inspects_rootfs
+                            {
+                                self.bus.rootfs_watches.publish(rootfs_value.to_owned());
+                            }
+                        },
+                        FileSystemChangeKind::UpdateDir(value, location, metadata) => {
+                            self.state.load_dir_metadata(value, location, *metadata);
+                        },
+                        FileSystemChangeKind::DirUnavailable(value, reason) => {
+                            self.state.unavailable_dirs.insert(value, reason);
+                        },
+                        FileSystemChangeKind::UpdateDiskSpace(rootfs_value, space) => {
+                            self.state.rootfs_space.insert(rootfs_value, space);
+                        },
+                        FileSystemChangeKind::OwnershipScanned(rootfs_value, scan) => {
+                            self.state.load_ownership_scan(rootfs_value, *scan);
+                        },
+                        FileSystemChangeKind::ConfigDirLost(path) => {
+                            self.state.config_dir_unavailable = Some(path);
+                        },
+                        FileSystemChangeKind::ConfigDirRestored(_) => {
+                            self.state.config_dir_unavailable = None;
+                            self.queue_config_dir()?;
                         },
                     };
 
+                    // A host only partly loaded would look like it is missing whatever the rest holds
+                    if self.state.is_loading() {
+                        return Ok(());
+                    }
+
                     self.state.evaluate_findings();
+
+                    if self.recorder.is_some() {
+                        let findings = &self.state.findings;
+                        let entry = Entry::Evaluated {
+                            findings: findings.len(),
+                            bad: findings.iter().filter(|f| f.kind == FindingKind::Bad).count(),
+                        };
+
+                        self.record(entry);
+                    }
+
+                    self.record_history();
+                    self.run_readiness_probes();
                 },
+                AppEvent::Notify(Notification { level, message }) => log!(level, "{message}"),
+                AppEvent::Rpc(call) => self.handle_rpc(call),
                 AppEvent::Quit => self.quit(),
             },
         }
         Ok(())
     }
 
-    fn load_rootfs_metadata(&mut self, rootfs_value: String, path: PathBuf, metadata: std::fs::Metadata) {
-        self.state.rootfs_info.insert(rootfs_value, (path, metadata));
-        self.state.rootfs_info.sort_unstable_keys();
+    /// Queues reading every file, which is otherwise only read once it changes.
+    pub fn initialize(&mut self) -> color_eyre::Result<()> {
+        self.queue_host_files();
+
+        if self.metadata.incus {
+            incus::discover(self.bus.clone());
+        }
+
+        self.queue_config_dir()
     }
 
-    fn load_container_id_map(&mut self, path: &Path, content: &str) -> color_eyre::Result<()> {
-        let filename = path
-            .file_name()
-            .and_then(|f| f.to_str())
-            .ok_or_else(|| eyre!("Invalid file name"))?;
-        let config = Config::from_str(content)?;
-        let section = config.section(None);
+    /// Queues reading the subid files, the LXC defaults and the host's users and groups.
+    fn queue_host_files(&mut self) {
+        self.queue_read(self.metadata.subuid_path.clone());
+        self.queue_read(self.metadata.subgid_path.clone());
 
-        if let Some(rootfs_value) = section.get_rootfs() {
-            self.monitor.watch_rootfs(rootfs_value)?;
+        for path in [
+            self.metadata.lxc_default_config.clone(),
+            self.metadata.passwd_path.clone(),
+            self.metadata.group_path.clone(),
+        ] {
+            if path.exists() {
+                self.queue_read(path);
+            }
         }
+    }
 
-        self.state.lxc_configs.insert(CompactString::new(filename), config);
-        self.state.lxc_configs.sort_unstable_keys();
+    /// Queues reading every container config in the config directory.
+    fn queue_config_dir(&mut self) -> color_eyre::Result<()> {
+        for path in self.list_config_dir()? {
+            self.queue_read(path);
+        }
 
         Ok(())
     }
 
-    fn unload_container_id_map(&mut self, path: &Path) -> color_eyre::Result<()> {
-        let filename = path
-            .file_name()
-            .and_then(|f| f.to_str())
-            .ok_or_else(|| eyre!("Invalid file name"))?;
-        let Some(config) = self.state.lxc_configs.shift_remove(filename) else {
-            warn!("Attempted to unload container ID map for non-existent file: {filename}");
-            return Ok(());
+    /// The container configs in the config directory, which are known from then on. Those which
+    /// were known before and are gone now, e.g. removed while /etc/pve was unmounted, are
+    /// unloaded.
+    fn list_config_dir(&mut self) -> color_eyre::Result<Vec<PathBuf>> {
+        let previous = std::mem::take(&mut self.known_configs);
+        // A missing directory is flagged by the monitor, which has it read once it appears
+        let entries = match read_dir(&self.metadata.lxc_config_dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                error!(
+                    "Failed to list the container configs in {}: {err}",
+                    self.metadata.lxc_config_dir.display()
+                );
+                self.known_configs = previous;
+
+                return Ok(Vec::new());
+            },
         };
-        let section = config.section(None);
+        let mut paths = Vec::new();
 
-        if let Some(rootfs) = section.get_rootfs() {
-            if self.state.rootfs_info.shift_remove(rootfs).is_none() {
-                warn!("Attempted to unload rootfs info for non-existent file: {filename}");
-                return Ok(());
-            };
+        for entry in entries {
+            let path = entry?.path();
+
+            if is_container_config(&path) {
+                if let Some(filename) = path.file_name().and_then(|f| f.to_str()) {
+                    self.known_configs.insert(filename.into());
+                }
+
+                paths.push(path);
+            }
         }
 
-        Ok(())
+        for filename in previous.difference(&self.known_configs) {
+            let path = self.metadata.lxc_config_dir.join(filename.as_str());
+
+            self.state.forget_load(&path);
+
+            if self.state.lxc_configs.contains_key(filename.as_str()) {
+                self.state.unload_config(&path)?;
+            }
+        }
+
+        Ok(paths)
     }
 
-    fn load_subid(&mut self, content: &str, subid: SubID) -> color_eyre::Result<()> {
-        let id_map = parse_subid_map(content)?;
+    /// Has the reader read `path`, which counts as pending until it did.
+    fn queue_read(&mut self, path: PathBuf) {
+        self.state.queue_load(path.clone());
+        self.bus.file_reads.publish(path);
+    }
+
+    /// Remembers to audit a config which appeared since everything was loaded, if its container
+    /// was never started.
+    fn queue_readiness_probe(&mut self, path: &Path) {
+        let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+            return;
+        };
 
-        match subid {
-            SubID::UID => self.state.host_mapping.subuid = id_map,
-            SubID::GID => self.state.host_mapping.subgid = id_map,
+        if !self.known_configs.insert(filename.into()) {
+            return;
         }
 
-        Ok(())
+        let vmid = filename.strip_suffix(".conf").unwrap_or(filename);
+
+        if !self.metadata.was_started(vmid) {
+            self.readiness_probes.push(filename.into());
+        }
     }
 
-    fn initialize(&mut self) -> color_eyre::Result<()> {
-        self.fs_reader_tx.send(PathBuf::from(ETC_SUBUID))?;
-        self.fs_reader_tx.send(PathBuf::from(ETC_SUBGID))?;
+    /// Reports the audit of every queued config whose rootfs has been loaded by now.
+    fn run_readiness_probes(&mut self) {
+        let state = &self.state;
+        let bus = &self.bus;
 
-        for entry in read_dir(&self.metadata.lxc_config_dir)? {
-            let path = entry?.path();
+        self.readiness_probes.retain(|filename| {
+            // Removed again before it could be audited, or privileged and so not using subordinate ids
+            let Some(config) = state.lxc_configs.get(filename) else {
+                return false;
+            };
 
-            if is_valid_file(&path) {
-                self.fs_reader_tx.send(path)?;
+            if !state
+                .dialect
+                .is_unprivileged(&config.section(None), &state.default_idmaps)
+            {
+                return false;
             }
+
+            let Some(readiness) = state.readiness(filename) else {
+                return true;
+            };
+
+            bus.notifications.publish(Notification {
+                level: if readiness.blockers.is_empty() {
+                    Level::Info
+                } else {
+                    Level::Warn
+                },
+                message: readiness.summary(),
+            });
+
+            false
+        });
+    }
+
+    /// Throws away everything read so far and starts over as if pupman was just launched, for when
+    /// the monitor drifted from the files, e.g. after missed events or a remount of /etc/pve.
+    fn hard_refresh(&mut self) -> color_eyre::Result<()> {
+        // Closing the bus stops the old workers. Whatever they already queued is tagged with the old
+        // generation and ignored.
+        self.bus.close();
+        self.stop_deep_scan();
+        self.stop_ownership_shift();
+        self.generation += 1;
+        self.bus = start_workers(&self.event_handler, self.generation);
+        self.metadata.reload_storage();
+
+        match MonitorHandler::new(self.bus.clone(), &self.metadata, self.state.settings.event_debounce()) {
+            Ok(monitor) => self.monitor = monitor,
+            Err(err) => error!("Failed to restart the file system monitor, changes won't show up live: {err}"),
         }
 
+        self.readiness_probes.clear();
+        self.state = State {
+            settings: self.state.settings.clone(),
+            rootfs_checks: self.state.rootfs_checks,
+            dialect: self.state.dialect,
+            root_prefix: self.state.root_prefix.clone(),
+            operator_uid: self.state.operator_uid,
+            runs_as_root: self.state.runs_as_root,
+            polling_files: self.monitor.is_polling(),
+            stats: std::mem::take(&mut self.state.stats),
+            ..State::default()
+        };
+        self.initialize()?;
+        info!("Reloaded everything from scratch");
+
         Ok(())
     }
 
-    /// Handles the key events and updates the state of [`App`].
-    pub fn handle_key_event(&mut self, key_event: KeyEvent) -> color_eyre::Result<()> {
-        // If the fix popup is shown, handle the key events for the fix popup.
-        if self.state.show_fix_popup {
-            match key_event.code {
-                KeyCode::Esc => self.state.show_fix_popup = false,
-                _ => {},
-            }
+    /// Reads every file again and lists the config directory anew, keeping everything else such as
+    /// the selection and queued changes. For files which changed while the monitor wasn't watching
+    /// them, which a restarted monitor watches again. Rootfs values are resolved again through the
+    /// storage config as it is now.
+    fn rescan(&mut self) -> color_eyre::Result<()> {
+        self.metadata.reload_storage();
 
-            return Ok(());
+        match MonitorHandler::new(self.bus.clone(), &self.metadata, self.state.settings.event_debounce()) {
+            Ok(monitor) => {
+                self.monitor = monitor;
+                self.state.polling_files = self.monitor.is_polling();
+            },
+            Err(err) => error!("Failed to restart the file system monitor, changes won't show up live: {err}"),
         }
 
-        // If the explain popup is shown, handle the key events for the popup.
-        if self.state.show_explain_popup {
-            match key_event.code {
-                KeyCode::Esc => self.state.show_explain_popup = false,
-                _ => {},
-            }
+        let known = self.known_configs.clone();
 
-            return Ok(());
+        self.queue_host_files();
+
+        if self.metadata.incus {
+            incus::discover(self.bus.clone());
         }
 
-        // If the settings page is shown, handle the key events for the settings page.
-        if self.state.show_settings_page {
-            match key_event.code {
-                KeyCode::Esc => self.state.show_settings_page = false,
-                _ => {},
+        // Read right away rather than queued, so the rootfs values resolved below are the ones the
+        // configs hold now
+        let mut configs = Vec::new();
+
+        for path in self.list_config_dir()? {
+            match read_to_string(&path) {
+                Ok(content) => configs.push((path, content)),
+                Err(err) => {
+                    error!("Failed to read {}: {err}", path.display());
+                    self.state.mark_failed(path, err.to_string());
+                },
             }
+        }
 
-            return Ok(());
+        let values = self.state.reload_configs(&configs)?;
+
+        for (path, _) in configs {
+            let includes = self.state.include_paths(&path);
+
+            self.bus.include_watches.publish((path, includes));
         }
 
-        // If the logs page is shown, handle the key events for the logger page.
-        if self.state.show_logs_page {
-            let state = &self.state.logger_page_state;
-
-            match key_event.code {
-                KeyCode::Esc => self.state.show_logs_page = false,
-                KeyCode::Char(' ') => state.transition(TuiWidgetEvent::SpaceKey),
-                KeyCode::Char('q') => state.transition(TuiWidgetEvent::EscapeKey),
-                KeyCode::PageUp => state.transition(TuiWidgetEvent::PrevPageKey),
-                KeyCode::PageDown => state.transition(TuiWidgetEvent::NextPageKey),
-                KeyCode::Up => state.transition(TuiWidgetEvent::UpKey),
-                KeyCode::Down => state.transition(TuiWidgetEvent::DownKey),
-                KeyCode::Left => state.transition(TuiWidgetEvent::LeftKey),
-                KeyCode::Right => state.transition(TuiWidgetEvent::RightKey),
-                KeyCode::Char('+') => state.transition(TuiWidgetEvent::PlusKey),
-                KeyCode::Char('-') => state.transition(TuiWidgetEvent::MinusKey),
-                KeyCode::Char('h') => state.transition(TuiWidgetEvent::HideKey),
-                KeyCode::Char('f') => state.transition(TuiWidgetEvent::FocusKey),
-                _ => {},
-            }
+        let added = self.known_configs.difference(&known).count();
+        let removed = known.difference(&self.known_configs).count();
+        let mut message = format!(
+            "Rescanned {}, {}, users, groups and {} container configs ({added} new, {removed} gone)",
+            self.metadata.subuid_path.display(),
+            self.metadata.subgid_path.display(),
+            self.known_configs.len()
+        );
 
-            return Ok(());
+        if self.state.inspects_rootfs() {
+            let _ = write!(
+                message,
+                ", resolving {} rootfs and mount point paths again",
+                values.len()
+            );
+
+            for value in values {
+                self.bus.rootfs_watches.publish(value);
+            }
         }
 
-        // Handle the key events for the main application.
-        match key_event.code {
-            // TODO: Prompt for confirmation before quitting. Esc should cancel the prompt for consistency.
-            // Enter or y to confirm quitting.
-            KeyCode::Esc => self.event_handler.send(AppEvent::Quit),
-            KeyCode::Char('c' | 'C') if key_event.modifiers == KeyModifiers::CONTROL => {
-                self.event_handler.send(AppEvent::Quit)
-            },
-            KeyCode::Char('f') if !self.state.show_fix_popup => {
-                if let Some(finding) = self.selected_finding() {
-                    if finding.kind == FindingKind::Bad {
-                        self.state.show_fix_popup = true;
-                    }
-                }
-            },
-            KeyCode::Char('e') if !self.state.show_explain_popup => {
-                if let Some(finding) = self.selected_finding() {
-                    if finding.kind == FindingKind::Bad {
-                        self.state.show_explain_popup = true;
-                    }
-                }
-            },
-            KeyCode::Char('l') => {
-                self.state.show_logs_page = true;
-            },
-            KeyCode::Char('s') => {
-                self.state.show_settings_page = true;
-            },
-            KeyCode::Up => {
-                if self.state.findings.is_empty() {
-                    return Ok(());
-                }
+        self.bus.notifications.publish(Notification {
+            level: Level::Info,
+            message,
+        });
 
-                if let Some(index) = self.state.selected_finding {
-                    if index > 0 {
-                        self.state.selected_finding = Some(index - 1);
-                    } else {
-                        self.state.selected_finding = None;
-                    }
-                } else {
-                    self.state.selected_finding = Some(self.state.findings.len() - 1);
-                }
-            },
-            KeyCode::Down => {
-                if self.state.findings.is_empty() {
-                    return Ok(());
-                }
+        Ok(())
+    }
 
-                if let Some(index) = self.state.selected_finding {
-                    if index < self.state.findings.len() - 1 {
-                        self.state.selected_finding = Some(index + 1);
-                    } else {
-                        self.state.selected_finding = None;
-                    }
-                } else {
-                    self.state.selected_finding = Some(0);
-                }
-            },
-            KeyCode::PageUp => {
-                if self.state.findings.is_empty() {
-                    return Ok(());
-                }
+    /// The wheel scrolls whatever the arrow keys would. A left click in the main view selects the
+    /// finding under it, or the first one highlighting the mapping under it.
+    pub fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> color_eyre::Result<()> {
+        match mouse_event.kind {
+            MouseEventKind::ScrollUp => self.handle_key_event(KeyCode::Up.into()),
+            MouseEventKind::ScrollDown => self.handle_key_event(KeyCode::Down.into()),
+            MouseEventKind::Down(MouseButton::Left) if self.is_main_view() => {
+                let position = Position::new(mouse_event.column, mouse_event.row);
 
-                self.state.selected_finding = Some(0);
-            },
-            KeyCode::PageDown => {
-                if self.state.findings.is_empty() {
-                    return Ok(());
+                if let Some(index) = self.finding_at(self.frame_area, position) {
+                    self.state.selected_finding = Some(index);
+                    self.focus_findings();
                 }
 
-                self.state.selected_finding = Some(self.state.findings.len() - 1);
+                Ok(())
             },
-            _ => {},
+            _ => Ok(()),
         }
-        Ok(())
     }
 
-    /// Handles the tick event of the terminal.
-    ///
-    /// The tick event is where you can update the state of your application with any logic that
-    /// needs to be updated at a fixed frame rate. E.g. polling a server, updating an animation.
-    pub fn tick(&self) {}
+    /// Moves the selection through the findings the filter leaves. Up and Down pass through having
+    /// nothing selected on their way around.
+    fn move_finding_selection(&mut self, code: KeyCode) {
+        let visible = self.state.visible_findings();
+        let Some(last) = visible.len().checked_sub(1) else {
+            return;
+        };
+        let position = self
+            .state
+            .selected_finding
+            .and_then(|selected| visible.iter().position(|i| *i == selected));
+        let position = match (code, position) {
+            (KeyCode::Up, Some(0)) => None,
+            (KeyCode::Up, Some(position)) => Some(position - 1),
+            (KeyCode::Up, None) => Some(last),
+            (KeyCode::Down, Some(position)) if position < last => Some(position + 1),
+            (KeyCode::Down, Some(_)) => None,
+            (KeyCode::Down, None) => Some(0),
+            (KeyCode::PageUp, position) => Some(position.unwrap_or_default().saturating_sub(FINDINGS_PAGE)),
+            (KeyCode::PageDown, position) => Some(position.map_or(0, |position| position + FINDINGS_PAGE).min(last)),
+            (KeyCode::Home, _) => Some(0),
+            (KeyCode::End, _) => Some(last),
+            (_, position) => position,
+        };
 
-    /// Set running to false to quit the application.
-    pub fn quit(&mut self) {
-        self.state.is_running = false;
+        self.state.selected_finding = position.map(|position| visible[position]);
     }
 
-    fn selected_finding(&self) -> Option<&Finding> {
-        self.state
-            .selected_finding
-            .and_then(|index| self.state.findings.get(index))
+    /// Moves focus `delta` panels on. The config panel picks a container as it gets focus, the one
+    /// of the selected finding if there is one.
+    fn cycle_focus(&mut self, delta: isize) {
+        let focus = self.state.focus.cycle(delta);
+
+        self.state.focus = focus;
+        self.state.selected_container = None;
+
+        if focus == Focus::LXCConfig {
+            let containers = self.state.panel_containers();
+            let selected = self
+                .selected_finding()
+                .and_then(|finding| finding.lxc_config_mapping_highlights.first())
+                .and_then(|(filename, _)| containers.iter().position(|f| *f == filename));
+
+            if !containers.is_empty() {
+                self.state.selected_container = Some(selected.unwrap_or(0));
+            }
+        }
     }
-}
 
-fn parse_subid_map(content: &str) -> color_eyre::Result<Vec<IdMapEntry>> {
-    let mut id_map = Vec::new();
+    fn focus_findings(&mut self) {
+        self.state.focus = Focus::Findings;
+        self.state.selected_container = None;
+    }
 
-    for line in content.lines() {
-        let trimmed = line.trim();
+    /// Whether the panels and findings are shown without a page, popup or editor over them.
+    fn is_main_view(&self) -> bool {
+        let state = &self.state;
 
-        if trimmed.is_empty() {
-            continue;
+        state.follow_up.is_none()
+            && state.write_preview.is_none()
+            && state.ownership_shift.is_none()
+            && state.import.is_none()
+            && state.subid_editor.is_none()
+            && state.idmap_editor.is_none()
+            && state.idmap_wizard.is_none()
+            && state.conversion.is_none()
+            && !state.show_changes_page
+            && !state.show_fix_popup
+            && !state.show_explain_popup
+            && !state.show_report_popup
+            && !state.show_settings_page
+            && state.source_view.is_none()
+            && state.container_detail.is_none()
+            && !state.show_stats_page
+            && !state.show_checks_page
+            && !state.show_logs_page
+    }
+
+    /// Enables or disables a check, persists the choice and re-evaluates findings.
+    fn toggle_check(&mut self, check: Check) {
+        let settings = &mut self.state.settings;
+
+        settings.set_enabled(check, !settings.is_enabled(check));
+
+        if let Err(err) = settings.save() {
+            error!("Failed to save settings: {err:?}");
         }
 
-        let mut iter = trimmed.split(':');
-        let host_user_id = CompactString::new(iter.next().ok_or_eyre("user id not found")?);
-        let host_sub_id: u32 = iter.next().ok_or_eyre("host sub id not found")?.parse()?;
-        let host_sub_id_count: u32 = iter
-            .next()
-            .ok_or_eyre("host sub id host_sub_id_count not found")?
-            .parse()?;
+        self.state.selected_finding = None;
+        self.state.evaluate_findings();
+        self.record_history();
+    }
 
-        id_map.push(IdMapEntry {
-            host_user_id,
-            host_sub_id,
-            host_sub_id_count,
-        });
+    /// Turns checking snapshot sections on or off, persists the choice and re-evaluates findings.
+    fn toggle_check_snapshots(&mut self) {
+        let settings = &mut self.state.settings;
+
+        settings.set_check_snapshots(!settings.check_snapshots());
+
+        if let Err(err) = settings.save() {
+            error!("Failed to save settings: {err:?}");
+        }
+
+        self.state.selected_finding = None;
+        self.state.evaluate_findings();
+        self.record_history();
+    }
+
+    /// Turns rootfs inspection on or off, persists the choice and re-evaluates findings. Rootfs
+    /// directories which weren't watched before start being watched now.
+    /// Whether a deep scan is still going.
+    pub fn is_deep_scanning(&self) -> bool {
+        self.deep_scan.as_ref().is_some_and(|scan| scan.is_running())
+    }
+
+    /// Starts a deep scan of every rootfs that was found, or cancels the one which is running.
+    fn toggle_deep_scan(&mut self) {
+        if self.is_deep_scanning() {
+            self.stop_deep_scan();
+            return;
+        }
+
+        if !self.state.inspects_rootfs() {
+            self.bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: "Rootfs directories aren't inspected, so there's nothing to scan".to_string(),
+            });
+            return;
+        }
+
+        let targets = self.state.deep_scan_targets();
+
+        if targets.is_empty() {
+            self.bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: "No rootfs directory of an unprivileged container was found to scan".to_string(),
+            });
+            return;
+        }
+
+        info!("Deep scanning {} rootfs directories", targets.len());
+        self.deep_scan = Some(DeepScan::start(targets, self.bus.clone()));
+    }
+
+    fn stop_deep_scan(&mut self) {
+        if let Some(scan) = self.deep_scan.take() {
+            scan.cancel();
+        }
+    }
+
+    fn stop_ownership_shift(&mut self) {
+        if let Some(job) = self.state.ownership_shift.as_ref().and_then(|shift| shift.job.as_ref()) {
+            job.cancel();
+        }
+    }
+
+    fn toggle_inspect_rootfs(&mut self) {
+        let settings = &mut self.state.settings;
+
+        settings.set_inspect_rootfs(!settings.inspect_rootfs());
+
+        if let Err(err) = settings.save() {
+            error!("Failed to save settings: {err:?}");
+        }
+
+        if self.state.inspects_rootfs() {
+            for config in self.state.lxc_configs.values() {
+                let section = config.section(None);
+
+                if let Some(rootfs_value) = section.get_rootfs() {
+                    self.bus.rootfs_watches.publish(rootfs_value.to_owned());
+                }
+
+                for mount in section.mount_points() {
+                    self.bus.rootfs_watches.publish(mount.value.to_owned());
+                }
+            }
+        }
+
+        self.state.selected_finding = None;
+        self.state.evaluate_findings();
+        self.record_history();
+    }
+
+    /// Adds the selected entries to the host mapping editor the import dialog was opened from,
+    /// where they are saved along with every other edit.
+    fn import_into_editor(&mut self) {
+        let (Some(import), Some(editor)) = (&self.state.import, &mut self.state.subid_editor) else {
+            return;
+        };
+        let imported = editor.import(import);
+        let notification = if imported == 0 {
+            Notification {
+                level: Level::Info,
+                message: "No entries selected to import".to_string(),
+            }
+        } else {
+            let message = format!(
+                "Added {imported} entries for {}, press w to save them",
+                import.target.name()
+            );
+
+            self.state.stats.entries_imported += imported;
+            self.state.import = None;
+
+            Notification {
+                level: Level::Info,
+                message,
+            }
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Opens the host mapping editor on the entries as the queued changes leave them, so saving
+    /// the edits builds on those instead of replacing them.
+    fn open_subid_editor(&mut self) {
+        let mut host_mapping = self.state.host_mapping.clone();
+
+        for sub_id in [SubID::UID, SubID::GID] {
+            if let Some(content) = self.state.changes.planned(self.metadata.subid_path(sub_id)) {
+                host_mapping.load(sub_id, content);
+            }
+        }
+
+        self.state.subid_editor = Some(SubidEditor::new(&host_mapping));
+    }
+
+    /// Checks the edited entries and shows the diff of each file whose ranges changed.
+    fn preview_subid_edits(&mut self) {
+        let Some(editor) = &self.state.subid_editor else {
+            return;
+        };
+        let problems = editor.problems();
+        let changed: Vec<_> = [SubID::UID, SubID::GID]
+            .into_iter()
+            .filter(|sub_id| editor.changed(*sub_id))
+            .collect();
+        let notification = if self.metadata.is_viewer_only() {
+            Notification {
+                level: Level::Warn,
+                message: "Entries cannot be edited in files inspected with --root-prefix".to_string(),
+            }
+        } else if let Some(problem) = problems.first() {
+            Notification {
+                level: Level::Warn,
+                message: format!("Not saving, {problem}"),
+            }
+        } else if changed.is_empty() {
+            self.state.subid_editor = None;
+
+            Notification {
+                level: Level::Info,
+                message: "No ranges were changed".to_string(),
+            }
+        } else {
+            let writes: color_eyre::Result<Vec<_>> = changed
+                .iter()
+                .map(|sub_id| {
+                    let path = self.metadata.subid_path(*sub_id);
+                    let proposed = editor.content(*sub_id);
+
+                    // The editor was opened on what the queue leaves in the file, which is what the write replaces
+                    match self.state.changes.planned(path) {
+                        Some(current) => Ok(PendingWrite {
+                            path: path.to_path_buf(),
+                            current: current.to_string(),
+                            proposed,
+                        }),
+                        None => PendingWrite::new(path, proposed),
+                    }
+                })
+                .collect();
+
+            match writes {
+                Ok(writes) => {
+                    self.state.write_preview = Some(WritePreview::new(
+                        "Save subuid/subgid entries?",
+                        PreviewAction::SubidEdits(changed),
+                        writes,
+                    ));
+
+                    return;
+                },
+                Err(err) => Notification {
+                    level: Level::Error,
+                    message: format!("Failed to save entries: {err:?}"),
+                },
+            }
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Writes the previewed entries and closes the editor. The file system monitor picks up the
+    /// change from there.
+    fn save_subid_edits(&mut self, changed: &[SubID], writes: &[PendingWrite]) {
+        // Entries imported into both files shouldn't end up in only one of them
+        let notification = match commit_together(writes, || Ok(())) {
+            Ok(()) => {
+                let changes: Vec<_> = changed.iter().copied().map(Change::SubidRangesEdited).collect();
+                let paths: Vec<_> = changed
+                    .iter()
+                    .map(|sub_id| self.metadata.subid_path(*sub_id).display().to_string())
+                    .collect();
+
+                self.state.subid_editor = None;
+                self.state.follow_up = Some(checklist(&changes, &self.state.affected_vmids(&self.metadata, writes)));
+
+                Notification {
+                    level: Level::Info,
+                    message: format!("Saved {}", paths.join(" and ")),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to save entries: {err:?}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
     }
 
-    Ok(id_map)
+    /// Checks the edited idmaps and shows the diff of the container's config with them in place of
+    /// its old ones. Other lines and comments are left where they were.
+    fn preview_idmap_edits(&mut self) {
+        let Some(editor) = &self.state.idmap_editor else {
+            return;
+        };
+        let problems = editor.problems();
+        let notification = if self.metadata.is_viewer_only() {
+            Notification {
+                level: Level::Warn,
+                message: "Configs cannot be edited when inspected with --root-prefix".to_string(),
+            }
+        } else if let Some(problem) = problems.first() {
+            Notification {
+                level: Level::Warn,
+                message: format!("Not saving, {problem}"),
+            }
+        } else if !editor.changed() {
+            self.state.idmap_editor = None;
+
+            Notification {
+                level: Level::Info,
+                message: "No idmaps were changed".to_string(),
+            }
+        } else {
+            let values = editor.values();
+            let values: Vec<_> = values.iter().map(String::as_str).collect();
+            let path = self.metadata.lxc_config_dir.join(&*editor.filename);
+            let write = self
+                .state
+                .changes
+                .read(&path)
+                .and_then(|content| fix::config_edits_write(&path, content, &[("lxc.idmap", &values)]));
+
+            match write {
+                Ok(write) => {
+                    let mut preview =
+                        WritePreview::new("Save lxc.idmap entries?", PreviewAction::IdmapEdits, vec![write]);
+
+                    // LXC refuses to start the container with overlaps, but saving is still allowed since it
+                    // may take more than one edit to line them up
+                    preview.notes = editor
+                        .coverage()
+                        .into_iter()
+                        .map(|(sub_id, issue)| format!("Note, {}s: {issue}", sub_id.kind_name()))
+                        .collect();
+                    self.state.write_preview = Some(preview);
+
+                    return;
+                },
+                Err(err) => Notification {
+                    level: Level::Error,
+                    message: format!("Failed to save idmaps: {err:?}"),
+                },
+            }
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Writes the previewed config and closes the editor.
+    fn save_idmap_edits(&mut self, writes: &[PendingWrite]) {
+        let Some(editor) = &self.state.idmap_editor else {
+            return;
+        };
+        let notification = match writes.iter().try_for_each(PendingWrite::commit) {
+            Ok(()) => {
+                let vmid = editor.filename.trim_end_matches(".conf").to_string();
+                let changes = [Change::ConfigEdited { vmid }];
+                let paths: Vec<_> = writes.iter().map(|write| write.path.display().to_string()).collect();
+
+                self.state.idmap_editor = None;
+                self.state.follow_up = Some(checklist(&changes, &[]));
+
+                Notification {
+                    level: Level::Info,
+                    message: format!("Saved {}", paths.join(" and ")),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to save idmaps: {err:?}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Shows the diff of the config and subid files with the wizard's mapping in place. The
+    /// container's own idmaps of the intended kinds are replaced and root's missing ranges appended.
+    fn preview_generated_mapping(&mut self) {
+        let Some(wizard) = &self.state.idmap_wizard else {
+            return;
+        };
+        let notification = if self.metadata.is_viewer_only() {
+            Notification {
+                level: Level::Warn,
+                message: "Configs cannot be edited when inspected with --root-prefix".to_string(),
+            }
+        } else {
+            let writes = self.state.generate_mapping(wizard).and_then(|generated| {
+                let mut writes = Vec::new();
+
+                for sub_id in [SubID::UID, SubID::GID] {
+                    let entries: Vec<_> = generated
+                        .subid_entries
+                        .iter()
+                        .filter(|(kind, _)| *kind == sub_id)
+                        .map(|(_, entry)| entry.clone())
+                        .collect();
+
+                    if entries.is_empty() {
+                        continue;
+                    }
+
+                    let path = self.metadata.subid_path(sub_id);
+                    let content = self.state.changes.read(path)?;
+
+                    writes.push(PendingWrite {
+                        path: path.to_path_buf(),
+                        proposed: append_entries(&content, &entries),
+                        current: content,
+                    });
+                }
+
+                let values = generated.values();
+                let values: Vec<_> = values.iter().map(String::as_str).collect();
+                let path = self.metadata.lxc_config_dir.join(&*generated.filename);
+                let content = self.state.changes.read(&path)?;
+
+                writes.push(fix::config_edits_write(&path, content, &[("lxc.idmap", &values)])?);
+
+                Ok((generated, writes))
+            });
+
+            match writes {
+                Ok((generated, writes)) => {
+                    let mut preview = WritePreview::new(
+                        "Apply generated mapping?",
+                        PreviewAction::GeneratedMapping(generated.clone()),
+                        writes,
+                    );
+
+                    if let Some((path, uid, gid)) = generated.rootfs.as_ref().filter(|_| generated.rootfs_needs_shift())
+                    {
+                        preview.notes.push(format!(
+                            "Note, {} is owned by {uid}:{gid} and isn't changed here, it should be owned by {}.",
+                            path.display(),
+                            generated.expected_owner_name()
+                        ));
+                    }
+
+                    if !generated.overlaps.is_empty() {
+                        preview.notes.push(format!(
+                            "Warning: the host ids are also mapped by {}.",
+                            generated.overlaps.join(", ")
+                        ));
+                    }
+
+                    self.state.write_preview = Some(preview);
+
+                    return;
+                },
+                Err(err) => Notification {
+                    level: Level::Warn,
+                    message: format!("Failed to generate the mapping: {err:?}"),
+                },
+            }
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Writes the previewed files and closes the wizard, leaving the rootfs ownership to the
+    /// follow-up steps.
+    fn apply_generated_mapping(&mut self, generated: &GeneratedMapping, writes: &[PendingWrite]) {
+        let notification = match commit_together(writes, || Ok(())) {
+            Ok(()) => {
+                let vmid = generated.filename.trim_end_matches(".conf");
+
+                self.state.remember_mapping_intent(vmid, generated.intent);
+                self.state.idmap_wizard = None;
+                self.state.follow_up = Some(checklist(
+                    &generated.changes(),
+                    &self.state.affected_vmids(&self.metadata, writes),
+                ));
+
+                Notification {
+                    level: Level::Info,
+                    message: format!("Mapped container {vmid} onto host ids from {} on", generated.offset),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to apply the generated mapping: {err:?}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Previews the highlighted step of the conversion assistant, or opens the ownership shift
+    /// dialog for the rootfs. Steps are taken in order, so the container is never left with a config
+    /// its host ids aren't ready for.
+    fn start_conversion_step(&mut self) -> color_eyre::Result<()> {
+        let Some(conversion) = &self.state.conversion else {
+            return Ok(());
+        };
+        let Some(filename) = conversion.filename().cloned() else {
+            return Ok(());
+        };
+        let steps = self.state.conversion_steps(conversion);
+        let Some(item) = steps.get(conversion.step) else {
+            return Ok(());
+        };
+
+        if let Some(reason) = &item.blocked {
+            return Err(eyre!("This step can't be applied here, {reason}"));
+        }
+
+        if item.done {
+            return Err(eyre!("This step is done already"));
+        }
+
+        if steps[..conversion.step].iter().any(|item| !item.done) {
+            return Err(eyre!("Apply the steps before this one first"));
+        }
+
+        if self.metadata.is_viewer_only() {
+            return Err(eyre!("Files cannot be changed when inspected with --root-prefix"));
+        }
+
+        let vmid = filename.trim_end_matches(".conf");
+        let writes = match item.step {
+            ConversionStep::AddSubids => {
+                let mut writes = Vec::new();
+                let entries = self.state.conversion_subid_entries(conversion);
+
+                for sub_id in [SubID::UID, SubID::GID] {
+                    let entries: Vec<_> = entries
+                        .iter()
+                        .filter(|(kind, _)| *kind == sub_id)
+                        .map(|(_, entry)| entry.clone())
+                        .collect();
+
+                    if entries.is_empty() {
+                        continue;
+                    }
+
+                    let path = self.metadata.subid_path(sub_id);
+                    let content = self.state.changes.read(path)?;
+
+                    writes.push(PendingWrite {
+                        path: path.to_path_buf(),
+                        proposed: append_entries(&content, &entries),
+                        current: content,
+                    });
+                }
+
+                writes
+            },
+            ConversionStep::EditConfig => {
+                if lxc_running(vmid).unwrap_or(false) {
+                    return Err(eyre!("Container {vmid} is running, stop it before converting it"));
+                }
+
+                let values = self.state.conversion_config_values(conversion);
+                let values: Vec<(&str, Vec<&str>)> = values
+                    .iter()
+                    .map(|(key, values)| (*key, values.iter().map(String::as_str).collect()))
+                    .collect();
+                let edits: Vec<_> = values.iter().map(|(key, values)| (*key, values.as_slice())).collect();
+
+                let path = self.metadata.lxc_config_dir.join(&*filename);
+
+                vec![fix::config_edits_write(&path, self.state.changes.read(&path)?, &edits)?]
+            },
+            ConversionStep::ShiftRootfs => {
+                let path = self
+                    .state
+                    .rootfs_path(&filename)
+                    .ok_or_else(|| eyre!("The rootfs wasn't found"))?
+                    .to_path_buf();
+
+                self.state.ownership_shift = Some(OwnershipShift {
+                    filename,
+                    path,
+                    field: ShiftField::From,
+                    from: "0".to_string(),
+                    to: conversion.offset.to_string(),
+                    count: CONTAINER_IDS.to_string(),
+                    job: None,
+                });
+
+                return Ok(());
+            },
+        };
+        let mut preview = WritePreview::new("Apply conversion step?", PreviewAction::Conversion(item.step), writes);
+
+        if item.step == ConversionStep::EditConfig {
+            preview.notes.push(format!(
+                "Container {vmid} can't be started until its rootfs was shifted in the next step."
+            ));
+            preview.notes.extend(self.state.conversion_notes(conversion));
+        }
+
+        self.state.write_preview = Some(preview);
+
+        Ok(())
+    }
+
+    /// Writes the previewed files of a conversion step. The assistant stays open and sees the step
+    /// done once the written files are read back.
+    fn apply_conversion_step(&mut self, step: ConversionStep, writes: &[PendingWrite]) {
+        let vmid = self
+            .state
+            .conversion
+            .as_ref()
+            .and_then(|conversion| conversion.filename())
+            .map(|filename| filename.trim_end_matches(".conf").to_string())
+            .unwrap_or_default();
+        let notification = match writes.iter().try_for_each(PendingWrite::commit) {
+            Ok(()) => Notification {
+                level: Level::Info,
+                message: match step {
+                    ConversionStep::AddSubids => format!("Gave root the host ids for container {vmid}"),
+                    _ => format!("Made container {vmid} unprivileged, shift its rootfs before starting it"),
+                },
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to convert container {vmid}: {err:?}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Carries out `strategy` from the fix popup, which closes unless it failed to start.
+    fn run_fix_strategy(&mut self, strategy: FixStrategy) {
+        let result = match strategy {
+            FixStrategy::Apply(fix) => {
+                self.preview_fix(fix);
+                Ok(())
+            },
+            FixStrategy::AdviseSharedVolume => {
+                let state = &self.state;
+                let plan = self.selected_finding().map(|finding| {
+                    state.shared_volume_plan(finding, |filename| {
+                        state.rootfs_path(filename).and_then(ContainerAccounts::read)
+                    })
+                });
+
+                match plan {
+                    Some(Ok(plan)) => self.preview_shared_volume(plan),
+                    Some(Err(err)) => Err(err),
+                    None => Ok(()),
+                }
+            },
+            FixStrategy::ShareWithAcls => {
+                let plan = self.selected_finding().map(|finding| self.state.acl_plan(finding));
+
+                match plan {
+                    Some(Ok(plan)) => {
+                        self.preview_acl(plan);
+                        Ok(())
+                    },
+                    Some(Err(err)) => Err(err),
+                    None => Ok(()),
+                }
+            },
+            FixStrategy::ShiftOwnership if self.metadata.is_viewer_only() => Err(eyre!(
+                "Ownership can't be shifted for files inspected with --root-prefix"
+            )),
+            FixStrategy::ShiftOwnership => self.state.open_ownership_shift(),
+            FixStrategy::GenerateIdmaps(_) => self.state.open_idmap_wizard(),
+            FixStrategy::EditIdmaps(_) => self.state.open_idmap_editor(&self.metadata.lxc_config_dir),
+            FixStrategy::EditSubids => {
+                self.open_subid_editor();
+                Ok(())
+            },
+        };
+
+        match result {
+            Ok(()) => self.state.show_fix_popup = false,
+            Err(err) => self.bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    /// Shows the diff of the files a fix writes, so nothing changes before it is confirmed.
+    fn preview_fix(&mut self, fix: Fix) {
+        let notification = if self.metadata.is_viewer_only() {
+            Notification {
+                level: Level::Warn,
+                message: "Fixes cannot be applied to files inspected with --root-prefix".to_string(),
+            }
+        } else {
+            let action = PreviewAction::Fix(fix);
+            let planned = self.state.changes.writes();
+
+            match fix.pending_writes_after(&self.metadata, &planned) {
+                Ok(writes) => {
+                    let mut preview = WritePreview::new("Apply fix?", action, writes);
+
+                    preview.notes.push(fix.description());
+                    self.state.write_preview = Some(preview);
+
+                    return;
+                },
+                Err(err) => Notification {
+                    level: Level::Error,
+                    message: format!("Failed to apply fix: {err:?}"),
+                },
+            }
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Shows the diffs of the files the fixes of every marked finding write, combined into one
+    /// write per file.
+    fn preview_marked_fixes(&mut self) {
+        let fixes = self.state.marked_fixes();
+        let notification = if fixes.is_empty() {
+            Notification {
+                level: Level::Warn,
+                message: "Mark findings with Space to fix them together".to_string(),
+            }
+        } else if self.metadata.is_viewer_only() {
+            Notification {
+                level: Level::Warn,
+                message: "Fixes cannot be applied to files inspected with --root-prefix".to_string(),
+            }
+        } else {
+            let action = PreviewAction::Fixes(fixes.clone());
+            let planned = self.state.changes.writes();
+
+            match fix::batch_writes(&fixes, &self.metadata, &planned) {
+                Ok(writes) => {
+                    let notes = fixes.iter().map(|fix| fix.description()).collect();
+                    let mut preview = WritePreview::new("Apply marked fixes?", action, writes);
+
+                    preview.notes = notes;
+                    self.state.write_preview = Some(preview);
+
+                    return;
+                },
+                Err(err) => Notification {
+                    level: Level::Error,
+                    message: format!("Failed to apply the marked fixes: {err:?}"),
+                },
+            }
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Shows the commands which would share a directory through ACLs, so nothing runs before it is
+    /// confirmed.
+    fn preview_acl(&mut self, plan: AclPlan) {
+        if self.metadata.is_viewer_only() {
+            self.bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: "Fixes cannot be applied to files inspected with --root-prefix".to_string(),
+            });
+            return;
+        }
+
+        let containers: Vec<_> = plan
+            .grants
+            .iter()
+            .map(|grant| grant.filename.trim_end_matches(".conf"))
+            .collect();
+        let mut preview = WritePreview::new("Share with ACLs?", PreviewAction::Acl(plan.clone()), Vec::new());
+
+        preview.notes.push(format!(
+            "Let containers {} read and write {} through ACLs for the host ids their root maps to, \
+             instead of mapping a common group into each of them. Default ACLs pass the access on to \
+             files created later.",
+            containers.join(", "),
+            plan.path.display()
+        ));
+        preview.notes.push(String::new());
+        preview.notes.extend(plan.commands());
+        self.state.write_preview = Some(preview);
+    }
+
+    /// Shows every change sharing a directory takes at once: the idmap and subgid writes as diffs,
+    /// then the host ids each container's users end up as and the commands run after the writes.
+    fn preview_shared_volume(&mut self, plan: SharedVolumePlan) -> color_eyre::Result<()> {
+        if self.metadata.is_viewer_only() {
+            return Err(eyre!("Fixes cannot be applied to files inspected with --root-prefix"));
+        }
+
+        let mut writes = Vec::new();
+
+        if let Some(entry) = &plan.subgid_entry {
+            let path = self.metadata.subid_path(SubID::GID);
+            let content = self.state.changes.read(path)?;
+
+            writes.push(PendingWrite {
+                path: path.to_path_buf(),
+                proposed: append_entries(&content, std::slice::from_ref(entry)),
+                current: content,
+            });
+        }
+
+        for container in &plan.containers {
+            if let Some(values) = &container.idmaps {
+                let values: Vec<_> = values.iter().map(String::as_str).collect();
+                let path = self.metadata.lxc_config_dir.join(&*container.filename);
+                let content = self.state.changes.read(&path)?;
+
+                writes.push(fix::config_edits_write(&path, content, &[("lxc.idmap", &values)])?);
+            }
+        }
+
+        let vmids: Vec<_> = plan.containers.iter().map(|container| container.vmid()).collect();
+        let summary = match &plan.strategy {
+            ShareStrategy::Group { gid, name } => format!(
+                "Map group {name} ({gid}) into containers {} and hand {} to it. Directories keep passing the \
+                 group on to files created in them.",
+                vmids.join(", "),
+                plan.path.display()
+            ),
+            ShareStrategy::Acl(_) => format!(
+                "Grant the users below access to {} through ACLs, since no common group can be mapped into {}.",
+                plan.path.display(),
+                plan.fallback_reason.as_deref().unwrap_or("every container")
+            ),
+        };
+        let mut preview = WritePreview::new(
+            "Share the directory?",
+            PreviewAction::SharedVolume(plan.clone()),
+            writes,
+        );
+
+        preview.notes.push(summary);
+        preview.notes.push(String::new());
+        preview.notes.extend(plan.summary());
+        preview.notes.push(String::new());
+        preview.notes.push("Once the files are written, runs:".to_string());
+        preview.notes.extend(plan.commands());
+        self.state.write_preview = Some(preview);
+
+        Ok(())
+    }
+
+    /// Starts shifting the rootfs in the shift dialog, or only counting what would change. Running
+    /// containers are left alone, since their processes would keep files open with the old owners.
+    fn start_ownership_shift(&mut self, dry_run: bool) {
+        let Some(shift) = &mut self.state.ownership_shift else {
+            return;
+        };
+        let vmid = shift.filename.trim_end_matches(".conf");
+        let result = shift.id_shift().and_then(|id_shift| {
+            if !dry_run && lxc_running(vmid).unwrap_or(false) {
+                return Err(eyre!("Container {vmid} is running, stop it before shifting its rootfs"));
+            }
+
+            Ok(id_shift)
+        });
+
+        match result {
+            Ok(id_shift) => {
+                if !dry_run {
+                    info!(
+                        "Shifting the owners of {} from {} to {}, {} ids",
+                        shift.path.display(),
+                        id_shift.from,
+                        id_shift.to,
+                        id_shift.count
+                    );
+                }
+
+                shift.job = Some(ShiftJob::start(shift.path.clone(), id_shift, dry_run));
+            },
+            Err(err) => self.bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    /// Queues the shift typed into the dialog and closes it. The shift starts once the queued
+    /// changes are applied, after the idmaps it moves the files along with are written.
+    fn queue_ownership_shift(&mut self) {
+        let Some(shift) = &self.state.ownership_shift else {
+            return;
+        };
+        let vmid = shift.filename.trim_end_matches(".conf");
+        let queued = shift.id_shift().and_then(|id_shift| {
+            self.state.changes.queue(QueuedChange {
+                description: format!(
+                    "Shift the owners of container {vmid}'s rootfs from {} to {}, {} ids",
+                    id_shift.from, id_shift.to, id_shift.count
+                ),
+                writes: Vec::new(),
+                steps: vec![QueuedStep::Shift {
+                    filename: shift.filename.clone(),
+                    path: shift.path.clone(),
+                    shift: id_shift,
+                }],
+                follow_up: Vec::new(),
+            })
+        });
+        let notification = match queued {
+            Ok(()) => {
+                self.state.ownership_shift = None;
+
+                Notification {
+                    level: Level::Info,
+                    message: "Queued, press p to review and apply the pending changes".to_string(),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Warn,
+                message: format!("Not queued, {err}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Runs the confirmed ACL commands, logging each so the log page shows what was changed.
+    fn apply_acl(&mut self, plan: &AclPlan) {
+        let change = QueuedChange {
+            description: format!("Share {} through ACLs", plan.path.display()),
+            writes: Vec::new(),
+            steps: vec![QueuedStep::Acl(plan.clone())],
+            follow_up: Vec::new(),
+        };
+        let notification = match change.apply() {
+            Ok(()) => {
+                for command in plan.commands() {
+                    info!("Ran {command}");
+                }
+
+                self.state.stats.fixes_applied += 1;
+
+                Notification {
+                    level: Level::Info,
+                    message: format!("Shared {} through ACLs", plan.path.display()),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to set ACLs on {}: {err:?}", plan.path.display()),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Writes the previewed idmaps and subgid entry and runs the plan's commands, leaving the
+    /// containers' own groups to the follow-up steps.
+    fn apply_shared_volume(&mut self, plan: &SharedVolumePlan, writes: &[PendingWrite]) {
+        let changes = plan.changes();
+        // The files are put back if a command fails, so the idmaps never point at a group that isn't there
+        let change = QueuedChange {
+            description: format!("Share {} between containers", plan.path.display()),
+            writes: writes.to_vec(),
+            steps: vec![QueuedStep::SharedVolume(plan.clone())],
+            follow_up: changes.clone(),
+        };
+        let notification = match change.apply() {
+            Ok(()) => {
+                for command in plan.commands() {
+                    info!("Ran {command}");
+                }
+
+                if !changes.is_empty() {
+                    self.state.follow_up =
+                        Some(checklist(&changes, &self.state.affected_vmids(&self.metadata, writes)));
+                }
+
+                self.state.stats.fixes_applied += 1;
+
+                Notification {
+                    level: Level::Info,
+                    message: format!("Shared {} between containers", plan.path.display()),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to share {}: {err:?}", plan.path.display()),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Carries out confirmed writes, along with whatever the feature which asked for them does
+    /// afterwards.
+    fn commit_preview(&mut self, preview: WritePreview) {
+        match preview.action {
+            PreviewAction::Fix(fix) => {
+                // Fixes work out their changes again when applied, which must still be the ones shown
+                match preview.writes.iter().find(|write| !write.is_current().unwrap_or(false)) {
+                    Some(write) => self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: format!(
+                            "{} was changed since it was previewed, not applying the fix",
+                            write.path.display()
+                        ),
+                    }),
+                    None => self.apply_fix(fix),
+                }
+            },
+            PreviewAction::Fixes(fixes) => {
+                match preview.writes.iter().find(|write| !write.is_current().unwrap_or(false)) {
+                    Some(write) => self.bus.notifications.publish(Notification {
+                        level: Level::Warn,
+                        message: format!(
+                            "{} was changed since it was previewed, not applying the fixes",
+                            write.path.display()
+                        ),
+                    }),
+                    None => self.apply_marked_fixes(&fixes),
+                }
+            },
+            PreviewAction::SubidEdits(changed) => self.save_subid_edits(&changed, &preview.writes),
+            PreviewAction::IdmapEdits => self.save_idmap_edits(&preview.writes),
+            PreviewAction::GeneratedMapping(generated) => self.apply_generated_mapping(&generated, &preview.writes),
+            PreviewAction::Acl(plan) => self.apply_acl(&plan),
+            PreviewAction::SharedVolume(plan) => self.apply_shared_volume(&plan, &preview.writes),
+            PreviewAction::Conversion(step) => self.apply_conversion_step(step, &preview.writes),
+            PreviewAction::Transaction => self.apply_transaction(),
+        }
+    }
+
+    /// Queues the previewed changes instead of making them, closing whatever asked for them as
+    /// though they were made. The preview stays open if they can't be queued.
+    fn queue_preview(&mut self, preview: WritePreview) {
+        let vmid = |filename: Option<&CompactString>| {
+            filename
+                .map(|filename| filename.trim_end_matches(".conf").to_string())
+                .unwrap_or_default()
+        };
+        let planned = self.state.changes.writes();
+        let described = match &preview.action {
+            PreviewAction::Fix(fix) => fix::batch(&[*fix], &self.metadata, &planned)
+                .map(|(_, steps)| (fix.description(), steps, fix.changes())),
+            PreviewAction::Fixes(fixes) => fix::batch(fixes, &self.metadata, &planned).map(|(_, steps)| {
+                (
+                    format!("Fix {} marked findings", fixes.len()),
+                    steps,
+                    fixes.iter().flat_map(|fix| fix.changes()).collect(),
+                )
+            }),
+            PreviewAction::SubidEdits(changed) => Ok((
+                format!(
+                    "Edit {}",
+                    changed
+                        .iter()
+                        .map(|sub_id| sub_id.path())
+                        .collect::<Vec<_>>()
+                        .join(" and ")
+                ),
+                Vec::new(),
+                changed.iter().copied().map(Change::SubidRangesEdited).collect(),
+            )),
+            PreviewAction::IdmapEdits => {
+                let vmid = vmid(self.state.idmap_editor.as_ref().map(|editor| &editor.filename));
+
+                Ok((
+                    format!("Edit the idmaps of container {vmid}"),
+                    Vec::new(),
+                    vec![Change::ConfigEdited { vmid }],
+                ))
+            },
+            PreviewAction::GeneratedMapping(generated) => {
+                let vmid = vmid(Some(&generated.filename));
+
+                Ok((
+                    format!("Map container {vmid} onto host ids from {} on", generated.offset),
+                    vec![QueuedStep::MappingIntent {
+                        vmid,
+                        intent: generated.intent,
+                    }],
+                    generated.changes(),
+                ))
+            },
+            PreviewAction::Acl(plan) => Ok((
+                format!("Share {} through ACLs", plan.path.display()),
+                vec![QueuedStep::Acl(plan.clone())],
+                Vec::new(),
+            )),
+            PreviewAction::SharedVolume(plan) => Ok((
+                format!("Share {} between containers", plan.path.display()),
+                vec![QueuedStep::SharedVolume(plan.clone())],
+                plan.changes(),
+            )),
+            PreviewAction::Conversion(step) => {
+                let vmid = vmid(
+                    self.state
+                        .conversion
+                        .as_ref()
+                        .and_then(|conversion| conversion.filename()),
+                );
+                let description = match step {
+                    ConversionStep::AddSubids => format!("Give root the host ids for container {vmid}"),
+                    _ => format!("Make container {vmid} unprivileged"),
+                };
+
+                Ok((description, Vec::new(), Vec::new()))
+            },
+            PreviewAction::Transaction => Err(eyre!("these changes are queued already")),
+        };
+        let queued = described.and_then(|(description, steps, follow_up)| {
+            self.state.changes.queue(QueuedChange {
+                description,
+                writes: preview.writes.clone(),
+                steps,
+                follow_up,
+            })
+        });
+
+        if let Err(err) = queued {
+            self.bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: format!("Not queued, {err}"),
+            });
+            self.state.write_preview = Some(preview);
+
+            return;
+        }
+
+        match preview.action {
+            PreviewAction::Fixes(_) => self.state.marked_findings.clear(),
+            PreviewAction::SubidEdits(_) => self.state.subid_editor = None,
+            PreviewAction::IdmapEdits => self.state.idmap_editor = None,
+            PreviewAction::GeneratedMapping(_) => self.state.idmap_wizard = None,
+            _ => {},
+        }
+
+        self.bus.notifications.publish(Notification {
+            level: Level::Info,
+            message: "Queued, press p to review and apply the pending changes".to_string(),
+        });
+    }
+
+    /// Drops the highlighted queued change, along with the changes planned on top of it.
+    fn drop_queued_change(&mut self) {
+        let dropped = self.state.changes.remove(self.state.selected_change);
+
+        if dropped == 0 {
+            return;
+        }
+
+        self.state.selected_change = self
+            .state
+            .selected_change
+            .min(self.state.changes.len().saturating_sub(1));
+        self.state.changes_scroll = 0;
+        self.bus.notifications.publish(Notification {
+            level: Level::Info,
+            message: match dropped {
+                1 => "Dropped the change".to_string(),
+                count => format!("Dropped the change and the {} planned on top of it", count - 1),
+            },
+        });
+    }
+
+    /// Shows the combined diff of every queued change, one write per file.
+    fn preview_transaction(&mut self) {
+        if self.state.changes.is_empty() {
+            self.bus.notifications.publish(Notification {
+                level: Level::Info,
+                message: "No changes are queued".to_string(),
+            });
+
+            return;
+        }
+
+        let mut preview = WritePreview::new(
+            "Apply all queued changes?",
+            PreviewAction::Transaction,
+            self.state.changes.writes(),
+        );
+
+        preview.notes = self
+            .state
+            .changes
+            .changes()
+            .iter()
+            .map(|change| change.description.clone())
+            .collect();
+
+        let commands: Vec<_> = self.state.changes.steps().flat_map(QueuedStep::commands).collect();
+
+        if !commands.is_empty() {
+            preview.notes.push(String::new());
+            preview.notes.push("Once the files are written, runs:".to_string());
+            preview.notes.extend(commands);
+        }
+
+        self.state.write_preview = Some(preview);
+    }
+
+    /// Writes every queued change and runs their commands, or none of them if one fails, and
+    /// empties the queue once they are applied. A queued ownership shift starts afterwards, in the
+    /// shift dialog.
+    fn apply_transaction(&mut self) {
+        let shift = self.state.changes.steps().find_map(|step| match step {
+            QueuedStep::Shift { filename, path, shift } => Some((filename.clone(), path.clone(), *shift)),
+            _ => None,
+        });
+
+        // Checked up front, since the files shouldn't be written without the shift they were queued with
+        if let Some((filename, ..)) = &shift {
+            let vmid = filename.trim_end_matches(".conf");
+
+            if lxc_running(vmid).unwrap_or(false) {
+                self.bus.notifications.publish(Notification {
+                    level: Level::Warn,
+                    message: format!("Container {vmid} is running, stop it before shifting its rootfs"),
+                });
+
+                return;
+            }
+        }
+
+        let notification = match self.state.changes.apply() {
+            Ok(()) => {
+                let count = self.state.changes.len();
+                let changes = self.state.changes.follow_up();
+                let writes = self.state.changes.writes();
+                let mut intents = Vec::new();
+
+                for step in self.state.changes.steps() {
+                    for command in step.commands() {
+                        info!("Ran {command}");
+                    }
+
+                    if let QueuedStep::MappingIntent { vmid, intent } = step {
+                        intents.push((vmid.clone(), *intent));
+                    }
+                }
+
+                for (vmid, intent) in intents {
+                    self.state.remember_mapping_intent(&vmid, intent);
+                }
+
+                if !changes.is_empty() {
+                    self.state.follow_up =
+                        Some(checklist(&changes, &self.state.affected_vmids(&self.metadata, &writes)));
+                }
+
+                if let Some((filename, path, shift)) = shift {
+                    info!(
+                        "Shifting the owners of {} from {} to {}, {} ids",
+                        path.display(),
+                        shift.from,
+                        shift.to,
+                        shift.count
+                    );
+
+                    self.state.ownership_shift = Some(OwnershipShift {
+                        filename,
+                        job: Some(ShiftJob::start(path.clone(), shift, false)),
+                        path,
+                        field: ShiftField::From,
+                        from: shift.from.to_string(),
+                        to: shift.to.to_string(),
+                        count: shift.count.to_string(),
+                    });
+                }
+
+                self.state.changes.clear();
+                self.state.show_changes_page = false;
+                self.state.selected_change = 0;
+                self.state.changes_scroll = 0;
+
+                Notification {
+                    level: Level::Info,
+                    message: format!("Applied {count} queued changes"),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to apply the queued changes, nothing was changed: {err:?}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Writes a report of everything pupman sees to the current directory, to share when asking
+    /// for help.
+    fn write_report(&mut self, format: ReportFormat) {
+        let now = Utc::now();
+        let filename = format!("pupman-report-{}.{}", now.format("%Y%m%d-%H%M%S"), format.extension());
+        let written = current_dir()
+            .wrap_err("Failed to find the current directory")
+            .and_then(|dir| {
+                let path = dir.join(filename);
+
+                write_atomic(&path, &report(&self.state, &self.metadata, format, now))?;
+
+                Ok(path)
+            });
+        let notification = match written {
+            Ok(path) => Notification {
+                level: Level::Info,
+                message: format!("Wrote the report to {}", path.display()),
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to write the report: {err:?}"),
+            },
+        };
+
+        self.state.show_report_popup = false;
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Restores the file written last this session from its backup. The file system monitor picks
+    /// up the change from there.
+    fn undo_last_change(&mut self) {
+        let notification = match backup::undo_last() {
+            Ok(Some(backup)) => Notification {
+                level: Level::Info,
+                message: format!("Restored {} from {}", backup.original.display(), backup.path.display()),
+            },
+            Ok(None) => Notification {
+                level: Level::Info,
+                message: "Nothing was written this session".to_string(),
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to undo the last change: {err:?}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Switches between writing container configs directly and through `pct set`, and persists the
+    /// choice.
+    fn toggle_apply_mode(&mut self) {
+        let settings = &mut self.state.settings;
+
+        settings.set_apply_mode(match settings.apply_mode() {
+            ApplyMode::Direct => ApplyMode::Pct,
+            ApplyMode::Pct => ApplyMode::Direct,
+        });
+
+        if let Err(err) = settings.save() {
+            error!("Failed to save settings: {err:?}");
+        }
+    }
+
+    /// Saves the history only when findings appear or disappear, so last seen timestamps are as of
+    /// the last change or exit.
+    fn record_history(&mut self) {
+        let ids: Vec<_> = self.state.findings.iter().map(Finding::id).collect();
+        let retention = self.state.settings.retention();
+        let now = Utc::now();
+        let changed = self.history.observe(ids.iter().map(String::as_str), now);
+        let pruned = self
+            .history
+            .prune(retention.history_entries, retention.history_max_age, now);
+
+        if (changed || pruned > 0)
+            && let Err(err) = self.history.save()
+        {
+            error!("Failed to save finding history: {err:?}");
+        }
+
+        // Findings are only sorted by recency once their first sighting is recorded
+        if self.state.settings.sort_order() == SortOrder::FirstSeen {
+            self.sort_findings();
+        }
+    }
+
+    /// Re-sorts findings, keeping the same finding selected.
+    fn sort_findings(&mut self) {
+        let selected = self.selected_finding().map(|finding| finding.id());
+        let history = &self.history;
+
+        self.state
+            .sort_findings(|finding| history.get(&finding.id()).map(|entry| entry.first_seen));
+
+        if let Some(id) = selected {
+            self.state.selected_finding = self.state.findings.iter().position(|finding| finding.id() == id);
+        }
+    }
+
+    /// Switches to the next finding sort order and persists the choice.
+    fn cycle_sort_order(&mut self) {
+        let settings = &mut self.state.settings;
+
+        settings.set_sort_order(settings.sort_order().next());
+
+        if let Err(err) = settings.save() {
+            error!("Failed to save settings: {err:?}");
+        }
+
+        self.sort_findings();
+    }
+
+    fn cycle_theme(&mut self) {
+        let settings = &mut self.state.settings;
+
+        settings.set_theme(settings.theme().next());
+
+        if let Err(err) = settings.save() {
+            error!("Failed to save settings: {err:?}");
+        }
+    }
+
+    /// Applies a fix by writing to disk. The file system monitor picks up the change and findings are
+    /// re-evaluated from there, so no state is updated here.
+    fn apply_fix(&mut self, fix: Fix) {
+        let notification = if self.metadata.is_viewer_only() {
+            Notification {
+                level: Level::Warn,
+                message: "Fixes cannot be applied to files inspected with --root-prefix".to_string(),
+            }
+        } else {
+            match self.commit_fix(fix) {
+                Ok(()) => Notification {
+                    level: Level::Info,
+                    message: format!("Applied fix: {}", fix.description()),
+                },
+                Err(err) => Notification {
+                    level: Level::Error,
+                    message: format!("Failed to apply fix: {err:?}"),
+                },
+            }
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Applies the fixes of the marked findings as one, unmarking them once they're applied.
+    fn apply_marked_fixes(&mut self, fixes: &[Fix]) {
+        // Read before applying, to tell which ranges the fixes changed
+        let writes = fix::batch_writes(fixes, &self.metadata, &[]).unwrap_or_default();
+        let notification = match fix::apply_batch(fixes, &self.metadata) {
+            Ok(()) => {
+                let mut changes = Vec::new();
+
+                for change in fixes.iter().flat_map(|fix| fix.changes()) {
+                    if !changes.contains(&change) {
+                        changes.push(change);
+                    }
+                }
+
+                self.state.stats.fixes_applied += fixes.len();
+                self.state.follow_up = Some(checklist(&changes, &self.state.affected_vmids(&self.metadata, &writes)));
+                self.state.marked_findings.clear();
+
+                Notification {
+                    level: Level::Info,
+                    message: format!("Applied {} fixes", fixes.len()),
+                }
+            },
+            Err(err) => Notification {
+                level: Level::Error,
+                message: format!("Failed to apply the marked fixes, nothing was changed: {err:?}"),
+            },
+        };
+
+        self.bus.notifications.publish(notification);
+    }
+
+    /// Applies `fix` and sets up the checklist to follow afterwards.
+    fn commit_fix(&mut self, fix: Fix) -> color_eyre::Result<()> {
+        let writes = fix.pending_writes(&self.metadata)?;
+
+        fix.apply(&self.metadata)?;
+        self.state.stats.fixes_applied += 1;
+        self.state.follow_up = Some(checklist(
+            &fix.changes(),
+            &self.state.affected_vmids(&self.metadata, &writes),
+        ));
+
+        Ok(())
+    }
+
+    /// Runs the read-only commands of the follow-up checklist, reporting each result as a
+    /// notification. Anything disruptive, like restarting a container, is left to the user.
+    fn run_safe_follow_up_steps(&self) {
+        let steps = self.state.follow_up.iter().flatten();
+
+        for step in steps.filter(|step| step.safe) {
+            let Some(command_line) = step.command_line() else {
+                continue;
+            };
+            let notification = match step.run() {
+                Ok(_) => Notification {
+                    level: Level::Info,
+                    message: format!("{command_line} succeeded"),
+                },
+                Err(err) => Notification {
+                    level: Level::Error,
+                    message: format!("{err:?}"),
+                },
+            };
+
+            self.bus.notifications.publish(notification);
+        }
+    }
+
+    /// Handles the tick event of the terminal.
+    ///
+    /// The tick event is where you can update the state of your application with any logic that
+    /// needs to be updated at a fixed frame rate. E.g. polling a server, updating an animation.
+    pub fn tick(&self) {}
+
+    /// Set running to false to quit the application.
+    /// Quits, unless that would throw away work, in which case the user is asked first.
+    fn request_quit(&mut self) {
+        let reasons = self.state.quit_blockers();
+
+        if reasons.is_empty() {
+            self.event_handler.send(AppEvent::Quit);
+        } else {
+            self.state.confirmation = Some(Confirmation {
+                action: ConfirmAction::Quit,
+                reasons,
+            });
+        }
+    }
+
+    fn run_confirmed(&mut self, action: ConfirmAction) {
+        match action {
+            ConfirmAction::Quit => self.event_handler.send(AppEvent::Quit),
+            ConfirmAction::DiscardEdits => {
+                self.state.subid_editor = None;
+                self.state.idmap_editor = None;
+            },
+        }
+    }
+
+    pub fn quit(&mut self) {
+        self.state.is_running = false;
+        self.stop_deep_scan();
+        self.stop_ownership_shift();
+
+        if let Err(err) = self.history.save() {
+            error!("Failed to save finding history: {err:?}");
+        }
+    }
+
+    /// Opens the file behind the selected finding with the cursor on its first offending line.
+    fn open_source(&mut self) {
+        let Some(finding) = self.selected_finding().filter(|f| f.kind != FindingKind::Good) else {
+            return;
+        };
+        let locations = self.state.source_locations(finding);
+        let Some(first) = locations.first() else {
+            return;
+        };
+        let path = match &first.file {
+            SourceFile::Subid(sub_id) => self.metadata.subid_path(*sub_id).to_path_buf(),
+            SourceFile::Config(filename) => self.metadata.lxc_config_dir.join(filename.as_str()),
+        };
+        let content = match read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                error!("Failed to read {}: {err}", path.display());
+                return;
+            },
+        };
+        let targets = locations
+            .iter()
+            .filter(|location| location.file == first.file)
+            .map(|location| location.line)
+            .collect();
+
+        self.state.source_view = Some(SourceView::new(
+            path.display().to_string(),
+            &content,
+            first.line,
+            targets,
+        ));
+    }
+
+    fn explain_line_count(&self) -> usize {
+        self.selected_finding().map_or(0, |finding| {
+            let explanation = self.state.explain(finding, &self.metadata.lxc_config_dir);
+
+            ui::explain_popup_lines(finding, &explanation).len()
+        })
+    }
+
+    fn selected_finding(&self) -> Option<&Finding> {
+        self.state
+            .selected_finding
+            .and_then(|index| self.state.findings.get(index))
+    }
+}
+
+/// Parses the entries of a subid file, failing on the first line which isn't one.
+pub fn parse_subid_map(content: &str) -> color_eyre::Result<Vec<IdMapEntry>> {
+    let (id_map, invalid) = parse_subid_lines(content);
+
+    match invalid.first() {
+        Some(invalid) => Err(eyre!("Line {}: {}", invalid.line_number, invalid.error)),
+        None => Ok(id_map),
+    }
+}
+
+/// Parses the entries of a subid file, along with every line which is neither an entry nor a
+/// comment.
+pub fn parse_subid_lines(content: &str) -> (Vec<IdMapEntry>, Vec<InvalidSubidLine>) {
+    let mut id_map = Vec::new();
+    let mut invalid = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() || is_comment(trimmed) {
+            continue;
+        }
+
+        let (fields, unusual_format) = split_fields(line);
+        let parse = |field: &'static str, value: &str| {
+            value.parse::<u32>().map_err(|_| SubidError::Number {
+                field,
+                value: value.to_string(),
+            })
+        };
+        let entry = match fields[..] {
+            ["", _, _] => Err(SubidError::EmptyOwner),
+            [host_user_id, start, count] => parse("start", start).and_then(|host_sub_id| {
+                Ok(IdMapEntry {
+                    host_user_id: host_user_id.into(),
+                    host_sub_id,
+                    host_sub_id_count: parse("count", count)?,
+                    unusual_format,
+                    line: line.into(),
+                    line_number: i + 1,
+                })
+            }),
+            _ => Err(SubidError::FieldCount(fields.len())),
+        };
+
+        match entry {
+            Ok(entry) => id_map.push(entry),
+            Err(error) => invalid.push(InvalidSubidLine {
+                line_number: i + 1,
+                line: line.into(),
+                error,
+            }),
+        }
+    }
+
+    (id_map, invalid)
+}
+
+/// Starts the file reader and forwards everything published on a new bus to the app, tagged with
+/// `generation`.
+fn start_workers(event_handler: &EventHandler, generation: u64) -> Bus {
+    let bus = Bus::default();
+    let app_tx = event_handler.sender();
+
+    bus.fs_changes.subscribe_with(move |change| {
+        app_tx
+            .send(Event::App(AppEvent::FileSystemChanged(generation, change)))
+            .is_ok()
+    });
+
+    let app_tx = event_handler.sender();
+
+    bus.notifications
+        .subscribe_with(move |notification| app_tx.send(Event::App(AppEvent::Notify(notification))).is_ok());
+
+    let file_reads = bus.file_reads.subscribe();
+    let reader_bus = bus.clone();
+
+    thread::spawn(move || fs::reader::start(file_reads, reader_bus));
+
+    bus
 }
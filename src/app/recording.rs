@@ -0,0 +1,500 @@
+//! Session recordings for bug reports. `--record` writes every event the app handles as a line of
+//! JSON, and `--replay` feeds a recording back in at the pace it was recorded.
+//!
+//! ```text
+//! {"ms":12,"event":"update-file","generation":0,"path":"/etc/pve/lxc/100.conf","content":"unprivileged: 1\n"}
+//! {"ms":15,"event":"evaluated","findings":3,"bad":1}
+//! {"ms":2040,"event":"key","code":"Down","modifiers":0}
+//! ```
+//!
+//! Keys, mouse input, pastes and the config, subid and passwd contents which were read are
+//! replayed. Rootfs metadata, disk space and deep scans can't be told apart from the host they
+//! came from, so they are only noted and come from the files pupman is pointed at on replay.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ahash::RandomState;
+use ratatui::crossterm::event::{
+    Event as CrosstermEvent, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::app::event::{AppEvent, Event, FileSystemChangeKind};
+use crate::export::json_string;
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("line {0}: {1}")]
+    Malformed(usize, String),
+}
+
+/// A single recorded event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Entry {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    Paste(String),
+    Resize(u16, u16),
+    RemoveFile(u64, PathBuf),
+    UpdateFile(u64, PathBuf, String),
+    UpdateIncusInstance(u64, String, String),
+    /// A change which isn't replayed, by its kind and the rootfs, mount point value or path it is
+    /// about.
+    Observed(u64, String, String),
+    /// The findings were evaluated, leaving this many of them and this many bad ones.
+    Evaluated {
+        findings: usize,
+        bad: usize,
+    },
+}
+
+impl Entry {
+    /// What of `event` is recorded, if anything. Ticks only redraw and aren't.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        Some(match event {
+            Event::Tick => return None,
+            Event::Crossterm(CrosstermEvent::Key(key)) => Entry::Key(*key),
+            Event::Crossterm(CrosstermEvent::Mouse(mouse)) => Entry::Mouse(*mouse),
+            Event::Crossterm(CrosstermEvent::Paste(text)) => Entry::Paste(text.clone()),
+            Event::Crossterm(CrosstermEvent::Resize(width, height)) => Entry::Resize(*width, *height),
+            Event::Crossterm(_) => return None,
+            Event::App(AppEvent::FileSystemChanged(generation, kind)) => {
+                let generation = *generation;
+
+                match kind {
+                    FileSystemChangeKind::RemoveFile(path) => Entry::RemoveFile(generation, path.clone()),
+                    FileSystemChangeKind::ReadStarted(path) => {
+                        Entry::Observed(generation, "read-started".into(), path.to_string_lossy().into_owned())
+                    },
+                    FileSystemChangeKind::ReadFailed(path, _) => {
+                        Entry::Observed(generation, "read-failed".into(), path.to_string_lossy().into_owned())
+                    },
+                    FileSystemChangeKind::UpdateFile(path, content) => {
+                        Entry::UpdateFile(generation, path.clone(), content.clone())
+                    },
+                    FileSystemChangeKind::UpdateIncusInstance(filename, content) => {
+                        Entry::UpdateIncusInstance(generation, filename.clone(), content.clone())
+                    },
+                    FileSystemChangeKind::UpdateDir(value, ..) => {
+                        Entry::Observed(generation, "update-dir".into(), value.clone())
+                    },
+                    FileSystemChangeKind::DirUnavailable(value, _) => {
+                        Entry::Observed(generation, "dir-unavailable".into(), value.clone())
+                    },
+                    FileSystemChangeKind::UpdateDiskSpace(value, _) => {
+                        Entry::Observed(generation, "update-disk-space".into(), value.clone())
+                    },
+                    FileSystemChangeKind::OwnershipScanned(value, _) => {
+                        Entry::Observed(generation, "ownership-scanned".into(), value.clone())
+                    },
+                    FileSystemChangeKind::ConfigDirLost(path) => Entry::Observed(
+                        generation,
+                        "config-dir-lost".into(),
+                        path.to_string_lossy().into_owned(),
+                    ),
+                    FileSystemChangeKind::ConfigDirRestored(path) => Entry::Observed(
+                        generation,
+                        "config-dir-restored".into(),
+                        path.to_string_lossy().into_owned(),
+                    ),
+                }
+            },
+            Event::App(AppEvent::Notify(_) | AppEvent::Rpc(_) | AppEvent::Quit) => return None,
+        })
+    }
+
+    /// The event to replay, unless the entry is only there to be read.
+    pub fn to_event(&self) -> Option<Event> {
+        let change = |generation, kind| Some(Event::App(AppEvent::FileSystemChanged(generation, kind)));
+
+        match self {
+            Entry::Key(key) => Some(Event::Crossterm(CrosstermEvent::Key(*key))),
+            Entry::Mouse(mouse) => Some(Event::Crossterm(CrosstermEvent::Mouse(*mouse))),
+            Entry::Paste(text) => Some(Event::Crossterm(CrosstermEvent::Paste(text.clone()))),
+            Entry::Resize(width, height) => Some(Event::Crossterm(CrosstermEvent::Resize(*width, *height))),
+            Entry::RemoveFile(generation, path) => change(*generation, FileSystemChangeKind::RemoveFile(path.clone())),
+            Entry::UpdateFile(generation, path, content) => change(
+                *generation,
+                FileSystemChangeKind::UpdateFile(path.clone(), content.clone()),
+            ),
+            Entry::UpdateIncusInstance(generation, filename, content) => change(
+                *generation,
+                FileSystemChangeKind::UpdateIncusInstance(filename.clone(), content.clone()),
+            ),
+            Entry::Observed(..) | Entry::Evaluated { .. } => None,
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, Field)> {
+        let text = |s: &str| Field::Text(s.to_string());
+        let path = |p: &Path| Field::Text(p.to_string_lossy().into_owned());
+
+        match self {
+            Entry::Key(key) => vec![
+                ("event", text("key")),
+                ("code", text(&key_code_name(key.code))),
+                ("modifiers", Field::Number(key.modifiers.bits().into())),
+            ],
+            Entry::Mouse(mouse) => vec![
+                ("event", text("mouse")),
+                ("kind", text(mouse_kind_name(mouse.kind))),
+                ("column", Field::Number(mouse.column.into())),
+                ("row", Field::Number(mouse.row.into())),
+                ("modifiers", Field::Number(mouse.modifiers.bits().into())),
+            ],
+            Entry::Paste(pasted) => vec![("event", text("paste")), ("text", text(pasted))],
+            Entry::Resize(width, height) => vec![
+                ("event", text("resize")),
+                ("width", Field::Number((*width).into())),
+                ("height", Field::Number((*height).into())),
+            ],
+            Entry::RemoveFile(generation, file) => vec![
+                ("event", text("remove-file")),
+                ("generation", Field::Number(*generation)),
+                ("path", path(file)),
+            ],
+            Entry::UpdateFile(generation, file, content) => vec![
+                ("event", text("update-file")),
+                ("generation", Field::Number(*generation)),
+                ("path", path(file)),
+                ("content", text(content)),
+            ],
+            Entry::UpdateIncusInstance(generation, filename, content) => vec![
+                ("event", text("update-incus-instance")),
+                ("generation", Field::Number(*generation)),
+                ("filename", text(filename)),
+                ("content", text(content)),
+            ],
+            Entry::Observed(generation, kind, value) => vec![
+                ("event", text(kind)),
+                ("generation", Field::Number(*generation)),
+                ("value", text(value)),
+            ],
+            Entry::Evaluated { findings, bad } => vec![
+                ("event", text("evaluated")),
+                ("findings", Field::Number(*findings as u64)),
+                ("bad", Field::Number(*bad as u64)),
+            ],
+        }
+    }
+
+    fn from_fields(fields: &HashMap<String, Field, RandomState>) -> Result<Self, String> {
+        let text = |key: &str| match fields.get(key) {
+            Some(Field::Text(text)) => Ok(text.clone()),
+            _ => Err(format!("expected text for {key:?}")),
+        };
+        let number = |key: &str| match fields.get(key) {
+            Some(Field::Number(number)) => Ok(*number),
+            _ => Err(format!("expected a number for {key:?}")),
+        };
+        let small = |key: &str| number(key).and_then(|n| u16::try_from(n).map_err(|_| format!("{key:?} is too large")));
+        let modifiers = || Ok::<_, String>(KeyModifiers::from_bits_truncate(small("modifiers")? as u8));
+
+        Ok(match text("event")?.as_str() {
+            "key" => {
+                let code = text("code")?;
+                let code = parse_key_code(&code).ok_or_else(|| format!("unknown key {code:?}"))?;
+
+                Entry::Key(KeyEvent::new(code, modifiers()?))
+            },
+            "mouse" => {
+                let kind = text("kind")?;
+
+                Entry::Mouse(MouseEvent {
+                    kind: parse_mouse_kind(&kind).ok_or_else(|| format!("unknown mouse event {kind:?}"))?,
+                    column: small("column")?,
+                    row: small("row")?,
+                    modifiers: modifiers()?,
+                })
+            },
+            "paste" => Entry::Paste(text("text")?),
+            "resize" => Entry::Resize(small("width")?, small("height")?),
+            "remove-file" => Entry::RemoveFile(number("generation")?, text("path")?.into()),
+            "update-file" => Entry::UpdateFile(number("generation")?, text("path")?.into(), text("content")?),
+            "update-incus-instance" => {
+                Entry::UpdateIncusInstance(number("generation")?, text("filename")?, text("content")?)
+            },
+            "evaluated" => Entry::Evaluated {
+                findings: number("findings")? as usize,
+                bad: number("bad")? as usize,
+            },
+            kind => Entry::Observed(number("generation")?, kind.to_string(), text("value")?),
+        })
+    }
+}
+
+/// A recorded [`Entry`] and when it happened, counted from the start of the recording.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Recorded {
+    pub at: Duration,
+    pub entry: Entry,
+}
+
+impl Recorded {
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"ms\":{}", self.at.as_millis());
+
+        for (key, field) in self.entry.fields() {
+            json.push_str(&format!(",{}:", json_string(key)));
+
+            match field {
+                Field::Text(text) => json.push_str(&json_string(&text)),
+                Field::Number(number) => json.push_str(&number.to_string()),
+            }
+        }
+
+        json.push('}');
+        json
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Field {
+    Text(String),
+    Number(u64),
+}
+
+/// Writes each entry as a line of its own as soon as it is recorded, so a crash still leaves a
+/// complete recording behind.
+pub struct Recorder {
+    file: LineWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, RecordingError> {
+        Ok(Self {
+            file: LineWriter::new(File::create(path)?),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, entry: Entry) -> Result<(), RecordingError> {
+        let recorded = Recorded {
+            at: self.started.elapsed(),
+            entry,
+        };
+
+        Ok(writeln!(self.file, "{}", recorded.to_json())?)
+    }
+}
+
+/// Reads a recording written by [`Recorder`]. Empty lines are skipped.
+pub fn read_recording(path: &Path) -> Result<Vec<Recorded>, RecordingError> {
+    parse_recording(&std::fs::read_to_string(path)?)
+}
+
+pub fn parse_recording(content: &str) -> Result<Vec<Recorded>, RecordingError> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let malformed = |reason| RecordingError::Malformed(i + 1, reason);
+            let fields = parse_object(line).map_err(malformed)?;
+            let at = match fields.get("ms") {
+                Some(Field::Number(ms)) => Duration::from_millis(*ms),
+                _ => return Err(malformed("expected a number for \"ms\"".into())),
+            };
+
+            Ok(Recorded {
+                at,
+                entry: Entry::from_fields(&fields).map_err(malformed)?,
+            })
+        })
+        .collect()
+}
+
+/// Sends the replayable entries of `recording` to `sender` on a thread of its own, each as long
+/// after the replay started as it was recorded after the recording started.
+pub fn replay(recording: Vec<Recorded>, sender: Sender<Event>) {
+    thread::spawn(move || {
+        let started = Instant::now();
+
+        for recorded in recording {
+            let Some(event) = recorded.entry.to_event() else {
+                continue;
+            };
+
+            thread::sleep(recorded.at.saturating_sub(started.elapsed()));
+
+            // The app quit, possibly through a replayed key
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Parses a flat JSON object of strings and unsigned integers, the only values recordings use.
+fn parse_object(line: &str) -> Result<HashMap<String, Field, RandomState>, String> {
+    let object: Map<String, Value> = serde_json::from_str(line).map_err(|err| err.to_string())?;
+
+    object
+        .into_iter()
+        .map(|(key, value)| {
+            let field = match value {
+                Value::String(text) => Field::Text(text),
+                Value::Number(number) => Field::Number(
+                    number
+                        .as_u64()
+                        .ok_or_else(|| format!("{number} is not a whole number"))?,
+                ),
+                _ => return Err(format!("expected a string or number for {key:?}")),
+            };
+
+            Ok((key, field))
+        })
+        .collect()
+}
+
+const NAMED_KEYS: [(KeyCode, &str); 15] = [
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Enter, "Enter"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::BackTab, "BackTab"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Insert, "Insert"),
+    (KeyCode::Esc, "Esc"),
+];
+
+/// A character key is recorded as itself, other keys by name. Keys pupman doesn't use are
+/// recorded as `Null`.
+fn key_code_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{n}"),
+        code => NAMED_KEYS
+            .iter()
+            .find(|(named, _)| *named == code)
+            .map_or("Null", |(_, name)| name)
+            .to_string(),
+    }
+}
+
+fn parse_key_code(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse().ok()) {
+        return Some(KeyCode::F(n));
+    }
+
+    if name == "Null" {
+        return Some(KeyCode::Null);
+    }
+
+    NAMED_KEYS
+        .iter()
+        .find(|(_, named)| *named == name)
+        .map(|(code, _)| *code)
+}
+
+const MOUSE_KINDS: [(MouseEventKind, &str); 7] = [
+    (MouseEventKind::Down(MouseButton::Left), "left-down"),
+    (MouseEventKind::Up(MouseButton::Left), "left-up"),
+    (MouseEventKind::Down(MouseButton::Right), "right-down"),
+    (MouseEventKind::Down(MouseButton::Middle), "middle-down"),
+    (MouseEventKind::ScrollUp, "scroll-up"),
+    (MouseEventKind::ScrollDown, "scroll-down"),
+    (MouseEventKind::Moved, "moved"),
+];
+
+/// Mouse events pupman ignores, like drags, are all recorded as `other`.
+fn mouse_kind_name(kind: MouseEventKind) -> &'static str {
+    MOUSE_KINDS
+        .iter()
+        .find(|(named, _)| *named == kind)
+        .map_or("other", |(_, name)| name)
+}
+
+fn parse_mouse_kind(name: &str) -> Option<MouseEventKind> {
+    match name {
+        "other" => Some(MouseEventKind::Moved),
+        name => MOUSE_KINDS
+            .iter()
+            .find(|(_, named)| *named == name)
+            .map(|(kind, _)| *kind),
+    }
+}
+
+#[test]
+fn test_recording_round_trip() {
+    let recording = vec![
+        Recorded {
+            at: Duration::from_millis(3),
+            entry: Entry::UpdateFile(
+                0,
+                "/etc/pve/lxc/100.conf".into(),
+                "unprivileged: 1\n# \"quoted\"\t\\\n".into(),
+            ),
+        },
+        Recorded {
+            at: Duration::from_millis(4),
+            entry: Entry::Observed(0, "update-dir".into(), "local-zfs:subvol-100-disk-0".into()),
+        },
+        Recorded {
+            at: Duration::from_millis(5),
+            entry: Entry::Evaluated { findings: 3, bad: 1 },
+        },
+        Recorded {
+            at: Duration::from_millis(2040),
+            entry: Entry::Key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL)),
+        },
+        Recorded {
+            at: Duration::from_millis(2100),
+            entry: Entry::Key(KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT)),
+        },
+        Recorded {
+            at: Duration::from_millis(2200),
+            entry: Entry::Mouse(MouseEvent {
+                kind: MouseEventKind::ScrollDown,
+                column: 12,
+                row: 40,
+                modifiers: KeyModifiers::NONE,
+            }),
+        },
+        Recorded {
+            at: Duration::from_millis(2300),
+            entry: Entry::Paste("root:100000:65536\u{1b}".into()),
+        },
+        Recorded {
+            at: Duration::from_millis(2400),
+            entry: Entry::RemoveFile(1, "/etc/pve/lxc/100.conf".into()),
+        },
+    ];
+    let content: String = recording.iter().map(|recorded| recorded.to_json() + "\n").collect();
+
+    assert_eq!(parse_recording(&content).expect("valid"), recording);
+    assert!(content.starts_with("{\"ms\":3,\"event\":\"update-file\",\"generation\":0,"));
+    // Only what came from the host is left to the files replay runs against
+    assert_eq!(recording.iter().filter(|r| r.entry.to_event().is_some()).count(), 6);
+    assert!(matches!(
+        parse_recording("{\"ms\":1,\"event\":\"key\",\"code\":\"Nope\",\"modifiers\":0}"),
+        Err(RecordingError::Malformed(1, _))
+    ));
+    assert!(matches!(
+        parse_recording("\n{\"ms\":1,\"event\":\"paste\",\"text\":\"unclosed}"),
+        Err(RecordingError::Malformed(2, _))
+    ));
+}
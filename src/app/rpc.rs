@@ -0,0 +1,166 @@
+//! The methods of the `pupman serve` API, run on the event loop against the app's live state.
+
+use std::fmt::Write;
+use std::os::unix::fs::MetadataExt;
+
+use log::Level;
+
+use super::App;
+use super::bus::Notification;
+use crate::export::{finding_json, json_string, skipped_json};
+use crate::server::rpc::{CALL_FAILED, METHOD_NOT_FOUND, RpcCall, RpcError, RpcRequest};
+
+/// `null` or the JSON of `value`.
+fn or_null<T>(value: Option<T>, json: impl FnOnce(T) -> String) -> String {
+    value.map_or_else(|| "null".to_string(), json)
+}
+
+impl App {
+    pub(super) fn handle_rpc(&mut self, call: RpcCall) {
+        let result = match call.request.method.as_str() {
+            "findings.list" => Ok(self.rpc_findings()),
+            "containers.get" => self.rpc_container(&call.request),
+            "fixes.apply" => self.rpc_apply_fix(&call.request),
+            method => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {method}"))),
+        };
+
+        // The client may have given up waiting
+        let _ = call.reply.send(result);
+    }
+
+    fn rpc_findings(&self) -> String {
+        let findings: Vec<_> = self
+            .state
+            .findings
+            .iter()
+            .map(|finding| finding_json(finding, &self.history))
+            .collect();
+
+        format!(
+            "{{\"loading\":{},\"findings\":[{}]}}",
+            self.state.is_loading(),
+            findings.join(",")
+        )
+    }
+
+    fn rpc_container(&self, request: &RpcRequest) -> Result<String, RpcError> {
+        let vmid = request.u32_param("vmid")?;
+        let filename = format!("{vmid}.conf");
+        let (Some(config), Some(detail)) = (
+            self.state.lxc_configs.get(filename.as_str()),
+            self.state.container_detail(&filename),
+        ) else {
+            return Err(RpcError::new(CALL_FAILED, format!("No container config {filename}")));
+        };
+        let (expected_uid, expected_gid) = detail.expected_rootfs_owner();
+        let mut out = format!(
+            "{{\"vmid\":{vmid},\"unprivileged\":{},\"rootfs\":{},\"expectedRootfsOwner\":{{\"uid\":{},\"gid\":{}}}",
+            self.state
+                .dialect
+                .is_unprivileged(&config.section(None), &self.state.default_idmaps),
+            or_null(detail.rootfs, |(value, resolved)| format!(
+                "{{\"value\":{},\"mountpoint\":{},\"uid\":{},\"gid\":{}}}",
+                json_string(value),
+                or_null(resolved, |(location, _)| json_string(
+                    &location.mountpoint.to_string_lossy()
+                )),
+                or_null(resolved, |(_, metadata)| metadata.uid().to_string()),
+                or_null(resolved, |(_, metadata)| metadata.gid().to_string()),
+            )),
+            or_null(expected_uid, |uid| uid.to_string()),
+            or_null(expected_gid, |gid| gid.to_string()),
+        );
+
+        out.push_str(",\"idmaps\":[");
+
+        for (i, idmap) in detail.idmaps.iter().enumerate() {
+            let _ = write!(
+                out,
+                "{}{{\"value\":{},\"line\":{},\"error\":{}}}",
+                if i > 0 { "," } else { "" },
+                json_string(&idmap.value),
+                or_null(idmap.line, |line| line.to_string()),
+                or_null(idmap.parsed.as_ref().err(), |err| json_string(&err.to_string())),
+            );
+        }
+
+        out.push_str("],\"subids\":[");
+
+        for (i, (sub_id, entry)) in detail.subids.iter().enumerate() {
+            let _ = write!(
+                out,
+                "{}{{\"file\":\"{}\",\"user\":{},\"start\":{},\"count\":{},\"line\":{}}}",
+                if i > 0 { "," } else { "" },
+                sub_id.file_name(),
+                json_string(&entry.host_user_id),
+                entry.host_sub_id,
+                entry.host_sub_id_count,
+                entry.line_number,
+            );
+        }
+
+        let findings: Vec<_> = detail
+            .findings
+            .iter()
+            .map(|finding| finding_json(finding, &self.history))
+            .collect();
+        let skipped: Vec<_> = detail.skipped.iter().map(|skipped| skipped_json(skipped)).collect();
+        let _ = write!(
+            out,
+            "],\"findings\":[{}],\"skipped\":[{}]}}",
+            findings.join(","),
+            skipped.join(",")
+        );
+
+        Ok(out)
+    }
+
+    fn rpc_apply_fix(&mut self, request: &RpcRequest) -> Result<String, RpcError> {
+        let finding_id = request.str_param("finding_id")?;
+
+        if self.metadata.is_viewer_only() {
+            return Err(RpcError::new(
+                CALL_FAILED,
+                "Fixes cannot be applied to files inspected with --root-prefix",
+            ));
+        }
+
+        let Some(finding) = self.state.findings.iter().find(|f| f.id() == finding_id) else {
+            return Err(RpcError::new(CALL_FAILED, format!("No finding with id {finding_id}")));
+        };
+        let Some(fix) = finding.fix else {
+            return Err(RpcError::new(
+                CALL_FAILED,
+                format!("Finding {finding_id} has no automated fix"),
+            ));
+        };
+
+        self.commit_fix(fix)
+            .map_err(|err| RpcError::new(CALL_FAILED, format!("Failed to apply fix: {err:#}")))?;
+        self.bus.notifications.publish(Notification {
+            level: Level::Info,
+            message: format!("Applied fix through the API: {}", fix.description()),
+        });
+
+        let steps: Vec<_> = self
+            .state
+            .follow_up
+            .iter()
+            .flatten()
+            .map(|step| {
+                format!(
+                    "{{\"description\":{},\"command\":{},\"safe\":{}}}",
+                    json_string(&step.description),
+                    or_null(step.command_line(), |command| json_string(&command)),
+                    step.safe
+                )
+            })
+            .collect();
+
+        Ok(format!(
+            "{{\"applied\":{},\"followUp\":[{}]}}",
+            json_string(&fix.description()),
+            steps.join(",")
+        ))
+    }
+}
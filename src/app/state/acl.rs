@@ -0,0 +1,205 @@
+//! Sharing a mount point's host directory between containers with different idmaps through POSIX
+//! ACLs, as an alternative to mapping a common group into each of them.
+
+use std::path::PathBuf;
+
+use color_eyre::eyre::eyre;
+use compact_str::CompactString;
+
+use super::State;
+use crate::check::Check;
+use crate::finding::Finding;
+use crate::fs::subid::SubID;
+use crate::linux::{setfacl_default_dirs, setfacl_recursive};
+
+/// The host ids one container's root maps to, which are granted access.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AclGrant {
+    pub filename: CompactString,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// ACL entries which let every container mounting `path` read and write it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AclPlan {
+    pub path: PathBuf,
+    pub grants: Vec<AclGrant>,
+}
+
+impl AclPlan {
+    /// The entries as `setfacl -m` takes them. `X` only makes directories and files which are
+    /// already executable for someone executable.
+    pub fn entries(&self) -> String {
+        let mut entries: Vec<String> = Vec::new();
+
+        for grant in &self.grants {
+            let uid = grant.uid.map(|uid| format!("u:{uid}:rwX"));
+            let gid = grant.gid.map(|gid| format!("g:{gid}:rwX"));
+
+            // Containers sharing an idmap need a single entry
+            for entry in uid.into_iter().chain(gid) {
+                if !entries.contains(&entry) {
+                    entries.push(entry);
+                }
+            }
+        }
+
+        entries.join(",")
+    }
+
+    /// The commands [`AclPlan::apply`] runs, for previewing and running by hand. Default ACLs can
+    /// only be set on directories, so they are set through `find`.
+    pub fn commands(&self) -> [String; 2] {
+        let entries = self.entries();
+        let path = self.path.display();
+
+        [
+            format!("setfacl -R -m {entries} {path}"),
+            format!("find {path} -type d -exec setfacl -d -m {entries} {{}} +"),
+        ]
+    }
+
+    /// Grants access to what's in the directory now, then to what is created in it later.
+    pub fn apply(&self) -> color_eyre::Result<()> {
+        let entries = self.entries();
+
+        setfacl_recursive(&entries, &self.path)?;
+        setfacl_default_dirs(&entries, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl State {
+    /// The host directory of the mount point a [`Check::MountOwnership`] finding is about, and the
+    /// unprivileged containers mounting it. Fails unless there are at least two of them.
+    pub fn shared_mount(&self, finding: &Finding) -> color_eyre::Result<(PathBuf, Vec<CompactString>)> {
+        let line = finding
+            .config_line_highlights
+            .first()
+            .filter(|_| finding.check == Check::MountOwnership)
+            .ok_or_else(|| eyre!("Only the host directory of a mount point can be shared"))?;
+        let (location, _) = self
+            .lxc_configs
+            .get(&line.filename)
+            .and_then(|config| config.section(None).mount_points().find(|mount| mount.key == line.key))
+            .and_then(|mount| self.mount_info.get(mount.value))
+            .ok_or_else(|| eyre!("The mount point's host directory wasn't found"))?;
+        let path = location.mountpoint.clone();
+        let mut filenames = Vec::new();
+
+        for (filename, config) in &self.lxc_configs {
+            let section = config.section(None);
+            let shares = section.mount_points().any(|mount| {
+                self.mount_info
+                    .get(mount.value)
+                    .is_some_and(|(location, _)| location.mountpoint == path)
+            });
+
+            if shares && self.dialect.is_unprivileged(&section, &self.default_idmaps) {
+                filenames.push(filename.clone());
+            }
+        }
+
+        if filenames.len() < 2 {
+            return Err(eyre!(
+                "{} is only mounted by one unprivileged container, chown it instead",
+                path.display()
+            ));
+        }
+
+        Ok((path, filenames))
+    }
+
+    /// Plans ACLs for the host directory of the mount point a [`Check::MountOwnership`] finding is
+    /// about, so every unprivileged container mounting it can write to it.
+    pub fn acl_plan(&self, finding: &Finding) -> color_eyre::Result<AclPlan> {
+        let (path, filenames) = self.shared_mount(finding)?;
+        let mut grants = Vec::new();
+
+        for filename in filenames {
+            let idmaps = self.idmaps(&filename);
+            let host_root = |kind| {
+                idmaps.iter().find_map(|idmap| {
+                    let parsed = idmap.parsed.as_ref().ok()?;
+
+                    (parsed.kind == kind && parsed.container_id == 0).then_some(parsed.host_id)
+                })
+            };
+
+            grants.push(AclGrant {
+                uid: host_root(SubID::UID),
+                gid: host_root(SubID::GID),
+                filename,
+            });
+        }
+
+        Ok(AclPlan { path, grants })
+    }
+}
+
+#[test]
+fn test_acl_plan() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    use crate::lxc::resolve_rootfs;
+    use crate::proxmox::storage::StorageConfig;
+
+    let dir = tempfile::tempdir()?;
+    let shared = dir.path().display();
+    let mut state = State::default();
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!("unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\nmp0: {shared},mp=/data\n"),
+    )?;
+    // Mounted elsewhere, but the same host directory
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        &format!(
+            "unprivileged: 1\nlxc.idmap: u 0 4000000000 65536\nlxc.idmap: g 0 4000000000 65536\nmp1: \
+             {shared},mp=/srv\n"
+        ),
+    )?;
+
+    for value in [format!("{shared},mp=/data"), format!("{shared},mp=/srv")] {
+        let location = resolve_rootfs(&value, &StorageConfig::default())?;
+
+        state.load_dir_metadata(value, location, std::fs::metadata(dir.path())?);
+    }
+
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::MountOwnership)
+        .expect("finding");
+    let plan = state.acl_plan(finding)?;
+
+    assert_eq!(plan.path, dir.path());
+    assert_eq!(plan.grants.len(), 2);
+    assert_eq!(
+        plan.entries(),
+        "u:100000:rwX,g:100000:rwX,u:4000000000:rwX,g:4000000000:rwX"
+    );
+    assert_eq!(
+        plan.commands()[1],
+        format!("find {shared} -type d -exec setfacl -d -m {} {{}} +", plan.entries())
+    );
+
+    // With only one container left there is nothing to share
+    state.unload_config(Path::new("/etc/pve/lxc/101.conf"))?;
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::MountOwnership)
+        .expect("finding");
+
+    assert!(state.acl_plan(finding).is_err());
+
+    Ok(())
+}
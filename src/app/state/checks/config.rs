@@ -0,0 +1,208 @@
+//! Checks of how container configs are written, whether or not the container is unprivileged.
+
+use std::collections::BTreeMap;
+
+use compact_str::CompactString;
+
+use crate::app::state::State;
+use crate::check::Check;
+use crate::finding::{ConfigLine, Finding, FindingKind};
+use crate::lxc::config::{Config, ConfigFormat};
+use crate::lxc::rootfs_volume;
+use crate::proxmox::features::Features;
+use crate::proxmox::schema::{self, SchemaError};
+
+impl State {
+    /// Containers whose rootfs is the same volume.
+    pub(super) fn check_rootfs_shared(&self, findings: &mut Vec<Finding>) {
+        // Copying a config by hand instead of cloning the container leaves both on the same volume
+        let mut rootfs_users: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+        for (filename, config) in &self.lxc_configs {
+            let Some(value) = config.section(None).get_rootfs() else {
+                continue;
+            };
+            let (Ok(volume), Some(line)) = (rootfs_volume(value), config.find_line(None, "rootfs", value)) else {
+                continue;
+            };
+
+            rootfs_users.entry(volume).or_default().push((
+                ConfigLine {
+                    filename: filename.clone(),
+                    key: "rootfs".into(),
+                    line,
+                },
+                value.to_string(),
+            ));
+        }
+
+        for mut users in rootfs_users.into_values().filter(|users| users.len() > 1) {
+            users.sort_by(|(a, _), (b, _)| a.filename.cmp(&b.filename));
+
+            let (lines, mut values): (Vec<_>, Vec<_>) = users.into_iter().unzip();
+
+            values.dedup();
+            findings.push(Finding {
+                kind: FindingKind::Bad,
+                check: Check::RootfsShared,
+                message: "Containers share the same rootfs volume",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: values,
+                config_line_highlights: lines,
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Single valued keys set more than once.
+    pub(super) fn check_duplicate_keys(&self, filename: &CompactString, config: &Config, findings: &mut Vec<Finding>) {
+        for (key, lines) in config.duplicate_keys() {
+            findings.push(Finding {
+                kind: FindingKind::Bad,
+                check: Check::ConfigDuplicateKeys,
+                message: "Config sets a single valued key more than once",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: lines
+                    .into_iter()
+                    .map(|line| ConfigLine {
+                        filename: filename.clone(),
+                        key: key.into(),
+                        line,
+                    })
+                    .collect(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Keys the dialect no longer supports.
+    pub(super) fn check_deprecated_keys(&self, filename: &CompactString, config: &Config, findings: &mut Vec<Finding>) {
+        let deprecated: Vec<_> = config
+            .key_lines(None)
+            .into_iter()
+            .filter(|(key, _)| self.dialect.deprecated(key).is_some())
+            .map(|(key, line)| ConfigLine {
+                filename: filename.clone(),
+                key: key.into(),
+                line,
+            })
+            .collect();
+
+        if !deprecated.is_empty() {
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::ConfigDeprecatedKeys,
+                message: "Config uses keys which are no longer supported",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: deprecated,
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Keys and values of PVE configs which PVE's schema doesn't allow.
+    pub(super) fn check_config_schema(&self, filename: &CompactString, config: &Config, findings: &mut Vec<Finding>) {
+        // Upstream LXC configs only have raw keys
+        if config.format() != ConfigFormat::Proxmox || !self.settings.is_enabled(Check::ConfigSchema) {
+            return;
+        }
+
+        let (mut unknown, mut malformed) = (Vec::new(), Vec::new());
+
+        for (line, key, value) in config.section_entries(None) {
+            let lines = match schema::validate(key, value) {
+                Ok(()) => continue,
+                Err(SchemaError::UnknownKey) => &mut unknown,
+                Err(SchemaError::Malformed(_)) => &mut malformed,
+            };
+
+            lines.push(ConfigLine {
+                filename: filename.clone(),
+                key: key.into(),
+                line,
+            });
+        }
+
+        for (lines, message) in [
+            (unknown, "Config uses keys PVE doesn't know"),
+            (malformed, "Config has values PVE can't parse"),
+        ] {
+            if lines.is_empty() {
+                continue;
+            }
+
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::ConfigSchema,
+                message,
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: lines,
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Features which don't apply to the container, given whether it is `unprivileged`.
+    pub(super) fn check_container_features(
+        &self,
+        filename: &CompactString,
+        config: &Config,
+        unprivileged: bool,
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.settings.is_enabled(Check::ContainerFeatures) {
+            return;
+        }
+
+        let Some(value) = config.section(None).get("features") else {
+            return;
+        };
+        let Ok(features) = Features::parse(value) else {
+            return;
+        };
+        let (kind, message) = if unprivileged && features.unmountable_types().next().is_some() {
+            (
+                FindingKind::Warning,
+                "features allows mounting filesystems an unprivileged container can't mount",
+            )
+        } else if !unprivileged && features.keyctl {
+            (
+                FindingKind::Info,
+                "features turns on keyctl, which only applies to unprivileged containers",
+            )
+        } else {
+            return;
+        };
+
+        findings.push(Finding {
+            kind,
+            check: Check::ContainerFeatures,
+            message,
+            host_mapping_highlights: Vec::new(),
+            lxc_config_mapping_highlights: Vec::new(),
+            rootfs_highlights: Vec::new(),
+            config_line_highlights: config
+                .find_line(None, "features", value)
+                .map(|line| ConfigLine {
+                    filename: filename.clone(),
+                    key: "features".into(),
+                    line,
+                })
+                .into_iter()
+                .collect(),
+            subid_line_highlights: Vec::new(),
+            fix: None,
+        });
+    }
+}
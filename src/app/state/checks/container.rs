@@ -0,0 +1,762 @@
+//! Checks of an unprivileged container: its rootfs, its idmaps, and the ids those idmaps have to
+//! cover.
+
+use std::collections::hash_map::Entry;
+use std::os::unix::fs::MetadataExt;
+
+use compact_str::CompactString;
+use log::error;
+
+use super::{OwnerIds, UsedEntries};
+use crate::app::state::{State, effective_idmaps, subid_ranges_cover};
+use crate::check::Check;
+use crate::finding::{ConfigLine, Finding, FindingKind};
+use crate::fs::subid::SubID;
+use crate::linux::{LinuxError, reserved};
+use crate::lxc::config::Config;
+use crate::lxc::idmap::{IdMap, IdMapCoverage, IdMapError, idmap_coverage};
+use crate::lxc::range_end;
+use crate::settings::MappingIntent;
+
+/// The idmaps of a container, as far as they could be parsed.
+struct ContainerIdmaps {
+    /// Parsed idmaps and their line in the config, unless they came from an include
+    parsed: Vec<(IdMap, Option<usize>)>,
+    /// Whether there is a uid idmap, even one which couldn't be parsed
+    has_user_idmap: bool,
+    /// Whether there is a gid idmap, even one which couldn't be parsed
+    has_group_idmap: bool,
+}
+
+impl State {
+    /// Runs the checks which only apply to unprivileged containers on `config`.
+    pub(super) fn check_container<'s>(
+        &'s self,
+        filename: &CompactString,
+        config: &Config,
+        owner_ids: &mut OwnerIds<'s>,
+        used_entries: &mut UsedEntries<'s>,
+        findings: &mut Vec<Finding>,
+    ) {
+        self.check_rootfs_writable(config, findings);
+
+        let idmaps = self.check_idmaps(filename, config, owner_ids, used_entries, findings);
+
+        self.check_snapshot_idmaps(filename, config, findings);
+        self.check_mount_ownership(filename, config, &idmaps.parsed, findings);
+        self.check_login_users(filename, &idmaps.parsed, findings);
+        self.check_device_ownership(filename, config, &idmaps.parsed, findings);
+        self.check_host_accounts(filename, &idmaps.parsed, findings);
+        self.check_reserved_ranges(filename, &idmaps.parsed, findings);
+        self.check_rootfs_contents(config, &idmaps, findings);
+        self.check_idmap_coverage(filename, &idmaps.parsed, findings);
+        self.check_idmap_symmetry(filename, &idmaps.parsed, findings);
+        self.check_idmap_present(filename, &idmaps, findings);
+    }
+
+    /// Rootfs datasets ZFS won't let the fixes write to.
+    fn check_rootfs_writable(&self, config: &Config, findings: &mut Vec<Finding>) {
+        if self.inspects_rootfs()
+            && let Some(rootfs_value) = config.section(None).get_rootfs()
+            && let Some(zfs) = self.rootfs_space.get(rootfs_value).and_then(|space| space.zfs.as_ref())
+            && zfs.blocks_writes()
+        {
+            findings.push(Finding {
+                kind: FindingKind::Bad,
+                check: Check::RootfsWritable,
+                message: if zfs.partially_received {
+                    "Rootfs dataset has an interrupted zfs receive and cannot be fixed"
+                } else {
+                    "Rootfs dataset is read-only and cannot be fixed"
+                },
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: vec![rootfs_value.to_string()],
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Parses the container's idmaps, reporting the malformed ones, and checks each against the
+    /// rootfs owner and the subid ranges. The subid entries it maps onto are added to
+    /// `used_entries`.
+    fn check_idmaps<'s>(
+        &'s self,
+        filename: &CompactString,
+        config: &Config,
+        owner_ids: &mut OwnerIds<'s>,
+        used_entries: &mut UsedEntries<'s>,
+        findings: &mut Vec<Finding>,
+    ) -> ContainerIdmaps {
+        let mut idmaps = ContainerIdmaps {
+            parsed: Vec::new(),
+            has_user_idmap: false,
+            has_group_idmap: false,
+        };
+
+        for config_idmap in effective_idmaps(config, &self.default_idmaps) {
+            // An unusable idmap is still there, it is reported as malformed instead of missing
+            match config_idmap.kind() {
+                Some(SubID::UID) => idmaps.has_user_idmap = true,
+                Some(SubID::GID) => idmaps.has_group_idmap = true,
+                None => {},
+            }
+
+            let parsed = match &config_idmap.parsed {
+                Ok(parsed) => *parsed,
+                Err(err) => {
+                    findings.push(Finding {
+                        kind: FindingKind::Bad,
+                        check: Check::IdRangeValues,
+                        message: match err {
+                            IdMapError::FieldCount(_) | IdMapError::Kind(_) | IdMapError::Number { .. } => {
+                                "lxc.idmap entry is malformed"
+                            },
+                            IdMapError::Empty => "lxc.idmap entry maps zero ids",
+                            IdMapError::Overflow(_) => "lxc.idmap range extends past the largest id",
+                        },
+                        host_mapping_highlights: Vec::new(),
+                        lxc_config_mapping_highlights: Vec::new(),
+                        rootfs_highlights: Vec::new(),
+                        config_line_highlights: config_idmap
+                            .line
+                            .map(|line| ConfigLine {
+                                filename: filename.clone(),
+                                key: "lxc.idmap".into(),
+                                line,
+                            })
+                            .into_iter()
+                            .collect(),
+                        subid_line_highlights: Vec::new(),
+                        fix: None,
+                    });
+                    continue;
+                },
+            };
+
+            idmaps.parsed.push((parsed, config_idmap.line));
+            self.record_used_entries(filename, parsed, used_entries);
+            self.check_rootfs_ownership(filename, config, parsed, findings);
+            self.check_idmap_host_range(filename, parsed, owner_ids, findings);
+        }
+
+        idmaps
+    }
+
+    /// Adds the subid entries `idmap` maps onto to `used_entries`.
+    fn record_used_entries<'s>(&'s self, filename: &CompactString, idmap: IdMap, used_entries: &mut UsedEntries<'s>) {
+        let mappings = match idmap.kind {
+            SubID::UID => &self.host_mapping.subuid,
+            SubID::GID => &self.host_mapping.subgid,
+        };
+
+        for entry in mappings.iter().filter(|entry| {
+            entry.host_sub_id <= idmap.host_id
+                && u64::from(idmap.host_id) < range_end(entry.host_sub_id, entry.host_sub_id_count)
+        }) {
+            match used_entries
+                .iter_mut()
+                .find(|(sub_id, used, _)| *sub_id == idmap.kind && used.line_number == entry.line_number)
+            {
+                Some((_, _, filenames)) if filenames.contains(filename) => {},
+                Some((_, _, filenames)) => filenames.push(filename.clone()),
+                None => used_entries.push((idmap.kind, entry, vec![filename.clone()])),
+            }
+        }
+    }
+
+    /// A rootfs owned by other ids than the first ones `idmap` maps onto.
+    fn check_rootfs_ownership(
+        &self,
+        filename: &CompactString,
+        config: &Config,
+        idmap: IdMap,
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.inspects_rootfs() || !self.settings.is_enabled(Check::RootfsOwnership) {
+            return;
+        }
+
+        let Some(value) = config.section(None).get_rootfs() else {
+            return;
+        };
+        let Some((_, metadata)) = self.rootfs_info.get(value) else {
+            return;
+        };
+        let (owner, message) = match idmap.kind {
+            SubID::UID => (metadata.uid(), "Rootfs uid does not match host mapping"),
+            SubID::GID => (metadata.gid(), "Rootfs gid does not match host mapping"),
+        };
+
+        if owner == idmap.host_id {
+            return;
+        }
+
+        findings.push(Finding {
+            kind: FindingKind::Bad,
+            check: Check::RootfsOwnership,
+            message,
+            host_mapping_highlights: Vec::new(),
+            lxc_config_mapping_highlights: vec![(filename.clone(), idmap.kind)],
+            rootfs_highlights: vec![value.to_string()],
+            config_line_highlights: Vec::new(),
+            subid_line_highlights: Vec::new(),
+            fix: None,
+        });
+    }
+
+    /// Host ranges of `idmap` which run outside the subid ranges of the owner it maps container
+    /// id 0 onto.
+    fn check_idmap_host_range<'s>(
+        &'s self,
+        filename: &CompactString,
+        idmap: IdMap,
+        owner_ids: &mut OwnerIds<'s>,
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.settings.is_enabled(Check::IdmapHostRange) {
+            return;
+        }
+
+        let (mappings, message) = match idmap.kind {
+            SubID::UID => (
+                &self.host_mapping.subuid,
+                "LXC config's host sub uid range outside of host mapping range",
+            ),
+            SubID::GID => (
+                &self.host_mapping.subgid,
+                "LXC config's host sub gid range outside of host mapping range",
+            ),
+        };
+
+        let mut owned = Vec::new();
+
+        for mapping in mappings {
+            let host_id = match owner_ids.entry((idmap.kind, &mapping.host_user_id)) {
+                Entry::Occupied(id) => *id.get(),
+                Entry::Vacant(vacancy) => {
+                    let id = match self.owner_id(&mapping.host_user_id, idmap.kind) {
+                        Ok(id) => Some(id),
+                        Err(err) => {
+                            if matches!(err.downcast_ref(), Some(LinuxError::Timeout(..))) {
+                                findings.push(Finding {
+                                    kind: FindingKind::Warning,
+                                    check: Check::IdmapHostRange,
+                                    message: "Host range not checked, looking up its owner timed out",
+                                    host_mapping_highlights: vec![(mapping.host_user_id.clone(), idmap.kind)],
+                                    lxc_config_mapping_highlights: vec![(filename.clone(), idmap.kind)],
+                                    rootfs_highlights: Vec::new(),
+                                    config_line_highlights: Vec::new(),
+                                    subid_line_highlights: Vec::new(),
+                                    fix: None,
+                                });
+                            }
+
+                            error!(
+                                "Failed to parse id for {} {}: {err:?}",
+                                idmap.kind.idmap_kind(),
+                                mapping.host_user_id
+                            );
+                            None
+                        },
+                    };
+                    *vacancy.insert(id)
+                },
+            };
+
+            if host_id == Some(idmap.container_id) {
+                owned.push(mapping);
+            }
+        }
+
+        // An owner may hold several ranges, together they have to cover the idmap
+        if owned.is_empty() || subid_ranges_cover(owned.iter().copied(), idmap.host_id, idmap.host_end()) {
+            return;
+        }
+
+        let mut host_mapping_highlights: Vec<_> = owned
+            .iter()
+            .map(|mapping| (mapping.host_user_id.clone(), idmap.kind))
+            .collect();
+
+        host_mapping_highlights.dedup();
+        findings.push(Finding {
+            kind: FindingKind::Bad,
+            check: Check::IdmapHostRange,
+            message,
+            host_mapping_highlights,
+            lxc_config_mapping_highlights: vec![(filename.clone(), idmap.kind)],
+            rootfs_highlights: Vec::new(),
+            config_line_highlights: Vec::new(),
+            subid_line_highlights: Vec::new(),
+            fix: None,
+        });
+    }
+
+    /// Snapshot idmaps outside of root's subid ranges.
+    fn check_snapshot_idmaps(&self, filename: &CompactString, config: &Config, findings: &mut Vec<Finding>) {
+        if !self.settings.check_snapshots() || !self.settings.is_enabled(Check::SnapshotIdmaps) {
+            return;
+        }
+
+        // Idmaps a snapshot shares with the live config are checked already
+        let live: Vec<_> = config.section(None).get_lxc_idmaps().collect();
+        let outside: Vec<_> = config
+            .snapshots()
+            .flat_map(|snapshot| config.section_entries(Some(snapshot)))
+            .filter(|(_, key, value)| *key == "lxc.idmap" && !live.contains(value))
+            .filter(|(_, _, value)| {
+                value
+                    .parse::<IdMap>()
+                    .is_ok_and(|idmap| !self.root_has_subids(idmap.kind, idmap.host_id, idmap.size))
+            })
+            .map(|(line, key, _)| ConfigLine {
+                filename: filename.clone(),
+                key: key.into(),
+                line,
+            })
+            .collect();
+
+        if !outside.is_empty() {
+            findings.push(Finding {
+                kind: FindingKind::Bad,
+                check: Check::SnapshotIdmaps,
+                message: "Snapshot lxc.idmap outside of root's host range, rolling back would break the container",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: outside,
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Mount points owned by host ids the container doesn't map.
+    fn check_mount_ownership(
+        &self,
+        filename: &CompactString,
+        config: &Config,
+        idmaps: &[(IdMap, Option<usize>)],
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.inspects_rootfs() || !self.settings.is_enabled(Check::MountOwnership) {
+            return;
+        }
+
+        for mount in config.section(None).mount_points() {
+            let Some((_, metadata)) = self.mount_info.get(mount.value) else {
+                continue;
+            };
+
+            for sub_id in [SubID::UID, SubID::GID] {
+                let (owner, message) = match sub_id {
+                    SubID::UID => (
+                        metadata.uid(),
+                        "Mount point is owned by a uid the container doesn't map",
+                    ),
+                    SubID::GID => (
+                        metadata.gid(),
+                        "Mount point is owned by a gid the container doesn't map",
+                    ),
+                };
+                let Some(ranges) = idmaps_of_kind(idmaps, sub_id) else {
+                    continue;
+                };
+
+                if ranges.iter().any(|(idmap, _)| idmap.maps_host_id(owner)) {
+                    continue;
+                }
+
+                findings.push(Finding {
+                    kind: FindingKind::Warning,
+                    check: Check::MountOwnership,
+                    message,
+                    host_mapping_highlights: Vec::new(),
+                    lxc_config_mapping_highlights: vec![(filename.clone(), sub_id)],
+                    rootfs_highlights: Vec::new(),
+                    config_line_highlights: config
+                        .find_line(None, mount.key, mount.value)
+                        .map(|line| ConfigLine {
+                            filename: filename.clone(),
+                            key: mount.key.into(),
+                            line,
+                        })
+                        .into_iter()
+                        .collect(),
+                    subid_line_highlights: Vec::new(),
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    /// Container uids mapped onto host users who log in.
+    fn check_login_users(
+        &self,
+        filename: &CompactString,
+        idmaps: &[(IdMap, Option<usize>)],
+        findings: &mut Vec<Finding>,
+    ) {
+        if self.settings.is_enabled(Check::IdmapLoginUsers)
+            && !self
+                .mapped_login_users(idmaps.iter().map(|(idmap, _)| idmap))
+                .is_empty()
+        {
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::IdmapLoginUsers,
+                message: "lxc.idmap maps container uids onto a host user who logs in",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: vec![(filename.clone(), SubID::UID)],
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Device nodes owned by container ids the container doesn't map.
+    fn check_device_ownership(
+        &self,
+        filename: &CompactString,
+        config: &Config,
+        idmaps: &[(IdMap, Option<usize>)],
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.settings.is_enabled(Check::DeviceOwnership) {
+            return;
+        }
+
+        for device in config.section(None).devices() {
+            for (sub_id, owner, message) in [
+                (
+                    SubID::UID,
+                    device.uid,
+                    "Device node is owned by a uid the container doesn't map",
+                ),
+                (
+                    SubID::GID,
+                    device.gid,
+                    "Device node is owned by a gid the container doesn't map",
+                ),
+            ] {
+                let Some(ranges) = idmaps_of_kind(idmaps, sub_id) else {
+                    continue;
+                };
+
+                if ranges.iter().any(|(idmap, _)| idmap.maps_container_id(owner)) {
+                    continue;
+                }
+
+                findings.push(Finding {
+                    kind: FindingKind::Warning,
+                    check: Check::DeviceOwnership,
+                    message,
+                    host_mapping_highlights: Vec::new(),
+                    lxc_config_mapping_highlights: vec![(filename.clone(), sub_id)],
+                    rootfs_highlights: Vec::new(),
+                    config_line_highlights: config
+                        .find_line(None, device.key, device.value)
+                        .map(|line| ConfigLine {
+                            filename: filename.clone(),
+                            key: device.key.into(),
+                            line,
+                        })
+                        .into_iter()
+                        .collect(),
+                    subid_line_highlights: Vec::new(),
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    /// Container ids mapped onto ids of existing host users or groups.
+    fn check_host_accounts(
+        &self,
+        filename: &CompactString,
+        idmaps: &[(IdMap, Option<usize>)],
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.settings.is_enabled(Check::IdmapHostAccounts) {
+            return;
+        }
+
+        let parsed: Vec<_> = idmaps.iter().map(|(idmap, _)| *idmap).collect();
+        let accounts = self.mapped_host_accounts(&parsed);
+
+        if accounts.is_empty() {
+            return;
+        }
+
+        let kinds = [SubID::UID, SubID::GID]
+            .into_iter()
+            .filter(|kind| accounts.iter().any(|(sub_id, ..)| sub_id == kind));
+
+        findings.push(Finding {
+            kind: FindingKind::Warning,
+            check: Check::IdmapHostAccounts,
+            message: "lxc.idmap maps container ids onto ids of existing host users or groups",
+            host_mapping_highlights: Vec::new(),
+            lxc_config_mapping_highlights: kinds.map(|kind| (filename.clone(), kind)).collect(),
+            rootfs_highlights: Vec::new(),
+            config_line_highlights: idmaps
+                .iter()
+                .filter(|(idmap, _)| {
+                    accounts
+                        .iter()
+                        .any(|(sub_id, _, id)| idmap.kind == *sub_id && idmap.maps_host_id(*id))
+                })
+                .filter_map(|(_, line)| *line)
+                .map(|line| ConfigLine {
+                    filename: filename.clone(),
+                    key: "lxc.idmap".into(),
+                    line,
+                })
+                .collect(),
+            subid_line_highlights: Vec::new(),
+            fix: None,
+        });
+    }
+
+    /// Container ids mapped onto host ids reserved for other uses.
+    fn check_reserved_ranges(
+        &self,
+        filename: &CompactString,
+        idmaps: &[(IdMap, Option<usize>)],
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.settings.is_enabled(Check::IdmapReservedRanges) {
+            return;
+        }
+
+        let reserved: Vec<_> = idmaps
+            .iter()
+            .filter(|(idmap, _)| reserved::colliding(idmap.host_id, idmap.size).next().is_some())
+            .collect();
+
+        if reserved.is_empty() {
+            return;
+        }
+
+        let kinds = [SubID::UID, SubID::GID]
+            .into_iter()
+            .filter(|kind| reserved.iter().any(|(idmap, _)| idmap.kind == *kind));
+
+        findings.push(Finding {
+            kind: FindingKind::Info,
+            check: Check::IdmapReservedRanges,
+            message: "lxc.idmap maps container ids onto host ids reserved for other uses",
+            host_mapping_highlights: Vec::new(),
+            lxc_config_mapping_highlights: kinds.map(|kind| (filename.clone(), kind)).collect(),
+            rootfs_highlights: Vec::new(),
+            config_line_highlights: reserved
+                .iter()
+                .filter_map(|(_, line)| *line)
+                .map(|line| ConfigLine {
+                    filename: filename.clone(),
+                    key: "lxc.idmap".into(),
+                    line,
+                })
+                .collect(),
+            subid_line_highlights: Vec::new(),
+            fix: None,
+        });
+    }
+
+    /// Files in the rootfs owned by ids the container doesn't map, as found by the last scan.
+    fn check_rootfs_contents(&self, config: &Config, idmaps: &ContainerIdmaps, findings: &mut Vec<Finding>) {
+        if !self.inspects_rootfs()
+            || !self.settings.is_enabled(Check::RootfsContents)
+            || !idmaps.has_user_idmap
+            || !idmaps.has_group_idmap
+        {
+            return;
+        }
+
+        let parsed: Vec<_> = idmaps.parsed.iter().map(|(idmap, _)| *idmap).collect();
+
+        if let Some(rootfs_value) = config.section(None).get_rootfs()
+            && let Some(scan) = self.ownership_scans.get(rootfs_value)
+            && !scan.unmapped(&parsed).is_empty()
+        {
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::RootfsContents,
+                message: "Rootfs contains files owned by ids the container doesn't map",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: vec![rootfs_value.to_owned()],
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Container ids left unmapped or mapped twice, which LXC refuses to start with.
+    fn check_idmap_coverage(
+        &self,
+        filename: &CompactString,
+        idmaps: &[(IdMap, Option<usize>)],
+        findings: &mut Vec<Finding>,
+    ) {
+        if !self.settings.is_enabled(Check::IdmapCoverage) {
+            return;
+        }
+
+        for sub_id in [SubID::UID, SubID::GID] {
+            let Some(ranges) = idmaps_of_kind(idmaps, sub_id) else {
+                continue;
+            };
+            let (values, lines): (Vec<_>, Vec<_>) = ranges.into_iter().unzip();
+
+            let issues = idmap_coverage(&values);
+            let mut overlapping: Vec<_> = issues
+                .iter()
+                .filter_map(|issue| match issue {
+                    IdMapCoverage::Overlap { first, second, .. } => Some([*first, *second]),
+                    IdMapCoverage::Gap { .. } => None,
+                })
+                .flatten()
+                .filter_map(|i| lines[i])
+                .collect();
+
+            overlapping.sort_unstable();
+            overlapping.dedup();
+
+            let has_gap = issues.iter().any(|issue| matches!(issue, IdMapCoverage::Gap { .. }));
+            let has_overlap = issues
+                .iter()
+                .any(|issue| matches!(issue, IdMapCoverage::Overlap { .. }));
+            let (gap_message, overlap_message) = match sub_id {
+                SubID::UID => (
+                    "lxc.idmap leaves container uids unmapped",
+                    "lxc.idmap maps the same container uids twice",
+                ),
+                SubID::GID => (
+                    "lxc.idmap leaves container gids unmapped",
+                    "lxc.idmap maps the same container gids twice",
+                ),
+            };
+
+            for (present, message, lines) in [
+                (has_gap, gap_message, Vec::new()),
+                (has_overlap, overlap_message, overlapping),
+            ] {
+                if !present {
+                    continue;
+                }
+
+                findings.push(Finding {
+                    kind: FindingKind::Bad,
+                    check: Check::IdmapCoverage,
+                    message,
+                    host_mapping_highlights: Vec::new(),
+                    lxc_config_mapping_highlights: vec![(filename.clone(), sub_id)],
+                    rootfs_highlights: Vec::new(),
+                    config_line_highlights: lines
+                        .into_iter()
+                        .map(|line| ConfigLine {
+                            filename: filename.clone(),
+                            key: "lxc.idmap".into(),
+                            line,
+                        })
+                        .collect(),
+                    subid_line_highlights: Vec::new(),
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    /// Uids and gids mapped differently, when the container is meant to map both the same.
+    fn check_idmap_symmetry(
+        &self,
+        filename: &CompactString,
+        idmaps: &[(IdMap, Option<usize>)],
+        findings: &mut Vec<Finding>,
+    ) {
+        let vmid = filename.strip_suffix(".conf").unwrap_or(filename);
+
+        if !self.settings.is_enabled(Check::IdmapSymmetry) || self.settings.mapping_intent(vmid) != MappingIntent::Both
+        {
+            return;
+        }
+
+        let ranges = |sub_id| {
+            let mut ranges: Vec<_> = idmaps_of_kind(idmaps, sub_id)?
+                .into_iter()
+                .map(|(idmap, _)| (idmap.container_id, idmap.host_id, idmap.size))
+                .collect();
+
+            ranges.sort_unstable();
+            Some(ranges)
+        };
+
+        if let (Some(uid_ranges), Some(gid_ranges)) = (ranges(SubID::UID), ranges(SubID::GID))
+            && uid_ranges != gid_ranges
+        {
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::IdmapSymmetry,
+                message: "lxc.idmap maps uids and gids differently",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: vec![(filename.clone(), SubID::UID), (filename.clone(), SubID::GID)],
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Containers without any uid or gid idmap.
+    // TODO: This still needs a test
+    fn check_idmap_present(&self, filename: &CompactString, idmaps: &ContainerIdmaps, findings: &mut Vec<Finding>) {
+        for (present, sub_id, message) in [
+            (
+                idmaps.has_user_idmap,
+                SubID::UID,
+                "lxc.idmap for uid is not set in config",
+            ),
+            (
+                idmaps.has_group_idmap,
+                SubID::GID,
+                "lxc.idmap for gid is not set in config",
+            ),
+        ] {
+            if present {
+                continue;
+            }
+
+            findings.push(Finding {
+                kind: FindingKind::Bad,
+                check: Check::IdmapPresent,
+                message,
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: vec![(filename.clone(), sub_id)],
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+}
+
+/// The `idmaps` of kind `sub_id`, or `None` if there are none. Checks of a kind's ranges skip
+/// containers without any, since those are reported as missing idmaps instead.
+fn idmaps_of_kind<T: Copy>(idmaps: &[(IdMap, T)], sub_id: SubID) -> Option<Vec<(IdMap, T)>> {
+    let ranges: Vec<_> = idmaps
+        .iter()
+        .filter(|(idmap, _)| idmap.kind == sub_id)
+        .copied()
+        .collect();
+
+    (!ranges.is_empty()).then_some(ranges)
+}
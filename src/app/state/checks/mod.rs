@@ -0,0 +1,129 @@
+//! The checks findings come from, grouped by what they look at: subid files, container configs and
+//! the unprivileged containers themselves.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use ahash::RandomState;
+use chrono::Utc;
+use compact_str::CompactString;
+
+use super::{CheckRun, State};
+use crate::app::ui::IdMapEntry;
+use crate::check::Check;
+use crate::finding::{Finding, FindingKind};
+use crate::fs::subid::SubID;
+
+mod config;
+mod container;
+mod subid;
+
+/// Subid entries whose ids containers map onto, with the containers mapping onto them.
+type UsedEntries<'s> = Vec<(SubID, &'s IdMapEntry, Vec<CompactString>)>;
+
+/// Ids of subid owners, kept between containers so each owner is looked up once. Failed lookups
+/// are kept too, so a hung `id` holds up evaluation only once.
+type OwnerIds<'s> = HashMap<(SubID, &'s CompactString), Option<u32>, RandomState>;
+
+impl State {
+    /// Findings are re-evaluated based on latest update
+    // TODO: Check for overlaps between configs
+    pub fn evaluate_findings(&mut self) {
+        let mut findings = Vec::new();
+        let mut owner_ids = HashMap::with_hasher(RandomState::new());
+        let mut used_entries = Vec::new();
+
+        self.check_subid_files(&mut findings);
+        self.check_subid_duplicates(&mut findings);
+        self.check_subid_formatting(&mut findings);
+        self.check_subid_managed(&mut findings);
+        self.check_subid_owners(&mut findings);
+        self.check_subid_ranges(&mut findings);
+        self.check_rootfs_shared(&mut findings);
+
+        for (filename, config) in &self.lxc_configs {
+            self.check_duplicate_keys(filename, config, &mut findings);
+            self.check_deprecated_keys(filename, config, &mut findings);
+            self.check_config_schema(filename, config, &mut findings);
+
+            let unprivileged = self
+                .dialect
+                .is_unprivileged(&config.section(None), &self.default_idmaps);
+
+            self.check_container_features(filename, config, unprivileged, &mut findings);
+
+            if unprivileged {
+                self.check_container(filename, config, &mut owner_ids, &mut used_entries, &mut findings);
+            }
+        }
+
+        self.check_subid_symmetry(used_entries, &mut findings);
+        self.check_file_monitoring(&mut findings);
+
+        self.findings = findings;
+        self.findings.retain(|f| self.settings.is_enabled(f.check));
+        self.sort_findings(|_| None);
+        self.evaluate_skipped_checks();
+
+        // Marks of findings which went away, like once they were fixed, are dropped
+        let findings = &self.findings;
+
+        self.marked_findings
+            .retain(|id| findings.iter().any(|finding| finding.id() == *id));
+        self.stats.record_evaluation(&self.findings);
+        self.record_owner_findings(Utc::now());
+
+        let now = Instant::now();
+
+        for check in Check::ALL {
+            if !self.settings.is_enabled(check) {
+                continue;
+            }
+
+            let findings = self
+                .findings
+                .iter()
+                .filter(|f| f.check == check && f.kind != FindingKind::Good)
+                .count();
+
+            self.check_runs.insert(
+                check,
+                CheckRun {
+                    last_run: now,
+                    findings,
+                },
+            );
+        }
+    }
+
+    /// Whether pupman can keep up with changes to the files it reads.
+    fn check_file_monitoring(&self, findings: &mut Vec<Finding>) {
+        if self.config_dir_unavailable.is_some() {
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::ConfigDirAvailable,
+                message: "Container config directory is unavailable, configs may be outdated",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+
+        if self.polling_files {
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::FilesWatched,
+                message: "Files are polled for changes, as inotify isn't available",
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Checks of /etc/subuid and /etc/subgid on their own, apart from the containers using them.
+
+use std::collections::HashMap;
+
+use ahash::RandomState;
+use compact_str::CompactString;
+
+use super::UsedEntries;
+use crate::app::state::State;
+use crate::app::state::import::overlaps;
+use crate::app::state::shadow::manual_entries;
+use crate::app::ui::IdMapEntry;
+use crate::check::Check;
+use crate::finding::{Finding, FindingKind};
+use crate::fix::Fix;
+use crate::fs::subid::SubID;
+use crate::linux::passwd::Passwd;
+use crate::lxc::{ID_SPACE_END, range_end};
+
+impl State {
+    /// Missing subid files, and lines in them which aren't entries.
+    pub(super) fn check_subid_files(&self, findings: &mut Vec<Finding>) {
+        for &sub_id in &self.missing_subids {
+            findings.push(Finding {
+                kind: FindingKind::Bad,
+                check: Check::SubidFiles,
+                message: match sub_id {
+                    SubID::UID => "/etc/subuid does not exist, unprivileged containers cannot start",
+                    SubID::GID => "/etc/subgid does not exist, unprivileged containers cannot start",
+                },
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: Some(Fix::CreateSubid(sub_id)),
+            });
+        }
+
+        for (invalid, sub_id, message) in [
+            (
+                &self.host_mapping.subuid_invalid,
+                SubID::UID,
+                "/etc/subuid has lines which aren't valid entries",
+            ),
+            (
+                &self.host_mapping.subgid_invalid,
+                SubID::GID,
+                "/etc/subgid has lines which aren't valid entries",
+            ),
+        ] {
+            if invalid.is_empty() {
+                continue;
+            }
+
+            findings.push(Finding {
+                kind: FindingKind::Bad,
+                check: Check::SubidSyntax,
+                message,
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: invalid.iter().map(|line| (sub_id, line.line_number)).collect(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Entries of the same owner whose ranges overlap.
+    pub(super) fn check_subid_duplicates(&self, findings: &mut Vec<Finding>) {
+        let no_users = Passwd::default();
+        let passwd = self.host_users.as_ref().unwrap_or(&no_users);
+        // Both ways the duplicated user is named, so either entry is highlighted
+        let duplicate_highlights = |first: &CompactString, duplicate: &CompactString, sub_id| {
+            let mut highlights = vec![(first.clone(), sub_id)];
+
+            if duplicate != first {
+                highlights.push((duplicate.clone(), sub_id));
+            }
+
+            highlights
+        };
+        let mut has_duplicates = false;
+
+        for (mappings, sub_id, message) in [
+            (
+                &self.host_mapping.subuid,
+                SubID::UID,
+                "Cannot have overlapping entries for the same user",
+            ),
+            (
+                &self.host_mapping.subgid,
+                SubID::GID,
+                "Cannot have overlapping entries for the same group",
+            ),
+        ] {
+            let mut owners: HashMap<_, Vec<&IdMapEntry>, _> = HashMap::with_hasher(RandomState::new());
+
+            for mapping in mappings {
+                // Owners are compared by uid where it is known, so `root` and `0` are one user
+                let ranges = owners
+                    .entry(passwd.owner_uid(&mapping.host_user_id).ok_or(&mapping.host_user_id))
+                    .or_default();
+
+                // Separate ranges for one owner are fine, granting the same ids twice is not
+                if let Some(first) = ranges.iter().find(|first| {
+                    overlaps(
+                        first.host_sub_id,
+                        first.host_sub_id_count,
+                        mapping.host_sub_id,
+                        mapping.host_sub_id_count,
+                    )
+                }) {
+                    has_duplicates = true;
+                    findings.push(Finding {
+                        kind: FindingKind::Bad,
+                        check: Check::SubidDuplicates,
+                        message,
+                        host_mapping_highlights: duplicate_highlights(
+                            &first.host_user_id,
+                            &mapping.host_user_id,
+                            sub_id,
+                        ),
+                        lxc_config_mapping_highlights: Vec::new(),
+                        rootfs_highlights: Vec::new(),
+                        config_line_highlights: Vec::new(),
+                        subid_line_highlights: Vec::new(),
+                        fix: None,
+                    });
+                }
+
+                ranges.push(mapping);
+            }
+        }
+
+        if !has_duplicates {
+            findings.push(Finding {
+                kind: FindingKind::Good,
+                check: Check::SubidDuplicates,
+                message: "No overlapping ranges found in subuid/subgid mappings",
+                // TODO: Highlight all entries?
+                host_mapping_highlights: Vec::new(),
+                lxc_config_mapping_highlights: Vec::new(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: None,
+            });
+        }
+    }
+
+    /// Entries written in a form shadow-utils wouldn't write them in.
+    pub(super) fn check_subid_formatting(&self, findings: &mut Vec<Finding>) {
+        for (mappings, sub_id, message) in [
+            (
+                &self.host_mapping.subuid,
+                SubID::UID,
+                "Unusual formatting in /etc/subuid entries",
+            ),
+            (
+                &self.host_mapping.subgid,
+                SubID::GID,
+                "Unusual formatting in /etc/subgid entries",
+            ),
+        ] {
+            let host_mapping_highlights: Vec<_> = mappings
+                .iter()
+                .filter(|mapping| mapping.unusual_format)
+                .map(|mapping| (mapping.host_user_id.clone(), sub_id))
+                .collect();
+
+            if !host_mapping_highlights.is_empty() {
+                findings.push(Finding {
+                    kind: FindingKind::Warning,
+                    check: Check::SubidFormatting,
+                    message,
+                    host_mapping_highlights,
+                    lxc_config_mapping_highlights: Vec::new(),
+                    rootfs_highlights: Vec::new(),
+                    config_line_highlights: Vec::new(),
+                    subid_line_highlights: Vec::new(),
+                    fix: Some(Fix::NormalizeSubid(sub_id)),
+                });
+            }
+        }
+    }
+
+    /// Entries added by hand, which `usermod` may reorder or drop.
+    pub(super) fn check_subid_managed(&self, findings: &mut Vec<Finding>) {
+        for (mappings, sub_id, message) in [
+            (
+                &self.host_mapping.subuid,
+                SubID::UID,
+                "/etc/subuid has hand-written entries which usermod may rewrite",
+            ),
+            (
+                &self.host_mapping.subgid,
+                SubID::GID,
+                "/etc/subgid has hand-written entries which usermod may rewrite",
+            ),
+        ] {
+            let mut host_mapping_highlights: Vec<_> = manual_entries(mappings, self.shadow_backups.get(&sub_id))
+                .into_iter()
+                .map(|(entry, _)| (entry.host_user_id.clone(), sub_id))
+                .collect();
+
+            host_mapping_highlights.dedup();
+
+            if !host_mapping_highlights.is_empty() {
+                findings.push(Finding {
+                    kind: FindingKind::Warning,
+                    check: Check::SubidManaged,
+                    message,
+                    host_mapping_highlights,
+                    lxc_config_mapping_highlights: Vec::new(),
+                    rootfs_highlights: Vec::new(),
+                    config_line_highlights: Vec::new(),
+                    subid_line_highlights: Vec::new(),
+                    fix: Some(Fix::ReAddWithUsermod(sub_id)),
+                });
+            }
+        }
+    }
+
+    /// Owners which aren't users on the host.
+    pub(super) fn check_subid_owners(&self, findings: &mut Vec<Finding>) {
+        // NIS users aren't listed, so any owner could be one of them
+        let Some(passwd) = self.host_users.as_ref().filter(|passwd| !passwd.includes_nis) else {
+            return;
+        };
+
+        for (mappings, sub_id, message) in [
+            (
+                &self.host_mapping.subuid,
+                SubID::UID,
+                "Subuid owner is not a user in /etc/passwd",
+            ),
+            (
+                &self.host_mapping.subgid,
+                SubID::GID,
+                "Subgid owner is not a user in /etc/passwd",
+            ),
+        ] {
+            for mapping in mappings {
+                let owner = mapping.host_user_id.as_str();
+
+                // Numeric owners are fine without a login, shadow-utils matches them by uid
+                if passwd.user(owner).is_some() || owner.parse::<u32>().is_ok() {
+                    continue;
+                }
+
+                let message = if sub_id == SubID::GID && self.host_groups.iter().any(|group| group.name == owner) {
+                    "Subgid owner is a group, but subgid entries belong to users"
+                } else {
+                    message
+                };
+
+                findings.push(Finding {
+                    kind: FindingKind::Warning,
+                    check: Check::SubidOwner,
+                    message,
+                    host_mapping_highlights: vec![(mapping.host_user_id.clone(), sub_id)],
+                    lxc_config_mapping_highlights: Vec::new(),
+                    rootfs_highlights: Vec::new(),
+                    config_line_highlights: Vec::new(),
+                    subid_line_highlights: Vec::new(),
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    /// Ranges which are empty or run past the largest id.
+    pub(super) fn check_subid_ranges(&self, findings: &mut Vec<Finding>) {
+        for (mappings, sub_id) in [
+            (&self.host_mapping.subuid, SubID::UID),
+            (&self.host_mapping.subgid, SubID::GID),
+        ] {
+            for mapping in mappings {
+                let message = if mapping.host_sub_id_count == 0 {
+                    "Subordinate id range is empty"
+                } else if range_end(mapping.host_sub_id, mapping.host_sub_id_count) > ID_SPACE_END {
+                    "Subordinate id range extends past the largest id"
+                } else {
+                    continue;
+                };
+
+                findings.push(Finding {
+                    kind: FindingKind::Bad,
+                    check: Check::IdRangeValues,
+                    message,
+                    host_mapping_highlights: vec![(mapping.host_user_id.clone(), sub_id)],
+                    lxc_config_mapping_highlights: Vec::new(),
+                    rootfs_highlights: Vec::new(),
+                    config_line_highlights: Vec::new(),
+                    subid_line_highlights: Vec::new(),
+                    fix: None,
+                });
+            }
+        }
+    }
+
+    /// Owners of the `used_entries` containers map onto need both a subuid and a subgid entry.
+    pub(super) fn check_subid_symmetry(&self, used_entries: UsedEntries, findings: &mut Vec<Finding>) {
+        if !self.settings.is_enabled(Check::SubidSymmetry) {
+            return;
+        }
+
+        let no_users = Passwd::default();
+        let passwd = self.host_users.as_ref().unwrap_or(&no_users);
+
+        for (sub_id, entry, filenames) in used_entries {
+            let counterpart = sub_id.counterpart();
+            let counterparts = match counterpart {
+                SubID::UID => &self.host_mapping.subuid,
+                SubID::GID => &self.host_mapping.subgid,
+            };
+
+            if counterparts
+                .iter()
+                .any(|other| passwd.same_user(&other.host_user_id, &entry.host_user_id))
+            {
+                continue;
+            }
+
+            // Handing out ids another user already has would only trade this finding for a worse one
+            let taken = counterparts.iter().any(|other| {
+                u64::from(other.host_sub_id) < range_end(entry.host_sub_id, entry.host_sub_id_count)
+                    && u64::from(entry.host_sub_id) < range_end(other.host_sub_id, other.host_sub_id_count)
+            });
+
+            findings.push(Finding {
+                kind: FindingKind::Warning,
+                check: Check::SubidSymmetry,
+                message: match sub_id {
+                    SubID::UID => "Subuid owner whose range containers use has no /etc/subgid entry",
+                    SubID::GID => "Subgid owner whose range containers use has no /etc/subuid entry",
+                },
+                host_mapping_highlights: vec![(entry.host_user_id.clone(), sub_id)],
+                lxc_config_mapping_highlights: filenames.into_iter().map(|filename| (filename, sub_id)).collect(),
+                rootfs_highlights: Vec::new(),
+                config_line_highlights: Vec::new(),
+                subid_line_highlights: Vec::new(),
+                fix: (!taken).then_some(Fix::MirrorSubidRange {
+                    sub_id: counterpart,
+                    start: entry.host_sub_id,
+                    count: entry.host_sub_id_count,
+                }),
+            });
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Actions which lose work, held back until the user confirms them in a dialog.
+
+use super::State;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConfirmAction {
+    Quit,
+    /// Closes the open editor without writing its changes.
+    DiscardEdits,
+}
+
+impl ConfirmAction {
+    pub fn question(self) -> &'static str {
+        match self {
+            ConfirmAction::Quit => "Quit pupman?",
+            ConfirmAction::DiscardEdits => "Discard your changes?",
+        }
+    }
+
+    /// What confirming does, for the key which does it.
+    pub fn label(self) -> &'static str {
+        match self {
+            ConfirmAction::Quit => "Quit",
+            ConfirmAction::DiscardEdits => "Discard",
+        }
+    }
+}
+
+/// An action waiting for confirmation, along with what it would throw away.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Confirmation {
+    pub action: ConfirmAction,
+    pub reasons: Vec<String>,
+}
+
+impl State {
+    /// What quitting now would throw away or cut short. Quitting only asks for confirmation when
+    /// there is something.
+    pub fn quit_blockers(&self) -> Vec<String> {
+        let mut reasons: Vec<_> = self
+            .unsaved_changes()
+            .into_iter()
+            .map(|file| format!("Unsaved changes to {file}"))
+            .collect();
+
+        if let Some(preview) = &self.write_preview {
+            reasons.push(match preview.writes.len() {
+                1 => "A file is waiting to be written".to_string(),
+                count => format!("{count} files are waiting to be written"),
+            });
+        }
+
+        match self.changes.len() {
+            0 => {},
+            1 => reasons.push("A queued change wasn't applied".to_string()),
+            count => reasons.push(format!("{count} queued changes weren't applied")),
+        }
+
+        if let Some(import) = &self.import
+            && import.reviewing
+        {
+            reasons.push("Imported entries are waiting to be applied".to_string());
+        }
+
+        if let Some(shift) = &self.ownership_shift
+            && shift.is_running()
+        {
+            reasons.push(format!(
+                "Shifting the owners in {} would stop halfway",
+                shift.path.display()
+            ));
+        }
+
+        reasons
+    }
+
+    /// Asks whether to discard the changes of the open editor.
+    pub fn confirm_discard(&mut self) {
+        let reasons = self
+            .unsaved_changes()
+            .into_iter()
+            .map(|file| format!("Unsaved changes to {file}"))
+            .collect();
+
+        self.confirmation = Some(Confirmation {
+            action: ConfirmAction::DiscardEdits,
+            reasons,
+        });
+    }
+}
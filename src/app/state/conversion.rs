@@ -0,0 +1,325 @@
+//! Converting a privileged container to an unprivileged one, as a checklist of steps applied one at
+//! a time.
+
+use std::os::unix::fs::MetadataExt;
+
+use color_eyre::eyre::eyre;
+use compact_str::CompactString;
+
+use super::wizard::{CONTAINER_IDS, generated_idmaps};
+use super::{State, finding_vmid};
+use crate::fs::subid::SubID;
+use crate::lxc::config::ConfigFormat;
+use crate::lxc::idmap::IdMap;
+use crate::proxmox::features::Features;
+
+/// A step of a conversion, in the order they are applied.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConversionStep {
+    /// Gives root the host ids the container is mapped onto in /etc/subuid and /etc/subgid.
+    AddSubids,
+    /// Sets `unprivileged: 1` and the container's idmaps.
+    EditConfig,
+    /// Shifts the owners of the rootfs' files onto the mapped host ids.
+    ShiftRootfs,
+}
+
+impl ConversionStep {
+    pub const ALL: [ConversionStep; 3] = [
+        ConversionStep::AddSubids,
+        ConversionStep::EditConfig,
+        ConversionStep::ShiftRootfs,
+    ];
+}
+
+/// A step of a conversion as things stand on the host.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConversionItem {
+    pub step: ConversionStep,
+    pub description: String,
+    /// Whether the host already looks the way the step leaves it.
+    pub done: bool,
+    /// Why the step can't be applied from here.
+    pub blocked: Option<String>,
+}
+
+/// The conversion assistant, while it is open.
+#[derive(Debug)]
+pub struct Conversion {
+    /// The privileged configs to pick from, as they were when the assistant was opened, so a
+    /// container stays listed once its config was converted.
+    pub configs: Vec<CompactString>,
+    pub selected: usize,
+    /// The highlighted step.
+    pub step: usize,
+    /// The first host id to map the container's ids to.
+    pub offset: u32,
+}
+
+impl Conversion {
+    pub fn filename(&self) -> Option<&CompactString> {
+        self.configs.get(self.selected)
+    }
+}
+
+impl State {
+    /// Opens the conversion assistant on the container of the selected finding, or the first
+    /// privileged container when the finding isn't about a privileged one.
+    pub fn open_conversion(&mut self) -> color_eyre::Result<()> {
+        let configs: Vec<_> = self
+            .lxc_configs
+            .iter()
+            .filter(|(_, config)| {
+                !self
+                    .dialect
+                    .is_unprivileged(&config.section(None), &self.default_idmaps)
+            })
+            .map(|(filename, _)| filename.clone())
+            .collect();
+
+        if configs.is_empty() {
+            return Err(eyre!("There are no privileged containers to convert"));
+        }
+
+        let selected = self
+            .selected_finding
+            .and_then(|index| self.findings.get(index))
+            .and_then(|finding| finding_vmid(&self.lxc_configs, finding))
+            .and_then(|vmid| configs.iter().position(|filename| *filename == format!("{vmid}.conf")))
+            .unwrap_or(0);
+        let offset = self.suggested_offset(&configs[selected]);
+
+        self.conversion = Some(Conversion {
+            configs,
+            selected,
+            step: 0,
+            offset,
+        });
+
+        Ok(())
+    }
+
+    /// Moves the assistant on to the next container, wrapping around, with a free offset for it.
+    pub fn next_conversion_container(&mut self) {
+        let Some(conversion) = &self.conversion else {
+            return;
+        };
+        let selected = (conversion.selected + 1) % conversion.configs.len();
+        let offset = self.suggested_offset(&conversion.configs[selected]);
+
+        if let Some(conversion) = &mut self.conversion {
+            conversion.selected = selected;
+            conversion.step = 0;
+            conversion.offset = offset;
+        }
+    }
+
+    /// The `root:start:count` entries /etc/subuid and /etc/subgid are missing for the conversion.
+    pub fn conversion_subid_entries(&self, conversion: &Conversion) -> Vec<(SubID, String)> {
+        [SubID::UID, SubID::GID]
+            .into_iter()
+            .filter(|&sub_id| !self.root_has_subids(sub_id, conversion.offset, CONTAINER_IDS))
+            .map(|sub_id| (sub_id, format!("root:{}:{CONTAINER_IDS}", conversion.offset)))
+            .collect()
+    }
+
+    /// The config keys the conversion sets, with their values. Upstream LXC has no `unprivileged`
+    /// key, its containers are unprivileged once they have idmaps.
+    pub fn conversion_config_values(&self, conversion: &Conversion) -> Vec<(&'static str, Vec<String>)> {
+        let idmaps = [SubID::UID, SubID::GID]
+            .into_iter()
+            .flat_map(|kind| generated_idmaps(kind, conversion.offset, None))
+            .map(|idmap| idmap.to_string())
+            .collect();
+        let format = conversion
+            .filename()
+            .and_then(|filename| self.lxc_configs.get(filename))
+            .map(|config| config.format())
+            .unwrap_or_default();
+        let mut values = Vec::new();
+
+        if format == ConfigFormat::Proxmox {
+            values.push(("unprivileged", vec!["1".to_string()]));
+        }
+
+        values.push(("lxc.idmap", idmaps));
+        values
+    }
+
+    /// The steps of the conversion, with which are done already worked out from the host as it is
+    /// now, so steps applied outside of pupman are picked up too.
+    pub fn conversion_steps(&self, conversion: &Conversion) -> Vec<ConversionItem> {
+        let Some(filename) = conversion.filename() else {
+            return Vec::new();
+        };
+        let offset = conversion.offset;
+        let last_id = offset + (CONTAINER_IDS - 1);
+        let config = self.lxc_configs.get(filename);
+        let rootfs = config.and_then(|config| config.section(None).get_rootfs());
+        let rootfs_info = rootfs.and_then(|value| self.rootfs_info.get(value));
+
+        ConversionStep::ALL
+            .into_iter()
+            .map(|step| {
+                let (description, done, blocked) = match step {
+                    ConversionStep::AddSubids => (
+                        format!("Give root the host ids {offset}-{last_id} in /etc/subuid and /etc/subgid"),
+                        self.conversion_subid_entries(conversion).is_empty(),
+                        None,
+                    ),
+                    ConversionStep::EditConfig => {
+                        let expected: Vec<_> = [SubID::UID, SubID::GID]
+                            .into_iter()
+                            .flat_map(|kind| generated_idmaps(kind, offset, None))
+                            .collect();
+                        let idmaps: Vec<IdMap> = self
+                            .idmaps(filename)
+                            .iter()
+                            .filter_map(|idmap| idmap.parsed.clone().ok())
+                            .collect();
+                        let unprivileged = config.is_some_and(|config| {
+                            self.dialect
+                                .is_unprivileged(&config.section(None), &self.default_idmaps)
+                        });
+
+                        (
+                            format!("Make {filename} unprivileged, mapping its ids onto host ids from {offset} on"),
+                            unprivileged && idmaps == expected,
+                            config.is_none().then(|| format!("{filename} no longer exists")),
+                        )
+                    },
+                    ConversionStep::ShiftRootfs => {
+                        let description = match rootfs_info {
+                            Some((location, _)) => format!(
+                                "Shift the owners of the files in {} from 0 to {offset}",
+                                location.mountpoint.display()
+                            ),
+                            None => format!("Shift the owners of the rootfs' files from 0 to {offset}"),
+                        };
+                        let blocked = match (rootfs, rootfs_info) {
+                            (None, _) => Some("the container has no rootfs".to_string()),
+                            (Some(_), None) => Some("the rootfs wasn't found on this host".to_string()),
+                            (Some(_), Some(_)) => None,
+                        };
+                        let done = rootfs_info
+                            .is_some_and(|(_, metadata)| metadata.uid() == offset && metadata.gid() == offset);
+
+                        (description, done, blocked)
+                    },
+                };
+
+                ConversionItem {
+                    step,
+                    description,
+                    done,
+                    blocked,
+                }
+            })
+            .collect()
+    }
+
+    /// What the conversion leaves alone, which may keep the container from working unprivileged.
+    pub fn conversion_notes(&self, conversion: &Conversion) -> Vec<String> {
+        let Some(config) = conversion
+            .filename()
+            .and_then(|filename| self.lxc_configs.get(filename))
+        else {
+            return Vec::new();
+        };
+        let section = config.section(None);
+        let mut notes = Vec::new();
+
+        if let Some(features) = section.get("features").and_then(|value| Features::parse(value).ok()) {
+            let unmountable: Vec<_> = features.unmountable_types().collect();
+
+            if !unmountable.is_empty() {
+                notes.push(format!(
+                    "features allows mounting {}, which an unprivileged container can't mount",
+                    unmountable.join(", ")
+                ));
+            }
+        }
+
+        for mount in section.mount_points() {
+            notes.push(format!(
+                "{} ({}) isn't shifted, its files keep their owners and show up as nobody in the container",
+                mount.key, mount.volume
+            ));
+        }
+
+        notes
+    }
+}
+
+#[test]
+fn test_conversion_steps() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        "arch: amd64\nrootfs: local:100/vm-101-disk-0.raw\nfeatures: mount=nfs\nmp0: /srv/data,mp=/data\n",
+    )?;
+    state.open_conversion()?;
+
+    let conversion = state.conversion.as_ref().expect("assistant");
+
+    // Only privileged containers are offered, onto the first host ids nobody maps
+    assert_eq!(conversion.configs, ["101.conf"]);
+    assert_eq!(conversion.offset, 165536);
+    assert_eq!(
+        state.conversion_subid_entries(conversion),
+        [
+            (SubID::UID, "root:165536:65536".to_string()),
+            (SubID::GID, "root:165536:65536".to_string())
+        ]
+    );
+    assert_eq!(
+        state.conversion_config_values(conversion),
+        [
+            ("unprivileged", vec!["1".to_string()]),
+            (
+                "lxc.idmap",
+                vec!["u 0 165536 65536".to_string(), "g 0 165536 65536".to_string()]
+            ),
+        ]
+    );
+
+    let steps = state.conversion_steps(conversion);
+
+    assert_eq!(steps.len(), 3);
+    assert!(steps.iter().all(|item| !item.done));
+    assert_eq!(
+        steps[2].blocked.as_deref(),
+        Some("the rootfs wasn't found on this host")
+    );
+    assert_eq!(state.conversion_notes(conversion).len(), 2);
+
+    // Steps applied elsewhere are picked up
+    state.load_subid("root:100000:131072\n", SubID::UID)?;
+    state.load_subid("root:100000:131072\n", SubID::GID)?;
+
+    let content = "arch: amd64\nrootfs: local:100/vm-101-disk-0.raw\nunprivileged: 1\nlxc.idmap: u 0 165536 65536\nlxc.idmap: g 0 165536 65536\n";
+
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), content)?;
+
+    let conversion = state.conversion.as_ref().expect("assistant");
+    let steps = state.conversion_steps(conversion);
+
+    assert!(steps[0].done);
+    assert!(steps[1].done);
+    assert!(!steps[2].done);
+
+    state.next_conversion_container();
+
+    assert_eq!(state.conversion.as_ref().expect("assistant").selected, 0);
+
+    Ok(())
+}
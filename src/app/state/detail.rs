@@ -0,0 +1,158 @@
+//! Everything pupman knows about a single container, for its detail page.
+
+use std::fs::Metadata;
+
+use compact_str::CompactString;
+
+use super::State;
+use super::filter::contains_ignore_case;
+use super::owner_history::OwnerHistory;
+use super::skipped::SkippedCheck;
+use crate::app::ui::IdMapEntry;
+use crate::finding::Finding;
+use crate::fs::subid::SubID;
+use crate::lxc::RootfsLocation;
+use crate::lxc::idmap::ConfigIdMap;
+use crate::lxc::range_end;
+
+pub struct ContainerDetail<'s> {
+    pub idmaps: &'s [ConfigIdMap],
+    /// The `rootfs` value as written, and where it resolved to once it was looked at.
+    pub rootfs: Option<(&'s str, Option<&'s (RootfsLocation, Metadata)>)>,
+    /// The subuid and subgid entries with host ids the container's idmaps map onto.
+    pub subids: Vec<(SubID, &'s IdMapEntry)>,
+    /// Findings about this container and no other.
+    pub findings: Vec<&'s Finding>,
+    /// Checks which couldn't run for this container.
+    pub skipped: Vec<&'s SkippedCheck>,
+    /// When the owner of the rootfs changed this session.
+    pub owner_history: Option<&'s OwnerHistory>,
+}
+
+impl ContainerDetail<'_> {
+    /// The host uid and gid the container's root maps to, which should own its rootfs.
+    pub fn expected_rootfs_owner(&self) -> (Option<u32>, Option<u32>) {
+        let host_root = |kind| {
+            self.idmaps.iter().find_map(|idmap| {
+                let parsed = idmap.parsed.as_ref().ok()?;
+
+                (parsed.kind == kind && parsed.container_id == 0).then_some(parsed.host_id)
+            })
+        };
+
+        (host_root(SubID::UID), host_root(SubID::GID))
+    }
+}
+
+impl State {
+    /// The unprivileged containers in the order the config panel lists them, narrowed down by
+    /// [`State::config_filter`].
+    pub fn panel_containers(&self) -> Vec<&CompactString> {
+        let filter = self.config_filter();
+
+        self.unprivileged_configs()
+            .filter(|filename| filter.is_none_or(|filter| contains_ignore_case(filename, filter)))
+            .collect()
+    }
+
+    pub fn container_detail(&self, filename: &str) -> Option<ContainerDetail<'_>> {
+        let (filename, config) = self.lxc_configs.get_key_value(filename)?;
+        let idmaps = self.idmaps(filename);
+        let rootfs_value = config.section(None).get_rootfs();
+        let rootfs = rootfs_value.map(|value| (value, self.rootfs_info.get(value)));
+        let subids = [
+            (SubID::UID, &self.host_mapping.subuid),
+            (SubID::GID, &self.host_mapping.subgid),
+        ]
+        .into_iter()
+        .flat_map(|(sub_id, entries)| entries.iter().map(move |entry| (sub_id, entry)))
+        .filter(|(sub_id, entry)| {
+            let entry_end = range_end(entry.host_sub_id, entry.host_sub_id_count);
+
+            idmaps
+                .iter()
+                .filter_map(|idmap| idmap.parsed.as_ref().ok())
+                .any(|parsed| {
+                    parsed.kind == *sub_id
+                        && u64::from(parsed.host_id) < entry_end
+                        && u64::from(entry.host_sub_id) < parsed.host_end()
+                })
+        })
+        .collect();
+        let findings = self
+            .findings
+            .iter()
+            .filter(|finding| {
+                let mut filenames = finding
+                    .lxc_config_mapping_highlights
+                    .iter()
+                    .map(|(highlighted, _)| highlighted)
+                    .chain(finding.config_line_highlights.iter().map(|line| &line.filename))
+                    .peekable();
+                let mentioned = filenames.peek().is_some();
+
+                if mentioned {
+                    filenames.all(|highlighted| highlighted == filename)
+                } else {
+                    !finding.rootfs_highlights.is_empty()
+                        && finding
+                            .rootfs_highlights
+                            .iter()
+                            .all(|highlighted| Some(highlighted.as_str()) == rootfs_value)
+                }
+            })
+            .collect();
+
+        Some(ContainerDetail {
+            idmaps,
+            rootfs,
+            subids,
+            findings,
+            skipped: self
+                .skipped_checks
+                .iter()
+                .filter(|skipped| skipped.filename == *filename)
+                .collect(),
+            owner_history: rootfs_value.and_then(|value| self.rootfs_owner_history.get(value)),
+        })
+    }
+}
+
+#[test]
+fn test_container_detail() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\nalice:165536:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nrootfs: local-zfs:subvol-100-disk-0,size=8G\nlxc.idmap: u 0 100000 65536\n",
+    )?;
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), "unprivileged: 1\n")?;
+    state.evaluate_findings();
+
+    assert_eq!(state.panel_containers(), ["100.conf", "101.conf"]);
+
+    let detail = state.container_detail("100.conf").expect("detail");
+
+    assert_eq!(
+        detail.rootfs.map(|(value, _)| value),
+        Some("local-zfs:subvol-100-disk-0,size=8G")
+    );
+    // Only root's uid range is mapped onto
+    assert_eq!(detail.subids.len(), 1);
+    assert_eq!(detail.subids[0].1.host_user_id, "root");
+    assert_eq!(detail.expected_rootfs_owner(), (Some(100000), None));
+    assert!(!detail.findings.is_empty());
+    assert!(detail.findings.iter().all(|finding| {
+        finding
+            .lxc_config_mapping_highlights
+            .iter()
+            .all(|(filename, _)| filename == "100.conf")
+    }));
+    assert!(state.container_detail("102.conf").is_none());
+
+    Ok(())
+}
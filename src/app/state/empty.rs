@@ -0,0 +1,174 @@
+//! Why a panel of the main view has nothing to list, so it can say what to do about it rather than
+//! show an empty table.
+
+use super::State;
+use super::filter::contains_ignore_case;
+use super::loading::LoadState;
+use crate::fs::subid::SubID;
+use crate::metadata::Metadata as SystemMetadata;
+
+/// The reason a panel is empty.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EmptyPanel {
+    /// Files the panel shows are still being read.
+    Loading,
+    NoConfigs,
+    /// There are this many configs, none of them of an unprivileged container.
+    NoUnprivileged(usize),
+    /// The filter matches no unprivileged container.
+    FilteredOut(String),
+    /// Neither /etc/subuid nor /etc/subgid have any ranges.
+    NoSubids,
+    /// A subordinate id file couldn't be read, with why.
+    SubidsUnreadable(SubID, String),
+    /// A subordinate id file doesn't exist.
+    SubidsMissing(SubID),
+    /// No container config has a rootfs.
+    NoRootfs,
+    /// This many containers have a rootfs, none of which was found on this host.
+    RootfsUnresolved(usize),
+}
+
+impl EmptyPanel {
+    /// What the panel shows in place of its table.
+    pub fn message(&self) -> String {
+        match self {
+            EmptyPanel::Loading => "Waiting for files to be read…".to_string(),
+            EmptyPanel::NoConfigs => "No container configs found. Create a container, or point --root-prefix at a \
+                                      copy of another host's files to audit those."
+                .to_string(),
+            EmptyPanel::NoUnprivileged(count) => format!(
+                "No unprivileged containers found among {count} configs, and only those use idmaps. Press u to \
+                 convert a container."
+            ),
+            EmptyPanel::FilteredOut(filter) => {
+                format!("No unprivileged container matches \"{filter}\", press Esc to clear the filter.")
+            },
+            EmptyPanel::NoSubids => "/etc/subuid and /etc/subgid have no ranges, so unprivileged containers can't \
+                                     start. Press m to add one for root, or m and then i to import ranges."
+                .to_string(),
+            EmptyPanel::SubidsUnreadable(sub_id, err) => format!(
+                "{} couldn't be read: {err}. pupman needs to run as root to see it.",
+                sub_id.path()
+            ),
+            EmptyPanel::SubidsMissing(sub_id) => format!(
+                "{} doesn't exist, so unprivileged containers can't start. Press f on its finding to create it with \
+                 a range for root.",
+                sub_id.path()
+            ),
+            EmptyPanel::NoRootfs => "None of the containers have a rootfs in their config.".to_string(),
+            EmptyPanel::RootfsUnresolved(count) => format!(
+                "None of the root filesystems of {count} containers were found on this host yet. Storage which \
+                 can't be resolved is logged, press l for the logs."
+            ),
+        }
+    }
+}
+
+impl State {
+    /// Why the config panel lists no containers, if it doesn't.
+    pub fn config_panel_empty(&self) -> Option<EmptyPanel> {
+        let mut unprivileged = self.unprivileged_configs().peekable();
+
+        if unprivileged.peek().is_some() {
+            let filter = self.config_filter()?;
+
+            return (!unprivileged.any(|filename| contains_ignore_case(filename, filter)))
+                .then(|| EmptyPanel::FilteredOut(filter.to_string()));
+        }
+
+        Some(if self.is_loading() {
+            EmptyPanel::Loading
+        } else if self.lxc_configs.is_empty() {
+            EmptyPanel::NoConfigs
+        } else {
+            EmptyPanel::NoUnprivileged(self.lxc_configs.len())
+        })
+    }
+
+    /// Why the host mapping panel lists no ranges, if it doesn't.
+    pub fn host_panel_empty(&self, metadata: &SystemMetadata) -> Option<EmptyPanel> {
+        if !self.host_mapping.subuid.is_empty() || !self.host_mapping.subgid.is_empty() {
+            return None;
+        }
+
+        for sub_id in [SubID::UID, SubID::GID] {
+            match self.file_loads.get(metadata.subid_path(sub_id)) {
+                Some(state) if state.is_pending() => return Some(EmptyPanel::Loading),
+                Some(LoadState::Failed(err)) => return Some(EmptyPanel::SubidsUnreadable(sub_id, err.clone())),
+                _ => {},
+            }
+        }
+
+        if let Some(sub_id) = self.missing_subids.first() {
+            return Some(EmptyPanel::SubidsMissing(*sub_id));
+        }
+
+        Some(EmptyPanel::NoSubids)
+    }
+
+    /// Why the rootfs panel lists no root filesystems, if it doesn't.
+    pub fn rootfs_panel_empty(&self) -> Option<EmptyPanel> {
+        if !self.rootfs_info.is_empty() {
+            return None;
+        }
+
+        let with_rootfs = self
+            .lxc_configs
+            .values()
+            .filter(|config| config.section(None).get_rootfs().is_some())
+            .count();
+
+        Some(if self.is_loading() {
+            EmptyPanel::Loading
+        } else if self.lxc_configs.is_empty() {
+            EmptyPanel::NoConfigs
+        } else if with_rootfs == 0 {
+            EmptyPanel::NoRootfs
+        } else {
+            EmptyPanel::RootfsUnresolved(with_rootfs)
+        })
+    }
+}
+
+#[test]
+fn test_empty_panels() -> color_eyre::Result<()> {
+    use std::path::{Path, PathBuf};
+
+    let metadata = SystemMetadata::with_root_prefix(PathBuf::from("/nonexistent"), Some(std::env::temp_dir()))?;
+    let mut state = State::default();
+
+    assert_eq!(state.config_panel_empty(), Some(EmptyPanel::NoConfigs));
+    assert_eq!(state.host_panel_empty(&metadata), Some(EmptyPanel::NoSubids));
+
+    state.mark_failed(metadata.subuid_path.clone(), "Permission denied".into());
+
+    assert_eq!(
+        state.host_panel_empty(&metadata),
+        Some(EmptyPanel::SubidsUnreadable(SubID::UID, "Permission denied".into()))
+    );
+
+    state.forget_load(&metadata.subuid_path);
+    state.unload_subid(SubID::GID);
+
+    assert_eq!(
+        state.host_panel_empty(&metadata),
+        Some(EmptyPanel::SubidsMissing(SubID::GID))
+    );
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), "rootfs: local-lvm:vm-100-disk-0\n")?;
+
+    assert_eq!(state.config_panel_empty(), Some(EmptyPanel::NoUnprivileged(1)));
+    assert_eq!(state.rootfs_panel_empty(), Some(EmptyPanel::RootfsUnresolved(1)));
+
+    state.load_config(Path::new("/etc/pve/lxc/200.conf"), "unprivileged: 1\n")?;
+    state.filter = "100".into();
+
+    assert_eq!(state.config_panel_empty(), Some(EmptyPanel::FilteredOut("100".into())));
+
+    state.filter = "200".into();
+
+    assert_eq!(state.config_panel_empty(), None);
+
+    Ok(())
+}
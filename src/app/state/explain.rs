@@ -0,0 +1,937 @@
+//! Detailed explanations for findings, quoting the lines which caused them and what a working
+//! configuration looks like.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use compact_str::CompactString;
+
+use super::State;
+use super::import::overlaps;
+use super::shadow::{ManualHint, manual_entries, usermod_command};
+use super::wizard::CONTAINER_IDS;
+use crate::app::ui::IdMapEntry;
+use crate::check::Check;
+use crate::finding::{Finding, FindingKind};
+use crate::fix::Fix;
+use crate::fs::monitor::POLL_INTERVAL;
+use crate::fs::subid::{InvalidSubidLine, SubID};
+use crate::linux::reserved;
+use crate::lxc::idmap::idmap_coverage;
+use crate::lxc::range_end;
+use crate::proxmox::features::Features;
+use crate::proxmox::schema::{self, SchemaError};
+
+/// Lines quoted from a single file.
+#[derive(Debug, PartialEq)]
+pub struct Excerpt {
+    pub source: String,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Explanation {
+    pub paragraphs: Vec<String>,
+    pub offending: Vec<Excerpt>,
+    /// What the lines should look like instead, if it can be worked out.
+    pub suggested: Option<Excerpt>,
+}
+
+/// A config line mounting a rootfs or mount point with the container's idmap. It takes the place
+/// of a chown when the files are owned by unshifted ids and their file system supports it.
+#[derive(Debug, PartialEq)]
+pub struct IdmappedMount {
+    pub filename: CompactString,
+    pub filesystem: &'static str,
+    pub line: String,
+}
+
+const DEFAULT_SUB_ID: u32 = 100000;
+const DEFAULT_SUB_ID_COUNT: u32 = 65536;
+
+impl State {
+    pub(super) fn subid_entries(&self, sub_id: SubID) -> &[IdMapEntry] {
+        match sub_id {
+            SubID::UID => &self.host_mapping.subuid,
+            SubID::GID => &self.host_mapping.subgid,
+        }
+    }
+
+    /// The line of /etc/subuid or /etc/subgid at `line_number`, if it isn't a valid entry.
+    fn invalid_subid_line(&self, sub_id: SubID, line_number: usize) -> Option<&InvalidSubidLine> {
+        let invalid = match sub_id {
+            SubID::UID => &self.host_mapping.subuid_invalid,
+            SubID::GID => &self.host_mapping.subgid_invalid,
+        };
+
+        invalid.iter().find(|line| line.line_number == line_number)
+    }
+
+    fn idmap_lines(&self, filename: &str, sub_id: SubID) -> Vec<String> {
+        self.idmaps(filename)
+            .iter()
+            .filter(|idmap| idmap.kind() == Some(sub_id))
+            .map(|idmap| match &idmap.include {
+                Some(include) => format!("lxc.idmap: {}  (from {})", idmap.value, include.display()),
+                None => format!("lxc.idmap: {}", idmap.value),
+            })
+            .collect()
+    }
+
+    /// The idmap line a container config would need to use root's range from the host mapping.
+    fn suggested_idmap(&self, sub_id: SubID) -> String {
+        let (start, count) = self
+            .subid_entries(sub_id)
+            .iter()
+            .find(|entry| entry.host_user_id == "root" || entry.host_user_id == "0")
+            .map_or((DEFAULT_SUB_ID, DEFAULT_SUB_ID_COUNT), |entry| {
+                (entry.host_sub_id, entry.host_sub_id_count)
+            });
+
+        format!("lxc.idmap: {} 0 {start} {count}", sub_id.idmap_kind())
+    }
+
+    /// How the rootfs or mount point of a [`Check::RootfsOwnership`] or [`Check::MountOwnership`]
+    /// finding could be mounted with an idmap instead of being chowned, if it can.
+    pub fn idmapped_mount(&self, finding: &Finding) -> Option<IdmappedMount> {
+        let (filename, _) = finding.lxc_config_mapping_highlights.first()?;
+        let config = self.lxc_configs.get(filename)?;
+        let (value, metadata, line) = match finding.check {
+            Check::RootfsOwnership => {
+                let value = finding.rootfs_highlights.first()?;
+                let (_, metadata) = self.rootfs_info.get(value)?;
+
+                (
+                    value.as_str(),
+                    metadata,
+                    "lxc.rootfs.options: idmap=container".to_string(),
+                )
+            },
+            Check::MountOwnership => {
+                let key = &finding.config_line_highlights.first()?.key;
+                let section = config.section(None);
+                // Storage volumes are PVE's to mount
+                let mount = section
+                    .mount_points()
+                    .find(|mount| mount.key == key)
+                    .filter(|mount| mount.is_bind_mount())?;
+                let (_, metadata) = self.mount_info.get(mount.value)?;
+                let target = mount.path.as_deref()?.trim_start_matches('/');
+
+                (
+                    mount.value,
+                    metadata,
+                    format!(
+                        "lxc.mount.entry: {} {target} none bind,create=dir,idmap=container 0 0",
+                        mount.volume
+                    ),
+                )
+            },
+            _ => return None,
+        };
+        let filesystem = self
+            .rootfs_space
+            .get(value)?
+            .filesystem
+            .as_ref()
+            .filter(|filesystem| filesystem.idmapped_mounts)?;
+
+        // The mount shifts ids by the idmap, so files which were already shifted would be twice
+        if metadata.uid() >= CONTAINER_IDS || metadata.gid() >= CONTAINER_IDS {
+            return None;
+        }
+
+        Some(IdmappedMount {
+            filename: filename.clone(),
+            filesystem: filesystem.name,
+            line,
+        })
+    }
+
+    /// Explains `finding` in terms of the currently loaded files. Config paths are shown relative to
+    /// `config_dir`.
+    pub fn explain(&self, finding: &Finding, config_dir: &Path) -> Explanation {
+        let config_source = |filename: &str| config_dir.join(filename).display().to_string();
+        let mut paragraphs = vec![finding.check.description().to_string() + "."];
+        let mut offending = Vec::new();
+        let mut suggested = None;
+
+        for (user, sub_id) in &finding.host_mapping_highlights {
+            let lines = self
+                .subid_entries(*sub_id)
+                .iter()
+                .filter(|entry| entry.host_user_id == *user)
+                .filter(|entry| finding.check != Check::SubidFormatting || entry.unusual_format)
+                .map(|entry| entry.line.to_string())
+                .collect();
+
+            offending.push(Excerpt {
+                source: sub_id.path().to_string(),
+                lines,
+            });
+        }
+
+        for &(sub_id, line_number) in &finding.subid_line_highlights {
+            let Some(invalid) = self.invalid_subid_line(sub_id, line_number) else {
+                continue;
+            };
+            let line = format!("{line_number}: {}", invalid.line);
+
+            match offending.last_mut() {
+                Some(excerpt) if excerpt.source == sub_id.path() => excerpt.lines.push(line),
+                _ => offending.push(Excerpt {
+                    source: sub_id.path().to_string(),
+                    lines: vec![line],
+                }),
+            }
+        }
+
+        for (filename, sub_id) in &finding.lxc_config_mapping_highlights {
+            offending.push(Excerpt {
+                source: config_source(filename),
+                lines: self.idmap_lines(filename, *sub_id),
+            });
+        }
+
+        for line in &finding.config_line_highlights {
+            let text = self
+                .lxc_configs
+                .get(&line.filename)
+                .and_then(|config| config.line(line.line))
+                .unwrap_or_default();
+            let source = config_source(&line.filename);
+
+            match offending.last_mut() {
+                Some(excerpt) if excerpt.source == source => excerpt.lines.push(format!("{}: {text}", line.line)),
+                _ => offending.push(Excerpt {
+                    source,
+                    lines: vec![format!("{}: {text}", line.line)],
+                }),
+            }
+        }
+
+        match finding.check {
+            Check::IdRangeValues => {
+                for line in &finding.config_line_highlights {
+                    let idmap = self
+                        .idmaps(&line.filename)
+                        .iter()
+                        .find(|idmap| idmap.line == Some(line.line));
+
+                    if let Some(Err(err)) = idmap.map(|idmap| &idmap.parsed) {
+                        paragraphs.push(format!("Line {}: {err}.", line.line));
+                    }
+                }
+
+                paragraphs.push(
+                    "Ranges are written as a start id and a count. A count of zero maps nothing, and a range may not \
+                     run past 4294967295, the largest id. LXC refuses to start a container with such an idmap, and \
+                     shadow-utils ignores such subuid/subgid entries."
+                        .to_string(),
+                );
+
+                if let [line, ..] = &finding.config_line_highlights[..] {
+                    suggested = Some(Excerpt {
+                        source: config_source(&line.filename),
+                        lines: vec![self.suggested_idmap(SubID::UID), self.suggested_idmap(SubID::GID)],
+                    });
+                }
+            },
+            Check::ConfigDuplicateKeys => {
+                paragraphs.push(
+                    "PVE only keeps the last value, so every earlier line is silently ignored. This usually happens \
+                     when a config was merged or edited by hand. Remove the lines which aren't meant to be used."
+                        .to_string(),
+                );
+
+                if let Some(last) = finding.config_line_highlights.last()
+                    && let Some(text) = self.lxc_configs.get(&last.filename).and_then(|c| c.line(last.line))
+                {
+                    suggested = Some(Excerpt {
+                        source: config_source(&last.filename),
+                        lines: vec![text],
+                    });
+                }
+            },
+            Check::ConfigDeprecatedKeys => {
+                paragraphs.push(
+                    "These keys aren't understood by the LXC shipped with the installed PVE version. Depending on \
+                     the key the container either refuses to start or the setting is silently ignored."
+                        .to_string(),
+                );
+
+                for line in &finding.config_line_highlights {
+                    if let Some(deprecated) = self.dialect.deprecated(&line.key) {
+                        paragraphs.push(format!(
+                            "Line {}: `{}` should be written as `{}`, it was {}.",
+                            line.line,
+                            line.key,
+                            deprecated.replace(&line.key),
+                            deprecated.reason
+                        ));
+                    }
+                }
+            },
+            Check::ConfigSchema => {
+                paragraphs.push(
+                    "PVE skips keys it doesn't know and values it can't parse, so the setting they were meant to make \
+                     silently doesn't apply. A misspelled unprivileged key leaves the container privileged."
+                        .to_string(),
+                );
+
+                for line in &finding.config_line_highlights {
+                    let Some((_, key, value)) = self
+                        .lxc_configs
+                        .get(&line.filename)
+                        .and_then(|config| config.section_entries(None).find(|(l, ..)| *l == line.line))
+                    else {
+                        continue;
+                    };
+
+                    match schema::validate(key, value) {
+                        Err(SchemaError::UnknownKey) => match schema::closest_key(key) {
+                            Some(closest) => paragraphs.push(format!(
+                                "Line {}: `{key}` isn't a key, did you mean `{closest}`?",
+                                line.line
+                            )),
+                            None => paragraphs.push(format!("Line {}: `{key}` isn't a key PVE knows.", line.line)),
+                        },
+                        Err(SchemaError::Malformed(kind)) => paragraphs.push(format!(
+                            "Line {}: `{key}` takes {}, not `{}`.",
+                            line.line,
+                            kind.expected(),
+                            value.trim()
+                        )),
+                        Ok(()) => {},
+                    }
+                }
+            },
+            Check::SubidFiles => {
+                if let Some(Fix::CreateSubid(sub_id)) = finding.fix {
+                    paragraphs.push(format!(
+                        "newuidmap and newgidmap read {} and {} to decide which host ids a user may map \
+                         containers onto. Without {} no ranges are granted, so unprivileged containers fail to start \
+                         and privileged ones are unaffected.",
+                        SubID::UID.path(),
+                        SubID::GID.path(),
+                        sub_id.path()
+                    ));
+                    paragraphs.push(format!(
+                        "Create it with a range for root, which PVE starts containers as. It should match root's range \
+                         in {}, if it has one there.",
+                        sub_id.counterpart().path()
+                    ));
+                    let (start, count) = self
+                        .subid_entries(sub_id.counterpart())
+                        .iter()
+                        .find(|entry| matches!(entry.host_user_id.as_str(), "root" | "0"))
+                        .map_or((100000, 65536), |entry| (entry.host_sub_id, entry.host_sub_id_count));
+
+                    suggested = Some(Excerpt {
+                        source: sub_id.path().to_string(),
+                        lines: vec![format!("root:{start}:{count}")],
+                    });
+                }
+            },
+            Check::SubidSyntax => {
+                paragraphs.push(
+                    "shadow-utils skips lines it can't parse, so whatever range such a line was meant to grant isn't \
+                     granted and containers mapped into it fail to start."
+                        .to_string(),
+                );
+
+                for &(sub_id, line_number) in &finding.subid_line_highlights {
+                    if let Some(invalid) = self.invalid_subid_line(sub_id, line_number) {
+                        paragraphs.push(format!("Line {line_number}: {}.", invalid.error));
+                    }
+                }
+
+                paragraphs.push("Write each of them as name:start:count, or comment them out with #.".to_string());
+            },
+            Check::SubidDuplicates => {
+                paragraphs.push(
+                    "An owner may hold several ranges, but these grant some of the same ids more than once. That is \
+                     usually a line pasted twice or a range grown by adding another entry, and it leaves unclear \
+                     which range containers are meant to use."
+                        .to_string(),
+                );
+
+                let owners: Vec<_> = finding.host_mapping_highlights.iter().map(|(user, _)| user).collect();
+
+                if let [(user, sub_id), ..] = &finding.host_mapping_highlights[..] {
+                    let entries: Vec<_> = self
+                        .subid_entries(*sub_id)
+                        .iter()
+                        .filter(|entry| owners.contains(&&entry.host_user_id))
+                        .collect();
+                    let overlapping = entries.iter().enumerate().find_map(|(i, first)| {
+                        entries[i + 1..]
+                            .iter()
+                            .find(|second| {
+                                overlaps(
+                                    first.host_sub_id,
+                                    first.host_sub_id_count,
+                                    second.host_sub_id,
+                                    second.host_sub_id_count,
+                                )
+                            })
+                            .map(|second| (first, second))
+                    });
+
+                    if let Some((first, second)) = overlapping {
+                        let start = first.host_sub_id.min(second.host_sub_id);
+                        let end = range_end(first.host_sub_id, first.host_sub_id_count)
+                            .max(range_end(second.host_sub_id, second.host_sub_id_count));
+
+                        paragraphs.push("Merge the overlapping ranges into a single entry.".to_string());
+                        suggested = Some(Excerpt {
+                            source: sub_id.path().to_string(),
+                            lines: vec![format!("{user}:{start}:{}", end - u64::from(start))],
+                        });
+                    }
+                }
+            },
+            Check::SubidOwner => {
+                paragraphs.push(
+                    "newuidmap and newgidmap only hand out a range to the user its entry names, so a range whose owner \
+                     doesn't exist can't be used by any container."
+                        .to_string(),
+                );
+
+                if let [(owner, SubID::GID), ..] = &finding.host_mapping_highlights[..]
+                    && self.host_groups.iter().any(|group| group.name == *owner)
+                {
+                    paragraphs.push(format!(
+                        "{owner} is a group. The first field of /etc/subgid names the user allowed to map the range, \
+                         which is root for containers PVE starts."
+                    ));
+                }
+
+                paragraphs.push(
+                    "Users from LDAP or another NSS source aren't listed in /etc/passwd. Disable this check if the \
+                     owner is one of them."
+                        .to_string(),
+                );
+            },
+            Check::SubidSymmetry => {
+                if let [(owner, sub_id), ..] = &finding.host_mapping_highlights[..] {
+                    let missing = sub_id.counterpart();
+
+                    paragraphs.push(format!(
+                        "LXC maps a container's uids with newuidmap and its gids with newgidmap, which check {} and \
+                         {} respectively. Containers use a range {owner} has in {}, but {owner} has no entry in {}, \
+                         so mapping their {}s is refused and they fail to start.",
+                        SubID::UID.path(),
+                        SubID::GID.path(),
+                        sub_id.path(),
+                        missing.path(),
+                        missing.kind_name()
+                    ));
+
+                    if let Some(entry) = self
+                        .subid_entries(*sub_id)
+                        .iter()
+                        .find(|entry| entry.host_user_id == *owner)
+                    {
+                        paragraphs.push(format!(
+                            "Give {owner} the same range in {}, so the containers' uids and gids line up.",
+                            missing.path()
+                        ));
+                        suggested = Some(Excerpt {
+                            source: missing.path().to_string(),
+                            lines: vec![format!("{owner}:{}:{}", entry.host_sub_id, entry.host_sub_id_count)],
+                        });
+                    }
+                }
+            },
+            Check::SubidManaged => {
+                if let [(_, sub_id), ..] = &finding.host_mapping_highlights[..] {
+                    let manual = manual_entries(self.subid_entries(*sub_id), self.shadow_backups.get(sub_id));
+
+                    for (entry, hint) in &manual {
+                        paragraphs.push(format!("{} looks hand-written: {}.", entry.line.trim(), hint.reason()));
+                    }
+
+                    paragraphs.push(
+                        "usermod and useradd rewrite the whole file whenever they allocate ranges, and entries they \
+                         don't know about are easily lost or overlapped. Adding the ranges through usermod instead \
+                         lets shadow-utils keep track of them, while entries which are only out of order just need \
+                         sorting by range."
+                            .to_string(),
+                    );
+
+                    let readded: Vec<_> = manual
+                        .iter()
+                        .filter(|(_, hint)| *hint == ManualHint::AddedSinceBackup)
+                        .map(|(entry, _)| usermod_command(*sub_id, entry))
+                        .collect();
+
+                    if !readded.is_empty() {
+                        suggested = Some(Excerpt {
+                            source: "shell".to_string(),
+                            lines: readded,
+                        });
+                    }
+                }
+            },
+            Check::SubidFormatting => {
+                paragraphs.push(
+                    "Whitespace around the fields is tolerated by pupman, but other tools like newuidmap may reject or \
+                     misread these lines."
+                        .to_string(),
+                );
+
+                if let [(_, sub_id), ..] = &finding.host_mapping_highlights[..] {
+                    suggested = Some(Excerpt {
+                        source: sub_id.path().to_string(),
+                        lines: self
+                            .subid_entries(*sub_id)
+                            .iter()
+                            .filter(|entry| entry.unusual_format)
+                            .map(|entry| {
+                                format!(
+                                    "{}:{}:{}",
+                                    entry.host_user_id, entry.host_sub_id, entry.host_sub_id_count
+                                )
+                            })
+                            .collect(),
+                    });
+                }
+            },
+            Check::IdmapPresent => {
+                paragraphs.push(
+                    "Without an lxc.idmap entry for both uids and gids, the container falls back to the default \
+                     mapping, which doesn't match the ownership of its rootfs if it was ever mapped differently."
+                        .to_string(),
+                );
+
+                if let [(filename, sub_id), ..] = &finding.lxc_config_mapping_highlights[..] {
+                    suggested = Some(Excerpt {
+                        source: config_source(filename),
+                        lines: vec![self.suggested_idmap(*sub_id)],
+                    });
+                }
+            },
+            // Only a lookup which timed out is a warning rather than bad
+            Check::IdmapHostRange if finding.kind == FindingKind::Warning => paragraphs.push(
+                "pupman asks `id` who owns each host range, and it didn't answer within the command timeout. Raise \
+                 command_timeout in pupman.conf if the host's user database is slow to answer."
+                    .to_string(),
+            ),
+            Check::IdmapHostRange => {
+                paragraphs.push(
+                    "newuidmap refuses to map ids the owner wasn't delegated, so the container fails to start with a \
+                     permission error."
+                        .to_string(),
+                );
+
+                if let [(filename, sub_id), ..] = &finding.lxc_config_mapping_highlights[..] {
+                    paragraphs.push(format!(
+                        "Either shrink the idmap to fit, or grow the range in {}.",
+                        sub_id.path()
+                    ));
+                    suggested = Some(Excerpt {
+                        source: config_source(filename),
+                        lines: vec![self.suggested_idmap(*sub_id)],
+                    });
+                }
+            },
+            Check::SnapshotIdmaps => {
+                for line in &finding.config_line_highlights {
+                    if let Some(snapshot) = self
+                        .lxc_configs
+                        .get(&line.filename)
+                        .and_then(|config| config.section_at(line.line))
+                    {
+                        paragraphs.push(format!("Line {} belongs to the snapshot {snapshot}.", line.line));
+                    }
+                }
+
+                paragraphs.push(
+                    "The container runs fine as it is, but rolling back restores the snapshot's idmaps, which map \
+                     onto host ids root wasn't delegated. The container then fails to start with a permission error \
+                     until the idmaps are fixed again or the ranges are added back to /etc/subuid and /etc/subgid."
+                        .to_string(),
+                );
+            },
+            Check::IdmapLoginUsers => {
+                for (filename, _) in &finding.lxc_config_mapping_highlights {
+                    let idmaps = self.idmaps(filename).iter();
+                    let users = self.mapped_login_users(idmaps.filter_map(|idmap| idmap.parsed.as_ref().ok()));
+                    let users: Vec<_> = users.iter().map(|(name, uid)| format!("{name} (uid {uid})")).collect();
+
+                    paragraphs.push(format!("The idmaps include the host uid of {}.", users.join(", ")));
+                }
+
+                paragraphs.push(
+                    "Files the container creates as the mapped uid show up on the host as owned by that user, who \
+                     can read and change them, while root in the container can do the same to the user's files \
+                     wherever a mount exposes them. Move the idmap to a subordinate range the user doesn't own, \
+                     like one from /etc/subuid."
+                        .to_string(),
+                );
+            },
+            Check::IdmapHostAccounts => {
+                for (filename, sub_id) in &finding.lxc_config_mapping_highlights {
+                    let idmaps: Vec<_> = self
+                        .idmaps(filename)
+                        .iter()
+                        .filter_map(|idmap| idmap.parsed.as_ref().ok().copied())
+                        .collect();
+                    let accounts: Vec<_> = self
+                        .mapped_host_accounts(&idmaps)
+                        .into_iter()
+                        .filter(|(kind, ..)| kind == sub_id)
+                        .map(|(kind, name, id)| format!("{name} ({} {id})", kind.kind_name()))
+                        .collect();
+
+                    if !accounts.is_empty() {
+                        let (what, file) = match sub_id {
+                            SubID::UID => ("users", "/etc/passwd"),
+                            SubID::GID => ("groups", "/etc/group"),
+                        };
+
+                        paragraphs.push(format!(
+                            "The idmaps include the host ids of the {what} {} from {file}.",
+                            accounts.join(", ")
+                        ));
+                    }
+                }
+
+                paragraphs.push(
+                    "Whatever the container creates as a mapped id is owned on the host by that account, and \
+                     root in the container can read and change the account's files wherever a mount exposes \
+                     them. Service accounts and groups like disk or shadow can give away more than a home \
+                     directory. Move the idmap to a subordinate range no host account uses, like one from \
+                     /etc/subuid."
+                        .to_string(),
+                );
+            },
+            Check::IdmapReservedRanges => {
+                let mut rationales = Vec::new();
+
+                for (filename, sub_id) in &finding.lxc_config_mapping_highlights {
+                    let idmaps = self.idmaps(filename).iter();
+
+                    for idmap in idmaps.filter_map(|idmap| idmap.parsed.as_ref().ok()) {
+                        if idmap.kind != *sub_id {
+                            continue;
+                        }
+
+                        let ranges: Vec<_> = reserved::colliding(idmap.host_id, idmap.size).collect();
+
+                        if ranges.is_empty() {
+                            continue;
+                        }
+
+                        let names: Vec<_> = ranges.iter().map(|range| range.to_string()).collect();
+
+                        paragraphs.push(format!("lxc.idmap: {idmap} reaches into {}.", names.join(", ")));
+
+                        for range in ranges {
+                            if !rationales.contains(&range.rationale) {
+                                rationales.push(range.rationale);
+                            }
+                        }
+                    }
+                }
+
+                paragraphs.extend(rationales.into_iter().map(str::to_string));
+                paragraphs.push(
+                    "These ranges are only reserved by convention, so the container works, but whatever else uses \
+                     them on this host shares the ids with it. Moving the idmap to a range like Proxmox' default \
+                     100000-165535 avoids that."
+                        .to_string(),
+                );
+            },
+            Check::IdmapCoverage => {
+                if let [(filename, sub_id), ..] = &finding.lxc_config_mapping_highlights[..]
+                    && self.lxc_configs.contains_key(filename)
+                {
+                    let idmaps: Vec<_> = self
+                        .idmaps(filename)
+                        .iter()
+                        .filter_map(|idmap| idmap.parsed.as_ref().ok().copied())
+                        .filter(|idmap| idmap.kind == *sub_id)
+                        .collect();
+                    let issues: Vec<_> = idmap_coverage(&idmaps).iter().map(ToString::to_string).collect();
+
+                    if !issues.is_empty() {
+                        paragraphs.push(format!("In this config: {}.", issues.join(", ")));
+                    }
+                }
+
+                paragraphs.push(
+                    "Files owned by an unmapped id show up as nobody inside the container and can't be changed. When \
+                     two idmaps cover the same container id, LXC refuses to start the container. Adjust the ranges so \
+                     they line up end to end."
+                        .to_string(),
+                );
+            },
+            Check::IdmapSymmetry => {
+                paragraphs.push(
+                    "Most containers map their uids and gids onto the same host ids, so a mismatch is usually a \
+                     typo. If only one kind is meant to differ, e.g. to pass a host group through to the container, \
+                     mark the container as mapping gids or uids only in the idmap wizard (g, then i) and this \
+                     warning goes away."
+                        .to_string(),
+                );
+            },
+            Check::RootfsOwnership => {
+                for rootfs in &finding.rootfs_highlights {
+                    let Some((location, metadata)) = self.rootfs_info.get(rootfs) else {
+                        continue;
+                    };
+
+                    offending.push(Excerpt {
+                        source: location.mountpoint.display().to_string(),
+                        lines: vec![format!("owned by {}:{}", metadata.uid(), metadata.gid())],
+                    });
+                }
+
+                if let Some(mount) = self.idmapped_mount(finding) {
+                    paragraphs.push(format!(
+                        "The rootfs is on {}, which this host can mount with an idmap, and its files are owned by \
+                         unshifted ids. Mounting it with the container's idmap lets the container use them as they \
+                         are, with nothing to chown. Otherwise:",
+                        mount.filesystem
+                    ));
+                    suggested = Some(Excerpt {
+                        source: config_source(&mount.filename),
+                        lines: vec![mount.line],
+                    });
+                }
+
+                paragraphs.push(
+                    "Root inside the container maps to the idmap's first host id. If the rootfs is owned by anything \
+                     else, the container can't write to its own files. Either chown the rootfs, or change the idmap to \
+                     start at its current owner."
+                        .to_string(),
+                );
+            },
+            Check::RootfsContents => {
+                for rootfs in &finding.rootfs_highlights {
+                    let Some(scan) = self.ownership_scans.get(rootfs) else {
+                        continue;
+                    };
+                    let idmaps: Vec<_> = self
+                        .lxc_configs
+                        .iter()
+                        .find(|(_, config)| config.section(None).get_rootfs() == Some(rootfs.as_str()))
+                        .into_iter()
+                        .flat_map(|(filename, _)| self.idmaps(filename))
+                        .filter_map(|idmap| idmap.parsed.as_ref().ok().copied())
+                        .collect();
+                    let unmapped = scan.unmapped(&idmaps);
+                    let files: u64 = unmapped.iter().map(|(_, owner)| owner.files).sum();
+                    let source = self.rootfs_info.get(rootfs).map_or_else(
+                        || rootfs.clone(),
+                        |(location, _)| location.mountpoint.display().to_string(),
+                    );
+                    let mut lines = Vec::new();
+
+                    for ((uid, gid), owner) in unmapped {
+                        lines.push(format!("{} files owned by {uid}:{gid}", owner.files));
+
+                        for example in &owner.examples {
+                            lines.push(format!("  {}", example.display()));
+                        }
+                    }
+
+                    paragraphs.push(format!(
+                        "{files} of the {} files the deep scan of {source} counted are owned by ids outside the \
+                         container's idmaps.",
+                        scan.files()
+                    ));
+
+                    if scan.cancelled {
+                        paragraphs.push("The scan was cancelled, so there may be more.".to_string());
+                    }
+
+                    if scan.unreadable > 0 {
+                        paragraphs.push(format!(
+                            "{} directories couldn't be read and weren't counted.",
+                            scan.unreadable
+                        ));
+                    }
+
+                    offending.push(Excerpt { source, lines });
+                }
+
+                paragraphs.push(
+                    "The container sees these files as owned by nobody, which usually means an earlier chown or \
+                     restore didn't get through the whole rootfs, or the idmaps changed since. Shift their owners \
+                     into the container's range, or run the deep scan again with D once they are fixed."
+                        .to_string(),
+                );
+            },
+            Check::DeviceOwnership => {
+                for line in &finding.config_line_highlights {
+                    let device = self
+                        .lxc_configs
+                        .get(&line.filename)
+                        .and_then(|config| config.section(None).devices().find(|device| device.key == line.key));
+
+                    if let Some(device) = device {
+                        paragraphs.push(format!(
+                            "{} is created in the container owned by uid {} and gid {}.",
+                            device.path, device.uid, device.gid
+                        ));
+                    }
+                }
+
+                paragraphs.push(
+                    "The uid and gid of a devN line are ids inside the container, which PVE shifts through the \
+                     container's idmaps when it creates the node. An id the idmaps don't map has no host id to \
+                     become, so the node can't be given to that user or group and whatever runs as them can't \
+                     open it. Pick an id the idmaps map, like the group the device's users are in within the \
+                     container, or add an idmap for it."
+                        .to_string(),
+                );
+            },
+            Check::ContainerFeatures => {
+                for line in &finding.config_line_highlights {
+                    let Some(config) = self.lxc_configs.get(&line.filename) else {
+                        continue;
+                    };
+                    let section = config.section(None);
+                    let Some(features) = section.get("features").and_then(|value| Features::parse(value).ok()) else {
+                        continue;
+                    };
+
+                    if self.dialect.is_unprivileged(&section, &self.default_idmaps) {
+                        let fs_types: Vec<_> = features.unmountable_types().collect();
+
+                        paragraphs.push(format!(
+                            "mount= allows {}, which the kernel only mounts for the host's own root. An \
+                             unprivileged container runs in a user namespace, so mounting them fails whatever its \
+                             features allow.",
+                            fs_types.join(", ")
+                        ));
+                        paragraphs.push(
+                            "This is usually left over from a privileged container which was converted. Mount the \
+                             filesystem on the host and pass it in with an mpN bind mount instead, then drop the \
+                             types from mount=."
+                                .to_string(),
+                        );
+                    } else {
+                        paragraphs.push(
+                            "PVE only denies the keyctl() syscall to unprivileged containers, so keyctl=1 changes \
+                             nothing for a privileged one. It does once the container is converted, which is when \
+                             Docker inside it needs it."
+                                .to_string(),
+                        );
+                    }
+                }
+            },
+            Check::MountOwnership => {
+                let mounts = finding.config_line_highlights.iter().filter_map(|line| {
+                    let config = self.lxc_configs.get(&line.filename)?;
+                    let mount = config
+                        .section(None)
+                        .mount_points()
+                        .find(|mount| mount.key == line.key)?;
+
+                    self.mount_info.get(mount.value)
+                });
+
+                for (location, metadata) in mounts {
+                    offending.push(Excerpt {
+                        source: location.mountpoint.display().to_string(),
+                        lines: vec![format!("owned by {}:{}", metadata.uid(), metadata.gid())],
+                    });
+                }
+
+                if let Some(mount) = self.idmapped_mount(finding) {
+                    paragraphs.push(format!(
+                        "The directory is on {}, which this host can mount with an idmap, and is owned by unshifted \
+                         ids. Bind mounting it with the container's idmap in place of the mpN line shows its files \
+                         to the container with the same ids they have on the host, with nothing to chown. \
+                         Otherwise:",
+                        mount.filesystem
+                    ));
+                    suggested = Some(Excerpt {
+                        source: config_source(&mount.filename),
+                        lines: vec![mount.line],
+                    });
+                }
+
+                paragraphs.push(
+                    "Files owned by an id outside the container's idmaps show up as nobody inside the container, \
+                     so it can read them at most through their other permissions and never write them. Chown the \
+                     directory to an id the container maps, for example its root at the idmap's first host id, or \
+                     add an idmap for the owner."
+                        .to_string(),
+                );
+            },
+            Check::RootfsShared => {
+                let vmids: Vec<_> = finding
+                    .config_line_highlights
+                    .iter()
+                    .map(|line| line.filename.trim_end_matches(".conf"))
+                    .collect();
+                let containers = match &vmids[..] {
+                    [first, second] => format!("{first} and {second} both"),
+                    _ => format!("{} all", vmids.join(", ")),
+                };
+
+                paragraphs.push(format!(
+                    "Containers {containers} start from the same volume, usually because a config was copied by hand \
+                     rather than the container cloned with pct clone. Running them at once has two systems write \
+                     the same files, like package databases and service state, and destroying one of them with pct \
+                     destroy deletes the volume they share. Keep them stopped, then give every container \
+                     but one a volume of its own by restoring a backup or cloning it, or delete the config that was \
+                     left behind."
+                ));
+            },
+            Check::RootfsWritable => {
+                paragraphs.push(
+                    "Ownership can't be fixed while the dataset is read-only. Clear the readonly property with \
+                     `zfs set readonly=off`, or resume or abort an interrupted receive with `zfs receive -A` first."
+                        .to_string(),
+                );
+
+                for rootfs in &finding.rootfs_highlights {
+                    let dataset = self
+                        .rootfs_info
+                        .get(rootfs)
+                        .and_then(|(location, _)| location.dataset.clone());
+
+                    offending.push(Excerpt {
+                        source: dataset.unwrap_or_else(|| rootfs.clone()),
+                        lines: vec![format!("rootfs: {rootfs}")],
+                    });
+                }
+            },
+            Check::ConfigDirAvailable => {
+                let dir = self
+                    .config_dir_unavailable
+                    .as_ref()
+                    .map_or_else(|| "The config directory".to_string(), |dir| dir.display().to_string());
+
+                paragraphs.push(format!(
+                    "{dir} went away, so the configs shown are as they were last read and edits to them go \
+                     unnoticed. On PVE it is part of /etc/pve, which pmxcfs only provides while pve-cluster runs, \
+                     e.g. not while it restarts or after it failed to reach quorum. pupman watches it again as soon \
+                     as it is back. If it stays away, check systemctl status pve-cluster and journalctl -u \
+                     pve-cluster."
+                ));
+            },
+            Check::FilesWatched => paragraphs.push(format!(
+                "inotify couldn't watch the files pupman reads, so they are checked for changes every {} seconds \
+                 instead and edits show up late. Either the inotify limits are used up by other programs, raise \
+                 fs.inotify.max_user_instances and fs.inotify.max_user_watches with sysctl and reload with Ctrl-R, \
+                 or the files are on a file system without inotify support, like some network file systems.",
+                POLL_INTERVAL.as_secs()
+            )),
+        }
+
+        offending.retain(|excerpt| !excerpt.lines.is_empty());
+
+        Explanation {
+            paragraphs,
+            offending,
+            suggested,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! The `/` filter, which narrows the findings list, and the config panel when it names containers,
+//! down to what contains the typed text.
+
+use std::ops::Range;
+
+use super::State;
+use crate::finding::Finding;
+
+/// Where `filter` first appears in `text`, ignoring case.
+pub fn match_range(text: &str, filter: &str) -> Option<Range<usize>> {
+    let lower = text.to_lowercase();
+
+    // Lowercasing may change the length of non-ASCII text, which would shift the range
+    if lower.len() != text.len() {
+        return None;
+    }
+
+    let start = lower.find(&filter.to_lowercase())?;
+
+    Some(start..start + filter.len())
+}
+
+pub fn contains_ignore_case(text: &str, filter: &str) -> bool {
+    text.to_lowercase().contains(&filter.to_lowercase())
+}
+
+/// Whether `filter` appears in the message or check of `finding`, or in anything it highlights
+/// such as a config, subid owner or rootfs.
+pub fn finding_matches(finding: &Finding, filter: &str) -> bool {
+    let highlights = finding
+        .host_mapping_highlights
+        .iter()
+        .map(|(owner, _)| owner.as_str())
+        .chain(
+            finding
+                .lxc_config_mapping_highlights
+                .iter()
+                .map(|(filename, _)| filename.as_str()),
+        )
+        .chain(finding.rootfs_highlights.iter().map(String::as_str))
+        .chain(finding.config_line_highlights.iter().map(|line| line.filename.as_str()));
+
+    [finding.message, finding.check.id(), finding.check.name()]
+        .into_iter()
+        .chain(highlights)
+        .any(|text| contains_ignore_case(text, filter))
+}
+
+impl State {
+    /// The indices of the findings the filter leaves, in order.
+    pub fn visible_findings(&self) -> Vec<usize> {
+        (0..self.findings.len())
+            .filter(|i| finding_matches(&self.findings[*i], &self.filter))
+            .collect()
+    }
+
+    /// The filter of the config panel. Only a filter naming at least one container applies, so
+    /// filtering the findings by e.g. their message leaves the panel alone.
+    pub fn config_filter(&self) -> Option<&str> {
+        let filter = self.filter.as_str();
+
+        (!filter.is_empty()
+            && self
+                .lxc_configs
+                .keys()
+                .any(|filename| contains_ignore_case(filename, filter)))
+        .then_some(filter)
+    }
+
+    /// Moves the selection to the first finding the filter leaves, unless it is on one already.
+    pub fn reselect_visible(&mut self) {
+        let visible = self.visible_findings();
+
+        if self
+            .selected_finding
+            .is_some_and(|selected| !visible.contains(&selected))
+        {
+            self.selected_finding = visible.first().copied();
+        }
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter.clear();
+        self.filter_input = false;
+    }
+}
+
+#[test]
+fn test_filter_findings() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", crate::fs::subid::SubID::UID)?;
+    state.load_subid("root:100000:65536\n", crate::fs::subid::SubID::GID)?;
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), "unprivileged: 1\n")?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/200.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    let all = state.visible_findings();
+
+    assert_eq!(all.len(), state.findings.len());
+
+    state.filter = "100.CONF".into();
+    state.selected_finding = all
+        .iter()
+        .copied()
+        .find(|i| !finding_matches(&state.findings[*i], "100.conf"));
+    state.reselect_visible();
+
+    let visible = state.visible_findings();
+
+    assert!(!visible.is_empty() && visible.len() < all.len());
+    assert_eq!(state.selected_finding, visible.first().copied());
+    assert_eq!(state.config_filter(), Some("100.CONF"));
+
+    // Not a container, so the config panel lists all of them
+    state.filter = "idmap".into();
+
+    assert_eq!(state.config_filter(), None);
+    assert_eq!(match_range("Missing lxc.idmap", "IDMAP"), Some(12..17));
+
+    Ok(())
+}
@@ -0,0 +1,322 @@
+//! What the fix popup shows for the selected finding: the files and ranges it is about, what they
+//! should look like instead, and the ways pupman can resolve it.
+
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use compact_str::CompactString;
+
+use super::explain::Excerpt;
+use super::{State, finding_vmid};
+use crate::check::Check;
+use crate::finding::{Finding, FindingKind};
+use crate::fix::Fix;
+use crate::lxc::range_end;
+
+/// A way of resolving a finding, offered in the fix popup.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FixStrategy {
+    /// Previews and applies an automated fix.
+    Apply(Fix),
+    /// Plans a common group, or ACLs where idmaps don't allow one, for the service users of every
+    /// container mounting a directory.
+    AdviseSharedVolume,
+    /// Grants the containers mounting a directory access to it through ACLs.
+    ShareWithAcls,
+    /// Shifts the owners of everything in the rootfs.
+    ShiftOwnership,
+    /// Opens the idmap wizard on the finding's container.
+    GenerateIdmaps(CompactString),
+    /// Opens the idmap editor on the finding's container.
+    EditIdmaps(CompactString),
+    /// Opens the /etc/subuid and /etc/subgid editor.
+    EditSubids,
+}
+
+impl FixStrategy {
+    pub fn label(&self) -> String {
+        match self {
+            FixStrategy::Apply(fix) => fix.description(),
+            FixStrategy::AdviseSharedVolume => {
+                "Plan shared access for the service users of every container mounting the directory".to_string()
+            },
+            FixStrategy::ShareWithAcls => "Share the directory with ACLs instead of mapping a common group".to_string(),
+            FixStrategy::ShiftOwnership => "Shift the owners in the rootfs to where the idmap puts them".to_string(),
+            FixStrategy::GenerateIdmaps(filename) => format!("Generate new idmaps for {filename}"),
+            FixStrategy::EditIdmaps(filename) => format!("Edit the idmaps of {filename}"),
+            FixStrategy::EditSubids => "Edit /etc/subuid and /etc/subgid".to_string(),
+        }
+    }
+}
+
+/// The concrete details of a finding, for the fix popup.
+#[derive(Debug, PartialEq)]
+pub struct FixContext {
+    /// The files involved, config lines with their line number.
+    pub files: Vec<String>,
+    /// The idmaps and subordinate id ranges involved.
+    pub ranges: Vec<String>,
+    /// What the offending lines should look like instead, if it can be worked out.
+    pub correction: Option<Excerpt>,
+    pub strategies: Vec<FixStrategy>,
+}
+
+impl State {
+    /// Whether the fix popup has anything to offer for `finding`.
+    pub fn fixable(&self, finding: &Finding) -> bool {
+        finding.kind == FindingKind::Bad || !self.fix_strategies(finding).is_empty()
+    }
+
+    /// Marks the selected finding for fixing along with others, or unmarks it. Only findings with
+    /// an automated fix can be applied together.
+    pub fn toggle_marked_finding(&mut self) -> color_eyre::Result<()> {
+        let Some(finding) = self.selected_finding.and_then(|index| self.findings.get(index)) else {
+            return Ok(());
+        };
+
+        if finding.fix.is_none() {
+            return Err(eyre!("Only findings with an automated fix can be marked"));
+        }
+
+        let id = finding.id();
+
+        if !self.marked_findings.remove(&id) {
+            self.marked_findings.insert(id);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_marked(&self, finding: &Finding) -> bool {
+        !self.marked_findings.is_empty() && self.marked_findings.contains(&finding.id())
+    }
+
+    /// The automated fixes of the marked findings in the order they are listed, each fix once.
+    pub fn marked_fixes(&self) -> Vec<Fix> {
+        let mut fixes = Vec::new();
+
+        for fix in self
+            .findings
+            .iter()
+            .filter(|finding| self.is_marked(finding))
+            .filter_map(|finding| finding.fix)
+        {
+            if !fixes.contains(&fix) {
+                fixes.push(fix);
+            }
+        }
+
+        fixes
+    }
+
+    /// The ways pupman can resolve `finding`, the automated fix first.
+    pub fn fix_strategies(&self, finding: &Finding) -> Vec<FixStrategy> {
+        if finding.kind == FindingKind::Good {
+            return Vec::new();
+        }
+
+        let mut strategies: Vec<_> = finding.fix.map(FixStrategy::Apply).into_iter().collect();
+
+        if self.shared_mount(finding).is_ok() {
+            strategies.extend([FixStrategy::AdviseSharedVolume, FixStrategy::ShareWithAcls]);
+        }
+
+        if finding.check == Check::RootfsOwnership {
+            strategies.push(FixStrategy::ShiftOwnership);
+        }
+
+        // The wizard and editor only take unprivileged containers
+        let container = finding_vmid(&self.lxc_configs, finding)
+            .map(|vmid| CompactString::new(format!("{vmid}.conf")))
+            .filter(|filename| self.unprivileged_configs().any(|f| f == filename));
+
+        if let Some(filename) = container {
+            if matches!(
+                finding.check,
+                Check::IdmapPresent
+                    | Check::IdmapHostRange
+                    | Check::IdmapCoverage
+                    | Check::IdmapSymmetry
+                    | Check::IdmapLoginUsers
+                    | Check::IdmapHostAccounts
+                    | Check::IdmapReservedRanges
+            ) {
+                strategies.push(FixStrategy::GenerateIdmaps(filename.clone()));
+            }
+
+            if matches!(
+                finding.check,
+                Check::IdmapHostRange
+                    | Check::IdmapCoverage
+                    | Check::IdmapSymmetry
+                    | Check::IdmapLoginUsers
+                    | Check::IdmapHostAccounts
+                    | Check::IdmapReservedRanges
+                    | Check::IdRangeValues
+                    | Check::MountOwnership
+                    | Check::DeviceOwnership
+            ) {
+                strategies.push(FixStrategy::EditIdmaps(filename));
+            }
+        }
+
+        if !finding.host_mapping_highlights.is_empty() || finding.check == Check::IdmapHostRange {
+            strategies.push(FixStrategy::EditSubids);
+        }
+
+        strategies
+    }
+
+    /// The files, ranges and corrections of `finding`, and how it can be resolved.
+    pub fn fix_context(&self, finding: &Finding, config_dir: &Path) -> FixContext {
+        let mut files = Vec::new();
+        let mut ranges = Vec::new();
+
+        for line in &finding.config_line_highlights {
+            push_new(
+                &mut files,
+                format!(
+                    "{}:{} ({})",
+                    config_dir.join(&line.filename).display(),
+                    line.line,
+                    line.key
+                ),
+            );
+        }
+
+        for (filename, sub_id) in &finding.lxc_config_mapping_highlights {
+            if !finding
+                .config_line_highlights
+                .iter()
+                .any(|line| line.filename == *filename)
+            {
+                push_new(&mut files, config_dir.join(filename).display().to_string());
+            }
+
+            let idmaps = self.idmaps(filename).iter();
+
+            for idmap in idmaps.filter(|idmap| idmap.kind() == Some(*sub_id)) {
+                let host_ids = match &idmap.parsed {
+                    Ok(parsed) if parsed.size > 0 => {
+                        format!(", host ids {}-{}", parsed.host_id, parsed.host_end() - 1)
+                    },
+                    _ => String::new(),
+                };
+
+                push_new(&mut ranges, format!("{filename}: lxc.idmap: {}{host_ids}", idmap.value));
+            }
+        }
+
+        for (owner, sub_id) in &finding.host_mapping_highlights {
+            push_new(&mut files, sub_id.path().to_string());
+
+            for entry in self.subid_entries(*sub_id) {
+                if entry.host_user_id != *owner {
+                    continue;
+                }
+
+                let end = range_end(entry.host_sub_id, entry.host_sub_id_count);
+
+                push_new(
+                    &mut ranges,
+                    format!(
+                        "{}: {owner}:{}:{}, host ids {}-{}",
+                        sub_id.path(),
+                        entry.host_sub_id,
+                        entry.host_sub_id_count,
+                        entry.host_sub_id,
+                        end.saturating_sub(1)
+                    ),
+                );
+            }
+        }
+
+        for rootfs in &finding.rootfs_highlights {
+            match self.rootfs_info.get(rootfs) {
+                Some((location, _)) => push_new(
+                    &mut files,
+                    format!("{} (rootfs {rootfs})", location.mountpoint.display()),
+                ),
+                None => push_new(&mut files, format!("rootfs {rootfs}")),
+            }
+        }
+
+        FixContext {
+            files,
+            ranges,
+            correction: self.explain(finding, config_dir).suggested,
+            strategies: self.fix_strategies(finding),
+        }
+    }
+
+    /// Moves the highlighted strategy of the fix popup by `delta`.
+    pub fn move_fix_selection(&mut self, delta: isize) {
+        let count = self
+            .selected_finding
+            .and_then(|index| self.findings.get(index))
+            .map_or(0, |finding| self.fix_strategies(finding).len());
+
+        self.fix_selection = self
+            .fix_selection
+            .saturating_add_signed(delta)
+            .min(count.saturating_sub(1));
+    }
+}
+
+fn push_new(list: &mut Vec<String>, item: String) {
+    if !list.contains(&item) {
+        list.push(item);
+    }
+}
+
+#[test]
+fn test_fix_context() -> color_eyre::Result<()> {
+    use crate::fs::subid::SubID;
+
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 60000\n",
+    )?;
+    state.evaluate_findings();
+
+    let coverage = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::IdmapCoverage && f.kind != FindingKind::Good)
+        .expect("a coverage finding");
+    let context = state.fix_context(coverage, Path::new("/etc/pve/lxc"));
+
+    assert_eq!(context.files, ["/etc/pve/lxc/100.conf"]);
+    assert_eq!(
+        context.ranges,
+        ["100.conf: lxc.idmap: g 0 100000 60000, host ids 100000-159999"]
+    );
+    assert_eq!(
+        context.strategies,
+        [
+            FixStrategy::GenerateIdmaps("100.conf".into()),
+            FixStrategy::EditIdmaps("100.conf".into())
+        ]
+    );
+    assert!(state.fixable(coverage));
+
+    // Nothing to fix about what is fine
+    let good = state
+        .findings
+        .iter()
+        .find(|f| f.kind == FindingKind::Good)
+        .expect("a good finding");
+
+    assert!(state.fix_strategies(good).is_empty());
+
+    state.selected_finding = state.findings.iter().position(|f| std::ptr::eq(f, coverage));
+    state.move_fix_selection(5);
+
+    assert_eq!(state.fix_selection, 1);
+
+    Ok(())
+}
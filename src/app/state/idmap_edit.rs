@@ -0,0 +1,319 @@
+//! Editing a container's `lxc.idmap` entries from the config panel.
+
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use compact_str::CompactString;
+
+use super::{State, effective_idmaps, finding_vmid};
+use crate::fs::subid::SubID;
+use crate::lxc::config::Config;
+use crate::lxc::idmap::{ConfigIdMap, IdMap, IdMapCoverage, idmap_coverage};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IdMapField {
+    Container,
+    Host,
+    Size,
+}
+
+impl IdMapField {
+    pub fn next(self) -> Self {
+        match self {
+            IdMapField::Container => IdMapField::Host,
+            IdMapField::Host => IdMapField::Size,
+            IdMapField::Size => IdMapField::Container,
+        }
+    }
+
+    pub fn prev(self) -> Self {
+        match self {
+            IdMapField::Container => IdMapField::Size,
+            IdMapField::Host => IdMapField::Container,
+            IdMapField::Size => IdMapField::Host,
+        }
+    }
+
+    pub fn value(self, idmap: &IdMap) -> u32 {
+        match self {
+            IdMapField::Container => idmap.container_id,
+            IdMapField::Host => idmap.host_id,
+            IdMapField::Size => idmap.size,
+        }
+    }
+}
+
+/// A working copy of one config's own idmaps. Idmaps from `lxc.include` files are left alone, and
+/// nothing is written until the user confirms saving.
+#[derive(Debug)]
+pub struct IdMapEditor {
+    pub filename: CompactString,
+    /// The idmaps as they were when the editor was opened.
+    pub original: Vec<IdMap>,
+    pub rows: Vec<IdMap>,
+    pub selected: usize,
+    pub field: IdMapField,
+    /// The text typed into the selected field while it's being edited.
+    pub input: Option<String>,
+}
+
+impl IdMapEditor {
+    /// Opens the editor on a config's idmaps. Malformed idmaps can't be shown as rows, so those
+    /// have to be fixed by hand first.
+    pub fn new(filename: CompactString, idmaps: &[ConfigIdMap]) -> color_eyre::Result<Self> {
+        let mut rows = Vec::new();
+
+        for idmap in idmaps.iter().filter(|idmap| idmap.include.is_none()) {
+            match &idmap.parsed {
+                Ok(parsed) => rows.push(*parsed),
+                Err(err) => return Err(eyre!("{filename} has a malformed lxc.idmap {:?}: {err}", idmap.value)),
+            }
+        }
+
+        Ok(Self {
+            filename,
+            original: rows.clone(),
+            rows,
+            selected: 0,
+            field: IdMapField::Container,
+            input: None,
+        })
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Starts typing into the selected field, prefilled with its current value.
+    pub fn begin_input(&mut self) {
+        if let Some(row) = self.rows.get(self.selected) {
+            self.input = Some(self.field.value(row).to_string());
+        }
+    }
+
+    /// Stores the typed number in the selected field. The input is kept when it isn't a number, so
+    /// it can be corrected.
+    pub fn commit_input(&mut self) -> color_eyre::Result<()> {
+        let (Some(input), Some(row)) = (&self.input, self.rows.get_mut(self.selected)) else {
+            return Ok(());
+        };
+        let value = input
+            .trim()
+            .parse()
+            .map_err(|_| eyre!("{input:?} is not a number between 0 and 4294967295"))?;
+
+        match self.field {
+            IdMapField::Container => row.container_id = value,
+            IdMapField::Host => row.host_id = value,
+            IdMapField::Size => row.size = value,
+        }
+
+        self.input = None;
+
+        Ok(())
+    }
+
+    /// Adds an idmap of the selected kind right after the selected one, continuing where the
+    /// selected one ends in both the container and on the host.
+    pub fn add_row(&mut self) {
+        let row = match self.rows.get(self.selected) {
+            Some(selected) => IdMap {
+                kind: selected.kind,
+                container_id: u32::try_from(selected.container_end()).unwrap_or(u32::MAX),
+                host_id: u32::try_from(selected.host_end()).unwrap_or(u32::MAX),
+                size: 1,
+            },
+            None => IdMap {
+                kind: SubID::UID,
+                container_id: 0,
+                host_id: 100000,
+                size: 65536,
+            },
+        };
+        let index = if self.rows.is_empty() { 0 } else { self.selected + 1 };
+
+        self.rows.insert(index, row);
+        self.selected = index;
+    }
+
+    pub fn delete_row(&mut self) {
+        if self.selected < self.rows.len() {
+            self.rows.remove(self.selected);
+            self.move_selection(0);
+        }
+    }
+
+    pub fn toggle_kind(&mut self) {
+        if let Some(row) = self.rows.get_mut(self.selected) {
+            row.kind = match row.kind {
+                SubID::UID => SubID::GID,
+                SubID::GID => SubID::UID,
+            };
+        }
+    }
+
+    pub fn changed(&self) -> bool {
+        self.rows != self.original
+    }
+
+    /// The `lxc.idmap` values to write, in order.
+    pub fn values(&self) -> Vec<String> {
+        self.rows.iter().map(IdMap::to_string).collect()
+    }
+
+    /// Reasons the idmaps can't be saved as they are.
+    pub fn problems(&self) -> Vec<String> {
+        self.rows
+            .iter()
+            .filter_map(|row| {
+                let value = row.to_string();
+
+                value
+                    .parse::<IdMap>()
+                    .err()
+                    .map(|err| format!("lxc.idmap: {value}: {err}"))
+            })
+            .collect()
+    }
+
+    /// Container ids left unmapped or mapped twice. LXC refuses to start the container with
+    /// overlaps, but saving is still allowed since it may take more than one edit to line them up.
+    pub fn coverage(&self) -> Vec<(SubID, IdMapCoverage)> {
+        [SubID::UID, SubID::GID]
+            .into_iter()
+            .flat_map(|kind| {
+                let idmaps: Vec<_> = self.rows.iter().filter(|row| row.kind == kind).copied().collect();
+                let issues = if idmaps.is_empty() {
+                    Vec::new()
+                } else {
+                    idmap_coverage(&idmaps)
+                };
+
+                issues.into_iter().map(move |issue| (kind, issue))
+            })
+            .collect()
+    }
+}
+
+impl State {
+    /// Opens the idmap editor on the container of the selected finding, or the first unprivileged
+    /// container when the finding isn't about one. Configs are found in `config_dir`.
+    pub fn open_idmap_editor(&mut self, config_dir: &Path) -> color_eyre::Result<()> {
+        let selected = self
+            .selected_finding
+            .and_then(|index| self.findings.get(index))
+            .and_then(|finding| finding_vmid(&self.lxc_configs, finding))
+            .map(|vmid| CompactString::new(format!("{vmid}.conf")))
+            .filter(|filename| self.unprivileged_configs().any(|f| f == filename));
+        let filename = selected
+            .or_else(|| self.unprivileged_configs().next().cloned())
+            .ok_or_else(|| eyre!("There are no unprivileged containers to edit idmaps of"))?;
+
+        self.idmap_editor = Some(self.idmap_editor_for(filename, config_dir)?);
+
+        Ok(())
+    }
+
+    /// Moves the idmap editor on to the next unprivileged container, unless there are unsaved edits.
+    pub fn next_idmap_editor(&mut self, config_dir: &Path) -> color_eyre::Result<()> {
+        let Some(editor) = &self.idmap_editor else {
+            return Ok(());
+        };
+
+        if editor.changed() {
+            return Err(eyre!("Save or discard the edits to {} first", editor.filename));
+        }
+
+        let configs: Vec<_> = self.unprivileged_configs().cloned().collect();
+        let Some(index) = configs.iter().position(|filename| *filename == editor.filename) else {
+            return Ok(());
+        };
+        let next = configs[(index + 1) % configs.len()].clone();
+
+        self.idmap_editor = Some(self.idmap_editor_for(next, config_dir)?);
+
+        Ok(())
+    }
+
+    /// An editor on the idmaps of `filename` as the queued changes leave them, so saving the edits
+    /// doesn't replace idmaps which are only queued.
+    fn idmap_editor_for(&self, filename: CompactString, config_dir: &Path) -> color_eyre::Result<IdMapEditor> {
+        let Some(content) = self.changes.planned(&config_dir.join(&*filename)) else {
+            let idmaps = self.idmaps(&filename);
+
+            return IdMapEditor::new(filename, idmaps);
+        };
+        let config: Config = content.parse()?;
+
+        IdMapEditor::new(filename, effective_idmaps(&config, &self.default_idmaps))
+    }
+
+    pub(super) fn unprivileged_configs(&self) -> impl Iterator<Item = &CompactString> {
+        self.lxc_configs
+            .iter()
+            .filter(|(_, config)| {
+                self.dialect
+                    .is_unprivileged(&config.section(None), &self.default_idmaps)
+            })
+            .map(|(filename, _)| filename)
+    }
+}
+
+#[test]
+fn test_edit_idmaps() -> color_eyre::Result<()> {
+    use crate::lxc::idmap::config_idmaps;
+
+    let config: Config = "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n".parse()?;
+    let mut editor = IdMapEditor::new("100.conf".into(), &config_idmaps(&config))?;
+
+    assert!(!editor.changed());
+    assert!(editor.coverage().is_empty());
+
+    // Pass container uid 1000 through to the host
+    editor.field = IdMapField::Size;
+    editor.input = Some("1000".into());
+    editor.commit_input()?;
+    editor.add_row();
+    editor.field = IdMapField::Host;
+    editor.input = Some("1000".into());
+    editor.commit_input()?;
+    editor.add_row();
+    editor.field = IdMapField::Size;
+    editor.input = Some("64535".into());
+    editor.commit_input()?;
+
+    assert_eq!(
+        editor.values(),
+        [
+            "u 0 100000 1000",
+            "u 1000 1000 1",
+            "u 1001 1001 64535",
+            "g 0 100000 65536"
+        ]
+    );
+    assert!(editor.changed());
+    assert!(editor.problems().is_empty());
+    assert!(editor.coverage().is_empty());
+
+    editor.input = Some("0".into());
+    editor.commit_input()?;
+
+    assert_eq!(editor.problems(), ["lxc.idmap: u 1001 1001 0: the range maps zero ids"]);
+    assert_eq!(
+        editor.coverage()[0].1.to_string(),
+        "container ids 1001-65535 not mapped"
+    );
+
+    editor.input = Some("many".into());
+
+    assert!(editor.commit_input().is_err());
+
+    let malformed: Config = "lxc.idmap: u 0 100000\n".parse()?;
+
+    assert!(IdMapEditor::new("101.conf".into(), &config_idmaps(&malformed)).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,291 @@
+//! Validation for subuid/subgid entries pasted into the import dialog.
+
+use compact_str::CompactString;
+
+use crate::app::ui::{HostMapping, IdMapEntry};
+use crate::fs::subid::{SubID, is_comment, split_fields};
+use crate::linux::passwd::Passwd;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImportStatus {
+    /// Not present yet and doesn't overlap any other range.
+    New,
+    /// Exactly the same entry already exists.
+    Duplicate,
+    /// The user already has a different range, or the range overlaps another user's.
+    Conflicting,
+    /// Not a `name:start:count` line.
+    Invalid,
+}
+
+impl ImportStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportStatus::New => "new",
+            ImportStatus::Duplicate => "duplicate",
+            ImportStatus::Conflicting => "conflicting",
+            ImportStatus::Invalid => "invalid",
+        }
+    }
+}
+
+/// Which files the imported entries are written to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImportTarget {
+    Both,
+    Subuid,
+    Subgid,
+}
+
+impl ImportTarget {
+    pub fn sub_ids(self) -> &'static [SubID] {
+        match self {
+            ImportTarget::Both => &[SubID::UID, SubID::GID],
+            ImportTarget::Subuid => &[SubID::UID],
+            ImportTarget::Subgid => &[SubID::GID],
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ImportTarget::Both => ImportTarget::Subuid,
+            ImportTarget::Subuid => ImportTarget::Subgid,
+            ImportTarget::Subgid => ImportTarget::Both,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportTarget::Both => "/etc/subuid and /etc/subgid",
+            ImportTarget::Subuid => "/etc/subuid",
+            ImportTarget::Subgid => "/etc/subgid",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportEntry {
+    /// The pasted line, trimmed.
+    pub line: String,
+    pub user: CompactString,
+    pub start: u32,
+    pub count: u32,
+    pub status: ImportStatus,
+    pub selected: bool,
+}
+
+impl ImportEntry {
+    /// The entry in canonical `name:start:count` form.
+    pub fn canonical(&self) -> String {
+        format!("{}:{}:{}", self.user, self.start, self.count)
+    }
+}
+
+#[derive(Debug)]
+pub struct SubidImport {
+    /// Everything pasted or typed so far.
+    pub text: String,
+    /// Whether the pasted entries have been validated and are being picked from.
+    pub reviewing: bool,
+    pub target: ImportTarget,
+    pub entries: Vec<ImportEntry>,
+    /// The index of the highlighted entry while reviewing.
+    pub selected: usize,
+}
+
+impl Default for SubidImport {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            reviewing: false,
+            target: ImportTarget::Both,
+            entries: Vec::new(),
+            selected: 0,
+        }
+    }
+}
+
+pub(super) fn overlaps(start: u32, count: u32, other_start: u32, other_count: u32) -> bool {
+    let end = u64::from(start) + u64::from(count);
+    let other_end = u64::from(other_start) + u64::from(other_count);
+
+    u64::from(start) < other_end && u64::from(other_start) < end
+}
+
+fn classify<'e>(
+    passwd: &Passwd,
+    user: &str,
+    start: u32,
+    count: u32,
+    existing: impl IntoIterator<Item = &'e IdMapEntry>,
+) -> ImportStatus {
+    let mut status = ImportStatus::New;
+
+    for entry in existing {
+        let same_user = passwd.same_user(&entry.host_user_id, user);
+
+        if same_user && entry.host_sub_id == start && entry.host_sub_id_count == count {
+            status = ImportStatus::Duplicate;
+        } else if same_user || overlaps(start, count, entry.host_sub_id, entry.host_sub_id_count) {
+            return ImportStatus::Conflicting;
+        }
+    }
+
+    status
+}
+
+impl SubidImport {
+    /// Validates every pasted line against the entries of the target files in `host_mapping` and the
+    /// entries pasted before it, with owners looked up in `passwd`. New entries start out selected.
+    pub fn review(&mut self, host_mapping: &HostMapping, passwd: &Passwd) {
+        self.entries.clear();
+        self.selected = 0;
+        self.reviewing = true;
+
+        for line in self.text.lines().map(str::trim) {
+            if line.is_empty() || is_comment(line) {
+                continue;
+            }
+
+            let (fields, _) = split_fields(line);
+            let parsed = match fields[..] {
+                [user, start, count] if !user.is_empty() => {
+                    start
+                        .parse()
+                        .ok()
+                        .zip(count.parse().ok())
+                        .map(|(start, count)| IdMapEntry {
+                            host_user_id: user.into(),
+                            host_sub_id: start,
+                            host_sub_id_count: count,
+                            ..IdMapEntry::default()
+                        })
+                },
+                _ => None,
+            };
+            let Some(parsed) = parsed else {
+                self.entries.push(ImportEntry {
+                    line: line.to_string(),
+                    user: CompactString::default(),
+                    start: 0,
+                    count: 0,
+                    status: ImportStatus::Invalid,
+                    selected: false,
+                });
+                continue;
+            };
+            // Earlier pasted entries count as existing ones, so a block can't conflict with itself
+            let pasted: Vec<_> = self
+                .entries
+                .iter()
+                .filter(|entry| entry.status == ImportStatus::New)
+                .map(|entry| IdMapEntry {
+                    host_user_id: entry.user.clone(),
+                    host_sub_id: entry.start,
+                    host_sub_id_count: entry.count,
+                    ..IdMapEntry::default()
+                })
+                .collect();
+            let status = self
+                .target
+                .sub_ids()
+                .iter()
+                .map(|sub_id| {
+                    let existing = match sub_id {
+                        SubID::UID => &host_mapping.subuid,
+                        SubID::GID => &host_mapping.subgid,
+                    };
+
+                    classify(
+                        passwd,
+                        &parsed.host_user_id,
+                        parsed.host_sub_id,
+                        parsed.host_sub_id_count,
+                        existing.iter().chain(&pasted),
+                    )
+                })
+                .max_by_key(|status| match status {
+                    ImportStatus::New => 0,
+                    ImportStatus::Duplicate => 1,
+                    ImportStatus::Conflicting | ImportStatus::Invalid => 2,
+                })
+                .unwrap_or(ImportStatus::New);
+
+            self.entries.push(ImportEntry {
+                line: line.to_string(),
+                user: parsed.host_user_id,
+                start: parsed.host_sub_id,
+                count: parsed.host_sub_id_count,
+                status,
+                selected: status == ImportStatus::New,
+            });
+        }
+    }
+
+    /// Selected entries in canonical form, ready to be appended to the target files.
+    pub fn selected_lines(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.selected)
+            .map(ImportEntry::canonical)
+            .collect()
+    }
+
+    /// Toggles the highlighted entry. Invalid entries can never be selected.
+    pub fn toggle_selected(&mut self) {
+        if let Some(entry) = self.entries.get_mut(self.selected)
+            && entry.status != ImportStatus::Invalid
+        {
+            entry.selected = !entry.selected;
+        }
+    }
+}
+
+#[test]
+fn test_review_pasted_entries() {
+    let host_mapping = HostMapping {
+        subuid: vec![IdMapEntry {
+            host_user_id: "root".into(),
+            host_sub_id: 100000,
+            host_sub_id_count: 65536,
+            ..IdMapEntry::default()
+        }],
+        ..HostMapping::default()
+    };
+    let mut import = SubidImport {
+        text: "# from the wiki\nroot:100000:65536\nalice : 165536 : 65536\nbob:200000:65536\n\
+               carol:400000:65536\nnot an entry\n0:500000:65536\n"
+            .into(),
+        ..SubidImport::default()
+    };
+
+    import.review(&host_mapping, &Passwd::default());
+
+    let statuses: Vec<_> = import.entries.iter().map(|entry| entry.status).collect();
+
+    // root is only in subuid, so it is a duplicate there and new in subgid
+    assert_eq!(
+        statuses,
+        [
+            ImportStatus::Duplicate,
+            ImportStatus::New,
+            // Overlaps alice's pasted range
+            ImportStatus::Conflicting,
+            ImportStatus::New,
+            ImportStatus::Invalid,
+            // root again, named by uid
+            ImportStatus::Conflicting,
+        ]
+    );
+    assert_eq!(import.selected_lines(), ["alice:165536:65536", "carol:400000:65536"]);
+
+    import.selected = 4;
+    import.toggle_selected();
+
+    assert!(!import.entries[4].selected);
+
+    import.target = ImportTarget::Subgid;
+    import.review(&host_mapping, &Passwd::default());
+
+    assert_eq!(import.entries[0].status, ImportStatus::New);
+}
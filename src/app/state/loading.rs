@@ -0,0 +1,114 @@
+//! How far along each file is in being read, so a file which hasn't arrived yet can be told apart
+//! from one which is missing or holds nothing of interest.
+
+use std::path::{Path, PathBuf};
+
+use super::State;
+use crate::fs::monitor::is_container_config;
+
+/// Where a file is in being read.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LoadState {
+    /// Waiting for the reader.
+    Queued,
+    /// Being read for the first time.
+    Loading,
+    Loaded,
+    /// The file couldn't be read, with why.
+    Failed(String),
+}
+
+impl LoadState {
+    /// Whether the file's content is still to arrive.
+    pub fn is_pending(&self) -> bool {
+        matches!(self, LoadState::Queued | LoadState::Loading)
+    }
+}
+
+impl State {
+    pub fn queue_load(&mut self, path: PathBuf) {
+        self.file_loads.insert(path, LoadState::Queued);
+    }
+
+    /// Marks `path` as being read. A file read before stays loaded while it is read again, as its
+    /// old content is there until the new one arrives.
+    pub fn mark_loading(&mut self, path: PathBuf) {
+        let state = self.file_loads.entry(path).or_insert(LoadState::Loading);
+
+        if *state != LoadState::Loaded {
+            *state = LoadState::Loading;
+        }
+    }
+
+    pub fn mark_loaded(&mut self, path: PathBuf) {
+        self.file_loads.insert(path, LoadState::Loaded);
+    }
+
+    pub fn mark_failed(&mut self, path: PathBuf, error: String) {
+        self.file_loads.insert(path, LoadState::Failed(error));
+    }
+
+    /// Forgets about a removed file.
+    pub fn forget_load(&mut self, path: &Path) {
+        self.file_loads.remove(path);
+    }
+
+    pub fn is_pending(&self, path: &Path) -> bool {
+        self.file_loads.get(path).is_some_and(LoadState::is_pending)
+    }
+
+    /// How many files are still to arrive. Findings aren't evaluated until none are, as they'd
+    /// flag whatever the missing files hold as absent.
+    pub fn pending_loads(&self) -> usize {
+        self.file_loads.values().filter(|state| state.is_pending()).count()
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.file_loads.values().any(LoadState::is_pending)
+    }
+
+    /// The filenames of the container configs in `config_dir` still to arrive, sorted.
+    pub fn pending_configs(&self, config_dir: &Path) -> Vec<&str> {
+        let mut filenames: Vec<_> = self
+            .file_loads
+            .iter()
+            .filter(|(path, state)| {
+                state.is_pending() && path.parent() == Some(config_dir) && is_container_config(path)
+            })
+            .filter_map(|(path, _)| path.file_name()?.to_str())
+            .collect();
+
+        filenames.sort_unstable();
+        filenames
+    }
+}
+
+#[test]
+fn test_load_lifecycle() {
+    let mut state = State::default();
+    let config = PathBuf::from("/etc/pve/lxc/100.conf");
+    let subuid = PathBuf::from("/etc/subuid");
+
+    state.queue_load(config.clone());
+    state.queue_load(subuid.clone());
+
+    assert_eq!(state.pending_loads(), 2);
+    assert_eq!(state.pending_configs(Path::new("/etc/pve/lxc")), ["100.conf"]);
+
+    state.mark_loading(subuid.clone());
+    state.mark_failed(subuid.clone(), "Permission denied".into());
+    state.mark_loading(config.clone());
+
+    assert!(!state.is_pending(&subuid));
+    assert!(state.is_pending(&config));
+
+    state.mark_loaded(config.clone());
+
+    assert!(!state.is_loading());
+
+    // Reading it again keeps what was loaded
+    state.mark_loading(config.clone());
+
+    assert_eq!(state.file_loads.get(&config), Some(&LoadState::Loaded));
+    assert!(state.pending_configs(Path::new("/etc/pve/lxc")).is_empty());
+}
@@ -1,35 +1,222 @@
-use std::collections::{HashMap, hash_map::Entry};
-use std::fs::{self, Metadata};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata, read_dir, read_to_string};
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
 
 use ahash::RandomState;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{WrapErr, eyre};
 use compact_str::CompactString;
 use indexmap::IndexMap;
-use log::error;
+use log::warn;
+use nix::unistd::geteuid;
 use tui_logger::TuiWidgetState;
 
-use super::ui::{Finding, FindingKind, HostMapping};
-use crate::fs::subid::SubID;
-use crate::linux::{groupname_to_id, username_to_id};
-use crate::lxc::config::Config;
-use crate::lxc::rootfs_value_to_path;
+use self::confirm::Confirmation;
+use self::conversion::Conversion;
+use self::idmap_edit::IdMapEditor;
+use self::import::SubidImport;
+use self::loading::LoadState;
+use self::owner_history::OwnerHistory;
+use self::preview::WritePreview;
+use self::shift::OwnershipShift;
+use self::skipped::SkippedCheck;
+use self::source::SourceView;
+use self::stats::SessionStats;
+use self::subid_edit::SubidEditor;
+use self::wizard::IdmapWizard;
+use super::parse_subid_lines;
+use super::ui::{HostMapping, IdMapEntry};
+use crate::changes::Transaction;
+use crate::check::Check;
+use crate::finding::Finding;
+use crate::fix::DEFAULT_ROOT_RANGE;
+use crate::followup::Step;
+use crate::fs::monitor::is_container_config;
+use crate::fs::scan::OwnershipScan;
+use crate::fs::subid::{ShadowBackup, SubID, read_shadow_backup};
+use crate::fs::writer::PendingWrite;
+use crate::incus;
+use crate::linux::passwd::{Group, Passwd, parse_group, parse_passwd};
+use crate::linux::zfs::ZfsCache;
+use crate::linux::{DiskSpace, disk_space, groupname_to_id, username_to_id};
+use crate::lxc::config::{Config, ConfigFormat};
+use crate::lxc::idmap::{ConfigIdMap, IdMap, config_idmaps};
+use crate::lxc::{RootfsLocation, range_end, resolve_rootfs};
+use crate::metadata::Metadata as SystemMetadata;
+use crate::proxmox::dialect::Dialect;
+use crate::settings::{Settings, SortOrder};
 
+pub mod acl;
+mod checks;
+pub mod confirm;
+pub mod conversion;
+pub mod detail;
+pub mod empty;
+pub mod explain;
+pub mod filter;
+pub mod fix_options;
+pub mod idmap_edit;
+pub mod import;
+pub mod loading;
+pub mod owner_history;
+pub mod preview;
+pub mod readiness;
+pub mod shadow;
+pub mod shared_volume;
+pub mod shift;
+pub mod skipped;
+pub mod source;
+pub mod stats;
+pub mod subid_edit;
 #[cfg(test)]
 mod tests;
+pub mod wizard;
+
+/// The outcome of the last evaluation of a single check.
+#[derive(Clone, Copy, Debug)]
+pub struct CheckRun {
+    pub last_run: Instant,
+    /// Number of non-good findings produced.
+    pub findings: usize,
+}
+
+/// The panel of the main view the arrow keys move through.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Focus {
+    HostMapping,
+    LXCConfig,
+    RootFS,
+    #[default]
+    Findings,
+}
+
+impl Focus {
+    const ALL: [Focus; 4] = [Focus::HostMapping, Focus::LXCConfig, Focus::RootFS, Focus::Findings];
+
+    /// The panel `delta` panels after this one, wrapping around.
+    pub fn cycle(self, delta: isize) -> Self {
+        let i = Self::ALL.iter().position(|focus| *focus == self).unwrap_or_default();
+
+        Self::ALL[(i as isize + delta).rem_euclid(Self::ALL.len() as isize) as usize]
+    }
+}
 
 pub struct State {
     pub is_running: bool,
     pub findings: Vec<Finding>,
     pub selected_finding: Option<usize>,
+    /// The ids of the findings marked for fixing together.
+    pub marked_findings: HashSet<String, RandomState>,
     pub host_mapping: HostMapping,
     pub lxc_configs: IndexMap<CompactString, Config, RandomState>,
-    pub rootfs_info: IndexMap<String, (PathBuf, Metadata), RandomState>,
+    pub rootfs_info: IndexMap<String, (RootfsLocation, Metadata), RandomState>,
+    pub rootfs_space: HashMap<String, DiskSpace, RandomState>,
+    /// Rootfs and mount point values which couldn't be resolved or looked at, with why.
+    pub unavailable_dirs: HashMap<String, String, RandomState>,
+    /// The host directories of `mpN` mount points by value, looked up like [`State::rootfs_info`].
+    pub mount_info: HashMap<String, (RootfsLocation, Metadata), RandomState>,
+    /// The last deep scan of each rootfs by value, which only runs when asked for.
+    pub ownership_scans: HashMap<String, OwnershipScan, RandomState>,
+    /// When the owner of each rootfs changed this session, by value.
+    pub rootfs_owner_history: HashMap<String, OwnerHistory, RandomState>,
+    /// The users of /etc/passwd, once it was read. Subid owners are looked up here rather than
+    /// through `id`.
+    pub host_users: Option<Passwd>,
+    /// The groups of /etc/group.
+    pub host_groups: Vec<Group>,
+    /// The uid of whoever runs pupman, see [`SystemMetadata::operator_uid`].
+    pub operator_uid: Option<u32>,
+    /// Whether pupman runs as root, without which rootfs directories often can't be looked at.
+    pub runs_as_root: bool,
+    /// What shadow-utils backed up the last time it wrote /etc/subuid or /etc/subgid.
+    pub shadow_backups: HashMap<SubID, ShadowBackup, RandomState>,
+    /// Which of /etc/subuid and /etc/subgid don't exist, as on minimal hosts.
+    pub missing_subids: Vec<SubID>,
+    /// The checks which couldn't run for each container at the last evaluation, in config order.
+    pub skipped_checks: Vec<SkippedCheck>,
+    /// Whether this system allows rootfs directories to be stat-ed at all. See
+    /// [`State::inspects_rootfs`] for whether they are.
+    pub rootfs_checks: bool,
+    /// How configs are read for the PVE version they belong to.
+    pub dialect: Dialect,
+    /// The directory copied host files are read from, see [`SystemMetadata::root_prefix`].
+    /// `lxc.include` targets are read from under it too.
+    pub root_prefix: Option<PathBuf>,
+    /// The `lxc.idmap` entries of /etc/lxc/default.conf, which plain LXC configs without idmaps of
+    /// their own use. PVE never reads default.conf.
+    pub default_idmaps: Vec<ConfigIdMap>,
     pub show_fix_popup: bool,
+    /// The index of the highlighted strategy in the fix popup.
+    pub fix_selection: usize,
     pub show_settings_page: bool,
     pub show_logs_page: bool,
     pub show_explain_popup: bool,
+    /// How many lines the explain popup is scrolled down by.
+    pub explain_scroll: usize,
+    /// Whether the popup picking the format of a report is shown.
+    pub show_report_popup: bool,
     pub logger_page_state: TuiWidgetState,
+    pub settings: Settings,
+    /// The index of the highlighted row on the settings page.
+    pub selected_setting: usize,
+    pub check_runs: HashMap<Check, CheckRun, RandomState>,
+    pub show_checks_page: bool,
+    /// The index of the highlighted row on the checks page.
+    pub selected_check: usize,
+    /// The subuid/subgid paste import dialog, while it is open.
+    pub import: Option<SubidImport>,
+    /// The host mapping editor, while it is open.
+    pub subid_editor: Option<SubidEditor>,
+    /// The idmap editor of a container config, while it is open.
+    pub idmap_editor: Option<IdMapEditor>,
+    /// The idmap wizard page, while it is open.
+    pub idmap_wizard: Option<IdmapWizard>,
+    /// The privileged to unprivileged conversion assistant, while it is open.
+    pub conversion: Option<Conversion>,
+    /// Previewed edits queued to be applied together.
+    pub changes: Transaction,
+    pub show_changes_page: bool,
+    /// The index of the highlighted change on the pending changes page.
+    pub selected_change: usize,
+    /// How many lines the diff on the pending changes page is scrolled down by.
+    pub changes_scroll: usize,
+    /// The text typed after `/`, which the findings list and config panel are narrowed down to.
+    pub filter: String,
+    /// Whether the filter is being typed into.
+    pub filter_input: bool,
+    /// The panel of the main view with focus.
+    pub focus: Focus,
+    /// How many rows the host mapping panel is scrolled down by.
+    pub host_mapping_scroll: usize,
+    /// How many rows the rootfs panel is scrolled down by.
+    pub rootfs_scroll: usize,
+    /// The container selected in the config panel while the panel has focus, as an index into
+    /// [`State::panel_containers`].
+    pub selected_container: Option<usize>,
+    /// The config whose detail page is open.
+    pub container_detail: Option<CompactString>,
+    /// The ownership shift dialog of a rootfs, while it is open.
+    pub ownership_shift: Option<OwnershipShift>,
+    /// The diff of files about to be written, while it waits for confirmation.
+    pub write_preview: Option<WritePreview>,
+    /// The checklist shown after a fix or import was applied, until it is dismissed.
+    pub follow_up: Option<Vec<Step>>,
+    pub stats: SessionStats,
+    pub show_stats_page: bool,
+    /// The file a finding was followed into, while it is shown.
+    pub source_view: Option<SourceView>,
+    /// How far along each file queued for reading is.
+    pub file_loads: HashMap<PathBuf, LoadState, RandomState>,
+    /// The action waiting for confirmation, drawn over everything else.
+    pub confirmation: Option<Confirmation>,
+    /// The container config directory, while it is gone and its configs are as last read.
+    pub config_dir_unavailable: Option<PathBuf>,
+    /// Whether files are polled for changes, as inotify isn't available.
+    pub polling_files: bool,
 }
 
 impl Default for State {
@@ -38,241 +225,678 @@ impl Default for State {
             is_running: true,
             findings: Vec::new(),
             selected_finding: None,
-            host_mapping: HostMapping {
-                subuid: Vec::new(),
-                subgid: Vec::new(),
-            },
+            marked_findings: HashSet::with_hasher(RandomState::new()),
+            host_mapping: HostMapping::default(),
             lxc_configs: IndexMap::with_hasher(RandomState::new()),
             rootfs_info: IndexMap::with_hasher(RandomState::new()),
+            rootfs_space: HashMap::with_hasher(RandomState::new()),
+            unavailable_dirs: HashMap::with_hasher(RandomState::new()),
+            mount_info: HashMap::with_hasher(RandomState::new()),
+            ownership_scans: HashMap::with_hasher(RandomState::new()),
+            rootfs_owner_history: HashMap::with_hasher(RandomState::new()),
+            host_users: None,
+            host_groups: Vec::new(),
+            operator_uid: None,
+            runs_as_root: true,
+            shadow_backups: HashMap::with_hasher(RandomState::new()),
+            missing_subids: Vec::new(),
+            skipped_checks: Vec::new(),
+            rootfs_checks: true,
+            dialect: Dialect::default(),
+            root_prefix: None,
+            default_idmaps: Vec::new(),
             show_fix_popup: false,
+            fix_selection: 0,
             show_settings_page: false,
             show_logs_page: false,
             show_explain_popup: false,
+            explain_scroll: 0,
+            show_report_popup: false,
             logger_page_state: TuiWidgetState::default(),
+            settings: Settings::default(),
+            selected_setting: 0,
+            check_runs: HashMap::with_hasher(RandomState::new()),
+            show_checks_page: false,
+            selected_check: 0,
+            import: None,
+            subid_editor: None,
+            idmap_editor: None,
+            idmap_wizard: None,
+            conversion: None,
+            changes: Transaction::default(),
+            show_changes_page: false,
+            selected_change: 0,
+            changes_scroll: 0,
+            filter: String::new(),
+            filter_input: false,
+            focus: Focus::default(),
+            host_mapping_scroll: 0,
+            rootfs_scroll: 0,
+            selected_container: None,
+            container_detail: None,
+            ownership_shift: None,
+            write_preview: None,
+            follow_up: None,
+            stats: SessionStats::default(),
+            show_stats_page: false,
+            source_view: None,
+            file_loads: HashMap::with_hasher(RandomState::new()),
+            confirmation: None,
+            config_dir_unavailable: None,
+            polling_files: false,
         }
     }
 }
 
 impl State {
-    /// Findings are re-evaluated based on latest update
-    // TODO: Check for overlaps between configs
-    pub fn evaluate_findings(&mut self) {
-        self.findings.clear();
-
-        let mut username_to_id_map = HashMap::with_hasher(RandomState::new());
-        let mut groupname_to_id_map = HashMap::with_hasher(RandomState::new());
-        let mut usernames: HashMap<_, (&CompactString, SubID), _> = HashMap::with_hasher(RandomState::new());
-        let mut groupnames: HashMap<_, (&CompactString, SubID), _> = HashMap::with_hasher(RandomState::new());
-
-        for mapping in &self.host_mapping.subuid {
-            match usernames.entry(&mapping.host_user_id) {
-                Entry::Occupied(occupancy) => {
-                    let (user_id, sub_id) = *occupancy.get();
-
-                    self.findings.push(Finding {
-                        kind: FindingKind::Bad,
-                        message: "Cannot have multiple entries for the same user",
-                        host_mapping_highlights: vec![(user_id.clone(), sub_id)],
-                        lxc_config_mapping_highlights: Vec::new(),
-                        rootfs_highlights: Vec::new(),
-                    });
-                },
-                Entry::Vacant(vacancy) => {
-                    vacancy.insert((&mapping.host_user_id, SubID::UID));
-                },
-            };
+    /// Synchronously loads everything the TUI would otherwise receive from the file system monitor,
+    /// then evaluates findings once. Files which fail to load are skipped and their errors returned.
+    pub fn collect(metadata: &SystemMetadata, settings: Settings) -> (Self, Vec<color_eyre::Report>) {
+        let mut state = State {
+            settings,
+            rootfs_checks: metadata.inspects_rootfs(),
+            dialect: metadata.dialect(),
+            root_prefix: metadata.root_prefix.clone(),
+            operator_uid: metadata.operator_uid(),
+            runs_as_root: geteuid().is_root(),
+            ..State::default()
+        };
+        let mut errors = Vec::new();
+        let mut zfs = ZfsCache::default();
+
+        if metadata.lxc_default_config.exists() {
+            let path = &metadata.lxc_default_config;
+            let result = read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))
+                .and_then(|content| state.load_lxc_defaults(path, &content));
+
+            if let Err(err) = result {
+                errors.push(err);
+            }
         }
 
-        for mapping in &self.host_mapping.subgid {
-            match groupnames.entry(&mapping.host_user_id) {
-                Entry::Occupied(occupancy) => {
-                    let (user_id, sub_id) = *occupancy.get();
-
-                    self.findings.push(Finding {
-                        kind: FindingKind::Bad,
-                        message: "Cannot have multiple entries for the same group",
-                        host_mapping_highlights: vec![(user_id.clone(), sub_id)],
-                        lxc_config_mapping_highlights: Vec::new(),
-                        rootfs_highlights: Vec::new(),
-                    });
-                },
-                Entry::Vacant(vacancy) => {
-                    vacancy.insert((&mapping.host_user_id, SubID::GID));
-                },
-            };
+        for (path, load) in [
+            (&metadata.passwd_path, State::load_passwd as fn(&mut State, &str)),
+            (&metadata.group_path, State::load_group),
+        ] {
+            // Copied host files often leave these out
+            if !path.exists() {
+                continue;
+            }
+
+            match read_to_string(path) {
+                Ok(content) => load(&mut state, &content),
+                Err(err) => errors.push(eyre!("Failed to read {}: {err}", path.display())),
+            }
         }
 
-        if !self
-            .findings
-            .iter()
-            .any(|f| f.message.starts_with("Cannot have multiple entries for the same"))
-        {
-            self.findings.push(Finding {
-                kind: FindingKind::Good,
-                message: "No duplicate ids found in subuid/subgid mappings",
-                // TODO: Highlight all entries?
-                host_mapping_highlights: Vec::new(),
-                lxc_config_mapping_highlights: Vec::new(),
-                rootfs_highlights: Vec::new(),
+        for subid in [SubID::UID, SubID::GID] {
+            let path = metadata.subid_path(subid);
+
+            if !path.exists() {
+                state.unload_subid(subid);
+                continue;
+            }
+
+            let result = read_to_string(path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))
+                .and_then(|content| state.load_subid(&content, subid));
+
+            state.load_shadow_backup(subid, read_shadow_backup(path));
+
+            if let Err(err) = result {
+                errors.push(err);
+            }
+        }
+
+        if metadata.incus {
+            // Without drivers, rootfs paths are left out rather than guessed
+            let drivers = incus::list_storage_drivers().unwrap_or_else(|err| {
+                errors.push(eyre!("Failed to list Incus storage pools: {err}"));
+                HashMap::default()
             });
+
+            match incus::list_containers() {
+                Ok(names) => {
+                    for name in names {
+                        let result = incus::show_container(&name, &drivers)
+                            .wrap_err_with(|| format!("Failed to read Incus container {name}"))
+                            .and_then(|instance| {
+                                state.load_config(Path::new(&instance.filename()), &instance.to_lxc_config())?;
+                                Ok(())
+                            });
+
+                        if let Err(err) = result {
+                            errors.push(err);
+                        }
+                    }
+                },
+                Err(err) => errors.push(eyre!("Failed to list Incus containers: {err}")),
+            }
         }
 
-        for (filename, config) in &self.lxc_configs {
-            let section = config.section(None);
+        let entries = match read_dir(&metadata.lxc_config_dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                errors.push(eyre!("Failed to read {}: {err}", metadata.lxc_config_dir.display()));
+                state.evaluate_findings();
+                return (state, errors);
+            },
+        };
+
+        for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            if !is_container_config(&path) {
+                continue;
+            }
+
+            let mut dir_values: Vec<_> = match read_to_string(&path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))
+                .and_then(|content| state.load_config(&path, &content))
+            {
+                Ok(rootfs_value) => rootfs_value.map(str::to_owned).into_iter().collect(),
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                },
+            };
 
-            if section.get_unprivileged() != Some("1") {
+            if !state.inspects_rootfs() {
                 continue;
             }
 
-            let rootfs = section.get_rootfs().and_then(|rootfs_value| {
-                let path = match rootfs_value_to_path(rootfs_value) {
-                    Ok(path) => path,
+            dir_values.extend(state.mount_point_values(&path));
+
+            for value in dir_values {
+                let location = match resolve_rootfs(&value, &metadata.storage) {
+                    Ok(location) => location.remount(&mut zfs),
                     Err(err) => {
-                        error!("Failed to convert rootfs value {rootfs_value} to path: {err}");
-                        return None;
+                        state.unavailable_dirs.insert(value.clone(), format!("{err:#}"));
+                        errors.push(err.wrap_err(format!("Failed to resolve rootfs {value}")));
+                        continue;
                     },
                 };
-                match fs::metadata(&path) {
-                    Ok(metadata) => Some((rootfs_value, metadata)),
+                let dir_path = location.mountpoint.clone();
+
+                match fs::metadata(&dir_path) {
+                    Ok(md) => state.load_dir_metadata(value.clone(), location, md),
                     Err(err) => {
-                        error!("Failed to get metadata for path {path:?}: {err}");
-                        None
+                        state
+                            .unavailable_dirs
+                            .insert(value.clone(), format!("{}: {err}", dir_path.display()));
+                        errors.push(eyre!("Failed to stat {}: {err}", dir_path.display()));
                     },
                 }
-            });
 
-            let mut has_user_idmap = false;
-            let mut has_group_idmap = false;
+                if let Ok(space) = disk_space(&dir_path) {
+                    state.rootfs_space.insert(value, space);
+                }
+            }
+        }
 
-            for idmap in section.get_lxc_idmaps() {
-                let mut idmap = idmap.trim().split(' ');
-                let Some(kind) = idmap.next() else {
-                    unreachable!("Invalid ID map entry kind");
-                };
-                let Some(host_id) = idmap.next() else {
-                    unreachable!("Invalid ID map entry host user id");
-                };
-                let parsed_host_id = host_id.parse::<u32>().unwrap();
-                let Some(host_sub_id) = idmap.next() else {
-                    unreachable!("Invalid ID map entry host sub id");
-                };
-                let parsed_host_sub_id = host_sub_id.parse::<u32>().unwrap();
-                let Some(host_sub_id_size) = idmap.next() else {
-                    unreachable!("Invalid ID map entry host sub id count");
-                };
-                let parsed_host_sub_id_size = host_sub_id_size.parse::<u32>().unwrap();
-                let (idmap, mappings, to_id) = if kind == "u" {
-                    has_user_idmap = true;
-
-                    (
-                        &mut username_to_id_map,
-                        &*self.host_mapping.subuid,
-                        username_to_id as fn(&str) -> color_eyre::Result<u32>,
-                    )
-                } else if kind == "g" {
-                    has_group_idmap = true;
-
-                    (
-                        &mut groupname_to_id_map,
-                        &*self.host_mapping.subgid,
-                        groupname_to_id as _,
-                    )
-                } else {
-                    unreachable!("Invalid sub id kind")
-                };
+        state.evaluate_findings();
 
-                if let Some((value, metadata)) = &rootfs {
-                    if kind == "u" && metadata.uid() != parsed_host_sub_id {
-                        self.findings.push(Finding {
-                            kind: FindingKind::Bad,
-                            message: "Rootfs uid does not match host mapping",
-                            host_mapping_highlights: Vec::new(),
-                            lxc_config_mapping_highlights: vec![(filename.clone(), SubID::UID)],
-                            rootfs_highlights: vec![value.to_string()],
-                        });
-                    }
+        (state, errors)
+    }
 
-                    if kind == "g" && metadata.gid() != parsed_host_sub_id {
-                        self.findings.push(Finding {
-                            kind: FindingKind::Bad,
-                            message: "Rootfs gid does not match host mapping",
-                            host_mapping_highlights: Vec::new(),
-                            lxc_config_mapping_highlights: vec![(filename.clone(), SubID::GID)],
-                            rootfs_highlights: vec![value.to_string()],
-                        });
-                    }
-                }
+    /// Whether rootfs directories are stat-ed, watched and checked.
+    pub fn inspects_rootfs(&self) -> bool {
+        self.rootfs_checks && self.settings.inspect_rootfs()
+    }
 
-                for mapping in mappings {
-                    let host_id = match idmap.entry(&mapping.host_user_id) {
-                        Entry::Occupied(id) => *id.get(),
-                        Entry::Vacant(vacancy) => {
-                            let id = match to_id(&mapping.host_user_id) {
-                                Ok(id) => id,
-                                Err(err) => {
-                                    error!("Failed to parse id for {kind} {}: {err:?}", mapping.host_user_id);
-                                    continue;
-                                },
-                            };
-                            *vacancy.insert(id)
-                        },
-                    };
-
-                    if host_id != parsed_host_id {
-                        continue;
-                    }
+    /// The files an open editor has changes to which aren't written yet.
+    pub fn unsaved_changes(&self) -> Vec<&str> {
+        let mut files = Vec::new();
 
-                    if parsed_host_sub_id < mapping.host_sub_id
-                        || parsed_host_sub_id > mapping.host_sub_id + mapping.host_sub_id_count
-                        || parsed_host_sub_id + parsed_host_sub_id_size
-                            > mapping.host_sub_id + mapping.host_sub_id_count
-                    {
-                        let (message, sub_id) = if kind == "u" {
-                            (
-                                "LXC config's host sub uid range outside of host mapping range",
-                                SubID::UID,
-                            )
-                        } else {
-                            (
-                                "LXC config's host sub gid range outside of host mapping range",
-                                SubID::GID,
-                            )
-                        };
-
-                        self.findings.push(Finding {
-                            kind: FindingKind::Bad,
-                            message,
-                            host_mapping_highlights: vec![(mapping.host_user_id.clone(), sub_id)],
-                            lxc_config_mapping_highlights: vec![(filename.clone(), sub_id)],
-                            rootfs_highlights: Vec::new(),
-                        });
-                    }
+        if let Some(editor) = &self.subid_editor {
+            for sub_id in [SubID::UID, SubID::GID] {
+                if editor.changed(sub_id) {
+                    files.push(sub_id.path());
                 }
             }
+        }
 
-            // TODO: This still needs a test
-            if !has_user_idmap {
-                self.findings.push(Finding {
-                    kind: FindingKind::Bad,
-                    message: "lxc.idmap for uid is not set in config",
-                    host_mapping_highlights: Vec::new(),
-                    lxc_config_mapping_highlights: vec![(filename.clone(), SubID::UID)],
-                    rootfs_highlights: Vec::new(),
-                });
-            }
+        if let Some(editor) = &self.idmap_editor
+            && editor.changed()
+        {
+            files.push(editor.filename.as_str());
+        }
+
+        files
+    }
+
+    /// The unprivileged containers whose idmaps map onto subordinate ids which `writes` grant or take
+    /// away, by kind. Only these pick anything up from restarting once the subid files are written.
+    pub fn affected_vmids(&self, metadata: &SystemMetadata, writes: &[PendingWrite]) -> Vec<(SubID, &str)> {
+        let mut affected = Vec::new();
+
+        for write in writes {
+            let Some(sub_id) = metadata.subid_for_path(&write.path) else {
+                continue;
+            };
+            let (before, _) = parse_subid_lines(&write.current);
+            let (after, _) = parse_subid_lines(&write.proposed);
+            let range = |entry: &IdMapEntry| (entry.host_sub_id, range_end(entry.host_sub_id, entry.host_sub_id_count));
+            let unchanged = |entry: &IdMapEntry, others: &[IdMapEntry]| {
+                others
+                    .iter()
+                    .any(|other| other.host_user_id == entry.host_user_id && range(other) == range(entry))
+            };
+            let changed: Vec<_> = before
+                .iter()
+                .filter(|entry| !unchanged(entry, &after))
+                .chain(after.iter().filter(|entry| !unchanged(entry, &before)))
+                .map(range)
+                .collect();
+
+            for (filename, config) in &self.lxc_configs {
+                if !self
+                    .dialect
+                    .is_unprivileged(&config.section(None), &self.default_idmaps)
+                {
+                    continue;
+                }
+
+                let vmid = filename.strip_suffix(".conf").unwrap_or(filename);
+                let mut ranges: Vec<_> = self
+                    .idmaps(filename)
+                    .iter()
+                    .filter_map(|idmap| idmap.parsed.as_ref().ok())
+                    .filter(|idmap| idmap.kind == sub_id)
+                    .map(|idmap| (idmap.host_id, idmap.host_end()))
+                    .collect();
+
+                // PVE maps containers without idmaps of a kind onto the range it grants root
+                if ranges.is_empty() {
+                    let (start, count) = DEFAULT_ROOT_RANGE;
+
+                    ranges.push((start, range_end(start, count)));
+                }
 
-            // TODO: This still needs a test
-            if !has_group_idmap {
-                self.findings.push(Finding {
-                    kind: FindingKind::Bad,
-                    message: "lxc.idmap for gid is not set in config",
-                    host_mapping_highlights: Vec::new(),
-                    lxc_config_mapping_highlights: vec![(filename.clone(), SubID::GID)],
-                    rootfs_highlights: Vec::new(),
+                let overlaps = ranges.iter().any(|(start, end)| {
+                    changed
+                        .iter()
+                        .any(|(from, to)| u64::from(*start) < *to && u64::from(*from) < *end)
                 });
+
+                if overlaps && !affected.contains(&(sub_id, vmid)) {
+                    affected.push((sub_id, vmid));
+                }
             }
         }
 
-        self.findings.sort_by_key(|f| f.kind != FindingKind::Bad);
+        affected
+    }
+
+    /// Orders findings by the sort order from the settings. `first_seen` looks up when a finding
+    /// first appeared, for sorting by recency.
+    pub fn sort_findings(&mut self, first_seen: impl Fn(&Finding) -> Option<DateTime<Utc>>) {
+        let configs = &self.lxc_configs;
+        let findings = &mut self.findings;
+
+        // Sorts are stable, so sorting by severity first leaves it as the tie breaker
+        findings.sort_by_key(|f| f.kind.sort_order());
+
+        match self.settings.sort_order() {
+            SortOrder::Severity => {},
+            SortOrder::Container => findings.sort_by_cached_key(|f| finding_vmid(configs, f)),
+            SortOrder::Check => findings.sort_by_key(|f| f.check),
+            SortOrder::FirstSeen => findings.sort_by_cached_key(|f| Reverse(first_seen(f))),
+        }
+    }
+
+    /// Parses and stores a container config, returning its rootfs value if it has one.
+    pub fn load_config(&mut self, path: &Path, content: &str) -> color_eyre::Result<Option<&str>> {
+        let filename = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .ok_or_else(|| eyre!("Invalid file name"))?;
+        let mut config = Config::from_str(content)?;
+
+        config.resolve_includes(path, self.root_prefix.as_deref());
+        self.stats.files_parsed += 1;
+
+        let filename = CompactString::new(filename);
+
+        self.lxc_configs.insert(filename.clone(), config);
+        self.lxc_configs.sort_unstable_keys();
+
+        Ok(self.lxc_configs[&filename].section(None).get_rootfs())
+    }
+
+    pub fn unload_config(&mut self, path: &Path) -> color_eyre::Result<()> {
+        let filename = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .ok_or_else(|| eyre!("Invalid file name"))?;
+        let Some(config) = self.lxc_configs.shift_remove(filename) else {
+            warn!("Attempted to unload container ID map for non-existent file: {filename}");
+            return Ok(());
+        };
+        let section = config.section(None);
+
+        if let Some(rootfs) = section.get_rootfs() {
+            self.unavailable_dirs.remove(rootfs);
+            self.rootfs_space.remove(rootfs);
+            self.ownership_scans.remove(rootfs);
+            self.rootfs_owner_history.remove(rootfs);
+        }
+
+        if let Some(rootfs) = section.get_rootfs()
+            && self.rootfs_info.shift_remove(rootfs).is_none()
+        {
+            warn!("Attempted to unload rootfs info for non-existent file: {filename}");
+        };
+
+        for mount in section.mount_points() {
+            self.unavailable_dirs.remove(mount.value);
+            self.mount_info.remove(mount.value);
+            self.rootfs_space.remove(mount.value);
+        }
+
+        Ok(())
+    }
+
+    /// The idmaps config `filename` ends up with, which may be the host-wide defaults. Configs which
+    /// aren't loaded have none.
+    pub fn idmaps(&self, filename: &str) -> &[ConfigIdMap] {
+        self.lxc_configs
+            .get(filename)
+            .map_or(&[], |config| effective_idmaps(config, &self.default_idmaps))
+    }
+
+    pub fn load_passwd(&mut self, content: &str) {
+        self.host_users = Some(parse_passwd(content));
+    }
+
+    pub fn load_group(&mut self, content: &str) {
+        self.host_groups = parse_group(content);
+    }
+
+    pub fn unload_passwd(&mut self) {
+        self.host_users = None;
+    }
+
+    pub fn unload_group(&mut self) {
+        self.host_groups.clear();
+    }
+
+    /// The id an owner of a subid entry goes by, its uid or for subgid its primary gid. Looked up
+    /// in /etc/passwd, `id` is only asked before that was read or for users it doesn't list, like
+    /// ones from LDAP.
+    fn owner_id(&self, owner: &str, sub_id: SubID) -> color_eyre::Result<u32> {
+        if let Some(user) = self.host_users.as_ref().and_then(|passwd| passwd.user(owner)) {
+            return Ok(match sub_id {
+                SubID::UID => user.uid,
+                SubID::GID => user.gid,
+            });
+        }
+
+        match sub_id {
+            SubID::UID => username_to_id(owner),
+            SubID::GID => groupname_to_id(owner),
+        }
+    }
+
+    /// The host users whose uid one of `idmaps` maps a container uid onto, by name and uid. These
+    /// are the users who can log in to the host and whoever runs pupman, so files the container
+    /// creates would show up as theirs. Passthroughs are left out, as they share an id on purpose.
+    pub fn mapped_login_users<'i>(&self, idmaps: impl IntoIterator<Item = &'i IdMap>) -> Vec<(CompactString, u32)> {
+        let users = self
+            .host_users
+            .as_ref()
+            .map(|passwd| &passwd.users[..])
+            .unwrap_or_default();
+        let mut login_users: Vec<_> = users
+            .iter()
+            .filter(|user| user.can_log_in() || Some(user.uid) == self.operator_uid)
+            .map(|user| (user.name.clone(), user.uid))
+            .collect();
+
+        if let Some(uid) = self.operator_uid
+            && !users.iter().any(|user| user.uid == uid)
+        {
+            login_users.push(("whoever runs pupman".into(), uid));
+        }
+
+        let uid_maps: Vec<_> = idmaps
+            .into_iter()
+            .filter(|idmap| idmap.kind == SubID::UID && !idmap.is_passthrough())
+            .collect();
+
+        login_users.retain(|(_, uid)| uid_maps.iter().any(|idmap| idmap.maps_host_id(*uid)));
+        login_users
+    }
+
+    /// The users of /etc/passwd and groups of /etc/group whose id one of `idmaps` maps a container
+    /// id onto, by kind, name and id. Users already reported as login users are left out while that
+    /// check is enabled, and so are passthroughs.
+    pub fn mapped_host_accounts(&self, idmaps: &[IdMap]) -> Vec<(SubID, CompactString, u32)> {
+        let login_users = if self.settings.is_enabled(Check::IdmapLoginUsers) {
+            self.mapped_login_users(idmaps)
+        } else {
+            Vec::new()
+        };
+        let maps = |kind, id| {
+            idmaps
+                .iter()
+                .any(|idmap| idmap.kind == kind && !idmap.is_passthrough() && idmap.maps_host_id(id))
+        };
+        let users = self
+            .host_users
+            .iter()
+            .flat_map(|passwd| &passwd.users)
+            .filter(|user| maps(SubID::UID, user.uid) && !login_users.iter().any(|(name, _)| *name == user.name))
+            .map(|user| (SubID::UID, user.name.clone(), user.uid));
+        let groups = self
+            .host_groups
+            .iter()
+            .filter(|group| maps(SubID::GID, group.gid))
+            .map(|group| (SubID::GID, group.name.clone(), group.gid));
+
+        users.chain(groups).collect()
+    }
+
+    /// Loads the idmaps of /etc/lxc/default.conf and passes them on to the plain LXC configs
+    /// without their own.
+    pub fn load_lxc_defaults(&mut self, path: &Path, content: &str) -> color_eyre::Result<()> {
+        let config = Config::from_str(content)?;
+
+        self.stats.files_parsed += 1;
+        self.default_idmaps = config_idmaps(&config)
+            .into_iter()
+            .map(|idmap| ConfigIdMap {
+                include: Some(path.to_path_buf()),
+                line: None,
+                ..idmap
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    pub fn unload_lxc_defaults(&mut self) {
+        self.default_idmaps.clear();
+    }
+
+    pub fn load_subid(&mut self, content: &str, subid: SubID) -> color_eyre::Result<()> {
+        self.stats.files_parsed += 1;
+        self.missing_subids.retain(|missing| *missing != subid);
+        self.host_mapping.load(subid, content);
+
+        Ok(())
+    }
+
+    /// Forgets the entries of /etc/subuid or /etc/subgid, which doesn't exist (anymore).
+    pub fn unload_subid(&mut self, subid: SubID) {
+        match subid {
+            SubID::UID => {
+                self.host_mapping.subuid.clear();
+                self.host_mapping.subuid_comments.clear();
+                self.host_mapping.subuid_invalid.clear();
+            },
+            SubID::GID => {
+                self.host_mapping.subgid.clear();
+                self.host_mapping.subgid_comments.clear();
+                self.host_mapping.subgid_invalid.clear();
+            },
+        }
+
+        self.shadow_backups.remove(&subid);
+
+        if !self.missing_subids.contains(&subid) {
+            self.missing_subids.push(subid);
+        }
+    }
+
+    pub fn load_shadow_backup(&mut self, sub_id: SubID, backup: Option<ShadowBackup>) {
+        match backup {
+            Some(backup) => self.shadow_backups.insert(sub_id, backup),
+            None => self.shadow_backups.remove(&sub_id),
+        };
+    }
+
+    pub fn load_rootfs_metadata(&mut self, rootfs_value: String, location: RootfsLocation, metadata: Metadata) {
+        self.rootfs_owner_history
+            .entry(rootfs_value.clone())
+            .or_default()
+            .record_owner(metadata.uid(), metadata.gid(), Utc::now());
+        self.rootfs_info.insert(rootfs_value, (location, metadata));
+        self.rootfs_info.sort_unstable_keys();
+    }
+
+    /// Stores what a watched directory was found to be, as either a mount point or a rootfs
+    /// depending on which kind of config value it was looked up for.
+    pub fn load_dir_metadata(&mut self, value: String, location: RootfsLocation, metadata: Metadata) {
+        self.unavailable_dirs.remove(&value);
+
+        if self.is_mount_point(&value) {
+            self.mount_info.insert(value, (location, metadata));
+        } else {
+            self.load_rootfs_metadata(value, location, metadata);
+        }
+    }
+
+    /// The `lxc.include` files the config at `path` pulled in, which are watched along with it.
+    pub fn include_paths(&self, path: &Path) -> Vec<PathBuf> {
+        path.file_name()
+            .and_then(|f| f.to_str())
+            .and_then(|filename| self.lxc_configs.get(filename))
+            .into_iter()
+            .flat_map(|config| config.includes())
+            .map(|include| include.path.clone())
+            .collect()
+    }
+
+    /// Loads configs which were just read again, then returns the rootfs and mount point values of
+    /// every loaded config, so they are resolved again as the configs hold them now.
+    pub fn reload_configs(&mut self, configs: &[(PathBuf, String)]) -> color_eyre::Result<Vec<String>> {
+        for (path, content) in configs {
+            self.mark_loaded(path.clone());
+            self.load_config(path, content)?;
+        }
+
+        Ok(self
+            .lxc_configs
+            .values()
+            .flat_map(|config| {
+                let section = config.section(None);
+
+                section
+                    .get_rootfs()
+                    .map(str::to_owned)
+                    .into_iter()
+                    .chain(section.mount_points().map(|mount| mount.value.to_owned()))
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    /// The `mpN` values of the config at `path`, which are stat-ed and watched like its rootfs.
+    pub fn mount_point_values(&self, path: &Path) -> Vec<String> {
+        path.file_name()
+            .and_then(|f| f.to_str())
+            .and_then(|filename| self.lxc_configs.get(filename))
+            .into_iter()
+            .flat_map(|config| config.section(None).mount_points())
+            .map(|mount| mount.value.to_owned())
+            .collect()
+    }
+
+    pub fn load_ownership_scan(&mut self, rootfs_value: String, scan: OwnershipScan) {
+        self.ownership_scans.insert(rootfs_value, scan);
+    }
+
+    /// The rootfs values of unprivileged containers and where they were found, for a deep scan.
+    pub fn deep_scan_targets(&self) -> Vec<(String, PathBuf)> {
+        self.lxc_configs
+            .values()
+            .map(|config| config.section(None))
+            .filter(|section| self.dialect.is_unprivileged(section, &self.default_idmaps))
+            .filter_map(|section| {
+                let rootfs_value = section.get_rootfs()?;
+                let (location, _) = self.rootfs_info.get(rootfs_value)?;
+
+                Some((rootfs_value.to_owned(), location.mountpoint.clone()))
+            })
+            .collect()
+    }
+
+    fn is_mount_point(&self, value: &str) -> bool {
+        self.lxc_configs
+            .values()
+            .any(|config| config.section(None).mount_points().any(|mount| mount.value == value))
+    }
+}
+
+/// The idmaps `config` ends up with, which are `default_idmaps` when it is a plain LXC config
+/// without any of its own.
+pub(crate) fn effective_idmaps<'c>(config: &'c Config, default_idmaps: &'c [ConfigIdMap]) -> &'c [ConfigIdMap] {
+    let idmaps = config.idmaps();
+
+    if idmaps.is_empty() && config.format() == ConfigFormat::Lxc {
+        default_idmaps
+    } else {
+        idmaps
+    }
+}
+
+/// Whether the subid `entries` together cover the host ids from `start` up to `end`.
+fn subid_ranges_cover<'e>(entries: impl IntoIterator<Item = &'e IdMapEntry>, start: u32, end: u64) -> bool {
+    let mut ranges: Vec<_> = entries
+        .into_iter()
+        .map(|entry| (entry.host_sub_id, range_end(entry.host_sub_id, entry.host_sub_id_count)))
+        .collect();
+    let mut covered = u64::from(start);
+
+    ranges.sort_unstable();
+
+    for (range_start, range_end) in ranges {
+        if u64::from(range_start) > covered {
+            break;
+        }
+
+        covered = covered.max(range_end);
     }
+
+    covered >= end
+}
+
+/// The container a finding is about, if it is about one rather than about the host.
+pub(crate) fn finding_vmid(configs: &IndexMap<CompactString, Config, RandomState>, finding: &Finding) -> Option<u32> {
+    let filename = finding
+        .lxc_config_mapping_highlights
+        .iter()
+        .map(|(filename, _)| filename)
+        .chain(finding.config_line_highlights.iter().map(|line| &line.filename))
+        .next()
+        .or_else(|| {
+            let rootfs = finding.rootfs_highlights.first()?;
+
+            configs
+                .iter()
+                .find(|(_, config)| config.section(None).get_rootfs() == Some(rootfs.as_str()))
+                .map(|(filename, _)| filename)
+        })?;
+
+    filename.strip_suffix(".conf")?.parse().ok()
 }
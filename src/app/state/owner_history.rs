@@ -0,0 +1,168 @@
+//! When the owner of each rootfs changed during the session, next to when its ownership finding
+//! appeared and resolved. Something outside of pupman which keeps resetting the owner shows up as a
+//! repeating pattern.
+
+use chrono::{DateTime, Utc};
+
+use super::State;
+use crate::check::Check;
+use crate::finding::FindingKind;
+
+/// Older events are dropped past this many, a rootfs being chowned in a loop would grow forever.
+const MAX_EVENTS: usize = 256;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OwnerEventKind {
+    /// The rootfs was found owned by `uid:gid`.
+    Owner(u32, u32),
+    FindingAppeared,
+    FindingResolved,
+}
+
+impl OwnerEventKind {
+    /// Which kind is shown when several share a cell of the timeline.
+    fn precedence(self) -> u8 {
+        match self {
+            OwnerEventKind::Owner(..) => 0,
+            OwnerEventKind::FindingResolved => 1,
+            OwnerEventKind::FindingAppeared => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OwnerEvent {
+    pub at: DateTime<Utc>,
+    pub kind: OwnerEventKind,
+}
+
+/// The ownership history of a single rootfs, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct OwnerHistory {
+    pub events: Vec<OwnerEvent>,
+    /// Whether the last evaluation found the rootfs owned by the wrong ids.
+    wrong_owner: bool,
+}
+
+impl OwnerHistory {
+    fn push(&mut self, at: DateTime<Utc>, kind: OwnerEventKind) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.remove(0);
+        }
+
+        self.events.push(OwnerEvent { at, kind });
+    }
+
+    /// Records the owner the poller found, unless it's the same as last time.
+    pub fn record_owner(&mut self, uid: u32, gid: u32, at: DateTime<Utc>) {
+        let last = self.events.iter().rev().find_map(|event| match event.kind {
+            OwnerEventKind::Owner(uid, gid) => Some((uid, gid)),
+            _ => None,
+        });
+
+        if last != Some((uid, gid)) {
+            self.push(at, OwnerEventKind::Owner(uid, gid));
+        }
+    }
+
+    /// Records whether the rootfs ownership finding is there after an evaluation, if that changed.
+    pub fn record_finding(&mut self, wrong_owner: bool, at: DateTime<Utc>) {
+        if wrong_owner == self.wrong_owner {
+            return;
+        }
+
+        self.wrong_owner = wrong_owner;
+        self.push(
+            at,
+            if wrong_owner {
+                OwnerEventKind::FindingAppeared
+            } else {
+                OwnerEventKind::FindingResolved
+            },
+        );
+    }
+
+    /// Owner changes since the owner was first seen.
+    pub fn owner_changes(&self) -> impl Iterator<Item = &OwnerEvent> {
+        self.events
+            .iter()
+            .filter(|event| matches!(event.kind, OwnerEventKind::Owner(..)))
+            .skip(1)
+    }
+
+    /// The events from the first one until `now` spread over `width` cells, each holding the most
+    /// notable of its events. The owner first seen isn't a change, so it is left out.
+    pub fn timeline(&self, width: usize, now: DateTime<Utc>) -> Vec<Option<OwnerEventKind>> {
+        let mut cells = vec![None; width];
+        let Some(start) = self.events.first().map(|event| event.at) else {
+            return cells;
+        };
+        let span = (now - start).num_milliseconds().max(1);
+        let first_owner = self
+            .events
+            .iter()
+            .position(|event| matches!(event.kind, OwnerEventKind::Owner(..)));
+
+        for (i, event) in self.events.iter().enumerate() {
+            if Some(i) == first_owner || width == 0 {
+                continue;
+            }
+
+            let offset = (event.at - start).num_milliseconds().clamp(0, span);
+            let cell = &mut cells[(offset * (width as i64 - 1) / span) as usize];
+
+            if cell.is_none_or(|kind: OwnerEventKind| kind.precedence() <= event.kind.precedence()) {
+                *cell = Some(event.kind);
+            }
+        }
+
+        cells
+    }
+}
+
+impl State {
+    /// Notes for each rootfs whether its ownership finding came or went with the last evaluation.
+    pub(super) fn record_owner_findings(&mut self, at: DateTime<Utc>) {
+        for (value, history) in &mut self.rootfs_owner_history {
+            let wrong_owner = self.findings.iter().any(|finding| {
+                finding.check == Check::RootfsOwnership
+                    && finding.kind != FindingKind::Good
+                    && finding.rootfs_highlights.contains(value)
+            });
+
+            history.record_finding(wrong_owner, at);
+        }
+    }
+}
+
+#[test]
+fn test_owner_history() {
+    use chrono::TimeDelta;
+
+    let start = Utc::now();
+    let at = |secs| start + TimeDelta::seconds(secs);
+    let mut history = OwnerHistory::default();
+
+    history.record_owner(100000, 100000, at(0));
+    history.record_finding(false, at(0));
+    // Polled again without a change
+    history.record_owner(100000, 100000, at(5));
+    history.record_owner(0, 0, at(10));
+    history.record_finding(true, at(10));
+    history.record_owner(100000, 100000, at(20));
+    history.record_finding(false, at(20));
+
+    assert_eq!(history.events.len(), 5);
+    assert_eq!(history.owner_changes().count(), 2);
+    assert_eq!(
+        history.timeline(5, at(20)),
+        [
+            None,
+            None,
+            Some(OwnerEventKind::FindingAppeared),
+            None,
+            Some(OwnerEventKind::FindingResolved),
+        ]
+    );
+    assert_eq!(OwnerHistory::default().timeline(3, at(0)), [None; 3]);
+}
@@ -0,0 +1,68 @@
+//! Previewing file writes as diffs, so nothing is written before the user has seen the change.
+
+use super::acl::AclPlan;
+use super::conversion::ConversionStep;
+use super::shared_volume::SharedVolumePlan;
+use super::wizard::GeneratedMapping;
+use crate::fix::Fix;
+use crate::fs::subid::SubID;
+use crate::fs::writer::PendingWrite;
+
+/// What to do once the previewed writes are confirmed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PreviewAction {
+    Fix(Fix),
+    /// The fixes of the marked findings, applied as one.
+    Fixes(Vec<Fix>),
+    /// Edits of the given files from the host mapping editor.
+    SubidEdits(Vec<SubID>),
+    IdmapEdits,
+    GeneratedMapping(GeneratedMapping),
+    /// ACLs on a shared directory, which writes no files, only runs the commands in the notes.
+    Acl(AclPlan),
+    /// Idmap and subgid writes for sharing a directory, followed by the plan's commands.
+    SharedVolume(SharedVolumePlan),
+    /// A step of the conversion assistant, which stays open for the next one.
+    Conversion(ConversionStep),
+    /// Every queued change, applied as one.
+    Transaction,
+}
+
+impl PreviewAction {
+    /// Whether the changes can be queued to be applied along with others. Commands, like `usermod`
+    /// or `setfacl`, are queued along with the writes and run once every file is written. Only
+    /// the queue itself can't be queued again.
+    pub fn queueable(&self) -> bool {
+        !matches!(self, PreviewAction::Transaction)
+    }
+}
+
+#[derive(Debug)]
+pub struct WritePreview {
+    pub title: &'static str,
+    pub action: PreviewAction,
+    pub writes: Vec<PendingWrite>,
+    /// Shown above the diffs, for anything the diffs don't tell.
+    pub notes: Vec<String>,
+    pub scroll: usize,
+}
+
+impl WritePreview {
+    pub fn new(title: &'static str, action: PreviewAction, writes: Vec<PendingWrite>) -> Self {
+        Self {
+            title,
+            action,
+            writes,
+            notes: Vec::new(),
+            scroll: 0,
+        }
+    }
+
+    /// The diffs of every write, one after another.
+    pub fn diff_lines(&self) -> Vec<String> {
+        self.writes
+            .iter()
+            .flat_map(|write| write.diff().lines().map(str::to_string).collect::<Vec<_>>())
+            .collect()
+    }
+}
@@ -0,0 +1,95 @@
+//! A focused audit of a container which was just created or restored, so problems with its first
+//! unprivileged start show up before it is started rather than among every other finding.
+
+use std::collections::HashSet;
+
+use super::State;
+use crate::finding::{Finding, FindingKind};
+
+/// What stands in the way of a container's first start.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Readiness {
+    pub vmid: String,
+    /// The messages of the findings which would make the start fail.
+    pub blockers: Vec<&'static str>,
+}
+
+impl Readiness {
+    /// A one line summary for a notification.
+    pub fn summary(&self) -> String {
+        const SHOWN: usize = 3;
+
+        let vmid = &self.vmid;
+
+        match &self.blockers[..] {
+            [] => format!("Container {vmid} looks ready for its first start as unprivileged"),
+            blockers => {
+                let mut summary = format!(
+                    "Container {vmid} will likely fail its first start: {}",
+                    blockers[..blockers.len().min(SHOWN)].join("; ")
+                );
+
+                if blockers.len() > SHOWN {
+                    summary.push_str(&format!(" and {} more", blockers.len() - SHOWN));
+                }
+
+                summary
+            },
+        }
+    }
+}
+
+impl State {
+    /// Audits the container of config `filename`. `None` until everything the audit depends on is
+    /// loaded, and for containers which aren't unprivileged.
+    pub fn readiness(&self, filename: &str) -> Option<Readiness> {
+        let config = self.lxc_configs.get(filename)?;
+        let section = config.section(None);
+
+        if !self.dialect.is_unprivileged(&section, &self.default_idmaps) {
+            return None;
+        }
+
+        let rootfs = section.get_rootfs();
+
+        let unavailable = rootfs.is_some_and(|rootfs| self.unavailable_dirs.contains_key(rootfs));
+
+        // The rootfs is stat-ed in the background, its ownership findings only exist once it's done
+        if self.inspects_rootfs()
+            && let Some(rootfs) = rootfs
+            && !self.rootfs_info.contains_key(rootfs)
+            && !unavailable
+        {
+            return None;
+        }
+
+        let concerns = |finding: &Finding| {
+            finding
+                .lxc_config_mapping_highlights
+                .iter()
+                .map(|(f, _)| f)
+                .chain(finding.config_line_highlights.iter().map(|line| &line.filename))
+                .any(|f| f == filename)
+                || rootfs.is_some_and(|rootfs| finding.rootfs_highlights.iter().any(|r| r == rootfs))
+        };
+
+        let mut seen = HashSet::new();
+        let mut blockers: Vec<_> = self
+            .findings
+            .iter()
+            .filter(|finding| finding.kind == FindingKind::Bad && concerns(finding))
+            .map(|finding| finding.message)
+            .filter(|message| seen.insert(*message))
+            .collect();
+
+        // Nothing about a missing rootfs can be checked, but the start would fail on it all the same
+        if unavailable && self.inspects_rootfs() {
+            blockers.insert(0, "Its rootfs can't be found on the host");
+        }
+
+        Some(Readiness {
+            vmid: filename.strip_suffix(".conf").unwrap_or(filename).to_string(),
+            blockers,
+        })
+    }
+}
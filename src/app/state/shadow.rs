@@ -0,0 +1,112 @@
+//! Tells hand-written /etc/subuid and /etc/subgid entries apart from the ones shadow-utils' `useradd`
+//! and `usermod --add-subuids` wrote, which later `usermod` runs may rewrite.
+
+use crate::app::ui::IdMapEntry;
+use crate::fs::subid::{ShadowBackup, SubID};
+
+/// Why an entry looks hand-written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ManualHint {
+    /// `useradd` hands out ranges in ascending order, so a lower range listed later was added by hand.
+    OutOfOrder,
+    /// The file was changed after shadow-utils last wrote it, and this entry isn't in its backup.
+    AddedSinceBackup,
+}
+
+impl ManualHint {
+    pub fn reason(self) -> &'static str {
+        match self {
+            ManualHint::OutOfOrder => "it is listed after a higher range, useradd allocates in ascending order",
+            ManualHint::AddedSinceBackup => "it was added after shadow-utils last wrote the file",
+        }
+    }
+}
+
+/// The entries which look hand-written, with the first reason found for each.
+///
+/// shadow-utils writes back the lines it doesn't change as they were, so numeric owners and unusual
+/// formatting alone don't put an entry at risk. Single id entries such as `root:1000:1` are how PVE
+/// documents passing a host id through, and are left alone too.
+pub fn manual_entries<'e>(
+    entries: &'e [IdMapEntry],
+    backup: Option<&ShadowBackup>,
+) -> Vec<(&'e IdMapEntry, ManualHint)> {
+    let mut highest_start = 0;
+    let mut manual = Vec::new();
+
+    for entry in entries.iter().filter(|entry| entry.host_sub_id_count > 1) {
+        let canonical = format!(
+            "{}:{}:{}",
+            entry.host_user_id, entry.host_sub_id, entry.host_sub_id_count
+        );
+        let hint = if entry.host_sub_id < highest_start {
+            Some(ManualHint::OutOfOrder)
+        } else if let Some(backup) = backup
+            && backup.edited_since
+            && !backup.entries.contains(&canonical)
+        {
+            Some(ManualHint::AddedSinceBackup)
+        } else {
+            None
+        };
+
+        highest_start = highest_start.max(entry.host_sub_id);
+
+        if let Some(hint) = hint {
+            manual.push((entry, hint));
+        }
+    }
+
+    manual
+}
+
+/// The `usermod` command which delegates `entry`'s range to its owner.
+pub fn usermod_command(sub_id: SubID, entry: &IdMapEntry) -> String {
+    format!(
+        "usermod --add-sub{}s {}-{} {}",
+        sub_id.kind_name(),
+        entry.host_sub_id,
+        u64::from(entry.host_sub_id) + u64::from(entry.host_sub_id_count).saturating_sub(1),
+        entry.host_user_id
+    )
+}
+
+#[test]
+fn test_manual_entries() {
+    let entry = |owner: &str, start, count| IdMapEntry {
+        host_user_id: owner.into(),
+        host_sub_id: start,
+        host_sub_id_count: count,
+        ..IdMapEntry::default()
+    };
+    let entries = [
+        entry("root", 100000, 65536),
+        entry("alice", 165536, 65536),
+        entry("root", 1000, 1),
+        entry("1001", 231072, 65536),
+        entry("bob", 20000, 10000),
+        entry("carol", 296608, 65536),
+    ];
+
+    let hints: Vec<_> = manual_entries(&entries, None)
+        .into_iter()
+        .map(|(entry, hint)| (entry.host_user_id.as_str(), hint))
+        .collect();
+
+    assert_eq!(hints, [("bob", ManualHint::OutOfOrder)]);
+
+    let backup = ShadowBackup {
+        entries: vec!["root:100000:65536".into(), "alice:165536:65536".into()],
+        edited_since: true,
+    };
+
+    assert_eq!(manual_entries(&entries[..3], Some(&backup)), []);
+    assert_eq!(
+        manual_entries(&entries[5..], Some(&backup)),
+        [(&entries[5], ManualHint::AddedSinceBackup)]
+    );
+    assert_eq!(
+        usermod_command(SubID::UID, &entries[4]),
+        "usermod --add-subuids 20000-29999 bob"
+    );
+}
@@ -0,0 +1,538 @@
+//! Advising on a host directory shared by several unprivileged containers: which host ids their
+//! service users end up as, and one set of changes letting all of them write to it.
+
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use compact_str::CompactString;
+
+use super::State;
+use super::acl::{AclGrant, AclPlan};
+use super::wizard::{CONTAINER_IDS, FIRST_OFFSET, generated_idmaps};
+use crate::finding::Finding;
+use crate::followup::Change;
+use crate::fs::subid::SubID;
+use crate::linux::passwd::{ETC_GROUP, ETC_PASSWD, Group, User, parse_group, parse_passwd};
+use crate::linux::{chgrp_recursive, chmod_recursive, groupadd, setgid_dirs};
+use crate::lxc::idmap::IdMap;
+
+/// Where the common group's id is picked from, above the ids distributions hand out to users.
+const FIRST_SHARED_GID: u32 = 10000;
+/// The longest group name `groupadd` takes.
+const GROUP_NAME_MAX: usize = 32;
+
+/// The users and groups of a container, as read from its rootfs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContainerAccounts {
+    pub users: Vec<User>,
+    pub groups: Vec<Group>,
+}
+
+impl ContainerAccounts {
+    /// Reads /etc/passwd and /etc/group below `rootfs`. `None` when the passwd file can't be read,
+    /// which it can't while a volume based rootfs isn't mounted.
+    pub fn read(rootfs: &Path) -> Option<Self> {
+        let relative = |path: &str| rootfs.join(path.trim_start_matches('/'));
+        let users = parse_passwd(&read_to_string(relative(ETC_PASSWD)).ok()?).users;
+        let groups = read_to_string(relative(ETC_GROUP))
+            .map(|content| parse_group(&content))
+            .unwrap_or_default();
+
+        Some(Self { users, groups })
+    }
+}
+
+/// A user services in a container may run as, and the host ids its files end up owned by.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ServiceUser {
+    pub name: CompactString,
+    pub uid: u32,
+    pub gid: u32,
+    /// `None` when the container's idmaps leave the id unmapped.
+    pub host_uid: Option<u32>,
+    pub host_gid: Option<u32>,
+}
+
+/// One of the containers mounting the shared directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharingContainer {
+    pub filename: CompactString,
+    pub users: Vec<ServiceUser>,
+    /// Whether the users were read from the rootfs, rather than only root being assumed.
+    pub accounts_read: bool,
+    /// The `lxc.idmap` values passing the common group through, or `None` when the container
+    /// already does or the group strategy isn't used.
+    pub idmaps: Option<Vec<String>>,
+}
+
+impl SharingContainer {
+    pub fn vmid(&self) -> &str {
+        self.filename.trim_end_matches(".conf")
+    }
+}
+
+/// How the containers get access to the directory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShareStrategy {
+    /// Every container maps group `gid` onto the same host gid, which owns the directory and is
+    /// inherited by what's created in it.
+    Group { gid: u32, name: String },
+    /// Containers whose idmaps can't take a common group get ACL entries for their users instead.
+    Acl(AclPlan),
+}
+
+/// Everything needed to share a host directory between containers, reviewed as a whole before any
+/// of it is applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedVolumePlan {
+    pub path: PathBuf,
+    pub containers: Vec<SharingContainer>,
+    pub strategy: ShareStrategy,
+    /// The `root:gid:1` entry /etc/subgid is missing for the common group to be mapped.
+    pub subgid_entry: Option<String>,
+    /// Why a common group couldn't be used, when the plan falls back to ACLs.
+    pub fallback_reason: Option<String>,
+}
+
+impl SharedVolumePlan {
+    /// What sharing the directory changes, for the follow-up checklist.
+    pub fn changes(&self) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        if self.subgid_entry.is_some() {
+            changes.push(Change::SubidRangesAdded(SubID::GID));
+        }
+
+        changes.extend(
+            self.containers
+                .iter()
+                .filter(|container| container.idmaps.is_some())
+                .map(|container| Change::ConfigEdited {
+                    vmid: container.vmid().to_string(),
+                }),
+        );
+
+        if let ShareStrategy::Group { gid, name } = &self.strategy {
+            changes.extend(self.containers.iter().map(|container| {
+                Change::SharedGroupMapped {
+                    vmid: container.vmid().to_string(),
+                    gid: *gid,
+                    group: name.clone(),
+                    users: container
+                        .users
+                        .iter()
+                        .filter(|user| user.uid != 0)
+                        .map(|user| user.name.to_string())
+                        .collect(),
+                }
+            }));
+        }
+
+        changes
+    }
+
+    /// The commands run on the host once the configs are written, for previewing.
+    pub fn commands(&self) -> Vec<String> {
+        let path = self.path.display();
+
+        match &self.strategy {
+            ShareStrategy::Group { gid, name } => vec![
+                format!("groupadd -g {gid} {name}"),
+                format!("chgrp -R {gid} {path}"),
+                format!("chmod -R g+rwX {path}"),
+                format!("find {path} -type d -exec chmod g+s {{}} +"),
+            ],
+            ShareStrategy::Acl(plan) => plan.commands().into(),
+        }
+    }
+
+    /// Runs the [`SharedVolumePlan::commands`].
+    pub fn apply(&self) -> color_eyre::Result<()> {
+        match &self.strategy {
+            ShareStrategy::Group { gid, name } => {
+                groupadd(name, *gid)?;
+                chgrp_recursive(*gid, &self.path)?;
+                chmod_recursive("g+rwX", &self.path)?;
+                setgid_dirs(&self.path)?;
+
+                Ok(())
+            },
+            ShareStrategy::Acl(plan) => plan.apply(),
+        }
+    }
+
+    /// A line per container listing the host ids its users end up as, for the preview.
+    pub fn summary(&self) -> Vec<String> {
+        self.containers
+            .iter()
+            .map(|container| {
+                let id = |id: Option<u32>| id.map_or_else(|| "unmapped".to_string(), |id| id.to_string());
+                let users: Vec<_> = container
+                    .users
+                    .iter()
+                    .map(|user| format!("{} as {}:{}", user.name, id(user.host_uid), id(user.host_gid)))
+                    .collect();
+                let unread = if container.accounts_read {
+                    ""
+                } else {
+                    " (its /etc/passwd couldn't be read)"
+                };
+
+                format!("Container {}: {}{unread}", container.vmid(), users.join(", "))
+            })
+            .collect()
+    }
+}
+
+impl State {
+    /// Where the rootfs of a container is found on the host, if it was resolved.
+    pub fn rootfs_path(&self, filename: &str) -> Option<&Path> {
+        let value = self.lxc_configs.get(filename)?.section(None).get_rootfs()?.to_string();
+
+        self.rootfs_info
+            .get(&value)
+            .map(|(location, _)| location.mountpoint.as_path())
+    }
+
+    /// Plans sharing the host directory of the mount point `finding` is about between every
+    /// unprivileged container mounting it. A common group is mapped into each of them where their
+    /// idmaps allow it, otherwise each of their service users is granted access through ACLs.
+    /// `accounts` gives the users of a container by its config's filename.
+    pub fn shared_volume_plan(
+        &self,
+        finding: &Finding,
+        accounts: impl Fn(&str) -> Option<ContainerAccounts>,
+    ) -> color_eyre::Result<SharedVolumePlan> {
+        let (path, filenames) = self.shared_mount(finding)?;
+        let accounts: Vec<_> = filenames.iter().map(|filename| accounts(filename)).collect();
+        let gid = self.free_shared_gid(&accounts);
+        let mut containers = Vec::new();
+        let mut fallback_reason = None;
+
+        for (filename, accounts) in filenames.into_iter().zip(accounts) {
+            let idmaps = self.container_idmaps(&filename);
+            let users = service_users(accounts.as_ref())
+                .map(|(name, uid, gid)| ServiceUser {
+                    name,
+                    uid,
+                    gid,
+                    host_uid: host_id(&idmaps, SubID::UID, uid),
+                    host_gid: host_id(&idmaps, SubID::GID, gid),
+                })
+                .collect();
+            let passed_through = host_id(&idmaps, SubID::GID, gid) == Some(gid);
+            let rewritten = if passed_through {
+                None
+            } else {
+                match self.passthrough_idmaps(&filename, &idmaps, gid) {
+                    Ok(values) => Some(values),
+                    Err(reason) => {
+                        fallback_reason
+                            .get_or_insert(format!("container {}: {reason}", filename.trim_end_matches(".conf")));
+                        None
+                    },
+                }
+            };
+
+            containers.push(SharingContainer {
+                filename,
+                users,
+                accounts_read: accounts.is_some(),
+                idmaps: rewritten,
+            });
+        }
+
+        if fallback_reason.is_some() {
+            let grants = containers
+                .iter_mut()
+                .flat_map(|container| {
+                    container.idmaps = None;
+                    container.users.iter().map(|user| AclGrant {
+                        filename: container.filename.clone(),
+                        uid: user.host_uid,
+                        gid: user.host_gid,
+                    })
+                })
+                .collect();
+
+            return Ok(SharedVolumePlan {
+                strategy: ShareStrategy::Acl(AclPlan {
+                    path: path.clone(),
+                    grants,
+                }),
+                path,
+                containers,
+                subgid_entry: None,
+                fallback_reason,
+            });
+        }
+
+        let subid_delegated = self.host_mapping.subgid.iter().any(|entry| {
+            matches!(&*entry.host_user_id, "root" | "0")
+                && entry.host_sub_id <= gid
+                && u64::from(gid) < u64::from(entry.host_sub_id) + u64::from(entry.host_sub_id_count)
+        });
+
+        Ok(SharedVolumePlan {
+            strategy: ShareStrategy::Group {
+                gid,
+                name: self.free_group_name(&path, gid),
+            },
+            subgid_entry: (!subid_delegated).then(|| format!("root:{gid}:1")),
+            path,
+            containers,
+            fallback_reason: None,
+        })
+    }
+
+    /// The idmaps of a container as parsed, or what PVE maps for an unprivileged container without
+    /// any.
+    fn container_idmaps(&self, filename: &str) -> Vec<IdMap> {
+        let idmaps: Vec<_> = self
+            .idmaps(filename)
+            .iter()
+            .filter_map(|idmap| idmap.parsed.as_ref().ok().copied())
+            .collect();
+
+        if !idmaps.is_empty() {
+            return idmaps;
+        }
+
+        [SubID::UID, SubID::GID]
+            .into_iter()
+            .map(|kind| IdMap {
+                kind,
+                container_id: 0,
+                host_id: FIRST_OFFSET,
+                size: CONTAINER_IDS,
+            })
+            .collect()
+    }
+
+    /// The `lxc.idmap` values of a container with `gid` passed through to the host, its uids kept
+    /// as they are. Only a single gid idmap can be split up like this.
+    fn passthrough_idmaps(&self, filename: &str, idmaps: &[IdMap], gid: u32) -> Result<Vec<String>, String> {
+        let own = self.idmaps(filename);
+
+        if own.iter().any(|idmap| idmap.include.is_some()) {
+            return Err("its idmaps come from an lxc.include file".to_string());
+        }
+
+        if own.iter().any(|idmap| idmap.parsed.is_err()) {
+            return Err("some of its idmaps are malformed".to_string());
+        }
+
+        let gid_maps: Vec<_> = idmaps.iter().filter(|idmap| idmap.kind == SubID::GID).collect();
+        let [
+            &IdMap {
+                container_id: 0,
+                host_id: offset,
+                size: CONTAINER_IDS,
+                ..
+            },
+        ] = gid_maps[..]
+        else {
+            return Err("its gid idmaps are already split up, which only the idmap editor can change".to_string());
+        };
+
+        Ok(idmaps
+            .iter()
+            .filter(|idmap| idmap.kind == SubID::UID)
+            .copied()
+            .chain(generated_idmaps(SubID::GID, offset, Some(gid)))
+            .map(|idmap| idmap.to_string())
+            .collect())
+    }
+
+    /// The lowest gid from [`FIRST_SHARED_GID`] on which no group or user on the host, or in the
+    /// containers, uses yet.
+    fn free_shared_gid(&self, accounts: &[Option<ContainerAccounts>]) -> u32 {
+        let host_users = self.host_users.as_ref().map_or(&[][..], |passwd| &passwd.users[..]);
+        let taken = |gid: u32| {
+            self.host_groups.iter().any(|group| group.gid == gid)
+                || host_users.iter().any(|user| user.gid == gid)
+                || accounts.iter().flatten().any(|accounts| {
+                    accounts.groups.iter().any(|group| group.gid == gid)
+                        || accounts.users.iter().any(|user| user.gid == gid)
+                })
+        };
+
+        (FIRST_SHARED_GID..CONTAINER_IDS)
+            .find(|&gid| !taken(gid))
+            .unwrap_or(FIRST_SHARED_GID)
+    }
+
+    /// The name of the common group for `path`, with `gid` appended when a host group of that name
+    /// exists already.
+    fn free_group_name(&self, path: &Path, gid: u32) -> String {
+        let name = group_name(path);
+
+        if self.host_groups.iter().any(|group| group.name == name) {
+            let suffix = format!("-{gid}");
+            let mut name = name;
+
+            name.truncate(GROUP_NAME_MAX - suffix.len());
+            name + &suffix
+        } else {
+            name
+        }
+    }
+}
+
+/// Root, which every service may run as, and the users from uid 100 on, which services and people
+/// get. The accounts below are the distribution's own, and `nobody` owns nothing. Only root when
+/// the accounts weren't read.
+fn service_users(accounts: Option<&ContainerAccounts>) -> impl Iterator<Item = (CompactString, u32, u32)> + '_ {
+    let users = accounts.map_or(&[][..], |accounts| &accounts.users[..]);
+    let root = users
+        .iter()
+        .all(|user| user.uid != 0)
+        .then(|| (CompactString::const_new("root"), 0, 0));
+
+    root.into_iter().chain(
+        users
+            .iter()
+            .filter(|user| user.uid == 0 || (100..65534).contains(&user.uid))
+            .map(|user| (user.name.clone(), user.uid, user.gid)),
+    )
+}
+
+/// The host id container id `id` of `kind` maps to.
+fn host_id(idmaps: &[IdMap], kind: SubID, id: u32) -> Option<u32> {
+    idmaps
+        .iter()
+        .find(|idmap| idmap.kind == kind && idmap.container_id <= id && u64::from(id) < idmap.container_end())
+        .map(|idmap| idmap.host_id + (id - idmap.container_id))
+}
+
+/// A name for the common group after the directory, as `groupadd` takes it.
+fn group_name(path: &Path) -> String {
+    let dirname: String = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let mut name = format!("share-{}", dirname.trim_matches('-'));
+
+    name.truncate(GROUP_NAME_MAX);
+    name.trim_end_matches('-').to_string()
+}
+
+#[test]
+fn test_shared_volume_plan() -> color_eyre::Result<()> {
+    use crate::check::Check;
+    use crate::lxc::resolve_rootfs;
+    use crate::proxmox::storage::StorageConfig;
+
+    let dir = tempfile::tempdir()?;
+    let shared = dir.path().display();
+    let mut state = State::default();
+
+    // PVE's default idmaps, and a custom offset
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!("unprivileged: 1\nmp0: {shared},mp=/data\n"),
+    )?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        &format!("unprivileged: 1\nlxc.idmap: u 0 200000 65536\nlxc.idmap: g 0 200000 65536\nmp0: {shared},mp=/srv\n"),
+    )?;
+    state.load_group("root:x:0:\nmedia:x:10000:\n");
+
+    for value in [format!("{shared},mp=/data"), format!("{shared},mp=/srv")] {
+        let location = resolve_rootfs(&value, &StorageConfig::default())?;
+
+        state.load_dir_metadata(value, location, std::fs::metadata(dir.path())?);
+    }
+
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::MountOwnership)
+        .expect("finding")
+        .clone();
+    let accounts = |filename: &str| {
+        (filename == "100.conf").then(|| ContainerAccounts {
+            users: parse_passwd(
+                "root:x:0:0::/root:/bin/bash\nwww-data:x:33:33::/var/www:/usr/sbin/nologin\njellyfin:x:105:110::/var/\
+                 lib/jellyfin:/bin/false\nnobody:x:65534:65534::/nonexistent:/usr/sbin/nologin\n",
+            )
+            .users,
+            groups: parse_group("jellyfin:x:110:\n"),
+        })
+    };
+    let plan = state.shared_volume_plan(&finding, accounts)?;
+    let name = group_name(dir.path());
+
+    // 10000 is taken on the host
+    assert_eq!(
+        plan.strategy,
+        ShareStrategy::Group {
+            gid: 10001,
+            name: name.clone()
+        }
+    );
+    assert_eq!(plan.subgid_entry.as_deref(), Some("root:10001:1"));
+    assert_eq!(
+        plan.summary(),
+        [
+            "Container 100: root as 100000:100000, jellyfin as 100105:100110",
+            "Container 101: root as 200000:200000 (its /etc/passwd couldn't be read)",
+        ]
+    );
+    assert_eq!(
+        plan.containers[0].idmaps.as_deref(),
+        Some(
+            &[
+                "u 0 100000 65536",
+                "g 0 100000 10001",
+                "g 10001 10001 1",
+                "g 10002 110002 55534"
+            ]
+            .map(String::from)[..]
+        )
+    );
+    assert_eq!(plan.commands()[0], format!("groupadd -g 10001 {name}"));
+
+    // A container with its gids split up already can only be given ACLs
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        &format!(
+            "unprivileged: 1\nlxc.idmap: u 0 200000 65536\nlxc.idmap: g 0 200000 1000\nlxc.idmap: g 1000 1000 \
+             1\nlxc.idmap: g 1001 201001 64535\nmp0: {shared},mp=/srv\n"
+        ),
+    )?;
+
+    let plan = state.shared_volume_plan(&finding, accounts)?;
+
+    assert!(
+        plan.fallback_reason
+            .is_some_and(|reason| reason.starts_with("container 101"))
+    );
+    assert!(plan.containers.iter().all(|container| container.idmaps.is_none()));
+
+    let ShareStrategy::Acl(acl) = plan.strategy else {
+        panic!("expected ACLs");
+    };
+
+    assert_eq!(
+        acl.entries(),
+        "u:100000:rwX,g:100000:rwX,u:100105:rwX,g:100110:rwX,u:200000:rwX,g:200000:rwX"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_group_name() {
+    assert_eq!(group_name(Path::new("/tank/Media Library")), "share-media-library");
+    assert_eq!(group_name(Path::new("/")), "share");
+    assert_eq!(
+        group_name(Path::new("/srv/a-very-long-directory-name-indeed")).len(),
+        GROUP_NAME_MAX
+    );
+}
@@ -0,0 +1,181 @@
+//! The guided ownership shift of a rootfs, for moving a container's files along with its idmaps.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use color_eyre::eyre::eyre;
+use compact_str::CompactString;
+
+use super::State;
+use super::wizard::CONTAINER_IDS;
+use crate::check::Check;
+use crate::fs::shift::{IdShift, ShiftJob};
+use crate::fs::subid::SubID;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShiftField {
+    From,
+    To,
+    Count,
+}
+
+impl ShiftField {
+    const ALL: [ShiftField; 3] = [ShiftField::From, ShiftField::To, ShiftField::Count];
+
+    /// The field `delta` fields after this one, wrapping around.
+    pub fn cycle(self, delta: isize) -> Self {
+        let i = Self::ALL.iter().position(|field| *field == self).unwrap_or_default();
+
+        Self::ALL[(i as isize + delta).rem_euclid(Self::ALL.len() as isize) as usize]
+    }
+}
+
+/// The ownership shift dialog of a container's rootfs.
+#[derive(Debug)]
+pub struct OwnershipShift {
+    pub filename: CompactString,
+    /// Where the rootfs was found.
+    pub path: PathBuf,
+    pub field: ShiftField,
+    /// The old idmap base, as typed.
+    pub from: String,
+    /// The new idmap base, as typed.
+    pub to: String,
+    /// How many ids to move, as typed.
+    pub count: String,
+    /// The last shift or dry run started from the dialog.
+    pub job: Option<Arc<ShiftJob>>,
+}
+
+impl OwnershipShift {
+    pub fn input(&mut self) -> &mut String {
+        match self.field {
+            ShiftField::From => &mut self.from,
+            ShiftField::To => &mut self.to,
+            ShiftField::Count => &mut self.count,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.job.as_ref().is_some_and(|job| job.is_running())
+    }
+
+    /// The shift as typed, if it moves any ids.
+    pub fn id_shift(&self) -> color_eyre::Result<IdShift> {
+        let parse = |name, text: &str| {
+            text.parse::<u32>()
+                .map_err(|_| eyre!("The {name} has to be a number, not {text:?}"))
+        };
+        let shift = IdShift {
+            from: parse("old base", &self.from)?,
+            to: parse("new base", &self.to)?,
+            count: parse("count", &self.count)?,
+        };
+
+        if shift.count == 0 || shift.from == shift.to {
+            return Err(eyre!("The shift wouldn't move any ids"));
+        }
+
+        if shift.from.checked_add(shift.count).is_none() || shift.to.checked_add(shift.count).is_none() {
+            return Err(eyre!("The shifted range runs past the largest id"));
+        }
+
+        Ok(shift)
+    }
+}
+
+impl State {
+    /// Opens the ownership shift dialog for the rootfs of the selected [`Check::RootfsOwnership`]
+    /// finding, suggesting to shift from its current owner to where the idmap puts root.
+    pub fn open_ownership_shift(&mut self) -> color_eyre::Result<()> {
+        let finding = self
+            .selected_finding
+            .and_then(|index| self.findings.get(index))
+            .filter(|finding| finding.check == Check::RootfsOwnership)
+            .ok_or_else(|| eyre!("Only a rootfs with the wrong owner can be shifted"))?;
+        let (filename, sub_id) = finding
+            .lxc_config_mapping_highlights
+            .first()
+            .cloned()
+            .ok_or_else(|| eyre!("The finding isn't about a container"))?;
+        let (location, metadata) = finding
+            .rootfs_highlights
+            .first()
+            .and_then(|value| self.rootfs_info.get(value))
+            .ok_or_else(|| eyre!("The rootfs wasn't found"))?;
+        let root_idmap = self
+            .idmaps(&filename)
+            .iter()
+            .filter_map(|idmap| idmap.parsed.as_ref().ok())
+            .find(|parsed| parsed.kind == sub_id && parsed.container_id == 0)
+            .ok_or_else(|| eyre!("{filename} has no idmap for its root"))?;
+        let owner = match sub_id {
+            SubID::UID => metadata.uid(),
+            SubID::GID => metadata.gid(),
+        };
+
+        self.ownership_shift = Some(OwnershipShift {
+            filename,
+            path: location.mountpoint.clone(),
+            field: ShiftField::From,
+            from: owner.to_string(),
+            to: root_idmap.host_id.to_string(),
+            count: root_idmap.size.min(CONTAINER_IDS).to_string(),
+            job: None,
+        });
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_open_ownership_shift() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    use crate::lxc::resolve_rootfs;
+    use crate::proxmox::storage::StorageConfig;
+
+    let dir = tempfile::tempdir()?;
+    let value = dir.path().display().to_string();
+    let metadata = std::fs::metadata(dir.path())?;
+    let mut state = State::default();
+
+    // The idmap moved on, the rootfs didn't
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!(
+            "unprivileged: 1\nrootfs: {value}\nlxc.idmap: u 0 4000000000 65536\nlxc.idmap: g 0 4000000000 65536\n"
+        ),
+    )?;
+    state.load_rootfs_metadata(
+        value.clone(),
+        resolve_rootfs(&value, &StorageConfig::default())?,
+        metadata.clone(),
+    );
+    state.evaluate_findings();
+
+    assert!(state.open_ownership_shift().is_err());
+
+    state.selected_finding = state.findings.iter().position(|f| f.check == Check::RootfsOwnership);
+    state.open_ownership_shift()?;
+
+    let shift = state.ownership_shift.as_mut().expect("dialog");
+
+    assert_eq!(shift.path, dir.path());
+    assert_eq!(
+        shift.id_shift()?,
+        IdShift {
+            from: metadata.uid(),
+            to: 4000000000,
+            count: 65536,
+        }
+    );
+
+    shift.field = ShiftField::From.cycle(-1);
+    shift.input().clear();
+
+    assert!(shift.id_shift().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Checks which couldn't run for a container, so a container without findings can be told apart from
+//! one pupman couldn't look at.
+
+use compact_str::CompactString;
+
+use super::State;
+use crate::check::Check;
+use crate::lxc::section::SectionView;
+
+/// Why a check couldn't run for a container.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+    /// Rootfs directories aren't looked at with `--root-prefix` or `--no-rootfs-checks`.
+    RootfsChecksUnavailable,
+    /// Inspecting rootfs directories is turned off in the settings.
+    RootfsChecksOff,
+    /// pupman doesn't run as root, so the directory couldn't be looked at.
+    NotRoot,
+    /// The rootfs wasn't found on this host, like when the container's storage isn't mounted.
+    RootfsNotFound,
+    /// A mount point's host directory wasn't found on this host.
+    MountNotFound,
+    /// The rootfs is on a ZFS dataset `zfs` didn't report on.
+    ZfsUnavailable,
+    /// No deep scan of the rootfs ran yet.
+    NotScanned,
+}
+
+impl SkipReason {
+    /// A stable identifier, used in exports.
+    pub fn id(self) -> &'static str {
+        match self {
+            SkipReason::RootfsChecksUnavailable => "rootfs-checks-unavailable",
+            SkipReason::RootfsChecksOff => "rootfs-checks-off",
+            SkipReason::NotRoot => "not-root",
+            SkipReason::RootfsNotFound => "rootfs-not-found",
+            SkipReason::MountNotFound => "mount-not-found",
+            SkipReason::ZfsUnavailable => "zfs-unavailable",
+            SkipReason::NotScanned => "not-scanned",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            SkipReason::RootfsChecksUnavailable => {
+                "rootfs directories aren't looked at with --root-prefix or --no-rootfs-checks"
+            },
+            SkipReason::RootfsChecksOff => "inspecting rootfs directories is turned off in the settings",
+            SkipReason::NotRoot => "pupman doesn't run as root, which looking at it needs",
+            SkipReason::RootfsNotFound => "the rootfs wasn't found on this host, its storage may not be mounted",
+            SkipReason::MountNotFound => "a mount point's host directory wasn't found on this host",
+            SkipReason::ZfsUnavailable => "zfs didn't report on the rootfs dataset",
+            SkipReason::NotScanned => "no deep scan of the rootfs ran yet",
+        }
+    }
+}
+
+/// A check which couldn't run for the container of `filename`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SkippedCheck {
+    pub filename: CompactString,
+    pub check: Check,
+    pub reason: SkipReason,
+}
+
+impl State {
+    /// Records which enabled checks couldn't run for each unprivileged container, and why.
+    pub(super) fn evaluate_skipped_checks(&mut self) {
+        let mut skipped = Vec::new();
+
+        for (filename, config) in &self.lxc_configs {
+            let section = config.section(None);
+
+            if !self.dialect.is_unprivileged(&section, &self.default_idmaps) {
+                continue;
+            }
+
+            for (check, reason) in self.skipped_checks_of(&section) {
+                if self.settings.is_enabled(check) {
+                    skipped.push(SkippedCheck {
+                        filename: filename.clone(),
+                        check,
+                        reason,
+                    });
+                }
+            }
+        }
+
+        self.skipped_checks = skipped;
+    }
+
+    fn skipped_checks_of(&self, section: &SectionView) -> Vec<(Check, SkipReason)> {
+        let rootfs = section.get_rootfs();
+        let has_mounts = section.mount_points().next().is_some();
+        let mut skipped = Vec::new();
+
+        if !self.inspects_rootfs() {
+            let reason = if self.rootfs_checks {
+                SkipReason::RootfsChecksOff
+            } else {
+                SkipReason::RootfsChecksUnavailable
+            };
+
+            if rootfs.is_some() {
+                skipped.extend(
+                    [Check::RootfsOwnership, Check::RootfsWritable, Check::RootfsContents].map(|check| (check, reason)),
+                );
+            }
+
+            if has_mounts {
+                skipped.push((Check::MountOwnership, reason));
+            }
+
+            return skipped;
+        }
+
+        let not_found = |reason| if self.runs_as_root { reason } else { SkipReason::NotRoot };
+
+        if let Some(rootfs) = rootfs {
+            match self.rootfs_info.get(rootfs) {
+                Some((location, _)) => {
+                    if location.dataset.is_some()
+                        && self.rootfs_space.get(rootfs).is_none_or(|space| space.zfs.is_none())
+                    {
+                        skipped.push((Check::RootfsWritable, SkipReason::ZfsUnavailable));
+                    }
+
+                    if !self.ownership_scans.contains_key(rootfs) {
+                        skipped.push((Check::RootfsContents, SkipReason::NotScanned));
+                    }
+                },
+                None => {
+                    let reason = not_found(SkipReason::RootfsNotFound);
+
+                    skipped.extend(
+                        [Check::RootfsOwnership, Check::RootfsWritable, Check::RootfsContents]
+                            .map(|check| (check, reason)),
+                    );
+                },
+            }
+        }
+
+        if section
+            .mount_points()
+            .any(|mount| !self.mount_info.contains_key(mount.value))
+        {
+            skipped.push((Check::MountOwnership, not_found(SkipReason::MountNotFound)));
+        }
+
+        skipped
+    }
+}
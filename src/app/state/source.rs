@@ -0,0 +1,123 @@
+//! Where findings come from in the files on disk, and a view of those files with a cursor on the
+//! offending line.
+
+use compact_str::CompactString;
+
+use super::State;
+use crate::finding::Finding;
+use crate::fs::subid::SubID;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SourceFile {
+    Subid(SubID),
+    /// A container config, by file name.
+    Config(CompactString),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceLocation {
+    pub file: SourceFile,
+    /// 1-based.
+    pub line: usize,
+}
+
+impl State {
+    /// Every line `finding` is about, in the order of its highlights. A missing idmap points at the
+    /// first line of the config, since there is no line to point at.
+    pub fn source_locations(&self, finding: &Finding) -> Vec<SourceLocation> {
+        let mut locations = Vec::new();
+
+        for (user, sub_id) in &finding.host_mapping_highlights {
+            let entries = match sub_id {
+                SubID::UID => &self.host_mapping.subuid,
+                SubID::GID => &self.host_mapping.subgid,
+            };
+
+            locations.extend(
+                entries
+                    .iter()
+                    .filter(|entry| entry.host_user_id == *user)
+                    .map(|entry| SourceLocation {
+                        file: SourceFile::Subid(*sub_id),
+                        line: entry.line_number,
+                    }),
+            );
+        }
+
+        locations.extend(
+            finding
+                .subid_line_highlights
+                .iter()
+                .map(|&(sub_id, line)| SourceLocation {
+                    file: SourceFile::Subid(sub_id),
+                    line,
+                }),
+        );
+
+        for (filename, sub_id) in &finding.lxc_config_mapping_highlights {
+            if !self.lxc_configs.contains_key(filename) {
+                continue;
+            }
+
+            let idmaps = self.idmaps(filename);
+            let file = SourceFile::Config(filename.clone());
+            let lines: Vec<_> = idmaps
+                .iter()
+                .filter(|idmap| idmap.kind() == Some(*sub_id))
+                .filter_map(|idmap| idmap.line)
+                .collect();
+
+            if lines.is_empty() {
+                locations.push(SourceLocation { file, line: 1 });
+            } else {
+                locations.extend(lines.into_iter().map(|line| SourceLocation {
+                    file: file.clone(),
+                    line,
+                }));
+            }
+        }
+
+        locations.extend(finding.config_line_highlights.iter().map(|line| SourceLocation {
+            file: SourceFile::Config(line.filename.clone()),
+            line: line.line,
+        }));
+
+        locations.dedup();
+        locations
+    }
+}
+
+/// A file as it is on disk, with a cursor. Opened from a finding, the cursor starts on the first
+/// offending line.
+#[derive(Debug)]
+pub struct SourceView {
+    /// Shown as the title, e.g. `/etc/subuid`.
+    pub path: String,
+    pub lines: Vec<String>,
+    /// 0-based index into `lines`.
+    pub cursor: usize,
+    /// 1-based lines to highlight.
+    pub targets: Vec<usize>,
+}
+
+impl SourceView {
+    /// A view of `content` with the cursor on `target`, clamped to the last line.
+    pub fn new(path: String, content: &str, target: usize, targets: Vec<usize>) -> Self {
+        let lines: Vec<_> = content.lines().map(str::to_string).collect();
+        let cursor = target.saturating_sub(1).min(lines.len().saturating_sub(1));
+
+        Self {
+            path,
+            lines,
+            cursor,
+            targets,
+        }
+    }
+
+    pub fn move_cursor(&mut self, delta: isize) {
+        self.cursor = self
+            .cursor
+            .saturating_add_signed(delta)
+            .min(self.lines.len().saturating_sub(1));
+    }
+}
@@ -0,0 +1,90 @@
+//! What pupman has done during this session. Nothing here is persisted or sent anywhere.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use ahash::RandomState;
+use chrono::{DateTime, Local};
+
+use crate::finding::{Finding, FindingKind};
+
+/// Rough minutes it takes to do each thing by hand, for the time saved estimate.
+const MINUTES_PER_FINDING: u64 = 5;
+const MINUTES_PER_FIX: u64 = 3;
+const MINUTES_PER_IMPORTED_ENTRY: u64 = 1;
+
+#[derive(Debug)]
+pub struct SessionStats {
+    pub started: Instant,
+    pub files_parsed: usize,
+    pub evaluations: usize,
+    /// When findings were last evaluated, which is when the panels last changed.
+    pub last_evaluation: Option<DateTime<Local>>,
+    pub fixes_applied: usize,
+    pub entries_imported: usize,
+    /// Ids of every warning and bad finding surfaced so far.
+    pub findings_seen: HashSet<String, RandomState>,
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            files_parsed: 0,
+            evaluations: 0,
+            last_evaluation: None,
+            fixes_applied: 0,
+            entries_imported: 0,
+            findings_seen: HashSet::with_hasher(RandomState::new()),
+        }
+    }
+}
+
+impl SessionStats {
+    pub fn record_evaluation(&mut self, findings: &[Finding]) {
+        self.evaluations += 1;
+        self.last_evaluation = Some(Local::now());
+        self.findings_seen.extend(
+            findings
+                .iter()
+                .filter(|finding| finding.kind != FindingKind::Good)
+                .map(Finding::id),
+        );
+    }
+
+    /// A rough estimate of how long tracking down the findings and making the changes would have
+    /// taken by hand.
+    pub fn time_saved(&self) -> Duration {
+        let minutes = self.findings_seen.len() as u64 * MINUTES_PER_FINDING
+            + self.fixes_applied as u64 * MINUTES_PER_FIX
+            + self.entries_imported as u64 * MINUTES_PER_IMPORTED_ENTRY;
+
+        Duration::from_secs(minutes * 60)
+    }
+}
+
+#[test]
+fn test_time_saved_counts_each_finding_once() {
+    use crate::check::Check;
+
+    let finding = Finding {
+        kind: FindingKind::Bad,
+        check: Check::IdmapPresent,
+        message: "lxc.idmap for uid is not set in config",
+        host_mapping_highlights: Vec::new(),
+        lxc_config_mapping_highlights: Vec::new(),
+        rootfs_highlights: Vec::new(),
+        config_line_highlights: Vec::new(),
+        subid_line_highlights: Vec::new(),
+        fix: None,
+    };
+    let mut stats = SessionStats::default();
+
+    stats.record_evaluation(std::slice::from_ref(&finding));
+    stats.record_evaluation(&[finding]);
+    stats.fixes_applied = 1;
+
+    assert_eq!(stats.evaluations, 2);
+    assert!(stats.last_evaluation.is_some());
+    assert_eq!(stats.time_saved(), Duration::from_secs(8 * 60));
+}
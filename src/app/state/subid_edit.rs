@@ -0,0 +1,476 @@
+//! Editing /etc/subuid and /etc/subgid entries in place from the host mappings panel.
+
+use color_eyre::eyre::eyre;
+use compact_str::CompactString;
+
+use super::import::SubidImport;
+use crate::app::ui::{HostMapping, IdMapEntry};
+use crate::fs::subid::SubID;
+use crate::lxc::{ID_SPACE_END, range_end};
+
+/// The size PVE gives a container's idmap, used for new entries.
+const DEFAULT_COUNT: u32 = 65536;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EditField {
+    User,
+    Start,
+    Count,
+}
+
+impl EditField {
+    pub fn next(self) -> Self {
+        match self {
+            EditField::User => EditField::Start,
+            EditField::Start => EditField::Count,
+            EditField::Count => EditField::User,
+        }
+    }
+
+    pub fn prev(self) -> Self {
+        match self {
+            EditField::User => EditField::Count,
+            EditField::Start => EditField::User,
+            EditField::Count => EditField::Start,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EditRow {
+    pub sub_id: SubID,
+    pub user: CompactString,
+    pub start: u32,
+    pub count: u32,
+}
+
+impl EditRow {
+    fn from_entry(sub_id: SubID, entry: &IdMapEntry) -> Self {
+        Self {
+            sub_id,
+            user: entry.host_user_id.clone(),
+            start: entry.host_sub_id,
+            count: entry.host_sub_id_count,
+        }
+    }
+
+    /// The entry in canonical `name:start:count` form.
+    pub fn canonical(&self) -> String {
+        format!("{}:{}:{}", self.user, self.start, self.count)
+    }
+
+    pub fn field(&self, field: EditField) -> String {
+        match field {
+            EditField::User => self.user.to_string(),
+            EditField::Start => self.start.to_string(),
+            EditField::Count => self.count.to_string(),
+        }
+    }
+}
+
+/// A working copy of both files' entries. Nothing is written until the user confirms saving.
+#[derive(Debug)]
+pub struct SubidEditor {
+    /// The entries and comments as they were when the editor was opened.
+    pub original: HostMapping,
+    /// Every subuid entry followed by every subgid entry, in file order.
+    pub rows: Vec<EditRow>,
+    pub selected: usize,
+    pub field: EditField,
+    /// The text typed into the selected field while it's being edited.
+    pub input: Option<String>,
+}
+
+impl SubidEditor {
+    pub fn new(host_mapping: &HostMapping) -> Self {
+        let subuid = host_mapping
+            .subuid
+            .iter()
+            .map(|entry| EditRow::from_entry(SubID::UID, entry));
+        let subgid = host_mapping
+            .subgid
+            .iter()
+            .map(|entry| EditRow::from_entry(SubID::GID, entry));
+
+        Self {
+            original: host_mapping.clone(),
+            rows: subuid.chain(subgid).collect(),
+            selected: 0,
+            field: EditField::User,
+            input: None,
+        }
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Starts typing into the selected field, prefilled with its current value.
+    pub fn begin_input(&mut self) {
+        if let Some(row) = self.rows.get(self.selected) {
+            self.input = Some(row.field(self.field));
+        }
+    }
+
+    /// Stores the typed text in the selected field. The input is kept when it isn't valid for the
+    /// field, so it can be corrected.
+    pub fn commit_input(&mut self) -> color_eyre::Result<()> {
+        let (Some(input), Some(row)) = (&self.input, self.rows.get_mut(self.selected)) else {
+            return Ok(());
+        };
+        let input = input.trim();
+
+        match self.field {
+            EditField::User => {
+                if input.is_empty() || input.contains([':', ' ', '\t']) {
+                    return Err(eyre!("{input:?} is not a valid user name or id"));
+                }
+
+                row.user = input.into();
+            },
+            EditField::Start => {
+                row.start = input
+                    .parse()
+                    .map_err(|_| eyre!("{input:?} is not an id between 0 and 4294967295"))?
+            },
+            EditField::Count => {
+                row.count = input
+                    .parse()
+                    .map_err(|_| eyre!("{input:?} is not a count between 0 and 4294967295"))?
+            },
+        }
+
+        self.input = None;
+
+        Ok(())
+    }
+
+    /// Adds an entry of the selected kind right after the selected one, for the same user and
+    /// starting past every other range of that kind.
+    pub fn add_row(&mut self) {
+        let (sub_id, user) = self
+            .rows
+            .get(self.selected)
+            .map_or((SubID::UID, CompactString::const_new("root")), |row| {
+                (row.sub_id, row.user.clone())
+            });
+        let start = self
+            .rows
+            .iter()
+            .filter(|row| row.sub_id == sub_id)
+            .map(|row| range_end(row.start, row.count))
+            .max()
+            .unwrap_or(100000);
+        let index = if self.rows.is_empty() { 0 } else { self.selected + 1 };
+
+        self.rows.insert(
+            index,
+            EditRow {
+                sub_id,
+                user,
+                start: u32::try_from(start).unwrap_or(u32::MAX),
+                count: DEFAULT_COUNT,
+            },
+        );
+        self.selected = index;
+    }
+
+    pub fn delete_row(&mut self) {
+        if self.selected < self.rows.len() {
+            self.rows.remove(self.selected);
+            self.move_selection(0);
+        }
+    }
+
+    /// Moves the selected entry to the other file, keeping subuid entries ahead of subgid ones.
+    pub fn toggle_kind(&mut self) {
+        if self.selected >= self.rows.len() {
+            return;
+        }
+
+        let mut row = self.rows.remove(self.selected);
+
+        row.sub_id = match row.sub_id {
+            SubID::UID => SubID::GID,
+            SubID::GID => SubID::UID,
+        };
+        self.selected = match row.sub_id {
+            SubID::UID => self.rows(SubID::UID).count(),
+            SubID::GID => self.rows.len(),
+        };
+        self.rows.insert(self.selected, row);
+    }
+
+    pub fn rows(&self, sub_id: SubID) -> impl Iterator<Item = &EditRow> {
+        self.rows.iter().filter(move |row| row.sub_id == sub_id)
+    }
+
+    /// The entries being edited, to check pasted entries against.
+    pub fn host_mapping(&self) -> HostMapping {
+        let entries = |sub_id| {
+            self.rows(sub_id)
+                .map(|row| IdMapEntry {
+                    host_user_id: row.user.clone(),
+                    host_sub_id: row.start,
+                    host_sub_id_count: row.count,
+                    ..IdMapEntry::default()
+                })
+                .collect()
+        };
+
+        HostMapping {
+            subuid: entries(SubID::UID),
+            subgid: entries(SubID::GID),
+            ..HostMapping::default()
+        }
+    }
+
+    /// Adds the entries selected in `import` after the last entry of each file it targets. They are
+    /// written along with every other edit once saving is confirmed. Returns how many were added.
+    pub fn import(&mut self, import: &SubidImport) -> usize {
+        let entries: Vec<_> = import.entries.iter().filter(|entry| entry.selected).collect();
+
+        for &sub_id in import.target.sub_ids() {
+            let index = match sub_id {
+                SubID::UID => self.rows(SubID::UID).count(),
+                SubID::GID => self.rows.len(),
+            };
+
+            self.rows.splice(
+                index..index,
+                entries.iter().map(|entry| EditRow {
+                    sub_id,
+                    user: entry.user.clone(),
+                    start: entry.start,
+                    count: entry.count,
+                }),
+            );
+        }
+
+        entries.len()
+    }
+
+    /// Whether saving would change any range in the file. Formatting alone doesn't count.
+    pub fn changed(&self, sub_id: SubID) -> bool {
+        let entries = match sub_id {
+            SubID::UID => &self.original.subuid,
+            SubID::GID => &self.original.subgid,
+        };
+
+        !self
+            .rows(sub_id)
+            .map(|row| (&row.user, row.start, row.count))
+            .eq(entries
+                .iter()
+                .map(|entry| (&entry.host_user_id, entry.host_sub_id, entry.host_sub_id_count)))
+    }
+
+    /// The file's new content. Comments and blank lines stay ahead of the entry they came before,
+    /// with trailing comments moved onto a line of their own.
+    pub fn content(&self, sub_id: SubID) -> String {
+        let (entries, comments) = match sub_id {
+            SubID::UID => (&self.original.subuid, &self.original.subuid_comments),
+            SubID::GID => (&self.original.subgid, &self.original.subgid_comments),
+        };
+        let mut rows = self.rows(sub_id);
+        let mut comments = comments.iter().peekable();
+        let mut content = String::new();
+
+        // Each row takes the place of an existing entry, in order, and any extra rows go at the end
+        for entry in entries {
+            while let Some(comment) = comments.next_if(|comment| comment.line_number <= entry.line_number) {
+                content.push_str(&comment.text);
+                content.push('\n');
+            }
+
+            if let Some(row) = rows.next() {
+                content.push_str(&row.canonical());
+                content.push('\n');
+            }
+        }
+
+        for row in rows {
+            content.push_str(&row.canonical());
+            content.push('\n');
+        }
+
+        for comment in comments {
+            content.push_str(&comment.text);
+            content.push('\n');
+        }
+
+        content
+    }
+
+    /// Reasons the entries can't be saved as they are.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let path = row.sub_id.path();
+
+            if row.count == 0 {
+                problems.push(format!("{path}: {} maps zero ids", row.canonical()));
+            } else if range_end(row.start, row.count) > ID_SPACE_END {
+                problems.push(format!("{path}: {} extends past the largest id", row.canonical()));
+            }
+
+            if self.rows[..i]
+                .iter()
+                .any(|other| other.sub_id == row.sub_id && other.user == row.user)
+            {
+                problems.push(format!("{path}: {} has more than one entry", row.user));
+            }
+        }
+
+        problems
+    }
+}
+
+#[test]
+fn test_edit_subid_entries() -> color_eyre::Result<()> {
+    let host_mapping = HostMapping {
+        subuid: vec![IdMapEntry {
+            host_user_id: "root".into(),
+            host_sub_id: 100000,
+            host_sub_id_count: 65536,
+            ..IdMapEntry::default()
+        }],
+        subgid: vec![IdMapEntry {
+            host_user_id: "root".into(),
+            host_sub_id: 100000,
+            host_sub_id_count: 65536,
+            ..IdMapEntry::default()
+        }],
+        ..HostMapping::default()
+    };
+    let mut editor = SubidEditor::new(&host_mapping);
+
+    assert!(!editor.changed(SubID::UID));
+
+    editor.field = EditField::Count;
+    editor.begin_input();
+
+    assert_eq!(editor.input.as_deref(), Some("65536"));
+
+    editor.input = Some("lots".into());
+
+    assert!(editor.commit_input().is_err());
+
+    editor.input = Some("131072".into());
+    editor.commit_input()?;
+
+    assert_eq!(editor.content(SubID::UID), "root:100000:131072\n");
+    assert!(editor.changed(SubID::UID));
+    assert!(!editor.changed(SubID::GID));
+
+    // A new entry keeps the selected row's kind and user, and starts past the other ranges
+    editor.add_row();
+
+    assert_eq!(editor.selected, 1);
+    assert_eq!(editor.content(SubID::UID), "root:100000:131072\nroot:231072:65536\n");
+    assert_eq!(editor.problems(), ["/etc/subuid: root has more than one entry"]);
+
+    editor.field = EditField::User;
+    editor.input = Some("alice".into());
+    editor.commit_input()?;
+
+    assert!(editor.problems().is_empty());
+
+    editor.move_selection(1);
+    editor.delete_row();
+
+    assert_eq!(editor.content(SubID::GID), "");
+    assert_eq!(editor.selected, 1);
+
+    editor.toggle_kind();
+
+    assert_eq!(editor.selected, 1);
+    assert_eq!(editor.content(SubID::UID), "root:100000:131072\n");
+    assert_eq!(editor.content(SubID::GID), "alice:231072:65536\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_edit_keeps_comments() -> color_eyre::Result<()> {
+    let content = "# containers\nroot:100000:65536 # pve\n\nalice:165536:65536\n# end\n";
+    let mut state = super::State::default();
+
+    state.load_subid(content, SubID::UID)?;
+
+    let mut editor = SubidEditor::new(&state.host_mapping);
+
+    assert_eq!(
+        editor.content(SubID::UID),
+        "# containers\n# pve\nroot:100000:65536\n\nalice:165536:65536\n# end\n"
+    );
+
+    editor.delete_row();
+    editor.add_row();
+
+    assert_eq!(
+        editor.content(SubID::UID),
+        "# containers\n# pve\nalice:165536:65536\n\nalice:231072:65536\n# end\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_import_into_editor() {
+    use super::import::ImportTarget;
+    use crate::linux::passwd::Passwd;
+
+    let host_mapping = HostMapping {
+        subuid: vec![IdMapEntry {
+            host_user_id: "root".into(),
+            host_sub_id: 100000,
+            host_sub_id_count: 65536,
+            ..IdMapEntry::default()
+        }],
+        subgid: vec![IdMapEntry {
+            host_user_id: "root".into(),
+            host_sub_id: 100000,
+            host_sub_id_count: 65536,
+            ..IdMapEntry::default()
+        }],
+        ..HostMapping::default()
+    };
+    let mut editor = SubidEditor::new(&host_mapping);
+
+    editor.rows[0].count = 1000;
+
+    let mut import = SubidImport {
+        text: "alice:165536:65536\nbob:101000:1000\n".into(),
+        target: ImportTarget::Subuid,
+        ..SubidImport::default()
+    };
+
+    // Checked against the edited entries rather than the files
+    import.review(&editor.host_mapping(), &Passwd::default());
+
+    assert_eq!(import.selected_lines(), ["alice:165536:65536", "bob:101000:1000"]);
+    assert_eq!(editor.import(&import), 2);
+    assert_eq!(
+        editor.content(SubID::UID),
+        "root:100000:1000\nalice:165536:65536\nbob:101000:1000\n"
+    );
+    assert!(!editor.changed(SubID::GID));
+
+    // bob overlaps root's unedited subgid range
+    import.target = ImportTarget::Both;
+    import.review(&editor.host_mapping(), &Passwd::default());
+
+    assert!(import.selected_lines().is_empty());
+
+    import.target = ImportTarget::Subgid;
+    import.review(&editor.host_mapping(), &Passwd::default());
+
+    assert_eq!(import.selected_lines(), ["alice:165536:65536"]);
+    assert_eq!(editor.import(&import), 1);
+    assert_eq!(editor.content(SubID::GID), "root:100000:65536\nalice:165536:65536\n");
+}
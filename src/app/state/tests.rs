@@ -1,18 +1,25 @@
+use std::collections::HashSet;
+use std::path::Path;
 use std::str::FromStr;
 
-use crate::app::ui::{FindingKind, HostMapping, IdMapEntry};
-use crate::fs::subid::SubID;
+use crate::app::ui::{HostMapping, IdMapEntry};
+use crate::changes::QueuedChange;
+use crate::check::Check;
+use crate::finding::FindingKind;
+use crate::fix::Fix;
+use crate::fs::subid::{ShadowBackup, SubID};
+use crate::fs::writer::PendingWrite;
+use crate::linux::{DiskSpace, ZfsDataset};
 use crate::lxc::config::Config;
+use crate::metadata::Metadata;
+use crate::settings::{MappingIntent, Settings, SortOrder};
 
-use super::State;
+use super::{State, finding_vmid};
 
 #[test]
 fn test_duplicate_username_not_allowed_in_subid() {
     let mut state = State {
-        host_mapping: HostMapping {
-            subuid: Vec::new(),
-            subgid: Vec::new(),
-        },
+        host_mapping: HostMapping::default(),
         ..State::default()
     };
 
@@ -26,11 +33,13 @@ fn test_duplicate_username_not_allowed_in_subid() {
             host_user_id: "1000".into(),
             host_sub_id: 10000,
             host_sub_id_count: 65000,
+            ..IdMapEntry::default()
         },
         IdMapEntry {
             host_user_id: "1000".into(),
             host_sub_id: 10000,
             host_sub_id_count: 65000,
+            ..IdMapEntry::default()
         },
     ];
 
@@ -40,7 +49,7 @@ fn test_duplicate_username_not_allowed_in_subid() {
     assert_eq!(state.findings[0].kind, FindingKind::Bad);
     assert_eq!(
         state.findings[0].message,
-        "Cannot have multiple entries for the same user"
+        "Cannot have overlapping entries for the same user"
     );
     assert_eq!(
         state.findings[0].host_mapping_highlights,
@@ -57,7 +66,7 @@ fn test_duplicate_username_not_allowed_in_subid() {
     assert_eq!(state.findings[0].kind, FindingKind::Bad);
     assert_eq!(
         state.findings[0].message,
-        "Cannot have multiple entries for the same group"
+        "Cannot have overlapping entries for the same group"
     );
     assert_eq!(
         state.findings[0].host_mapping_highlights,
@@ -66,6 +75,70 @@ fn test_duplicate_username_not_allowed_in_subid() {
     assert_eq!(state.findings[0].lxc_config_mapping_highlights, Vec::new());
 }
 
+#[test]
+fn test_duplicate_owner_by_name_and_uid() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_passwd("alice:x:1000:1000::/home/alice:/bin/bash\n");
+    state.load_subid("root:100000:65536\nalice:165536:65536\n0:131072:65536\n", SubID::UID)?;
+    state.load_subid("alice:165536:65536\n1000:165536:65536\n1001:296608:65536\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    let duplicates: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::SubidDuplicates)
+        .map(|f| &f.host_mapping_highlights[..])
+        .collect();
+
+    // root needs no /etc/passwd entry to be uid 0
+    assert_eq!(
+        duplicates,
+        [
+            &[("root".into(), SubID::UID), ("0".into(), SubID::UID)][..],
+            &[("alice".into(), SubID::GID), ("1000".into(), SubID::GID)][..],
+        ]
+    );
+
+    let explanation = state.explain(
+        state
+            .findings
+            .iter()
+            .find(|f| f.check == Check::SubidDuplicates)
+            .expect("duplicate finding"),
+        Path::new("/etc/pve/lxc"),
+    );
+
+    assert_eq!(explanation.offending[0].lines, ["root:100000:65536"]);
+    assert_eq!(explanation.offending[1].lines, ["0:131072:65536"]);
+    assert_eq!(
+        explanation.suggested.map(|excerpt| excerpt.lines),
+        Some(vec!["root:100000:96608".to_string()])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_disjoint_ranges_for_one_owner() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n0:1000:1\nroot:200000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\nroot:1000:1\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    let duplicates: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::SubidDuplicates)
+        .collect();
+
+    assert_eq!(duplicates.len(), 1);
+    assert_eq!(duplicates[0].kind, FindingKind::Good);
+
+    Ok(())
+}
+
 #[test]
 fn test_subid_out_of_range() -> color_eyre::Result<()> {
     let config = r#"
@@ -84,17 +157,24 @@ unprivileged: 1
                 host_user_id: "0".into(),
                 host_sub_id: 10000,
                 host_sub_id_count: 65000,
+                ..IdMapEntry::default()
             }],
             subgid: vec![IdMapEntry {
                 host_user_id: "0".into(),
                 host_sub_id: 10000,
                 host_sub_id_count: 65000,
+                ..IdMapEntry::default()
             }],
+            ..HostMapping::default()
         },
         lxc_configs: [("test.conf".into(), Config::from_str(config)?)].into_iter().collect(),
         ..State::default()
     };
 
+    // Both configs leave the top container ids unmapped and reach into systemd's reserved ids, which
+    // are different checks
+    state.settings.set_enabled(Check::IdmapCoverage, false);
+    state.settings.set_enabled(Check::IdmapReservedRanges, false);
     state.evaluate_findings();
 
     assert!(state.findings.iter().all(|f| f.kind == FindingKind::Good));
@@ -133,3 +213,1830 @@ unprivileged: 1
 
     Ok(())
 }
+
+#[test]
+fn test_host_range_across_ranges() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\nroot:1000:1\n0:165536:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\nroot:300000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 131072\nlxc.idmap: g 0 100000 131072\n",
+    )?;
+    state.evaluate_findings();
+
+    let out_of_range: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::IdmapHostRange && f.kind == FindingKind::Bad)
+        .collect();
+
+    // root's adjacent uid ranges cover the idmap between them, its gid ranges leave a gap
+    assert_eq!(out_of_range.len(), 1);
+    assert_eq!(out_of_range[0].host_mapping_highlights, [("root".into(), SubID::GID)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_rootfs() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nrootfs: local-lvm:vm-100-disk-0,size=8G\n",
+    )?;
+    // A hand-copied config, with the volume spelled out
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        "hostname: copy\nrootfs: volume=local-lvm:vm-100-disk-0,size=8G\n",
+    )?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/102.conf"),
+        "rootfs: local-lvm:vm-102-disk-0,size=8G\n",
+    )?;
+    state.evaluate_findings();
+
+    let shared: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::RootfsShared)
+        .collect();
+
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0].kind, FindingKind::Bad);
+    assert_eq!(
+        shared[0]
+            .config_line_highlights
+            .iter()
+            .map(|line| (line.filename.as_str(), line.line))
+            .collect::<Vec<_>>(),
+        [("100.conf", 2), ("101.conf", 2)]
+    );
+
+    let explanation = state.explain(shared[0], Path::new("/etc/pve/lxc"));
+
+    assert!(
+        explanation
+            .paragraphs
+            .iter()
+            .any(|paragraph| paragraph.starts_with("Containers 100 and 101 both start"))
+    );
+
+    state.unload_config(Path::new("/etc/pve/lxc/101.conf"))?;
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::RootfsShared));
+
+    Ok(())
+}
+
+#[test]
+fn test_readonly_rootfs_dataset() -> color_eyre::Result<()> {
+    let config = r#"
+lxc.idmap = u 0 100000 65536
+lxc.idmap = g 0 100000 65536
+rootfs: local-zfs:subvol-100-disk-0,size=4G
+unprivileged: 1
+"#;
+    let mut state = State {
+        lxc_configs: [("100.conf".into(), Config::from_str(config)?)].into_iter().collect(),
+        ..State::default()
+    };
+    let mut zfs = ZfsDataset {
+        name: "rpool/data/subvol-100-disk-0".into(),
+        readonly: true,
+        refquota: None,
+        partially_received: false,
+    };
+
+    state.rootfs_space.insert(
+        "local-zfs:subvol-100-disk-0,size=4G".into(),
+        DiskSpace {
+            available: 1024,
+            quota: None,
+            zfs: Some(zfs.clone()),
+            filesystem: None,
+        },
+    );
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.message == "Rootfs dataset is read-only and cannot be fixed")
+        .expect("read-only finding");
+
+    assert_eq!(finding.kind, FindingKind::Bad);
+    assert_eq!(finding.rootfs_highlights, ["local-zfs:subvol-100-disk-0,size=4G"]);
+
+    zfs.readonly = false;
+    state
+        .rootfs_space
+        .values_mut()
+        .for_each(|space| space.zfs = Some(zfs.clone()));
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.message.starts_with("Rootfs dataset")));
+
+    // Nothing about rootfs datasets is reported once rootfs inspection is turned off
+    zfs.readonly = true;
+    state
+        .rootfs_space
+        .values_mut()
+        .for_each(|space| space.zfs = Some(zfs.clone()));
+    state.settings.set_inspect_rootfs(false);
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.message.starts_with("Rootfs dataset")));
+
+    Ok(())
+}
+
+#[test]
+fn test_unusual_subid_formatting() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root : 100000 : 65536\nuser:200000:65536\n", SubID::UID)?;
+    state.load_subid("root\t100000\t65536\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    assert_eq!(state.host_mapping.subuid[0].host_sub_id, 100000);
+    assert_eq!(state.host_mapping.subgid[0].host_sub_id_count, 65536);
+
+    let warnings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.kind == FindingKind::Warning)
+        .collect();
+
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0].message, "Unusual formatting in /etc/subuid entries");
+    assert_eq!(warnings[0].host_mapping_highlights, [("root".into(), SubID::UID)]);
+    assert_eq!(warnings[0].fix, Some(Fix::NormalizeSubid(SubID::UID)));
+    assert_eq!(warnings[1].message, "Unusual formatting in /etc/subgid entries");
+
+    Ok(())
+}
+
+#[test]
+fn test_marked_findings() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root : 100000 : 65536\n", SubID::UID)?;
+    state.load_subid("root\t100000\t65536\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    let fixable: Vec<_> = (0..state.findings.len())
+        .filter(|&i| state.findings[i].fix.is_some())
+        .collect();
+
+    for &i in &fixable {
+        state.selected_finding = Some(i);
+        state.toggle_marked_finding()?;
+    }
+
+    assert_eq!(
+        state.marked_fixes(),
+        [Fix::NormalizeSubid(SubID::UID), Fix::NormalizeSubid(SubID::GID)]
+    );
+
+    // Marking again unmarks
+    state.selected_finding = Some(fixable[1]);
+    state.toggle_marked_finding()?;
+
+    assert_eq!(state.marked_fixes(), [Fix::NormalizeSubid(SubID::UID)]);
+
+    // A fixed finding goes away along with its mark
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.evaluate_findings();
+
+    assert!(state.marked_findings.is_empty());
+
+    state.selected_finding = state.findings.iter().position(|f| f.fix.is_none());
+
+    assert!(state.selected_finding.is_some());
+    assert!(state.toggle_marked_finding().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_subid_comments() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid(
+        "# Allocated by pve\n#root:1:2\n   \t\nroot:100000:65536\n  # indented\nalice:165536:65536 # alice's containers\n",
+        SubID::UID,
+    )?;
+    state.evaluate_findings();
+
+    let entries = &state.host_mapping.subuid;
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!((entries[0].line_number, entries[0].unusual_format), (4, false));
+    assert_eq!(entries[1].host_user_id, "alice");
+    assert_eq!(entries[1].host_sub_id_count, 65536);
+    assert_eq!(state.host_mapping.subuid_comments.len(), 5);
+
+    // shadow-utils doesn't allow trailing comments, so alice's entry is flagged
+    let warnings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.kind == FindingKind::Warning)
+        .collect();
+
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].host_mapping_highlights, [("alice".into(), SubID::UID)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_disabled_check_produces_no_findings() -> color_eyre::Result<()> {
+    let mut state = State {
+        lxc_configs: [("100.conf".into(), Config::from_str("unprivileged: 1")?)]
+            .into_iter()
+            .collect(),
+        ..State::default()
+    };
+
+    state.evaluate_findings();
+
+    assert!(state.findings.iter().any(|f| f.check == Check::IdmapPresent));
+    assert_eq!(state.check_runs[&Check::IdmapPresent].findings, 2);
+
+    state.settings.set_enabled(Check::IdmapPresent, false);
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::IdmapPresent));
+
+    Ok(())
+}
+
+#[test]
+fn test_explain_quotes_offending_lines() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    let mut state = State {
+        lxc_configs: [(
+            "100.conf".into(),
+            Config::from_str("unprivileged: 1\nlxc.idmap: u 0 100000 65536\n")?,
+        )]
+        .into_iter()
+        .collect(),
+        ..State::default()
+    };
+
+    state.load_subid("root : 100000 : 65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.settings.set_enabled(Check::RootfsOwnership, false);
+    state.evaluate_findings();
+
+    let formatting = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::SubidFormatting)
+        .expect("formatting finding");
+    let explanation = state.explain(formatting, Path::new("/etc/pve/lxc"));
+
+    assert_eq!(explanation.offending[0].source, "/etc/subuid");
+    assert_eq!(explanation.offending[0].lines, ["root : 100000 : 65536"]);
+    assert_eq!(explanation.suggested.unwrap().lines, ["root:100000:65536"]);
+
+    let missing = state
+        .findings
+        .iter()
+        .find(|f| f.message == "lxc.idmap for gid is not set in config")
+        .expect("missing gid idmap finding");
+    let explanation = state.explain(missing, Path::new("/etc/pve/lxc"));
+    let suggested = explanation.suggested.unwrap();
+
+    // The config has no gid idmap to quote, only the uid one
+    assert!(explanation.offending.is_empty());
+    assert_eq!(suggested.source, "/etc/pve/lxc/100.conf");
+    assert_eq!(suggested.lines, ["lxc.idmap: g 0 100000 65536"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_config_keys() -> color_eyre::Result<()> {
+    let config = "rootfs: local-zfs:subvol-100-disk-0\nunprivileged: 1\nrootfs: local-zfs:subvol-100-disk-1\n";
+    let mut state = State {
+        lxc_configs: [("100.conf".into(), Config::from_str(config)?)].into_iter().collect(),
+        ..State::default()
+    };
+
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::ConfigDuplicateKeys)
+        .expect("duplicate key finding");
+    let lines: Vec<_> = finding.config_line_highlights.iter().map(|l| l.line).collect();
+
+    assert_eq!(finding.kind, FindingKind::Bad);
+    assert_eq!(lines, [1, 3]);
+    assert_eq!(finding.id(), "config-duplicate-keys:100.conf/rootfs");
+
+    Ok(())
+}
+
+#[test]
+fn test_degenerate_id_ranges() -> color_eyre::Result<()> {
+    let config = "unprivileged: 1\nlxc.idmap: u 0 100000 0\nlxc.idmap: g 0 4294967295 65536\nlxc.idmap: x y z\n";
+    let mut state = State {
+        lxc_configs: [("100.conf".into(), Config::from_str(config)?)].into_iter().collect(),
+        ..State::default()
+    };
+
+    state.load_subid("root:100000:0\nuser:4294967295:2\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    // Must not panic or overflow anywhere along the way
+    state.evaluate_findings();
+
+    let messages: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::IdRangeValues)
+        .map(|f| f.message)
+        .collect();
+
+    assert_eq!(
+        messages,
+        [
+            "Subordinate id range is empty",
+            "Subordinate id range extends past the largest id",
+            "lxc.idmap entry maps zero ids",
+            "lxc.idmap range extends past the largest id",
+            "lxc.idmap entry is malformed",
+        ]
+    );
+
+    let malformed = state
+        .findings
+        .iter()
+        .find(|f| f.message == "lxc.idmap entry is malformed")
+        .expect("malformed idmap finding");
+
+    assert_eq!(malformed.config_line_highlights[0].line, 4);
+    assert!(
+        state
+            .explain(malformed, Path::new("/etc/pve/lxc"))
+            .paragraphs
+            .contains(&"Line 4: expected 4 fields `u|g <container id> <host id> <count>`, found 3.".to_string())
+    );
+    // The degenerate idmaps still count as present
+    assert!(!state.findings.iter().any(|f| f.check == Check::IdmapPresent));
+
+    Ok(())
+}
+
+#[test]
+fn test_source_locations() -> color_eyre::Result<()> {
+    use super::source::{SourceFile, SourceLocation, SourceView};
+
+    let mut state = State {
+        lxc_configs: [(
+            "100.conf".into(),
+            Config::from_str("unprivileged: 1\n# mapping\nlxc.idmap: u 0 100000 65536\n")?,
+        )]
+        .into_iter()
+        .collect(),
+        ..State::default()
+    };
+
+    state.load_subid("\n\nroot : 100000 : 65536\n", SubID::UID)?;
+    state.settings.set_enabled(Check::RootfsOwnership, false);
+    state.evaluate_findings();
+
+    let formatting = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::SubidFormatting)
+        .expect("formatting finding");
+
+    assert_eq!(
+        state.source_locations(formatting),
+        [SourceLocation {
+            file: SourceFile::Subid(SubID::UID),
+            line: 3,
+        }]
+    );
+
+    let missing = state
+        .findings
+        .iter()
+        .find(|f| f.message == "lxc.idmap for gid is not set in config")
+        .expect("missing gid idmap finding");
+
+    // There is no line for a missing idmap, so the config is opened at the top
+    assert_eq!(state.source_locations(missing)[0].line, 1);
+
+    let mut view = SourceView::new("/etc/subuid".into(), "a\nb\nc", 9, vec![3]);
+
+    assert_eq!(view.cursor, 2);
+
+    view.move_cursor(-5);
+
+    assert_eq!(view.cursor, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_sort_findings_by_container() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_config(Path::new("/etc/pve/lxc/200.conf"), "unprivileged: 1\n")?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 0\n",
+    )?;
+    state.load_subid("root: 100000 :65536\n", SubID::UID)?;
+    state.settings.set_sort_order(SortOrder::Container);
+    state.evaluate_findings();
+
+    let order: Vec<_> = state
+        .findings
+        .iter()
+        .map(|f| (finding_vmid(&state.lxc_configs, f), f.kind))
+        .collect();
+
+    // Host findings come first, then each container's findings with the most severe first
+    assert_eq!(order[0], (None, FindingKind::Warning));
+    assert!(order.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    assert!(order.iter().any(|(vmid, _)| *vmid == Some(101)));
+    assert_eq!(order.last().map(|(vmid, _)| *vmid), Some(Some(200)));
+
+    state.settings.set_sort_order(SortOrder::Check);
+    state.sort_findings(|_| None);
+
+    assert!(state.findings.windows(2).all(|pair| pair[0].check <= pair[1].check));
+
+    Ok(())
+}
+
+#[test]
+fn test_idmap_coverage_findings() -> color_eyre::Result<()> {
+    let config = "unprivileged: 1\n\
+                  lxc.idmap: u 0 100000 1000\n\
+                  lxc.idmap: u 1001 101001 64535\n\
+                  lxc.idmap: g 0 100000 65536\n\
+                  lxc.idmap: g 100 1000 1\n";
+    let mut state = State::default();
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.evaluate_findings();
+
+    let coverage: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::IdmapCoverage)
+        .collect();
+
+    assert_eq!(coverage.len(), 2);
+    assert_eq!(coverage[0].message, "lxc.idmap leaves container uids unmapped");
+    assert_eq!(coverage[1].message, "lxc.idmap maps the same container gids twice");
+    assert_eq!(
+        coverage[1]
+            .config_line_highlights
+            .iter()
+            .map(|line| line.line)
+            .collect::<Vec<_>>(),
+        [4, 5]
+    );
+
+    let explanation = state.explain(coverage[0], Path::new("/etc/pve/lxc"));
+
+    assert!(
+        explanation
+            .paragraphs
+            .contains(&"In this config: container id 1000 not mapped.".to_string())
+    );
+
+    state.settings.set_enabled(Check::IdmapCoverage, false);
+    state.evaluate_findings();
+
+    assert!(state.findings.iter().all(|f| f.check != Check::IdmapCoverage));
+
+    Ok(())
+}
+
+#[test]
+fn test_hand_written_subid_entries() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    // Passing a single host id through is left alone, even though it's listed after a higher range
+    state.load_subid("root:100000:65536\nroot:1000:1\nalice:20000:10000\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_shadow_backup(
+        SubID::GID,
+        Some(ShadowBackup {
+            entries: Vec::new(),
+            edited_since: false,
+        }),
+    );
+    state.evaluate_findings();
+
+    let managed: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::SubidManaged)
+        .collect();
+
+    assert_eq!(managed.len(), 1);
+    assert_eq!(managed[0].host_mapping_highlights, [("alice".into(), SubID::UID)]);
+    assert_eq!(managed[0].fix, Some(Fix::ReAddWithUsermod(SubID::UID)));
+
+    // Entries which are only out of order are sorted rather than added again
+    let explanation = state.explain(managed[0], Path::new("/etc/pve/lxc"));
+
+    assert_eq!(explanation.suggested, None);
+
+    // Once the file changed behind shadow-utils' back, anything missing from its backup is suspect
+    state.load_shadow_backup(
+        SubID::GID,
+        Some(ShadowBackup {
+            entries: Vec::new(),
+            edited_since: true,
+        }),
+    );
+    state.evaluate_findings();
+
+    let managed = state
+        .findings
+        .iter()
+        .find(|f| f.message == "/etc/subgid has hand-written entries which usermod may rewrite")
+        .expect("a finding for /etc/subgid");
+    let explanation = state.explain(managed, Path::new("/etc/pve/lxc"));
+
+    assert_eq!(
+        explanation.suggested.map(|excerpt| excerpt.lines),
+        Some(vec!["usermod --add-subgids 100000-165535 root".to_string()])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_deprecated_config_keys() -> color_eyre::Result<()> {
+    use crate::proxmox::dialect::Dialect;
+
+    let config = "unprivileged: 1\n\
+                  lxc.id_map: u 0 100000 65536\n\
+                  lxc.idmap: g 0 100000 65536\n\
+                  lxc.cgroup.devices.allow: c 10:200 rwm\n";
+    let mut state = State::default();
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.evaluate_findings();
+
+    let deprecated = |state: &State| {
+        state
+            .findings
+            .iter()
+            .find(|f| f.check == Check::ConfigDeprecatedKeys)
+            .map(|f| f.config_line_highlights.iter().map(|l| l.line).collect::<Vec<_>>())
+    };
+
+    // cgroup v1 keys still work on PVE 8
+    assert_eq!(deprecated(&state), Some(vec![2]));
+
+    state.dialect = Dialect::Pve9;
+    state.evaluate_findings();
+
+    assert_eq!(deprecated(&state), Some(vec![2, 4]));
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::ConfigDeprecatedKeys)
+        .expect("deprecated key finding");
+
+    assert!(
+        state
+            .explain(finding, Path::new("/etc/pve/lxc"))
+            .paragraphs
+            .iter()
+            .any(|p| p
+                .starts_with("Line 4: `lxc.cgroup.devices.allow` should be written as `lxc.cgroup2.devices.allow`"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_readiness_of_new_container() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/105.conf"),
+        "unprivileged: 1\nrootfs: local-zfs:subvol-105-disk-0,size=8G\nlxc.idmap: u 0 100000 65536\n",
+    )?;
+    state.load_config(Path::new("/etc/pve/lxc/106.conf"), "arch: amd64\n")?;
+    state.evaluate_findings();
+
+    // The rootfs hasn't been stat-ed yet
+    assert_eq!(state.readiness("105.conf"), None);
+    assert_eq!(state.readiness("106.conf"), None);
+
+    state.rootfs_checks = false;
+
+    let readiness = state.readiness("105.conf").expect("readiness");
+
+    assert_eq!(readiness.blockers, ["lxc.idmap for gid is not set in config"]);
+    assert_eq!(
+        readiness.summary(),
+        "Container 105 will likely fail its first start: lxc.idmap for gid is not set in config"
+    );
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/105.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    assert_eq!(
+        state.readiness("105.conf").map(|readiness| readiness.summary()),
+        Some("Container 105 looks ready for its first start as unprivileged".to_string())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_readiness_of_unresolvable_rootfs() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/105.conf"),
+        "unprivileged: 1\nrootfs: missing:subvol-105-disk-0,size=8G\nlxc.idmap: u 0 100000 65536\n\
+         lxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    assert_eq!(state.readiness("105.conf"), None);
+
+    state
+        .unavailable_dirs
+        .insert("missing:subvol-105-disk-0,size=8G".into(), "Unknown storage".into());
+
+    let blockers = state.readiness("105.conf").expect("readiness").blockers;
+
+    assert_eq!(blockers[0], "Its rootfs can't be found on the host");
+    assert_eq!(
+        blockers.len(),
+        blockers.iter().collect::<HashSet<_>>().len(),
+        "{blockers:?}"
+    );
+
+    state.unload_config(Path::new("/etc/pve/lxc/105.conf"))?;
+
+    assert!(state.unavailable_dirs.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_lxc_default_idmaps() -> color_eyre::Result<()> {
+    let default_conf = Path::new("/etc/lxc/default.conf");
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(Path::new("/var/lib/lxc/100.conf"), "lxc.arch = amd64\n")?;
+    state.load_lxc_defaults(
+        default_conf,
+        "lxc.net.0.type = veth\nlxc.idmap = u 0 100000 65536\nlxc.idmap = g 0 100000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    assert_eq!(state.default_idmaps.len(), 2);
+    assert_eq!(state.unprivileged_configs().collect::<Vec<_>>(), ["100.conf"]);
+    assert!(
+        state
+            .idmaps("100.conf")
+            .iter()
+            .all(|idmap| idmap.include.as_deref() == Some(default_conf))
+    );
+    assert!(
+        !state
+            .findings
+            .iter()
+            .any(|f| f.check == Check::IdmapPresent && f.kind == FindingKind::Bad)
+    );
+
+    // Own idmaps take the place of the defaults rather than adding to them
+    state.load_config(
+        Path::new("/var/lib/lxc/101.conf"),
+        "lxc.arch = amd64\nlxc.idmap = u 0 100000 65536\n",
+    )?;
+
+    assert_eq!(state.idmaps("101.conf").len(), 1);
+
+    // Without any idmaps a plain LXC container runs privileged
+    state.unload_lxc_defaults();
+    state.evaluate_findings();
+
+    assert!(state.idmaps("100.conf").is_empty());
+    assert_eq!(state.unprivileged_configs().collect::<Vec<_>>(), ["101.conf"]);
+
+    // PVE never reads default.conf, wherever its configs are read from
+    let mut state = State::default();
+
+    state.load_config(Path::new("/srv/pve-copy/100.conf"), "unprivileged: 1\n")?;
+    state.load_lxc_defaults(default_conf, "lxc.idmap = u 0 100000 65536\n")?;
+
+    assert!(state.idmaps("100.conf").is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_mount_point_ownership() -> color_eyre::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    use crate::lxc::resolve_rootfs;
+    use crate::proxmox::storage::StorageConfig;
+
+    let dir = tempfile::tempdir()?;
+    let metadata = std::fs::metadata(dir.path())?;
+    let (uid, gid) = (metadata.uid(), metadata.gid());
+    let value = format!("{},mp=/media", dir.path().display());
+    let mut state = State::default();
+
+    // The directory's owner is far below the container's host ids
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!("unprivileged: 1\nlxc.idmap: u 0 4000000000 65536\nlxc.idmap: g 0 4000000000 65536\nmp0: {value}\n"),
+    )?;
+
+    assert_eq!(
+        state.mount_point_values(Path::new("/etc/pve/lxc/100.conf")),
+        [value.as_str()]
+    );
+
+    let location = resolve_rootfs(&value, &StorageConfig::default())?;
+
+    state.load_dir_metadata(value.clone(), location.clone(), metadata.clone());
+    state.evaluate_findings();
+
+    // Mount points don't show up as a rootfs
+    assert!(state.rootfs_info.is_empty());
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::MountOwnership)
+        .collect();
+
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].kind, FindingKind::Warning);
+    assert_eq!(findings[0].config_line_highlights[0].line, 4);
+    assert_eq!(finding_vmid(&state.lxc_configs, findings[0]), Some(100));
+
+    let explanation = state.explain(findings[0], Path::new("/etc/pve/lxc"));
+
+    assert!(
+        explanation
+            .offending
+            .iter()
+            .any(|excerpt| excerpt.lines == [format!("owned by {uid}:{gid}")])
+    );
+
+    // Mapping the owner's ids into the container is enough
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!("unprivileged: 1\nlxc.idmap: u 0 {uid} 65536\nlxc.idmap: g 0 {gid} 65536\nmp0: {value}\n"),
+    )?;
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::MountOwnership));
+
+    state.unload_config(Path::new("/etc/pve/lxc/100.conf"))?;
+
+    assert!(state.mount_info.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_idmap_symmetry() -> color_eyre::Result<()> {
+    let mut state = State::default();
+    let config = "unprivileged: 1\n\
+                  lxc.idmap: u 0 100000 65536\n\
+                  lxc.idmap: g 0 100000 44\n\
+                  lxc.idmap: g 44 44 1\n\
+                  lxc.idmap: g 45 100045 65491\n";
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        "unprivileged: 1\nlxc.idmap: g 0 100000 65536\nlxc.idmap: u 0 100000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    let asymmetric: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::IdmapSymmetry)
+        .collect();
+
+    // The order idmaps are written in doesn't matter
+    assert_eq!(asymmetric.len(), 1);
+    assert_eq!(asymmetric[0].kind, FindingKind::Warning);
+    assert_eq!(finding_vmid(&state.lxc_configs, asymmetric[0]), Some(100));
+
+    state.settings.set_mapping_intent("100", MappingIntent::GidOnly);
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::IdmapSymmetry));
+
+    Ok(())
+}
+
+#[test]
+fn test_rootfs_contents() -> color_eyre::Result<()> {
+    use std::path::PathBuf;
+
+    use crate::fs::scan::{OwnerFiles, OwnershipScan};
+    use crate::lxc::resolve_rootfs;
+    use crate::proxmox::storage::StorageConfig;
+
+    let dir = tempfile::tempdir()?;
+    let value = dir.path().display().to_string();
+    let mut state = State::default();
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!("unprivileged: 1\nrootfs: {value}\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n"),
+    )?;
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), "arch: amd64\n")?;
+    state.load_rootfs_metadata(
+        value.clone(),
+        resolve_rootfs(&value, &StorageConfig::default())?,
+        std::fs::metadata(dir.path())?,
+    );
+
+    assert_eq!(state.deep_scan_targets(), [(value.clone(), dir.path().to_path_buf())]);
+
+    let owner_files = |files, example: &str| OwnerFiles {
+        files,
+        examples: vec![PathBuf::from(example)],
+    };
+    let mut scan = OwnershipScan {
+        cancelled: true,
+        ..OwnershipScan::default()
+    };
+
+    scan.owners.insert((100000, 100000), owner_files(40, "etc"));
+    // Left behind by a restore which didn't shift ownership
+    scan.owners.insert((0, 0), owner_files(2, "etc/shadow"));
+    // Mapped uid, unmapped gid
+    scan.owners.insert((100033, 33), owner_files(1, "var/www"));
+
+    state.load_ownership_scan(value.clone(), scan);
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::RootfsContents)
+        .expect("finding");
+
+    assert_eq!(finding.kind, FindingKind::Warning);
+    assert_eq!(finding_vmid(&state.lxc_configs, finding), Some(100));
+
+    let explanation = state.explain(finding, Path::new("/etc/pve/lxc"));
+
+    assert!(
+        explanation
+            .paragraphs
+            .iter()
+            .any(|p| p.starts_with("3 of the 43 files"))
+    );
+    assert!(explanation.paragraphs.iter().any(|p| p.contains("cancelled")));
+    assert_eq!(
+        explanation.offending[0].lines,
+        [
+            "2 files owned by 0:0",
+            "  etc/shadow",
+            "1 files owned by 100033:33",
+            "  var/www"
+        ]
+    );
+
+    // Without looking at rootfs directories there's nothing to go by
+    state.settings.set_inspect_rootfs(false);
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::RootfsContents));
+
+    state.unload_config(Path::new("/etc/pve/lxc/100.conf"))?;
+
+    assert!(state.ownership_scans.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_idmapped_mount_suggestion() -> color_eyre::Result<()> {
+    use crate::linux::Filesystem;
+    use crate::lxc::resolve_rootfs;
+    use crate::proxmox::storage::StorageConfig;
+
+    let dir = tempfile::tempdir()?;
+    let value = dir.path().display().to_string();
+    let mut state = State::default();
+
+    // Restored from a privileged backup, so still owned by unshifted ids
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!("unprivileged: 1\nrootfs: {value}\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n"),
+    )?;
+    state.load_rootfs_metadata(
+        value.clone(),
+        resolve_rootfs(&value, &StorageConfig::default())?,
+        std::fs::metadata(dir.path())?,
+    );
+
+    let set_support = |state: &mut State, idmapped_mounts| {
+        state.rootfs_space.insert(
+            value.clone(),
+            DiskSpace {
+                available: 1024,
+                quota: None,
+                zfs: None,
+                filesystem: Some(Filesystem {
+                    name: "ext4",
+                    idmapped_mounts,
+                }),
+            },
+        );
+        state.evaluate_findings();
+    };
+
+    set_support(&mut state, true);
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::RootfsOwnership)
+        .expect("finding");
+    let explanation = state.explain(finding, Path::new("/etc/pve/lxc"));
+
+    assert_eq!(
+        explanation.suggested.map(|excerpt| excerpt.lines),
+        Some(vec!["lxc.rootfs.options: idmap=container".to_string()])
+    );
+    assert!(explanation.paragraphs.iter().any(|p| p.contains("ext4")));
+
+    // Without kernel support it's back to chowning
+    set_support(&mut state, false);
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::RootfsOwnership)
+        .expect("finding");
+
+    assert_eq!(state.idmapped_mount(finding), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_subid_owner() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\nghost:165536:65536\n1000:231072:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\nlxc-users:165536:65536\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    // Nothing can be said before /etc/passwd was read
+    assert!(state.findings.iter().all(|f| f.check != Check::SubidOwner));
+
+    state.load_passwd("root:x:0:0:root:/root:/bin/bash\n");
+    state.load_group("root:x:0:\nlxc-users:x:1001:\n");
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state.findings.iter().filter(|f| f.check == Check::SubidOwner).collect();
+
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].host_mapping_highlights, [("ghost".into(), SubID::UID)]);
+    assert_eq!(
+        findings[1].message,
+        "Subgid owner is a group, but subgid entries belong to users"
+    );
+
+    // NIS users could be anyone
+    state.load_passwd("root:x:0:0:root:/root:/bin/bash\n+::::::\n");
+    state.evaluate_findings();
+
+    assert!(state.findings.iter().all(|f| f.check != Check::SubidOwner));
+
+    Ok(())
+}
+
+#[test]
+fn test_owner_id_from_passwd() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_passwd("pupman-native:x:4242:4343::/nonexistent:/usr/sbin/nologin\n");
+
+    // Unknown to `id`, so this only resolves through the loaded /etc/passwd
+    assert_eq!(state.owner_id("pupman-native", SubID::UID)?, 4242);
+    assert_eq!(state.owner_id("pupman-native", SubID::GID)?, 4343);
+    assert_eq!(state.owner_id("4242", SubID::GID)?, 4343);
+
+    state.unload_passwd();
+
+    assert!(state.owner_id("pupman-native", SubID::UID).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_config_schema() -> color_eyre::Result<()> {
+    let config = "unprivilegd: 1\n\
+                  memory: 512M\n\
+                  net0: name=eth0,bridge=vmbr0\n\
+                  lxc.idmap: u 0 100000 65536\n";
+    let mut state = State::default();
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::ConfigSchema)
+        .collect();
+
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].config_line_highlights[0].line, 1);
+    assert_eq!(findings[1].config_line_highlights[0].line, 2);
+    assert!(
+        state
+            .explain(findings[0], Path::new("/etc/pve/lxc"))
+            .paragraphs
+            .contains(&"Line 1: `unprivilegd` isn't a key, did you mean `unprivileged`?".to_string())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_idmap_login_users() -> color_eyre::Result<()> {
+    let config = "unprivileged: 1\n\
+                  lxc.idmap: u 0 100000 1000\n\
+                  lxc.idmap: u 1000 1000 2\n\
+                  lxc.idmap: u 1002 101002 64534\n\
+                  lxc.idmap: g 0 100000 65536\n";
+    let mut state = State {
+        operator_uid: Some(101500),
+        ..State::default()
+    };
+
+    state.load_passwd(
+        "root:x:0:0:root:/root:/bin/bash\n\
+         alice:x:1000:1000::/home/alice:/bin/bash\n\
+         backup:x:100034:34:backup:/var/backups:/usr/sbin/nologin\n",
+    );
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::IdmapLoginUsers)
+        .collect();
+
+    assert_eq!(findings.len(), 1);
+
+    let explanation = state.explain(findings[0], Path::new("/etc/pve/lxc"));
+
+    // The service account can't log in, so only alice and whoever runs pupman count
+    assert!(explanation.paragraphs[1].contains("alice (uid 1000), whoever runs pupman (uid 101500)"));
+
+    state.operator_uid = None;
+    state.load_passwd("alice:x:1000:1000::/home/alice:/usr/sbin/nologin\n");
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::IdmapLoginUsers));
+
+    Ok(())
+}
+
+#[test]
+fn test_idmap_host_accounts() -> color_eyre::Result<()> {
+    let config = "unprivileged: 1\n\
+                  lxc.idmap: u 0 100000 1000\n\
+                  lxc.idmap: u 1000 1000 2\n\
+                  lxc.idmap: u 1002 101002 64534\n\
+                  lxc.idmap: g 0 0 65536\n";
+    let mut state = State::default();
+
+    state.load_passwd(
+        "alice:x:1000:1000::/home/alice:/bin/bash\n\
+         backup:x:100034:34:backup:/var/backups:/usr/sbin/nologin\n\
+         nobody:x:200000:200000::/:/usr/sbin/nologin\n",
+    );
+    state.load_group("disk:x:6:\nalice:x:1000:\nbackup:x:100034:\n");
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::IdmapHostAccounts)
+        .collect();
+
+    assert_eq!(findings.len(), 1);
+    assert_eq!(
+        findings[0].lxc_config_mapping_highlights,
+        [("100.conf".into(), SubID::UID), ("100.conf".into(), SubID::GID)]
+    );
+
+    let lines: Vec<_> = findings[0]
+        .config_line_highlights
+        .iter()
+        .map(|line| line.line)
+        .collect();
+
+    // alice logs in, so only the login users check reports her uid
+    assert_eq!(lines, [2, 5]);
+
+    let explanation = state.explain(findings[0], Path::new("/etc/pve/lxc"));
+
+    assert!(explanation.paragraphs[1].contains("users backup (uid 100034) from /etc/passwd"));
+    assert!(explanation.paragraphs[2].contains("groups disk (gid 6), alice (gid 1000) from /etc/group"));
+
+    state.settings.set_enabled(Check::IdmapLoginUsers, false);
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::IdmapHostAccounts)
+        .expect("host accounts finding");
+    let lines: Vec<_> = finding.config_line_highlights.iter().map(|line| line.line).collect();
+
+    assert_eq!(lines, [2, 3, 5]);
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 300000 65536\nlxc.idmap: g 0 300000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::IdmapHostAccounts));
+
+    Ok(())
+}
+
+#[test]
+fn test_device_ownership() -> color_eyre::Result<()> {
+    let config = "unprivileged: 1\n\
+                  dev0: /dev/dri/renderD128,gid=104\n\
+                  dev1: /dev/ttyUSB0,uid=70000,gid=70000\n\
+                  lxc.idmap: u 0 100000 65536\n\
+                  lxc.idmap: g 0 100000 65536\n";
+    let mut state = State::default();
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::DeviceOwnership)
+        .collect();
+
+    assert_eq!(findings.len(), 2);
+    assert_eq!(
+        findings[0].message,
+        "Device node is owned by a uid the container doesn't map"
+    );
+    assert_eq!(findings[0].config_line_highlights[0].key, "dev1");
+    assert_eq!(
+        findings[1].lxc_config_mapping_highlights,
+        [("100.conf".into(), SubID::GID)]
+    );
+    assert!(
+        state.explain(findings[0], Path::new("/etc/pve/lxc")).paragraphs[1]
+            .contains("/dev/ttyUSB0 is created in the container owned by uid 70000 and gid 70000")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_container_features() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\n\
+         features: nesting=1,mount=nfs;tmpfs\n\
+         lxc.idmap: u 0 100000 65536\n\
+         lxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), "features: keyctl=1,mount=nfs\n")?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/102.conf"),
+        "unprivileged: 1\nfeatures: keyctl=1,nesting=1,mount=fuse\n",
+    )?;
+    state.evaluate_findings();
+
+    let mut findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::ContainerFeatures)
+        .collect();
+
+    findings.sort_by_key(|f| &f.config_line_highlights[0].filename);
+
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].kind, FindingKind::Warning);
+    assert_eq!(findings[0].config_line_highlights[0].line, 2);
+    assert!(
+        state.explain(findings[0], Path::new("/etc/pve/lxc")).paragraphs[1].starts_with("mount= allows nfs, which")
+    );
+    assert_eq!(findings[1].kind, FindingKind::Info);
+    assert_eq!(findings[1].config_line_highlights[0].filename, "101.conf");
+
+    Ok(())
+}
+
+#[test]
+fn test_idmap_reserved_ranges() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1
+lxc.idmap: u 0 100000 65536
+lxc.idmap: g 0 100000 65536
+",
+    )?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        "unprivileged: 1
+         lxc.idmap: u 0 100000 65534
+         lxc.idmap: u 65534 65534 1
+         lxc.idmap: u 65535 600000 1
+         lxc.idmap: g 0 600000 65536
+",
+    )?;
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::IdmapReservedRanges)
+        .collect();
+
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].kind, FindingKind::Info);
+    assert_eq!(
+        findings[0].lxc_config_mapping_highlights,
+        [("101.conf".into(), SubID::UID), ("101.conf".into(), SubID::GID)]
+    );
+    assert_eq!(
+        findings[0]
+            .config_line_highlights
+            .iter()
+            .map(|line| line.line)
+            .collect::<Vec<_>>(),
+        [3, 4, 5]
+    );
+
+    let explanation = state.explain(findings[0], Path::new("/etc/pve/lxc"));
+
+    assert!(explanation.paragraphs[1].contains("u 65534 65534 1 reaches into 65534 (nobody)"));
+    assert!(explanation.paragraphs[2].contains("524288-1879048191 (systemd-nspawn pool)"));
+
+    // Info findings are listed after every problem, but before what is fine
+    let kinds: Vec<_> = state.findings.iter().map(|f| f.kind.sort_order()).collect();
+
+    assert!(kinds.is_sorted());
+    assert!(!FindingKind::Info.is_problem());
+
+    Ok(())
+}
+
+#[test]
+fn test_unsaved_changes() -> color_eyre::Result<()> {
+    use super::subid_edit::{EditField, SubidEditor};
+
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.subid_editor = Some(SubidEditor::new(&state.host_mapping));
+
+    assert!(state.unsaved_changes().is_empty());
+
+    let editor = state.subid_editor.as_mut().expect("editor");
+
+    editor.field = EditField::Count;
+    editor.input = Some("131072".into());
+    editor.commit_input()?;
+
+    assert_eq!(state.unsaved_changes(), ["/etc/subuid"]);
+    assert_eq!(state.quit_blockers(), ["Unsaved changes to /etc/subuid"]);
+
+    state.subid_editor = None;
+
+    assert!(state.unsaved_changes().is_empty());
+    assert!(state.quit_blockers().is_empty());
+
+    // Queued changes are lost on quitting too
+    state.changes.queue(QueuedChange {
+        description: "Add alice".to_string(),
+        writes: vec![PendingWrite {
+            path: "/etc/subuid".into(),
+            current: "root:100000:65536\n".to_string(),
+            proposed: "root:100000:65536\nalice:200000:65536\n".to_string(),
+        }],
+        steps: Vec::new(),
+        follow_up: Vec::new(),
+    })?;
+
+    assert_eq!(state.quit_blockers(), ["A queued change wasn't applied"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_idmap_editor_builds_on_queue() -> color_eyre::Result<()> {
+    let config_dir = Path::new("/etc/pve/lxc");
+    let path = config_dir.join("100.conf");
+    let content = "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n";
+    let mut state = State::default();
+
+    state.load_config(&path, content)?;
+    state.open_idmap_editor(config_dir)?;
+
+    assert_eq!(
+        state.idmap_editor.as_ref().expect("editor").values(),
+        ["u 0 100000 65536", "g 0 100000 65536"]
+    );
+
+    // Idmaps which are only queued are edited along with the others, rather than replaced
+    state.changes.queue(QueuedChange {
+        description: "Pass gid 1000 through".to_string(),
+        writes: vec![PendingWrite {
+            path: path.clone(),
+            current: content.to_string(),
+            proposed: "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 1000\n\
+                       lxc.idmap: g 1000 1000 1\nlxc.idmap: g 1001 101001 64535\n"
+                .to_string(),
+        }],
+        steps: Vec::new(),
+        follow_up: Vec::new(),
+    })?;
+    state.open_idmap_editor(config_dir)?;
+
+    let editor = state.idmap_editor.as_ref().expect("editor");
+
+    assert!(!editor.changed());
+    assert_eq!(
+        editor.values(),
+        [
+            "u 0 100000 65536",
+            "g 0 100000 1000",
+            "g 1000 1000 1",
+            "g 1001 101001 64535"
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_subid_symmetry() -> color_eyre::Result<()> {
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\nalice:200000:65536\n", SubID::UID)?;
+    state.load_subid("alice:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::SubidSymmetry)
+        .collect();
+
+    // alice has both, and root's range can't be mirrored since alice already has those gids
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].kind, FindingKind::Warning);
+    assert_eq!(findings[0].host_mapping_highlights, [("root".into(), SubID::UID)]);
+    assert_eq!(
+        findings[0].lxc_config_mapping_highlights,
+        [("100.conf".into(), SubID::UID)]
+    );
+    assert_eq!(findings[0].fix, None);
+
+    state.load_subid("alice:300000:65536\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::SubidSymmetry)
+        .expect("root still has no subgid entry");
+
+    assert_eq!(
+        finding.fix,
+        Some(Fix::MirrorSubidRange {
+            sub_id: SubID::GID,
+            start: 100000,
+            count: 65536,
+        })
+    );
+
+    let explanation = state.explain(finding, Path::new("/etc/pve/lxc"));
+
+    assert_eq!(
+        explanation.suggested.expect("a range to add").lines,
+        ["root:100000:65536"]
+    );
+
+    // A numeric owner is the same user as the login it belongs to
+    state.load_passwd("root:x:0:0:root:/root:/bin/bash\n");
+    state.load_subid("0:100000:65536\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::SubidSymmetry));
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_subid_files() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    std::fs::create_dir_all(dir.path().join("etc/pve/lxc"))?;
+
+    let metadata = Metadata::with_root_prefix(dir.path().to_path_buf(), None)?;
+
+    std::fs::write(&metadata.subuid_path, "root:100000:65536\n")?;
+
+    let (mut state, errors) = State::collect(&metadata, Settings::default());
+
+    // A missing file is a finding rather than a read error
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(state.missing_subids, [SubID::GID]);
+
+    let missing: Vec<_> = state.findings.iter().filter(|f| f.check == Check::SubidFiles).collect();
+
+    assert_eq!(missing.len(), 1);
+    assert_eq!(missing[0].kind, FindingKind::Bad);
+    assert_eq!(
+        missing[0].message,
+        "/etc/subgid does not exist, unprivileged containers cannot start"
+    );
+    assert_eq!(missing[0].fix, Some(Fix::CreateSubid(SubID::GID)));
+
+    let explanation = state.explain(missing[0], Path::new("/etc/pve/lxc"));
+
+    assert_eq!(
+        explanation.suggested.expect("a file to create").lines,
+        ["root:100000:65536"]
+    );
+
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.evaluate_findings();
+
+    assert!(state.missing_subids.is_empty());
+    assert!(!state.findings.iter().any(|f| f.check == Check::SubidFiles));
+
+    state.unload_subid(SubID::UID);
+    state.evaluate_findings();
+
+    assert!(state.host_mapping.subuid.is_empty());
+    assert_eq!(state.missing_subids, [SubID::UID]);
+
+    Ok(())
+}
+
+#[test]
+fn test_skipped_checks() -> color_eyre::Result<()> {
+    use crate::lxc::resolve_rootfs;
+    use crate::proxmox::storage::StorageConfig;
+
+    use super::skipped::SkipReason;
+
+    let dir = tempfile::tempdir()?;
+    let value = dir.path().display().to_string();
+    let mut state = State::default();
+    let skipped = |state: &State| -> Vec<_> {
+        state
+            .skipped_checks
+            .iter()
+            .map(|skipped| (skipped.filename.to_string(), skipped.check, skipped.reason))
+            .collect()
+    };
+
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        &format!("unprivileged: 1\nrootfs: {value}\nmp0: /mnt/data,mp=/data\n"),
+    )?;
+    // Rootfs checks don't apply to privileged containers
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), "rootfs: local-lvm:vm-101-disk-0\n")?;
+    state.evaluate_findings();
+
+    assert_eq!(
+        skipped(&state),
+        [
+            (
+                "100.conf".to_string(),
+                Check::RootfsOwnership,
+                SkipReason::RootfsNotFound
+            ),
+            (
+                "100.conf".to_string(),
+                Check::RootfsWritable,
+                SkipReason::RootfsNotFound
+            ),
+            (
+                "100.conf".to_string(),
+                Check::RootfsContents,
+                SkipReason::RootfsNotFound
+            ),
+            ("100.conf".to_string(), Check::MountOwnership, SkipReason::MountNotFound),
+        ]
+    );
+
+    state.runs_as_root = false;
+    state.settings.set_enabled(Check::RootfsContents, false);
+    state.evaluate_findings();
+
+    assert_eq!(
+        skipped(&state),
+        [
+            ("100.conf".to_string(), Check::RootfsOwnership, SkipReason::NotRoot),
+            ("100.conf".to_string(), Check::RootfsWritable, SkipReason::NotRoot),
+            ("100.conf".to_string(), Check::MountOwnership, SkipReason::NotRoot),
+        ]
+    );
+
+    state.settings.set_enabled(Check::RootfsContents, true);
+    state.load_rootfs_metadata(
+        value.clone(),
+        resolve_rootfs(&value, &StorageConfig::default())?,
+        std::fs::metadata(dir.path())?,
+    );
+    state.evaluate_findings();
+
+    assert_eq!(
+        skipped(&state),
+        [
+            ("100.conf".to_string(), Check::RootfsContents, SkipReason::NotScanned),
+            ("100.conf".to_string(), Check::MountOwnership, SkipReason::NotRoot),
+        ]
+    );
+    assert_eq!(state.container_detail("100.conf").expect("detail").skipped.len(), 2);
+
+    state.rootfs_checks = false;
+    state.evaluate_findings();
+
+    assert!(
+        state
+            .skipped_checks
+            .iter()
+            .all(|skipped| skipped.reason == SkipReason::RootfsChecksUnavailable)
+    );
+    assert_eq!(state.skipped_checks.len(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_subid_syntax() -> color_eyre::Result<()> {
+    use crate::app::parse_subid_map;
+    use crate::fs::subid::SubidError;
+
+    let content = "root:100000:65536\nalice:200000\n# bob\n:300000:65536\ncarol:4294967296:1 # too far\ndave:x:1\n";
+    let mut state = State::default();
+
+    state.load_subid(content, SubID::UID)?;
+    state.evaluate_findings();
+
+    // The rest of the file is still read
+    assert_eq!(state.host_mapping.subuid.len(), 1);
+
+    let errors: Vec<_> = state
+        .host_mapping
+        .subuid_invalid
+        .iter()
+        .map(|line| (line.line_number, line.error.clone()))
+        .collect();
+
+    assert_eq!(
+        errors,
+        [
+            (2, SubidError::FieldCount(2)),
+            (4, SubidError::EmptyOwner),
+            (
+                5,
+                SubidError::Number {
+                    field: "start",
+                    value: "4294967296".into()
+                }
+            ),
+            (
+                6,
+                SubidError::Number {
+                    field: "start",
+                    value: "x".into()
+                }
+            ),
+        ]
+    );
+
+    let finding = state
+        .findings
+        .iter()
+        .find(|f| f.check == Check::SubidSyntax)
+        .expect("invalid lines are reported");
+
+    assert_eq!(finding.kind, FindingKind::Bad);
+    assert_eq!(
+        finding.subid_line_highlights,
+        [(SubID::UID, 2), (SubID::UID, 4), (SubID::UID, 5), (SubID::UID, 6)]
+    );
+    assert_eq!(finding.id(), "subid-syntax:subuid");
+
+    let explanation = state.explain(finding, Path::new("/etc/pve/lxc"));
+
+    assert_eq!(explanation.offending[0].lines[0], "2: alice:200000");
+    assert!(
+        explanation
+            .paragraphs
+            .contains(&"Line 2: expected 3 fields `name:start:count`, found 2.".to_string())
+    );
+
+    // Invalid lines survive the file being rewritten by the editor
+    assert!(
+        state
+            .host_mapping
+            .subuid_comments
+            .iter()
+            .any(|comment| comment.text == "carol:4294967296:1 # too far")
+    );
+    assert!(parse_subid_map(content).is_err());
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::SubidSyntax));
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_idmaps() -> color_eyre::Result<()> {
+    let config = "unprivileged: 1\n\
+                  lxc.idmap: u 0 100000 65536\n\
+                  lxc.idmap: g 0 100000 65536\n\
+                  \n\
+                  [pre-setup]\n\
+                  unprivileged: 1\n\
+                  lxc.idmap: u 0 300000 65536\n\
+                  lxc.idmap: g 0 100000 65536\n\
+                  \n\
+                  [pve:pending]\n\
+                  lxc.idmap: u 0 400000 65536\n";
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), config)?;
+    state.evaluate_findings();
+
+    // Snapshots are only checked once turned on
+    assert!(!state.findings.iter().any(|f| f.check == Check::SnapshotIdmaps));
+
+    state.settings.set_check_snapshots(true);
+    state.evaluate_findings();
+
+    let findings: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|f| f.check == Check::SnapshotIdmaps)
+        .collect();
+
+    // Pending changes aren't a snapshot, and the live config is fine
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].kind, FindingKind::Bad);
+    assert_eq!(findings[0].config_line_highlights[0].line, 7);
+    assert_eq!(findings[0].config_line_highlights.len(), 1);
+    assert!(!state.findings.iter().any(|f| f.check == Check::IdmapHostRange));
+
+    let explanation = state.explain(findings[0], Path::new("/etc/pve/lxc"));
+
+    assert!(
+        explanation
+            .paragraphs
+            .contains(&"Line 7 belongs to the snapshot pre-setup.".to_string())
+    );
+
+    state.load_subid("root:100000:65536\nroot:300000:65536\n", SubID::UID)?;
+    state.evaluate_findings();
+
+    assert!(!state.findings.iter().any(|f| f.check == Check::SnapshotIdmaps));
+
+    Ok(())
+}
+
+#[test]
+fn test_affected_vmids() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    std::fs::create_dir_all(dir.path().join("etc/pve/lxc"))?;
+
+    let metadata = Metadata::with_root_prefix(dir.path().to_path_buf(), None)?;
+    let mut state = State::default();
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), "unprivileged: 1\n")?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 200000 65536\n",
+    )?;
+    state.load_config(Path::new("/etc/pve/lxc/102.conf"), "unprivileged: 0\n")?;
+
+    let added = PendingWrite {
+        path: metadata.subuid_path.clone(),
+        current: "root:100000:65536\n".to_string(),
+        proposed: "root:100000:65536\nroot:200000:65536\n".to_string(),
+    };
+
+    assert_eq!(state.affected_vmids(&metadata, &[added]), [(SubID::UID, "101")]);
+
+    // 101 has no gid idmaps of its own, so it maps the default range like 100
+    let edited = PendingWrite {
+        path: metadata.subgid_path.clone(),
+        current: "root:100000:65536\n".to_string(),
+        proposed: "root:100000:70000\n".to_string(),
+    };
+
+    assert_eq!(
+        state.affected_vmids(&metadata, &[edited]),
+        [(SubID::GID, "100"), (SubID::GID, "101")]
+    );
+
+    let reformatted = PendingWrite {
+        path: metadata.subuid_path.clone(),
+        current: "root: 100000 :65536\n".to_string(),
+        proposed: "root:100000:65536\n".to_string(),
+    };
+
+    assert!(state.affected_vmids(&metadata, &[reformatted]).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_reload_configs() -> color_eyre::Result<()> {
+    let path = Path::new("/etc/pve/lxc/100.conf");
+    let mut state = State::default();
+
+    state.load_config(path, "unprivileged: 1\nrootfs: local-lvm:vm-100-disk-0,size=8G\n")?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/101.conf"),
+        "unprivileged: 1\nrootfs: local-lvm:vm-101-disk-0,size=8G\n",
+    )?;
+
+    // Moved to another volume while the monitor wasn't watching, which is what is resolved again
+    let values = state.reload_configs(&[(
+        path.to_path_buf(),
+        "unprivileged: 1\nrootfs: local-zfs:subvol-100-disk-0,size=8G\nmp0: /srv/data,mp=/data\n".to_string(),
+    )])?;
+
+    assert_eq!(
+        values,
+        [
+            "local-zfs:subvol-100-disk-0,size=8G",
+            "/srv/data,mp=/data",
+            "local-lvm:vm-101-disk-0,size=8G"
+        ]
+    );
+    assert!(!state.is_pending(path));
+
+    Ok(())
+}
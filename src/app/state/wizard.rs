@@ -0,0 +1,540 @@
+//! Generating a consistent set of idmaps, subordinate id ranges and rootfs ownership for a
+//! container from a single host offset.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+use color_eyre::eyre::eyre;
+use compact_str::CompactString;
+use log::error;
+
+use super::{State, finding_vmid, subid_ranges_cover};
+use crate::followup::Change;
+use crate::fs::subid::SubID;
+use crate::linux::reserved::{self, ReservedRange};
+use crate::lxc::idmap::{ConfigIdMap, IdMap};
+use crate::lxc::{ID_SPACE_END, range_end};
+use crate::settings::MappingIntent;
+
+/// Ids a container gets of each kind, the same as PVE maps by default.
+pub const CONTAINER_IDS: u32 = 65536;
+/// Where PVE starts handing out host ids, so offsets are suggested in steps of
+/// [`CONTAINER_IDS`] from here.
+pub const FIRST_OFFSET: u32 = 100000;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WizardField {
+    Container,
+    Offset,
+    Passthrough,
+}
+
+impl WizardField {
+    const ALL: [WizardField; 3] = [WizardField::Container, WizardField::Offset, WizardField::Passthrough];
+
+    /// The field `delta` fields after this one, wrapping around.
+    pub fn cycle(self, delta: isize) -> Self {
+        let i = Self::ALL.iter().position(|field| *field == self).unwrap_or_default();
+
+        Self::ALL[(i as isize + delta).rem_euclid(Self::ALL.len() as isize) as usize]
+    }
+}
+
+/// The choices made so far in the idmap wizard.
+#[derive(Debug)]
+pub struct IdmapWizard {
+    /// The unprivileged configs to pick from.
+    pub configs: Vec<CompactString>,
+    pub selected: usize,
+    pub field: WizardField,
+    /// The first host id to map the container's ids to, as typed.
+    pub offset: String,
+    /// A host id to map to the same id in the container, as typed. Empty for none.
+    pub passthrough: String,
+    /// Which kinds of ids to generate idmaps for, initially as marked for the container.
+    pub intent: MappingIntent,
+}
+
+impl IdmapWizard {
+    pub fn filename(&self) -> Option<&CompactString> {
+        self.configs.get(self.selected)
+    }
+}
+
+/// Everything a container needs to map its ids onto the host ids from `offset` on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneratedMapping {
+    pub filename: CompactString,
+    pub offset: u32,
+    pub intent: MappingIntent,
+    /// The host id mapped to the same id in the container, if any.
+    pub passthrough: Option<u32>,
+    /// The `lxc.idmap` entries of the kinds in `intent`, which replace the container's own.
+    pub idmaps: Vec<IdMap>,
+    /// The container's own idmaps of the other kind, which are kept as they are.
+    pub kept: Vec<ConfigIdMap>,
+    /// The `root:start:count` entries /etc/subuid and /etc/subgid are missing for the idmaps.
+    pub subid_entries: Vec<(SubID, String)>,
+    /// The rootfs mountpoint and who owns it now, if it was looked at.
+    pub rootfs: Option<(PathBuf, u32, u32)>,
+    /// The host uid and gid the container's root maps to, which should own the rootfs. `None` for a
+    /// kept kind without an idmap for root.
+    pub expected_owner: (Option<u32>, Option<u32>),
+    /// Other containers whose idmaps already map some of the same host ids.
+    pub overlaps: Vec<CompactString>,
+    /// The reserved host id ranges the idmaps reach into.
+    pub reserved: Vec<&'static ReservedRange>,
+}
+
+impl GeneratedMapping {
+    /// What applying the mapping changes, for the follow-up checklist. The rootfs ownership is left
+    /// to the checklist, when it doesn't match the idmaps.
+    pub fn changes(&self) -> Vec<Change> {
+        let vmid = self.filename.trim_end_matches(".conf").to_string();
+        let mut changes: Vec<_> = self
+            .subid_entries
+            .iter()
+            .map(|(sub_id, _)| Change::SubidRangesAdded(*sub_id))
+            .collect();
+
+        if let Some((path, uid, gid)) = self.rootfs.as_ref().filter(|_| self.rootfs_needs_shift()) {
+            let (expected_uid, expected_gid) = self.expected_owner;
+
+            changes.push(Change::RootfsOwnerExpected {
+                vmid: vmid.clone(),
+                path: path.clone(),
+                uid: expected_uid.unwrap_or(*uid),
+                gid: expected_gid.unwrap_or(*gid),
+            });
+        }
+
+        changes.push(Change::ConfigEdited { vmid });
+        changes
+    }
+
+    /// The `lxc.idmap` values to write, uids first.
+    pub fn values(&self) -> Vec<String> {
+        [SubID::UID, SubID::GID]
+            .into_iter()
+            .flat_map(|kind| {
+                let generated = self
+                    .idmaps
+                    .iter()
+                    .filter(move |idmap| idmap.kind == kind)
+                    .map(IdMap::to_string);
+                let kept = self
+                    .kept
+                    .iter()
+                    .filter(move |idmap| idmap.kind() == Some(kind))
+                    .map(|idmap| idmap.value.to_string());
+
+                generated.chain(kept)
+            })
+            .collect()
+    }
+
+    /// Whether the rootfs is owned by someone other than the container's root on the host.
+    pub fn rootfs_needs_shift(&self) -> bool {
+        let (expected_uid, expected_gid) = self.expected_owner;
+
+        self.rootfs.as_ref().is_some_and(|(_, uid, gid)| {
+            expected_uid.is_some_and(|expected| expected != *uid)
+                || expected_gid.is_some_and(|expected| expected != *gid)
+        })
+    }
+
+    /// The expected owner as `uid:gid`, with `?` for unknown ids.
+    pub fn expected_owner_name(&self) -> String {
+        let name = |id: Option<u32>| id.map_or_else(|| "?".to_string(), |id| id.to_string());
+
+        format!("{}:{}", name(self.expected_owner.0), name(self.expected_owner.1))
+    }
+}
+
+impl State {
+    /// Remembers which ids container `vmid` is meant to map, so the symmetry check leaves containers
+    /// mapping one kind on purpose alone.
+    pub fn remember_mapping_intent(&mut self, vmid: &str, intent: MappingIntent) {
+        if self.settings.mapping_intent(vmid) == intent {
+            return;
+        }
+
+        self.settings.set_mapping_intent(vmid, intent);
+
+        if let Err(err) = self.settings.save() {
+            error!("Failed to save settings: {err:?}");
+        }
+    }
+
+    /// Opens the wizard on the container of the selected finding, or the first unprivileged
+    /// container when the finding isn't about one.
+    pub fn open_idmap_wizard(&mut self) -> color_eyre::Result<()> {
+        let configs: Vec<_> = self.unprivileged_configs().cloned().collect();
+
+        if configs.is_empty() {
+            return Err(eyre!("There are no unprivileged containers to generate idmaps for"));
+        }
+
+        let selected = self
+            .selected_finding
+            .and_then(|index| self.findings.get(index))
+            .and_then(|finding| finding_vmid(&self.lxc_configs, finding))
+            .and_then(|vmid| configs.iter().position(|filename| *filename == format!("{vmid}.conf")))
+            .unwrap_or(0);
+        let offset = self.suggested_offset(&configs[selected]).to_string();
+        let intent = self.marked_intent(&configs[selected]);
+
+        self.idmap_wizard = Some(IdmapWizard {
+            configs,
+            selected,
+            field: WizardField::Container,
+            offset,
+            passthrough: String::new(),
+            intent,
+        });
+
+        Ok(())
+    }
+
+    /// Picks another container in the wizard, suggesting a free offset for it.
+    pub fn move_wizard_selection(&mut self, delta: isize) {
+        let Some(wizard) = &self.idmap_wizard else {
+            return;
+        };
+        let selected = wizard
+            .selected
+            .saturating_add_signed(delta)
+            .min(wizard.configs.len().saturating_sub(1));
+        let offset = self.suggested_offset(&wizard.configs[selected]).to_string();
+        let intent = self.marked_intent(&wizard.configs[selected]);
+
+        if let Some(wizard) = &mut self.idmap_wizard {
+            wizard.selected = selected;
+            wizard.offset = offset;
+            wizard.intent = intent;
+        }
+    }
+
+    /// The intent persisted for config `filename`.
+    fn marked_intent(&self, filename: &str) -> MappingIntent {
+        self.settings
+            .mapping_intent(filename.strip_suffix(".conf").unwrap_or(filename))
+    }
+
+    /// The first offset from 100000 on, in steps of 65536, whose host ids no other container maps.
+    pub fn suggested_offset(&self, filename: &str) -> u32 {
+        (0u64..)
+            .map(|step| u64::from(FIRST_OFFSET) + step * u64::from(CONTAINER_IDS))
+            .take_while(|&start| start + u64::from(CONTAINER_IDS) <= ID_SPACE_END)
+            .filter_map(|start| u32::try_from(start).ok())
+            .find(|&start| self.mapped_by_others(filename, start).is_empty())
+            .unwrap_or(FIRST_OFFSET)
+    }
+
+    /// Works out what the wizard's choices amount to. Fails when the offset isn't usable.
+    pub fn generate_mapping(&self, wizard: &IdmapWizard) -> color_eyre::Result<GeneratedMapping> {
+        let filename = wizard.filename().ok_or_else(|| eyre!("No container selected"))?;
+        let offset: u32 = wizard
+            .offset
+            .trim()
+            .parse()
+            .map_err(|_| eyre!("{:?} is not a number between 0 and 4294967295", wizard.offset))?;
+
+        if range_end(offset, CONTAINER_IDS) > ID_SPACE_END {
+            return Err(eyre!(
+                "An offset of {offset} leaves less than {CONTAINER_IDS} host ids to map to"
+            ));
+        }
+
+        let passthrough = match wizard.passthrough.trim() {
+            "" => None,
+            id => match id.parse::<u32>() {
+                Ok(id) if id < CONTAINER_IDS => Some(id),
+                _ => {
+                    return Err(eyre!(
+                        "{id:?} can't be passed through, only ids below {CONTAINER_IDS} exist in the container"
+                    ));
+                },
+            },
+        };
+        let kinds = wizard.intent.kinds();
+        let idmaps: Vec<_> = kinds
+            .iter()
+            .flat_map(|&kind| generated_idmaps(kind, offset, passthrough))
+            .collect();
+        let kept = self
+            .idmaps(filename)
+            .iter()
+            // Included idmaps can't be rewritten, and malformed ones are better dropped
+            .filter(|idmap| idmap.include.is_none())
+            .filter(|idmap| idmap.kind().is_some_and(|kind| !kinds.contains(&kind)))
+            .cloned()
+            .collect();
+        let mut subid_entries = Vec::new();
+
+        for &sub_id in kinds {
+            if !self.root_has_subids(sub_id, offset, CONTAINER_IDS) {
+                subid_entries.push((sub_id, format!("root:{offset}:{CONTAINER_IDS}")));
+            }
+
+            if let Some(id) = passthrough
+                && !self.root_has_subids(sub_id, id, 1)
+            {
+                subid_entries.push((sub_id, format!("root:{id}:1")));
+            }
+        }
+
+        let root_host_id = if passthrough == Some(0) { 0 } else { offset };
+        let expected = |kind| {
+            if kinds.contains(&kind) {
+                return Some(root_host_id);
+            }
+
+            self.idmaps(filename).iter().find_map(|idmap| {
+                let parsed = idmap.parsed.as_ref().ok()?;
+
+                (parsed.kind == kind && parsed.container_id == 0).then_some(parsed.host_id)
+            })
+        };
+        let expected_owner = (expected(SubID::UID), expected(SubID::GID));
+        let rootfs = self
+            .lxc_configs
+            .get(filename)
+            .and_then(|config| config.section(None).get_rootfs().map(str::to_string))
+            .and_then(|value| self.rootfs_info.get(&value))
+            .map(|(location, metadata)| (location.mountpoint.clone(), metadata.uid(), metadata.gid()));
+
+        let mut reserved = Vec::new();
+
+        for idmap in &idmaps {
+            for range in reserved::colliding(idmap.host_id, idmap.size) {
+                if !reserved.contains(&range) {
+                    reserved.push(range);
+                }
+            }
+        }
+
+        Ok(GeneratedMapping {
+            filename: filename.clone(),
+            offset,
+            intent: wizard.intent,
+            passthrough,
+            idmaps,
+            kept,
+            subid_entries,
+            rootfs,
+            expected_owner,
+            overlaps: self.mapped_by_others(filename, offset),
+            reserved,
+        })
+    }
+
+    /// Whether root's subordinate ids already cover the `count` host ids from `start` on.
+    pub(super) fn root_has_subids(&self, sub_id: SubID, start: u32, count: u32) -> bool {
+        let entries = match sub_id {
+            SubID::UID => &self.host_mapping.subuid,
+            SubID::GID => &self.host_mapping.subgid,
+        };
+
+        subid_ranges_cover(
+            entries
+                .iter()
+                .filter(|entry| matches!(&*entry.host_user_id, "root" | "0")),
+            start,
+            range_end(start, count),
+        )
+    }
+
+    /// Configs other than `filename` with idmaps onto any of the host ids from `offset` on.
+    fn mapped_by_others(&self, filename: &str, offset: u32) -> Vec<CompactString> {
+        let end = range_end(offset, CONTAINER_IDS);
+
+        self.lxc_configs
+            .keys()
+            .filter(|other| *other != filename)
+            .filter(|other| {
+                self.idmaps(other).iter().any(|idmap| {
+                    idmap
+                        .parsed
+                        .as_ref()
+                        .is_ok_and(|parsed| u64::from(parsed.host_id) < end && u64::from(offset) < parsed.host_end())
+                })
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// The idmaps of one kind mapping the container's ids onto the host ids from `offset` on, except
+/// for `passthrough` which maps to the same id on the host.
+pub(super) fn generated_idmaps(kind: SubID, offset: u32, passthrough: Option<u32>) -> Vec<IdMap> {
+    let idmap = |container_id, host_id, size| IdMap {
+        kind,
+        container_id,
+        host_id,
+        size,
+    };
+    let Some(id) = passthrough else {
+        return vec![idmap(0, offset, CONTAINER_IDS)];
+    };
+    let after = id + 1;
+
+    [
+        idmap(0, offset, id),
+        idmap(id, id, 1),
+        idmap(after, offset + after, CONTAINER_IDS - after),
+    ]
+    .into_iter()
+    .filter(|idmap| idmap.size > 0)
+    .collect()
+}
+
+#[test]
+fn test_generate_mapping() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:131072\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), "unprivileged: 1\n")?;
+    state.load_config(Path::new("/etc/pve/lxc/102.conf"), "arch: amd64\n")?;
+    state.open_idmap_wizard()?;
+
+    let wizard = state.idmap_wizard.as_ref().expect("wizard");
+
+    // Privileged containers aren't offered, and 100's ids are taken
+    assert_eq!(wizard.configs, ["100.conf", "101.conf"]);
+    assert_eq!(wizard.offset, "100000");
+    assert_eq!(state.suggested_offset("101.conf"), 165536);
+
+    state.move_wizard_selection(1);
+
+    let wizard = state.idmap_wizard.as_ref().expect("wizard");
+    let generated = state.generate_mapping(wizard)?;
+
+    assert_eq!(generated.values(), ["u 0 165536 65536", "g 0 165536 65536"]);
+    // Only the gid range is wide enough already
+    assert_eq!(generated.subid_entries, [(SubID::UID, "root:165536:65536".to_string())]);
+    assert!(generated.overlaps.is_empty());
+    assert!(generated.reserved.is_empty());
+    assert!(!generated.rootfs_needs_shift());
+
+    let mut wizard = IdmapWizard {
+        configs: vec!["101.conf".into()],
+        selected: 0,
+        field: WizardField::Offset,
+        offset: "120000".into(),
+        passthrough: String::new(),
+        intent: MappingIntent::Both,
+    };
+
+    assert_eq!(state.generate_mapping(&wizard)?.overlaps, ["100.conf"]);
+
+    wizard.offset = "500000".into();
+
+    let reserved = state.generate_mapping(&wizard)?.reserved;
+
+    assert_eq!(reserved.len(), 1);
+    assert_eq!(reserved[0].owner, "systemd-nspawn pool");
+
+    wizard.offset = "4294967295".into();
+
+    assert!(state.generate_mapping(&wizard).is_err());
+
+    wizard.offset = "lots".into();
+
+    assert!(state.generate_mapping(&wizard).is_err());
+
+    // Passing the host's video group through to 100, leaving its uids alone
+    let wizard = IdmapWizard {
+        configs: vec!["100.conf".into()],
+        selected: 0,
+        field: WizardField::Passthrough,
+        offset: "100000".into(),
+        passthrough: "44".into(),
+        intent: MappingIntent::GidOnly,
+    };
+    let generated = state.generate_mapping(&wizard)?;
+
+    assert_eq!(
+        generated.values(),
+        ["u 0 100000 65536", "g 0 100000 44", "g 44 44 1", "g 45 100045 65491"]
+    );
+    assert_eq!(generated.subid_entries, [(SubID::GID, "root:44:1".to_string())]);
+    assert_eq!(generated.expected_owner, (Some(100000), Some(100000)));
+    assert_eq!(WizardField::Container.cycle(-1), WizardField::Passthrough);
+
+    Ok(())
+}
+
+#[test]
+fn test_generated_mapping_passes_checks() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    use crate::finding::FindingKind;
+
+    let mut state = State::default();
+
+    state.load_passwd("root:x:0:0::/root:/bin/bash\nalice:x:1000:1000::/home/alice:/bin/bash\n");
+    state.load_group("root:x:0:\nalice:x:1000:\n");
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), "unprivileged: 1\n")?;
+
+    // Sharing alice's files with the container
+    let wizard = IdmapWizard {
+        configs: vec!["100.conf".into()],
+        selected: 0,
+        field: WizardField::Passthrough,
+        offset: "100000".into(),
+        passthrough: "1000".into(),
+        intent: MappingIntent::Both,
+    };
+    let generated = state.generate_mapping(&wizard)?;
+
+    for sub_id in [SubID::UID, SubID::GID] {
+        let lines: String = ["root:100000:65536".to_string()]
+            .into_iter()
+            .chain(
+                generated
+                    .subid_entries
+                    .iter()
+                    .filter(|(kind, _)| *kind == sub_id)
+                    .map(|(_, line)| line.clone()),
+            )
+            .map(|line| line + "\n")
+            .collect();
+
+        state.load_subid(&lines, sub_id)?;
+    }
+
+    let config: String = ["unprivileged: 1".to_string()]
+        .into_iter()
+        .chain(
+            generated
+                .values()
+                .into_iter()
+                .map(|value| format!("lxc.idmap: {value}")),
+        )
+        .map(|line| line + "\n")
+        .collect();
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), &config)?;
+    state.evaluate_findings();
+
+    let complaints: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|finding| finding.kind == FindingKind::Bad || finding.kind == FindingKind::Warning)
+        .map(|finding| (finding.check, finding.message))
+        .collect();
+
+    assert_eq!(complaints, []);
+
+    Ok(())
+}
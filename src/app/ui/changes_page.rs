@@ -0,0 +1,82 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Widget};
+
+use super::footer::{Footer, FooterItem};
+use super::write_preview_popup::diff_line;
+use crate::changes::QueuedChange;
+
+/// Lists the queued changes, with the diff of the highlighted one next to them.
+pub struct ChangesPage<'s> {
+    changes: &'s [QueuedChange],
+    selected: usize,
+    scroll: usize,
+    footer: &'s [FooterItem],
+}
+
+impl<'s> ChangesPage<'s> {
+    pub fn new(changes: &'s [QueuedChange], selected: usize, scroll: usize, footer: &'s [FooterItem]) -> Self {
+        Self {
+            changes,
+            selected,
+            scroll,
+            footer,
+        }
+    }
+}
+
+/// The diff lines of a queued change, which are what the page scrolls through.
+pub fn change_diff_lines(change: &QueuedChange) -> Vec<Line<'static>> {
+    change
+        .writes
+        .iter()
+        .flat_map(|write| write.diff().lines().map(str::to_string).collect::<Vec<_>>())
+        .map(diff_line)
+        .collect()
+}
+
+impl Widget for ChangesPage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let [list_area, diff_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)]).areas(main_area);
+        let rows = self.changes.iter().enumerate().map(|(i, change)| {
+            let style = if i == self.selected {
+                Style::new().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::new()
+            };
+
+            Row::new([format!("{}. {}", i + 1, change.description)]).style(style)
+        });
+
+        Table::new(rows, [Constraint::Min(0)])
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Pending changes ({})", self.changes.len()))
+                    .title_alignment(Alignment::Center),
+            )
+            .render(list_area, buf);
+
+        let lines = match self.changes.get(self.selected) {
+            Some(change) => change_diff_lines(change).into_iter().skip(self.scroll).collect(),
+            None => vec![Line::from(
+                "Nothing is queued. Press q on a preview to queue its changes instead of writing them.",
+            )],
+        };
+
+        Paragraph::new(Text::from(lines))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Diff")
+                    .title_alignment(Alignment::Center),
+            )
+            .render(diff_area, buf);
+
+        Footer::new(self.footer).render(footer_area, buf);
+    }
+}
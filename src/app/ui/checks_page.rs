@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use ahash::RandomState;
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Row, Table, Widget};
+
+use super::footer::{Footer, FooterItem::*};
+use crate::app::state::CheckRun;
+use crate::check::Check;
+use crate::settings::Settings;
+
+/// Lists every check pupman knows about along with how it last went.
+pub struct ChecksPage<'s> {
+    settings: &'s Settings,
+    runs: &'s HashMap<Check, CheckRun, RandomState>,
+    selected: usize,
+}
+
+impl<'s> ChecksPage<'s> {
+    pub fn new(settings: &'s Settings, runs: &'s HashMap<Check, CheckRun, RandomState>, selected: usize) -> Self {
+        Self {
+            settings,
+            runs,
+            selected,
+        }
+    }
+}
+
+impl Widget for ChecksPage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let header = Row::new(["", "Check", "Last run", "Findings", "Description"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let mut rows = Vec::with_capacity(Check::ALL.len());
+
+        for (i, check) in Check::ALL.into_iter().enumerate() {
+            let enabled = self.settings.is_enabled(check);
+            let run = self.runs.get(&check);
+            let last_run = match run {
+                Some(run) => format!("{}s ago", run.last_run.elapsed().as_secs()),
+                None => "never".to_string(),
+            };
+            let findings = run.map_or_else(|| "-".to_string(), |run| run.findings.to_string());
+            let mut style = match run {
+                _ if !enabled => Style::default().fg(Color::DarkGray),
+                Some(run) if run.findings > 0 => Style::default().fg(Color::LightRed),
+                Some(_) => Style::default().fg(Color::LightGreen),
+                None => Style::default(),
+            };
+
+            if i == self.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+
+            rows.push(
+                Row::new([
+                    (if enabled { "[x]" } else { "[ ]" }).to_string(),
+                    check.name().to_string(),
+                    if enabled { last_run } else { "disabled".to_string() },
+                    findings,
+                    check.description().to_string(),
+                ])
+                .style(style),
+            );
+        }
+
+        let widths = [
+            Constraint::Length(3),
+            Constraint::Length(30),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Min(0),
+        ];
+
+        Table::new(rows, widths)
+            .header(header)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(
+                        "Checks ({}/{} enabled)",
+                        Check::ALL.iter().filter(|c| self.settings.is_enabled(**c)).count(),
+                        Check::ALL.len()
+                    ))
+                    .title_alignment(Alignment::Center),
+            )
+            .render(main_area, buf);
+
+        let items = &[
+            Key("Esc", "Back", Color::LightRed),
+            Div,
+            Key("↑↓", "Navigate", Color::LightGreen),
+            Key("Space", "Toggle", Color::LightGreen),
+        ];
+
+        Footer::new(items).render(footer_area, buf);
+    }
+}
@@ -0,0 +1,26 @@
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span, Text};
+
+use crate::app::state::confirm::Confirmation;
+
+/// The body of the dialog asking to confirm an action which loses work.
+pub fn confirm_popup_text(confirmation: &Confirmation) -> Text<'_> {
+    let mut lines = vec![Line::from(confirmation.action.question()), Line::from("")];
+
+    for reason in &confirmation.reasons {
+        lines.push(Line::from(format!("• {reason}")));
+    }
+
+    if !confirmation.reasons.is_empty() {
+        lines.push(Line::from(""));
+    }
+
+    lines.push(Line::from(vec![
+        Span::styled("Enter/y", Style::new().fg(Color::White)),
+        Span::raw(format!(" {}   ", confirmation.action.label())),
+        Span::styled("Esc/n", Style::new().fg(Color::White)),
+        Span::raw(" Cancel"),
+    ]));
+
+    Text::from(lines)
+}
@@ -0,0 +1,244 @@
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use chrono::Utc;
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Widget, Wrap};
+
+use super::footer::{Footer, FooterItem::*};
+use crate::app::state::detail::ContainerDetail;
+use crate::app::state::owner_history::{OwnerEventKind, OwnerHistory};
+use crate::fs::subid::SubID;
+use crate::settings::Theme;
+
+/// Cells in the rootfs ownership timeline.
+const TIMELINE_WIDTH: usize = 32;
+
+/// Everything about one container on a page of its own.
+pub struct ContainerDetailPage<'s> {
+    filename: &'s str,
+    detail: Option<ContainerDetail<'s>>,
+    lxc_config_dir: &'s Path,
+    theme: Theme,
+}
+
+impl<'s> ContainerDetailPage<'s> {
+    pub fn new(filename: &'s str, detail: Option<ContainerDetail<'s>>, lxc_config_dir: &'s Path) -> Self {
+        Self {
+            filename,
+            detail,
+            lxc_config_dir,
+            theme: Theme::Default,
+        }
+    }
+
+    /// Draws the badges of `theme`.
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+fn block(title: impl Into<String>) -> Block<'static> {
+    Block::default()
+        .borders(Borders::ALL)
+        .title(title.into())
+        .title_alignment(Alignment::Center)
+}
+
+fn kind_name(kind: Option<SubID>) -> &'static str {
+    match kind {
+        Some(SubID::UID) => "UID",
+        Some(SubID::GID) => "GID",
+        None => "?",
+    }
+}
+
+fn rootfs_lines(detail: &ContainerDetail) -> Vec<Line<'static>> {
+    let Some((value, info)) = detail.rootfs else {
+        return vec![Line::from("No rootfs set in the config")];
+    };
+    let mut lines = vec![Line::from(format!("Value: {value}"))];
+    let (uid, gid) = detail.expected_rootfs_owner();
+    let expected = format!(
+        "{}:{}",
+        uid.map_or_else(|| "?".to_string(), |uid| uid.to_string()),
+        gid.map_or_else(|| "?".to_string(), |gid| gid.to_string())
+    );
+
+    match info {
+        Some((location, metadata)) => {
+            let owner = format!("{}:{}", metadata.uid(), metadata.gid());
+            let style = if owner == expected {
+                Style::new()
+            } else {
+                Style::new().fg(Color::LightRed)
+            };
+
+            lines.push(Line::from(format!("Path: {}", location.mountpoint.display())));
+
+            if let Some(dataset) = &location.dataset {
+                lines.push(Line::from(format!("Dataset: {dataset}")));
+            }
+
+            lines.push(Line::from(vec![
+                Span::raw("Owner: "),
+                Span::styled(owner, style),
+                Span::raw(format!(
+                    " (mode {:o}), expected {expected}",
+                    metadata.permissions().mode() & 0o7777
+                )),
+            ]));
+        },
+        None => lines.push(Line::from(format!(
+            "Not looked at yet, expected to be owned by {expected}"
+        ))),
+    }
+
+    if let Some(history) = detail.owner_history {
+        lines.extend(owner_history_lines(history));
+    }
+
+    lines
+}
+
+/// A timeline of the session, with owner changes in yellow and the ownership finding appearing in
+/// red and resolving in green.
+fn owner_history_lines(history: &OwnerHistory) -> [Line<'static>; 2] {
+    let cells = history
+        .timeline(TIMELINE_WIDTH, Utc::now())
+        .into_iter()
+        .map(|cell| match cell {
+            None => Span::styled("·", Style::new().fg(Color::DarkGray)),
+            Some(OwnerEventKind::Owner(..)) => Span::styled("●", Style::new().fg(Color::LightYellow)),
+            Some(OwnerEventKind::FindingAppeared) => Span::styled("▲", Style::new().fg(Color::LightRed)),
+            Some(OwnerEventKind::FindingResolved) => Span::styled("▼", Style::new().fg(Color::LightGreen)),
+        });
+    let changes = history.owner_changes().count();
+    let summary = match history.owner_changes().last().map(|event| (event.at, event.kind)) {
+        Some((at, OwnerEventKind::Owner(uid, gid))) => format!(
+            "{changes} owner change{} this session, last to {uid}:{gid} at {}",
+            if changes == 1 { "" } else { "s" },
+            at.format("%H:%M:%S")
+        ),
+        _ => "Owner unchanged this session".to_string(),
+    };
+
+    [
+        Line::from(std::iter::once(Span::raw("History: ")).chain(cells).collect::<Vec<_>>()),
+        Line::from(summary).style(Style::new().fg(Color::Gray)),
+    ]
+}
+
+impl Widget for ContainerDetailPage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let title = format!(
+            "Container {} ({})",
+            self.filename.trim_end_matches(".conf"),
+            self.lxc_config_dir.join(self.filename).display()
+        );
+
+        Footer::new(&[Key("Esc", "Back", Color::LightRed)]).render(footer_area, buf);
+
+        let Some(detail) = self.detail else {
+            Paragraph::new("The config is no longer there")
+                .block(block(title))
+                .render(main_area, buf);
+            return;
+        };
+
+        let [idmap_area, lower_area] = Layout::vertical([
+            Constraint::Length(detail.idmaps.len().max(1) as u16 + 3),
+            Constraint::Min(0),
+        ])
+        .areas(main_area);
+        let [left_area, findings_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(lower_area);
+        let [rootfs_area, subid_area] = Layout::vertical([Constraint::Length(8), Constraint::Min(0)]).areas(left_area);
+        let header =
+            Row::new(["Kind", "ID", "Sub ID", "Size", "Source"]).style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = detail.idmaps.iter().map(|idmap| {
+            let source = match (&idmap.include, idmap.line) {
+                (Some(include), _) => include.display().to_string(),
+                (None, Some(line)) => format!("line {line}"),
+                (None, None) => String::new(),
+            };
+            let cells = match &idmap.parsed {
+                Ok(parsed) => [
+                    parsed.container_id.to_string(),
+                    parsed.host_id.to_string(),
+                    parsed.size.to_string(),
+                ],
+                Err(err) => [idmap.value.to_string(), err.to_string(), String::new()],
+            };
+            let [container, host, size] = cells;
+
+            Row::new([kind_name(idmap.kind()).to_string(), container, host, size, source])
+        });
+        let widths = [
+            Constraint::Length(6),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Min(0),
+        ];
+
+        Table::new(rows, widths)
+            .header(header)
+            .block(block(format!("{title}: lxc.idmap")))
+            .render(idmap_area, buf);
+
+        Paragraph::new(Text::from(rootfs_lines(&detail)))
+            .wrap(Wrap { trim: false })
+            .block(block("Rootfs"))
+            .render(rootfs_area, buf);
+
+        let subid_lines: Vec<_> = if detail.subids.is_empty() {
+            vec![Line::from("No subuid or subgid entries cover the idmaps")]
+        } else {
+            detail
+                .subids
+                .iter()
+                .map(|(sub_id, entry)| Line::from(format!("{}:{}  {}", sub_id.path(), entry.line_number, entry.line)))
+                .collect()
+        };
+
+        Paragraph::new(Text::from(subid_lines))
+            .block(block("Subordinate ids"))
+            .render(subid_area, buf);
+
+        let mut finding_lines: Vec<_> = if detail.findings.is_empty() {
+            vec![Line::from("Nothing specific to this container")]
+        } else {
+            detail
+                .findings
+                .iter()
+                .map(|finding| {
+                    Line::from(vec![
+                        Span::styled(finding.badge(self.theme), Style::new().fg(finding.base_fg())),
+                        Span::raw(finding.to_string()),
+                    ])
+                })
+                .collect()
+        };
+
+        // So a lack of findings isn't mistaken for everything having been checked
+        if !detail.skipped.is_empty() {
+            finding_lines.push(Line::default());
+            finding_lines.push(Line::from("Not checked:").style(Style::new().add_modifier(Modifier::BOLD)));
+            finding_lines.extend(detail.skipped.iter().map(|skipped| {
+                Line::from(format!("{}: {}", skipped.check.name(), skipped.reason.description()))
+                    .style(Style::new().fg(Color::Gray))
+            }));
+        }
+
+        Paragraph::new(Text::from(finding_lines))
+            .wrap(Wrap { trim: false })
+            .block(block("Findings"))
+            .render(findings_area, buf);
+    }
+}
@@ -0,0 +1,129 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Widget, Wrap};
+
+use super::footer::{Footer, FooterItem};
+use crate::app::state::conversion::{Conversion, ConversionItem};
+
+/// Walks through converting a privileged container to an unprivileged one, a step at a time.
+pub struct ConversionPage<'s> {
+    conversion: &'s Conversion,
+    steps: &'s [ConversionItem],
+    notes: &'s [String],
+    footer: &'s [FooterItem],
+}
+
+impl<'s> ConversionPage<'s> {
+    pub fn new(
+        conversion: &'s Conversion,
+        steps: &'s [ConversionItem],
+        notes: &'s [String],
+        footer: &'s [FooterItem],
+    ) -> Self {
+        Self {
+            conversion,
+            steps,
+            notes,
+            footer,
+        }
+    }
+}
+
+/// A step with whether it is done, marking the first one left as next.
+fn step_line(i: usize, item: &ConversionItem, next: bool, selected: bool) -> Line<'static> {
+    let (mark, style) = if item.done {
+        ("✓", Style::new().fg(Color::LightGreen))
+    } else if item.blocked.is_some() {
+        ("✗", Style::new().fg(Color::LightRed))
+    } else if next {
+        ("▶", Style::new().fg(Color::LightCyan))
+    } else {
+        (" ", Style::new().fg(Color::Gray))
+    };
+    let text_style = if selected {
+        Style::new().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::new()
+    };
+
+    Line::from(vec![
+        Span::styled(format!(" {mark} "), style),
+        Span::styled(format!("{}. {}", i + 1, item.description), text_style),
+    ])
+}
+
+impl Widget for ConversionPage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let [configs_area, steps_area] =
+            Layout::horizontal([Constraint::Percentage(25), Constraint::Percentage(75)]).areas(main_area);
+        let rows = self.conversion.configs.iter().enumerate().map(|(i, filename)| {
+            let style = if i == self.conversion.selected {
+                Style::new().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::new()
+            };
+
+            Row::new([filename.to_string()]).style(style)
+        });
+
+        Table::new(rows, [Constraint::Min(0)])
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Privileged container")
+                    .title_alignment(Alignment::Center),
+            )
+            .render(configs_area, buf);
+
+        let next = self.steps.iter().position(|item| !item.done);
+        let mut lines = vec![
+            Line::from(format!("Host offset: {}", self.conversion.offset)),
+            Line::from("Stop the container first, and don't start it again before every step is done."),
+            Line::from(""),
+        ];
+
+        for (i, item) in self.steps.iter().enumerate() {
+            lines.push(step_line(i, item, next == Some(i), i == self.conversion.step));
+
+            if let Some(reason) = &item.blocked {
+                lines.push(Line::styled(
+                    format!("      Can't be applied here, {reason}"),
+                    Style::new().fg(Color::LightRed),
+                ));
+            }
+        }
+
+        if next.is_none() {
+            lines.push(Line::from(""));
+            lines.push(Line::styled(
+                "Converted, the container can be started again.",
+                Style::new().fg(Color::LightGreen),
+            ));
+        }
+
+        if !self.notes.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::styled("Left as it is", Style::new().add_modifier(Modifier::BOLD)));
+            lines.extend(
+                self.notes
+                    .iter()
+                    .map(|note| Line::styled(format!("  {note}"), Style::new().fg(Color::Yellow))),
+            );
+        }
+
+        Paragraph::new(Text::from(lines))
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Conversion steps")
+                    .title_alignment(Alignment::Center),
+            )
+            .render(steps_area, buf);
+
+        Footer::new(self.footer).render(footer_area, buf);
+    }
+}
@@ -0,0 +1,79 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+
+use super::severity_line;
+use crate::app::state::explain::{Excerpt, Explanation};
+use crate::finding::Finding;
+
+/// Paragraphs are wrapped by hand since the popup sizes itself to its widest line.
+const WRAP_WIDTH: usize = 76;
+
+fn wrap(paragraph: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in paragraph.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > WRAP_WIDTH {
+            lines.push(std::mem::take(&mut current));
+        }
+
+        if !current.is_empty() {
+            current.push(' ');
+        }
+
+        current.push_str(word);
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+fn push_excerpt(lines: &mut Vec<Line<'static>>, excerpt: &Excerpt, color: Color) {
+    lines.push(Line::from(Span::styled(
+        format!("{}:", excerpt.source),
+        Style::new().add_modifier(Modifier::BOLD),
+    )));
+    lines.extend(
+        excerpt
+            .lines
+            .iter()
+            .map(|line| Line::from(Span::styled(format!("    {line}"), Style::new().fg(color)))),
+    );
+}
+
+/// The body of the explain popup, one entry per rendered line so it can be scrolled.
+pub fn explain_popup_lines(finding: &Finding, explanation: &Explanation) -> Vec<Line<'static>> {
+    let mut lines = vec![
+        Line::from(Span::styled(finding.message, Style::new().add_modifier(Modifier::BOLD))),
+        Line::from(Span::styled(
+            format!("Check: {}", finding.check.name()),
+            Style::new().fg(Color::Gray),
+        )),
+        severity_line(finding),
+    ];
+
+    for paragraph in &explanation.paragraphs {
+        lines.push(Line::from(""));
+        lines.extend(wrap(paragraph).into_iter().map(Line::from));
+    }
+
+    if !explanation.offending.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from("Offending lines:"));
+
+        for excerpt in &explanation.offending {
+            push_excerpt(&mut lines, excerpt, Color::LightRed);
+        }
+    }
+
+    if let Some(suggested) = &explanation.suggested {
+        lines.push(Line::from(""));
+        lines.push(Line::from("A working configuration looks like:"));
+        push_excerpt(&mut lines, suggested, Color::LightGreen);
+    }
+
+    lines
+}
@@ -1,37 +1,157 @@
-use super::Finding;
+use crate::app::state::filter::{finding_matches, match_range};
+use crate::app::ui::focus_style;
+use crate::finding::Finding;
+use crate::settings::{SortOrder, Theme};
 use ratatui::prelude::*;
 use ratatui::style::{Color, Modifier, Style};
-use ratatui::widgets::{Block, Borders};
+use ratatui::widgets::{Block, Borders, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget};
 
 #[derive(Clone, Copy, Debug)]
 pub struct FindingsList<'f> {
     pub findings: &'f [Finding],
     pub selected: Option<usize>,
+    pub sort_order: SortOrder,
+    pub focused: bool,
+    /// Only findings matching the filter are listed.
+    pub filter: &'f str,
+    /// Whether the filter is being typed into, which shows it in place of the title.
+    pub editing: bool,
+    /// How many files are still being read, which the findings wait for.
+    pub loading: usize,
+    pub theme: Theme,
+    /// The indices of the findings marked for fixing together.
+    pub marked: &'f [usize],
 }
 
 impl<'f> FindingsList<'f> {
-    pub fn new(findings: &'f [Finding], selected: Option<usize>) -> Self {
-        Self { findings, selected }
+    pub fn new(findings: &'f [Finding], selected: Option<usize>, sort_order: SortOrder) -> Self {
+        Self {
+            findings,
+            selected,
+            sort_order,
+            focused: false,
+            filter: "",
+            editing: false,
+            loading: 0,
+            theme: Theme::Default,
+            marked: &[],
+        }
+    }
+
+    /// Ticks the findings at `marked`.
+    pub fn marked(mut self, marked: &'f [usize]) -> Self {
+        self.marked = marked;
+        self
+    }
+
+    /// Draws the badges of `theme`.
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Notes that `files` are still being read, so no findings doesn't read as nothing to find.
+    pub fn loading(mut self, files: usize) -> Self {
+        self.loading = files;
+        self
+    }
+
+    /// Narrows the list down to the findings matching `filter`.
+    pub fn filter(mut self, filter: &'f str, editing: bool) -> Self {
+        self.filter = filter;
+        self.editing = editing;
+        self
+    }
+
+    /// Highlights the border of the list while it has focus.
+    pub fn focused(mut self, focused: bool) -> Self {
+        self.focused = focused;
+        self
+    }
+}
+
+impl FindingsList<'_> {
+    /// The indices of the listed findings.
+    pub fn visible(&self) -> Vec<usize> {
+        (0..self.findings.len())
+            .filter(|i| finding_matches(&self.findings[*i], self.filter))
+            .collect()
+    }
+
+    /// The first row of [`FindingsList::visible`] shown in a list `height` rows high. The selected
+    /// finding is kept roughly centered once the list doesn't fit.
+    pub fn offset(&self, height: usize) -> usize {
+        let visible = self.visible();
+        let selected = self
+            .selected
+            .and_then(|selected| visible.iter().position(|i| *i == selected));
+
+        selected
+            .unwrap_or_default()
+            .saturating_sub(height / 2)
+            .min(visible.len().saturating_sub(height))
     }
 }
 
 impl Widget for FindingsList<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let visible = self.visible();
+        let position = self
+            .selected
+            .and_then(|selected| visible.iter().position(|i| *i == selected));
+        let count = match position {
+            Some(position) => format!("{}/{}", position + 1, visible.len()),
+            None => visible.len().to_string(),
+        };
+        let mut title = if self.editing {
+            format!("Filter: {}█ ({count})", self.filter)
+        } else if !self.filter.is_empty() {
+            format!("Findings matching \"{}\" ({count})", self.filter)
+        } else {
+            format!("Findings by {} ({count})", self.sort_order.name())
+        };
+
+        if !self.marked.is_empty() {
+            title.push_str(&format!(" - {} marked", self.marked.len()));
+        }
+
+        if self.loading > 0 {
+            title.push_str(" - loading…");
+        }
         // Draw block around the list
         let block = Block::default()
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Gray))
-            .title("Findings")
+            .border_style(if self.focused {
+                focus_style(true)
+            } else {
+                Style::default().fg(Color::Gray)
+            })
+            .title(title)
             .title_alignment(Alignment::Center);
 
         let inner_area = block.inner(area);
 
         block.render(area, buf);
 
-        let max = self.findings.len().min(inner_area.height as usize);
+        if self.loading > 0 && visible.is_empty() {
+            let files = if self.loading == 1 { "file" } else { "files" };
+
+            Line::styled(
+                format!("Waiting for {} {files} to be read…", self.loading),
+                Style::default().fg(Color::DarkGray),
+            )
+            .centered()
+            .render(inner_area, buf);
+
+            return;
+        }
+
+        let height = usize::from(inner_area.height);
+        let offset = self.offset(height);
 
-        for (i, item) in self.findings.iter().take(max).enumerate() {
-            let y = inner_area.y + i as u16;
+        for (row, &i) in visible.iter().skip(offset).take(height).enumerate() {
+            let item = &self.findings[i];
+            let y = inner_area.y + row as u16;
             let is_selected = Some(i) == self.selected;
             let base_fg = item.base_fg();
             let selected_bg = item.selected_bg();
@@ -45,12 +165,42 @@ impl Widget for FindingsList<'_> {
             } else {
                 Modifier::empty()
             });
-            let prefix = if is_selected { "▶ " } else { "  " };
-            let badge_content = item.badge();
+            let prefix = match (is_selected, self.marked.contains(&i)) {
+                (true, true) => "▶✓",
+                (true, false) => "▶ ",
+                (false, true) => " ✓",
+                (false, false) => "  ",
+            };
+            let badge_content = item.badge(self.theme);
             let bullet = Span::styled(badge_content, Style::default().fg(base_fg));
-            let content = Line::from(vec![Span::raw(prefix), bullet, Span::styled(item.to_string(), style)]);
+            let message = item.to_string();
+            let mut spans = vec![Span::raw(prefix), bullet];
+
+            match match_range(&message, self.filter).filter(|_| !self.filter.is_empty()) {
+                Some(range) => spans.extend([
+                    Span::styled(message[..range.start].to_string(), style),
+                    Span::styled(
+                        message[range.clone()].to_string(),
+                        style.add_modifier(Modifier::UNDERLINED | Modifier::BOLD),
+                    ),
+                    Span::styled(message[range.end..].to_string(), style),
+                ]),
+                None => spans.push(Span::styled(message, style)),
+            }
+
+            let content = Line::from(spans);
 
             buf.set_line(inner_area.x, y, &content, inner_area.width);
         }
+
+        if visible.len() > height {
+            let mut state = ScrollbarState::new(visible.len().saturating_sub(height)).position(offset);
+
+            // Drawn over the right border, between the corners
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None)
+                .render(area.inner(Margin::new(0, 1)), buf, &mut state);
+        }
     }
 }
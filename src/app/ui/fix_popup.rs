@@ -0,0 +1,81 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+use super::severity_line;
+use crate::app::state::fix_options::{FixContext, FixStrategy};
+use crate::finding::{Finding, FindingKind};
+
+/// The body of the fix popup: what the finding is about, then the strategies to resolve it with
+/// `selected` highlighted.
+pub fn fix_popup_text(finding: &Finding, context: &FixContext, selected: usize) -> Text<'static> {
+    let heading = Style::new().add_modifier(Modifier::BOLD);
+    let mut lines = vec![
+        Line::styled(finding.message, heading),
+        Line::styled(format!("Check: {}", finding.check.name()), Style::new().fg(Color::Gray)),
+        severity_line(finding),
+    ];
+
+    for (title, items) in [("Files", &context.files), ("Ranges", &context.ranges)] {
+        if items.is_empty() {
+            continue;
+        }
+
+        lines.push(Line::from(""));
+        lines.push(Line::styled(format!("{title}:"), heading));
+        lines.extend(items.iter().map(|item| Line::from(format!("  {item}"))));
+    }
+
+    if let Some(correction) = &context.correction {
+        lines.push(Line::from(""));
+        lines.push(Line::styled(format!("Correction for {}:", correction.source), heading));
+        lines.extend(
+            correction
+                .lines
+                .iter()
+                .map(|line| Line::styled(format!("  {line}"), Style::new().fg(Color::LightGreen))),
+        );
+    }
+
+    lines.push(Line::from(""));
+
+    if context.strategies.is_empty() {
+        lines.push(Line::from(
+            "pupman can't fix this one for you yet. Press e on the finding for what to change by hand.",
+        ));
+
+        return Text::from(lines);
+    }
+
+    // Nothing needs doing about info findings, so their strategies are only offered
+    lines.push(Line::styled(
+        if finding.kind == FindingKind::Info {
+            "Optional changes:"
+        } else {
+            "Options:"
+        },
+        heading,
+    ));
+
+    for (i, strategy) in context.strategies.iter().enumerate() {
+        let style = if i == selected {
+            Style::new().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::new()
+        };
+
+        lines.push(Line::from(vec![
+            Span::raw(if i == selected { "▶ " } else { "  " }),
+            Span::styled(strategy.label(), style),
+        ]));
+    }
+
+    if let Some(FixStrategy::Apply(_)) = context.strategies.get(selected) {
+        lines.push(Line::from(""));
+        lines.push(
+            Line::from(format!("Headless: pupman fix --finding-id {}", finding.id()))
+                .style(Style::new().fg(Color::Gray)),
+        );
+    }
+
+    Text::from(lines)
+}
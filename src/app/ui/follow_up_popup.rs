@@ -0,0 +1,25 @@
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span, Text};
+
+use crate::followup::Step;
+
+/// The body of the checklist popup shown after a change was applied.
+pub fn follow_up_popup_text(steps: &[Step]) -> Text<'_> {
+    let mut lines = vec![Line::from("Change applied. To finish up:"), Line::from("")];
+
+    for step in steps {
+        lines.push(Line::from(format!("☐ {}", step.description)));
+
+        if let Some(command_line) = step.command_line() {
+            let label = if step.safe { "run with x" } else { "run manually" };
+
+            lines.push(Line::from(vec![
+                Span::raw("    "),
+                Span::styled(command_line, Style::new().fg(Color::LightYellow)),
+                Span::styled(format!(" ({label})"), Style::new().fg(Color::Gray)),
+            ]));
+        }
+    }
+
+    Text::from(lines)
+}
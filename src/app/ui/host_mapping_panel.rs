@@ -4,29 +4,115 @@ use ratatui::buffer::Buffer;
 use ratatui::layout::{Alignment, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::Text;
-use ratatui::widgets::{Block, Borders, Row, Table, Widget};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Widget, Wrap};
 
-use crate::app::ui::{Finding, HostMapping};
+use crate::app::state::empty::EmptyPanel;
+use crate::app::state::subid_edit::{EditField, SubidEditor};
+use crate::app::ui::{HostMapping, focus_style};
+use crate::finding::Finding;
 use crate::fs::subid::SubID;
 
 pub struct HostMappingPanel<'a> {
     mapping: &'a HostMapping,
     selected_finding: Option<&'a Finding>,
+    editor: Option<&'a SubidEditor>,
+    scroll: usize,
+    focused: bool,
+    loading: Vec<SubID>,
+    empty: Option<EmptyPanel>,
 }
 
 impl<'a> HostMappingPanel<'a> {
-    pub fn new(mapping: &'a HostMapping, selected_finding: Option<&'a Finding>) -> Self {
+    pub fn new(
+        mapping: &'a HostMapping,
+        selected_finding: Option<&'a Finding>,
+        editor: Option<&'a SubidEditor>,
+    ) -> Self {
         Self {
             mapping,
             selected_finding,
+            editor,
+            scroll: 0,
+            focused: false,
+            loading: Vec::new(),
+            empty: None,
         }
     }
+
+    /// Why there are no ranges to list, which is shown in place of the table.
+    pub fn empty(mut self, empty: Option<EmptyPanel>) -> Self {
+        self.empty = empty;
+        self
+    }
+
+    /// Shows the files of `sub_ids` as still being read, rather than as empty.
+    pub fn loading(mut self, sub_ids: Vec<SubID>) -> Self {
+        self.loading = sub_ids;
+        self
+    }
+
+    /// Skips the first `scroll` entries. The editor always shows all of its rows.
+    pub fn scroll(mut self, scroll: usize) -> Self {
+        self.scroll = scroll;
+        self
+    }
+
+    /// Highlights the border of the panel with focus.
+    pub fn focused(mut self, focused: bool) -> Self {
+        self.focused = focused;
+        self
+    }
 }
 
-impl Widget for HostMappingPanel<'_> {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let mut host_rows = Vec::new();
+/// The editor's rows in place of the files' entries, with the selected field under a cursor.
+fn editor_rows(editor: &SubidEditor) -> Vec<Row<'_>> {
+    editor
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let selected = i == editor.selected;
+            let cell = |field: EditField| {
+                let text = match &editor.input {
+                    Some(input) if selected && editor.field == field => format!("{input}█"),
+                    _ => row.field(field),
+                };
+                let mut style = Style::default();
+
+                if selected && editor.field == field {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
 
+                Text::from(text).style(style).alignment(Alignment::Center)
+            };
+            let range = match row.count.checked_sub(1) {
+                Some(last) => format!("{} → {}", row.start, u64::from(row.start) + u64::from(last)),
+                None => "empty".to_string(),
+            };
+
+            Row::new([
+                cell(EditField::User),
+                Text::from(match row.sub_id {
+                    SubID::UID => "UID",
+                    SubID::GID => "GID",
+                })
+                .alignment(Alignment::Center),
+                cell(EditField::Start),
+                cell(EditField::Count),
+                Text::from(range).alignment(Alignment::Center),
+            ])
+            .style(if selected {
+                Style::default().fg(Color::LightCyan)
+            } else {
+                Style::default()
+            })
+        })
+        .collect()
+}
+
+impl<'a> HostMappingPanel<'a> {
+    fn entry_rows(&self) -> Vec<Row<'a>> {
+        let mut host_rows = Vec::new();
         let entries = self
             .mapping
             .subuid
@@ -65,6 +151,86 @@ impl Widget for HostMappingPanel<'_> {
             );
         }
 
+        // Lines which aren't entries, in place of the ranges they were likely meant to grant
+        let invalid = self
+            .mapping
+            .subuid_invalid
+            .iter()
+            .zip(repeat(SubID::UID))
+            .chain(self.mapping.subgid_invalid.iter().zip(repeat(SubID::GID)));
+
+        for (line, sub_id) in invalid {
+            let mut style = Style::default().fg(Color::LightRed);
+
+            if let Some(finding) = self.selected_finding
+                && finding.subid_line_highlights.contains(&(sub_id, line.line_number))
+            {
+                style = style.bg(finding.selected_bg()).fg(Color::Black);
+            }
+
+            host_rows.push(
+                Row::new([
+                    Text::from(line.line.trim().to_string()).alignment(Alignment::Center),
+                    Text::from(match sub_id {
+                        SubID::UID => "UID",
+                        SubID::GID => "GID",
+                    })
+                    .alignment(Alignment::Center),
+                    Text::from(format!("line {}", line.line_number)).alignment(Alignment::Center),
+                    Text::from("invalid").alignment(Alignment::Center),
+                ])
+                .style(style),
+            );
+        }
+
+        for sub_id in &self.loading {
+            host_rows.push(
+                Row::new([
+                    Text::from(sub_id.path()).alignment(Alignment::Center),
+                    Text::from(match sub_id {
+                        SubID::UID => "UID",
+                        SubID::GID => "GID",
+                    })
+                    .alignment(Alignment::Center),
+                    Text::from("loading…").alignment(Alignment::Center),
+                ])
+                .style(Style::default().fg(Color::DarkGray)),
+            );
+        }
+
+        host_rows
+    }
+}
+
+impl Widget for HostMappingPanel<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (host_rows, title) = match self.editor {
+            Some(editor) => (editor_rows(editor), "Editing Host Mappings (/etc/subuid /etc/subgid)"),
+            None => (
+                self.entry_rows().into_iter().skip(self.scroll).collect(),
+                "Host Mappings (/etc/subuid /etc/subgid)",
+            ),
+        };
+
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(focus_style(self.focused))
+            .title_alignment(Alignment::Center);
+
+        if self.editor.is_none()
+            && host_rows.is_empty()
+            && let Some(empty) = &self.empty
+        {
+            Paragraph::new(empty.message())
+                .style(Style::default().fg(Color::DarkGray))
+                .wrap(Wrap { trim: true })
+                .block(block)
+                .render(area, buf);
+
+            return;
+        }
+
         let host_header = Row::new([
             Text::from("ID").alignment(Alignment::Center),
             Text::from("Kind").alignment(Alignment::Center),
@@ -76,12 +242,7 @@ impl Widget for HostMappingPanel<'_> {
 
         Table::new(host_rows, &[])
             .header(host_header)
-            .block(
-                Block::default()
-                    .title("Host Mappings (/etc/subuid /etc/subgid)")
-                    .borders(Borders::ALL)
-                    .title_alignment(Alignment::Center),
-            )
+            .block(block)
             .render(area, buf);
     }
 }
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Local};
+use ratatui::prelude::*;
+use ratatui::widgets::Paragraph;
+
+use crate::metadata::Metadata;
+
+/// A line describing the host above the panels, so screenshots say where they were taken.
+pub struct HostSummary<'m> {
+    metadata: &'m Metadata,
+    configs: usize,
+    last_evaluation: Option<DateTime<Local>>,
+    config_dir_unavailable: bool,
+}
+
+impl<'m> HostSummary<'m> {
+    pub fn new(metadata: &'m Metadata, configs: usize, last_evaluation: Option<DateTime<Local>>) -> Self {
+        Self {
+            metadata,
+            configs,
+            last_evaluation,
+            config_dir_unavailable: false,
+        }
+    }
+
+    /// Marks the configs as possibly outdated, while their directory is gone.
+    pub fn config_dir_unavailable(mut self, unavailable: bool) -> Self {
+        self.config_dir_unavailable = unavailable;
+        self
+    }
+}
+
+impl Widget for HostSummary<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let metadata = self.metadata;
+        let pve = match metadata.pve_version_name() {
+            Some(version) => format!("PVE {version}"),
+            None => "LXC".to_string(),
+        };
+        let configs = match self.configs {
+            1 => "1 config".to_string(),
+            count => format!("{count} configs"),
+        };
+        let mut fields = vec![
+            ("host", metadata.hostname.clone().unwrap_or_else(|| "?".to_string())),
+            ("", pve),
+        ];
+
+        if let Some(kernel) = &metadata.kernel {
+            fields.push(("kernel", kernel.clone()));
+        }
+
+        if let Some(root_prefix) = &metadata.root_prefix {
+            fields.push(("copied to", root_prefix.display().to_string()));
+        }
+
+        fields.push(("", configs));
+        fields.push((
+            "refreshed",
+            self.last_evaluation
+                .map_or_else(|| "not yet".to_string(), |time| time.format("%H:%M:%S").to_string()),
+        ));
+
+        let mut spans = Vec::new();
+
+        for (i, (label, value)) in fields.into_iter().enumerate() {
+            if i != 0 {
+                spans.push(Span::styled("  ·  ", Style::new().fg(Color::DarkGray)));
+            }
+
+            if !label.is_empty() {
+                spans.push(Span::styled(format!("{label} "), Style::new().fg(Color::Gray)));
+            }
+
+            spans.push(Span::styled(value, Style::new().fg(Color::White)));
+        }
+
+        if self.config_dir_unavailable {
+            spans.push(Span::styled("  ·  ", Style::new().fg(Color::DarkGray)));
+            spans.push(Span::styled(
+                "config directory unavailable, retrying",
+                Style::new().fg(Color::LightRed).add_modifier(Modifier::BOLD),
+            ));
+        }
+
+        Paragraph::new(Line::from(spans))
+            .alignment(Alignment::Center)
+            .render(area, buf);
+    }
+}
@@ -0,0 +1,196 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Widget, Wrap};
+
+use super::footer::{Footer, FooterItem::*};
+use crate::app::state::wizard::{GeneratedMapping, IdmapWizard, WizardField};
+use crate::fs::subid::SubID;
+use crate::settings::MappingIntent;
+
+/// Generates the idmaps, subordinate ids and rootfs ownership for a container from one offset.
+pub struct IdmapWizardPage<'s> {
+    wizard: &'s IdmapWizard,
+    generated: &'s color_eyre::Result<GeneratedMapping>,
+    previewing: bool,
+}
+
+impl<'s> IdmapWizardPage<'s> {
+    pub fn new(wizard: &'s IdmapWizard, generated: &'s color_eyre::Result<GeneratedMapping>, previewing: bool) -> Self {
+        Self {
+            wizard,
+            generated,
+            previewing,
+        }
+    }
+}
+
+fn heading(text: &str) -> Line<'static> {
+    Line::styled(text.to_string(), Style::new().add_modifier(Modifier::BOLD))
+}
+
+/// Everything the wizard would write or expects, in the order it is applied.
+fn generated_lines(generated: &GeneratedMapping) -> Vec<Line<'static>> {
+    let mut lines = vec![heading(&format!("lxc.idmap entries of {}", generated.filename))];
+
+    lines.extend(
+        generated
+            .values()
+            .into_iter()
+            .map(|value| Line::from(format!("  lxc.idmap: {value}"))),
+    );
+
+    if generated.intent != MappingIntent::Both {
+        let kept = if generated.intent.kinds() == [SubID::UID] {
+            "gid"
+        } else {
+            "uid"
+        };
+
+        lines.push(Line::styled(
+            format!("  {kept} idmaps are kept as they are"),
+            Style::new().fg(Color::DarkGray),
+        ));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(heading("/etc/subuid and /etc/subgid"));
+
+    if generated.subid_entries.is_empty() {
+        lines.push(Line::from("  root already has the host ids, nothing to add"));
+    }
+
+    for (sub_id, entry) in &generated.subid_entries {
+        lines.push(Line::styled(
+            format!("  {}: add {entry}", sub_id.path()),
+            Style::new().fg(Color::LightGreen),
+        ));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(heading("Rootfs ownership"));
+
+    let owner = generated.expected_owner_name();
+
+    lines.push(match &generated.rootfs {
+        Some((path, uid, gid)) if generated.rootfs_needs_shift() => Line::styled(
+            format!(
+                "  {} is owned by {uid}:{gid}, it should be {owner}. Its files need shifting by the same amount.",
+                path.display()
+            ),
+            Style::new().fg(Color::LightRed),
+        ),
+        Some((path, _, _)) => Line::from(format!("  {} is already owned by {owner}", path.display())),
+        None => Line::from(format!("  Should be owned by {owner}, the rootfs wasn't looked at")),
+    });
+
+    if !generated.overlaps.is_empty() {
+        let overlaps: Vec<_> = generated.overlaps.iter().map(|filename| filename.as_str()).collect();
+
+        lines.push(Line::from(""));
+        lines.push(Line::styled(
+            format!(
+                "Warning: {} already map some of these host ids, so their containers could access each other's files",
+                overlaps.join(", ")
+            ),
+            Style::new().fg(Color::Yellow),
+        ));
+    }
+
+    if !generated.reserved.is_empty() {
+        let reserved: Vec<_> = generated.reserved.iter().map(|range| range.to_string()).collect();
+
+        lines.push(Line::from(""));
+        lines.push(Line::styled(
+            format!(
+                "Note: these host ids reach into {}, which are reserved by convention",
+                reserved.join(", ")
+            ),
+            Style::new().fg(Color::Yellow),
+        ));
+    }
+
+    lines
+}
+
+impl Widget for IdmapWizardPage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let [configs_area, result_area] =
+            Layout::horizontal([Constraint::Percentage(25), Constraint::Percentage(75)]).areas(main_area);
+        let rows = self.wizard.configs.iter().enumerate().map(|(i, filename)| {
+            let mut style = Style::default();
+
+            if i == self.wizard.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+
+                if self.wizard.field == WizardField::Container {
+                    style = style.fg(Color::LightCyan);
+                }
+            }
+
+            Row::new([filename.to_string()]).style(style)
+        });
+
+        Table::new(rows, [Constraint::Min(0)])
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Container")
+                    .title_alignment(Alignment::Center),
+            )
+            .render(configs_area, buf);
+
+        let input = |label: &'static str, value: &str, field: WizardField| {
+            let (style, cursor) = if self.wizard.field == field {
+                (Style::new().fg(Color::LightCyan).add_modifier(Modifier::REVERSED), "█")
+            } else {
+                (Style::new(), "")
+            };
+
+            Line::from(vec![label.into(), Span::styled(format!("{value}{cursor}"), style)])
+        };
+        let mut lines = vec![
+            Line::from(format!("Maps: {}", self.wizard.intent.name())),
+            input("Host offset: ", &self.wizard.offset, WizardField::Offset),
+            input("Pass through id: ", &self.wizard.passthrough, WizardField::Passthrough),
+            Line::from(""),
+        ];
+
+        match self.generated {
+            Ok(generated) => lines.extend(generated_lines(generated)),
+            Err(err) => lines.push(Line::styled(err.to_string(), Style::new().fg(Color::LightRed))),
+        }
+
+        Paragraph::new(Text::from(lines))
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Generated mapping")
+                    .title_alignment(Alignment::Center),
+            )
+            .render(result_area, buf);
+
+        let items = if self.previewing {
+            vec![
+                Key("Esc", "Cancel", Color::LightRed),
+                Key("↑↓", "Scroll", Color::LightGreen),
+                Key("Enter", "Write", Color::LightGreen),
+            ]
+        } else {
+            vec![
+                Key("Esc", "Back", Color::LightRed),
+                Div,
+                Key("Tab", "Field", Color::LightGreen),
+                Key("↑↓", "Container", Color::LightGreen),
+                Key("0-9", "Ids", Color::LightGreen),
+                Key("i", "Uids/gids", Color::LightGreen),
+                Key("Enter", "Apply", Color::LightGreen),
+            ]
+        };
+
+        Footer::new(&items).render(footer_area, buf);
+    }
+}
@@ -0,0 +1,57 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+use crate::app::state::import::{ImportStatus, SubidImport};
+
+fn status_color(status: ImportStatus) -> Color {
+    match status {
+        ImportStatus::New => Color::LightGreen,
+        ImportStatus::Duplicate => Color::Gray,
+        ImportStatus::Conflicting | ImportStatus::Invalid => Color::LightRed,
+    }
+}
+
+/// The body of the subuid/subgid import popup: the pasted text while editing, or the validated
+/// entries while reviewing.
+pub fn import_popup_text(import: &SubidImport) -> Text<'_> {
+    let mut lines = Vec::new();
+
+    if !import.reviewing {
+        lines.push(Line::from("Paste or type name:start:count entries, one per line."));
+        lines.push(Line::from(""));
+        lines.extend(import.text.lines().map(Line::from));
+        lines.push(Line::from("█"));
+
+        return Text::from(lines);
+    }
+
+    lines.push(Line::from(format!("Target: {}", import.target.name())));
+    lines.push(Line::from(""));
+
+    if import.entries.is_empty() {
+        lines.push(Line::from("Nothing to import."));
+    }
+
+    for (i, entry) in import.entries.iter().enumerate() {
+        let mut style = Style::default().fg(status_color(entry.status));
+
+        if i == import.selected {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+
+        lines.push(Line::from(vec![
+            Span::raw(if i == import.selected { "▶ " } else { "  " }),
+            Span::styled(
+                format!(
+                    "[{}] {:<32} {}",
+                    if entry.selected { "x" } else { " " },
+                    entry.line,
+                    entry.status.name()
+                ),
+                style,
+            ),
+        ]));
+    }
+
+    Text::from(lines)
+}
@@ -5,11 +5,13 @@ use super::footer::{Footer, FooterItem::*};
 
 pub struct LogsPage<'s> {
     state: &'s TuiWidgetState,
+    /// How many lines are kept before the oldest are dropped.
+    kept: usize,
 }
 
 impl<'s> LogsPage<'s> {
-    pub fn new(state: &'s TuiWidgetState) -> Self {
-        Self { state }
+    pub fn new(state: &'s TuiWidgetState, kept: usize) -> Self {
+        Self { state, kept }
     }
 }
 
@@ -29,6 +31,7 @@ impl Widget for LogsPage<'_> {
             .output_target(true)
             .output_file(true)
             .output_line(true)
+            .title_log(format!("Logs (the last {} lines are kept)", self.kept))
             .state(self.state)
             .render(main_area, buf);
 
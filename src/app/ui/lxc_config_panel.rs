@@ -7,16 +7,33 @@ use ratatui::buffer::Buffer;
 use ratatui::layout::{Alignment, Rect};
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::Text;
-use ratatui::widgets::{Block, Borders, Row, Table, Widget};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Widget, Wrap};
 
-use crate::app::ui::Finding;
+use crate::app::state::effective_idmaps;
+use crate::app::state::empty::EmptyPanel;
+use crate::app::state::filter::contains_ignore_case;
+use crate::app::state::idmap_edit::{IdMapEditor, IdMapField};
+use crate::app::ui::focus_style;
+use crate::finding::Finding;
 use crate::fs::subid::SubID;
-use crate::lxc::config::Config;
+use crate::lxc::config::{Config, ConfigFormat};
+use crate::lxc::idmap::ConfigIdMap;
+use crate::proxmox::dialect::Dialect;
 
 pub struct LXCConfigPanel<'a> {
     configs: &'a IndexMap<CompactString, Config, RandomState>,
     selected_finding: Option<&'a Finding>,
     lxc_config_dir: &'a Path,
+    dialect: Dialect,
+    editor: Option<&'a IdMapEditor>,
+    lxc_defaults: &'a [ConfigIdMap],
+    lxc_defaults_applied: bool,
+    selected: Option<&'a CompactString>,
+    focused: bool,
+    filter: Option<&'a str>,
+    loading: Vec<&'a str>,
+    lxc_defaults_loading: bool,
+    empty: Option<EmptyPanel>,
 }
 
 impl<'a> LXCConfigPanel<'a> {
@@ -24,13 +41,155 @@ impl<'a> LXCConfigPanel<'a> {
         configs: &'a IndexMap<CompactString, Config, RandomState>,
         selected_finding: Option<&'a Finding>,
         lxc_config_dir: &'a Path,
+        dialect: Dialect,
+        editor: Option<&'a IdMapEditor>,
     ) -> Self {
         Self {
             configs,
             selected_finding,
             lxc_config_dir,
+            dialect,
+            editor,
+            lxc_defaults: &[],
+            lxc_defaults_applied: false,
+            selected: None,
+            focused: false,
+            filter: None,
+            loading: Vec::new(),
+            lxc_defaults_loading: false,
+            empty: None,
         }
     }
+
+    /// Why no containers are listed, which is shown in place of the table.
+    pub fn empty(mut self, empty: Option<EmptyPanel>) -> Self {
+        self.empty = empty;
+        self
+    }
+
+    /// Lists the configs `filenames` as still being read. While default.conf is too, idmaps the
+    /// containers lack may still come from it, so they aren't shown as missing.
+    pub fn loading(mut self, filenames: Vec<&'a str>, lxc_defaults: bool) -> Self {
+        self.loading = filenames;
+        self.lxc_defaults_loading = lxc_defaults;
+        self
+    }
+
+    /// Marks the container picked for its detail page.
+    pub fn selected(mut self, filename: Option<&'a CompactString>) -> Self {
+        self.selected = filename;
+        self
+    }
+
+    /// Only lists the configs whose name contains `filter`.
+    pub fn filter(mut self, filter: Option<&'a str>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Whether the config is listed at all, which only unprivileged ones are.
+    fn lists(&self, filename: &str, config: &Config) -> bool {
+        self.dialect.is_unprivileged(&config.section(None), self.lxc_defaults)
+            && self.filter.is_none_or(|filter| contains_ignore_case(filename, filter))
+    }
+
+    /// Highlights the border of the panel with focus.
+    pub fn focused(mut self, focused: bool) -> Self {
+        self.focused = focused;
+        self
+    }
+
+    /// Also lists the idmaps of /etc/lxc/default.conf, dimmed when no plain LXC config uses them.
+    pub fn lxc_defaults(mut self, idmaps: &'a [ConfigIdMap]) -> Self {
+        self.lxc_defaults = idmaps;
+        self.lxc_defaults_applied = self.configs.values().any(|config| config.format() == ConfigFormat::Lxc);
+        self
+    }
+
+    /// The idmaps `config` ends up with, which are those of default.conf when it is a plain LXC
+    /// config without any.
+    fn idmaps(&self, config: &'a Config) -> &'a [ConfigIdMap] {
+        effective_idmaps(config, self.lxc_defaults)
+    }
+
+    /// The config and id kind of each row of the panel, in the order they're drawn. Rows of the
+    /// idmaps from default.conf are left out.
+    pub fn row_targets(&self) -> Vec<(&'a CompactString, Option<SubID>)> {
+        let mut targets = Vec::new();
+
+        for (filename, config) in self.configs {
+            if !self.lists(filename, config) {
+                continue;
+            }
+
+            let kinds: Vec<_> = self.idmaps(config).iter().map(|idmap| idmap.kind()).collect();
+
+            targets.extend(kinds.iter().map(|kind| (filename, *kind)));
+
+            // Missing idmaps get a row of their own
+            for sub_id in [SubID::UID, SubID::GID] {
+                if !kinds.contains(&Some(sub_id)) {
+                    targets.push((filename, Some(sub_id)));
+                }
+            }
+        }
+
+        targets
+    }
+}
+
+/// The editor's rows in place of the config's own idmaps, with the selected field under a cursor.
+fn editor_rows(editor: &IdMapEditor) -> Vec<Row<'_>> {
+    if editor.rows.is_empty() {
+        return vec![Row::new([
+            Text::from(&*editor.filename).alignment(Alignment::Center),
+            Text::from("No idmaps, press a to add one").alignment(Alignment::Center),
+        ])];
+    }
+
+    editor
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let selected = i == editor.selected;
+            let cell = |field: IdMapField| {
+                let text = match &editor.input {
+                    Some(input) if selected && editor.field == field => format!("{input}█"),
+                    _ => field.value(row).to_string(),
+                };
+                let mut style = Style::default();
+
+                if selected && editor.field == field {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+
+                Text::from(text).style(style).alignment(Alignment::Center)
+            };
+            let range = match row.size.checked_sub(1) {
+                Some(last) => format!("{} → {}", row.host_id, u64::from(row.host_id) + u64::from(last)),
+                None => "empty".to_string(),
+            };
+
+            Row::new([
+                Text::from(if i == 0 { &*editor.filename } else { "" }).alignment(Alignment::Center),
+                Text::from(match row.kind {
+                    SubID::UID => "UID",
+                    SubID::GID => "GID",
+                })
+                .alignment(Alignment::Center),
+                cell(IdMapField::Container),
+                cell(IdMapField::Host),
+                cell(IdMapField::Size),
+                Text::from(range).alignment(Alignment::Center),
+            ])
+            .style(if selected {
+                Style::default().fg(Color::LightCyan)
+            } else {
+                Style::default()
+            })
+        })
+        .collect()
 }
 
 impl Widget for LXCConfigPanel<'_> {
@@ -46,74 +205,95 @@ impl Widget for LXCConfigPanel<'_> {
         .style(Style::default().add_modifier(Modifier::BOLD));
 
         let mut rows = Vec::new();
+        let (unknown, unknown_range) = if self.lxc_defaults_loading && self.lxc_defaults_applied {
+            ("…", "loading default.conf")
+        } else {
+            ("?", "? → ?")
+        };
 
         for (filename, config) in self.configs {
-            let section = config.section(None);
+            if !self.lists(filename, config) {
+                continue;
+            }
 
-            if section.get_unprivileged() != Some("1") {
+            if let Some(editor) = self.editor.filter(|editor| editor.filename == *filename) {
+                rows.extend(editor_rows(editor));
                 continue;
             }
 
+            let start = rows.len();
             let mut first = true;
             let mut has_user_idmap = false;
             let mut has_group_idmap = false;
 
-            for idmap in section.get_lxc_idmaps() {
-                let filename_display = if first {
-                    first = false;
-                    filename
-                } else {
-                    ""
+            for idmap in self.idmaps(config) {
+                // Idmaps pulled in through lxc.include show which file they came from
+                let filename_display = match (first, idmap.include.as_deref().and_then(|i| i.file_name())) {
+                    (true, Some(include)) => format!("{filename} ↳ {}", include.to_string_lossy()),
+                    (false, Some(include)) => format!("↳ {}", include.to_string_lossy()),
+                    (true, None) => filename.to_string(),
+                    (false, None) => String::new(),
                 };
 
-                let mut idmap = idmap.trim().split(' ');
-                let Some(kind) = idmap.next() else {
-                    unreachable!("Invalid ID map entry kind");
-                };
-                let Some(host_user_id) = idmap.next() else {
-                    unreachable!("Invalid ID map entry host user id");
-                };
-                let Some(host_sub_id) = idmap.next() else {
-                    unreachable!("Invalid ID map entry host sub id");
-                };
-                let Some(host_sub_id_size) = idmap.next() else {
-                    unreachable!("Invalid ID map entry host sub id count");
-                };
-                let sub_id = if kind == "u" {
-                    has_user_idmap = true;
-                    SubID::UID
-                } else if kind == "g" {
-                    has_group_idmap = true;
-                    SubID::GID
-                } else {
-                    unreachable!("Invalid ID map entry kind");
+                first = false;
+
+                let sub_id = idmap.kind();
+                let cells = match &idmap.parsed {
+                    Ok(parsed) => [
+                        parsed.container_id.to_string(),
+                        parsed.host_id.to_string(),
+                        parsed.size.to_string(),
+                        format!("{} → {}", parsed.host_id, parsed.host_end() - 1),
+                    ],
+                    // Shown as written, the finding for it explains what is wrong
+                    Err(_) => {
+                        let mut fields = idmap.value.split_whitespace().skip(1).map(str::to_string);
+
+                        [
+                            fields.next().unwrap_or_default(),
+                            fields.next().unwrap_or_default(),
+                            fields.next().unwrap_or_default(),
+                            "invalid".to_string(),
+                        ]
+                    },
                 };
 
+                match sub_id {
+                    Some(SubID::UID) => has_user_idmap = true,
+                    Some(SubID::GID) => has_group_idmap = true,
+                    None => {},
+                }
+
                 let mut style = Style::default();
 
-                if let Some(finding) = self.selected_finding {
-                    if finding
-                        .lxc_config_mapping_highlights
-                        .contains(&(filename.clone(), sub_id))
-                    {
-                        style = style.bg(finding.selected_bg()).fg(Color::Black);
-                    }
+                if let Some(finding) = self.selected_finding
+                    && (sub_id.is_some_and(|sub_id| {
+                        finding
+                            .lxc_config_mapping_highlights
+                            .contains(&(filename.clone(), sub_id))
+                    }) || finding
+                        .config_line_highlights
+                        .iter()
+                        .any(|line| line.filename == *filename && idmap.line == Some(line.line)))
+                {
+                    style = style.bg(finding.selected_bg()).fg(Color::Black);
                 }
 
+                let [container_start, host_start, count, range] = cells;
+
                 rows.push(
                     Row::new([
                         Text::from(filename_display).alignment(Alignment::Center),
-                        Text::from(if kind == "u" { "UID" } else { "GID" }).alignment(Alignment::Center),
-                        Text::from(host_user_id).alignment(Alignment::Center),
-                        Text::from(host_sub_id.to_string()).alignment(Alignment::Center),
-                        Text::from(host_sub_id_size).alignment(Alignment::Center),
-                        Text::from(format!(
-                            "{host_sub_id} → {}",
-                            host_sub_id.parse::<u32>().expect("fixme")
-                                + host_sub_id_size.parse::<u32>().expect("fixme")
-                                - 1
-                        ))
+                        Text::from(match sub_id {
+                            Some(SubID::UID) => "UID",
+                            Some(SubID::GID) => "GID",
+                            None => "?",
+                        })
                         .alignment(Alignment::Center),
+                        Text::from(container_start).alignment(Alignment::Center),
+                        Text::from(host_start).alignment(Alignment::Center),
+                        Text::from(count).alignment(Alignment::Center),
+                        Text::from(range).alignment(Alignment::Center),
                     ])
                     .style(style),
                 );
@@ -126,23 +306,22 @@ impl Widget for LXCConfigPanel<'_> {
 
                 let mut style = Style::default();
 
-                if let Some(finding) = self.selected_finding {
-                    if finding
+                if let Some(finding) = self.selected_finding
+                    && finding
                         .lxc_config_mapping_highlights
                         .contains(&(filename.clone(), SubID::UID))
-                    {
-                        style = style.bg(finding.selected_bg()).fg(Color::Black);
-                    }
+                {
+                    style = style.bg(finding.selected_bg()).fg(Color::Black);
                 }
 
                 rows.push(
                     Row::new([
                         Text::from(&**filename).alignment(Alignment::Center),
                         Text::from("UID").alignment(Alignment::Center),
-                        Text::from("?").alignment(Alignment::Center),
-                        Text::from("?").alignment(Alignment::Center),
-                        Text::from("?").alignment(Alignment::Center),
-                        Text::from("? → ?").alignment(Alignment::Center),
+                        Text::from(unknown).alignment(Alignment::Center),
+                        Text::from(unknown).alignment(Alignment::Center),
+                        Text::from(unknown).alignment(Alignment::Center),
+                        Text::from(unknown_range).alignment(Alignment::Center),
                     ])
                     .style(style),
                 );
@@ -153,34 +332,124 @@ impl Widget for LXCConfigPanel<'_> {
 
                 let mut style = Style::default();
 
-                if let Some(finding) = self.selected_finding {
-                    if finding
+                if let Some(finding) = self.selected_finding
+                    && finding
                         .lxc_config_mapping_highlights
                         .contains(&(filename.clone(), SubID::GID))
-                    {
-                        style = style.bg(finding.selected_bg()).fg(Color::Black);
-                    }
+                {
+                    style = style.bg(finding.selected_bg()).fg(Color::Black);
                 }
 
                 rows.push(
                     Row::new([
                         Text::from(filename_display).alignment(Alignment::Center),
                         Text::from("GID").alignment(Alignment::Center),
-                        Text::from("?").alignment(Alignment::Center),
-                        Text::from("?").alignment(Alignment::Center),
-                        Text::from("?").alignment(Alignment::Center),
-                        Text::from("? → ?").alignment(Alignment::Center),
+                        Text::from(unknown).alignment(Alignment::Center),
+                        Text::from(unknown).alignment(Alignment::Center),
+                        Text::from(unknown).alignment(Alignment::Center),
+                        Text::from(unknown_range).alignment(Alignment::Center),
                     ])
                     .style(style),
                 );
             }
+
+            if self.selected == Some(filename) {
+                for row in &mut rows[start..] {
+                    *row = std::mem::take(row)
+                        .style(Style::default().fg(Color::LightCyan).add_modifier(Modifier::REVERSED));
+                }
+            }
+        }
+
+        for filename in &self.loading {
+            if self
+                .filter
+                .is_some_and(|filter| !contains_ignore_case(filename, filter))
+            {
+                continue;
+            }
+
+            rows.push(
+                Row::new([
+                    Text::from(*filename).alignment(Alignment::Center),
+                    Text::from("").alignment(Alignment::Center),
+                    Text::from("loading…").alignment(Alignment::Center),
+                ])
+                .style(Style::default().fg(Color::DarkGray)),
+            );
         }
 
+        let title = match self.editor {
+            Some(editor) => format!(
+                "Editing LXC Mappings ({})",
+                self.lxc_config_dir.join(&*editor.filename).display()
+            ),
+            None => format!("LXC Mappings ({})", self.lxc_config_dir.display()),
+        };
         let block = Block::default()
-            .title(format!("LXC Mappings ({})", self.lxc_config_dir.display()))
+            .title(title)
             .borders(Borders::ALL)
+            .border_style(focus_style(self.focused))
             .title_alignment(Alignment::Center);
 
+        // The idmaps of default.conf alone aren't worth a table
+        if rows.is_empty()
+            && let Some(empty) = &self.empty
+        {
+            Paragraph::new(empty.message())
+                .style(Style::default().fg(Color::DarkGray))
+                .wrap(Wrap { trim: true })
+                .block(block)
+                .render(area, buf);
+
+            return;
+        }
+
+        let defaults_style = if self.lxc_defaults_applied {
+            Style::default()
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+
+        for (i, idmap) in self.lxc_defaults.iter().enumerate() {
+            let filename = idmap
+                .include
+                .as_deref()
+                .and_then(Path::file_name)
+                .map(|f| f.to_string_lossy());
+            let (container_start, host_start, count, range) = match &idmap.parsed {
+                Ok(parsed) => (
+                    parsed.container_id.to_string(),
+                    parsed.host_id.to_string(),
+                    parsed.size.to_string(),
+                    format!("{} → {}", parsed.host_id, parsed.host_end() - 1),
+                ),
+                Err(_) => (String::new(), String::new(), String::new(), "invalid".to_string()),
+            };
+
+            rows.push(
+                Row::new([
+                    Text::from(if i == 0 {
+                        filename.unwrap_or_default().into_owned()
+                    } else {
+                        String::new()
+                    })
+                    .alignment(Alignment::Center),
+                    Text::from(match idmap.kind() {
+                        Some(SubID::UID) => "UID",
+                        Some(SubID::GID) => "GID",
+                        None => "?",
+                    })
+                    .alignment(Alignment::Center),
+                    Text::from(container_start).alignment(Alignment::Center),
+                    Text::from(host_start).alignment(Alignment::Center),
+                    Text::from(count).alignment(Alignment::Center),
+                    Text::from(range).alignment(Alignment::Center),
+                ])
+                .style(defaults_style),
+            );
+        }
+
         Table::new(rows, &[]).header(header).block(block).render(area, buf);
     }
 }
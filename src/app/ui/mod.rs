@@ -1,45 +1,253 @@
+use crate::app::parse_subid_lines;
+use crate::app::state::Focus;
+use crate::app::state::preview::WritePreview;
+use crate::app::state::shift::OwnershipShift;
 use crate::app::ui::host_mapping_panel::HostMappingPanel;
 use crate::app::ui::lxc_config_panel::LXCConfigPanel;
 use crate::app::ui::rootfs_panel::RootFSPanel;
-use crate::fs::subid::SubID;
+use crate::finding::{Finding, FindingKind};
+use crate::fs::backup;
+use crate::fs::subid::{InvalidSubidLine, SubID, SubidComment, comment_lines};
+use crate::linux::DiskSpace;
 
 use super::App;
+use ahash::RandomState;
 use compact_str::CompactString;
 use footer::{Footer, FooterItem};
 use logs_page::LogsPage;
 use ratatui::buffer::Buffer;
-use ratatui::layout::{Alignment, Rect};
+use ratatui::layout::{Alignment, Margin, Position, Rect};
 use ratatui::layout::{Constraint, Layout};
-use ratatui::style::{Color, Style};
-use ratatui::text::Text;
-use ratatui::widgets::{Block, BorderType, Borders, Paragraph, Widget};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, BorderType, Borders, Widget};
 use tui_widgets::popup::Popup;
 
-use std::fmt::Display;
+use std::collections::HashMap;
 
+mod changes_page;
+mod checks_page;
+mod confirm_popup;
+mod container_detail_page;
+mod conversion_page;
+mod explain_popup;
 mod findings_list;
+mod fix_popup;
+mod follow_up_popup;
 mod footer;
 mod host_mapping_panel;
+mod host_summary;
+mod idmap_wizard_page;
+mod import_popup;
 mod logs_page;
 mod lxc_config_panel;
 mod rootfs_panel;
+mod settings_page;
+mod shift_popup;
+mod source_page;
+mod stats_page;
+mod theme;
+mod write_preview_popup;
 
+use changes_page::ChangesPage;
+pub use changes_page::change_diff_lines;
+use checks_page::ChecksPage;
+use confirm_popup::confirm_popup_text;
+use container_detail_page::ContainerDetailPage;
+use conversion_page::ConversionPage;
+pub use explain_popup::explain_popup_lines;
 use findings_list::FindingsList;
+use fix_popup::fix_popup_text;
+use follow_up_popup::follow_up_popup_text;
+use host_summary::HostSummary;
+use idmap_wizard_page::IdmapWizardPage;
+use import_popup::import_popup_text;
+pub use settings_page::SettingOption;
+use settings_page::SettingsPage;
+use shift_popup::{shift_footer_items, shift_popup_text};
+use source_page::SourcePage;
+use stats_page::StatsPage;
+use write_preview_popup::write_preview_footer_items;
+pub use write_preview_popup::write_preview_popup_lines;
+
+/// The border style of a main view panel, highlighted while it has focus.
+fn focus_style(focused: bool) -> Style {
+    if focused {
+        Style::default().fg(Color::LightCyan)
+    } else {
+        Style::default()
+    }
+}
+
+fn outer_block() -> Block<'static> {
+    Block::bordered()
+        .title("Proxmox UnPrivileged Manager")
+        .title_alignment(Alignment::Center)
+        .borders(Borders::TOP)
+        .border_type(BorderType::Rounded)
+}
+
+/// Where the panels of the main view are drawn.
+pub struct MainAreas {
+    pub summary: Rect,
+    pub host: Rect,
+    pub config: Rect,
+    pub rootfs: Rect,
+    pub findings: Rect,
+    pub footer: Rect,
+}
+
+impl App {
+    /// Lays out the main view within the frame `area`.
+    pub fn main_areas(&self, area: Rect) -> MainAreas {
+        let host = &self.state.host_mapping;
+        let [summary, main_area, footer] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)])
+                .areas(outer_block().inner(area));
+        let [left_area, findings] =
+            Layout::horizontal([Constraint::Percentage(75), Constraint::Percentage(25)]).areas(main_area);
+        let host_rows = match &self.state.subid_editor {
+            Some(editor) => editor.rows.len(),
+            None => host.row_count(),
+        };
+        let [host, config, rootfs] = Layout::vertical([
+            Constraint::Length(3 + host_rows as u16),
+            Constraint::Min(2),
+            Constraint::Percentage(25),
+        ])
+        .areas(left_area);
+
+        MainAreas {
+            summary,
+            host,
+            config,
+            rootfs,
+            findings,
+            footer,
+        }
+    }
+
+    /// The index of the finding a click at `position` in the main view selects. Clicking an entry
+    /// of the host or LXC mappings selects the first finding highlighting it.
+    pub fn finding_at(&self, area: Rect, position: Position) -> Option<usize> {
+        let areas = self.main_areas(area);
+        // Table rows start below the top border and the header
+        let table_row = |area: Rect| position.y.checked_sub(area.y + 2).map(usize::from);
+        let findings = &self.state.findings;
+
+        if areas.findings.contains(position) {
+            let list = FindingsList::new(findings, self.state.selected_finding, self.state.settings.sort_order())
+                .filter(&self.state.filter, false);
+            let inner = areas.findings.inner(Margin::new(1, 1));
+            let row = usize::from(position.y.checked_sub(inner.y)?);
+
+            return list
+                .visible()
+                .get(list.offset(usize::from(inner.height)) + row)
+                .copied();
+        }
+
+        if areas.host.contains(position) {
+            let host = &self.state.host_mapping;
+            let (user, sub_id) = host
+                .subuid
+                .iter()
+                .map(|entry| (&entry.host_user_id, SubID::UID))
+                .chain(host.subgid.iter().map(|entry| (&entry.host_user_id, SubID::GID)))
+                .nth(table_row(areas.host)? + self.state.host_mapping_scroll)?;
+
+            return findings.iter().position(|finding| {
+                finding
+                    .host_mapping_highlights
+                    .iter()
+                    .any(|(highlight, kind)| highlight == user && *kind == sub_id)
+            });
+        }
+
+        if areas.config.contains(position) {
+            let panel = LXCConfigPanel::new(
+                &self.state.lxc_configs,
+                None,
+                &self.metadata.lxc_config_dir,
+                self.state.dialect,
+                None,
+            )
+            .lxc_defaults(&self.state.default_idmaps)
+            .filter(self.state.config_filter());
+            let (filename, sub_id) = *panel.row_targets().get(table_row(areas.config)?)?;
+            let highlights = |finding: &&Finding| {
+                finding
+                    .lxc_config_mapping_highlights
+                    .iter()
+                    .any(|(highlight, kind)| highlight == filename && sub_id.is_none_or(|sub_id| *kind == sub_id))
+            };
+            let mentions = |finding: &&Finding| {
+                finding
+                    .config_line_highlights
+                    .iter()
+                    .any(|line| line.filename == *filename)
+            };
+
+            return findings
+                .iter()
+                .position(|finding| highlights(&finding))
+                .or_else(|| findings.iter().position(|finding| mentions(&finding)));
+        }
+
+        None
+    }
+}
 
 impl Widget for &App {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        self.render_view(area, buf);
+
+        // Drawn last, over whichever page is open
+        if let Some(confirmation) = &self.state.confirmation {
+            Popup::new(confirm_popup_text(confirmation))
+                .title("Confirm")
+                .style(Style::new().fg(Color::LightRed).bg(Color::Rgb(48, 0, 0)))
+                .render(area, buf);
+        }
+
+        theme::apply(self.state.settings.theme(), area, buf);
+    }
+}
+
+impl App {
     /// Renders the user interface widgets.
     ///
     // This is where you add new widgets.
     // See the following resources:
     // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
     // - https://github.com/ratatui/ratatui/tree/master/examples
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let host = &self.state.host_mapping;
-        let outer_block = Block::bordered()
-            .title("Proxmox UnPrivileged Manager")
-            .title_alignment(Alignment::Center)
-            .borders(Borders::TOP)
-            .border_type(BorderType::Rounded);
+    fn render_view(&self, area: Rect, buf: &mut Buffer) {
+        let unsaved = self.state.unsaved_changes();
+        let pending = self.state.changes.len();
+        let mut outer_block = outer_block();
+
+        if pending > 0 {
+            outer_block = outer_block.title(
+                Line::styled(
+                    match pending {
+                        1 => " ◆ 1 pending change ".to_string(),
+                        count => format!(" ◆ {count} pending changes "),
+                    },
+                    Style::new().fg(Color::LightCyan).add_modifier(Modifier::BOLD),
+                )
+                .left_aligned(),
+            );
+        }
+
+        if !unsaved.is_empty() {
+            outer_block = outer_block.title(
+                Line::styled(
+                    format!(" ● Unsaved changes to {} ", unsaved.join(", ")),
+                    Style::new().fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+                )
+                .right_aligned(),
+            );
+        }
 
         outer_block.clone().render(area, buf);
 
@@ -50,120 +258,638 @@ impl Widget for &App {
         }
 
         if self.state.show_logs_page {
-            LogsPage::new(&self.state.logger_page_state).render(inner_area, buf);
+            LogsPage::new(
+                &self.state.logger_page_state,
+                self.state.settings.retention().log_entries,
+            )
+            .render(inner_area, buf);
             return;
         }
 
-        if self.state.show_settings_page {
-            // Render settings page
-            Paragraph::new("Settings page is not yet implemented")
-                .alignment(Alignment::Center)
+        if let Some(view) = &self.state.source_view {
+            SourcePage::new(view).render(inner_area, buf);
+            return;
+        }
+
+        if let Some(filename) = &self.state.container_detail {
+            ContainerDetailPage::new(
+                filename,
+                self.state.container_detail(filename),
+                &self.metadata.lxc_config_dir,
+            )
+            .theme(self.state.settings.theme())
+            .render(inner_area, buf);
+            return;
+        }
+
+        if self.state.show_stats_page {
+            StatsPage::new(&self.state.stats, &self.history, self.metadata.pve_version_name()).render(inner_area, buf);
+            return;
+        }
+
+        if self.state.show_checks_page {
+            ChecksPage::new(&self.state.settings, &self.state.check_runs, self.state.selected_check)
                 .render(inner_area, buf);
             return;
         }
 
+        if let Some(wizard) = &self.state.idmap_wizard {
+            let generated = self.state.generate_mapping(wizard);
+
+            IdmapWizardPage::new(wizard, &generated, self.state.write_preview.is_some()).render(inner_area, buf);
+
+            if let Some(preview) = &self.state.write_preview {
+                render_write_preview(preview, pending, inner_area, buf);
+            }
+
+            return;
+        }
+
+        if let Some(conversion) = &self.state.conversion {
+            let steps = self.state.conversion_steps(conversion);
+            let notes = self.state.conversion_notes(conversion);
+            let footer = if let Some(preview) = &self.state.write_preview {
+                write_preview_footer_items(preview, pending)
+            } else if let Some(shift) = &self.state.ownership_shift {
+                shift_footer_items(shift)
+            } else {
+                vec![
+                    FooterItem::Key("Esc", "Back", Color::LightRed),
+                    FooterItem::Div,
+                    FooterItem::Key("↑↓", "Step", Color::LightGreen),
+                    FooterItem::Key("n", "Next container", Color::LightGreen),
+                    FooterItem::Key("Enter", "Apply step", Color::LightGreen),
+                ]
+            };
+
+            ConversionPage::new(conversion, &steps, &notes, &footer).render(inner_area, buf);
+
+            if let Some(shift) = &self.state.ownership_shift {
+                render_shift_popup(shift, inner_area, buf);
+            }
+
+            if let Some(preview) = &self.state.write_preview {
+                render_write_preview(preview, pending, inner_area, buf);
+            }
+
+            return;
+        }
+
+        if self.state.show_changes_page {
+            let footer = match &self.state.write_preview {
+                Some(preview) => write_preview_footer_items(preview, pending),
+                None => vec![
+                    FooterItem::Key("Esc", "Back", Color::LightRed),
+                    FooterItem::Div,
+                    FooterItem::Key("↑↓", "Select", Color::LightGreen),
+                    FooterItem::Key("PgUp/PgDn", "Scroll", Color::LightGreen),
+                    FooterItem::Key("d", "Drop", Color::LightRed),
+                    FooterItem::Key("A", "Apply all", Color::Rgb(255, 102, 0)),
+                ],
+            };
+
+            ChangesPage::new(
+                self.state.changes.changes(),
+                self.state.selected_change,
+                self.state.changes_scroll,
+                &footer,
+            )
+            .render(inner_area, buf);
+
+            if let Some(preview) = &self.state.write_preview {
+                render_write_preview(preview, pending, inner_area, buf);
+            }
+
+            return;
+        }
+
+        if self.state.show_settings_page {
+            SettingsPage::new(
+                &self.state.settings,
+                self.state.selected_setting,
+                self.state.rootfs_checks,
+                backup::last_backup(),
+            )
+            .render(inner_area, buf);
+            return;
+        }
+
         let selected_finding = self.selected_finding();
-        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(inner_area);
-        let [left_area, right_area] =
-            Layout::horizontal([Constraint::Percentage(75), Constraint::Percentage(25)]).areas(main_area);
-        let [host_area, config_area, rootfs_area] = Layout::vertical([
-            Constraint::Length(3 + (host.subgid.len() + host.subuid.len()) as u16),
-            Constraint::Min(2),
-            Constraint::Percentage(25),
-        ])
-        .areas(left_area);
+        let marked: Vec<_> = (0..self.state.findings.len())
+            .filter(|&i| self.state.is_marked(&self.state.findings[i]))
+            .collect();
+        let MainAreas {
+            summary: summary_area,
+            host: host_area,
+            config: config_area,
+            rootfs: rootfs_area,
+            findings: right_area,
+            footer: footer_area,
+        } = self.main_areas(area);
 
         // Command Bar Footer
 
-        let items = if self.state.show_fix_popup {
-            vec![FooterItem::Key("Esc", "Back", Color::LightRed)]
+        let items = if let Some(steps) = &self.state.follow_up {
+            let mut items = vec![FooterItem::Key("Esc", "Close", Color::LightRed)];
+
+            if steps.iter().any(|step| step.safe && step.command.is_some()) {
+                items.push(FooterItem::Key("x", "Run safe steps", Color::LightGreen));
+            }
+
+            items
+        } else if let Some(preview) = &self.state.write_preview {
+            write_preview_footer_items(preview, pending)
+        } else if let Some(shift) = &self.state.ownership_shift {
+            shift_footer_items(shift)
+        } else if let Some(import) = &self.state.import {
+            if import.reviewing {
+                vec![
+                    FooterItem::Key("Esc", "Edit", Color::LightRed),
+                    FooterItem::Div,
+                    FooterItem::Key("↑↓", "Navigate", Color::LightGreen),
+                    FooterItem::Key("Space", "Toggle", Color::LightGreen),
+                    FooterItem::Key("t", "Target", Color::LightGreen),
+                    FooterItem::Key("Enter", "Add", Color::LightGreen),
+                ]
+            } else {
+                vec![
+                    FooterItem::Key("Esc", "Cancel", Color::LightRed),
+                    FooterItem::Key("Tab", "Review", Color::LightGreen),
+                ]
+            }
+        } else if let Some(editor) = &self.state.subid_editor {
+            if editor.input.is_some() {
+                vec![
+                    FooterItem::Key("Esc", "Cancel", Color::LightRed),
+                    FooterItem::Key("Enter", "Set", Color::LightGreen),
+                ]
+            } else {
+                vec![
+                    FooterItem::Key("Esc", "Discard", Color::LightRed),
+                    FooterItem::Div,
+                    FooterItem::Key("↑↓", "Navigate", Color::LightGreen),
+                    FooterItem::Key("←→", "Field", Color::LightGreen),
+                    FooterItem::Key("Enter", "Edit", Color::LightGreen),
+                    FooterItem::Key("a", "Add", Color::LightGreen),
+                    FooterItem::Key("d", "Delete", Color::LightGreen),
+                    FooterItem::Key("k", "Kind", Color::LightGreen),
+                    FooterItem::Key("i", "Import", Color::LightGreen),
+                    FooterItem::Key("w", "Save", Color::LightGreen),
+                ]
+            }
+        } else if let Some(editor) = &self.state.idmap_editor {
+            if editor.input.is_some() {
+                vec![
+                    FooterItem::Key("Esc", "Cancel", Color::LightRed),
+                    FooterItem::Key("Enter", "Set", Color::LightGreen),
+                ]
+            } else {
+                vec![
+                    FooterItem::Key("Esc", "Discard", Color::LightRed),
+                    FooterItem::Div,
+                    FooterItem::Key("↑↓", "Navigate", Color::LightGreen),
+                    FooterItem::Key("←→", "Field", Color::LightGreen),
+                    FooterItem::Key("Enter", "Edit", Color::LightGreen),
+                    FooterItem::Key("a", "Add", Color::LightGreen),
+                    FooterItem::Key("d", "Delete", Color::LightGreen),
+                    FooterItem::Key("k", "Kind", Color::LightGreen),
+                    FooterItem::Key("n", "Next container", Color::LightGreen),
+                    FooterItem::Key("w", "Save", Color::LightGreen),
+                ]
+            }
+        } else if self.state.show_fix_popup {
+            let strategies = selected_finding.map_or(0, |f| self.state.fix_strategies(f).len());
+            let mut items = vec![FooterItem::Key("Esc", "Back", Color::LightRed)];
+
+            if strategies > 1 {
+                items.push(FooterItem::Key("↑↓", "Select", Color::LightGreen));
+            }
+
+            if strategies > 0 {
+                items.push(FooterItem::Key("Enter", "Apply", Color::LightGreen));
+            }
+
+            items
         } else if self.state.show_explain_popup {
-            vec![FooterItem::Key("Esc", "Back", Color::LightRed)]
+            vec![
+                FooterItem::Key("Esc", "Back", Color::LightRed),
+                FooterItem::Key("↑↓", "Scroll", Color::LightGreen),
+            ]
+        } else if self.state.show_report_popup {
+            vec![
+                FooterItem::Key("Esc", "Cancel", Color::LightRed),
+                FooterItem::Key("m", "Markdown", Color::LightGreen),
+                FooterItem::Key("h", "HTML", Color::LightGreen),
+            ]
+        } else if self.state.filter_input {
+            vec![
+                FooterItem::Key("Esc", "Clear", Color::LightRed),
+                FooterItem::Key("Enter", "Apply", Color::LightGreen),
+            ]
+        } else if self.state.focus == Focus::LXCConfig {
+            vec![
+                FooterItem::Key("Esc", "Back", Color::LightRed),
+                FooterItem::Div,
+                FooterItem::Key("Tab", "Next panel", Color::LightGreen),
+                FooterItem::Key("↑↓", "Container", Color::LightGreen),
+                FooterItem::Key("Enter", "Details", Color::LightGreen),
+            ]
+        } else if self.state.focus != Focus::Findings {
+            vec![
+                FooterItem::Key("Esc", "Back", Color::LightRed),
+                FooterItem::Div,
+                FooterItem::Key("Tab", "Next panel", Color::LightGreen),
+                FooterItem::Key("↑↓", "Scroll", Color::LightGreen),
+            ]
         } else {
             // Esc: Quit  │  ↑↓: Navigate  e: Explain  f: Fix  |  s: Settings  l: Logs
             let mut items = vec![
-                FooterItem::Key("Esc", "Quit", Color::LightRed),
+                if !self.state.filter.is_empty() {
+                    FooterItem::Key("Esc", "Clear filter", Color::LightRed)
+                } else if !self.state.marked_findings.is_empty() {
+                    FooterItem::Key("Esc", "Unmark", Color::LightRed)
+                } else {
+                    FooterItem::Key("Esc", "Quit", Color::LightRed)
+                },
                 FooterItem::Div,
                 FooterItem::Key("↑↓", "Navigate", Color::LightGreen),
+                FooterItem::Key("Tab", "Next panel", Color::LightGreen),
+                FooterItem::Key("/", "Filter", Color::LightGreen),
             ];
 
-            if selected_finding.is_some_and(|f| f.kind == FindingKind::Bad) {
-                items.extend([
-                    FooterItem::Key("e", "Explain", Color::LightCyan),
-                    FooterItem::Key("f", "Fix", Color::Rgb(255, 102, 0)),
-                ]);
+            if let Some(finding) = selected_finding.filter(|f| f.kind != FindingKind::Good) {
+                items.push(FooterItem::Key("Enter", "Go to", Color::LightCyan));
+                items.push(FooterItem::Key("e", "Explain", Color::LightCyan));
+
+                if self.state.fixable(finding) {
+                    items.push(FooterItem::Key("f", "Fix", Color::Rgb(255, 102, 0)));
+                }
+
+                if finding.fix.is_some() {
+                    items.push(FooterItem::Key("Space", "Mark", Color::LightCyan));
+                }
+            }
+
+            if !self.state.marked_findings.is_empty() {
+                items.push(FooterItem::Key("F", "Fix marked", Color::Rgb(255, 102, 0)));
+            }
+
+            if pending > 0 {
+                items.push(FooterItem::Key("p", "Pending", Color::LightCyan));
             }
 
             items.extend([
+                FooterItem::Key("m", "Edit mappings", Color::LightGreen),
+                FooterItem::Key("M", "Edit idmaps", Color::LightGreen),
+                FooterItem::Key("g", "Generate idmaps", Color::LightGreen),
+                FooterItem::Key("u", "Convert", Color::LightGreen),
+                FooterItem::Key("o", "Sort", Color::LightGreen),
+                FooterItem::Key("r", "Report", Color::LightGreen),
+                if self.is_deep_scanning() {
+                    FooterItem::Key("D", "Stop scan", Color::LightRed)
+                } else {
+                    FooterItem::Key("D", "Deep scan", Color::LightGreen)
+                },
                 FooterItem::Div,
+                FooterItem::Key("c", "Checks", Color::White),
                 FooterItem::Key("s", "Settings", Color::White),
+                FooterItem::Key("t", "Stats", Color::White),
                 FooterItem::Key("l", "Logs", Color::White),
+                FooterItem::Key("R", "Rescan", Color::White),
+                FooterItem::Key("^R", "Reload", Color::White),
             ]);
 
             items
         };
 
-        HostMappingPanel::new(&self.state.host_mapping, selected_finding).render(host_area, buf);
-        LXCConfigPanel::new(&self.state.lxc_configs, selected_finding, &self.metadata.lxc_config_dir)
-            .render(config_area, buf);
-        RootFSPanel::new(&self.state.rootfs_info, selected_finding).render(rootfs_area, buf);
-        FindingsList::new(&self.state.findings, self.state.selected_finding).render(right_area, buf);
+        HostSummary::new(
+            &self.metadata,
+            self.state.lxc_configs.len(),
+            self.state.stats.last_evaluation,
+        )
+        .config_dir_unavailable(self.state.config_dir_unavailable.is_some())
+        .render(summary_area, buf);
+        HostMappingPanel::new(
+            &self.state.host_mapping,
+            selected_finding,
+            self.state.subid_editor.as_ref(),
+        )
+        .scroll(self.state.host_mapping_scroll)
+        .focused(self.state.focus == Focus::HostMapping)
+        .loading(
+            [SubID::UID, SubID::GID]
+                .into_iter()
+                .filter(|sub_id| self.state.is_pending(self.metadata.subid_path(*sub_id)))
+                .collect(),
+        )
+        .empty(self.state.host_panel_empty(&self.metadata))
+        .render(host_area, buf);
+        LXCConfigPanel::new(
+            &self.state.lxc_configs,
+            selected_finding,
+            &self.metadata.lxc_config_dir,
+            self.state.dialect,
+            self.state.idmap_editor.as_ref(),
+        )
+        .lxc_defaults(&self.state.default_idmaps)
+        .loading(
+            self.state.pending_configs(&self.metadata.lxc_config_dir),
+            self.state.is_pending(&self.metadata.lxc_default_config),
+        )
+        .empty(self.state.config_panel_empty())
+        .focused(self.state.focus == Focus::LXCConfig)
+        .filter(self.state.config_filter())
+        .selected(
+            self.state
+                .selected_container
+                .and_then(|index| self.state.panel_containers().get(index).copied()),
+        )
+        .render(config_area, buf);
+        RootFSPanel::new(
+            &self.state.rootfs_info,
+            &self.state.rootfs_space,
+            selected_finding,
+            self.state.inspects_rootfs(),
+        )
+        .scroll(self.state.rootfs_scroll)
+        .focused(self.state.focus == Focus::RootFS)
+        .empty(self.state.rootfs_panel_empty())
+        .render(rootfs_area, buf);
+        FindingsList::new(
+            &self.state.findings,
+            self.state.selected_finding,
+            self.state.settings.sort_order(),
+        )
+        .focused(self.state.focus == Focus::Findings)
+        .filter(&self.state.filter, self.state.filter_input)
+        .loading(self.state.pending_loads())
+        .theme(self.state.settings.theme())
+        .marked(&marked)
+        .render(right_area, buf);
         Footer::new(&items).render(footer_area, buf);
 
-        if self.state.show_explain_popup {
+        if self.state.show_explain_popup
+            && let Some(finding) = selected_finding
+        {
+            let explanation = self.state.explain(finding, &self.metadata.lxc_config_dir);
+            let lines = explain_popup_lines(finding, &explanation);
+            // Leave room for the popup's border and some of the screen around it
+            let height = usize::from(inner_area.height.saturating_sub(6)).max(1);
+            let scroll = self.state.explain_scroll.min(lines.len().saturating_sub(1));
+            let title = if lines.len() > height {
+                format!(
+                    "Explain finding ({}-{} of {})",
+                    scroll + 1,
+                    (scroll + height).min(lines.len()),
+                    lines.len()
+                )
+            } else {
+                "Explain finding".to_string()
+            };
+
             Popup::new(Text::from(
-                "Not yet implemented. This will show detailed information about the selected finding.",
+                lines.into_iter().skip(scroll).take(height).collect::<Vec<_>>(),
             ))
-            .title("Explain finding")
+            .title(title)
             .style(Style::new().fg(Color::LightCyan).bg(Color::Rgb(0, 48, 48)))
             .render(inner_area, buf);
         }
 
-        if self.state.show_fix_popup {
-            Popup::new(Text::from("Not yet implemented. This will provide options to fix the selected finding."))
-                .title("Fix finding")
-                // .style(Style::new().fg(Color::White).bg(Color::DarkGray)) // Normal
-                .style(Style::new().fg(Color::LightRed).bg(Color::Rgb(48, 0, 0))) // Warning
-                // .style(Style::new().fg(Color::LightGreen).bg(Color::Rgb(0, 48, 0))) // Success?
+        if self.state.show_fix_popup
+            && let Some(finding) = selected_finding
+        {
+            let context = self.state.fix_context(finding, &self.metadata.lxc_config_dir);
+            let mut text = fix_popup_text(finding, &context, self.state.fix_selection);
+
+            append_rootfs_space_context(&mut text, finding, &self.state.rootfs_space);
+
+            // Mounting with an idmap beats chowning, so it's offered first
+            if let Some(mount) = self.state.idmapped_mount(finding) {
+                let preferred = [
+                    Line::from(format!(
+                        "Preferred: {} supports idmapped mounts, so instead add to {}:",
+                        mount.filesystem, mount.filename
+                    )),
+                    Line::from(mount.line).style(Style::new().fg(Color::LightGreen)),
+                    Line::from(""),
+                ];
+
+                text.lines.splice(0..0, preferred);
+            }
+
+            Popup::new(text)
+                .title(format!("Fix {}", finding.kind.label()))
+                .style(finding.popup_style())
+                .render(inner_area, buf);
+        }
+
+        if self.state.show_report_popup {
+            let dir = std::env::current_dir()
+                .map_or_else(|_| "the current directory".to_string(), |dir| dir.display().to_string());
+
+            Popup::new(Text::from(vec![
+                Line::from("Writes the host mappings, containers and findings with their explanations to"),
+                Line::from(format!("a file in {dir}, to share when asking for help.")),
+                Line::from(""),
+                Line::from("m  Markdown, for forum posts"),
+                Line::from("h  HTML"),
+            ]))
+            .title("Report")
+            .style(Style::new().fg(Color::White).bg(Color::Rgb(0, 0, 48)))
+            .render(inner_area, buf);
+        }
+
+        if let Some(import) = &self.state.import {
+            Popup::new(import_popup_text(import))
+                .title("Import subuid/subgid entries")
+                .style(Style::new().fg(Color::White).bg(Color::Rgb(0, 0, 48)))
+                .render(inner_area, buf);
+        }
+
+        if let Some(shift) = &self.state.ownership_shift {
+            render_shift_popup(shift, inner_area, buf);
+        }
+
+        if let Some(preview) = &self.state.write_preview {
+            render_write_preview(preview, pending, inner_area, buf);
+        }
+
+        if let Some(steps) = &self.state.follow_up {
+            Popup::new(follow_up_popup_text(steps))
+                .title("Next steps")
+                .style(Style::new().fg(Color::LightGreen).bg(Color::Rgb(0, 48, 0)))
                 .render(inner_area, buf);
         }
     }
 }
 
+fn render_shift_popup(shift: &OwnershipShift, area: Rect, buf: &mut Buffer) {
+    Popup::new(shift_popup_text(shift))
+        .title(format!(
+            "Shift ownership of container {}",
+            shift.filename.trim_end_matches(".conf")
+        ))
+        .style(Style::new().fg(Color::White).bg(Color::Rgb(0, 0, 48)))
+        .render(area, buf);
+}
+
+/// Shows the diffs of files about to be written, scrolled to where the user left them.
+fn render_write_preview(preview: &WritePreview, pending: usize, area: Rect, buf: &mut Buffer) {
+    let lines = write_preview_popup_lines(preview, pending);
+    // Leave room for the popup's border and some of the screen around it
+    let height = usize::from(area.height.saturating_sub(6)).max(1);
+    let scroll = preview.scroll.min(lines.len().saturating_sub(1));
+    let title = if lines.len() > height {
+        format!(
+            "{} ({}-{} of {})",
+            preview.title,
+            scroll + 1,
+            (scroll + height).min(lines.len()),
+            lines.len()
+        )
+    } else {
+        preview.title.to_string()
+    };
+
+    Popup::new(Text::from(
+        lines.into_iter().skip(scroll).take(height).collect::<Vec<_>>(),
+    ))
+    .title(title)
+    .style(Style::new().fg(Color::White).bg(Color::Rgb(0, 0, 48)))
+    .render(area, buf);
+}
+
+/// Ownership fixes rewrite every inode of a rootfs, so the user should know how much room is left
+/// and whether the dataset can be written to at all before attempting one.
+fn append_rootfs_space_context(
+    text: &mut Text,
+    finding: &Finding,
+    rootfs_space: &HashMap<String, DiskSpace, RandomState>,
+) {
+    for rootfs in &finding.rootfs_highlights {
+        let Some(space) = rootfs_space.get(rootfs) else {
+            continue;
+        };
+        let quota = space
+            .quota
+            .map_or_else(|| "no quota".to_string(), |q| format!("quota {}", format_bytes(q)));
+
+        text.push_line("");
+        text.push_line(format!(
+            "{rootfs}: {} available, {quota}",
+            format_bytes(space.available)
+        ));
+
+        let Some(zfs) = &space.zfs else {
+            continue;
+        };
+
+        if let Some(refquota) = zfs.refquota {
+            text.push_line(format!("refquota {} on {}", format_bytes(refquota), zfs.name));
+        }
+
+        if zfs.blocks_writes() {
+            text.push_line(Line::styled(
+                format!("Blocked: {} cannot be written to. Remediate with:", zfs.name),
+                Style::new().add_modifier(Modifier::BOLD),
+            ));
+
+            for command in zfs.remediation_commands() {
+                text.push_line(format!("  {command}"));
+            }
+        }
+
+        text.push_line(Line::styled(
+            "Warning: a mass chown rewrites metadata for every file. Existing ZFS snapshots keep the old \
+             blocks, so used space can balloon until those snapshots are destroyed.",
+            Style::new().add_modifier(Modifier::BOLD),
+        ));
+    }
+}
+
+/// Formats a byte count using binary units, e.g. `1.5 GiB`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} {}", UNITS[0])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
 // Data structures
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct IdMapEntry {
     pub host_user_id: CompactString,
     pub host_sub_id: u32,
     pub host_sub_id_count: u32,
+    /// Whether the line deviated from the canonical `name:start:count` formatting.
+    pub unusual_format: bool,
+    /// The line as written in the file, for quoting it back to the user.
+    pub line: CompactString,
+    /// 1-based, counting blank lines too.
+    pub line_number: usize,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct HostMapping {
     pub subuid: Vec<IdMapEntry>,
     pub subgid: Vec<IdMapEntry>,
+    pub subuid_comments: Vec<SubidComment>,
+    pub subgid_comments: Vec<SubidComment>,
+    pub subuid_invalid: Vec<InvalidSubidLine>,
+    pub subgid_invalid: Vec<InvalidSubidLine>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum FindingKind {
-    Good,
-    Bad,
-}
+impl HostMapping {
+    /// Replaces the entries and comments of /etc/subuid or /etc/subgid with those of `content`.
+    pub fn load(&mut self, sub_id: SubID, content: &str) {
+        let (id_map, invalid) = parse_subid_lines(content);
+        let mut comments = comment_lines(content);
+
+        // Kept like comments too, so rewriting the file doesn't lose them
+        comments.retain(|comment| !invalid.iter().any(|line| line.line_number == comment.line_number));
+        comments.extend(invalid.iter().map(|line| SubidComment {
+            line_number: line.line_number,
+            text: line.line.clone(),
+        }));
+        comments.sort_by_key(|comment| comment.line_number);
 
-// REVIEW: Vecs here should maybe be SmallVecs?
-#[derive(Clone, Debug)]
-pub struct Finding {
-    pub kind: FindingKind,
-    pub message: &'static str,
-    pub host_mapping_highlights: Vec<(CompactString, SubID)>,
-    pub lxc_config_mapping_highlights: Vec<(CompactString, SubID)>,
-    pub rootfs_highlights: Vec<String>,
+        match sub_id {
+            SubID::UID => {
+                self.subuid = id_map;
+                self.subuid_comments = comments;
+                self.subuid_invalid = invalid;
+            },
+            SubID::GID => {
+                self.subgid = id_map;
+                self.subgid_comments = comments;
+                self.subgid_invalid = invalid;
+            },
+        }
+    }
+
+    /// How many rows the host mapping panel lists, invalid lines included.
+    pub fn row_count(&self) -> usize {
+        self.subuid.len() + self.subgid.len() + self.subuid_invalid.len() + self.subgid_invalid.len()
+    }
 }
 
 impl Finding {
     fn base_fg(&self) -> Color {
         match self.kind {
             FindingKind::Good => Color::Green,
+            FindingKind::Info => Color::Cyan,
+            FindingKind::Warning => Color::Yellow,
             FindingKind::Bad => Color::Red,
         }
     }
@@ -171,20 +897,27 @@ impl Finding {
     fn selected_bg(&self) -> Color {
         match self.kind {
             FindingKind::Good => Color::LightGreen,
+            FindingKind::Info => Color::LightCyan,
+            FindingKind::Warning => Color::LightYellow,
             FindingKind::Bad => Color::LightRed,
         }
     }
 
-    fn badge(&self) -> &'static str {
+    /// The popup colors matching the severity.
+    fn popup_style(&self) -> Style {
         match self.kind {
-            FindingKind::Good => "✅ ",
-            FindingKind::Bad => "❌ ",
+            FindingKind::Good => Style::new().fg(Color::LightGreen).bg(Color::Rgb(0, 48, 0)),
+            FindingKind::Info => Style::new().fg(Color::LightCyan).bg(Color::Rgb(0, 48, 48)),
+            FindingKind::Warning => Style::new().fg(Color::LightYellow).bg(Color::Rgb(48, 48, 0)),
+            FindingKind::Bad => Style::new().fg(Color::LightRed).bg(Color::Rgb(48, 0, 0)),
         }
     }
 }
 
-impl Display for Finding {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.message)
-    }
+/// The severity of `finding` and what it means, in its color.
+fn severity_line(finding: &Finding) -> Line<'static> {
+    Line::styled(
+        format!("Severity: {}. {}", finding.kind.label(), finding.kind.meaning()),
+        Style::new().fg(finding.base_fg()),
+    )
 }
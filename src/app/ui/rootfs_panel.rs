@@ -1,55 +1,158 @@
+use std::collections::HashMap;
 use std::fs::Metadata;
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
 
 use ahash::RandomState;
 use indexmap::IndexMap;
 use ratatui::buffer::Buffer;
 use ratatui::layout::{Alignment, Rect};
 use ratatui::style::{Color, Modifier, Style};
-use ratatui::text::Text;
-use ratatui::widgets::{Block, Borders, Row, Table, Widget};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, Widget, Wrap};
 
-use crate::app::ui::Finding;
+use crate::app::state::empty::EmptyPanel;
+use crate::app::ui::{focus_style, format_bytes};
+use crate::finding::Finding;
+use crate::linux::DiskSpace;
+use crate::lxc::RootfsLocation;
 
 pub struct RootFSPanel<'a> {
-    info: &'a IndexMap<String, (PathBuf, Metadata), RandomState>,
+    info: &'a IndexMap<String, (RootfsLocation, Metadata), RandomState>,
+    space: &'a HashMap<String, DiskSpace, RandomState>,
     selected_finding: Option<&'a Finding>,
+    /// When false, a note replaces the table.
+    inspects_rootfs: bool,
+    scroll: usize,
+    focused: bool,
+    empty: Option<EmptyPanel>,
 }
 
 impl<'a> RootFSPanel<'a> {
     pub fn new(
-        info: &'a IndexMap<String, (PathBuf, Metadata), RandomState>,
+        info: &'a IndexMap<String, (RootfsLocation, Metadata), RandomState>,
+        space: &'a HashMap<String, DiskSpace, RandomState>,
         selected_finding: Option<&'a Finding>,
+        inspects_rootfs: bool,
     ) -> Self {
-        Self { info, selected_finding }
+        Self {
+            info,
+            space,
+            selected_finding,
+            inspects_rootfs,
+            scroll: 0,
+            focused: false,
+            empty: None,
+        }
+    }
+
+    /// Why no root filesystems are listed, which is shown in place of the table.
+    pub fn empty(mut self, empty: Option<EmptyPanel>) -> Self {
+        self.empty = empty;
+        self
+    }
+
+    /// Skips the first `scroll` rootfs rows.
+    pub fn scroll(mut self, scroll: usize) -> Self {
+        self.scroll = scroll;
+        self
+    }
+
+    /// Highlights the border of the panel with focus.
+    pub fn focused(mut self, focused: bool) -> Self {
+        self.focused = focused;
+        self
     }
 }
 
 impl Widget for RootFSPanel<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title("Root Filesystems")
+            .borders(Borders::ALL)
+            .border_style(focus_style(self.focused))
+            .title_alignment(Alignment::Center);
+
+        let note = if !self.inspects_rootfs {
+            Some(
+                "Rootfs checks are disabled by --no-rootfs-checks, --root-prefix or the settings page. Rootfs \
+                 ownership and writability are not checked."
+                    .to_string(),
+            )
+        } else if self.info.is_empty() {
+            self.empty.as_ref().map(EmptyPanel::message)
+        } else {
+            None
+        };
+
+        if let Some(note) = note {
+            Paragraph::new(note)
+                .style(Style::default().fg(Color::DarkGray))
+                .wrap(Wrap { trim: true })
+                .block(block)
+                .render(area, buf);
+
+            return;
+        }
+
         let rootfs_header = Row::new([
-            Text::from("Path").alignment(Alignment::Center),
+            Text::from("Volume").alignment(Alignment::Center),
+            Text::from("Dataset").alignment(Alignment::Center),
+            Text::from("Mountpoint").alignment(Alignment::Center),
             Text::from("UID").alignment(Alignment::Center),
             Text::from("GID").alignment(Alignment::Center),
+            Text::from("Avail").alignment(Alignment::Center),
+            Text::from("Quota").alignment(Alignment::Center),
+            Text::from("FS").alignment(Alignment::Center),
         ])
         .style(Style::default().add_modifier(Modifier::BOLD));
         let mut rootfs_rows = Vec::new();
 
-        for (rootfs, (path, metadata)) in self.info {
+        for (rootfs, (location, metadata)) in self.info.iter().skip(self.scroll) {
             let mut style = Style::default();
 
-            if let Some(finding) = self.selected_finding {
-                if finding.rootfs_highlights.contains(rootfs) {
-                    style = style.bg(finding.selected_bg()).fg(Color::Black);
-                }
+            if let Some(finding) = self.selected_finding
+                && finding.rootfs_highlights.contains(rootfs)
+            {
+                style = style.bg(finding.selected_bg()).fg(Color::Black);
             }
 
+            let space = self.space.get(rootfs);
+            let avail = space.map_or_else(|| "?".to_string(), |space| format_bytes(space.available));
+            let quota = match space {
+                Some(space) => {
+                    let refquota = space.zfs.as_ref().and_then(|zfs| zfs.refquota);
+
+                    space
+                        .quota
+                        .or(refquota)
+                        .map_or_else(|| "none".to_string(), format_bytes)
+                },
+                None => "?".to_string(),
+            };
+            let filesystem = match space.map(|space| &space.filesystem) {
+                Some(Some(filesystem)) if filesystem.idmapped_mounts => Line::from(vec![
+                    Span::raw(filesystem.name),
+                    Span::styled(" idmap", Style::default().fg(Color::LightGreen)),
+                ]),
+                Some(Some(filesystem)) => Line::from(filesystem.name),
+                Some(None) => Line::from("other"),
+                None => Line::from("?"),
+            };
+            let avail = match space.and_then(|space| space.zfs.as_ref()) {
+                Some(zfs) if zfs.blocks_writes() => format!("{avail} (ro)"),
+                _ => avail,
+            };
+
             rootfs_rows.push(
                 Row::new(vec![
-                    Text::from(path.to_string_lossy()).alignment(Alignment::Center),
+                    Text::from(location.volume_id.as_str()).alignment(Alignment::Center),
+                    Text::from(location.dataset.as_deref().unwrap_or("-")).alignment(Alignment::Center),
+                    Text::from(location.mountpoint.to_string_lossy()).alignment(Alignment::Center),
                     Text::from(metadata.uid().to_string()).alignment(Alignment::Center),
                     Text::from(metadata.gid().to_string()).alignment(Alignment::Center),
+                    Text::from(avail).alignment(Alignment::Center),
+                    Text::from(quota).alignment(Alignment::Center),
+                    Text::from(filesystem).alignment(Alignment::Center),
                 ])
                 .style(style),
             );
@@ -57,12 +160,7 @@ impl Widget for RootFSPanel<'_> {
 
         Table::new(rootfs_rows, &[])
             .header(rootfs_header)
-            .block(
-                Block::default()
-                    .title("Root Filesystems")
-                    .borders(Borders::ALL)
-                    .title_alignment(Alignment::Center),
-            )
+            .block(block)
             .render(area, buf);
     }
 }
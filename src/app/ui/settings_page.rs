@@ -0,0 +1,203 @@
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph};
+
+use super::footer::{Footer, FooterItem::*};
+use crate::check::Check;
+use crate::fs::backup::Backup;
+use crate::settings::{ApplyMode, Settings};
+
+/// Settings listed below the checks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SettingOption {
+    InspectRootfs,
+    CheckSnapshots,
+    ApplyMode,
+    SortOrder,
+    Theme,
+    /// Not a setting, but restoring a backup is rare enough not to need a key of its own.
+    UndoLastChange,
+}
+
+impl SettingOption {
+    pub const ALL: [SettingOption; 6] = [
+        SettingOption::InspectRootfs,
+        SettingOption::CheckSnapshots,
+        SettingOption::ApplyMode,
+        SettingOption::SortOrder,
+        SettingOption::Theme,
+        SettingOption::UndoLastChange,
+    ];
+
+    /// The option on settings page row `row`, if it isn't a check.
+    pub fn at_row(row: usize) -> Option<Self> {
+        row.checked_sub(Check::ALL.len())
+            .and_then(|i| Self::ALL.get(i).copied())
+    }
+
+    pub fn row_count() -> usize {
+        Check::ALL.len() + Self::ALL.len()
+    }
+}
+
+pub struct SettingsPage<'s> {
+    settings: &'s Settings,
+    selected: usize,
+    /// Whether rootfs inspection is allowed by the command line at all.
+    rootfs_allowed: bool,
+    /// The backup undoing the last change would restore.
+    last_backup: Option<Backup>,
+}
+
+impl<'s> SettingsPage<'s> {
+    pub fn new(settings: &'s Settings, selected: usize, rootfs_allowed: bool, last_backup: Option<Backup>) -> Self {
+        Self {
+            settings,
+            selected,
+            rootfs_allowed,
+            last_backup,
+        }
+    }
+}
+
+fn row_style(enabled: bool, is_selected: bool) -> Style {
+    let mut style = if enabled {
+        Style::default()
+    } else {
+        Style::default().fg(Color::DarkGray)
+    };
+
+    if is_selected {
+        style = style.add_modifier(Modifier::REVERSED);
+    }
+
+    style
+}
+
+impl Widget for SettingsPage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Settings")
+            .title_alignment(Alignment::Center);
+        let mut lines = Vec::with_capacity(SettingOption::row_count() + 1);
+
+        for (i, check) in Check::ALL.into_iter().enumerate() {
+            let enabled = self.settings.is_enabled(check);
+            let is_selected = i == self.selected;
+            let style = row_style(enabled, is_selected);
+
+            lines.push(Line::from(vec![
+                Span::raw(if is_selected { "▶ " } else { "  " }),
+                Span::styled(
+                    format!(
+                        "[{}] {:<32} {}",
+                        if enabled { "x" } else { " " },
+                        check.name(),
+                        check.id()
+                    ),
+                    style,
+                ),
+            ]));
+        }
+
+        lines.push(Line::from(""));
+
+        for (i, option) in SettingOption::ALL.into_iter().enumerate() {
+            let is_selected = self.selected == Check::ALL.len() + i;
+            let (text, style) = match option {
+                SettingOption::InspectRootfs => {
+                    let enabled = self.settings.inspect_rootfs();
+
+                    (
+                        format!(
+                            "[{}] {:<32} {}",
+                            if enabled { "x" } else { " " },
+                            "Inspect rootfs directories",
+                            if self.rootfs_allowed {
+                                "inspect_rootfs"
+                            } else {
+                                "inspect_rootfs (overridden on the command line)"
+                            }
+                        ),
+                        row_style(enabled && self.rootfs_allowed, is_selected),
+                    )
+                },
+                SettingOption::CheckSnapshots => {
+                    let enabled = self.settings.check_snapshots();
+
+                    (
+                        format!(
+                            "[{}] {:<32} check_snapshots",
+                            if enabled { "x" } else { " " },
+                            "Check snapshot sections"
+                        ),
+                        row_style(enabled, is_selected),
+                    )
+                },
+                SettingOption::ApplyMode => (
+                    format!(
+                        "    {:<32} apply_mode: {}",
+                        match self.settings.apply_mode() {
+                            ApplyMode::Direct => "Write container configs directly",
+                            ApplyMode::Pct => "Write container configs via pct",
+                        },
+                        self.settings.apply_mode().id()
+                    ),
+                    row_style(true, is_selected),
+                ),
+                SettingOption::SortOrder => (
+                    format!(
+                        "    {:<32} sort_order: {}",
+                        format!("Sort findings by {}", self.settings.sort_order().name()),
+                        self.settings.sort_order().id()
+                    ),
+                    row_style(true, is_selected),
+                ),
+                SettingOption::Theme => (
+                    format!(
+                        "    {:<32} theme: {}",
+                        format!("Colors for {}", self.settings.theme().name()),
+                        if self.settings.theme_overridden() {
+                            format!("{} (overridden on the command line)", self.settings.theme().id())
+                        } else {
+                            self.settings.theme().id().to_string()
+                        }
+                    ),
+                    row_style(true, is_selected),
+                ),
+                SettingOption::UndoLastChange => match &self.last_backup {
+                    Some(backup) => (
+                        format!(
+                            "    {:<32} {} from {}",
+                            "Undo last change",
+                            backup.original.display(),
+                            backup.taken_at.format("%H:%M:%S")
+                        ),
+                        row_style(true, is_selected),
+                    ),
+                    None => (
+                        format!("    {:<32} nothing written this session", "Undo last change"),
+                        row_style(false, is_selected),
+                    ),
+                },
+            };
+
+            lines.push(Line::from(vec![
+                Span::raw(if is_selected { "▶ " } else { "  " }),
+                Span::styled(text, style),
+            ]));
+        }
+
+        Paragraph::new(lines).block(block).render(main_area, buf);
+
+        let items = &[
+            Key("Esc", "Back", Color::LightRed),
+            Div,
+            Key("↑↓", "Navigate", Color::LightGreen),
+            Key("Space", "Toggle", Color::LightGreen),
+        ];
+
+        Footer::new(items).render(footer_area, buf);
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::atomic::Ordering;
+
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+use super::footer::FooterItem;
+use crate::app::state::shift::{OwnershipShift, ShiftField};
+
+/// Width of the progress bar in cells.
+const BAR_WIDTH: u64 = 40;
+
+fn field_line<'s>(shift: &'s OwnershipShift, field: ShiftField, label: &'static str, value: &'s str) -> Line<'s> {
+    let editing = shift.field == field && !shift.is_running();
+    let style = if editing {
+        Style::new().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::new()
+    };
+
+    Line::from(vec![
+        Span::raw(if editing { "▶ " } else { "  " }),
+        Span::raw(format!("{label:<10}")),
+        Span::styled(value, style),
+    ])
+}
+
+/// The body of the ownership shift popup: the bases while editing, and the progress of the last
+/// shift or dry run.
+pub fn shift_popup_text(shift: &OwnershipShift) -> Text<'_> {
+    let mut lines = vec![
+        Line::from(format!("Rootfs: {}", shift.path.display())),
+        Line::from("uids and gids from the old base on are moved to the same offset from the new base."),
+        Line::from(""),
+        field_line(shift, ShiftField::From, "Old base", &shift.from),
+        field_line(shift, ShiftField::To, "New base", &shift.to),
+        field_line(shift, ShiftField::Count, "Count", &shift.count),
+    ];
+
+    let Some(job) = &shift.job else {
+        return Text::from(lines);
+    };
+    let total = job.total.load(Ordering::Relaxed);
+    let done = job.done.load(Ordering::Relaxed);
+    let changed = job.changed.load(Ordering::Relaxed);
+    let failed = job.failed.load(Ordering::Relaxed);
+    let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(0).min(BAR_WIDTH);
+
+    lines.push(Line::from(""));
+
+    if job.is_running() && done == 0 {
+        lines.push(Line::from(format!("Counting files... {total}")));
+    } else {
+        lines.push(Line::from(vec![
+            Span::styled(
+                "█".repeat(filled as usize),
+                Style::new().fg(if job.dry_run {
+                    Color::LightCyan
+                } else {
+                    Color::LightGreen
+                }),
+            ),
+            Span::styled("░".repeat((BAR_WIDTH - filled) as usize), Style::new().fg(Color::Gray)),
+            Span::raw(format!(" {done}/{total}")),
+        ]));
+    }
+
+    let summary = match (job.is_running(), job.dry_run) {
+        (true, true) => format!("{changed} files would change so far"),
+        (true, false) => format!("{changed} files shifted so far"),
+        (false, true) => format!("Dry run: {changed} of {total} files would change"),
+        (false, false) => format!("Shifted {changed} of {total} files"),
+    };
+
+    lines.push(Line::from(summary));
+
+    if failed > 0 {
+        lines.push(Line::styled(
+            format!("{failed} files couldn't be changed, see the logs"),
+            Style::new().fg(Color::LightRed),
+        ));
+    }
+
+    if !job.is_running() && job.was_cancelled() {
+        lines.push(Line::from("Cancelled before it got through everything."));
+    }
+
+    Text::from(lines)
+}
+
+/// The keys of the ownership shift popup, which only stops a shift while one runs.
+pub fn shift_footer_items(shift: &OwnershipShift) -> Vec<FooterItem> {
+    if shift.is_running() {
+        return vec![FooterItem::Key("Esc", "Stop", Color::LightRed)];
+    }
+
+    vec![
+        FooterItem::Key("Esc", "Close", Color::LightRed),
+        FooterItem::Div,
+        FooterItem::Key("Tab", "Field", Color::LightGreen),
+        FooterItem::Key("d", "Dry run", Color::LightGreen),
+        FooterItem::Key("q", "Queue", Color::LightCyan),
+        FooterItem::Key("Enter", "Shift", Color::LightGreen),
+    ]
+}
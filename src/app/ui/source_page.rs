@@ -0,0 +1,69 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Row, Table, Widget};
+
+use super::footer::{Footer, FooterItem::*};
+use crate::app::state::source::SourceView;
+
+/// A file opened from a finding, with the offending lines highlighted and the cursor on the first.
+pub struct SourcePage<'s> {
+    view: &'s SourceView,
+}
+
+impl<'s> SourcePage<'s> {
+    pub fn new(view: &'s SourceView) -> Self {
+        Self { view }
+    }
+}
+
+impl Widget for SourcePage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let view = self.view;
+        // Keep the cursor roughly centered once the file doesn't fit
+        let height = usize::from(main_area.height.saturating_sub(2));
+        let offset = view
+            .cursor
+            .saturating_sub(height / 2)
+            .min(view.lines.len().saturating_sub(height));
+        let rows = view
+            .lines
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(height)
+            .map(|(i, line)| {
+                let mut style = if view.targets.contains(&(i + 1)) {
+                    Style::default().fg(Color::LightRed)
+                } else {
+                    Style::default()
+                };
+
+                if i == view.cursor {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+
+                Row::new([format!("{:>4}", i + 1), line.clone()]).style(style)
+            });
+        let widths = [Constraint::Length(4), Constraint::Min(0)];
+
+        Table::new(rows, widths)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("{} (line {})", view.path, view.cursor + 1))
+                    .title_alignment(Alignment::Center),
+            )
+            .render(main_area, buf);
+
+        let items = &[
+            Key("Esc", "Back", Color::LightRed),
+            Div,
+            Key("↑↓", "Move", Color::LightGreen),
+            Key("n", "Next offending line", Color::LightGreen),
+        ];
+
+        Footer::new(items).render(footer_area, buf);
+    }
+}
@@ -0,0 +1,74 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Row, Table, Widget};
+
+use super::footer::{Footer, FooterItem::*};
+use crate::app::state::stats::SessionStats;
+use crate::history::FindingHistory;
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Summarizes what pupman has done this session. Everything shown stays on this machine.
+pub struct StatsPage<'s> {
+    stats: &'s SessionStats,
+    history: &'s FindingHistory,
+    /// The PVE version of the host, `None` without PVE.
+    pve_version: Option<String>,
+}
+
+impl<'s> StatsPage<'s> {
+    pub fn new(stats: &'s SessionStats, history: &'s FindingHistory, pve_version: Option<String>) -> Self {
+        Self {
+            stats,
+            history,
+            pve_version,
+        }
+    }
+}
+
+impl Widget for StatsPage<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main_area, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let stats = self.stats;
+        let rows = [
+            ("Running for", format_duration(stats.started.elapsed().as_secs())),
+            ("Files parsed", stats.files_parsed.to_string()),
+            ("Evaluations run", stats.evaluations.to_string()),
+            ("Problems surfaced", stats.findings_seen.len().to_string()),
+            ("Fixes applied", stats.fixes_applied.to_string()),
+            ("Entries imported", stats.entries_imported.to_string()),
+            (
+                "Time saved (rough estimate)",
+                format_duration(stats.time_saved().as_secs()),
+            ),
+            ("Findings in history", self.history.len().to_string()),
+            // Past the retention limits from the settings
+            ("Dropped from history", self.history.pruned().to_string()),
+            (
+                "Proxmox VE",
+                self.pve_version.unwrap_or_else(|| "not detected".to_string()),
+            ),
+        ]
+        .map(|(name, value)| Row::new([name.to_string(), value]));
+        let widths = [Constraint::Length(30), Constraint::Min(0)];
+
+        Table::new(rows, widths)
+            .header(Row::new(["Session", ""]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Stats (local only, nothing is sent anywhere)")
+                    .title_alignment(Alignment::Center),
+            )
+            .render(main_area, buf);
+
+        Footer::new(&[Key("Esc", "Back", Color::LightRed)]).render(footer_area, buf);
+    }
+}
@@ -0,0 +1,119 @@
+//! Draws a frame in the [`Theme`] from the settings. Widgets pick their colors for a dark terminal,
+//! which are swapped for the theme's own once the whole frame is drawn.
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier};
+
+use crate::finding::{Finding, FindingKind};
+use crate::settings::Theme;
+
+/// Recolors everything drawn in `area` for `theme`.
+pub fn apply(theme: Theme, area: Rect, buf: &mut Buffer) {
+    if theme == Theme::Default {
+        return;
+    }
+
+    for position in area.positions() {
+        let cell = &mut buf[position];
+
+        match theme {
+            Theme::Default => {},
+            Theme::Light => {
+                cell.fg = light_fg(cell.fg);
+                cell.bg = light_bg(cell.bg);
+            },
+            Theme::Mono => {
+                // Highlights keep standing out by swapping the terminal's own colors
+                if is_highlight(cell.bg) {
+                    cell.modifier.insert(Modifier::REVERSED);
+                }
+
+                if matches!(cell.fg, Color::Gray | Color::DarkGray) {
+                    cell.modifier.insert(Modifier::DIM);
+                }
+
+                cell.fg = Color::Reset;
+                cell.bg = Color::Reset;
+
+                if let Some(ascii) = ascii_symbol(cell.symbol()) {
+                    cell.set_char(ascii);
+                }
+            },
+        }
+    }
+}
+
+/// The text colors meant for a dark background, darkened until they read on a light one.
+fn light_fg(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Gray => Color::DarkGray,
+        Color::LightRed => Color::Red,
+        Color::LightGreen => Color::Green,
+        Color::Yellow | Color::LightYellow => Color::Indexed(136),
+        Color::Cyan | Color::LightCyan => Color::Indexed(30),
+        Color::LightBlue => Color::Blue,
+        Color::LightMagenta => Color::Magenta,
+        color => color,
+    }
+}
+
+/// The dark surfaces of popups, as light tints of the same hue.
+fn light_bg(color: Color) -> Color {
+    let lighten = |c: u8| (215 + u16::from(c) * 40 / 64) as u8;
+
+    match color {
+        Color::Rgb(r, g, b) if r.max(g).max(b) <= 64 => Color::Rgb(lighten(r), lighten(g), lighten(b)),
+        Color::Black => Color::White,
+        Color::DarkGray => Color::Gray,
+        color => color,
+    }
+}
+
+/// Whether a background marks something as selected or highlighted, rather than being the surface
+/// of a popup.
+fn is_highlight(color: Color) -> bool {
+    !matches!(
+        color,
+        Color::Reset | Color::Black | Color::DarkGray | Color::Rgb(..) | Color::Indexed(_)
+    )
+}
+
+/// A stand-in for a symbol which may be missing from a terminal's font, of the same width.
+fn ascii_symbol(symbol: &str) -> Option<char> {
+    Some(match symbol {
+        "▶" | "→" | "↳" => '>',
+        "←" => '<',
+        "↑" | "▲" => '^',
+        "↓" | "▼" => 'v',
+        "⇆" => '-',
+        "█" => '#',
+        "░" | "…" => '.',
+        "●" => '*',
+        "☐" => '-',
+        "─" | "━" => '-',
+        "═" => '=',
+        "│" | "┃" | "║" => '|',
+        "┌" | "┐" | "└" | "┘" | "├" | "┤" | "┬" | "┴" | "┼" | "╭" | "╮" | "╰" | "╯" | "╔" | "╗" | "╚" | "╝" => {
+            '+'
+        },
+        _ => return None,
+    })
+}
+
+impl Finding {
+    /// The severity in front of the finding's message, an emoji unless the theme does without.
+    pub(super) fn badge(&self, theme: Theme) -> &'static str {
+        match (theme, self.kind) {
+            (Theme::Mono, FindingKind::Good) => "[+] ",
+            (Theme::Mono, FindingKind::Info) => "[i] ",
+            (Theme::Mono, FindingKind::Warning) => "[!] ",
+            (Theme::Mono, FindingKind::Bad) => "[x] ",
+            (_, FindingKind::Good) => "✅ ",
+            (_, FindingKind::Info) => "ℹ️ ",
+            (_, FindingKind::Warning) => "⚠️ ",
+            (_, FindingKind::Bad) => "❌ ",
+        }
+    }
+}
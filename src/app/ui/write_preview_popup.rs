@@ -0,0 +1,75 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+
+use super::footer::FooterItem;
+use crate::app::state::preview::{PreviewAction, WritePreview};
+
+/// The body of the popup confirming file writes, one entry per rendered line so it can be scrolled.
+/// `pending` is how many changes are queued.
+pub fn write_preview_popup_lines(preview: &WritePreview, pending: usize) -> Vec<Line<'static>> {
+    let mut lines: Vec<_> = preview.notes.iter().map(|note| Line::from(note.clone())).collect();
+    let diff = preview.diff_lines();
+
+    if !lines.is_empty() {
+        lines.push(Line::from(""));
+    }
+
+    let runs_commands = matches!(preview.action, PreviewAction::Acl(_) | PreviewAction::SharedVolume(_));
+
+    if diff.is_empty() && !runs_commands {
+        lines.push(Line::from("Nothing would change."));
+    }
+
+    lines.extend(diff.into_iter().map(diff_line));
+    lines.push(Line::from(""));
+    let verb = match (runs_commands, preview.writes.is_empty()) {
+        (true, true) => "run",
+        (true, false) => "write and run",
+        (false, _) => "write",
+    };
+
+    lines.push(Line::from(match (preview.action.queueable(), pending) {
+        (true, 0) => format!("Press Enter to {verb}, q to queue, Esc to cancel."),
+        (true, _) => format!("Press Enter to queue after the {pending} pending changes, Esc to cancel."),
+        (false, _) => format!("Press Enter to {verb}, Esc to cancel."),
+    }));
+
+    lines
+}
+
+/// A line of a unified diff, colored by what it is.
+pub fn diff_line(line: String) -> Line<'static> {
+    let style = if line.starts_with("---") || line.starts_with("+++") {
+        Style::new().add_modifier(Modifier::BOLD)
+    } else if line.starts_with("@@") {
+        Style::new().fg(Color::LightCyan)
+    } else if line.starts_with('-') {
+        Style::new().fg(Color::LightRed)
+    } else if line.starts_with('+') {
+        Style::new().fg(Color::LightGreen)
+    } else {
+        Style::new()
+    };
+
+    Line::styled(line, style)
+}
+
+/// The footer while a write preview is shown. Once changes are queued, anything which can be
+/// queued is, since it was planned on top of them.
+pub fn write_preview_footer_items(preview: &WritePreview, pending: usize) -> Vec<FooterItem> {
+    let mut items = vec![
+        FooterItem::Key("Esc", "Cancel", Color::LightRed),
+        FooterItem::Key("↑↓", "Scroll", Color::LightGreen),
+    ];
+
+    match (preview.action.queueable(), pending) {
+        (true, 0) => items.extend([
+            FooterItem::Key("Enter", "Write", Color::LightGreen),
+            FooterItem::Key("q", "Queue", Color::LightCyan),
+        ]),
+        (true, _) => items.push(FooterItem::Key("Enter", "Queue", Color::LightCyan)),
+        (false, _) => items.push(FooterItem::Key("Enter", "Write", Color::LightGreen)),
+    }
+
+    items
+}
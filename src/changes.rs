@@ -0,0 +1,382 @@
+//! Edits queued up as one transaction, so several fixes and edits can be reviewed together and
+//! applied all at once, or not at all.
+
+use std::fs::{Permissions, read_to_string, set_permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{WrapErr, eyre};
+use compact_str::CompactString;
+
+use crate::app::state::acl::AclPlan;
+use crate::app::state::shared_volume::SharedVolumePlan;
+use crate::followup::Change;
+use crate::fs::shift::IdShift;
+use crate::fs::subid::{SubID, sort_entries};
+use crate::fs::writer::{PendingWrite, commit_together, write_atomic};
+use crate::linux::usermod_add_sub_ids;
+use crate::settings::MappingIntent;
+
+/// A previewed edit which was queued instead of written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueuedChange {
+    pub description: String,
+    /// The writes as planned on top of the changes queued before this one.
+    pub writes: Vec<PendingWrite>,
+    /// What the edit does besides writing files, in order once every write is in place.
+    pub steps: Vec<QueuedStep>,
+    /// What the edit amounts to, for the checklist once the queue is applied.
+    pub follow_up: Vec<Change>,
+}
+
+impl QueuedChange {
+    /// Applies the change right away, as a queue of its own.
+    pub fn apply(self) -> color_eyre::Result<()> {
+        Transaction { changes: vec![self] }.apply()
+    }
+}
+
+/// What a queued change does besides writing files.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueuedStep {
+    /// Entries of the subid file at `path` added again through `usermod`, as the owner's login
+    /// and the first and last id. The file is sorted again afterwards, since `usermod` appends.
+    Usermod {
+        sub_id: SubID,
+        path: PathBuf,
+        additions: Vec<(String, u32, u32)>,
+    },
+    /// ACLs letting containers share a directory.
+    Acl(AclPlan),
+    /// The group or ACLs letting containers share a directory.
+    SharedVolume(SharedVolumePlan),
+    /// A mapping intent to remember in the settings.
+    MappingIntent { vmid: String, intent: MappingIntent },
+    /// A shift of the owners of a container's rootfs, which runs in the ownership shift dialog.
+    Shift {
+        filename: CompactString,
+        path: PathBuf,
+        shift: IdShift,
+    },
+}
+
+impl QueuedStep {
+    /// The commands the step runs, for previewing.
+    pub fn commands(&self) -> Vec<String> {
+        match self {
+            QueuedStep::Usermod { sub_id, additions, .. } => additions
+                .iter()
+                .map(|(login, first, last)| format!("usermod --add-sub{}s {first}-{last} {login}", sub_id.kind_name()))
+                .collect(),
+            QueuedStep::Acl(plan) => plan.commands().into(),
+            QueuedStep::SharedVolume(plan) => plan.commands(),
+            QueuedStep::MappingIntent { .. } | QueuedStep::Shift { .. } => Vec::new(),
+        }
+    }
+
+    /// Runs the step's commands. Mapping intents and shifts are left to the app once the queue is
+    /// applied, as the settings aren't files of the queue and a shift runs in the background.
+    fn run(&self) -> color_eyre::Result<()> {
+        match self {
+            QueuedStep::Usermod {
+                sub_id,
+                path,
+                additions,
+            } => {
+                for (login, first, last) in additions {
+                    usermod_add_sub_ids(sub_id.kind_name(), login, *first, *last)
+                        .wrap_err_with(|| format!("usermod failed to add {first}-{last} to {login}"))?;
+                }
+
+                // usermod appends what it adds, which may leave a lower range after a higher one again
+                let added = read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+                let sorted = sort_entries(&added);
+
+                if sorted != added {
+                    write_atomic(path, &sorted)?;
+                }
+
+                Ok(())
+            },
+            QueuedStep::Acl(plan) => plan.apply(),
+            QueuedStep::SharedVolume(plan) => plan.apply(),
+            QueuedStep::MappingIntent { .. } | QueuedStep::Shift { .. } => Ok(()),
+        }
+    }
+}
+
+/// The queued changes, applied in the order they were queued.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transaction {
+    changes: Vec<QueuedChange>,
+}
+
+impl Transaction {
+    pub fn changes(&self) -> &[QueuedChange] {
+        &self.changes
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    /// What `path` holds once the queue is applied, `None` when nothing is queued for it.
+    pub fn planned(&self, path: &Path) -> Option<&str> {
+        self.changes
+            .iter()
+            .rev()
+            .flat_map(|change| &change.writes)
+            .find(|write| write.path == path)
+            .map(|write| write.proposed.as_str())
+    }
+
+    /// The content of `path` as the queue leaves it, which is what further edits build on.
+    pub fn read(&self, path: &Path) -> color_eyre::Result<String> {
+        match self.planned(path) {
+            Some(content) => Ok(content.to_string()),
+            None => read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// The steps of every queued change, in order.
+    pub fn steps(&self) -> impl Iterator<Item = &QueuedStep> {
+        self.changes.iter().flat_map(|change| &change.steps)
+    }
+
+    /// Adds `change` to the end of the queue. Its writes have to build on what the queue leaves in
+    /// each file, so nothing queued before it is lost.
+    pub fn queue(&mut self, change: QueuedChange) -> color_eyre::Result<()> {
+        for write in &change.writes {
+            // What usermod adds back isn't known until it ran, so nothing can be planned on top of it
+            if self
+                .steps()
+                .any(|step| matches!(step, QueuedStep::Usermod { path, .. } if *path == write.path))
+            {
+                return Err(eyre!(
+                    "{} has entries queued to be added back through usermod, apply those first",
+                    write.path.display()
+                ));
+            }
+
+            if self
+                .planned(&write.path)
+                .is_some_and(|planned| planned != write.current)
+            {
+                return Err(eyre!(
+                    "{} was edited without the changes queued for it, apply or drop those first",
+                    write.path.display()
+                ));
+            }
+        }
+
+        let is_shift = |step: &QueuedStep| matches!(step, QueuedStep::Shift { .. });
+
+        if self.steps().any(is_shift) && change.steps.iter().any(is_shift) {
+            return Err(eyre!(
+                "An ownership shift is queued already, only one can run at a time"
+            ));
+        }
+
+        self.changes.push(change);
+
+        Ok(())
+    }
+
+    /// Drops the change at `index` along with every later change writing any of the same files,
+    /// since those were planned on top of it. Returns how many changes were dropped.
+    pub fn remove(&mut self, index: usize) -> usize {
+        if index >= self.changes.len() {
+            return 0;
+        }
+
+        let removed = self.changes.remove(index);
+        let mut paths: Vec<_> = removed.writes.iter().map(|write| write.path.clone()).collect();
+        let mut count = 1;
+        let mut i = index;
+
+        while i < self.changes.len() {
+            let writes = &self.changes[i].writes;
+
+            if writes.iter().any(|write| paths.contains(&write.path)) {
+                paths.extend(writes.iter().map(|write| write.path.clone()));
+                self.changes.remove(i);
+                count += 1;
+            } else {
+                i += 1;
+            }
+        }
+
+        count
+    }
+
+    /// One write per file, from its content before the first queued change to what the last one
+    /// leaves.
+    pub fn writes(&self) -> Vec<PendingWrite> {
+        let mut writes: Vec<PendingWrite> = Vec::new();
+
+        for write in self.changes.iter().flat_map(|change| &change.writes) {
+            match writes.iter_mut().find(|planned| planned.path == write.path) {
+                Some(planned) => planned.proposed.clone_from(&write.proposed),
+                None => writes.push(write.clone()),
+            }
+        }
+
+        writes
+    }
+
+    /// What the queued changes amount to, each once.
+    pub fn follow_up(&self) -> Vec<Change> {
+        let mut follow_up = Vec::new();
+
+        for change in self.changes.iter().flat_map(|change| &change.follow_up) {
+            if !follow_up.contains(change) {
+                follow_up.push(change.clone());
+            }
+        }
+
+        follow_up
+    }
+
+    /// Writes every queued change, then runs the commands of their steps. Nothing is written unless
+    /// every file still has the content the queue was planned on, and if a write or command fails,
+    /// the files written before it are put back.
+    pub fn apply(&self) -> color_eyre::Result<()> {
+        let writes = self.writes();
+
+        if let Some(write) = writes.iter().find(|write| !write.is_current().unwrap_or(false)) {
+            return Err(eyre!("{} was changed since it was queued", write.path.display()));
+        }
+
+        let created: Vec<_> = writes
+            .iter()
+            .filter(|write| !write.path.exists())
+            .map(|write| &write.path)
+            .collect();
+
+        commit_together(&writes, || {
+            // New files are written private, but every user may read the subid files and configs
+            for path in created {
+                set_permissions(path, Permissions::from_mode(0o644))
+                    .wrap_err_with(|| format!("Failed to make {} readable", path.display()))?;
+            }
+
+            self.steps().try_for_each(QueuedStep::run)
+        })
+    }
+}
+
+#[test]
+fn test_transaction() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let subuid = dir.path().join("subuid");
+    let subgid = dir.path().join("subgid");
+    let mut transaction = Transaction::default();
+
+    std::fs::write(&subuid, "root:100000:65536\n")?;
+
+    let append = |transaction: &Transaction, path: &Path, line: &str| -> color_eyre::Result<QueuedChange> {
+        let current = transaction.read(path)?;
+
+        Ok(QueuedChange {
+            description: format!("Add {line}"),
+            writes: vec![PendingWrite {
+                path: path.to_path_buf(),
+                proposed: format!("{current}{line}\n"),
+                current,
+            }],
+            steps: Vec::new(),
+            follow_up: vec![Change::SubidRangesAdded(SubID::UID)],
+        })
+    };
+
+    transaction.queue(append(&transaction, &subuid, "alice:200000:65536")?)?;
+    transaction.queue(append(&transaction, &subuid, "bob:300000:65536")?)?;
+    transaction.queue(QueuedChange {
+        description: "Create subgid".to_string(),
+        writes: vec![PendingWrite {
+            path: subgid.clone(),
+            current: String::new(),
+            proposed: "root:100000:65536\n".to_string(),
+        }],
+        steps: Vec::new(),
+        follow_up: vec![Change::SubidRangesAdded(SubID::UID)],
+    })?;
+
+    // A write planned on the file as it is, rather than as the queue leaves it
+    let stale = QueuedChange {
+        writes: vec![PendingWrite::new(&subuid, "root:100000:131072\n".into())?],
+        ..append(&transaction, &subuid, "")?
+    };
+
+    assert!(transaction.queue(stale).is_err());
+
+    let writes = transaction.writes();
+
+    assert_eq!(writes.len(), 2);
+    assert_eq!(writes[0].current, "root:100000:65536\n");
+    assert_eq!(
+        writes[0].proposed,
+        "root:100000:65536\nalice:200000:65536\nbob:300000:65536\n"
+    );
+    assert_eq!(transaction.follow_up(), [Change::SubidRangesAdded(SubID::UID)]);
+
+    // Dropping alice drops bob too, whose write builds on it
+    let mut dropped = transaction.clone();
+
+    assert_eq!(dropped.remove(0), 2);
+    assert_eq!(dropped.len(), 1);
+
+    transaction.apply()?;
+
+    assert_eq!(read_to_string(&subuid)?, writes[0].proposed);
+    assert_eq!(read_to_string(&subgid)?, "root:100000:65536\n");
+    assert_eq!(std::fs::metadata(&subgid)?.permissions().mode() & 0o777, 0o644);
+
+    // The files moved on, so the same queue can't be applied again
+    assert!(transaction.apply().is_err());
+
+    // Nothing can be planned on entries usermod adds back, and only one shift runs at a time
+    let mut transaction = Transaction::default();
+    let shift = QueuedChange {
+        description: "Shift the rootfs".to_string(),
+        writes: Vec::new(),
+        steps: vec![QueuedStep::Shift {
+            filename: "100.conf".into(),
+            path: dir.path().join("rootfs"),
+            shift: IdShift {
+                from: 0,
+                to: 100000,
+                count: 65536,
+            },
+        }],
+        follow_up: Vec::new(),
+    };
+
+    transaction.queue(QueuedChange {
+        steps: vec![QueuedStep::Usermod {
+            sub_id: SubID::UID,
+            path: subuid.clone(),
+            additions: vec![("alice".to_string(), 200000, 265535)],
+        }],
+        ..append(&transaction, &subuid, "")?
+    })?;
+    transaction.queue(shift.clone())?;
+
+    assert!(
+        transaction
+            .queue(append(&transaction, &subuid, "carol:400000:65536")?)
+            .is_err()
+    );
+    assert!(transaction.queue(shift).is_err());
+    assert_eq!(transaction.len(), 2);
+
+    Ok(())
+}
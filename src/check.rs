@@ -0,0 +1,248 @@
+//! The catalog of validations pupman runs. Every finding is produced by exactly one check, which lets
+//! checks be toggled individually.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use color_eyre::eyre::eyre;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Check {
+    /// /etc/subuid or /etc/subgid doesn't exist.
+    SubidFiles,
+    /// A line of /etc/subuid or /etc/subgid is neither an entry nor a comment.
+    SubidSyntax,
+    /// A user appears more than once in /etc/subuid or /etc/subgid.
+    SubidDuplicates,
+    /// An /etc/subuid or /etc/subgid entry isn't written as `name:start:count`.
+    SubidFormatting,
+    /// An /etc/subuid or /etc/subgid entry looks hand-written rather than added through shadow-utils.
+    SubidManaged,
+    /// An /etc/subuid or /etc/subgid entry belongs to a user who isn't in /etc/passwd.
+    SubidOwner,
+    /// A user's subordinate ids which containers use are only granted in one of /etc/subuid and /etc/subgid.
+    SubidSymmetry,
+    /// An unprivileged container has no uid or gid idmap.
+    IdmapPresent,
+    /// A container's idmap falls outside of the host's subordinate id range.
+    IdmapHostRange,
+    /// A snapshot's idmap falls outside of root's subordinate id range, so rolling back to it breaks
+    /// the container.
+    SnapshotIdmaps,
+    /// A container's idmaps leave some of its ids unmapped, or map some of them twice.
+    IdmapCoverage,
+    /// A container maps its uids and gids differently without being marked as mapping only one of them.
+    IdmapSymmetry,
+    /// A container maps its uids onto the uid of a host user who logs in, or of whoever runs pupman.
+    IdmapLoginUsers,
+    /// A container maps ids onto the ids of users in /etc/passwd or groups in /etc/group.
+    IdmapHostAccounts,
+    /// A container maps ids onto host ids reserved by convention, like systemd's dynamic users.
+    IdmapReservedRanges,
+    /// The rootfs isn't owned by the container's mapped root user.
+    RootfsOwnership,
+    /// A deep scan found files in the rootfs owned by ids the container doesn't map.
+    RootfsContents,
+    /// The rootfs dataset cannot be written to.
+    RootfsWritable,
+    /// Several containers use the same rootfs volume.
+    RootfsShared,
+    /// A mount point's host directory is owned by an id the container doesn't map.
+    MountOwnership,
+    /// A device passed through with `devN` is owned by a container id the container doesn't map.
+    DeviceOwnership,
+    /// A container's `features` turn on something its privilege doesn't allow.
+    ContainerFeatures,
+    /// A key PVE only reads once appears multiple times in a config section.
+    ConfigDuplicateKeys,
+    /// A config uses keys the installed PVE version no longer supports.
+    ConfigDeprecatedKeys,
+    /// A config has keys PVE doesn't know, or values it can't parse.
+    ConfigSchema,
+    /// A subuid, subgid or lxc.idmap range is malformed, empty or runs past the largest id.
+    IdRangeValues,
+    /// The container config directory is unavailable, so changes to configs go unnoticed.
+    ConfigDirAvailable,
+    /// Files are polled for changes, as inotify isn't available.
+    FilesWatched,
+}
+
+impl Check {
+    pub const ALL: [Check; 28] = [
+        Check::SubidFiles,
+        Check::SubidSyntax,
+        Check::SubidDuplicates,
+        Check::SubidFormatting,
+        Check::SubidManaged,
+        Check::SubidOwner,
+        Check::SubidSymmetry,
+        Check::IdmapPresent,
+        Check::IdmapHostRange,
+        Check::SnapshotIdmaps,
+        Check::IdmapCoverage,
+        Check::IdmapSymmetry,
+        Check::IdmapLoginUsers,
+        Check::IdmapHostAccounts,
+        Check::IdmapReservedRanges,
+        Check::RootfsOwnership,
+        Check::RootfsContents,
+        Check::RootfsWritable,
+        Check::RootfsShared,
+        Check::MountOwnership,
+        Check::DeviceOwnership,
+        Check::ContainerFeatures,
+        Check::ConfigDuplicateKeys,
+        Check::ConfigDeprecatedKeys,
+        Check::ConfigSchema,
+        Check::IdRangeValues,
+        Check::ConfigDirAvailable,
+        Check::FilesWatched,
+    ];
+
+    /// A stable identifier, used in the settings file.
+    pub fn id(self) -> &'static str {
+        match self {
+            Check::SubidFiles => "subid-files",
+            Check::SubidSyntax => "subid-syntax",
+            Check::SubidDuplicates => "subid-duplicates",
+            Check::SubidFormatting => "subid-formatting",
+            Check::SubidManaged => "subid-managed",
+            Check::SubidOwner => "subid-owner",
+            Check::SubidSymmetry => "subid-symmetry",
+            Check::IdmapPresent => "idmap-present",
+            Check::IdmapHostRange => "idmap-host-range",
+            Check::SnapshotIdmaps => "snapshot-idmaps",
+            Check::IdmapCoverage => "idmap-coverage",
+            Check::IdmapSymmetry => "idmap-symmetry",
+            Check::IdmapLoginUsers => "idmap-login-users",
+            Check::IdmapHostAccounts => "idmap-host-accounts",
+            Check::IdmapReservedRanges => "idmap-reserved-ranges",
+            Check::RootfsOwnership => "rootfs-ownership",
+            Check::RootfsContents => "rootfs-contents",
+            Check::RootfsWritable => "rootfs-writable",
+            Check::RootfsShared => "rootfs-shared",
+            Check::MountOwnership => "mount-ownership",
+            Check::DeviceOwnership => "device-ownership",
+            Check::ContainerFeatures => "container-features",
+            Check::ConfigDuplicateKeys => "config-duplicate-keys",
+            Check::ConfigDeprecatedKeys => "config-deprecated-keys",
+            Check::ConfigSchema => "config-schema",
+            Check::IdRangeValues => "id-range-values",
+            Check::ConfigDirAvailable => "config-dir-available",
+            Check::FilesWatched => "files-watched",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::SubidFiles => "subuid/subgid files exist",
+            Check::SubidSyntax => "subuid/subgid syntax",
+            Check::SubidDuplicates => "Overlapping subuid/subgid ranges",
+            Check::SubidFormatting => "subuid/subgid formatting",
+            Check::SubidManaged => "subuid/subgid managed by shadow-utils",
+            Check::SubidOwner => "subuid/subgid owners exist",
+            Check::SubidSymmetry => "subuid/subgid granted alike",
+            Check::IdmapPresent => "lxc.idmap present",
+            Check::IdmapHostRange => "lxc.idmap within host range",
+            Check::SnapshotIdmaps => "Snapshot lxc.idmap within range",
+            Check::IdmapCoverage => "lxc.idmap container coverage",
+            Check::IdmapSymmetry => "lxc.idmap symmetry",
+            Check::IdmapLoginUsers => "lxc.idmap avoids login users",
+            Check::IdmapHostAccounts => "lxc.idmap avoids host accounts",
+            Check::IdmapReservedRanges => "lxc.idmap avoids reserved ranges",
+            Check::RootfsOwnership => "Rootfs ownership",
+            Check::RootfsContents => "Rootfs contents ownership",
+            Check::RootfsWritable => "Rootfs dataset writable",
+            Check::RootfsShared => "Rootfs used by one container",
+            Check::MountOwnership => "Mount point ownership",
+            Check::DeviceOwnership => "Device node ownership",
+            Check::ContainerFeatures => "Container features",
+            Check::ConfigDuplicateKeys => "Duplicate config keys",
+            Check::ConfigDeprecatedKeys => "Deprecated config keys",
+            Check::ConfigSchema => "Known config keys and values",
+            Check::IdRangeValues => "Valid id ranges",
+            Check::ConfigDirAvailable => "Config directory available",
+            Check::FilesWatched => "Files watched for changes",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Check::SubidFiles => "/etc/subuid and /etc/subgid exist, as newuidmap and newgidmap read them",
+            Check::SubidSyntax => "Every line is a name:start:count entry with numeric ids, or a comment",
+            Check::SubidDuplicates => "No user is granted the same ids twice in /etc/subuid or /etc/subgid",
+            Check::SubidFormatting => "Entries are written as name:start:count without stray whitespace",
+            Check::SubidManaged => "Entries were added through usermod, which may otherwise rewrite hand edits",
+            Check::SubidOwner => "Each entry belongs to a user listed in /etc/passwd",
+            Check::SubidSymmetry => {
+                "Users whose ranges containers use have entries in both /etc/subuid and /etc/subgid"
+            },
+            Check::IdmapPresent => "Unprivileged containers define both uid and gid lxc.idmap entries",
+            Check::IdmapHostRange => "lxc.idmap host ranges fit inside the owner's /etc/subuid or /etc/subgid range",
+            Check::SnapshotIdmaps => {
+                "Snapshots' lxc.idmap host ranges fit inside root's ranges, once snapshot checks are turned on"
+            },
+            Check::IdmapCoverage => "lxc.idmap maps each container id from 0 to 65535 exactly once",
+            Check::IdmapSymmetry => {
+                "uids and gids are mapped alike, unless the container is marked as mapping only one"
+            },
+            Check::IdmapLoginUsers => {
+                "lxc.idmap host ranges don't include the uid of a host user who logs in, unless it is passed through"
+            },
+            Check::IdmapHostAccounts => {
+                "lxc.idmap host ranges don't include the ids of users in /etc/passwd or groups in /etc/group, unless \
+                 they are passed through"
+            },
+            Check::IdmapReservedRanges => {
+                "lxc.idmap host ranges stay clear of ids systemd and others reserve, like DynamicUser's"
+            },
+            Check::RootfsOwnership => "The rootfs is owned by the host id the container's root maps to",
+            Check::RootfsContents => {
+                "Files inside the rootfs are owned by host ids the container maps, once a deep scan ran"
+            },
+            Check::RootfsWritable => "The rootfs ZFS dataset is not read-only or partially received",
+            Check::RootfsShared => "No two container configs point at the same rootfs volume",
+            Check::MountOwnership => {
+                "Directories mounted with mpN are owned by host ids the container's lxc.idmap maps"
+            },
+            Check::DeviceOwnership => "Devices passed through with devN are owned by container ids lxc.idmap maps",
+            Check::ContainerFeatures => {
+                "features only turns on what the container's privilege allows, like mount types it can mount"
+            },
+            Check::ConfigDuplicateKeys => "Keys such as rootfs and unprivileged are set at most once per section",
+            Check::ConfigDeprecatedKeys => "Configs only use keys supported by the installed PVE and LXC versions",
+            Check::ConfigSchema => "Configs only use keys PVE knows, with values it can parse",
+            Check::IdRangeValues => "Id ranges are well formed, non-empty and end within the 32 bit id space",
+            Check::ConfigDirAvailable => {
+                "The container config directory, /etc/pve/lxc on PVE, is mounted and watched for changes"
+            },
+            Check::FilesWatched => "Changes to files are noticed through inotify right away, rather than polled for",
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl FromStr for Check {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> color_eyre::Result<Self> {
+        Check::ALL
+            .into_iter()
+            .find(|check| check.id() == s)
+            .ok_or_else(|| eyre!("unknown check {s}"))
+    }
+}
+
+#[test]
+fn test_check_ids_round_trip() {
+    for check in Check::ALL {
+        assert_eq!(check.id().parse::<Check>().unwrap(), check);
+    }
+
+    assert!("nope".parse::<Check>().is_err());
+}
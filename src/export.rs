@@ -0,0 +1,248 @@
+//! Machine and human readable exports of the current findings, including how long each one has
+//! been around according to [`FindingHistory`], and of the checks which couldn't run.
+
+use std::fmt::Write;
+
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+
+use crate::app::state::State;
+use crate::app::state::skipped::SkippedCheck;
+use crate::finding::{Finding, FindingKind};
+use crate::history::FindingHistory;
+use crate::metadata::Metadata;
+use crate::settings::Settings;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+}
+
+fn kind_name(kind: FindingKind) -> &'static str {
+    match kind {
+        FindingKind::Good => "good",
+        FindingKind::Info => "info",
+        FindingKind::Warning => "warning",
+        FindingKind::Bad => "bad",
+    }
+}
+
+pub fn export(
+    findings: &[Finding],
+    skipped: &[SkippedCheck],
+    history: &FindingHistory,
+    format: ExportFormat,
+) -> String {
+    match format {
+        ExportFormat::Json => export_json(findings, skipped, history),
+        ExportFormat::Markdown => export_markdown(findings, skipped, history),
+    }
+}
+
+/// Evaluates all findings once and exports them along with their `history`. Findings the history
+/// doesn't know yet count as first seen now, without recording them, as exporting shouldn't change
+/// what the TUI remembers. Files which failed to load are skipped and their errors returned.
+pub fn export_with(
+    metadata: &Metadata,
+    settings: Settings,
+    history: &FindingHistory,
+    format: ExportFormat,
+) -> (String, Vec<color_eyre::Report>) {
+    let (state, errors) = State::collect(metadata, settings);
+    let ids: Vec<_> = state.findings.iter().map(Finding::id).collect();
+    let mut history = history.clone();
+
+    history.observe(ids.iter().map(String::as_str), Utc::now());
+
+    (export(&state.findings, &state.skipped_checks, &history, format), errors)
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            },
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+fn export_json(findings: &[Finding], skipped: &[SkippedCheck], history: &FindingHistory) -> String {
+    let mut out = String::from("{\"findings\":[");
+
+    for (i, finding) in findings.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        out.push_str(&finding_json(finding, history));
+    }
+
+    out.push_str("],\"skipped\":[");
+
+    for (i, skipped) in skipped.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        out.push_str(&skipped_json(skipped));
+    }
+
+    out.push_str("]}\n");
+    out
+}
+
+/// A check which couldn't run as the JSON object exports list it.
+pub(crate) fn skipped_json(skipped: &SkippedCheck) -> String {
+    format!(
+        "{{\"container\":{},\"check\":\"{}\",\"reason\":\"{}\",\"description\":{}}}",
+        json_string(&skipped.filename),
+        skipped.check,
+        skipped.reason.id(),
+        json_string(skipped.reason.description())
+    )
+}
+
+/// A finding as the JSON object exports list it, along with when it was first and last seen.
+pub(crate) fn finding_json(finding: &Finding, history: &FindingHistory) -> String {
+    let id = finding.id();
+    let mut out = String::new();
+
+    let _ = write!(
+        out,
+        "{{\"id\":{},\"kind\":\"{}\",\"check\":\"{}\",\"message\":{},\"fix\":{}",
+        json_string(&id),
+        kind_name(finding.kind),
+        finding.check,
+        json_string(finding.message),
+        finding
+            .fix
+            .map_or_else(|| "null".to_string(), |fix| json_string(&fix.description())),
+    );
+
+    match history.get(&id) {
+        Some(entry) => {
+            let _ = write!(
+                out,
+                ",\"firstSeen\":\"{}\",\"lastSeen\":\"{}\",\"occurrences\":{}}}",
+                entry.first_seen.to_rfc3339_opts(SecondsFormat::Secs, true),
+                entry.last_seen.to_rfc3339_opts(SecondsFormat::Secs, true),
+                entry.occurrences
+            );
+        },
+        None => out.push_str(",\"firstSeen\":null,\"lastSeen\":null,\"occurrences\":0}"),
+    }
+
+    out
+}
+
+fn export_markdown(findings: &[Finding], skipped: &[SkippedCheck], history: &FindingHistory) -> String {
+    let mut out = String::from(
+        "| Kind | Finding | Id | First seen | Last seen | Occurrences |\n\
+         |------|---------|----|------------|-----------|-------------|\n",
+    );
+
+    for finding in findings {
+        let id = finding.id();
+        let (first_seen, last_seen, occurrences) = match history.get(&id) {
+            Some(entry) => (
+                entry.first_seen.format("%Y-%m-%d %H:%M").to_string(),
+                entry.last_seen.format("%Y-%m-%d %H:%M").to_string(),
+                entry.occurrences.to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string(), "0".to_string()),
+        };
+
+        let _ = writeln!(
+            out,
+            "| {} | {} | `{id}` | {first_seen} | {last_seen} | {occurrences} |",
+            kind_name(finding.kind),
+            finding.message.replace('|', "\\|"),
+        );
+    }
+
+    if !skipped.is_empty() {
+        out.push_str(
+            "\nNot checked:\n\n\
+             | Container | Check | Why |\n\
+             |-----------|-------|-----|\n",
+        );
+
+        for skipped in skipped {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} |",
+                skipped.filename,
+                skipped.check.name(),
+                skipped.reason.description()
+            );
+        }
+    }
+
+    out
+}
+
+#[test]
+fn test_export_json_includes_history() {
+    use chrono::DateTime;
+
+    use crate::check::Check;
+
+    let finding = Finding {
+        kind: FindingKind::Bad,
+        check: Check::IdmapPresent,
+        message: "Missing \"uid\" idmap",
+        host_mapping_highlights: Vec::new(),
+        lxc_config_mapping_highlights: Vec::new(),
+        rootfs_highlights: Vec::new(),
+        config_line_highlights: Vec::new(),
+        subid_line_highlights: Vec::new(),
+        fix: None,
+    };
+    let mut history = FindingHistory::default();
+
+    history.observe(["idmap-present"], DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+
+    assert_eq!(
+        export(&[finding], &[], &history, ExportFormat::Json),
+        "{\"findings\":[{\"id\":\"idmap-present\",\"kind\":\"bad\",\"check\":\"idmap-present\",\
+         \"message\":\"Missing \\\"uid\\\" idmap\",\"fix\":null,\"firstSeen\":\"2023-11-14T22:13:20Z\",\
+         \"lastSeen\":\"2023-11-14T22:13:20Z\",\"occurrences\":1}],\"skipped\":[]}\n"
+    );
+}
+
+#[test]
+fn test_export_skipped_checks() {
+    use crate::app::state::skipped::SkipReason;
+    use crate::check::Check;
+
+    let skipped = SkippedCheck {
+        filename: "100.conf".into(),
+        check: Check::RootfsOwnership,
+        reason: SkipReason::NotRoot,
+    };
+    let history = FindingHistory::default();
+
+    assert_eq!(
+        export(&[], std::slice::from_ref(&skipped), &history, ExportFormat::Json),
+        "{\"findings\":[],\"skipped\":[{\"container\":\"100.conf\",\"check\":\"rootfs-ownership\",\
+         \"reason\":\"not-root\",\"description\":\"pupman doesn't run as root, which looking at it needs\"}]}\n"
+    );
+    assert!(
+        export(&[], &[skipped], &history, ExportFormat::Markdown)
+            .ends_with("| 100.conf | Rootfs ownership | pupman doesn't run as root, which looking at it needs |\n")
+    );
+}
@@ -0,0 +1,190 @@
+//! Findings are the results of evaluating checks against the host's id mappings, container
+//! configs and rootfs ownership.
+
+use std::fmt::{self, Display, Write};
+
+use compact_str::CompactString;
+
+use crate::check::Check;
+use crate::fix::Fix;
+use crate::fs::subid::SubID;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FindingKind {
+    Good,
+    /// Worth knowing about, but nothing is wrong.
+    Info,
+    /// Suspicious but still works.
+    Warning,
+    /// Breaks the container, which won't start or can't use its files until it is fixed.
+    Bad,
+}
+
+impl FindingKind {
+    /// Findings are listed most severe first.
+    pub fn sort_order(self) -> u8 {
+        match self {
+            FindingKind::Bad => 0,
+            FindingKind::Warning => 1,
+            FindingKind::Info => 2,
+            FindingKind::Good => 3,
+        }
+    }
+
+    /// The name of the severity, for badges.
+    pub fn label(self) -> &'static str {
+        match self {
+            FindingKind::Good => "ok",
+            FindingKind::Info => "info",
+            FindingKind::Warning => "warning",
+            FindingKind::Bad => "error",
+        }
+    }
+
+    /// What the severity means for whether to act on a finding.
+    pub fn meaning(self) -> &'static str {
+        match self {
+            FindingKind::Good => "Nothing to do.",
+            FindingKind::Info => "Nothing is broken, change it only if it matters on this host.",
+            FindingKind::Warning => "The container works, but this is likely to cause trouble later.",
+            FindingKind::Bad => "The container won't work as intended until this is fixed.",
+        }
+    }
+
+    /// Whether something is wrong, rather than fine or only worth knowing about.
+    pub fn is_problem(self) -> bool {
+        matches!(self, FindingKind::Warning | FindingKind::Bad)
+    }
+}
+
+// REVIEW: Vecs here should maybe be SmallVecs?
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// The check which produced this finding.
+    pub check: Check,
+    pub message: &'static str,
+    pub host_mapping_highlights: Vec<(CompactString, SubID)>,
+    pub lxc_config_mapping_highlights: Vec<(CompactString, SubID)>,
+    pub rootfs_highlights: Vec<String>,
+    pub config_line_highlights: Vec<ConfigLine>,
+    /// 1-based lines of /etc/subuid or /etc/subgid which aren't entries, so have no owner to
+    /// highlight by.
+    pub subid_line_highlights: Vec<(SubID, usize)>,
+    pub fix: Option<Fix>,
+}
+
+/// A single line of a container config.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigLine {
+    pub filename: CompactString,
+    pub key: CompactString,
+    /// 1-based, as shown by editors.
+    pub line: usize,
+}
+
+impl Finding {
+    /// An identifier which stays the same across runs for as long as the underlying issue exists,
+    /// e.g. `idmap-present:100.conf/uid`. Parts are percent-escaped, so rootfs values such as
+    /// `local-zfs:subvol-100-disk-0` can't be mistaken for several parts.
+    pub fn id(&self) -> String {
+        let mut id = self.check.id().to_string();
+
+        for (user, sub_id) in &self.host_mapping_highlights {
+            let _ = write!(id, ":{}/{}", sub_id.file_name(), Escaped(user));
+        }
+
+        for (filename, sub_id) in &self.lxc_config_mapping_highlights {
+            let _ = write!(id, ":{}/{}", Escaped(filename), sub_id.kind_name());
+        }
+
+        for rootfs in &self.rootfs_highlights {
+            let _ = write!(id, ":{}", Escaped(rootfs));
+        }
+
+        // Line numbers shift whenever the file is edited, so only the file and key are part of the id
+        let mut previous = None;
+
+        for line in &self.config_line_highlights {
+            if previous != Some((&line.filename, &line.key)) {
+                let _ = write!(id, ":{}/{}", Escaped(&line.filename), Escaped(&line.key));
+            }
+
+            previous = Some((&line.filename, &line.key));
+        }
+
+        let mut previous = None;
+
+        for (sub_id, _) in &self.subid_line_highlights {
+            if previous != Some(sub_id) {
+                let _ = write!(id, ":{}", sub_id.file_name());
+            }
+
+            previous = Some(sub_id);
+        }
+
+        id
+    }
+}
+
+/// Writes a part of a finding id with the characters separating parts percent-escaped.
+struct Escaped<'a>(&'a str);
+
+impl Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '%' => f.write_str("%25")?,
+                '/' => f.write_str("%2F")?,
+                ':' => f.write_str("%3A")?,
+                c => f.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+#[test]
+fn test_finding_id() {
+    let finding = Finding {
+        kind: FindingKind::Bad,
+        check: Check::RootfsOwnership,
+        message: "Rootfs uid does not match host mapping",
+        host_mapping_highlights: vec![("root".into(), SubID::UID)],
+        lxc_config_mapping_highlights: vec![("100.conf".into(), SubID::UID)],
+        rootfs_highlights: vec!["local-zfs:subvol-100-disk-0".into()],
+        config_line_highlights: Vec::new(),
+        subid_line_highlights: Vec::new(),
+        fix: None,
+    };
+
+    assert_eq!(
+        finding.id(),
+        "rootfs-ownership:subuid/root:100.conf/uid:local-zfs%3Asubvol-100-disk-0"
+    );
+
+    // Two rootfs values don't collide with one containing the separator
+    let split = Finding {
+        rootfs_highlights: vec!["local-zfs".into(), "subvol-100-disk-0".into()],
+        ..finding.clone()
+    };
+
+    assert_ne!(finding.id(), split.id());
+
+    let dir = Finding {
+        rootfs_highlights: vec!["/var/lib/vz/images/100%:subvol".into()],
+        ..finding
+    };
+
+    assert_eq!(
+        dir.id(),
+        "rootfs-ownership:subuid/root:100.conf/uid:%2Fvar%2Flib%2Fvz%2Fimages%2F100%25%3Asubvol"
+    );
+}
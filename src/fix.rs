@@ -0,0 +1,591 @@
+//! Automated fixes for findings, shared by the TUI and the headless `fix` command.
+
+use std::fs::read_to_string;
+use std::path::Path;
+
+use color_eyre::eyre::{WrapErr, eyre};
+
+use crate::app::parse_subid_map;
+use crate::app::state::State;
+use crate::app::state::shadow::{ManualHint, manual_entries};
+use crate::app::ui::IdMapEntry;
+use crate::changes::{QueuedChange, QueuedStep};
+use crate::finding::Finding;
+use crate::followup::{Change, Step, checklist};
+use crate::fs::subid::{ShadowBackup, SubID, append_entries, normalize, read_shadow_backup, sort_entries};
+use crate::fs::writer::PendingWrite;
+use crate::linux::passwd::parse_passwd;
+use crate::linux::{id_to_username, pct_set};
+use crate::lxc::config::Config;
+use crate::lxc::range_end;
+use crate::metadata::Metadata;
+use crate::settings::{ApplyMode, Settings};
+
+/// An automated fix which can be applied to resolve a finding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fix {
+    /// Rewrite all entries of /etc/subuid or /etc/subgid as `name:start:count`.
+    NormalizeSubid(SubID),
+    /// Remove hand-written entries of /etc/subuid or /etc/subgid and add them again through
+    /// `usermod`, so shadow-utils keeps track of them.
+    ReAddWithUsermod(SubID),
+    /// Grant the owner of the `count` ids from `start` in the other subid file the same range in
+    /// /etc/subuid or /etc/subgid, which it has no entry in.
+    MirrorSubidRange { sub_id: SubID, start: u32, count: u32 },
+    /// Create /etc/subuid or /etc/subgid, which doesn't exist, with a range for root.
+    CreateSubid(SubID),
+}
+
+/// The range Proxmox VE grants root out of the box, and which its unprivileged containers map by
+/// default.
+pub const DEFAULT_ROOT_RANGE: (u32, u32) = (100000, 65536);
+
+impl Fix {
+    pub fn description(self) -> String {
+        match self {
+            Fix::NormalizeSubid(sub_id) => format!(
+                "Rewrite {} so every entry is written as name:start:count, without surrounding whitespace.",
+                sub_id.path()
+            ),
+            Fix::ReAddWithUsermod(sub_id) => format!(
+                "Sort the entries of {} by range, and add the ones written since shadow-utils last wrote it again \
+                 with usermod --add-sub{}s.",
+                sub_id.path(),
+                sub_id.kind_name()
+            ),
+            Fix::MirrorSubidRange { sub_id, start, count } => format!(
+                "Add an entry to {} granting the owner of {start}:{count} in {} the same range.",
+                sub_id.path(),
+                sub_id.counterpart().path()
+            ),
+            Fix::CreateSubid(sub_id) => format!(
+                "Create {} with an entry for root, granting the same range as {} does or 100000:65536.",
+                sub_id.path(),
+                sub_id.counterpart().path()
+            ),
+        }
+    }
+
+    /// What the fix changes, for the follow-up checklist shown once it is applied.
+    pub fn changes(self) -> Vec<Change> {
+        match self {
+            Fix::NormalizeSubid(sub_id) | Fix::ReAddWithUsermod(sub_id) => vec![Change::SubidReformatted(sub_id)],
+            Fix::MirrorSubidRange { sub_id, .. } | Fix::CreateSubid(sub_id) => {
+                vec![Change::SubidRangesAdded(sub_id)]
+            },
+        }
+    }
+
+    /// The files the fix writes and what it writes to them, without touching anything. Ranges
+    /// added back through `usermod` don't show up here, only their removal does. The subid files
+    /// are the ones `metadata` points at.
+    pub fn pending_writes(self, metadata: &Metadata) -> color_eyre::Result<Vec<PendingWrite>> {
+        self.pending_writes_after(metadata, &[])
+    }
+
+    /// Like [`Fix::pending_writes`], but on top of the `planned` writes which haven't happened yet.
+    /// Of several writes to the same file, the last one counts.
+    pub fn pending_writes_after(
+        self,
+        metadata: &Metadata,
+        planned: &[PendingWrite],
+    ) -> color_eyre::Result<Vec<PendingWrite>> {
+        let read = |path: &Path| planned_content(planned, path);
+
+        match self {
+            Fix::NormalizeSubid(sub_id) => {
+                let path = metadata.subid_path(sub_id);
+                let content = read(path)?;
+
+                Ok(vec![PendingWrite {
+                    path: path.to_path_buf(),
+                    proposed: normalize(&content),
+                    current: content,
+                }])
+            },
+            Fix::ReAddWithUsermod(sub_id) => {
+                let path = metadata.subid_path(sub_id);
+                let content = read(path)?;
+                let entries = parse_subid_map(&content)?;
+                let manual = readded_entries(&entries, read_shadow_backup(path).as_ref());
+
+                Ok(vec![PendingWrite {
+                    path: path.to_path_buf(),
+                    proposed: sort_entries(&without_entries(&content, &manual)),
+                    current: content,
+                }])
+            },
+            Fix::MirrorSubidRange { sub_id, start, count } => {
+                let source = read(metadata.subid_path(sub_id.counterpart()))?;
+                let path = metadata.subid_path(sub_id);
+                let content = read(path)?;
+                let entries = parse_subid_map(&content)?;
+                let passwd = read_to_string(&metadata.passwd_path)
+                    .map(|content| parse_passwd(&content))
+                    .unwrap_or_default();
+                let Some(owner) = parse_subid_map(&source)?
+                    .into_iter()
+                    .find(|entry| entry.host_sub_id == start && entry.host_sub_id_count == count)
+                    .map(|entry| entry.host_user_id)
+                else {
+                    return Err(eyre!(
+                        "{} no longer has the range {start}:{count}",
+                        sub_id.counterpart().path()
+                    ));
+                };
+
+                if entries
+                    .iter()
+                    .any(|entry| passwd.same_user(&entry.host_user_id, &owner))
+                {
+                    return Err(eyre!("{owner} already has an entry in {}", sub_id.path()));
+                }
+
+                if let Some(entry) = entries.iter().find(|entry| {
+                    u64::from(entry.host_sub_id) < range_end(start, count)
+                        && u64::from(start) < range_end(entry.host_sub_id, entry.host_sub_id_count)
+                }) {
+                    return Err(eyre!(
+                        "{start}:{count} overlaps the range of {} in {}",
+                        entry.host_user_id,
+                        sub_id.path()
+                    ));
+                }
+
+                Ok(vec![PendingWrite {
+                    path: path.to_path_buf(),
+                    proposed: append_entries(&content, &[format!("{owner}:{start}:{count}")]),
+                    current: content,
+                }])
+            },
+            Fix::CreateSubid(sub_id) => {
+                let path = metadata.subid_path(sub_id);
+
+                if path.exists() || planned.iter().any(|write| write.path == path) {
+                    return Err(eyre!("{} exists already", path.display()));
+                }
+
+                // Whatever root was granted in the other file is what its containers map already
+                let passwd = read_to_string(&metadata.passwd_path)
+                    .map(|content| parse_passwd(&content))
+                    .unwrap_or_default();
+                let (start, count) = read(metadata.subid_path(sub_id.counterpart()))
+                    .ok()
+                    .and_then(|content| parse_subid_map(&content).ok())
+                    .and_then(|entries| {
+                        entries
+                            .into_iter()
+                            .find(|entry| passwd.same_user(&entry.host_user_id, "root"))
+                    })
+                    .map_or(DEFAULT_ROOT_RANGE, |entry| (entry.host_sub_id, entry.host_sub_id_count));
+
+                Ok(vec![PendingWrite {
+                    path: path.to_path_buf(),
+                    current: String::new(),
+                    proposed: format!("root:{start}:{count}\n"),
+                }])
+            },
+        }
+    }
+
+    /// What the fix does besides writing files, on top of the `planned` writes like
+    /// [`Fix::pending_writes_after`]. Only ranges added back through `usermod` need more than a write.
+    pub fn steps_after(self, metadata: &Metadata, planned: &[PendingWrite]) -> color_eyre::Result<Vec<QueuedStep>> {
+        let Fix::ReAddWithUsermod(sub_id) = self else {
+            return Ok(Vec::new());
+        };
+        let path = metadata.subid_path(sub_id);
+        let content = planned_content(planned, path)?;
+        let entries = parse_subid_map(&content)?;
+        let manual = readded_entries(&entries, read_shadow_backup(path).as_ref());
+        let mut additions = Vec::with_capacity(manual.len());
+        // Numeric owners are named through /etc/passwd, and through `id` for users it doesn't list
+        let passwd = read_to_string(&metadata.passwd_path)
+            .map(|content| parse_passwd(&content))
+            .unwrap_or_default();
+
+        // Resolve everything up front, so nothing is removed which can't be added back
+        for (entry, _) in &manual {
+            let login = match numeric_owner(entry.host_user_id.as_str()) {
+                Some(uid) => match passwd.user(uid) {
+                    Some(user) => user.name.to_string(),
+                    None => id_to_username(uid)?,
+                },
+                None => entry.host_user_id.to_string(),
+            };
+            let last = entry
+                .host_sub_id
+                .saturating_add(entry.host_sub_id_count.saturating_sub(1));
+
+            additions.push((login, entry.host_sub_id, last));
+        }
+
+        Ok(vec![QueuedStep::Usermod {
+            sub_id,
+            path: path.to_path_buf(),
+            additions,
+        }])
+    }
+
+    /// Applies the fix by writing to disk. Files are replaced atomically, and put back if adding
+    /// ranges through `usermod` fails afterwards.
+    pub fn apply(self, metadata: &Metadata) -> color_eyre::Result<()> {
+        apply_batch(&[self], metadata)
+    }
+}
+
+/// The fixes of a batch without repeats, in the order they are applied. Fixes going through
+/// `usermod` come last, as `usermod` edits the files itself.
+fn batch_order(fixes: &[Fix]) -> Vec<Fix> {
+    let mut ordered: Vec<Fix> = Vec::with_capacity(fixes.len());
+
+    for &fix in fixes {
+        if !ordered.contains(&fix) {
+            ordered.push(fix);
+        }
+    }
+
+    ordered.sort_by_key(|fix| matches!(fix, Fix::ReAddWithUsermod(_)));
+    ordered
+}
+
+/// The writes of several fixes applied one after another on top of the `planned` writes, with one
+/// write per file from its content before the first fix to what the last fix writing it leaves,
+/// along with the steps they take afterwards.
+pub fn batch(
+    fixes: &[Fix],
+    metadata: &Metadata,
+    planned: &[PendingWrite],
+) -> color_eyre::Result<(Vec<PendingWrite>, Vec<QueuedStep>)> {
+    let mut layers = planned.to_vec();
+    let mut writes: Vec<PendingWrite> = Vec::new();
+    let mut steps = Vec::new();
+
+    for fix in batch_order(fixes) {
+        steps.extend(fix.steps_after(metadata, &layers)?);
+
+        for write in fix.pending_writes_after(metadata, &layers)? {
+            match writes.iter_mut().find(|planned| planned.path == write.path) {
+                Some(planned) => planned.proposed.clone_from(&write.proposed),
+                None => writes.push(write.clone()),
+            }
+
+            layers.push(write);
+        }
+    }
+
+    Ok((writes, steps))
+}
+
+/// The writes of [`batch`] alone.
+pub fn batch_writes(
+    fixes: &[Fix],
+    metadata: &Metadata,
+    planned: &[PendingWrite],
+) -> color_eyre::Result<Vec<PendingWrite>> {
+    batch(fixes, metadata, planned).map(|(writes, _)| writes)
+}
+
+/// Applies several fixes as one, putting back every file already written if one of them fails.
+pub fn apply_batch(fixes: &[Fix], metadata: &Metadata) -> color_eyre::Result<()> {
+    let (writes, steps) = batch(fixes, metadata, &[])?;
+
+    QueuedChange {
+        description: format!("Fix {} findings", fixes.len()),
+        writes,
+        steps,
+        follow_up: fixes.iter().flat_map(|fix| fix.changes()).collect(),
+    }
+    .apply()
+}
+
+/// The content of `path` once the `planned` writes are made. Of several writes to the same file,
+/// the last one counts.
+fn planned_content(planned: &[PendingWrite], path: &Path) -> color_eyre::Result<String> {
+    match planned.iter().rev().find(|write| write.path == path) {
+        Some(write) => Ok(write.proposed.clone()),
+        None => read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// The hand-written entries which are removed and added again through `usermod`. Those which are
+/// only out of order are sorted into place instead, as `usermod` would append them again.
+fn readded_entries<'e>(entries: &'e [IdMapEntry], backup: Option<&ShadowBackup>) -> Vec<(&'e IdMapEntry, ManualHint)> {
+    let mut manual = manual_entries(entries, backup);
+
+    manual.retain(|(_, hint)| *hint == ManualHint::AddedSinceBackup);
+    manual
+}
+
+/// Subid file `content` without the lines of the `manual` entries.
+fn without_entries(content: &str, manual: &[(&IdMapEntry, ManualHint)]) -> String {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(i, _)| !manual.iter().any(|(entry, _)| entry.line_number == i + 1))
+        .map(|(_, line)| format!("{line}\n"))
+        .collect()
+}
+
+/// The user id of a numeric subid owner, which `usermod` needs as a login name.
+fn numeric_owner(owner: &str) -> Option<&str> {
+    owner.bytes().all(|b| b.is_ascii_digit()).then_some(owner)
+}
+
+/// Replaces every value of `key` in the container config at `path` with `values`.
+///
+/// With [`ApplyMode::Pct`] single valued options go through `pct set`. Raw `lxc.*` keys and multi
+/// valued options aren't supported by `pct`, so those are always written to the file directly.
+pub fn set_config_values(mode: ApplyMode, path: &Path, key: &str, values: &[&str]) -> color_eyre::Result<()> {
+    let vmid = path.file_stem().and_then(|stem| stem.to_str());
+
+    if mode == ApplyMode::Pct
+        && !key.starts_with("lxc.")
+        && let (Some(vmid), [value]) = (vmid, values)
+    {
+        return pct_set(vmid, key, value).wrap_err_with(|| format!("pct set {vmid} --{key} failed"));
+    }
+
+    config_values_write(path, key, values)?.commit()
+}
+
+/// Plans replacing every value of `key` in the container config at `path` with `values`, without
+/// writing it. Comments and the order of other keys are kept.
+pub fn config_values_write(path: &Path, key: &str, values: &[&str]) -> color_eyre::Result<PendingWrite> {
+    let content = read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+
+    config_edits_write(path, content, &[(key, values)])
+}
+
+/// Plans replacing the values of several keys in the container config at `path`, whose content is
+/// `content`, in one write, like [`config_values_write`] does for one.
+pub fn config_edits_write(path: &Path, content: String, edits: &[(&str, &[&str])]) -> color_eyre::Result<PendingWrite> {
+    let mut config: Config = content.parse()?;
+
+    for (key, values) in edits {
+        config.section_mut(None).replace_all(key, values);
+    }
+
+    Ok(PendingWrite {
+        path: path.to_path_buf(),
+        proposed: format!("{config}\n"),
+        current: content,
+    })
+}
+
+/// Evaluates all findings from scratch and returns the one identified by `finding_id` along with
+/// its default fix and the steps to follow once it is applied.
+pub fn locate(
+    metadata: &Metadata,
+    settings: Settings,
+    finding_id: &str,
+) -> color_eyre::Result<(Finding, Fix, Vec<Step>)> {
+    if metadata.is_viewer_only() {
+        return Err(eyre!("Fixes cannot be applied to files inspected with --root-prefix"));
+    }
+
+    let (state, _) = State::collect(metadata, settings);
+    let Some(finding) = state.findings.iter().find(|f| f.id() == finding_id) else {
+        let ids: Vec<_> = state
+            .findings
+            .iter()
+            .filter(|f| f.fix.is_some())
+            .map(Finding::id)
+            .collect();
+
+        return Err(eyre!(
+            "No finding with id {finding_id}. Fixable findings: {}",
+            if ids.is_empty() {
+                "none".to_string()
+            } else {
+                ids.join(", ")
+            }
+        ));
+    };
+    let Some(fix) = finding.fix else {
+        return Err(eyre!("Finding {finding_id} has no automated fix"));
+    };
+
+    let writes = fix.pending_writes(metadata)?;
+    let follow_up = checklist(&fix.changes(), &state.affected_vmids(metadata, &writes));
+
+    Ok((finding.clone(), fix, follow_up))
+}
+
+#[test]
+fn test_set_config_values_direct() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("100.conf");
+
+    std::fs::write(&path, "# keep me\nunprivileged: 1\nlxc.idmap: u 0 1000 3000\n")?;
+    // pct can't set raw lxc keys, so this is written directly even in pct mode
+    set_config_values(
+        ApplyMode::Pct,
+        &path,
+        "lxc.idmap",
+        &["u 0 100000 65536", "g 0 100000 65536"],
+    )?;
+
+    assert_eq!(
+        read_to_string(&path)?,
+        "# keep me\nunprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_pending_writes_custom_subid_path() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let metadata = Metadata {
+        subuid_path: dir.path().join("subuid"),
+        ..Metadata::default()
+    };
+
+    std::fs::write(&metadata.subuid_path, " root : 100000 : 65536 \n")?;
+
+    let writes = Fix::NormalizeSubid(SubID::UID).pending_writes(&metadata)?;
+
+    assert_eq!(writes[0].path, metadata.subuid_path);
+    assert_eq!(writes[0].proposed, "root:100000:65536\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_sort_out_of_order_entries() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let metadata = Metadata {
+        subuid_path: dir.path().join("subuid"),
+        passwd_path: dir.path().join("passwd"),
+        ..Metadata::default()
+    };
+    let fix = Fix::ReAddWithUsermod(SubID::UID);
+
+    std::fs::write(&metadata.subuid_path, "root:100000:65536\nalice:20000:10000\n")?;
+
+    // usermod would only append alice's range again, so it is moved in place instead
+    let writes = fix.pending_writes(&metadata)?;
+
+    assert_eq!(writes[0].proposed, "alice:20000:10000\nroot:100000:65536\n");
+
+    fix.apply(&metadata)?;
+
+    assert_eq!(
+        read_to_string(&metadata.subuid_path)?,
+        "alice:20000:10000\nroot:100000:65536\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_mirror_subid_range() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let metadata = Metadata {
+        subuid_path: dir.path().join("subuid"),
+        subgid_path: dir.path().join("subgid"),
+        passwd_path: dir.path().join("passwd"),
+        ..Metadata::default()
+    };
+    let fix = Fix::MirrorSubidRange {
+        sub_id: SubID::GID,
+        start: 100000,
+        count: 65536,
+    };
+
+    std::fs::write(&metadata.subuid_path, "root:100000:65536\n")?;
+    std::fs::write(&metadata.subgid_path, "alice:200000:65536")?;
+    fix.apply(&metadata)?;
+
+    assert_eq!(
+        read_to_string(&metadata.subgid_path)?,
+        "alice:200000:65536\nroot:100000:65536\n"
+    );
+    // Applying it twice would give root a second entry
+    assert!(fix.pending_writes(&metadata).is_err());
+
+    std::fs::write(&metadata.subgid_path, "alice:150000:65536\n")?;
+
+    assert!(fix.pending_writes(&metadata).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_create_subid() -> color_eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let metadata = Metadata {
+        subuid_path: dir.path().join("subuid"),
+        subgid_path: dir.path().join("subgid"),
+        passwd_path: dir.path().join("passwd"),
+        ..Metadata::default()
+    };
+
+    Fix::CreateSubid(SubID::UID).apply(&metadata)?;
+
+    assert_eq!(read_to_string(&metadata.subuid_path)?, "root:100000:65536\n");
+    assert_eq!(
+        std::fs::metadata(&metadata.subuid_path)?.permissions().mode() & 0o777,
+        0o644
+    );
+    assert!(Fix::CreateSubid(SubID::UID).pending_writes(&metadata).is_err());
+
+    // root's range in /etc/subuid is mirrored, even when granted to its uid
+    std::fs::write(&metadata.subuid_path, "alice:100000:65536\n0:200000:65536\n")?;
+    std::fs::write(&metadata.passwd_path, "root:x:0:0:root:/root:/bin/bash\n")?;
+
+    let writes = Fix::CreateSubid(SubID::GID).pending_writes(&metadata)?;
+
+    assert_eq!(writes[0].current, "");
+    assert_eq!(writes[0].proposed, "root:200000:65536\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_apply_batch() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let metadata = Metadata {
+        subuid_path: dir.path().join("subuid"),
+        subgid_path: dir.path().join("subgid"),
+        passwd_path: dir.path().join("passwd"),
+        ..Metadata::default()
+    };
+    let fixes = [
+        Fix::NormalizeSubid(SubID::UID),
+        Fix::CreateSubid(SubID::GID),
+        Fix::MirrorSubidRange {
+            sub_id: SubID::GID,
+            start: 200000,
+            count: 65536,
+        },
+        Fix::NormalizeSubid(SubID::UID),
+    ];
+
+    std::fs::write(&metadata.subuid_path, " root : 100000 : 65536 \nalice:200000:65536\n")?;
+
+    // The mirrored range goes into the file created before it, in the same write
+    let writes = batch_writes(&fixes, &metadata, &[])?;
+
+    assert_eq!(writes.len(), 2);
+    assert_eq!(writes[1].path, metadata.subgid_path);
+    assert_eq!(writes[1].current, "");
+    assert_eq!(writes[1].proposed, "root:100000:65536\nalice:200000:65536\n");
+
+    apply_batch(&fixes, &metadata)?;
+
+    assert_eq!(
+        read_to_string(&metadata.subuid_path)?,
+        "root:100000:65536\nalice:200000:65536\n"
+    );
+    assert_eq!(
+        read_to_string(&metadata.subgid_path)?,
+        "root:100000:65536\nalice:200000:65536\n"
+    );
+    assert!(batch_writes(&fixes, &metadata, &[]).is_err());
+
+    Ok(())
+}
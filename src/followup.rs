@@ -0,0 +1,231 @@
+//! Follow-up steps after pupman changes a file, since most changes only take effect once a container
+//! restarts.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::Command;
+
+use color_eyre::eyre::{WrapErr, eyre};
+
+use crate::fs::subid::SubID;
+use crate::linux::command;
+
+/// What kind of change was made.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// Entries in /etc/subuid or /etc/subgid were reformatted without changing any range.
+    SubidReformatted(SubID),
+    /// Ranges were added to /etc/subuid or /etc/subgid.
+    SubidRangesAdded(SubID),
+    /// Ranges in /etc/subuid or /etc/subgid were edited or removed.
+    SubidRangesEdited(SubID),
+    /// A container config was edited.
+    ConfigEdited { vmid: String },
+    /// A container's idmaps now expect its rootfs to be owned by `uid:gid` on the host, which it isn't.
+    RootfsOwnerExpected {
+        vmid: String,
+        path: PathBuf,
+        uid: u32,
+        gid: u32,
+    },
+    /// A container's idmaps pass host group `gid` through, which its users still need to join.
+    SharedGroupMapped {
+        vmid: String,
+        gid: u32,
+        group: String,
+        users: Vec<String>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub description: String,
+    /// A command which performs or verifies the step.
+    pub command: Option<Vec<String>>,
+    /// Whether the command only reads state, so it can be run without asking.
+    pub safe: bool,
+}
+
+impl Step {
+    fn note(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            command: None,
+            safe: true,
+        }
+    }
+
+    fn command(description: impl Into<String>, command: &[&str], safe: bool) -> Self {
+        Self {
+            description: description.into(),
+            command: Some(command.iter().map(|arg| arg.to_string()).collect()),
+            safe,
+        }
+    }
+
+    /// Runs the step's command, returning its trimmed stdout.
+    pub fn run(&self) -> color_eyre::Result<String> {
+        let Some((program, args)) = self.command.as_ref().and_then(|command| command.split_first()) else {
+            return Ok(String::new());
+        };
+        let mut command = Command::new(program);
+
+        command.args(args);
+
+        // Only steps which just read state may be run again when they hang
+        let output = if self.safe {
+            command::query(&mut command)
+        } else {
+            command::change(&mut command)
+        }
+        .wrap_err_with(|| format!("Failed to run {program}"))?;
+
+        if !output.status.success() {
+            return Err(eyre!(
+                "{} failed: {}",
+                self.command_line().unwrap_or_default(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    pub fn command_line(&self) -> Option<String> {
+        self.command.as_ref().map(|command| command.join(" "))
+    }
+}
+
+/// The checklist for a set of changes. `affected` are the containers whose idmaps map onto the
+/// subordinate ids of each kind which changed, and so are the only ones needing a restart.
+pub fn checklist(changes: &[Change], affected: &[(SubID, &str)]) -> Vec<Step> {
+    let affected_by = |sub_id: SubID| {
+        affected
+            .iter()
+            .filter(move |(kind, _)| *kind == sub_id)
+            .map(|(_, vmid)| *vmid)
+    };
+    let mut steps = Vec::new();
+
+    for change in changes {
+        match change {
+            Change::SubidReformatted(sub_id) => steps.push(Step::note(format!(
+                "Nothing to restart: {} was only reformatted and every range is unchanged.",
+                sub_id.path()
+            ))),
+            Change::SubidRangesAdded(sub_id) => {
+                steps.push(Step::note(format!(
+                    "No PVE service needs restarting. {} is read each time a container starts.",
+                    sub_id.path()
+                )));
+                steps.extend(affected_by(*sub_id).map(|vmid| {
+                    Step::command(
+                        format!("Restart container {vmid} if it should use the new ranges"),
+                        &["pct", "reboot", vmid],
+                        false,
+                    )
+                }));
+            },
+            Change::SubidRangesEdited(sub_id) => {
+                steps.push(Step::note(format!(
+                    "Running containers keep their old ranges until restarted. Check that their lxc.idmap \
+                     entries still fit inside {}.",
+                    sub_id.path()
+                )));
+                steps.extend(affected_by(*sub_id).map(|vmid| {
+                    Step::command(
+                        format!("Restart container {vmid} to pick up the edited ranges"),
+                        &["pct", "reboot", vmid],
+                        false,
+                    )
+                }));
+            },
+            Change::ConfigEdited { vmid } => {
+                steps.push(Step::command(
+                    format!("Check that PVE accepts the config of container {vmid}"),
+                    &["pct", "config", vmid],
+                    true,
+                ));
+                steps.push(Step::command(
+                    format!("Restart container {vmid}, idmaps only apply on start"),
+                    &["pct", "reboot", vmid],
+                    false,
+                ));
+            },
+            Change::RootfsOwnerExpected { vmid, path, uid, gid } => steps.push(Step::note(format!(
+                "Shift the ownership of every file in {} so its root is owned by {uid}:{gid} before starting \
+                 container {vmid}, or it won't be able to write to them. Fixing its rootfs ownership finding with \
+                 f then s does so, after a dry run.",
+                path.display()
+            ))),
+            Change::SharedGroupMapped {
+                vmid,
+                gid,
+                group,
+                users,
+            } => {
+                let gid = gid.to_string();
+
+                steps.push(Step::command(
+                    format!("Create group {group} in container {vmid} once it has restarted"),
+                    &["pct", "exec", vmid, "--", "groupadd", "-g", &gid, group],
+                    false,
+                ));
+                steps.extend(users.iter().map(|user| {
+                    Step::command(
+                        format!("Add {user} to {group} in container {vmid}, then restart its services"),
+                        &["pct", "exec", vmid, "--", "usermod", "-aG", group, user],
+                        false,
+                    )
+                }));
+            },
+        }
+    }
+
+    // A container restarted for several changes only needs restarting once
+    let mut seen = HashSet::new();
+
+    steps.retain(|step| seen.insert(step.command_line().unwrap_or_else(|| step.description.clone())));
+    steps
+}
+
+#[test]
+fn test_checklist() {
+    let steps = checklist(
+        &[
+            Change::SubidRangesAdded(SubID::UID),
+            Change::SubidRangesAdded(SubID::GID),
+            Change::ConfigEdited { vmid: "101".into() },
+        ],
+        &[(SubID::UID, "100"), (SubID::GID, "100"), (SubID::GID, "102")],
+    );
+    let commands: Vec<_> = steps.iter().filter_map(Step::command_line).collect();
+
+    assert_eq!(
+        commands,
+        ["pct reboot 100", "pct reboot 102", "pct config 101", "pct reboot 101"]
+    );
+    assert!(
+        steps
+            .iter()
+            .filter(|step| step.safe)
+            .all(|step| { step.command.as_ref().is_none_or(|command| command[1] == "config") })
+    );
+}
+
+#[test]
+fn test_rootfs_owner_step() {
+    let steps = checklist(
+        &[Change::RootfsOwnerExpected {
+            vmid: "100".into(),
+            path: "/rpool/data/subvol-100-disk-0".into(),
+            uid: 165536,
+            gid: 165536,
+        }],
+        &[],
+    );
+
+    assert_eq!(steps.len(), 1);
+    assert!(steps[0].command.is_none());
+    assert!(steps[0].description.contains("owned by 165536:165536"));
+}
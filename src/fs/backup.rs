@@ -0,0 +1,131 @@
+//! Timestamped copies of config and subid files, taken right before pupman writes them so the
+//! last change can be undone.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::WrapErr;
+
+use crate::fs::writer::write_atomic;
+
+/// Sets pupman's backups apart from the `-` suffixed ones shadow-utils keeps.
+const BACKUP_INFIX: &str = ".pupman.bak-";
+
+/// Backups taken this session, oldest first. Writes happen from both the TUI and fixes, so this is
+/// kept here rather than threaded through every caller.
+static SESSION: Mutex<Vec<Backup>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backup {
+    /// The file which was written.
+    pub original: PathBuf,
+    /// The copy of it from right before.
+    pub path: PathBuf,
+    pub taken_at: DateTime<Utc>,
+}
+
+fn session() -> MutexGuard<'static, Vec<Backup>> {
+    SESSION.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Where a backup of `path` taken at `taken_at` goes, e.g. `100.conf.pupman.bak-20250101T120301`.
+/// A number is added when the file was already backed up within the same second.
+fn backup_path(path: &Path, taken_at: DateTime<Utc>) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+
+    name.push(BACKUP_INFIX);
+    name.push(taken_at.format("%Y%m%dT%H%M%S").to_string());
+
+    let mut backup = PathBuf::from(&name);
+
+    for n in 1.. {
+        if !backup.exists() {
+            break;
+        }
+
+        let mut numbered = name.clone();
+
+        numbered.push(format!(".{n}"));
+        backup = numbered.into();
+    }
+
+    backup
+}
+
+/// Copies `path` next to itself before it is written, and remembers the copy for
+/// [`undo_last`]. Nothing is copied for a file which doesn't exist yet.
+pub fn back_up(path: &Path) -> color_eyre::Result<Option<Backup>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let taken_at = Utc::now();
+    let backup = Backup {
+        original: path.to_path_buf(),
+        path: backup_path(path, taken_at),
+        taken_at,
+    };
+
+    fs::copy(path, &backup.path).wrap_err_with(|| format!("Failed to back up {}", path.display()))?;
+    session().push(backup.clone());
+
+    Ok(Some(backup))
+}
+
+/// The most recent backup of this session, which [`undo_last`] would restore.
+pub fn last_backup() -> Option<Backup> {
+    session().last().cloned()
+}
+
+/// Puts the content of `backup` back in place of its original. The backup itself is kept.
+pub fn restore(backup: &Backup) -> color_eyre::Result<()> {
+    let content =
+        fs::read_to_string(&backup.path).wrap_err_with(|| format!("Failed to read {}", backup.path.display()))?;
+
+    write_atomic(&backup.original, &content)
+}
+
+/// Restores the most recent backup of this session and forgets it, so undoing again goes back one
+/// more change.
+pub fn undo_last() -> color_eyre::Result<Option<Backup>> {
+    let mut session = session();
+    let Some(backup) = session.last() else {
+        return Ok(None);
+    };
+
+    restore(backup)?;
+
+    Ok(session.pop())
+}
+
+#[test]
+fn test_back_up_and_restore() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("100.conf");
+
+    assert_eq!(back_up(&path)?, None);
+
+    fs::write(&path, "unprivileged: 1\n")?;
+
+    let first = back_up(&path)?.expect("the config exists");
+    let second = back_up(&path)?.expect("the config exists");
+    let name = first
+        .path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    assert!(name.starts_with("100.conf.pupman.bak-"), "{name}");
+    // Backups never share a path, even when taken within the same second
+    assert_ne!(first.path, second.path);
+
+    fs::write(&path, "unprivileged: 0\n")?;
+    restore(&first)?;
+
+    assert_eq!(fs::read_to_string(&path)?, "unprivileged: 1\n");
+
+    Ok(())
+}
@@ -1,4 +1,7 @@
+pub mod backup;
 pub mod monitor;
 pub mod reader;
+pub mod scan;
+pub mod shift;
 pub mod subid;
 pub mod writer;
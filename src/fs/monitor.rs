@@ -1,25 +1,40 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{self, RecvTimeoutError, Sender};
-use std::time::Duration;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
 use std::{fs, thread};
 
-use log::{debug, error};
-use notify::event::{CreateKind, ModifyKind, RemoveKind};
+use crate::app::bus::{Bus, Notification};
+use crate::app::event::FileSystemChangeKind;
+use crate::linux::zfs::ZfsCache;
+use crate::linux::{LinuxError, disk_space};
+use crate::lxc::{RootfsLocation, resolve_rootfs};
+use crate::metadata::Metadata;
+use crate::proxmox::storage::StorageConfig;
+use log::{Level, debug, error, info, warn};
+use notify::event::{CreateKind, MetadataKind, ModifyKind, RemoveKind};
 use notify::{
-    Config, Event as NotifyEvent, EventHandler, EventKind, INotifyWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+    Config, Event as NotifyEvent, EventHandler, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
 };
 
-use super::subid::{ETC_SUBGID, ETC_SUBUID};
-use crate::app::event::{AppEvent, Event, FileSystemChangeKind};
-use crate::lxc::rootfs_value_to_path;
+/// How often the container config directory is checked for having gone away or been remounted.
+const CONFIG_DIR_POLL: Duration = Duration::from_secs(2);
+/// The first wait before watching a config directory which went away again, doubled after every
+/// failed attempt up to [`MAX_REWATCH_DELAY`].
+const REWATCH_DELAY: Duration = Duration::from_secs(1);
+const MAX_REWATCH_DELAY: Duration = Duration::from_secs(30);
+/// How often files are polled for changes when inotify isn't available.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
 
-pub fn is_valid_file(path: &Path) -> bool {
-    if path == Path::new(ETC_SUBGID) || path == Path::new(ETC_SUBUID) {
-        return true;
-    }
+/// A watcher of either kind, inotify or polling.
+type FileWatcher = Box<dyn Watcher + Send>;
+/// The `lxc.include` files of each config, by the config's path.
+type Includes = Arc<Mutex<HashMap<PathBuf, Vec<PathBuf>>>>;
 
+/// Whether `path` looks like a container config, ie `<vmid>.conf`.
+pub fn is_container_config(path: &Path) -> bool {
     match path.file_name().and_then(|f| f.to_str()) {
         Some(filename) if filename.ends_with(".conf") => {
             let prefix = &filename[..filename.len() - 5];
@@ -29,50 +44,365 @@ pub fn is_valid_file(path: &Path) -> bool {
     }
 }
 
+/// What became of a watched file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileChange {
+    /// Created or written, so it needs to be read.
+    Written,
+    /// Removed or renamed away.
+    Removed,
+}
+
+/// Holds back the changes to each file until none came in for a while. Editors save in bursts:
+/// vim renames the file away, creates it again and writes it, which would otherwise unload a
+/// config, read it several times over and evaluate the findings after each.
+#[derive(Debug)]
+pub struct Debouncer {
+    window: Duration,
+    /// The last change to each file and when it is due.
+    pending: HashMap<PathBuf, (FileChange, Instant)>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Records a change, replacing one still pending for the same file as only the last one
+    /// matters, and holds it back for another window.
+    pub fn push(&mut self, path: PathBuf, change: FileChange, now: Instant) {
+        self.pending.insert(path, (change, now + self.window));
+    }
+
+    /// When the next change is due, if any are pending.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|&(_, due)| due).min()
+    }
+
+    /// Takes the changes to files which saw none for a whole window, sorted by path.
+    pub fn take_due(&mut self, now: Instant) -> Vec<(PathBuf, FileChange)> {
+        let mut due: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, due))| *due <= now)
+            .map(|(path, &(change, _))| (path.clone(), change))
+            .collect();
+
+        for (path, _) in &due {
+            self.pending.remove(path);
+        }
+
+        due.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        due
+    }
+}
+
+/// Passes changes on once they've settled, until the watcher sending them is dropped.
+fn forward_changes(changes: Receiver<(PathBuf, FileChange)>, bus: Bus, window: Duration) {
+    let mut debouncer = Debouncer::new(window);
+
+    loop {
+        let received = match debouncer.next_due() {
+            Some(due) => changes.recv_timeout(due.saturating_duration_since(Instant::now())),
+            None => changes.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok((path, change)) => debouncer.push(path, change, Instant::now()),
+            Err(RecvTimeoutError::Timeout) => {},
+            // The watcher was dropped, e.g. by a hard refresh
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        for (path, change) in debouncer.take_due(Instant::now()) {
+            match change {
+                FileChange::Written => bus.file_reads.publish(path),
+                FileChange::Removed => bus.fs_changes.publish(FileSystemChangeKind::RemoveFile(path)),
+            }
+        }
+    }
+}
+
 pub struct FileEventHandler {
-    app_tx: Sender<Event>,
-    file_tx: Sender<PathBuf>,
+    changes: Sender<(PathBuf, FileChange)>,
+    metadata: Metadata,
+    includes: Includes,
 }
 
 impl FileEventHandler {
-    pub fn new(app_tx: Sender<Event>, file_tx: Sender<PathBuf>) -> Self {
-        Self { app_tx, file_tx }
+    fn new(changes: Sender<(PathBuf, FileChange)>, metadata: Metadata, includes: Includes) -> Self {
+        Self {
+            changes,
+            metadata,
+            includes,
+        }
+    }
+
+    /// The configs which include the file at `path`.
+    fn including_configs(&self, path: &Path) -> Vec<PathBuf> {
+        self.includes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(_, includes)| includes.iter().any(|include| include == path))
+            .map(|(config, _)| config.clone())
+            .collect()
     }
 }
 
 impl EventHandler for FileEventHandler {
     fn handle_event(&mut self, event: Result<NotifyEvent, notify::Error>) {
-        if let Ok(event) = event {
-            for path in &event.paths {
-                if !is_valid_file(path) {
-                    continue;
-                }
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("File system monitor error: {err}");
+                return;
+            },
+        };
 
-                match &event.kind {
-                    EventKind::Create(CreateKind::File) | EventKind::Modify(ModifyKind::Data(_)) => {
-                        if self.file_tx.send(path.clone()).is_err() {
-                            error!("Failed to send file system change event {:?} for {path:?}", event.kind);
-                        }
-                    },
-                    // REVIEW: Not sure if (re)name is correct:
-                    EventKind::Modify(ModifyKind::Name(_)) | EventKind::Remove(RemoveKind::File) => {
-                        if self
-                            .app_tx
-                            .send(Event::App(AppEvent::FileSystemChanged(
-                                FileSystemChangeKind::RemoveFile(path.clone()),
-                            )))
-                            .is_err()
-                        {
-                            error!("Failed to send file system change event {:?} for {path:?}", event.kind);
-                        }
-                    },
-                    _ => {
-                        debug!("Unsupported file system change kind: {event:?}");
+        for path in &event.paths {
+            let watched = is_container_config(path)
+                || self.metadata.subid_for_path(path).is_some()
+                || *path == self.metadata.lxc_default_config
+                || *path == self.metadata.passwd_path
+                || *path == self.metadata.group_path;
+            let including = self.including_configs(path);
 
-                        continue;
-                    },
-                };
+            if !watched && including.is_empty() {
+                continue;
+            }
+
+            let Some(change) = file_change(&event.kind, path) else {
+                debug!("Unsupported file system change kind: {event:?}");
+
+                continue;
+            };
+
+            if watched {
+                let _ = self.changes.send((path.clone(), change));
+            }
+
+            // Whether an include was written or removed, the configs including it read differently now
+            for config in including {
+                let _ = self.changes.send((config, FileChange::Written));
+            }
+        }
+    }
+}
+
+/// What an event means for the file at `path`, if it changed it at all.
+fn file_change(kind: &EventKind, path: &Path) -> Option<FileChange> {
+    match kind {
+        // Polling only tells whether a file appeared, vanished or got a newer modification time
+        EventKind::Create(CreateKind::File | CreateKind::Any)
+        | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(MetadataKind::WriteTime)) => {
+            Some(FileChange::Written)
+        },
+        // Renames are how shadow-utils and editors replace files, so whether one took a file away or
+        // put it in place depends on which end of it the path is. The replaced file is removed
+        // too, while the path already holds the new one.
+        EventKind::Modify(ModifyKind::Name(_)) | EventKind::Remove(RemoveKind::File | RemoveKind::Any) => {
+            Some(if path.exists() {
+                FileChange::Written
+            } else {
+                FileChange::Removed
+            })
+        },
+        _ => None,
+    }
+}
+
+/// The rootfs watcher's paths, with the rootfs value, location and last metadata of each.
+type RootfsPaths = HashMap<PathBuf, (String, RootfsLocation, fs::Metadata)>;
+
+/// Resolves `rootfs_value` to where it is mounted and publishes its metadata, then keeps checking
+/// it from the watcher loop.
+fn watch_rootfs(bus: &Bus, storage: &StorageConfig, zfs: &mut ZfsCache, paths: &mut RootfsPaths, rootfs_value: String) {
+    let location = match resolve_rootfs(&rootfs_value, storage) {
+        Ok(location) => location.remount(zfs),
+        Err(err) => {
+            error!("Failed to resolve rootfs value {rootfs_value} for load: {err:?}");
+            bus.fs_changes
+                .publish(FileSystemChangeKind::DirUnavailable(rootfs_value, format!("{err:#}")));
+            return;
+        },
+    };
+    let path = location.mountpoint.clone();
+    let md = match fs::metadata(&path) {
+        Ok(md) => md,
+        Err(err) => {
+            error!("Failed to monitor metadata for {}: {err:?}", path.display());
+            bus.fs_changes.publish(FileSystemChangeKind::DirUnavailable(
+                rootfs_value,
+                format!("{}: {err}", path.display()),
+            ));
+            return;
+        },
+    };
+
+    paths.insert(path.clone(), (rootfs_value.clone(), location.clone(), md.clone()));
+    send_disk_space(bus, &rootfs_value, &path);
+    bus.fs_changes
+        .publish(FileSystemChangeKind::UpdateDir(rootfs_value, location, Box::new(md)));
+}
+
+fn send_disk_space(bus: &Bus, rootfs_value: &str, path: &Path) {
+    let space = match disk_space(path) {
+        Ok(space) => space,
+        // Shown rather than only logged, the rootfs checks are stale until it answers again
+        Err(err @ LinuxError::Timeout(..)) => {
+            bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: format!("Disk space of {} is unknown: {err}", path.display()),
+            });
+            return;
+        },
+        Err(err) => {
+            error!("Failed to look up disk space for {}: {err:?}", path.display());
+            return;
+        },
+    };
+
+    bus.fs_changes
+        .publish(FileSystemChangeKind::UpdateDiskSpace(rootfs_value.to_owned(), space));
+}
+
+/// Tells a directory apart from one mounted or created at the same path later, which inotify
+/// watches don't carry over to.
+fn dir_identity(path: &Path) -> Option<(u64, u64)> {
+    fs::metadata(path)
+        .ok()
+        .filter(|md| md.is_dir())
+        .map(|md| (md.dev(), md.ino()))
+}
+
+/// Keeps the container config directory watched across pmxcfs restarts. /etc/pve is a FUSE mount
+/// which disappears while pve-cluster restarts, taking the inotify watch with it without so much
+/// as an error. Starts out retrying when the directory couldn't be watched to begin with. Stops
+/// once `watcher` is dropped.
+fn guard_config_dir(watcher: Weak<Mutex<FileWatcher>>, bus: Bus, dir: PathBuf, watched: bool) {
+    let mut identity = dir_identity(&dir);
+    let mut available = watched;
+    let mut delay = CONFIG_DIR_POLL;
+
+    if !watched {
+        bus.fs_changes.publish(FileSystemChangeKind::ConfigDirLost(dir.clone()));
+        delay = REWATCH_DELAY;
+    }
+
+    loop {
+        thread::sleep(delay);
+
+        let Some(watcher) = watcher.upgrade() else {
+            break;
+        };
+        let current = dir_identity(&dir);
+
+        if available {
+            if current == identity {
+                continue;
             }
+
+            // The watch is gone along with the old mount, whether or not a new one is already there
+            warn!(
+                "{} became unavailable, changes to container configs go unnoticed until it is back",
+                dir.display()
+            );
+            let _ = watcher.lock().unwrap_or_else(PoisonError::into_inner).unwatch(&dir);
+            bus.fs_changes.publish(FileSystemChangeKind::ConfigDirLost(dir.clone()));
+            available = false;
+            delay = REWATCH_DELAY;
+
+            if current.is_none() {
+                continue;
+            }
+        }
+
+        let watched = match current {
+            Some(_) => watcher
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .watch(&dir, RecursiveMode::Recursive)
+                .map_err(|err| err.to_string()),
+            None => Err("it doesn't exist".to_string()),
+        };
+
+        match watched {
+            Ok(()) => {
+                info!("Watching {} again", dir.display());
+                identity = current;
+                available = true;
+                delay = CONFIG_DIR_POLL;
+                bus.fs_changes
+                    .publish(FileSystemChangeKind::ConfigDirRestored(dir.clone()));
+            },
+            Err(err) => {
+                debug!("Can't watch {} yet, retrying in {delay:?}: {err}", dir.display());
+                delay = (delay * 2).min(MAX_REWATCH_DELAY);
+            },
+        }
+    }
+}
+
+/// Watches the `lxc.include` files of each config as they are published to [`Bus::include_watches`].
+/// Their directories are watched rather than the files, as editors replace files with a rename.
+/// Stops once `watcher` is dropped.
+fn watch_includes(
+    configs: Receiver<(PathBuf, Vec<PathBuf>)>,
+    watcher: Weak<Mutex<FileWatcher>>,
+    includes: Includes,
+    changes: Sender<(PathBuf, FileChange)>,
+    config_dir: PathBuf,
+) {
+    let mut watched_dirs = HashSet::new();
+
+    loop {
+        let received = configs.recv_timeout(Duration::from_secs(5));
+        let Some(watcher) = watcher.upgrade() else {
+            break;
+        };
+        let (config, paths) = match received {
+            Ok(received) => received,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let previous = if paths.is_empty() {
+            includes.lock().unwrap_or_else(PoisonError::into_inner).remove(&config)
+        } else {
+            includes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(config.clone(), paths.clone())
+        }
+        .unwrap_or_default();
+
+        for dir in paths.iter().filter_map(|path| path.parent()) {
+            // The config directory is watched recursively already
+            if dir.starts_with(&config_dir) || watched_dirs.contains(dir) {
+                continue;
+            }
+
+            match watcher
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .watch(dir, RecursiveMode::NonRecursive)
+            {
+                Ok(()) => {
+                    watched_dirs.insert(dir.to_path_buf());
+                },
+                Err(err) => warn!(
+                    "Not watching {}, changes to lxc.include files in it show up after a reload: {err}",
+                    dir.display()
+                ),
+            }
+        }
+
+        // A new include may have changed between the config being read and it being watched
+        if paths.iter().any(|path| !previous.contains(path)) {
+            let _ = changes.send((config, FileChange::Written));
         }
     }
 }
@@ -80,72 +410,159 @@ impl EventHandler for FileEventHandler {
 /// The handler for the file system monitor.
 // It turns out that Linux and INotify don't support notifications when owner / group
 // changes, so we need a secondary poller to detect that change.
-#[derive(Debug)]
 pub struct MonitorHandler {
-    /// Watches all files: `/etc/subuid`, `/etc/subgid`, and the LXC config directory.
-    _file_watcher: INotifyWatcher,
-    /// Sender to watch all rootfs owner/group changes.
-    dir_watcher_tx: Sender<String>,
+    /// Watches all files: `/etc/subuid`, `/etc/subgid`, `/etc/lxc/default.conf`, the directory of
+    /// `/etc/passwd` and `/etc/group`, the LXC config directory and the directories of the configs'
+    /// `lxc.include` files. Shared with the threads which watch the include directories and watch
+    /// the config directory again after it was remounted.
+    _file_watcher: Arc<Mutex<FileWatcher>>,
+    /// Whether files are polled every [`POLL_INTERVAL`], as inotify isn't available.
+    polling: bool,
 }
 
 impl MonitorHandler {
-    pub fn new(app_tx: Sender<Event>, file_tx: Sender<PathBuf>, lxc_config_dir: &Path) -> notify::Result<Self> {
-        let event_handler = FileEventHandler {
-            app_tx: app_tx.clone(),
-            file_tx,
+    /// Starts watching files right away, passing on changes to each once none came in for
+    /// `debounce`. Rootfs directories are watched once their values are published to
+    /// [`Bus::rootfs_watches`]. Every thread it starts stops once it is dropped.
+    pub fn new(bus: Bus, metadata: &Metadata, debounce: Duration) -> notify::Result<Self> {
+        let (changes_tx, changes_rx) = mpsc::channel();
+        let forward_bus = bus.clone();
+
+        thread::spawn(move || forward_changes(changes_rx, forward_bus, debounce));
+
+        let config_dir = &metadata.lxc_config_dir;
+        let includes = Includes::default();
+        let poll_watcher = || -> notify::Result<FileWatcher> {
+            let event_handler = FileEventHandler::new(changes_tx.clone(), metadata.clone(), includes.clone());
+
+            Ok(Box::new(PollWatcher::new(
+                event_handler,
+                Config::default().with_poll_interval(POLL_INTERVAL),
+            )?))
         };
-        let mut file_watcher = RecommendedWatcher::new(event_handler, Config::default())?;
+        let (mut file_watcher, mut polling) = match RecommendedWatcher::new(
+            FileEventHandler::new(changes_tx.clone(), metadata.clone(), includes.clone()),
+            Config::default(),
+        ) {
+            Ok(watcher) => (Box::new(watcher) as FileWatcher, false),
+            Err(err) => {
+                warn!("inotify isn't available, polling files for changes instead: {err}");
+                (poll_watcher()?, true)
+            },
+        };
+        let mut config_dir_watched = file_watcher.watch(config_dir, RecursiveMode::Recursive);
+
+        // An existing directory inotify refuses is past the watch limit, or on a file system without
+        // inotify support
+        if !polling
+            && let Err(err) = &config_dir_watched
+            && config_dir.is_dir()
+        {
+            warn!(
+                "Can't watch {} with inotify, polling files for changes instead: {err}",
+                config_dir.display()
+            );
+            file_watcher = poll_watcher()?;
+            polling = true;
+            config_dir_watched = file_watcher.watch(config_dir, RecursiveMode::Recursive);
+        }
+
+        if let Err(err) = &config_dir_watched {
+            warn!("Not watching {}: {err}", config_dir.display());
+        }
 
-        file_watcher.watch(Path::new(ETC_SUBGID), RecursiveMode::NonRecursive)?;
-        file_watcher.watch(Path::new(ETC_SUBUID), RecursiveMode::NonRecursive)?;
-        file_watcher.watch(lxc_config_dir, RecursiveMode::Recursive)?;
+        // Without them containers can't start, which a finding says, but they may be created later.
+        // Their directory is watched until then, as a path which doesn't exist can't be
+        for path in [&metadata.subuid_path, &metadata.subgid_path] {
+            let watched = match path.parent() {
+                Some(dir) if !path.exists() => dir,
+                _ => path.as_path(),
+            };
 
-        let (dir_watcher_tx, dir_watcher_rx) = mpsc::channel::<String>();
+            if let Err(err) = file_watcher.watch(watched, RecursiveMode::NonRecursive) {
+                warn!(
+                    "Not watching {}, changes to it show up after a reload: {err}",
+                    path.display()
+                );
+            }
+        }
+
+        // Most PVE hosts have no default.conf, which only matters for plain LXC containers anyway
+        if let Err(err) = file_watcher.watch(&metadata.lxc_default_config, RecursiveMode::NonRecursive) {
+            debug!("Not watching {}: {err}", metadata.lxc_default_config.display());
+        }
+
+        // useradd, userdel and friends replace /etc/passwd and /etc/group with a rename, which a
+        // watch on the file itself doesn't outlive, so their directory is watched instead. Without
+        // them, owners are looked up through `id`
+        let mut account_dirs: Vec<&Path> = Vec::new();
+
+        for path in [&metadata.passwd_path, &metadata.group_path] {
+            if let Some(dir) = path.parent()
+                && !account_dirs.contains(&dir)
+            {
+                account_dirs.push(dir);
+            }
+        }
+
+        for dir in account_dirs {
+            if let Err(err) = file_watcher.watch(dir, RecursiveMode::NonRecursive) {
+                warn!(
+                    "Not watching {}, changes to users and groups show up after a reload: {err}",
+                    dir.display()
+                );
+            }
+        }
+
+        let file_watcher = Arc::new(Mutex::new(file_watcher));
+        let (guarded_watcher, guard_bus, guarded_dir) =
+            (Arc::downgrade(&file_watcher), bus.clone(), config_dir.clone());
+
+        thread::spawn(move || guard_config_dir(guarded_watcher, guard_bus, guarded_dir, config_dir_watched.is_ok()));
+
+        let (include_rx, include_watcher, include_dir) = (
+            bus.include_watches.subscribe(),
+            Arc::downgrade(&file_watcher),
+            config_dir.clone(),
+        );
+
+        thread::spawn(move || watch_includes(include_rx, include_watcher, includes, changes_tx, include_dir));
+
+        let dir_watcher_rx = bus.rootfs_watches.subscribe();
+        let storage = metadata.storage.clone();
+        // Stops along with the handler, like when it is replaced by a rescan
+        let handler_alive = Arc::downgrade(&file_watcher);
 
         thread::spawn(move || {
             let mut paths = HashMap::new();
+            let mut zfs = ZfsCache::default();
 
             loop {
+                let received = dir_watcher_rx.recv_timeout(Duration::from_secs(5));
+
+                if handler_alive.strong_count() == 0 {
+                    debug!("RootFS ownership watcher stopped along with its monitor");
+                    break;
+                }
+
                 // Wait up to 5 seconds for a new value, otherwise timeout to re-check
-                match dir_watcher_rx.recv_timeout(Duration::from_secs(5)) {
+                match received {
                     Ok(rootfs_value) => {
-                        let path = match rootfs_value_to_path(&rootfs_value) {
-                            Ok(path) => path,
-                            Err(err) => {
-                                error!("Failed to convert rootfs value {rootfs_value} to path for load: {err:?}");
-                                continue;
-                            },
-                        };
-                        let md = match fs::metadata(&path) {
-                            Ok(md) => md,
-                            Err(err) => {
-                                error!("Failed to monitor metadata for {}: {err:?}", path.display());
-                                continue;
-                            },
-                        };
-
-                        paths.insert(path.clone(), (rootfs_value.clone(), md.clone()));
-
-                        if app_tx
-                            .send(Event::App(AppEvent::FileSystemChanged(
-                                FileSystemChangeKind::UpdateDir(rootfs_value, path, md),
-                            )))
-                            .is_err()
-                        {
-                            error!("Failed to send initial UpdateDir event");
-                        }
-
+                        watch_rootfs(&bus, &storage, &mut zfs, &mut paths, rootfs_value);
                         continue;
                     },
                     // Timeout: time to re-check all watched paths
                     Err(RecvTimeoutError::Timeout) => {},
+                    // The bus was closed, e.g. by a hard refresh
                     Err(RecvTimeoutError::Disconnected) => {
-                        error!("RootFS ownership watcher died unexpectedly!");
+                        debug!("RootFS ownership watcher stopped");
                         break;
                     },
                 };
 
-                for (path, (rootfs_value, old_md)) in &mut paths {
+                let mut remounted = Vec::new();
+
+                for (path, (rootfs_value, location, old_md)) in &mut paths {
                     let md = match fs::metadata(path) {
                         Ok(md) => md,
                         Err(err) => {
@@ -154,16 +571,34 @@ impl MonitorHandler {
                         },
                     };
 
-                    if md.gid() != old_md.gid() || md.uid() != old_md.uid() {
-                        if app_tx
-                            .send(Event::App(AppEvent::FileSystemChanged(
-                                FileSystemChangeKind::UpdateDir(rootfs_value.clone(), path.clone(), md.clone()),
-                            )))
-                            .is_err()
-                        {
-                            error!("Failed to send UpdateDir event on change");
-                        }
+                    // Another file system at the path means the volume was mounted elsewhere or
+                    // unmounted, so where it lives is looked up again
+                    if md.dev() != old_md.dev() || md.ino() != old_md.ino() {
+                        remounted.push(path.clone());
+                    } else if md.gid() != old_md.gid() || md.uid() != old_md.uid() {
+                        bus.fs_changes.publish(FileSystemChangeKind::UpdateDir(
+                            rootfs_value.clone(),
+                            location.clone(),
+                            Box::new(md.clone()),
+                        ));
                         *old_md = md;
+
+                        // Ownership changes are usually a mass chown, which can eat into free space
+                        send_disk_space(&bus, rootfs_value, path);
+                    }
+                }
+
+                if !remounted.is_empty() {
+                    zfs.refresh();
+                }
+
+                for path in remounted {
+                    if let Some((rootfs_value, ..)) = paths.remove(&path) {
+                        info!(
+                            "{} was remounted, looking up rootfs {rootfs_value} again",
+                            path.display()
+                        );
+                        watch_rootfs(&bus, &storage, &mut zfs, &mut paths, rootfs_value);
                     }
                 }
             }
@@ -171,12 +606,77 @@ impl MonitorHandler {
 
         Ok(Self {
             _file_watcher: file_watcher,
-            dir_watcher_tx,
+            polling,
         })
     }
 
-    pub fn watch_rootfs(&mut self, rootfs_value: &str) -> notify::Result<()> {
-        self.dir_watcher_tx.send(rootfs_value.to_owned())?;
-        Ok(())
+    /// Whether files are polled for changes rather than watched through inotify.
+    pub fn is_polling(&self) -> bool {
+        self.polling
     }
 }
+
+#[test]
+fn test_debouncer_coalesces_bursts() {
+    let window = Duration::from_millis(100);
+    let mut debouncer = Debouncer::new(window);
+    let start = Instant::now();
+    let config = PathBuf::from("/etc/pve/lxc/100.conf");
+    let subuid = PathBuf::from("/etc/subuid");
+
+    assert_eq!(debouncer.next_due(), None);
+
+    // How vim saves: the file is renamed away, created again and written
+    debouncer.push(config.clone(), FileChange::Removed, start);
+    debouncer.push(config.clone(), FileChange::Written, start + Duration::from_millis(10));
+    debouncer.push(config.clone(), FileChange::Written, start + Duration::from_millis(20));
+    debouncer.push(subuid.clone(), FileChange::Removed, start + Duration::from_millis(50));
+
+    assert!(debouncer.take_due(start + Duration::from_millis(110)).is_empty());
+    assert_eq!(debouncer.next_due(), Some(start + Duration::from_millis(120)));
+    assert_eq!(
+        debouncer.take_due(start + Duration::from_millis(120)),
+        [(config, FileChange::Written)]
+    );
+    assert_eq!(
+        debouncer.take_due(start + Duration::from_millis(200)),
+        [(subuid, FileChange::Removed)]
+    );
+    assert_eq!(debouncer.next_due(), None);
+
+    // Without a window, every change is due right away
+    let mut debouncer = Debouncer::new(Duration::ZERO);
+
+    debouncer.push(PathBuf::from("/etc/subgid"), FileChange::Written, start);
+
+    assert_eq!(debouncer.take_due(start).len(), 1);
+}
+
+#[test]
+fn test_file_change() -> std::io::Result<()> {
+    use notify::event::{DataChange, RenameMode};
+
+    let dir = tempfile::tempdir()?;
+    let passwd = dir.path().join("passwd");
+    let renamed = EventKind::Modify(ModifyKind::Name(RenameMode::Any));
+    let removed = EventKind::Remove(RemoveKind::File);
+
+    assert_eq!(file_change(&renamed, &passwd), Some(FileChange::Removed));
+    assert_eq!(file_change(&removed, &passwd), Some(FileChange::Removed));
+    assert_eq!(
+        file_change(&EventKind::Modify(ModifyKind::Data(DataChange::Any)), &passwd),
+        Some(FileChange::Written)
+    );
+    assert_eq!(
+        file_change(&EventKind::Access(notify::event::AccessKind::Any), &passwd),
+        None
+    );
+
+    // How userdel saves: passwd+ is written, then renamed over passwd
+    fs::write(&passwd, "root:x:0:0::/root:/bin/bash\n")?;
+
+    assert_eq!(file_change(&renamed, &passwd), Some(FileChange::Written));
+    assert_eq!(file_change(&removed, &passwd), Some(FileChange::Written));
+
+    Ok(())
+}
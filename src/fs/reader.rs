@@ -1,30 +1,32 @@
-use core::panic;
 use std::fs::read_to_string;
+use std::io::ErrorKind;
 use std::path::PathBuf;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::mpsc::Receiver;
 
-use log::error;
+use log::debug;
 
-use crate::app::event::{AppEvent, Event, FileSystemChangeKind};
+use crate::app::bus::Bus;
+use crate::app::event::FileSystemChangeKind;
 
 /// Receives requests to read files from the file system monitor. Should run in a separate thread.
 /// This thread will read the file and send the contents back to the main thread.
 /// The main thread will then process the file and update the UI accordingly.
-pub fn start(rx: Receiver<PathBuf>, tx: Sender<Event>) {
+/// Returns once the bus is closed.
+pub fn start(rx: Receiver<PathBuf>, bus: Bus) {
     while let Ok(path) = rx.recv() {
-        match read_to_string(&path) {
-            Ok(content) => {
-                let app_event = Event::App(AppEvent::FileSystemChanged(FileSystemChangeKind::UpdateFile(
-                    path, content,
-                )));
+        bus.fs_changes.publish(FileSystemChangeKind::ReadStarted(path.clone()));
 
-                if let Err(err) = tx.send(app_event) {
-                    error!("Failed to send file system change event: {err}");
-                };
+        match read_to_string(&path) {
+            Ok(content) => bus.fs_changes.publish(FileSystemChangeKind::UpdateFile(path, content)),
+            // Gone before it could be read, or never there like /etc/subuid on minimal hosts
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                bus.fs_changes.publish(FileSystemChangeKind::RemoveFile(path))
             },
-            Err(err) => error!("Failed to read file: {err}"),
+            Err(err) => bus
+                .fs_changes
+                .publish(FileSystemChangeKind::ReadFailed(path, err.to_string())),
         }
     }
 
-    panic!("File system monitor thread exited unexpectedly");
+    debug!("File reader stopped");
 }
@@ -0,0 +1,212 @@
+//! Deep ownership scans, which walk a rootfs and count who owns the files in it. Only the rootfs
+//! directory itself is stat-ed otherwise, which misses files a failed chown or an old restore left
+//! behind.
+
+use std::collections::HashMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fs, thread};
+
+use log::Level;
+
+use crate::app::bus::{Bus, Notification};
+use crate::app::event::FileSystemChangeKind;
+use crate::fs::subid::SubID;
+use crate::lxc::idmap::IdMap;
+
+/// How many files of each owner are kept as examples.
+const EXAMPLES: usize = 3;
+
+/// The files of a single owner found by a scan.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OwnerFiles {
+    pub files: u64,
+    /// The first few files found, relative to the scanned directory.
+    pub examples: Vec<PathBuf>,
+}
+
+/// Who owns the files below a directory, by host `(uid, gid)`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OwnershipScan {
+    pub owners: HashMap<(u32, u32), OwnerFiles>,
+    /// Directories which couldn't be read, and so weren't counted.
+    pub unreadable: u64,
+    /// Whether the scan was cancelled before it got through everything.
+    pub cancelled: bool,
+}
+
+impl OwnershipScan {
+    pub fn files(&self) -> u64 {
+        self.owners.values().map(|owner| owner.files).sum()
+    }
+
+    /// The owners with a uid or gid outside of `idmaps`' host ranges, by most files first.
+    pub fn unmapped<'s>(&'s self, idmaps: &[IdMap]) -> Vec<(&'s (u32, u32), &'s OwnerFiles)> {
+        let mapped = |kind, id: u32| {
+            idmaps
+                .iter()
+                .any(|idmap| idmap.kind == kind && idmap.host_id <= id && u64::from(id) < idmap.host_end())
+        };
+        let mut unmapped: Vec<_> = self
+            .owners
+            .iter()
+            .filter(|((uid, gid), _)| !mapped(SubID::UID, *uid) || !mapped(SubID::GID, *gid))
+            .collect();
+
+        unmapped.sort_by(|(a_owner, a), (b_owner, b)| b.files.cmp(&a.files).then(a_owner.cmp(b_owner)));
+        unmapped
+    }
+}
+
+/// Visits everything below `root` with its metadata, without following symlinks or leaving its file
+/// system, until `cancel` is set. Returns how many directories couldn't be read and whether it was
+/// cancelled.
+pub fn walk(root: &Path, cancel: &AtomicBool, mut visit: impl FnMut(&Path, &fs::Metadata)) -> (u64, bool) {
+    let Ok(root_md) = fs::symlink_metadata(root) else {
+        return (1, false);
+    };
+    let mut unreadable = 0;
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            unreadable += 1;
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            if cancel.load(Ordering::Relaxed) {
+                return (unreadable, true);
+            }
+
+            let path = entry.path();
+            let Ok(md) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+
+            visit(&path, &md);
+
+            // Mounts below the rootfs belong to something else
+            if md.is_dir() && md.dev() == root_md.dev() {
+                dirs.push(path);
+            }
+        }
+    }
+
+    (unreadable, false)
+}
+
+/// Counts the owners of everything below `root`, until `cancel` is set.
+pub fn scan_ownership(root: &Path, cancel: &AtomicBool) -> OwnershipScan {
+    let mut owners: HashMap<(u32, u32), OwnerFiles> = HashMap::new();
+    let (unreadable, cancelled) = walk(root, cancel, |path, md| {
+        let owner = owners.entry((md.uid(), md.gid())).or_default();
+
+        owner.files += 1;
+
+        if owner.examples.len() < EXAMPLES {
+            owner
+                .examples
+                .push(path.strip_prefix(root).unwrap_or(path).to_path_buf());
+        }
+    });
+
+    OwnershipScan {
+        owners,
+        unreadable,
+        cancelled,
+    }
+}
+
+/// Scans of several rootfs directories, one after the other on a thread of their own.
+#[derive(Debug, Default)]
+pub struct DeepScan {
+    cancel: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl DeepScan {
+    /// Starts scanning each `(rootfs value, path)` of `targets`. Every scan is published to
+    /// [`Bus::fs_changes`] as soon as it's done.
+    pub fn start(targets: Vec<(String, PathBuf)>, bus: Bus) -> Arc<Self> {
+        let deep_scan = Arc::new(Self::default());
+        let handle = Arc::clone(&deep_scan);
+
+        thread::spawn(move || {
+            let count = targets.len();
+
+            for (rootfs_value, path) in targets {
+                let scan = scan_ownership(&path, &handle.cancel);
+
+                bus.fs_changes
+                    .publish(FileSystemChangeKind::OwnershipScanned(rootfs_value, Box::new(scan)));
+
+                if handle.cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+
+            handle.finished.store(true, Ordering::Relaxed);
+            bus.notifications.publish(Notification {
+                level: Level::Info,
+                message: if handle.cancel.load(Ordering::Relaxed) {
+                    "Deep scan cancelled, the counts so far are kept".to_string()
+                } else {
+                    format!("Deep scan of {count} rootfs directories finished")
+                },
+            });
+        });
+
+        deep_scan
+    }
+
+    /// Stops the scan after the file it is on.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.finished.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn test_scan_ownership() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    fs::create_dir_all(dir.path().join("etc/apt"))?;
+    fs::write(dir.path().join("etc/hostname"), "ct\n")?;
+    fs::write(dir.path().join("etc/apt/sources.list"), "")?;
+
+    let md = fs::metadata(dir.path())?;
+    let owner = (md.uid(), md.gid());
+    let scan = scan_ownership(dir.path(), &AtomicBool::new(false));
+
+    assert!(!scan.cancelled);
+    assert_eq!(scan.files(), 4);
+    assert_eq!(scan.owners[&owner].examples.len(), EXAMPLES);
+
+    let mapping = |(uid, gid): (u32, u32)| {
+        [(SubID::UID, uid), (SubID::GID, gid)].map(|(kind, host_id)| IdMap {
+            kind,
+            container_id: 0,
+            host_id,
+            size: 1,
+        })
+    };
+
+    assert!(scan.unmapped(&mapping(owner)).is_empty());
+    // A single unmapped kind is enough
+    assert_eq!(
+        scan.unmapped(&mapping((owner.0.wrapping_add(1), owner.1)))[0].1.files,
+        4
+    );
+
+    let scan = scan_ownership(dir.path(), &AtomicBool::new(true));
+
+    assert!(scan.cancelled);
+
+    Ok(())
+}
@@ -0,0 +1,347 @@
+//! Shifting the owners of a rootfs from one idmap base to another, like the classic `fuidshift`.
+//! Needed whenever a container's `lxc.idmap` moves to other host ids, since its files keep the old
+//! ones.
+
+use std::collections::HashSet;
+use std::fs::{self, Metadata, Permissions};
+use std::io;
+use std::os::unix::fs::{MetadataExt, PermissionsExt, lchown};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+
+use log::warn;
+
+use crate::fs::scan::walk;
+
+/// Extended attributes holding POSIX ACLs, whose named user and group entries are ids to shift too.
+const ACL_XATTRS: [&str; 2] = ["system.posix_acl_access", "system.posix_acl_default"];
+/// File capabilities, which the kernel drops whenever a file's owner changes.
+const CAPABILITY_XATTR: &str = "security.capability";
+
+/// Moves ids in `from..from + count` to the same offset from `to`. uids and gids are shifted alike.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IdShift {
+    pub from: u32,
+    pub to: u32,
+    pub count: u32,
+}
+
+impl IdShift {
+    /// Where `id` ends up, if it is in the shifted range.
+    pub fn apply(self, id: u32) -> Option<u32> {
+        let offset = id.checked_sub(self.from).filter(|offset| *offset < self.count)?;
+
+        self.to.checked_add(offset)
+    }
+
+    /// The new owner of a file owned by `uid:gid`, unless neither changes.
+    pub fn owner(self, uid: u32, gid: u32) -> Option<(u32, u32)> {
+        let (new_uid, new_gid) = (self.apply(uid), self.apply(gid));
+
+        (new_uid.is_some() || new_gid.is_some()).then(|| (new_uid.unwrap_or(uid), new_gid.unwrap_or(gid)))
+    }
+
+    /// A POSIX ACL xattr value with its named user and group entries shifted, unless none of them
+    /// change. Values which aren't laid out like an ACL are left alone.
+    fn acl(self, value: &[u8]) -> Option<Vec<u8>> {
+        const HEADER: usize = 4;
+        const ENTRY: usize = 8;
+        const ACL_USER: u16 = 0x02;
+        const ACL_GROUP: u16 = 0x08;
+
+        if value.len() < HEADER || !(value.len() - HEADER).is_multiple_of(ENTRY) {
+            return None;
+        }
+
+        let mut shifted = value.to_vec();
+        let mut changed = false;
+
+        // Each entry is a little endian tag, permissions and id
+        for entry in shifted[HEADER..].chunks_exact_mut(ENTRY) {
+            let tag = u16::from_le_bytes([entry[0], entry[1]]);
+
+            if tag != ACL_USER && tag != ACL_GROUP {
+                continue;
+            }
+
+            if let Some(id) = self.apply(u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]])) {
+                entry[4..].copy_from_slice(&id.to_le_bytes());
+                changed = true;
+            }
+        }
+
+        changed.then_some(shifted)
+    }
+
+    /// A `security.capability` value with the root id of a namespaced, version 3 capability shifted.
+    /// Older versions don't name a root and are kept as they are.
+    fn capability(self, mut value: Vec<u8>) -> Vec<u8> {
+        const REVISION_MASK: u32 = 0xFF00_0000;
+        const REVISION_3: u32 = 0x0300_0000;
+        const ROOT_ID: usize = 20;
+
+        if value.len() >= ROOT_ID + 4
+            && u32::from_le_bytes([value[0], value[1], value[2], value[3]]) & REVISION_MASK == REVISION_3
+            && let Some(root_id) = self.apply(u32::from_le_bytes([
+                value[ROOT_ID],
+                value[ROOT_ID + 1],
+                value[ROOT_ID + 2],
+                value[ROOT_ID + 3],
+            ]))
+        {
+            value[ROOT_ID..ROOT_ID + 4].copy_from_slice(&root_id.to_le_bytes());
+        }
+
+        value
+    }
+}
+
+/// A shift running on a thread of its own, with its progress so far.
+#[derive(Debug, Default)]
+pub struct ShiftJob {
+    pub dry_run: bool,
+    /// Files found below the rootfs, counted before anything is shifted.
+    pub total: AtomicU64,
+    pub done: AtomicU64,
+    /// Files which were, or in a dry run would be, given a new owner.
+    pub changed: AtomicU64,
+    pub failed: AtomicU64,
+    cancel: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl ShiftJob {
+    /// Starts shifting everything in and below `root`. A dry run only counts what would change.
+    pub fn start(root: PathBuf, shift: IdShift, dry_run: bool) -> Arc<Self> {
+        let job = Arc::new(Self {
+            dry_run,
+            ..Self::default()
+        });
+        let handle = Arc::clone(&job);
+
+        thread::spawn(move || {
+            handle.run(&root, shift);
+            handle.finished.store(true, Ordering::Relaxed);
+        });
+
+        job
+    }
+
+    fn run(&self, root: &Path, shift: IdShift) {
+        let (_, cancelled) = walk(root, &self.cancel, |_, _| {
+            self.total.fetch_add(1, Ordering::Relaxed);
+        });
+
+        if cancelled {
+            return;
+        }
+
+        // The rootfs itself is counted on top of what's in it
+        self.total.fetch_add(1, Ordering::Relaxed);
+
+        // Hard links share an inode, which would otherwise be shifted again through every other link
+        let mut linked = HashSet::new();
+
+        if let Ok(md) = fs::symlink_metadata(root) {
+            self.shift_file(root, &md, shift, &mut linked);
+        }
+
+        walk(root, &self.cancel, |path, md| {
+            self.shift_file(path, md, shift, &mut linked)
+        });
+    }
+
+    fn shift_file(&self, path: &Path, md: &Metadata, shift: IdShift, linked: &mut HashSet<(u64, u64)>) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+
+        if !md.is_dir() && md.nlink() > 1 && !linked.insert((md.dev(), md.ino())) {
+            return;
+        }
+
+        let owner = shift.owner(md.uid(), md.gid());
+        // Symlinks can't have ACLs
+        let acls: Vec<_> = if md.is_symlink() {
+            Vec::new()
+        } else {
+            ACL_XATTRS
+                .into_iter()
+                .filter_map(|name| Some((name, shift.acl(&xattr::get(path, name).ok()??)?)))
+                .collect()
+        };
+
+        if owner.is_none() && acls.is_empty() {
+            return;
+        }
+
+        self.changed.fetch_add(1, Ordering::Relaxed);
+
+        if self.dry_run {
+            return;
+        }
+
+        let shifted = owner
+            .map_or(Ok(()), |owner| shift_owner(path, md, owner, shift))
+            .and_then(|()| acls.iter().try_for_each(|(name, value)| xattr::set(path, name, value)));
+
+        if let Err(err) = shifted {
+            warn!("Failed to shift the ids of {}: {err}", path.display());
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops the shift after the file it is on. Files shifted so far stay shifted.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.finished.load(Ordering::Relaxed)
+    }
+
+    pub fn was_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+/// Gives `path` its shifted `uid:gid` owner, keeping what changing the owner would clear otherwise.
+fn shift_owner(path: &Path, md: &Metadata, (uid, gid): (u32, u32), shift: IdShift) -> io::Result<()> {
+    let capability = if md.is_file() {
+        xattr::get(path, CAPABILITY_XATTR).ok().flatten()
+    } else {
+        None
+    };
+
+    lchown(path, Some(uid), Some(gid))?;
+
+    // chown clears setuid and setgid bits, which the shifted file should keep
+    if !md.is_symlink() && md.mode() & 0o6000 != 0 {
+        fs::set_permissions(path, Permissions::from_mode(md.mode() & 0o7777))?;
+    }
+
+    // As well as file capabilities, which a namespaced one also needs its root shifted for
+    if let Some(capability) = capability {
+        xattr::set(path, CAPABILITY_XATTR, &shift.capability(capability))?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_id_shift() {
+    let shift = IdShift {
+        from: 100000,
+        to: 200000,
+        count: 65536,
+    };
+
+    assert_eq!(shift.apply(100000), Some(200000));
+    assert_eq!(shift.apply(165535), Some(265535));
+    assert_eq!(shift.apply(165536), None);
+    assert_eq!(shift.apply(0), None);
+    // Only the gid is in range
+    assert_eq!(shift.owner(0, 100033), Some((0, 200033)));
+    assert_eq!(shift.owner(0, 0), None);
+}
+
+#[test]
+fn test_shift_job_dry_run() -> color_eyre::Result<()> {
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir()?;
+
+    fs::create_dir(dir.path().join("etc"))?;
+    fs::write(dir.path().join("etc/hostname"), "ct\n")?;
+
+    let md = fs::metadata(dir.path())?;
+    let shift = IdShift {
+        from: md.uid(),
+        to: md.uid().wrapping_add(1),
+        count: 1,
+    };
+    let job = ShiftJob::start(dir.path().to_path_buf(), shift, true);
+
+    while job.is_running() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(job.total.load(Ordering::Relaxed), 3);
+    assert_eq!(job.done.load(Ordering::Relaxed), 3);
+    assert_eq!(job.changed.load(Ordering::Relaxed), 3);
+    // Nothing was actually changed
+    assert_eq!(fs::metadata(dir.path())?.uid(), md.uid());
+
+    Ok(())
+}
+
+#[test]
+fn test_shift_xattrs() {
+    let shift = IdShift {
+        from: 100000,
+        to: 200000,
+        count: 65536,
+    };
+    let acl = |user: u32, group: u32| {
+        let mut acl = 2u32.to_le_bytes().to_vec();
+
+        // Owner, named user, named group and mask entries
+        for (tag, id) in [(0x01u16, u32::MAX), (0x02, user), (0x08, group), (0x10, u32::MAX)] {
+            acl.extend(tag.to_le_bytes());
+            acl.extend(7u16.to_le_bytes());
+            acl.extend(id.to_le_bytes());
+        }
+
+        acl
+    };
+
+    assert_eq!(shift.acl(&acl(100033, 101000)), Some(acl(200033, 201000)));
+    assert_eq!(shift.acl(&acl(0, 33)), None);
+    assert_eq!(shift.acl(&[2, 0, 0]), None);
+
+    let capability = |revision: u32, root_id: u32| {
+        let mut capability = revision.to_le_bytes().to_vec();
+
+        capability.extend([0; 16]);
+        capability.extend(root_id.to_le_bytes());
+        capability
+    };
+
+    assert_eq!(
+        shift.capability(capability(0x0300_0000, 100000)),
+        capability(0x0300_0000, 200000)
+    );
+    // Version 2 capabilities have no root id, the bytes past them are left alone
+    assert_eq!(
+        shift.capability(capability(0x0200_0000, 100000)),
+        capability(0x0200_0000, 100000)
+    );
+}
+
+#[test]
+fn test_shift_job_hard_links() -> color_eyre::Result<()> {
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir()?;
+
+    fs::write(dir.path().join("passwd"), "root:x:0:0::/root:/bin/sh\n")?;
+    fs::hard_link(dir.path().join("passwd"), dir.path().join("passwd.link"))?;
+
+    let md = fs::metadata(dir.path())?;
+    let shift = IdShift {
+        from: md.uid(),
+        to: md.uid().wrapping_add(1),
+        count: 1,
+    };
+    let job = ShiftJob::start(dir.path().to_path_buf(), shift, true);
+
+    while job.is_running() {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(job.done.load(Ordering::Relaxed), 3);
+    // The file is only shifted through one of its links
+    assert_eq!(job.changed.load(Ordering::Relaxed), 2);
+
+    Ok(())
+}
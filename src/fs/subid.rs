@@ -1,8 +1,335 @@
+use std::fs::{self, read_to_string};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use compact_str::CompactString;
+use thiserror::Error;
+
 pub const ETC_SUBGID: &str = "/etc/subgid";
 pub const ETC_SUBUID: &str = "/etc/subuid";
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum SubID {
     UID,
     GID,
 }
+
+impl SubID {
+    pub fn path(self) -> &'static str {
+        match self {
+            SubID::UID => ETC_SUBUID,
+            SubID::GID => ETC_SUBGID,
+        }
+    }
+
+    /// The file name without its directory, i.e. `subuid` or `subgid`.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            SubID::UID => "subuid",
+            SubID::GID => "subgid",
+        }
+    }
+
+    /// `uid` or `gid`.
+    pub fn kind_name(self) -> &'static str {
+        match self {
+            SubID::UID => "uid",
+            SubID::GID => "gid",
+        }
+    }
+
+    /// The kind prefix of matching `lxc.idmap` values, `u` or `g`.
+    pub fn idmap_kind(self) -> &'static str {
+        match self {
+            SubID::UID => "u",
+            SubID::GID => "g",
+        }
+    }
+
+    /// The other file, whose entries a container needs alongside these.
+    pub fn counterpart(self) -> SubID {
+        match self {
+            SubID::UID => SubID::GID,
+            SubID::GID => SubID::UID,
+        }
+    }
+}
+
+/// A comment, blank line or invalid line of a subid file, kept so rewriting the file doesn't lose it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SubidComment {
+    /// 1-based. A trailing comment shares the line of its entry.
+    pub line_number: usize,
+    /// The comment as written, or an empty string for a blank line.
+    pub text: CompactString,
+}
+
+/// Why a line of /etc/subuid or /etc/subgid isn't an entry.
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum SubidError {
+    #[error("expected 3 fields `name:start:count`, found {0}")]
+    FieldCount(usize),
+    #[error("the owner is empty")]
+    EmptyOwner,
+    #[error("{field} {value:?} is not a number between 0 and 4294967295")]
+    Number { field: &'static str, value: String },
+}
+
+/// A line of a subid file which isn't a comment, yet can't be read as an entry either.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidSubidLine {
+    /// 1-based, counting blank lines too.
+    pub line_number: usize,
+    /// The line as written.
+    pub line: CompactString,
+    pub error: SubidError,
+}
+
+/// Whether the whole line is a comment. shadow-utils skips these.
+pub fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+/// Splits a trailing `# comment` off an entry. User names can't contain `#`, so anything from the
+/// first one on is the comment.
+pub fn split_comment(line: &str) -> (&str, Option<&str>) {
+    match line.find('#') {
+        Some(index) => (&line[..index], Some(line[index..].trim_end())),
+        None => (line, None),
+    }
+}
+
+/// The comment and blank lines of a subid file, including comments trailing an entry.
+pub fn comment_lines(content: &str) -> Vec<SubidComment> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let text = if line.trim().is_empty() {
+                ""
+            } else {
+                split_comment(line).1?
+            };
+
+            Some(SubidComment {
+                line_number: i + 1,
+                text: text.into(),
+            })
+        })
+        .collect()
+}
+
+/// Splits a subid line into its trimmed fields, leaving out any trailing comment.
+///
+/// Also reports whether the line deviates from the canonical `name:start:count` formatting, such as
+/// whitespace around fields, tab separated fields or a trailing comment, which shadow-utils may not
+/// parse the same way.
+pub fn split_fields(line: &str) -> (Vec<&str>, bool) {
+    let trimmed = split_comment(line).0.trim();
+    let mut unusual = trimmed.len() != line.len();
+    let fields = if trimmed.contains(':') {
+        trimmed
+            .split(':')
+            .map(|field| {
+                let field_trimmed = field.trim();
+
+                unusual |= field_trimmed.len() != field.len();
+                field_trimmed
+            })
+            .collect()
+    } else {
+        unusual = true;
+        trimmed.split_whitespace().collect()
+    };
+
+    (fields, unusual)
+}
+
+/// Rewrites every well formed entry as `name:start:count`, leaving any other line untouched.
+/// Trailing comments are moved onto a line of their own above their entry.
+pub fn normalize(content: &str) -> String {
+    let mut normalized = String::with_capacity(content.len());
+
+    for line in content.lines() {
+        let (fields, unusual) = split_fields(line);
+
+        if line.trim().is_empty() {
+            // Whitespace only lines become plain empty lines
+        } else if is_comment(line) {
+            normalized.push_str(line);
+        } else if unusual && fields.len() == 3 {
+            if let (_, Some(comment)) = split_comment(line) {
+                normalized.push_str(comment);
+                normalized.push('\n');
+            }
+
+            normalized.push_str(&fields.join(":"));
+        } else {
+            normalized.push_str(line);
+        }
+
+        normalized.push('\n');
+    }
+
+    normalized
+}
+
+/// What shadow-utils backed up the last time it rewrote a subid file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShadowBackup {
+    /// The backed up entries, as `name:start:count`.
+    pub entries: Vec<String>,
+    /// Whether the file was modified after shadow-utils last wrote it.
+    pub edited_since: bool,
+}
+
+/// shadow-utils keeps the previous version of a file next to it, with a `-` suffix.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+
+    backup.push("-");
+    backup.into()
+}
+
+/// Reads the shadow-utils backup of the subid file at `path`, if there is one. The backup keeps the
+/// original's modification time, but its change time is when shadow-utils made it, right before
+/// rewriting the file.
+pub fn read_shadow_backup(path: &Path) -> Option<ShadowBackup> {
+    let backup_path = backup_path(path);
+    let content = read_to_string(&backup_path).ok()?;
+    let backed_up = fs::metadata(&backup_path).ok()?;
+    let current = fs::metadata(path).ok()?;
+
+    Some(ShadowBackup {
+        entries: content
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !is_comment(line))
+            .map(|line| split_fields(line).0.join(":"))
+            .collect(),
+        // Both are written within the same second, give or take
+        edited_since: current.mtime() > backed_up.ctime() + 1,
+    })
+}
+
+/// Orders the entries of subid file `content` by the start of their range, the order `useradd`
+/// allocates them in. Entries move between the lines entries were on, so comments and lines which
+/// aren't entries stay where they are.
+pub fn sort_entries(content: &str) -> String {
+    let lines: Vec<_> = content.lines().collect();
+    let start = |line: &str| {
+        let (fields, _) = split_fields(line);
+
+        match fields[..] {
+            [_, start, _] if !is_comment(line) => start.parse::<u32>().ok(),
+            _ => None,
+        }
+    };
+    let slots: Vec<_> = (0..lines.len()).filter(|i| start(lines[*i]).is_some()).collect();
+    let mut entries: Vec<_> = slots.iter().map(|i| lines[*i]).collect();
+    let mut sorted: Vec<_> = lines.clone();
+
+    entries.sort_by_key(|line| start(line));
+
+    for (slot, entry) in slots.into_iter().zip(entries) {
+        sorted[slot] = entry;
+    }
+
+    sorted.into_iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// Appends `lines` to subid file `content`, skipping any which are already present.
+pub fn append_entries(content: &str, lines: &[String]) -> String {
+    let existing: Vec<_> = content.lines().map(|line| split_fields(line).0.join(":")).collect();
+    let mut appended = content.to_string();
+
+    if !appended.is_empty() && !appended.ends_with('\n') {
+        appended.push('\n');
+    }
+
+    for line in lines {
+        if !existing.contains(line) {
+            appended.push_str(line);
+            appended.push('\n');
+        }
+    }
+
+    appended
+}
+
+#[test]
+fn test_append_entries() {
+    assert_eq!(
+        append_entries(
+            "root : 100000 : 65536",
+            &["root:100000:65536".into(), "alice:165536:65536".into()]
+        ),
+        "root : 100000 : 65536\nalice:165536:65536\n"
+    );
+}
+
+#[test]
+fn test_split_fields_and_normalize() {
+    assert_eq!(
+        split_fields("root:100000:65536"),
+        (vec!["root", "100000", "65536"], false)
+    );
+    assert_eq!(
+        split_fields("root : 100000 : 65536"),
+        (vec!["root", "100000", "65536"], true)
+    );
+    assert_eq!(
+        split_fields("root\t100000\t65536"),
+        (vec!["root", "100000", "65536"], true)
+    );
+    assert_eq!(
+        split_fields("  root:100000:65536"),
+        (vec!["root", "100000", "65536"], true)
+    );
+
+    assert_eq!(
+        normalize("root : 100000 : 65536\n \t\nuser\t200000\t65536\nbad line here too\nok:1:2"),
+        "root:100000:65536\n\nuser:200000:65536\nbad line here too\nok:1:2\n"
+    );
+}
+
+#[test]
+fn test_comments() {
+    assert!(is_comment("  # root:100000:65536"));
+    assert!(!is_comment("root:100000:65536 # main range"));
+    assert_eq!(
+        split_fields("root:100000:65536 # main range"),
+        (vec!["root", "100000", "65536"], true)
+    );
+    assert_eq!(split_fields("# root 100000 65536"), (Vec::<&str>::new(), true));
+    assert_eq!(
+        comment_lines("# containers\nroot:100000:65536\n  \nalice:165536:65536\t# for alice \n"),
+        [
+            SubidComment {
+                line_number: 1,
+                text: "# containers".into()
+            },
+            SubidComment {
+                line_number: 3,
+                text: "".into()
+            },
+            SubidComment {
+                line_number: 4,
+                text: "# for alice".into()
+            },
+        ]
+    );
+    // A commented out entry stays commented out, even when it looks unusual
+    assert_eq!(
+        normalize("#root 100000 65536\nalice : 165536 : 65536 # for alice\n"),
+        "#root 100000 65536\n# for alice\nalice:165536:65536\n"
+    );
+}
+
+#[test]
+fn test_sort_entries() {
+    assert_eq!(
+        sort_entries("# root\nroot:200000:65536\nalice:100000:65536\ninvalid\nbob : 165536 : 65536\n"),
+        "# root\nalice:100000:65536\nbob : 165536 : 65536\ninvalid\nroot:200000:65536\n"
+    );
+    assert_eq!(sort_entries("root:100000:65536\n"), "root:100000:65536\n");
+}
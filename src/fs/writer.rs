@@ -1 +1,320 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{MetadataExt, chown};
+use std::path::{Path, PathBuf};
 
+use color_eyre::eyre::{WrapErr, eyre};
+use log::error;
+use tempfile::NamedTempFile;
+
+use crate::fs::backup;
+
+/// Replaces the contents of `path` atomically by writing to a temporary file in the same directory
+/// and renaming it over the original. Permissions and ownership of an existing file are preserved.
+pub fn write_atomic(path: &Path, content: &str) -> color_eyre::Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| eyre!("{} has no parent directory", path.display()))?;
+    let mut file =
+        NamedTempFile::new_in(dir).wrap_err_with(|| format!("Failed to create temp file in {}", dir.display()))?;
+
+    file.write_all(content.as_bytes())?;
+    file.as_file().sync_all()?;
+
+    if let Ok(md) = fs::metadata(path) {
+        fs::set_permissions(file.path(), md.permissions())?;
+        chown(file.path(), Some(md.uid()), Some(md.gid()))
+            .wrap_err_with(|| format!("Failed to preserve ownership of {}", path.display()))?;
+    }
+
+    file.persist(path)
+        .wrap_err_with(|| format!("Failed to replace {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Lines of unchanged content shown around each change in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// A file write which hasn't happened yet, so it can be shown as a diff and confirmed first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingWrite {
+    pub path: PathBuf,
+    /// The content when the write was planned. Empty for a file which doesn't exist yet.
+    pub current: String,
+    pub proposed: String,
+}
+
+impl PendingWrite {
+    /// Plans replacing the content of `path` with `proposed`.
+    pub fn new(path: &Path, proposed: String) -> color_eyre::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            current: read_current(path)?,
+            proposed,
+        })
+    }
+
+    /// A unified diff from the current to the proposed content, empty if they're the same.
+    pub fn diff(&self) -> String {
+        unified_diff(&self.path.display().to_string(), &self.current, &self.proposed)
+    }
+
+    /// Whether the file still has the content it had when the write was planned.
+    pub fn is_current(&self) -> color_eyre::Result<bool> {
+        Ok(read_current(&self.path)? == self.current)
+    }
+
+    /// Backs up the file and writes the proposed content. Fails without writing if the file was
+    /// changed since the write was planned, as the diff which was confirmed no longer applies.
+    pub fn commit(&self) -> color_eyre::Result<()> {
+        if !self.is_current()? {
+            return Err(eyre!("{} was changed since it was previewed", self.path.display()));
+        }
+
+        backup::back_up(&self.path)?;
+        write_atomic(&self.path, &self.proposed)
+    }
+}
+
+/// Commits `writes` one after another and runs `after` once they're all written. If anything fails,
+/// the files already written are put back the way they were, and files which didn't exist are
+/// removed again.
+pub fn commit_together(
+    writes: &[PendingWrite],
+    after: impl FnOnce() -> color_eyre::Result<()>,
+) -> color_eyre::Result<()> {
+    let mut committed = Vec::with_capacity(writes.len());
+    let result = writes
+        .iter()
+        .try_for_each(|write| {
+            let existed = write.path.exists();
+
+            write.commit()?;
+            committed.push((write, existed));
+
+            Ok(())
+        })
+        .and_then(|()| after());
+
+    if result.is_err() {
+        for (write, existed) in committed.into_iter().rev() {
+            let restored = if existed {
+                write_atomic(&write.path, &write.current)
+            } else {
+                fs::remove_file(&write.path).map_err(Into::into)
+            };
+
+            if let Err(err) = restored {
+                error!("Failed to put back {}: {err:?}", write.path.display());
+            }
+        }
+    }
+
+    result
+}
+
+fn read_current(path: &Path) -> color_eyre::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err).wrap_err_with(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LineEdit<'a> {
+    Keep(&'a str),
+    Remove(&'a str),
+    Add(&'a str),
+}
+
+/// The shortest edit from `old` to `new`, with removals ahead of additions where they're
+/// interchangeable. The files written are at most a few hundred lines, so a plain longest common
+/// subsequence table is fast enough.
+fn line_edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<LineEdit<'a>> {
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push(LineEdit::Keep(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            edits.push(LineEdit::Remove(old[i]));
+            i += 1;
+        } else {
+            edits.push(LineEdit::Add(new[j]));
+            j += 1;
+        }
+    }
+
+    edits
+}
+
+/// A unified diff between two versions of the file `label`, like `diff -u` prints it. Empty when
+/// the lines are the same.
+pub fn unified_diff(label: &str, old: &str, new: &str) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    let edits = line_edits(&old, &new);
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+
+    for (index, _) in edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, LineEdit::Keep(_)))
+    {
+        let start = index.saturating_sub(DIFF_CONTEXT);
+        let end = (index + 1 + DIFF_CONTEXT).min(edits.len());
+
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {label}\n+++ {label}\n");
+    // Lines of each version which come before an edit
+    let old_before = |index: usize| edits[..index].iter().filter(|e| !matches!(e, LineEdit::Add(_))).count();
+    let new_before = |index: usize| {
+        edits[..index]
+            .iter()
+            .filter(|e| !matches!(e, LineEdit::Remove(_)))
+            .count()
+    };
+    // Empty ranges are numbered by the line before them, and single lines go without a length
+    let range = |before: usize, len: usize| match len {
+        0 => format!("{before},0"),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{len}", before + 1),
+    };
+
+    for (start, end) in hunks {
+        let old_len = old_before(end) - old_before(start);
+        let new_len = new_before(end) - new_before(start);
+
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_before(start), old_len),
+            range(new_before(start), new_len)
+        ));
+
+        for edit in &edits[start..end] {
+            let (prefix, line) = match edit {
+                LineEdit::Keep(line) => (' ', line),
+                LineEdit::Remove(line) => ('-', line),
+                LineEdit::Add(line) => ('+', line),
+            };
+
+            diff.push(prefix);
+            diff.push_str(line);
+            diff.push('\n');
+        }
+    }
+
+    diff
+}
+
+#[test]
+fn test_write_atomic() -> color_eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("subuid");
+
+    fs::write(&path, "old")?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640))?;
+
+    write_atomic(&path, "new")?;
+
+    assert_eq!(fs::read_to_string(&path)?, "new");
+    assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o640);
+
+    Ok(())
+}
+
+#[test]
+fn test_unified_diff() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+
+    assert_eq!(unified_diff("f", old, old), "");
+    assert_eq!(
+        unified_diff("/etc/subuid", old, "a\nb\nc\nD\ne\nf\ng\nh\ni\nj\nk\n"),
+        "--- /etc/subuid\n+++ /etc/subuid\n@@ -1,10 +1,11 @@\n a\n b\n c\n-d\n+D\n e\n f\n g\n h\n i\n j\n+k\n"
+    );
+    // Changes far enough apart get their own hunks
+    assert_eq!(
+        unified_diff(
+            "f",
+            "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n",
+            "2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n"
+        ),
+        "--- f\n+++ f\n@@ -1,4 +1,3 @@\n-1\n 2\n 3\n 4\n@@ -8,3 +7,4 @@\n 8\n 9\n 10\n+11\n"
+    );
+    assert_eq!(unified_diff("f", "", "x\n"), "--- f\n+++ f\n@@ -0,0 +1 @@\n+x\n");
+}
+
+#[test]
+fn test_pending_write() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("subgid");
+    let write = PendingWrite::new(&path, "root:100000:65536\n".into())?;
+
+    assert_eq!(write.current, "");
+
+    fs::write(&path, "alice:165536:65536\n")?;
+
+    // The file no longer matches what the diff was made from
+    assert!(write.commit().is_err());
+
+    let write = PendingWrite::new(&path, "root:100000:65536\n".into())?;
+
+    write.commit()?;
+
+    assert_eq!(fs::read_to_string(&path)?, "root:100000:65536\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_commit_together() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let subuid = dir.path().join("subuid");
+    let subgid = dir.path().join("subgid");
+
+    fs::write(&subuid, "root:100000:65536\n")?;
+
+    let writes = [
+        PendingWrite::new(&subuid, "root:100000:131072\n".into())?,
+        PendingWrite::new(&subgid, "root:100000:131072\n".into())?,
+    ];
+
+    // Everything is put back when a later step fails
+    assert!(commit_together(&writes, || Err(eyre!("usermod failed"))).is_err());
+    assert_eq!(fs::read_to_string(&subuid)?, "root:100000:65536\n");
+    assert!(!subgid.exists());
+
+    commit_together(&writes, || Ok(()))?;
+
+    assert_eq!(fs::read_to_string(&subgid)?, "root:100000:131072\n");
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+//! A lightweight, TUI-free summary of pupman's findings, for embedding in dashboards or MOTD
+//! generators.
+
+use std::fmt::{self, Display};
+
+use log::warn;
+
+use crate::app::state::State;
+use crate::finding::{Finding, FindingKind};
+use crate::metadata::Metadata;
+use crate::settings::Settings;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[non_exhaustive]
+pub enum HealthStatus {
+    /// Everything was checked and no problems were found.
+    Ok,
+    /// No problems were found, but there are warnings or some files could not be loaded so the check
+    /// is incomplete.
+    Warn,
+    /// At least one problem was found.
+    Fail,
+}
+
+impl Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HealthStatus::Ok => "ok",
+            HealthStatus::Warn => "warn",
+            HealthStatus::Fail => "fail",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Health {
+    pub status: HealthStatus,
+    /// Number of container configs which were loaded.
+    pub containers: usize,
+    pub good_findings: usize,
+    /// Findings only worth knowing about, which leave the status alone.
+    pub info_findings: usize,
+    pub warning_findings: usize,
+    pub bad_findings: usize,
+    /// Number of files which could not be loaded.
+    pub load_errors: usize,
+}
+
+impl Health {
+    pub(crate) fn from_state(state: &State, load_errors: usize) -> Self {
+        let count = |kind| state.findings.iter().filter(|f| f.kind == kind).count();
+        let bad_findings = count(FindingKind::Bad);
+        let warning_findings = count(FindingKind::Warning);
+        let status = if bad_findings > 0 {
+            HealthStatus::Fail
+        } else if warning_findings > 0 || load_errors > 0 {
+            HealthStatus::Warn
+        } else {
+            HealthStatus::Ok
+        };
+
+        Health {
+            status,
+            containers: state.lxc_configs.len(),
+            good_findings: count(FindingKind::Good),
+            info_findings: count(FindingKind::Info),
+            warning_findings,
+            bad_findings,
+            load_errors,
+        }
+    }
+}
+
+impl Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pupman: {} ({} containers, {} bad, {} warnings, {} info, {} good",
+            self.status,
+            self.containers,
+            self.bad_findings,
+            self.warning_findings,
+            self.info_findings,
+            self.good_findings
+        )?;
+
+        if self.load_errors > 0 {
+            write!(f, ", {} unreadable", self.load_errors)?;
+        }
+
+        f.write_str(")")
+    }
+}
+
+/// Runs all enabled checks once against the default system locations and summarizes the result.
+pub fn healthcheck() -> color_eyre::Result<Health> {
+    Ok(healthcheck_with(&Metadata::collect(None)?, Settings::load_default()))
+}
+
+/// Runs all checks enabled in `settings` once against the given system metadata and summarizes the
+/// result.
+pub fn healthcheck_with(metadata: &Metadata, settings: Settings) -> Health {
+    let (health, _, errors) = check_with(metadata, settings);
+
+    for err in &errors {
+        warn!("{err:?}");
+    }
+
+    health
+}
+
+/// Like [`healthcheck_with`], but also returns every finding, most severe first, and the errors of
+/// files which could not be loaded.
+pub fn check_with(metadata: &Metadata, settings: Settings) -> (Health, Vec<Finding>, Vec<color_eyre::Report>) {
+    let (state, errors) = State::collect(metadata, settings);
+    let health = Health::from_state(&state, errors.len());
+
+    (health, state.findings, errors)
+}
+
+#[test]
+fn test_healthcheck_with() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    std::fs::write(dir.path().join("100.conf"), "unprivileged: 1\n")?;
+
+    let health = healthcheck_with(
+        &Metadata {
+            lxc_config_dir: dir.path().to_path_buf(),
+            ..Metadata::default()
+        },
+        Settings::default(),
+    );
+
+    assert_eq!(health.containers, 1);
+    assert_eq!(health.status, HealthStatus::Fail);
+    // Missing uid and gid idmaps
+    assert!(health.bad_findings >= 2);
+
+    Ok(())
+}
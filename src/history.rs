@@ -0,0 +1,265 @@
+//! Remembers when each finding was first and last seen, persisted in
+//! `$XDG_STATE_HOME/pupman/history` so findings can be aged across runs.
+//!
+//! The file holds one tab separated line per finding id:
+//! `id first_seen last_seen occurrences present`, with RFC 3339 timestamps.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{WrapErr, eyre};
+use log::warn;
+
+use crate::fs::writer::write_atomic;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// How many times the finding appeared after being absent, including the first time.
+    pub occurrences: u32,
+    /// Whether the finding was present in the most recent evaluation.
+    pub present: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FindingHistory {
+    /// Where the history is saved to, if anywhere.
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, HistoryEntry>,
+    /// How many entries [`FindingHistory::prune`] forgot since the history was loaded.
+    pruned: usize,
+}
+
+impl FindingHistory {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("pupman").join("history"))
+    }
+
+    /// Loads history from `path`. A missing file yields an empty history which will be saved there.
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        let content = match read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).wrap_err_with(|| format!("Failed to read {}", path.display())),
+        };
+        let mut entries = BTreeMap::new();
+
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match parse_line(line) {
+                Ok((id, entry)) => {
+                    entries.insert(id.to_string(), entry);
+                },
+                Err(err) => warn!("Ignoring history line {line:?}: {err}"),
+            }
+        }
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            entries,
+            pruned: 0,
+        })
+    }
+
+    /// Loads history from the default location, falling back to an unsaved, empty history on any
+    /// error.
+    pub fn load_default() -> Self {
+        let Some(path) = Self::default_path() else {
+            return Self::default();
+        };
+
+        Self::load(&path).unwrap_or_else(|err| {
+            warn!("Failed to load finding history: {err:?}");
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> color_eyre::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut content = String::new();
+
+        for (id, entry) in &self.entries {
+            content.push_str(&format!(
+                "{id}\t{}\t{}\t{}\t{}\n",
+                entry.first_seen.to_rfc3339(),
+                entry.last_seen.to_rfc3339(),
+                entry.occurrences,
+                u8::from(entry.present)
+            ));
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        write_atomic(path, &content)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&HistoryEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn pruned(&self) -> usize {
+        self.pruned
+    }
+
+    /// Forgets findings which went away longer than `max_age` before `now`, then those gone the
+    /// longest until at most `max_entries` are left. Findings still present are never forgotten.
+    /// Returns how many were.
+    pub fn prune(&mut self, max_entries: usize, max_age: Duration, now: DateTime<Utc>) -> usize {
+        let before = self.entries.len();
+        let cutoff = chrono::Duration::from_std(max_age).map_or(DateTime::<Utc>::MIN_UTC, |age| now - age);
+
+        self.entries
+            .retain(|_, entry| entry.present || entry.last_seen >= cutoff);
+
+        if self.entries.len() > max_entries {
+            let mut gone: Vec<_> = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| !entry.present)
+                .map(|(id, entry)| (entry.last_seen, id.clone()))
+                .collect();
+
+            gone.sort_unstable();
+
+            for (_, id) in gone.into_iter().take(self.entries.len() - max_entries) {
+                self.entries.remove(&id);
+            }
+        }
+
+        let pruned = before - self.entries.len();
+
+        self.pruned += pruned;
+        pruned
+    }
+
+    /// Records the ids of all findings from an evaluation at `now`. Returns whether any finding
+    /// appeared or disappeared, ie whether the history is worth saving.
+    pub fn observe<'i>(&mut self, ids: impl IntoIterator<Item = &'i str>, now: DateTime<Utc>) -> bool {
+        let mut changed = false;
+        let mut seen = HashSet::new();
+
+        for id in ids {
+            seen.insert(id);
+
+            match self.entries.get_mut(id) {
+                Some(entry) => {
+                    if !entry.present {
+                        entry.present = true;
+                        entry.occurrences += 1;
+                        changed = true;
+                    }
+
+                    entry.last_seen = now;
+                },
+                None => {
+                    self.entries.insert(
+                        id.to_string(),
+                        HistoryEntry {
+                            first_seen: now,
+                            last_seen: now,
+                            occurrences: 1,
+                            present: true,
+                        },
+                    );
+                    changed = true;
+                },
+            }
+        }
+
+        for (id, entry) in &mut self.entries {
+            if entry.present && !seen.contains(&id.as_str()) {
+                entry.present = false;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+fn parse_line(line: &str) -> color_eyre::Result<(&str, HistoryEntry)> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let [id, first_seen, last_seen, occurrences, present] = fields[..] else {
+        return Err(eyre!("expected 5 fields, found {}", fields.len()));
+    };
+
+    Ok((
+        id,
+        HistoryEntry {
+            first_seen: DateTime::parse_from_rfc3339(first_seen)?.to_utc(),
+            last_seen: DateTime::parse_from_rfc3339(last_seen)?.to_utc(),
+            occurrences: occurrences.parse()?,
+            present: present == "1",
+        },
+    ))
+}
+
+#[test]
+fn test_history_observe_and_reload() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("history");
+    let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let t1 = DateTime::from_timestamp(1_700_000_060, 0).unwrap();
+    let t2 = DateTime::from_timestamp(1_700_000_120, 0).unwrap();
+    let mut history = FindingHistory::load(&path)?;
+
+    assert!(history.observe(["a", "b"], t0));
+    assert!(!history.observe(["a", "b"], t1));
+    // b disappears, then comes back
+    assert!(history.observe(["a"], t1));
+    assert!(history.observe(["a", "b"], t2));
+
+    history.save()?;
+
+    let history = FindingHistory::load(&path)?;
+    let b = history.get("b").unwrap();
+
+    assert_eq!(history.get("a").unwrap().occurrences, 1);
+    assert_eq!(b.first_seen, t0);
+    assert_eq!(b.last_seen, t2);
+    assert_eq!(b.occurrences, 2);
+    assert!(b.present);
+
+    Ok(())
+}
+
+#[test]
+fn test_history_prune() {
+    let day = Duration::from_secs(24 * 60 * 60);
+    let t0 = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut history = FindingHistory::default();
+
+    history.observe(["a", "b", "c", "d"], t0);
+    history.observe(["c", "d"], t0 + chrono::Duration::days(5));
+    history.observe(["d"], t0 + chrono::Duration::days(6));
+
+    // a and b were last seen 10 days ago, c 5 days ago
+    let now = t0 + chrono::Duration::days(10);
+
+    assert_eq!(history.prune(10, 11 * day, now), 0);
+    assert_eq!(history.prune(10, 7 * day, now), 2);
+    assert!(history.get("c").is_some());
+
+    // Still present, so kept over the limit
+    assert_eq!(history.prune(0, 7 * day, now), 1);
+    assert!(history.get("d").is_some());
+    assert_eq!(history.pruned(), 3);
+}
@@ -0,0 +1,447 @@
+//! Incus (and LXD) containers, audited alongside PVE's. Each instance is read through
+//! `incus config show --expanded` and translated into an upstream LXC config, so the same checks
+//! and panels apply to it.
+//!
+//! ```text
+//! config:
+//!   raw.idmap: |-
+//!     both 1000 1000
+//!   volatile.idmap.current: '[{"Isuid":true,"Isgid":false,"Hostid":1000000,"Nsid":0,"Maprange":1000000000}]'
+//! devices:
+//!   root:
+//!     path: /
+//!     pool: default
+//!     type: disk
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+use std::{str, thread};
+
+use log::Level;
+use serde::Deserialize;
+
+use crate::app::bus::{Bus, Notification};
+use crate::app::event::FileSystemChangeKind;
+use crate::linux::LinuxError;
+use crate::linux::command;
+
+/// Where Incus keeps its state when `INCUS_DIR` doesn't say otherwise.
+const INCUS_DIR: &str = "/var/lib/incus";
+/// Sets instance configs apart from PVE's `<vmid>.conf`.
+pub const INCUS_CONFIG_PREFIX: &str = "incus-";
+/// The ids Incus hands to containers when root has no subuid or subgid entries of its own, which
+/// is also how its packages set up root's entries.
+const DEFAULT_IDMAP: (u32, u32) = (1000000, 1000000000);
+
+/// A single range of `volatile.idmap.current`, which Incus applies when the instance starts.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub struct IncusIdmap {
+    #[serde(rename = "Isuid")]
+    pub is_uid: bool,
+    #[serde(rename = "Isgid")]
+    pub is_gid: bool,
+    #[serde(rename = "Hostid")]
+    pub host_id: u32,
+    #[serde(rename = "Nsid")]
+    pub ns_id: u32,
+    #[serde(rename = "Maprange")]
+    pub range: u32,
+}
+
+/// What pupman needs from `incus config show --expanded`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IncusInstance {
+    pub name: String,
+    /// The `config:` keys.
+    pub config: BTreeMap<String, String>,
+    /// The storage pool of the root disk device.
+    pub root_pool: Option<String>,
+    /// The driver of that pool, e.g. `zfs`, if the pool could be looked up.
+    pub root_driver: Option<String>,
+}
+
+impl IncusInstance {
+    pub fn is_privileged(&self) -> bool {
+        self.config.get("security.privileged").map(String::as_str) == Some("true")
+    }
+
+    /// The idmap Incus applied the last time the instance started, or will apply next time.
+    /// Instances which never started have neither, so they are assumed to get Incus's default
+    /// allocation with their `raw.idmap` entries punched into it.
+    pub fn idmaps(&self) -> Vec<IncusIdmap> {
+        let current = ["volatile.idmap.current", "volatile.idmap.next"]
+            .iter()
+            .find_map(|key| self.config.get(*key))
+            .map(|value| parse_volatile_idmap(value))
+            .unwrap_or_default();
+
+        if !current.is_empty() {
+            return current;
+        }
+
+        let (host_id, range) = DEFAULT_IDMAP;
+        let base = [(true, false), (false, true)].map(|(is_uid, is_gid)| IncusIdmap {
+            is_uid,
+            is_gid,
+            host_id,
+            ns_id: 0,
+            range,
+        });
+
+        self.config
+            .get("raw.idmap")
+            .map(|value| parse_raw_idmap(value))
+            .unwrap_or_default()
+            .into_iter()
+            .fold(base.to_vec(), add_raw_idmap)
+    }
+
+    /// The config name the instance is loaded as, e.g. `incus-web.conf`.
+    pub fn filename(&self) -> String {
+        format!("{INCUS_CONFIG_PREFIX}{}.conf", self.name)
+    }
+
+    /// Where the rootfs is on the host, if it is there to be looked at. Incus mounts every volume
+    /// below its storage pools and hands LXC that directory, whatever the pool's driver.
+    pub fn rootfs_path(&self) -> Option<PathBuf> {
+        let pool = self.root_pool.as_deref()?;
+        let mounted = match self.root_driver.as_deref()? {
+            // Directories and subvolumes are there whether the instance runs or not
+            "dir" | "btrfs" => true,
+            // Datasets and block devices are only mounted while it runs
+            _ => self.config.get("volatile.last_state.power").map(String::as_str) == Some("RUNNING"),
+        };
+        let incus_dir = env::var_os("INCUS_DIR").map_or_else(|| PathBuf::from(INCUS_DIR), PathBuf::from);
+
+        mounted.then(|| {
+            incus_dir
+                .join("storage-pools")
+                .join(pool)
+                .join("containers")
+                .join(&self.name)
+                .join("rootfs")
+        })
+    }
+
+    /// The instance as an upstream LXC config. Privileged instances get no idmaps, which is what
+    /// makes an LXC config privileged.
+    pub fn to_lxc_config(&self) -> String {
+        let mut lines = vec![format!("# Incus instance {}", self.name)];
+
+        if let Some(rootfs) = self.rootfs_path() {
+            lines.push(format!("lxc.rootfs.path = dir:{}", rootfs.display()));
+        }
+
+        if !self.is_privileged() {
+            for idmap in self.idmaps() {
+                for (applies, kind) in [(idmap.is_uid, 'u'), (idmap.is_gid, 'g')] {
+                    if applies {
+                        lines.push(format!(
+                            "lxc.idmap = {kind} {} {} {}",
+                            idmap.ns_id, idmap.host_id, idmap.range
+                        ));
+                    }
+                }
+            }
+        }
+
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Adds `raw` to `idmaps` the way Incus adds `raw.idmap` entries, cutting the container ids it maps
+/// out of the ranges of the same kind already there.
+fn add_raw_idmap(idmaps: Vec<IncusIdmap>, raw: IncusIdmap) -> Vec<IncusIdmap> {
+    let raw_end = u64::from(raw.ns_id) + u64::from(raw.range);
+    let mut added = Vec::new();
+
+    for idmap in idmaps {
+        let end = u64::from(idmap.ns_id) + u64::from(idmap.range);
+        let overlaps = (idmap.is_uid && raw.is_uid || idmap.is_gid && raw.is_gid)
+            && u64::from(idmap.ns_id) < raw_end
+            && u64::from(raw.ns_id) < end;
+
+        if !overlaps {
+            added.push(idmap);
+            continue;
+        }
+
+        // Kinds raw doesn't map keep the whole range
+        let (is_uid, is_gid) = (idmap.is_uid && !raw.is_uid, idmap.is_gid && !raw.is_gid);
+
+        if is_uid || is_gid {
+            added.push(IncusIdmap {
+                is_uid,
+                is_gid,
+                ..idmap
+            });
+        }
+
+        let (is_uid, is_gid) = (idmap.is_uid && raw.is_uid, idmap.is_gid && raw.is_gid);
+        let mut keep = |ns_id: u64, end: u64| {
+            if ns_id < end {
+                added.push(IncusIdmap {
+                    is_uid,
+                    is_gid,
+                    // Both stay within the range being cut, which fits in u32
+                    host_id: (u64::from(idmap.host_id) + ns_id - u64::from(idmap.ns_id)) as u32,
+                    ns_id: ns_id as u32,
+                    range: (end - ns_id) as u32,
+                });
+            }
+        };
+
+        keep(u64::from(idmap.ns_id), u64::from(raw.ns_id));
+        keep(raw_end, end);
+    }
+
+    // Listed per kind like the rest, so each kind's ranges end up next to each other
+    for (is_uid, is_gid) in [(raw.is_uid, false), (false, raw.is_gid)] {
+        if is_uid || is_gid {
+            added.push(IncusIdmap { is_uid, is_gid, ..raw });
+        }
+    }
+
+    added.sort_by_key(|idmap| (!idmap.is_uid, !idmap.is_gid, idmap.ns_id));
+    added
+}
+
+/// The parts of `incus config show --expanded` pupman reads.
+#[derive(Deserialize)]
+struct ConfigShow {
+    #[serde(default)]
+    config: BTreeMap<String, String>,
+    #[serde(default)]
+    devices: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// Parses the output of `incus config show --expanded`. The root disk is the one mounted at `/`.
+pub fn parse_config_show(name: &str, yaml: &str) -> Result<IncusInstance, serde_yaml::Error> {
+    let show: ConfigShow = serde_yaml::from_str(yaml)?;
+    let root_pool = show
+        .devices
+        .values()
+        .find(|device| {
+            device.get("type").map(String::as_str) == Some("disk")
+                && device.get("path").map(String::as_str) == Some("/")
+        })
+        .and_then(|device| device.get("pool").cloned());
+
+    Ok(IncusInstance {
+        name: name.to_string(),
+        config: show.config,
+        root_pool,
+        root_driver: None,
+    })
+}
+
+/// Parses `volatile.idmap.current`, a JSON list of `{"Isuid","Isgid","Hostid","Nsid","Maprange"}`.
+pub fn parse_volatile_idmap(json: &str) -> Vec<IncusIdmap> {
+    serde_json::from_str(json).unwrap_or_default()
+}
+
+/// Parses `raw.idmap` lines like `both 1000 1000` or `uid 1000-1009 500-509`.
+pub fn parse_raw_idmap(value: &str) -> Vec<IncusIdmap> {
+    let range = |ids: &str| -> Option<(u32, u32)> {
+        match ids.split_once('-') {
+            Some((first, last)) => {
+                let (first, last): (u32, u32) = (first.parse().ok()?, last.parse().ok()?);
+
+                Some((first, last.checked_sub(first)?.checked_add(1)?))
+            },
+            None => Some((ids.parse().ok()?, 1)),
+        }
+    };
+
+    value
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (is_uid, is_gid) = match fields.next()? {
+                "uid" => (true, false),
+                "gid" => (false, true),
+                "both" => (true, true),
+                _ => return None,
+            };
+            let (host_id, host_range) = range(fields.next()?)?;
+            let (ns_id, ns_range) = range(fields.next()?)?;
+
+            (host_range == ns_range).then_some(IncusIdmap {
+                is_uid,
+                is_gid,
+                host_id,
+                ns_id,
+                range: host_range,
+            })
+        })
+        .collect()
+}
+
+/// A storage pool as `incus storage list --format yaml` lists it.
+#[derive(Deserialize)]
+struct StoragePool {
+    name: String,
+    driver: String,
+}
+
+/// The driver of each Incus storage pool by name.
+pub fn list_storage_drivers() -> Result<HashMap<String, String>, LinuxError> {
+    let output = command::query(Command::new("incus").args(["storage", "list", "--format", "yaml"]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    let pools: Vec<StoragePool> =
+        serde_yaml::from_slice(&output.stdout).map_err(|err| LinuxError::Parse(err.to_string()))?;
+
+    Ok(pools.into_iter().map(|pool| (pool.name, pool.driver)).collect())
+}
+
+/// The names of the Incus containers on this host. Virtual machines have no idmaps to audit.
+pub fn list_containers() -> Result<Vec<String>, LinuxError> {
+    let output =
+        command::query(Command::new("incus").args(["list", "--format", "csv", "--columns", "n", "type=container"]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(str::from_utf8(&output.stdout)?
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Reads container `name` through `incus config show --expanded`, which includes what its profiles
+/// set. `drivers` are those of [`list_storage_drivers`].
+pub fn show_container(name: &str, drivers: &HashMap<String, String>) -> Result<IncusInstance, LinuxError> {
+    let output = command::query(Command::new("incus").args(["config", "show", "--expanded", name]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    let mut instance =
+        parse_config_show(name, str::from_utf8(&output.stdout)?).map_err(|err| LinuxError::Parse(err.to_string()))?;
+
+    instance.root_driver = instance.root_pool.as_ref().and_then(|pool| drivers.get(pool)).cloned();
+
+    Ok(instance)
+}
+
+/// Reads every Incus container on a thread of its own, publishing each to [`Bus::fs_changes`] as an
+/// LXC config.
+pub fn discover(bus: Bus) {
+    thread::spawn(move || {
+        let names = match list_containers() {
+            Ok(names) => names,
+            Err(err) => {
+                bus.notifications.publish(Notification {
+                    level: Level::Warn,
+                    message: format!("Failed to list Incus containers: {err}"),
+                });
+                return;
+            },
+        };
+
+        // Without drivers, rootfs paths are left out rather than guessed
+        let drivers = list_storage_drivers().unwrap_or_else(|err| {
+            bus.notifications.publish(Notification {
+                level: Level::Warn,
+                message: format!("Failed to list Incus storage pools: {err}"),
+            });
+            HashMap::new()
+        });
+
+        for name in names {
+            match show_container(&name, &drivers) {
+                Ok(instance) => bus.fs_changes.publish(FileSystemChangeKind::UpdateIncusInstance(
+                    instance.filename(),
+                    instance.to_lxc_config(),
+                )),
+                Err(err) => bus.notifications.publish(Notification {
+                    level: Level::Warn,
+                    message: format!("Failed to read Incus container {name}: {err}"),
+                }),
+            }
+        }
+    });
+}
+
+#[test]
+fn test_parse_config_show() -> color_eyre::Result<()> {
+    let yaml = "architecture: x86_64\n\
+                config:\n  \
+                  image.os: Debian\n  \
+                  limits.cpu: 2\n  \
+                  raw.idmap: |-\n    \
+                    both 1000 1000\n    \
+                    uid 2000-2009 500-509\n  \
+                  security.nesting: \"true\"\n  \
+                  volatile.idmap.current: '[{\"Isuid\":true,\"Isgid\":false,\"Hostid\":1000000,\"Nsid\":0,\"Maprange\":1000000000},{\"Isuid\":false,\"Isgid\":true,\"Hostid\":1000000,\"Nsid\":0,\"Maprange\":1000000000}]'\n  \
+                  volatile.last_state.power: STOPPED\n\
+                devices:\n  \
+                  eth0:\n    \
+                    pool: nope\n  \
+                  rootdisk:\n    \
+                    path: /\n    \
+                    pool: default\n    \
+                    type: disk\n\
+                ephemeral: false\n";
+    let mut instance = parse_config_show("web", yaml)?;
+
+    assert_eq!(instance.root_pool.as_deref(), Some("default"));
+    assert_eq!(instance.config["security.nesting"], "true");
+    assert_eq!(instance.config["limits.cpu"], "2");
+    assert_eq!(instance.config["raw.idmap"], "both 1000 1000\nuid 2000-2009 500-509");
+    assert!(!instance.is_privileged());
+    // The pool's driver isn't known, so neither is where the rootfs is
+    assert_eq!(
+        instance.to_lxc_config(),
+        "# Incus instance web\n\
+         lxc.idmap = u 0 1000000 1000000000\n\
+         lxc.idmap = g 0 1000000 1000000000\n"
+    );
+
+    instance.root_driver = Some("zfs".into());
+
+    // A dataset of a stopped instance isn't mounted
+    assert_eq!(instance.rootfs_path(), None);
+
+    instance.root_driver = Some("dir".into());
+
+    assert!(
+        instance
+            .to_lxc_config()
+            .contains("lxc.rootfs.path = dir:/var/lib/incus/storage-pools/default/containers/web/rootfs\n")
+    );
+
+    // Never started, so the default allocation is assumed around what raw.idmap maps
+    instance.config.remove("volatile.idmap.current");
+
+    assert_eq!(
+        instance.to_lxc_config(),
+        "# Incus instance web\n\
+         lxc.rootfs.path = dir:/var/lib/incus/storage-pools/default/containers/web/rootfs\n\
+         lxc.idmap = u 0 1000000 500\n\
+         lxc.idmap = u 500 2000 10\n\
+         lxc.idmap = u 510 1000510 490\n\
+         lxc.idmap = u 1000 1000 1\n\
+         lxc.idmap = u 1001 1001001 999998999\n\
+         lxc.idmap = g 0 1000000 1000\n\
+         lxc.idmap = g 1000 1000 1\n\
+         lxc.idmap = g 1001 1001001 999998999\n"
+    );
+
+    instance.config.insert("security.privileged".into(), "true".into());
+
+    assert!(!instance.to_lxc_config().contains("lxc.idmap"));
+
+    Ok(())
+}
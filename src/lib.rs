@@ -1,5 +1,29 @@
+//! Audits the id mappings of unprivileged LXC containers. The TUI is built from the modules here,
+//! but only [`api`] is meant for other crates and follows semver; see its docs for what that covers.
+
+#[cfg(not(unix))]
+compile_error!("pupman relies on Unix file ownership and only builds for Unix-like platforms");
+
+pub mod api;
 pub mod app;
+pub mod changes;
+pub mod check;
+pub mod export;
+pub mod finding;
+pub mod fix;
+pub mod followup;
 pub mod fs;
+pub mod health;
+pub mod history;
+pub mod incus;
 pub mod linux;
 pub mod lxc;
 pub mod metadata;
+pub mod metrics;
+pub mod proxmox;
+pub mod report;
+pub mod server;
+pub mod settings;
+pub mod triage;
+
+pub use health::healthcheck;
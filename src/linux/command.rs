@@ -0,0 +1,145 @@
+//! Running external commands with a time limit, so a hung `zfs` on a busy pool or a stuck `id`
+//! lookup can't freeze evaluation. Commands which only read state are retried a few times, ones
+//! which change something never are.
+
+use std::io::Read;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use super::LinuxError;
+
+/// How often a running command is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long external commands may take and how often queries are retried when they don't finish.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CommandPolicy {
+    pub timeout: Duration,
+    /// Attempts after the first for commands which only read state.
+    pub retries: u32,
+}
+
+impl CommandPolicy {
+    pub const DEFAULT: CommandPolicy = CommandPolicy {
+        timeout: Duration::from_secs(10),
+        retries: 2,
+    };
+}
+
+impl Default for CommandPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Commands are run from the monitor, state evaluation and fixes alike, so the policy is kept here
+/// rather than threaded through every caller.
+static POLICY: Mutex<CommandPolicy> = Mutex::new(CommandPolicy::DEFAULT);
+
+pub fn policy() -> CommandPolicy {
+    *POLICY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn set_policy(policy: CommandPolicy) {
+    *POLICY.lock().unwrap_or_else(PoisonError::into_inner) = policy;
+}
+
+/// Runs a command which only reads state, retrying it when it times out.
+pub fn query(command: &mut Command) -> Result<Output, LinuxError> {
+    let policy = policy();
+    let mut attempt = 0;
+
+    loop {
+        match run(command, policy.timeout) {
+            Err(LinuxError::Timeout(program, timeout)) if attempt < policy.retries => {
+                attempt += 1;
+                warn!(
+                    "{program} didn't finish within {timeout:?}, retrying ({attempt}/{})",
+                    policy.retries
+                );
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Runs a command which changes something. It isn't retried, since it might have gotten part way.
+pub fn change(command: &mut Command) -> Result<Output, LinuxError> {
+    run(command, policy().timeout)
+}
+
+/// Runs `command` to completion, or kills it once `timeout` has passed. Either way the process is
+/// waited on, so none are left behind as zombies.
+fn run(command: &mut Command, timeout: Duration) -> Result<Output, LinuxError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read while waiting, a command filling up its pipe would otherwise never exit
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+
+        if Instant::now() >= deadline {
+            kill(&mut child);
+
+            break None;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+    // Something the command started may still hold its pipes open, so they aren't waited for
+    let Some(status) = status else {
+        return Err(LinuxError::Timeout(program, timeout));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+
+        buf
+    })
+}
+
+fn kill(child: &mut Child) {
+    if let Err(err) = child.kill() {
+        warn!("Failed to kill command {}: {err}", child.id());
+    }
+
+    let _ = child.wait();
+}
+
+#[test]
+fn test_run_timeout() {
+    let output = run(Command::new("echo").arg("hi"), Duration::from_secs(5)).expect("echo runs");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hi\n");
+
+    let started = Instant::now();
+    let err = run(Command::new("sleep").arg("5"), Duration::from_millis(50)).expect_err("sleep times out");
+
+    assert!(matches!(err, LinuxError::Timeout(program, _) if program == "sleep"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
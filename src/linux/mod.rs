@@ -1,6 +1,13 @@
+pub mod command;
+pub mod passwd;
+pub mod reserved;
+pub mod zfs;
+
+use std::path::Path;
 use std::process::Command;
+use std::process::Output;
 use std::str;
-use std::{path::PathBuf, process::Output};
+use std::time::Duration;
 
 use color_eyre::eyre::{Context, eyre};
 use thiserror::Error;
@@ -13,6 +20,12 @@ pub enum LinuxError {
     IO(#[from] std::io::Error),
     #[error("Failed to convert string to utf-8: {0}")]
     Utf8(#[from] std::str::Utf8Error),
+    #[error("System call failed with error: {0}")]
+    Errno(#[from] nix::errno::Errno),
+    #[error("Failed to parse command output: {0}")]
+    Parse(String),
+    #[error("{0} didn't finish within {1:?} and was killed")]
+    Timeout(String, Duration),
 }
 
 impl From<Output> for LinuxError {
@@ -25,12 +38,9 @@ impl From<Output> for LinuxError {
     }
 }
 
+/// Asks `id` for the uid of `username`, for users which aren't in /etc/passwd.
 pub fn username_to_id(username: &str) -> color_eyre::Result<u32> {
-    let output = Command::new("id")
-        .arg("-u")
-        .arg(username)
-        .output()
-        .wrap_err("Failed to execute id bin")?;
+    let output = command::query(Command::new("id").arg("-u").arg(username)).wrap_err("Failed to execute id bin")?;
 
     if !output.status.success() {
         return Err(eyre!("id command failed"));
@@ -40,12 +50,9 @@ pub fn username_to_id(username: &str) -> color_eyre::Result<u32> {
     id_str.trim().parse().wrap_err("Failed to parse user ID")
 }
 
+/// Asks `id` for the primary gid of user `groupname`, for users which aren't in /etc/passwd.
 pub fn groupname_to_id(groupname: &str) -> color_eyre::Result<u32> {
-    let output = Command::new("id")
-        .arg("-g")
-        .arg(groupname)
-        .output()
-        .wrap_err("Failed to execute id bin")?;
+    let output = command::query(Command::new("id").arg("-g").arg(groupname)).wrap_err("Failed to execute id bin")?;
 
     if !output.status.success() {
         return Err(eyre!("id command failed"));
@@ -55,22 +62,349 @@ pub fn groupname_to_id(groupname: &str) -> color_eyre::Result<u32> {
     id_str.trim().parse().wrap_err("Failed to parse group ID")
 }
 
-pub fn zfs_volume_to_mountpoint(volume: &str) -> Result<Option<PathBuf>, LinuxError> {
-    let output = Command::new("zfs").args(["list", "-o", "mountpoint"]).output()?;
+/// Looks up the login name of user id `uid`.
+pub fn id_to_username(uid: &str) -> color_eyre::Result<String> {
+    let output = command::query(Command::new("id").arg("-nu").arg(uid)).wrap_err("Failed to execute id bin")?;
+
+    if !output.status.success() {
+        return Err(eyre!("id command failed"));
+    }
+
+    Ok(std::str::from_utf8(&output.stdout)
+        .wrap_err("Failed to parse id output")?
+        .trim()
+        .to_string())
+}
+
+/// Delegates the subordinate id range `first..=last` to `login` through shadow-utils' `usermod`.
+/// `kind` is `uid` or `gid`.
+pub fn usermod_add_sub_ids(kind: &str, login: &str, first: u32, last: u32) -> Result<(), LinuxError> {
+    let output = command::change(Command::new("usermod").args([
+        &format!("--add-sub{kind}s"),
+        &format!("{first}-{last}"),
+        login,
+    ]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Adds ACL `entries`, as `setfacl -m` takes them, to `path` and everything below it.
+pub fn setfacl_recursive(entries: &str, path: &Path) -> Result<(), LinuxError> {
+    let output = Command::new("setfacl").args(["-R", "-m", entries]).arg(path).output()?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Adds default ACL `entries` to `path` and every directory below it, which files created in them
+/// inherit.
+pub fn setfacl_default_dirs(entries: &str, path: &Path) -> Result<(), LinuxError> {
+    let output = Command::new("find")
+        .arg(path)
+        .args(["-type", "d", "-exec", "setfacl", "-d", "-m", entries, "{}", "+"])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Creates group `name` with id `gid`.
+pub fn groupadd(name: &str, gid: u32) -> Result<(), LinuxError> {
+    let output = command::change(Command::new("groupadd").args(["-g", &gid.to_string(), name]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Hands `path` and everything below it to group `gid`. Not time limited like other changes, as
+/// large directories take a while.
+pub fn chgrp_recursive(gid: u32, path: &Path) -> Result<(), LinuxError> {
+    let output = Command::new("chgrp")
+        .args(["-R", &gid.to_string()])
+        .arg(path)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Changes the mode of `path` and everything below it, as `chmod` takes `mode`.
+pub fn chmod_recursive(mode: &str, path: &Path) -> Result<(), LinuxError> {
+    let output = Command::new("chmod").args(["-R", mode]).arg(path).output()?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Sets the setgid bit on `path` and every directory below it, so files created in them get the
+/// directory's group rather than their creator's.
+pub fn setgid_dirs(path: &Path) -> Result<(), LinuxError> {
+    let output = Command::new("find")
+        .arg(path)
+        .args(["-type", "d", "-exec", "chmod", "g+s", "{}", "+"])
+        .output()?;
 
     if !output.status.success() {
         return Err(output.into());
     }
 
-    let stdout = str::from_utf8(&output.stdout)?;
+    Ok(())
+}
+
+/// Sets a single option of container `vmid` through Proxmox's `pct set`.
+pub fn pct_set(vmid: &str, key: &str, value: &str) -> Result<(), LinuxError> {
+    let output = command::change(Command::new("pct").args(["set", vmid, &format!("--{key}"), value]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Whether container `vmid` is running, according to `lxc-info`.
+pub fn lxc_running(vmid: &str) -> Result<bool, LinuxError> {
+    let output = command::query(Command::new("lxc-info").args(["-n", vmid, "-s"]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(str::from_utf8(&output.stdout)?.contains("RUNNING"))
+}
+
+/// Tries to start container `vmid` in the background with LXC's debug log written to `log_path`.
+/// Returns whether it started, since a failed start is what the log is usually wanted for.
+pub fn lxc_start_logged(vmid: &str, log_path: &Path) -> Result<bool, LinuxError> {
+    let output = command::change(
+        Command::new("lxc-start")
+            .args(["-n", vmid, "-l", "DEBUG", "-o"])
+            .arg(log_path),
+    )?;
 
-    for line in stdout.lines() {
-        if line.trim_end().ends_with(volume) {
-            return Ok(Some(PathBuf::from(line.trim_end())));
+    Ok(output.status.success())
+}
+
+/// Stops container `vmid`, giving it the time `lxc-stop` does by default to shut down cleanly.
+pub fn lxc_stop(vmid: &str) -> Result<(), LinuxError> {
+    let output = command::change(Command::new("lxc-stop").args(["-n", vmid]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(())
+}
+
+/// Space available to a rootfs, as seen from the host.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiskSpace {
+    /// Bytes available for writing.
+    pub available: u64,
+    /// The dataset quota in bytes, if one is set.
+    pub quota: Option<u64>,
+    /// Set when the path is backed by a ZFS dataset, in which case snapshots pin old blocks.
+    pub zfs: Option<ZfsDataset>,
+    /// The file system the path is on, if it's one pupman knows.
+    pub filesystem: Option<Filesystem>,
+}
+
+/// File systems which can be mounted with an idmap and the kernel version which first could, by
+/// their `statfs` magic. ZFS supports it on its own terms, see [`ZFS_IDMAPPED_MOUNTS`].
+const IDMAPPED_MOUNT_FILESYSTEMS: &[(i64, &str, (u32, u32))] = &[
+    (0xEF53, "ext4", (5, 12)),
+    (0x58465342, "xfs", (5, 12)),
+    (0x4d44, "vfat", (5, 12)),
+    (0x9123683E, "btrfs", (5, 15)),
+    (0xF2F52010, "f2fs", (5, 18)),
+    (0x794c7630, "overlay", (5, 19)),
+    (0x01021994, "tmpfs", (6, 3)),
+    (0x00C36400, "ceph", (6, 7)),
+];
+const ZFS_MAGIC: i64 = 0x2fc12fc1;
+/// The OpenZFS version which added idmapped mounts, on kernels which have them at all.
+const ZFS_IDMAPPED_MOUNTS: (u32, u32) = (2, 2);
+/// The kernel version which added idmapped mounts.
+const KERNEL_IDMAPPED_MOUNTS: (u32, u32) = (5, 12);
+
+/// The file system a rootfs or mount point is on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Filesystem {
+    pub name: &'static str,
+    /// Whether it can be mounted with an idmap on this kernel, which lets a container see files
+    /// owned by unshifted ids as its own without chowning them.
+    pub idmapped_mounts: bool,
+}
+
+impl Filesystem {
+    /// Identifies the file system with `statfs` magic `magic`. Versions are `(major, minor)`.
+    fn identify(magic: i64, kernel: Option<(u32, u32)>, zfs_module: Option<(u32, u32)>) -> Option<Self> {
+        let kernel_supports = |since| kernel.is_some_and(|kernel| kernel >= since);
+
+        if magic == ZFS_MAGIC {
+            return Some(Self {
+                name: "zfs",
+                idmapped_mounts: kernel_supports(KERNEL_IDMAPPED_MOUNTS)
+                    && zfs_module.is_some_and(|zfs| zfs >= ZFS_IDMAPPED_MOUNTS),
+            });
         }
+
+        let (_, name, since) = IDMAPPED_MOUNT_FILESYSTEMS.iter().find(|(m, ..)| *m == magic)?;
+
+        Some(Self {
+            name,
+            idmapped_mounts: kernel_supports(*since),
+        })
     }
+}
+
+/// The `major.minor` prefix of a version like `6.8.12-4-pve` or `2.2.6-pve1`.
+fn major_minor(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split(|c: char| !c.is_ascii_digit());
+
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
 
-    Ok(None)
+/// Looks up which file system `path` is on and whether this host can mount it with an idmap.
+pub fn filesystem(path: &Path) -> Option<Filesystem> {
+    let magic = nix::sys::statfs::statfs(path).ok()?.filesystem_type().0 as i64;
+    let version = |path| std::fs::read_to_string(path).ok().as_deref().and_then(major_minor);
+
+    Filesystem::identify(
+        magic,
+        version("/proc/sys/kernel/osrelease"),
+        version("/sys/module/zfs/version"),
+    )
+}
+
+/// ZFS dataset properties which can prevent writes to a rootfs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ZfsDataset {
+    /// The dataset name, e.g. `rpool/data/subvol-100-disk-0`.
+    pub name: String,
+    pub readonly: bool,
+    /// The `refquota` in bytes, if one is set.
+    pub refquota: Option<u64>,
+    /// Whether an interrupted `zfs receive` left a resume token, which makes the dataset unusable.
+    pub partially_received: bool,
+}
+
+impl ZfsDataset {
+    /// Whether a property of this dataset would make a chown of its contents fail.
+    pub fn blocks_writes(&self) -> bool {
+        self.readonly || self.partially_received
+    }
+
+    /// Commands which lift any write blocking properties.
+    pub fn remediation_commands(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+
+        if self.partially_received {
+            commands.push(format!("zfs receive -A {}", self.name));
+        }
+
+        if self.readonly {
+            commands.push(format!("zfs set readonly=off {}", self.name));
+        }
+
+        commands
+    }
+}
+
+/// Looks up the free space for a path, preferring ZFS properties over `statvfs` so quotas are included.
+pub fn disk_space(path: &Path) -> Result<DiskSpace, LinuxError> {
+    match zfs_disk_space(path) {
+        Ok(space) => {
+            return Ok(DiskSpace {
+                filesystem: filesystem(path),
+                ..space
+            });
+        },
+        // A pool too busy to answer zfs would hang statvfs just the same
+        Err(err @ LinuxError::Timeout(..)) => return Err(err),
+        Err(_) => {},
+    }
+
+    let stat = nix::sys::statvfs::statvfs(path)?;
+
+    Ok(DiskSpace {
+        available: stat.blocks_available() as u64 * stat.fragment_size() as u64,
+        quota: None,
+        zfs: None,
+        filesystem: filesystem(path),
+    })
+}
+
+fn zfs_disk_space(path: &Path) -> Result<DiskSpace, LinuxError> {
+    let output = command::query(
+        Command::new("zfs")
+            .args([
+                "get",
+                "-H",
+                "-p",
+                "-o",
+                "name,value",
+                "available,quota,refquota,readonly,receive_resume_token",
+            ])
+            .arg(path),
+    )?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    parse_zfs_get(str::from_utf8(&output.stdout)?)
+        .ok_or_else(|| LinuxError::Parse(format!("zfs properties for {}", path.display())))
+}
+
+/// Parses `zfs get -H -p -o name,value available,quota,refquota,readonly,receive_resume_token` output.
+fn parse_zfs_get(stdout: &str) -> Option<DiskSpace> {
+    let mut name = None;
+    let mut values = stdout.lines().map(|line| {
+        let (dataset, value) = line.split_once('\t')?;
+
+        name.get_or_insert_with(|| dataset.to_string());
+
+        Some(value.trim())
+    });
+    let mut next_bytes = || values.next().flatten()?.parse::<u64>().ok();
+    let available = next_bytes()?;
+    // A quota of 0 means no quota is set
+    let quota = Some(next_bytes()?).filter(|quota| *quota != 0);
+    let refquota = Some(next_bytes()?).filter(|quota| *quota != 0);
+    let readonly = values.next().flatten()? == "on";
+    let partially_received = values.next().flatten()? != "-";
+
+    Some(DiskSpace {
+        available,
+        quota,
+        zfs: Some(ZfsDataset {
+            name: name?,
+            readonly,
+            refquota,
+            partially_received,
+        }),
+        filesystem: None,
+    })
 }
 
 #[test]
@@ -82,3 +416,72 @@ fn test_username_to_id() {
 fn test_groupname_to_id() {
     assert_eq!(groupname_to_id("root").unwrap(), 0);
 }
+
+#[test]
+fn test_disk_space() {
+    let stdout = "rpool/data/subvol-101-disk-0\t1073741824\nrpool/data/subvol-101-disk-0\t8589934592\n\
+                  rpool/data/subvol-101-disk-0\t0\nrpool/data/subvol-101-disk-0\toff\n\
+                  rpool/data/subvol-101-disk-0\t-\n";
+    let space = parse_zfs_get(stdout).unwrap();
+    let zfs = space.zfs.as_ref().unwrap();
+
+    assert_eq!(space.available, 1073741824);
+    assert_eq!(space.quota, Some(8589934592));
+    assert_eq!(zfs.refquota, None);
+    assert!(!zfs.blocks_writes());
+
+    // Missing or unparsable properties
+    assert_eq!(parse_zfs_get(""), None);
+    assert_eq!(parse_zfs_get("rpool/data\t1024\nrpool/data\t0\n"), None);
+    assert_eq!(parse_zfs_get(&stdout.replace("1073741824", "1G")), None);
+}
+
+#[test]
+fn test_filesystem_identify() {
+    assert_eq!(major_minor("6.8.12-4-pve"), Some((6, 8)));
+    assert_eq!(major_minor("2.2.6-pve1\n"), Some((2, 2)));
+
+    let ext4 = Filesystem::identify(0xEF53, Some((6, 8)), None).unwrap();
+
+    assert_eq!(ext4.name, "ext4");
+    assert!(ext4.idmapped_mounts);
+    // Too old a kernel for tmpfs
+    assert!(
+        !Filesystem::identify(0x01021994, Some((5, 15)), None)
+            .unwrap()
+            .idmapped_mounts
+    );
+    // ZFS only has them from 2.2 on
+    assert!(
+        !Filesystem::identify(ZFS_MAGIC, Some((6, 8)), Some((2, 1)))
+            .unwrap()
+            .idmapped_mounts
+    );
+    assert!(
+        Filesystem::identify(ZFS_MAGIC, Some((6, 8)), Some((2, 2)))
+            .unwrap()
+            .idmapped_mounts
+    );
+    assert_eq!(Filesystem::identify(0x6969, Some((6, 8)), None), None);
+}
+
+#[test]
+fn test_parse_zfs_get() {
+    let stdout = "rpool/data/subvol-100-disk-0\t4096\nrpool/data/subvol-100-disk-0\t0\n\
+                  rpool/data/subvol-100-disk-0\t8589934592\nrpool/data/subvol-100-disk-0\ton\n\
+                  rpool/data/subvol-100-disk-0\t-\n";
+    let space = parse_zfs_get(stdout).unwrap();
+    let zfs = space.zfs.as_ref().unwrap();
+
+    assert_eq!(space.available, 4096);
+    assert_eq!(space.quota, None);
+    assert_eq!(zfs.name, "rpool/data/subvol-100-disk-0");
+    assert_eq!(zfs.refquota, Some(8589934592));
+    assert!(zfs.readonly);
+    assert!(!zfs.partially_received);
+    assert!(zfs.blocks_writes());
+    assert_eq!(
+        zfs.remediation_commands(),
+        ["zfs set readonly=off rpool/data/subvol-100-disk-0"]
+    );
+}
@@ -0,0 +1,158 @@
+//! Reads /etc/passwd and /etc/group directly, so owners of subordinate id ranges can be looked up
+//! without spawning `id` for each of them.
+
+use compact_str::CompactString;
+
+pub const ETC_PASSWD: &str = "/etc/passwd";
+pub const ETC_GROUP: &str = "/etc/group";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct User {
+    pub name: CompactString,
+    pub uid: u32,
+    /// The user's primary group.
+    pub gid: u32,
+    pub shell: CompactString,
+}
+
+impl User {
+    /// Whether the user has a shell to log in with. Service accounts get `nologin` or `false`
+    /// instead, and an empty shell means `/bin/sh`.
+    pub fn can_log_in(&self) -> bool {
+        !["nologin", "false", "sync", "shutdown", "halt"]
+            .iter()
+            .any(|disabled| self.shell.rsplit('/').next() == Some(*disabled))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Group {
+    pub name: CompactString,
+    pub gid: u32,
+}
+
+/// The users of /etc/passwd.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Passwd {
+    pub users: Vec<User>,
+    /// Whether the file pulls in NIS users with a `+` line, which means users can exist without
+    /// being listed here.
+    pub includes_nis: bool,
+}
+
+impl Passwd {
+    /// Looks up an owner as subuid and subgid entries name them, by login or by numeric uid.
+    pub fn user(&self, owner: &str) -> Option<&User> {
+        match owner.parse::<u32>() {
+            Ok(uid) => self.users.iter().find(|user| user.uid == uid),
+            Err(_) => self.users.iter().find(|user| user.name == owner),
+        }
+    }
+
+    /// The uid an owner of subid entries goes by, whether it is named by login or by uid. Logins
+    /// this doesn't list have none, except for root which is always uid 0.
+    pub fn owner_uid(&self, owner: &str) -> Option<u32> {
+        match owner.parse::<u32>() {
+            Ok(uid) => Some(uid),
+            Err(_) => self.user(owner).map(|user| user.uid).or((owner == "root").then_some(0)),
+        }
+    }
+
+    /// Whether two owners of subid entries are the same user, which one entry may name by login and
+    /// the other by uid.
+    pub fn same_user(&self, a: &str, b: &str) -> bool {
+        a == b || matches!((self.owner_uid(a), self.owner_uid(b)), (Some(a), Some(b)) if a == b)
+    }
+}
+
+/// Parses `name:password:uid:gid:gecos:home:shell` lines. Comments and malformed lines are skipped.
+pub fn parse_passwd(content: &str) -> Passwd {
+    let mut passwd = Passwd::default();
+
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('+') || line.starts_with('-') {
+            passwd.includes_nis = true;
+            continue;
+        }
+
+        let mut fields = line.split(':');
+        let (Some(name), _, Some(uid), Some(gid)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let shell = fields.nth(2).unwrap_or_default();
+        let (Ok(uid), Ok(gid)) = (uid.parse(), gid.parse()) else {
+            continue;
+        };
+
+        if name.is_empty() || name.starts_with('#') {
+            continue;
+        }
+
+        passwd.users.push(User {
+            name: name.into(),
+            uid,
+            gid,
+            shell: shell.into(),
+        });
+    }
+
+    passwd
+}
+
+/// Parses `name:password:gid:members` lines. Comments and malformed lines are skipped.
+pub fn parse_group(content: &str) -> Vec<Group> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#') && !line.starts_with('+') && !line.starts_with('-'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next().filter(|name| !name.is_empty())?;
+            let gid = fields.nth(1)?.parse().ok()?;
+
+            Some(Group { name: name.into(), gid })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_passwd() {
+    let passwd = parse_passwd(
+        "root:x:0:0:root:/root:/bin/bash\n\
+         # a comment\n\
+         backup:x:34:34:backup:/var/backups:/usr/sbin/nologin\n\
+         broken:x:nope:0::/:/bin/sh\n\
+         +@netgroup\n",
+    );
+
+    assert_eq!(passwd.users.len(), 2);
+    assert!(passwd.includes_nis);
+    assert_eq!(passwd.user("backup").map(|user| user.gid), Some(34));
+    assert_eq!(passwd.user("0").map(|user| user.name.as_str()), Some("root"));
+    assert_eq!(passwd.user("nobody"), None);
+    assert!(passwd.user("root").is_some_and(User::can_log_in));
+    assert!(!passwd.user("backup").is_some_and(User::can_log_in));
+    assert_eq!(passwd.owner_uid("backup"), Some(34));
+    assert_eq!(passwd.owner_uid("1001"), Some(1001));
+    assert_eq!(passwd.owner_uid("nobody"), None);
+    assert!(passwd.same_user("root", "0"));
+    assert!(passwd.same_user("34", "backup"));
+    assert!(!passwd.same_user("backup", "nobody"));
+    assert!(Passwd::default().same_user("root", "0"));
+
+    let groups = parse_group("root:x:0:\nlxc-users:x:1001:alice,bob\n#old:x:5:\n");
+
+    assert_eq!(
+        groups,
+        [
+            Group {
+                name: "root".into(),
+                gid: 0
+            },
+            Group {
+                name: "lxc-users".into(),
+                gid: 1001
+            },
+        ]
+    );
+}
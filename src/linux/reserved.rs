@@ -0,0 +1,116 @@
+//! Host id ranges with a conventional meaning, as systemd's `UIDS-GIDS.md` lays them out. Nothing
+//! stops an idmap or subordinate range from covering them, but whatever else uses them on the host
+//! then shares ids with the container.
+
+use crate::lxc::range_end;
+
+/// Ids set aside for one purpose, `first..=last`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReservedRange {
+    pub first: u32,
+    pub last: u32,
+    /// What uses the ids, e.g. `systemd-homed`.
+    pub owner: &'static str,
+    /// Why sharing them with a container is a problem.
+    pub rationale: &'static str,
+}
+
+impl ReservedRange {
+    /// Whether any of the `count` ids from `start` on are in this range.
+    pub fn collides(&self, start: u32, count: u32) -> bool {
+        count > 0 && start <= self.last && u64::from(self.first) < range_end(start, count)
+    }
+}
+
+impl std::fmt::Display for ReservedRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.first == self.last {
+            write!(f, "{} ({})", self.first, self.owner)
+        } else {
+            write!(f, "{}-{} ({})", self.first, self.last, self.owner)
+        }
+    }
+}
+
+pub const RESERVED_RANGES: &[ReservedRange] = &[
+    ReservedRange {
+        first: 60001,
+        last: 60513,
+        owner: "systemd-homed",
+        rationale: "systemd-homed allocates the uids of home directory users from this range, so files owned \
+                    by them would be owned by a regular user of the host who logs in.",
+    },
+    ReservedRange {
+        first: 60514,
+        last: 60577,
+        owner: "systemd-nspawn --bind-user",
+        rationale: "systemd-nspawn maps host users it binds into its containers onto these ids.",
+    },
+    ReservedRange {
+        first: 61184,
+        last: 65519,
+        owner: "systemd DynamicUser",
+        rationale: "systemd hands these out to services with DynamicUser=yes while they run, and a later service \
+                    may get the same id, which would then own whatever the container left behind.",
+    },
+    ReservedRange {
+        first: 65534,
+        last: 65534,
+        owner: "nobody",
+        rationale: "nobody is what ids without a mapping show up as, so files really owned by it can't be told \
+                    apart from unmapped ones.",
+    },
+    ReservedRange {
+        first: 65535,
+        last: 65535,
+        owner: "16 bit -1",
+        rationale: "65535 is -1 to anything still using 16 bit ids, and many tools refuse to use it.",
+    },
+    ReservedRange {
+        first: 524288,
+        last: 1879048191,
+        owner: "systemd-nspawn pool",
+        rationale: "systemd-nspawn --private-users=pick picks the ids of its containers from this range, which \
+                    only avoids ranges other nspawn containers use. It matters only if nspawn or systemd's \
+                    portable services run on this host.",
+    },
+    ReservedRange {
+        first: 2147352576,
+        last: 2147418111,
+        owner: "systemd foreign ids",
+        rationale: "systemd maps the ids of foreign OS images, like those mountfsd mounts, onto this range.",
+    },
+    ReservedRange {
+        first: u32::MAX,
+        last: u32::MAX,
+        owner: "32 bit -1",
+        rationale: "4294967295 is -1, which system calls such as chown take to mean leaving the id as it is.",
+    },
+];
+
+/// The reserved ranges which any of the `count` ids from `start` on fall into.
+pub fn colliding(start: u32, count: u32) -> impl Iterator<Item = &'static ReservedRange> {
+    RESERVED_RANGES.iter().filter(move |range| range.collides(start, count))
+}
+
+#[test]
+fn test_colliding() {
+    let owners = |start, count| colliding(start, count).map(|range| range.owner).collect::<Vec<_>>();
+
+    // Proxmox' default range is clear of all of them
+    assert!(owners(100000, 65536).is_empty());
+    assert_eq!(
+        owners(0, 65536),
+        [
+            "systemd-homed",
+            "systemd-nspawn --bind-user",
+            "systemd DynamicUser",
+            "nobody",
+            "16 bit -1"
+        ]
+    );
+    assert_eq!(owners(65535, 1), ["16 bit -1"]);
+    assert_eq!(owners(493216, 65536), ["systemd-nspawn pool"]);
+    assert!(owners(458752, 65536).is_empty());
+    assert_eq!(owners(u32::MAX - 65535, 65536), ["32 bit -1"]);
+}
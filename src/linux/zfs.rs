@@ -0,0 +1,103 @@
+//! Where ZFS actually mounted each dataset. storage.cfg only implies it from the pool name, which
+//! is wrong for pools with a `mountpoint` of their own. Asking `zfs` for every rootfs would spawn it
+//! on each file change, so all datasets are listed at once and kept for a while.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str;
+use std::time::{Duration, Instant};
+
+use ahash::RandomState;
+use log::debug;
+
+use super::LinuxError;
+use super::command;
+
+/// How long a listing is used before it is taken again.
+const MAX_AGE: Duration = Duration::from_secs(60);
+/// How long a listing is used at least, even when it doesn't know a dataset. Pools without the
+/// dataset would otherwise be listed on every lookup.
+const MIN_AGE: Duration = Duration::from_secs(5);
+
+/// The `mountpoint` of every mounted dataset, as `zfs list` last reported them.
+#[derive(Debug, Default)]
+pub struct ZfsCache {
+    mountpoints: HashMap<String, PathBuf, RandomState>,
+    refreshed: Option<Instant>,
+}
+
+impl ZfsCache {
+    /// Where `dataset` is mounted. The listing is taken again once it is old, or when it doesn't
+    /// know the dataset and wasn't only just taken.
+    pub fn mountpoint(&mut self, dataset: &str) -> Option<PathBuf> {
+        let age = self.refreshed.map(|refreshed| refreshed.elapsed());
+        let stale = age.is_none_or(|age| age >= MAX_AGE);
+        let unknown = !self.mountpoints.contains_key(dataset) && age.is_none_or(|age| age >= MIN_AGE);
+
+        if stale || unknown {
+            self.refresh();
+        }
+
+        self.mountpoints.get(dataset).cloned()
+    }
+
+    /// Takes the listing again. Hosts without ZFS end up with an empty one, which is kept as long
+    /// as any other.
+    pub fn refresh(&mut self) {
+        self.refreshed = Some(Instant::now());
+        self.mountpoints = match list_mountpoints() {
+            Ok(mountpoints) => mountpoints,
+            Err(err) => {
+                debug!("Failed to list zfs mountpoints: {err}");
+                Default::default()
+            },
+        };
+    }
+}
+
+fn list_mountpoints() -> Result<HashMap<String, PathBuf, RandomState>, LinuxError> {
+    let output = command::query(Command::new("zfs").args(["list", "-H", "-t", "filesystem", "-o", "name,mountpoint"]))?;
+
+    if !output.status.success() {
+        return Err(output.into());
+    }
+
+    Ok(parse_zfs_list(str::from_utf8(&output.stdout)?).collect())
+}
+
+/// Parses `zfs list -H -o name,mountpoint` output. Datasets which are `legacy` mounted or not
+/// mounted at all are left out.
+fn parse_zfs_list(stdout: &str) -> impl Iterator<Item = (String, PathBuf)> {
+    stdout.lines().filter_map(|line| {
+        let (name, mountpoint) = line.split_once('\t')?;
+
+        mountpoint
+            .starts_with('/')
+            .then(|| (name.to_string(), PathBuf::from(mountpoint.trim_end())))
+    })
+}
+
+#[test]
+fn test_zfs_cache() {
+    let listing = "rpool\t/rpool\n\
+                   rpool/data\t/rpool/data\n\
+                   tank/ct\t/mnt/ct\n\
+                   tank/ct/subvol-101-disk-0\t/mnt/ct/subvol-101-disk-0\n\
+                   rpool/ROOT/pve-1\t/\n\
+                   rpool/legacy\tlegacy\n\
+                   rpool/none\tnone\n";
+    let mut cache = ZfsCache {
+        mountpoints: parse_zfs_list(listing).collect(),
+        refreshed: Some(Instant::now()),
+    };
+
+    assert_eq!(cache.mountpoints.len(), 5);
+    assert_eq!(
+        cache.mountpoint("tank/ct/subvol-101-disk-0"),
+        Some(PathBuf::from("/mnt/ct/subvol-101-disk-0"))
+    );
+    // Just listed, so an unknown dataset doesn't list again
+    assert_eq!(cache.mountpoint("rpool/legacy"), None);
+    assert_eq!(cache.mountpoints.len(), 5);
+}
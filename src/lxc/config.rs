@@ -1,16 +1,23 @@
-//! A Proxmox LXC container configuration file parser, writer, and validator.
+//! A Proxmox LXC container configuration file parser, writer, and validator. Upstream LXC configs,
+//! which separate keys and values with `=` rather than `:`, are read and written just the same.
 //!
 //! A config should have near constant time lookups on methods since data is constantly read and
 //! displayed to the user. Writes can be slower as they are infrequent operations.
 
+use std::borrow::Cow;
 use std::fmt::{Display, Write};
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 use ahash::HashMap;
 use compact_str::{CompactString, ToCompactString};
+use indexmap::IndexMap;
 
+use super::idmap::{ConfigIdMap, config_idmaps};
+use super::include::Include;
 use super::section::SectionView;
 use super::section_mut::SectionViewMut;
+use crate::proxmox::property_string::PropertyString;
 
 #[derive(Clone, Debug)]
 pub enum ConfEntry {
@@ -20,13 +27,61 @@ pub enum ConfEntry {
     EmptyLine,
 }
 
+/// How a config separates its keys from their values, which is kept when it's written back out. PVE
+/// reads `lxc.*` keys with either separator, so a file is only taken for upstream LXC when every key
+/// uses `=`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ConfigFormat {
+    /// `key: value`, as PVE writes `/etc/pve/lxc/<vmid>.conf`.
+    #[default]
+    Proxmox,
+    /// `key = value`, as upstream LXC reads `/var/lib/lxc/<name>/config`.
+    Lxc,
+}
+
+impl ConfigFormat {
+    pub fn separator(self) -> &'static str {
+        match self {
+            ConfigFormat::Proxmox => ": ",
+            ConfigFormat::Lxc => " = ",
+        }
+    }
+
+    /// Splits a line on whichever of `:` and `=` comes first, since keys never contain either but
+    /// values may contain both, like `lxc.rootfs.path = dir:/var/lib/lxc/web/rootfs`.
+    fn split(line: &str) -> Option<(&str, &str, Self)> {
+        let i = line.find([':', '='])?;
+        let format = if line.as_bytes()[i] == b':' {
+            ConfigFormat::Proxmox
+        } else {
+            ConfigFormat::Lxc
+        };
+
+        Some((&line[..i], &line[i + 1..], format))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
+    pub(super) format: ConfigFormat,
     pub(super) entries: Vec<ConfEntry>,
     pub(super) index: HashMap<(Option<CompactString>, CompactString), Vec<CompactString>>,
+    /// Fragments pulled in via `lxc.include`. These are never written back out.
+    pub(super) includes: Vec<Include>,
+    /// The parsed `lxc.idmap` entries, parsed on first use and again after the config changes.
+    pub(super) idmaps: OnceLock<Vec<ConfigIdMap>>,
 }
 
 impl Config {
+    pub fn format(&self) -> ConfigFormat {
+        self.format
+    }
+
+    /// The `lxc.idmap` entries of the top level section, including those of `lxc.include` files.
+    pub fn idmaps(&self) -> &[ConfigIdMap] {
+        self.idmaps.get_or_init(|| config_idmaps(self))
+    }
+
     pub fn section<'s, S>(&self, section: S) -> SectionView<'s, '_>
     where
         S: Into<Option<&'s str>>,
@@ -41,6 +96,8 @@ impl Config {
     where
         S: Into<Option<&'s str>>,
     {
+        self.idmaps.take();
+
         SectionViewMut {
             config: self,
             section: section.into(),
@@ -48,6 +105,194 @@ impl Config {
     }
 }
 
+impl Config {
+    /// Keys which are set more than once in the same section, with the 1-based line of each
+    /// occurrence. Raw `lxc.*` keys are skipped since many of them, like `lxc.idmap`, are meant to
+    /// be repeated.
+    pub fn duplicate_keys(&self) -> Vec<(&str, Vec<usize>)> {
+        let mut seen: IndexMap<(Option<&str>, &str), Vec<usize>, ahash::RandomState> = IndexMap::default();
+        let mut section = None;
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            match entry {
+                ConfEntry::Section(name) => section = Some(name.as_str()),
+                ConfEntry::KeyValue(key, _) if !key.starts_with("lxc.") => {
+                    seen.entry((section, key.as_str())).or_default().push(i + 1);
+                },
+                _ => {},
+            }
+        }
+
+        seen.into_iter()
+            .filter(|(_, lines)| lines.len() > 1)
+            .map(|((_, key), lines)| (key, lines))
+            .collect()
+    }
+
+    /// The names of the snapshot sections, like `pre-setup` for `[pre-setup]`, in file order. PVE's
+    /// own sections such as `[pve:pending]` aren't snapshots and are left out.
+    pub fn snapshots(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().filter_map(|entry| match entry {
+            ConfEntry::Section(name) if !name.starts_with("pve:") => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The section the 1-based `line` is in, `None` for the part before any section.
+    pub fn section_at(&self, line: usize) -> Option<&str> {
+        self.entries
+            .iter()
+            .take(line)
+            .filter_map(|entry| match entry {
+                ConfEntry::Section(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .next_back()
+    }
+
+    /// Every key and value set in `section` with its 1-based line, in file order.
+    pub fn section_entries<'c>(&'c self, section: Option<&str>) -> impl Iterator<Item = (usize, &'c str, &'c str)> {
+        let mut current = None;
+
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, entry)| match entry {
+                ConfEntry::Section(name) => {
+                    current = Some(name.as_str());
+                    None
+                },
+                ConfEntry::KeyValue(key, value) if current == section => Some((i + 1, key.as_str(), value.as_str())),
+                _ => None,
+            })
+    }
+
+    /// Every key set in `section` with its 1-based line, in file order.
+    pub fn key_lines(&self, section: Option<&str>) -> Vec<(&str, usize)> {
+        self.section_entries(section)
+            .map(|(line, key, _)| (key, line))
+            .collect()
+    }
+
+    /// The 1-based line where `key` is set to `value` in `section`.
+    pub fn find_line(&self, section: Option<&str>, key: &str, value: &str) -> Option<usize> {
+        self.section_entries(section)
+            .find(|(_, k, v)| *k == key && *v == value)
+            .map(|(line, _, _)| line)
+    }
+
+    /// A line as it would be written out, without a trailing newline.
+    pub fn line(&self, line: usize) -> Option<String> {
+        let entry = self.entries.get(line.checked_sub(1)?)?;
+
+        Some(match entry {
+            ConfEntry::Section(section) => format!("[{section}]"),
+            ConfEntry::KeyValue(key, value) => format!("{key}{}{value}", self.format.separator()),
+            ConfEntry::Comment(comment) => comment.clone(),
+            ConfEntry::EmptyLine => String::new(),
+        })
+    }
+}
+
+/// A `mpN` mount point of a container, such as `mp0: /tank/media,mp=/media`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MountPoint<'c> {
+    /// The key, `mp0` up to `mp255`.
+    pub key: &'c str,
+    /// The whole value, which is what the mount's host directory is looked up and watched by.
+    pub value: &'c str,
+    /// A host directory for bind mounts, otherwise a storage volume like `local-zfs:subvol-100-disk-1`.
+    pub volume: Cow<'c, str>,
+    /// Where it is mounted inside the container.
+    pub path: Option<Cow<'c, str>>,
+}
+
+impl<'c> MountPoint<'c> {
+    /// Parses `key: value` if `key` is a mount point key. The volume may be given positionally or
+    /// as `volume=`, like PVE accepts.
+    pub fn parse(key: &'c str, value: &'c str) -> Option<Self> {
+        let index = key.strip_prefix("mp")?;
+
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut volume = None;
+        let mut path = None;
+
+        for (i, property) in PropertyString::parse(value).ok()?.properties.into_iter().enumerate() {
+            match property.key {
+                Some("volume") => volume = Some(property.value),
+                Some("mp") => path = Some(property.value),
+                Some(_) => {},
+                None if i == 0 => volume = Some(property.value),
+                None => {},
+            }
+        }
+
+        Some(Self {
+            key,
+            value,
+            volume: volume.filter(|volume| !volume.is_empty())?,
+            path,
+        })
+    }
+
+    /// Whether a host directory is mounted as is, rather than a volume PVE created.
+    pub fn is_bind_mount(&self) -> bool {
+        self.volume.starts_with('/')
+    }
+}
+
+/// A `devN` device passed through to a container, such as `dev0: /dev/dri/renderD128,gid=104`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Device<'c> {
+    /// The key, `dev0` up to `dev255`.
+    pub key: &'c str,
+    pub value: &'c str,
+    /// The host's device node, which is created at the same path in the container.
+    pub path: Cow<'c, str>,
+    /// The container uid the node is owned by, 0 unless given.
+    pub uid: u32,
+    /// The container gid the node is owned by, 0 unless given.
+    pub gid: u32,
+}
+
+impl<'c> Device<'c> {
+    /// Parses `key: value` if `key` is a device key. Ids which aren't numbers are left at 0, the
+    /// schema check reports them.
+    pub fn parse(key: &'c str, value: &'c str) -> Option<Self> {
+        let index = key.strip_prefix("dev")?;
+
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut path = None;
+        let mut uid = 0;
+        let mut gid = 0;
+
+        for (i, property) in PropertyString::parse(value).ok()?.properties.into_iter().enumerate() {
+            match property.key {
+                Some("path") => path = Some(property.value),
+                Some("uid") => uid = property.value.trim().parse().unwrap_or_default(),
+                Some("gid") => gid = property.value.trim().parse().unwrap_or_default(),
+                Some(_) => {},
+                None if i == 0 => path = Some(property.value),
+                None => {},
+            }
+        }
+
+        Some(Self {
+            key,
+            value,
+            path: path.filter(|path| !path.is_empty())?,
+            uid,
+            gid,
+        })
+    }
+}
+
 impl FromStr for Config {
     type Err = color_eyre::Report;
 
@@ -57,6 +302,7 @@ impl FromStr for Config {
         let mut entries = Vec::with_capacity(lines.size_hint().1.unwrap_or(0));
         let mut index: HashMap<_, Vec<_>> = HashMap::default();
         let mut current_section: Option<CompactString> = None;
+        let mut format = None;
 
         for line in lines {
             let trimmed = line.trim();
@@ -70,7 +316,11 @@ impl FromStr for Config {
 
                 entries.push(ConfEntry::Section(section.clone()));
                 current_section = Some(section);
-            } else if let Some((key, value)) = trimmed.split_once(':').or_else(|| trimmed.split_once('=')) {
+            } else if let Some((key, value, line_format)) = ConfigFormat::split(trimmed) {
+                if format != Some(ConfigFormat::Proxmox) {
+                    format = Some(line_format);
+                }
+
                 let key = key.trim().to_compact_string();
                 let value = value.trim().to_compact_string();
 
@@ -87,7 +337,13 @@ impl FromStr for Config {
             }
         }
 
-        Ok(Config { entries, index })
+        Ok(Config {
+            format: format.unwrap_or_default(),
+            entries,
+            index,
+            includes: Vec::new(),
+            idmaps: OnceLock::new(),
+        })
     }
 }
 
@@ -100,7 +356,7 @@ impl Display for Config {
 
             match entry {
                 ConfEntry::Section(section) => write!(f, "[{section}]")?,
-                ConfEntry::KeyValue(key, value) => write!(f, "{key}: {value}")?,
+                ConfEntry::KeyValue(key, value) => write!(f, "{key}{}{value}", self.format.separator())?,
                 ConfEntry::Comment(comment) => write!(f, "{comment}")?,
                 ConfEntry::EmptyLine => {},
             }
@@ -179,6 +435,102 @@ fn test_config_to_from_str() -> color_eyre::Result<()> {
     assert_eq!(idmaps[1], "g 0 6653600 65536");
 
     assert_eq!(config.to_string(), SAMPLE_CONFIG);
+    assert_eq!(config.snapshots().collect::<Vec<_>>(), ["pre-setup"]);
+    assert_eq!(config.section_at(13), None);
+    assert_eq!(config.section_at(16), Some("pre-setup"));
+    assert_eq!(config.section_at(29), Some("pre-setup"));
+
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_keys() -> color_eyre::Result<()> {
+    let config = Config::from_str(
+        "rootfs: local-zfs:subvol-100-disk-0\n\
+         lxc.idmap: u 0 100000 65536\n\
+         lxc.idmap: g 0 100000 65536\n\
+         unprivileged: 0\n\
+         rootfs: local-zfs:subvol-100-disk-1\n\
+         unprivileged: 1\n\
+         \n\
+         [snap]\n\
+         rootfs: local-zfs:subvol-100-disk-0",
+    )?;
+
+    assert_eq!(
+        config.duplicate_keys(),
+        [("rootfs", vec![1, 5]), ("unprivileged", vec![4, 6])]
+    );
+    assert_eq!(config.line(5).as_deref(), Some("rootfs: local-zfs:subvol-100-disk-1"));
+    // The last value wins, like it does for PVE
+    assert_eq!(config.section(None).get_unprivileged(), Some("1"));
+
+    Ok(())
+}
+
+#[test]
+fn test_mount_point_parse() {
+    let mount = MountPoint::parse("mp0", "/tank/media,mp=/media,ro=1").expect("mount point");
+
+    assert_eq!(mount.volume, "/tank/media");
+    assert_eq!(mount.path.as_deref(), Some("/media"));
+    assert!(mount.is_bind_mount());
+
+    let mount = MountPoint::parse("mp12", "mp=/data,volume=local-zfs:subvol-100-disk-1").expect("mount point");
+
+    assert_eq!(mount.volume, "local-zfs:subvol-100-disk-1");
+    assert_eq!(mount.path.as_deref(), Some("/data"));
+    assert_eq!(
+        MountPoint::parse("mp1", r#"/srv/a,mp="/srv/b,c""#).and_then(|mount| mount.path),
+        Some("/srv/b,c".into())
+    );
+    assert!(!mount.is_bind_mount());
+    assert_eq!(MountPoint::parse("mp", "/tank"), None);
+    assert_eq!(MountPoint::parse("mpx", "/tank"), None);
+    assert_eq!(MountPoint::parse("rootfs", "/tank"), None);
+    assert_eq!(MountPoint::parse("mp0", "mp=/media"), None);
+}
+
+#[test]
+fn test_device_parse() {
+    let device = Device::parse("dev0", "/dev/dri/renderD128,gid=104,mode=0660").expect("device");
+
+    assert_eq!(device.path, "/dev/dri/renderD128");
+    assert_eq!((device.uid, device.gid), (0, 104));
+
+    let device = Device::parse("dev12", "path=/dev/ttyUSB0,uid=1000").expect("device");
+
+    assert_eq!(device.path, "/dev/ttyUSB0");
+    assert_eq!((device.uid, device.gid), (1000, 0));
+    assert_eq!(Device::parse("dev", "/dev/null"), None);
+    assert_eq!(Device::parse("devx", "/dev/null"), None);
+    assert_eq!(Device::parse("dev0", "gid=5"), None);
+}
+
+#[test]
+fn test_lxc_format() -> color_eyre::Result<()> {
+    let content = "# Template used to create this container: /usr/share/lxc/templates/lxc-download\n\
+                   lxc.include = /usr/share/lxc/config/common.conf\n\
+                   lxc.idmap = u 0 100000 65536\n\
+                   lxc.idmap = g 0 100000 65536\n\
+                   lxc.rootfs.path = dir:/var/lib/lxc/web/rootfs\n\
+                   lxc.net.0.hwaddr = 00:16:3e:4b:1a:2c";
+    let config = Config::from_str(content)?;
+    let section = config.section(None);
+
+    assert_eq!(config.format(), ConfigFormat::Lxc);
+    assert_eq!(section.get_rootfs(), Some("dir:/var/lib/lxc/web/rootfs"));
+    assert_eq!(section.get("lxc.net.0.hwaddr"), Some("00:16:3e:4b:1a:2c"));
+    assert_eq!(section.get_lxc_idmaps().count(), 2);
+    assert_eq!(config.line(3).as_deref(), Some("lxc.idmap = u 0 100000 65536"));
+    assert_eq!(config.to_string(), content);
+
+    // A PVE config with a hand written `lxc.*` line is still a PVE config
+    let config = Config::from_str("lxc.idmap = u 0 100000 65536\nnet0: name=eth0,hwaddr=AD:24:14:45:A8:38")?;
+
+    assert_eq!(config.format(), ConfigFormat::Proxmox);
+    assert_eq!(config.section(None).get_lxc_idmaps().count(), 1);
+    assert_eq!(config.line(1).as_deref(), Some("lxc.idmap: u 0 100000 65536"));
 
     Ok(())
 }
@@ -0,0 +1,360 @@
+//! `lxc.idmap` entries, which map a range of container ids onto host subordinate ids.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use compact_str::CompactString;
+use thiserror::Error;
+
+use crate::fs::subid::SubID;
+use crate::lxc::config::Config;
+use crate::lxc::{ID_SPACE_END, range_end};
+
+/// A parsed `lxc.idmap` value such as `u 0 100000 65536`. Parsing rejects ranges which are empty
+/// or don't fit in 32 bit ids, so later range math never has to deal with them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IdMap {
+    pub kind: SubID,
+    pub container_id: u32,
+    pub host_id: u32,
+    pub size: u32,
+}
+
+impl IdMap {
+    /// One past the last container id this maps.
+    pub fn container_end(&self) -> u64 {
+        range_end(self.container_id, self.size)
+    }
+
+    /// One past the last host id this maps to.
+    pub fn host_end(&self) -> u64 {
+        range_end(self.host_id, self.size)
+    }
+
+    /// Whether container id `id` is one this maps.
+    pub fn maps_container_id(&self, id: u32) -> bool {
+        self.container_id <= id && u64::from(id) < self.container_end()
+    }
+
+    /// Whether `id` falls within the host ids this maps to.
+    pub fn maps_host_id(&self, id: u32) -> bool {
+        self.host_id <= id && u64::from(id) < self.host_end()
+    }
+
+    /// Whether this hands a single host id other than root through to the same id in the
+    /// container, as the idmap wizard's passthrough does.
+    pub fn is_passthrough(&self) -> bool {
+        self.size == 1 && self.container_id == self.host_id && self.host_id != 0
+    }
+}
+
+impl fmt::Display for IdMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.kind.idmap_kind(),
+            self.container_id,
+            self.host_id,
+            self.size
+        )
+    }
+}
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum IdMapError {
+    #[error("expected 4 fields `u|g <container id> <host id> <count>`, found {0}")]
+    FieldCount(usize),
+    #[error("unknown id kind {0:?}, expected u or g")]
+    Kind(String),
+    #[error("{field} {value:?} is not a number between 0 and 4294967295")]
+    Number { field: &'static str, value: String },
+    #[error("the range maps zero ids")]
+    Empty,
+    #[error("the {0} range extends past the largest 32 bit id")]
+    Overflow(&'static str),
+}
+
+impl FromStr for IdMap {
+    type Err = IdMapError;
+
+    fn from_str(value: &str) -> Result<Self, IdMapError> {
+        let fields: Vec<_> = value.split_whitespace().collect();
+        let [kind, container_id, host_id, size] = fields[..] else {
+            return Err(IdMapError::FieldCount(fields.len()));
+        };
+        let kind = idmap_sub_id(kind).ok_or_else(|| IdMapError::Kind(kind.to_string()))?;
+        let parse = |field: &'static str, value: &str| {
+            value.parse::<u32>().map_err(|_| IdMapError::Number {
+                field,
+                value: value.to_string(),
+            })
+        };
+        let idmap = IdMap {
+            kind,
+            container_id: parse("container id", container_id)?,
+            host_id: parse("host id", host_id)?,
+            size: parse("count", size)?,
+        };
+
+        if idmap.size == 0 {
+            return Err(IdMapError::Empty);
+        }
+
+        if idmap.container_end() > ID_SPACE_END {
+            return Err(IdMapError::Overflow("container id"));
+        }
+
+        if idmap.host_end() > ID_SPACE_END {
+            return Err(IdMapError::Overflow("host id"));
+        }
+
+        Ok(idmap)
+    }
+}
+
+/// The kind an `lxc.idmap` value maps, as far as can be told from its first field. Works for values
+/// which otherwise fail to parse.
+pub fn idmap_sub_id(value: &str) -> Option<SubID> {
+    match value.split_whitespace().next() {
+        Some("u") => Some(SubID::UID),
+        Some("g") => Some(SubID::GID),
+        _ => None,
+    }
+}
+
+/// An `lxc.idmap` entry of a container config, parsed once per version of the config.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigIdMap {
+    /// The value as written.
+    pub value: CompactString,
+    /// The line in the config, or `None` when it came from an `lxc.include` file.
+    pub line: Option<usize>,
+    /// The included file it came from.
+    pub include: Option<PathBuf>,
+    pub parsed: Result<IdMap, IdMapError>,
+}
+
+impl ConfigIdMap {
+    /// The kind this maps, even when the rest of the value is malformed.
+    pub fn kind(&self) -> Option<SubID> {
+        match &self.parsed {
+            Ok(idmap) => Some(idmap.kind),
+            Err(_) => idmap_sub_id(&self.value),
+        }
+    }
+}
+
+/// Parses the idmaps of a config's top level section, including those from `lxc.include` files.
+pub fn config_idmaps(config: &Config) -> Vec<ConfigIdMap> {
+    // Taken by line rather than looked up by value, so repeated values each keep their own line
+    let own = config
+        .section_entries(None)
+        .filter(|(_, key, _)| *key == "lxc.idmap")
+        .map(|(line, _, value)| (Some(line), None, value));
+    let included = config
+        .section(None)
+        .get_lxc_idmaps_with_origin()
+        .filter_map(|(origin, value)| origin.map(|origin| (None, Some(origin), value)));
+
+    own.chain(included)
+        .map(|(line, include, value)| ConfigIdMap {
+            value: value.into(),
+            line,
+            include: include.map(Into::into),
+            parsed: value.parse(),
+        })
+        .collect()
+}
+
+/// The container ids an unprivileged container needs mapped, since distributions allocate users
+/// and groups below 65536.
+pub const CONTAINER_ID_SPACE_END: u64 = 1 << 16;
+
+/// How a container's ids are covered by its idmaps of one kind.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IdMapCoverage {
+    /// Container ids `start..end` aren't mapped by any idmap.
+    Gap { start: u64, end: u64 },
+    /// Container ids `start..end` are mapped by both idmaps, given as indices.
+    Overlap {
+        start: u64,
+        end: u64,
+        first: usize,
+        second: usize,
+    },
+}
+
+impl fmt::Display for IdMapCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, end) = match self {
+            IdMapCoverage::Gap { start, end } | IdMapCoverage::Overlap { start, end, .. } => (start, end),
+        };
+        let ids = if end - start == 1 {
+            format!("container id {start}")
+        } else {
+            format!("container ids {start}-{}", end - 1)
+        };
+
+        match self {
+            IdMapCoverage::Gap { .. } => write!(f, "{ids} not mapped"),
+            IdMapCoverage::Overlap { .. } => write!(f, "{ids} mapped twice"),
+        }
+    }
+}
+
+/// Finds container ids below [`CONTAINER_ID_SPACE_END`] which `idmaps` leave unmapped, and container
+/// ids mapped by more than one of them. `idmaps` should all be of the same kind.
+pub fn idmap_coverage(idmaps: &[IdMap]) -> Vec<IdMapCoverage> {
+    let container_range = |idmap: &IdMap| (u64::from(idmap.container_id), idmap.container_end());
+    let mut issues = Vec::new();
+
+    for (first, a) in idmaps.iter().enumerate() {
+        for (second, b) in idmaps.iter().enumerate().skip(first + 1) {
+            let (a_start, a_end) = container_range(a);
+            let (b_start, b_end) = container_range(b);
+            let (start, end) = (a_start.max(b_start), a_end.min(b_end));
+
+            if start < end {
+                issues.push(IdMapCoverage::Overlap {
+                    start,
+                    end,
+                    first,
+                    second,
+                });
+            }
+        }
+    }
+
+    let mut ranges: Vec<_> = idmaps.iter().map(container_range).collect();
+    let mut covered_until = 0;
+
+    ranges.sort_unstable();
+
+    for (start, end) in ranges
+        .into_iter()
+        .chain([(CONTAINER_ID_SPACE_END, CONTAINER_ID_SPACE_END)])
+    {
+        if start > covered_until && covered_until < CONTAINER_ID_SPACE_END {
+            issues.push(IdMapCoverage::Gap {
+                start: covered_until,
+                end: start.min(CONTAINER_ID_SPACE_END),
+            });
+        }
+
+        covered_until = covered_until.max(end);
+    }
+
+    issues
+}
+
+#[test]
+fn test_parse_idmap() {
+    assert_eq!(
+        "u 0 100000 65536".parse(),
+        Ok(IdMap {
+            kind: SubID::UID,
+            container_id: 0,
+            host_id: 100000,
+            size: 65536,
+        })
+    );
+    // Extra whitespace is fine, LXC splits on any amount of it
+    assert!(" g  0 100000\t65536 ".parse::<IdMap>().is_ok());
+    assert_eq!("u 0 100000 0".parse::<IdMap>(), Err(IdMapError::Empty));
+    assert_eq!(
+        "u 0 4294967295 2".parse::<IdMap>(),
+        Err(IdMapError::Overflow("host id"))
+    );
+    assert_eq!(
+        "u 4294967295 100000 2".parse::<IdMap>(),
+        Err(IdMapError::Overflow("container id"))
+    );
+    // Ending exactly at the last id is allowed
+    assert!("u 0 4294901760 65536".parse::<IdMap>().is_ok());
+    assert_eq!("u 0 100000".parse::<IdMap>(), Err(IdMapError::FieldCount(3)));
+    assert_eq!("x 0 100000 65536".parse::<IdMap>(), Err(IdMapError::Kind("x".into())));
+
+    let err = "u 0 -1 65536".parse::<IdMap>().unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "host id \"-1\" is not a number between 0 and 4294967295"
+    );
+    assert_eq!(idmap_sub_id("g 0 x"), Some(SubID::GID));
+    assert_eq!(
+        " g  0 100000\t65536 ".parse::<IdMap>().map(|idmap| idmap.to_string()),
+        Ok("g 0 100000 65536".to_string())
+    );
+}
+
+#[test]
+fn test_config_idmaps() -> color_eyre::Result<()> {
+    let config: Config = "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 x\n".parse()?;
+    let idmaps = config_idmaps(&config);
+
+    assert_eq!(idmaps.len(), 2);
+    assert_eq!(idmaps[0].line, Some(2));
+    assert_eq!(idmaps[0].parsed.as_ref().map(|idmap| idmap.host_id), Ok(100000));
+    assert_eq!(idmaps[1].kind(), Some(SubID::GID));
+    assert_eq!(idmaps[1].parsed, Err(IdMapError::FieldCount(3)));
+
+    Ok(())
+}
+
+#[test]
+fn test_repeated_idmap_lines() -> color_eyre::Result<()> {
+    let mut config: Config = "lxc.idmap: u 0 100000 65536\nlxc.idmap: u 0 100000 65536\n".parse()?;
+    let lines: Vec<_> = config.idmaps().iter().map(|idmap| idmap.line).collect();
+
+    assert_eq!(lines, [Some(1), Some(2)]);
+
+    // Edits replace the parsed idmaps
+    config.section_mut(None).remove_all("lxc.idmap");
+
+    assert!(config.idmaps().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_idmap_coverage() {
+    let parse = |values: &[&str]| values.iter().map(|v| v.parse::<IdMap>().unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(idmap_coverage(&parse(&["u 0 100000 65536"])), []);
+    assert_eq!(
+        idmap_coverage(&parse(&["u 0 100000 1000", "u 1000 1000 1", "u 1001 101001 64535"])),
+        []
+    );
+
+    let issues = idmap_coverage(&parse(&["u 1001 101001 64535", "u 0 100000 1000"]));
+
+    assert_eq!(issues, [IdMapCoverage::Gap { start: 1000, end: 1001 }]);
+    assert_eq!(issues[0].to_string(), "container id 1000 not mapped");
+
+    let issues = idmap_coverage(&parse(&["u 0 100000 2000", "u 1000 1000 1", "u 1001 101001 60000"]));
+
+    assert_eq!(
+        issues,
+        [
+            IdMapCoverage::Overlap {
+                start: 1000,
+                end: 1001,
+                first: 0,
+                second: 1
+            },
+            IdMapCoverage::Overlap {
+                start: 1001,
+                end: 2000,
+                first: 0,
+                second: 2
+            },
+            IdMapCoverage::Gap {
+                start: 61001,
+                end: 65536
+            },
+        ]
+    );
+    assert_eq!(issues[2].to_string(), "container ids 61001-65535 not mapped");
+}
@@ -0,0 +1,227 @@
+//! Resolution of `lxc.include` entries.
+//!
+//! Vanilla LXC configs (and some hand-written PVE configs) pull in shared fragments which may
+//! themselves contain idmap lines. Included files are parsed and attached to the including
+//! [`Config`] so that lookups on the top level section see the effective, merged values.
+
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use ahash::HashSet;
+use log::warn;
+
+use super::config::Config;
+
+pub const LXC_INCLUDE: &str = "lxc.include";
+
+/// Includes nested deeper than this are ignored, even if no cycle was detected.
+const MAX_INCLUDE_DEPTH: usize = 16;
+/// LXC opens include paths as written, so relative ones are relative to the working directory of
+/// `lxc-start` rather than to the including file. PVE starts containers from systemd, which runs
+/// them from `/`.
+const LXC_WORKING_DIR: &str = "/";
+
+/// A config fragment pulled in through `lxc.include`.
+#[derive(Clone, Debug)]
+pub struct Include {
+    /// The included file.
+    pub path: PathBuf,
+    /// The file which contained the `lxc.include` line.
+    pub included_from: PathBuf,
+    pub config: Config,
+}
+
+impl Config {
+    /// Follows all `lxc.include` entries of the top level section, recursively.
+    ///
+    /// `origin` is the path this config was read from, which is used to detect include cycles.
+    /// Include paths are taken relative to `root_prefix` when the config was copied from another
+    /// host. Any previously resolved includes are replaced.
+    pub fn resolve_includes(&mut self, origin: &Path, root_prefix: Option<&Path>) {
+        let mut visited = HashSet::default();
+        let mut includes = Vec::new();
+
+        visited.insert(canonical(origin));
+
+        collect_includes(self, origin, root_prefix, &mut visited, &mut includes, 0);
+
+        self.includes = includes;
+        self.idmaps.take();
+    }
+
+    /// All config fragments included by this config, in resolution order.
+    pub fn includes(&self) -> &[Include] {
+        &self.includes
+    }
+}
+
+fn collect_includes(
+    config: &Config,
+    origin: &Path,
+    root_prefix: Option<&Path>,
+    visited: &mut HashSet<PathBuf>,
+    includes: &mut Vec<Include>,
+    depth: usize,
+) {
+    if depth >= MAX_INCLUDE_DEPTH {
+        warn!("Maximum lxc.include depth reached in {}", origin.display());
+        return;
+    }
+
+    for value in config.section(None).own_values(LXC_INCLUDE) {
+        let path = Path::new(LXC_WORKING_DIR).join(value);
+        let path = match root_prefix {
+            Some(root_prefix) => root_prefix.join(path.strip_prefix(LXC_WORKING_DIR).unwrap_or(&path)),
+            None => path,
+        };
+
+        // LXC includes every *.conf file when pointed at a directory
+        let paths = if path.is_dir() {
+            match read_dir(&path) {
+                Ok(dir) => {
+                    let mut paths: Vec<_> = dir
+                        .filter_map(|entry| entry.ok().map(|e| e.path()))
+                        .filter(|p| p.extension().is_some_and(|ext| ext == "conf"))
+                        .collect();
+
+                    paths.sort();
+                    paths
+                },
+                Err(err) => {
+                    warn!("Failed to read lxc.include directory {}: {err}", path.display());
+                    continue;
+                },
+            }
+        } else {
+            vec![path]
+        };
+
+        for path in paths {
+            if !visited.insert(canonical(&path)) {
+                warn!(
+                    "Skipping cyclic lxc.include of {} from {}",
+                    path.display(),
+                    origin.display()
+                );
+                continue;
+            }
+
+            let content = match read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => {
+                    warn!("Failed to read lxc.include {}: {err}", path.display());
+                    continue;
+                },
+            };
+            let included = match Config::from_str(&content) {
+                Ok(config) => config,
+                Err(err) => {
+                    warn!("Failed to parse lxc.include {}: {err}", path.display());
+                    continue;
+                },
+            };
+
+            includes.push(Include {
+                path: path.clone(),
+                included_from: origin.to_path_buf(),
+                config: included.clone(),
+            });
+
+            collect_includes(&included, &path, root_prefix, visited, includes, depth + 1);
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[test]
+fn test_resolve_includes() -> color_eyre::Result<()> {
+    use std::fs::write;
+
+    let dir = tempfile::tempdir()?;
+    let common = dir.path().join("common.conf");
+    let cyclic = dir.path().join("cyclic.conf");
+    let container = dir.path().join("100.conf");
+
+    write(
+        &common,
+        format!("lxc.idmap = u 0 100000 65536\nlxc.include = {}", cyclic.display()),
+    )?;
+    write(
+        &cyclic,
+        format!("lxc.idmap = g 0 100000 65536\nlxc.include = {}", common.display()),
+    )?;
+    write(
+        &container,
+        format!("unprivileged: 1\nlxc.include: {}", common.display()),
+    )?;
+
+    let mut config = Config::from_str(&std::fs::read_to_string(&container)?)?;
+
+    config.resolve_includes(&container, None);
+
+    assert_eq!(config.includes().len(), 2);
+    assert_eq!(config.includes()[0].path, common);
+    assert_eq!(config.includes()[0].included_from, container);
+    assert_eq!(config.includes()[1].path, cyclic);
+    assert_eq!(config.includes()[1].included_from, common);
+
+    let section = config.section(None);
+    let idmaps: Vec<_> = section.get_lxc_idmaps().collect();
+
+    assert_eq!(idmaps, ["u 0 100000 65536", "g 0 100000 65536"]);
+    assert!(section.has_lxc_idmap());
+    assert_eq!(
+        section.get_lxc_idmaps_with_origin().next().unwrap().0,
+        Some(common.as_path())
+    );
+
+    // Includes are not part of the serialized config
+    assert!(!config.to_string().contains("idmap"));
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve_relative_include() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let container = dir.path().join("100.conf");
+
+    std::fs::write(dir.path().join("common.conf"), "lxc.idmap = u 0 100000 65536")?;
+
+    let mut config = Config::from_str("lxc.include: common.conf")?;
+
+    config.resolve_includes(&container, None);
+
+    // LXC doesn't look next to the including file
+    assert!(config.includes().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve_prefixed_include() -> color_eyre::Result<()> {
+    let root_prefix = tempfile::tempdir()?;
+    let common = root_prefix.path().join("usr/share/lxc/config/common.conf");
+    let container = root_prefix.path().join("var/lib/lxc/100/config");
+
+    std::fs::create_dir_all(common.parent().unwrap_or(root_prefix.path()))?;
+    std::fs::write(&common, "lxc.idmap = u 0 100000 65536")?;
+
+    let mut config = Config::from_str("lxc.include = /usr/share/lxc/config/common.conf")?;
+
+    config.resolve_includes(&container, Some(root_prefix.path()));
+
+    // The copied file is read rather than the one of the host running pupman
+    assert_eq!(config.includes().len(), 1);
+    assert_eq!(config.includes()[0].path, common);
+    assert_eq!(
+        config.section(None).get_lxc_idmaps().collect::<Vec<_>>(),
+        ["u 0 100000 65536"]
+    );
+
+    Ok(())
+}
@@ -1,16 +1,19 @@
 pub mod config;
+pub mod idmap;
+pub mod include;
 pub mod section;
 pub mod section_mut;
 
-use crate::linux::zfs_volume_to_mountpoint;
+use crate::linux::zfs::ZfsCache;
+use crate::proxmox::property_string::{self, PropertyString};
+use crate::proxmox::storage::{StorageConfig, VolumePath};
 
-use color_eyre::eyre::ContextCompat;
-use color_eyre::eyre::eyre;
+use color_eyre::eyre::{ContextCompat, WrapErr};
 
 use std::path::PathBuf;
 
 #[cfg(test)]
-const SAMPLE_CONFIG: &'static str = r#"arch: amd64
+const SAMPLE_CONFIG: &str = r#"arch: amd64
 cores: 1
 features: nesting=1
 hostname: trash-pandas
@@ -40,27 +43,87 @@ unprivileged: 1
 lxc.idmap: u 0 1000 3000
 lxc.idmap: g 0 1000 3000"#;
 
-pub fn rootfs_value_to_path(value: &str) -> color_eyre::Result<PathBuf> {
-    let (storage_id, volume_id) = parse_rootfs_value(value).wrap_err("invalid rootfs value")?;
-
-    match storage_id {
-        "local-zfs" => {
-            let Some(path) = zfs_volume_to_mountpoint(volume_id)? else {
-                return Err(eyre!("failed to find zfs mountpoint for {volume_id}"));
-            };
-            Ok(path)
-        },
-        _ => Err(eyre!("unsupported storage id {storage_id}")),
+/// The largest id plus one. Ranges may end here, but not past it.
+pub const ID_SPACE_END: u64 = 1 << 32;
+
+/// One past the last id of a range, without overflowing.
+pub fn range_end(start: u32, count: u32) -> u64 {
+    u64::from(start) + u64::from(count)
+}
+
+/// Where a container's rootfs lives, from the storage volume down to the host directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RootfsLocation {
+    /// The storage the volume belongs to, e.g. `local-zfs`. Empty for absolute paths.
+    pub storage_id: String,
+    /// The volume within that storage, e.g. `subvol-100-disk-0`.
+    pub volume_id: String,
+    /// The backing ZFS dataset, e.g. `rpool/data/subvol-100-disk-0`.
+    pub dataset: Option<String>,
+    /// Where the rootfs is mounted on the host.
+    pub mountpoint: PathBuf,
+}
+
+impl RootfsLocation {
+    /// Moves the mountpoint to where ZFS mounted the dataset, when that isn't where storage.cfg
+    /// implies.
+    pub fn remount(mut self, zfs: &mut ZfsCache) -> Self {
+        if let Some(mountpoint) = self.dataset.as_deref().and_then(|dataset| zfs.mountpoint(dataset)) {
+            self.mountpoint = mountpoint;
+        }
+
+        self
+    }
+}
+
+/// The volume of a `rootfs` or `mpN` config value without its options, e.g. `local-zfs:subvol-100-disk-0`
+/// or a host directory.
+pub fn rootfs_volume(value: &str) -> color_eyre::Result<String> {
+    let properties = PropertyString::parse(value).wrap_err("invalid rootfs value")?;
+    let volume = properties
+        .get("volume")
+        .or_else(|| properties.positional())
+        .wrap_err("rootfs value has no volume")?;
+    // Upstream LXC prefixes directories with their storage backend, `dir:/var/lib/lxc/web/rootfs`
+    let volume = volume
+        .strip_prefix("dir:")
+        .filter(|path| path.starts_with('/'))
+        .unwrap_or(volume);
+
+    Ok(volume.to_string())
+}
+
+/// Resolves a `rootfs` or `mpN` config value to where it lives on the host.
+pub fn resolve_rootfs(value: &str, storage: &StorageConfig) -> color_eyre::Result<RootfsLocation> {
+    let volume = rootfs_volume(value)?;
+    let volume = volume.as_str();
+
+    // Bind mounted host directories are used as is
+    if volume.starts_with('/') {
+        return Ok(RootfsLocation {
+            storage_id: String::new(),
+            volume_id: volume.to_string(),
+            dataset: None,
+            mountpoint: PathBuf::from(volume),
+        });
     }
+
+    let (storage_id, volume_id) = parse_rootfs_value(volume).wrap_err("invalid rootfs value")?;
+    let VolumePath { dataset, mountpoint } = storage.resolve_volume(storage_id, volume_id)?;
+
+    Ok(RootfsLocation {
+        storage_id: storage_id.to_string(),
+        volume_id: volume_id.to_string(),
+        dataset,
+        mountpoint,
+    })
 }
 
 fn parse_rootfs_value(value: &str) -> Option<(&str, &str)> {
-    let mut iter = value.split(':');
-    let storage_id = iter.next()?;
-    let rest = iter.next()?;
-    let volume_id = rest.split(',').next()?;
+    let volume = *property_string::split(value).ok()?.first()?;
+    let volume = volume.strip_prefix("volume=").unwrap_or(volume);
 
-    Some((storage_id, volume_id))
+    volume.split_once(':')
 }
 
 #[test]
@@ -75,3 +138,34 @@ fn test_parse_rootfs_value() {
     );
     assert_eq!(parse_rootfs_value("local-zfs"), None);
 }
+
+#[test]
+fn test_resolve_rootfs() -> color_eyre::Result<()> {
+    let storage = StorageConfig::default();
+    let location = resolve_rootfs("local:100/vm-100-disk-0.raw,size=8G", &storage)?;
+
+    assert_eq!(location.storage_id, "local");
+    assert_eq!(location.volume_id, "100/vm-100-disk-0.raw");
+    assert_eq!(location.dataset, None);
+
+    let location = resolve_rootfs("local-zfs:subvol-101-disk-0,size=8G", &storage)?;
+
+    assert_eq!(location.dataset.as_deref(), Some("rpool/data/subvol-101-disk-0"));
+    assert_eq!(location.mountpoint, PathBuf::from("/rpool/data/subvol-101-disk-0"));
+
+    let location = resolve_rootfs("/mnt/containers/100,size=0T", &storage)?;
+
+    assert_eq!(location.mountpoint, PathBuf::from("/mnt/containers/100"));
+    assert!(location.storage_id.is_empty());
+
+    let location = resolve_rootfs("volume=/tank/media,mp=/media", &storage)?;
+
+    assert_eq!(location.mountpoint, PathBuf::from("/tank/media"));
+
+    let location = resolve_rootfs("dir:/var/lib/lxc/web/rootfs", &storage)?;
+
+    assert_eq!(location.mountpoint, PathBuf::from("/var/lib/lxc/web/rootfs"));
+    assert!(resolve_rootfs("ceph:vm-102-disk-0", &storage).is_err());
+
+    Ok(())
+}
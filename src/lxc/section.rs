@@ -1,6 +1,9 @@
+use std::path::Path;
+
+use ahash::HashSet;
 use compact_str::CompactString;
 
-use crate::lxc::config::Config;
+use crate::lxc::config::{Config, ConfigFormat, Device, MountPoint};
 
 #[derive(Clone, Copy, Debug)]
 pub struct SectionView<'s, 'c> {
@@ -8,20 +11,24 @@ pub struct SectionView<'s, 'c> {
     pub(super) section: Option<&'s str>,
 }
 
-impl<'c> SectionView<'_, 'c> {
+impl<'s, 'c> SectionView<'s, 'c> {
+    /// The value of a single valued key. Like PVE and LXC, a later value overrides an earlier one
+    /// when the key is set more than once.
     pub fn get(&self, key: &str) -> Option<&'c str> {
-        let section = self.section.map(CompactString::new);
-        let key = CompactString::new(key);
+        self.get_all(key).last()
+    }
 
-        self.config
-            .index
-            .get(&(section, key))
-            .and_then(|vals| vals.first().map(|s| s.as_str()))
+    pub fn format(&self) -> ConfigFormat {
+        self.config.format
     }
 
+    /// PVE's `rootfs`, or `lxc.rootfs.path` in an upstream LXC config.
     #[inline]
     pub fn get_rootfs(&self) -> Option<&'c str> {
-        self.get("rootfs")
+        match self.config.format {
+            ConfigFormat::Proxmox => self.get("rootfs"),
+            ConfigFormat::Lxc => self.get("lxc.rootfs.path"),
+        }
     }
 
     #[inline]
@@ -29,7 +36,35 @@ impl<'c> SectionView<'_, 'c> {
         self.get("unprivileged")
     }
 
-    pub fn get_all(&self, key: &str) -> impl Iterator<Item = &'c str> {
+    /// All values of `key` in this section, followed by values from `lxc.include` fragments when
+    /// this is the top level section.
+    pub fn get_all(&self, key: &str) -> impl Iterator<Item = &'c str> + use<'c> {
+        self.get_all_with_origin(key).map(|(_, value)| value)
+    }
+
+    /// Like [`SectionView::get_all`], but also yields the included file each value came from, or
+    /// `None` for values from the config itself.
+    pub fn get_all_with_origin(&self, key: &str) -> impl Iterator<Item = (Option<&'c Path>, &'c str)> + use<'c> {
+        let own = self.own_values(key).map(|value| (None, value));
+        let key = CompactString::new(key);
+        let includes = if self.section.is_none() {
+            &*self.config.includes
+        } else {
+            &[]
+        };
+        let included = includes.iter().flat_map(move |include| {
+            include
+                .config
+                .section(None)
+                .own_values(&key)
+                .map(|value| (Some(include.path.as_path()), value))
+        });
+
+        own.chain(included)
+    }
+
+    /// Values of `key` from this config only, ignoring includes.
+    pub(super) fn own_values(&self, key: &str) -> impl Iterator<Item = &'c str> + use<'c> {
         let section = self.section.map(CompactString::new);
         let key = CompactString::new(key);
 
@@ -42,15 +77,30 @@ impl<'c> SectionView<'_, 'c> {
     }
 
     #[inline]
-    pub fn get_lxc_idmaps(&self) -> impl Iterator<Item = &'c str> {
+    pub fn get_lxc_idmaps(&self) -> impl Iterator<Item = &'c str> + use<'c> {
         self.get_all("lxc.idmap")
     }
 
+    /// The `mpN` mount points of this section in file order. Includes are ignored, PVE only reads
+    /// mount points from the config itself.
+    pub fn mount_points(&self) -> impl Iterator<Item = MountPoint<'c>> + use<'s, 'c> {
+        self.key_values()
+            .filter_map(|(key, value)| MountPoint::parse(key, value))
+    }
+
+    /// The `devN` devices passed through to this section in file order. Like mount points, they
+    /// are only read from the config itself.
+    pub fn devices(&self) -> impl Iterator<Item = Device<'c>> + use<'s, 'c> {
+        self.key_values().filter_map(|(key, value)| Device::parse(key, value))
+    }
+
     pub fn has_key(&self, key: &str) -> bool {
-        let section = self.section.map(CompactString::new);
-        let key = CompactString::new(key);
+        self.get_all(key).next().is_some()
+    }
 
-        self.config.index.contains_key(&(section, key))
+    #[inline]
+    pub fn get_lxc_idmaps_with_origin(&self) -> impl Iterator<Item = (Option<&'c Path>, &'c str)> + use<'c> {
+        self.get_all_with_origin("lxc.idmap")
     }
 
     #[inline]
@@ -58,14 +108,22 @@ impl<'c> SectionView<'_, 'c> {
         self.has_key("lxc.idmap")
     }
 
-    pub fn keys(&self) -> impl Iterator<Item = &str> {
-        self.config.index.keys().filter_map(move |(section, key)| {
-            if section.as_deref() == self.section {
-                Some(key.as_str())
-            } else {
-                None
-            }
-        })
+    /// Every key set in this section, each once and in the order they first appear in the file.
+    /// Includes are ignored.
+    pub fn keys(&self) -> impl Iterator<Item = &'c str> + use<'s, 'c> {
+        let mut seen = HashSet::default();
+
+        self.key_values()
+            .map(|(key, _)| key)
+            .filter(move |key| seen.insert(*key))
+    }
+
+    /// Every key and value set in this section in file order, including repeated keys. Includes are
+    /// ignored.
+    pub fn key_values(&self) -> impl Iterator<Item = (&'c str, &'c str)> + use<'s, 'c> {
+        self.config
+            .section_entries(self.section)
+            .map(|(_, key, value)| (key, value))
     }
 }
 
@@ -86,6 +144,8 @@ fn test_section_section_view() -> color_eyre::Result<()> {
     let keys: Vec<_> = section.keys().collect();
 
     assert_eq!(keys.len(), 13);
+    assert_eq!(keys[..3], ["arch", "cores", "features"]);
+    assert_eq!(keys.last(), Some(&"lxc.idmap"));
     assert!(keys.contains(&"arch"));
     assert!(keys.contains(&"cores"));
     assert!(keys.contains(&"features"));
@@ -104,5 +164,14 @@ fn test_section_section_view() -> color_eyre::Result<()> {
 
     assert_eq!(pre_setup.get("snaptime"), Some("1764532648"));
 
+    let idmaps: Vec<_> = pre_setup
+        .key_values()
+        .filter(|(key, _)| *key == "lxc.idmap")
+        .map(|(_, value)| value)
+        .collect();
+
+    assert_eq!(idmaps, ["u 0 1000 3000", "g 0 1000 3000"]);
+    assert_eq!(pre_setup.key_values().next(), Some(("arch", "amd64")));
+
     Ok(())
 }
@@ -10,24 +10,31 @@ pub struct SectionViewMut<'s, 'c> {
 
 impl SectionViewMut<'_, '_> {
     pub fn set(&mut self, key: &str, value: &str) {
-        self.remove_all(key);
-        self.append(key, value);
+        self.replace_all(key, &[value]);
     }
 
+    /// Appends a value for `key`. Values for the same key are kept together, so a new idmap ends up
+    /// right after the existing ones rather than at the end of the section.
     pub fn append(&mut self, key: &str, value: &str) {
-        let key = CompactString::new(key);
-        let value = CompactString::new(value);
-        let section_key = (self.section.map(CompactString::new), key.clone());
+        let insert_index = self.find_append_point(key);
 
-        // Update index
-        self.config.index.entry(section_key).or_default().push(value.clone());
+        self.insert(insert_index, key, value);
+    }
 
-        // Insert into entries
-        let insert_index = self.find_append_point();
+    /// Replaces every value of `key` with `values`. The new values take the place of the first old
+    /// one, so comments kept above or next to them stay where they were.
+    pub fn replace_all(&mut self, key: &str, values: &[&str]) {
+        let (start, end) = self.span();
+        let first_index =
+            (start..end).find(|&i| matches!(&self.config.entries[i], ConfEntry::KeyValue(k, _) if k == key));
 
-        self.config
-            .entries
-            .insert(insert_index, ConfEntry::KeyValue(key, value));
+        self.remove_all(key);
+
+        let insert_index = first_index.unwrap_or_else(|| self.find_append_point(key));
+
+        for (i, value) in values.iter().enumerate() {
+            self.insert(insert_index + i, key, value);
+        }
     }
 
     pub fn remove_all(&mut self, key: &str) {
@@ -48,25 +55,185 @@ impl SectionViewMut<'_, '_> {
         });
     }
 
-    fn find_append_point(&self) -> usize {
-        let mut in_section = self.section.is_none();
-        let mut last_match_index = None;
+    fn insert(&mut self, index: usize, key: &str, value: &str) {
+        let key = CompactString::new(key);
+        let value = CompactString::new(value);
+        let section_key = (self.section.map(CompactString::new), key.clone());
 
-        for (i, entry) in self.config.entries.iter().enumerate() {
-            match entry {
-                ConfEntry::Section(sec) => {
-                    in_section = self.section == Some(sec.as_str());
-                },
-                ConfEntry::KeyValue(_, _) if in_section => {
-                    last_match_index = Some(i);
+        self.config.index.entry(section_key).or_default().push(value.clone());
+        self.config.entries.insert(index, ConfEntry::KeyValue(key, value));
+    }
+
+    /// The range of entries in this section, excluding its header. A section which doesn't exist yet
+    /// is added to the end of the config.
+    fn span(&mut self) -> (usize, usize) {
+        let entries = &self.config.entries;
+        let start = match self.section {
+            None => 0,
+            Some(section) => match entries
+                .iter()
+                .position(|entry| matches!(entry, ConfEntry::Section(sec) if sec == section))
+            {
+                Some(i) => i + 1,
+                None => {
+                    if !entries.is_empty() {
+                        self.config.entries.push(ConfEntry::EmptyLine);
+                    }
+
+                    self.config.entries.push(ConfEntry::Section(section.into()));
+
+                    return (self.config.entries.len(), self.config.entries.len());
                 },
-                _ => {},
+            },
+        };
+        let end = entries[start..]
+            .iter()
+            .position(|entry| matches!(entry, ConfEntry::Section(_)))
+            .map_or(entries.len(), |i| start + i);
+
+        (start, end)
+    }
+
+    /// Where a new value for `key` goes: after the last value of the same key, otherwise after the
+    /// last key of the section. A section without keys gets it after its own comments, but before
+    /// blank lines and any comment block attached to the next section's header.
+    fn find_append_point(&mut self, key: &str) -> usize {
+        let (start, end) = self.span();
+        let entries = &self.config.entries[start..end];
+        let last_same_key = entries
+            .iter()
+            .rposition(|entry| matches!(entry, ConfEntry::KeyValue(k, _) if k == key));
+        let last_key = entries
+            .iter()
+            .rposition(|entry| matches!(entry, ConfEntry::KeyValue(..)));
+
+        if let Some(i) = last_same_key.or(last_key) {
+            return start + i + 1;
+        }
+
+        let mut index = end;
+
+        if end < self.config.entries.len() {
+            let mut block_start = end;
+
+            while block_start > start && matches!(self.config.entries[block_start - 1], ConfEntry::Comment(_)) {
+                block_start -= 1;
+            }
+
+            // Comments directly above the next header describe that section, unless nothing separates
+            // them from the start of this one
+            if block_start > start {
+                index = block_start;
             }
         }
 
-        match last_match_index {
-            Some(i) => i + 1,
-            None => self.config.entries.len(),
+        while index > start && matches!(self.config.entries[index - 1], ConfEntry::EmptyLine) {
+            index -= 1;
         }
+
+        index
     }
 }
+
+#[test]
+fn test_append_keeps_idmaps_grouped() -> color_eyre::Result<()> {
+    let mut config: Config = "arch: amd64\n\
+        # uid/gid mapping for the media share\n\
+        lxc.idmap: u 0 100000 1000\n\
+        lxc.idmap: u 1000 1000 1\n\
+        unprivileged: 1\n\
+        \n\
+        # taken before the upgrade\n\
+        [pre-upgrade]\n\
+        arch: amd64"
+        .parse()?;
+
+    config.section_mut(None).append("lxc.idmap", "u 1001 101001 64535");
+
+    assert_eq!(
+        config.to_string(),
+        "arch: amd64\n\
+         # uid/gid mapping for the media share\n\
+         lxc.idmap: u 0 100000 1000\n\
+         lxc.idmap: u 1000 1000 1\n\
+         lxc.idmap: u 1001 101001 64535\n\
+         unprivileged: 1\n\
+         \n\
+         # taken before the upgrade\n\
+         [pre-upgrade]\n\
+         arch: amd64"
+    );
+
+    config.section_mut(None).append("swap", "512");
+
+    assert!(
+        config
+            .to_string()
+            .contains("unprivileged: 1\nswap: 512\n\n# taken before the upgrade")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_replace_all_keeps_position_under_comment() -> color_eyre::Result<()> {
+    let mut config: Config = "# mapped by hand, see wiki\n\
+        lxc.idmap: u 0 1000 3000\n\
+        lxc.idmap: g 0 1000 3000\n\
+        # keep this last\n\
+        unprivileged: 1"
+        .parse()?;
+
+    config
+        .section_mut(None)
+        .replace_all("lxc.idmap", &["u 0 100000 65536", "g 0 100000 65536"]);
+
+    assert_eq!(
+        config.to_string(),
+        "# mapped by hand, see wiki\n\
+         lxc.idmap: u 0 100000 65536\n\
+         lxc.idmap: g 0 100000 65536\n\
+         # keep this last\n\
+         unprivileged: 1"
+    );
+    assert_eq!(
+        config.section(None).get_lxc_idmaps().collect::<Vec<_>>(),
+        ["u 0 100000 65536", "g 0 100000 65536"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_append_to_section_without_keys() -> color_eyre::Result<()> {
+    // The description is the root section's own comment, the second block belongs to the snapshot
+    let mut config: Config = "#Media server\n\
+        \n\
+        # snapshot before the upgrade\n\
+        [pre-upgrade]\n\
+        arch: amd64"
+        .parse()?;
+
+    config.section_mut(None).append("unprivileged", "1");
+
+    assert_eq!(
+        config.to_string(),
+        "#Media server\n\
+         unprivileged: 1\n\
+         \n\
+         # snapshot before the upgrade\n\
+         [pre-upgrade]\n\
+         arch: amd64"
+    );
+
+    config.section_mut("pre-upgrade").append("unprivileged", "1");
+    config.section_mut("new").append("arch", "amd64");
+
+    assert!(
+        config
+            .to_string()
+            .ends_with("[pre-upgrade]\narch: amd64\nunprivileged: 1\n\n[new]\narch: amd64")
+    );
+
+    Ok(())
+}
@@ -1,10 +1,29 @@
+use std::io::{BufRead, Write};
+use std::net::{SocketAddr, TcpListener};
 use std::path::PathBuf;
 
-use clap::Parser;
-use color_eyre::eyre::Context;
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{Context, bail};
+use crossterm::event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture};
+use crossterm::execute;
 use log::{LevelFilter, info};
 use pupman::app::App;
+use pupman::app::event::EventHandler;
+use pupman::app::recording::read_recording;
+use pupman::export::{ExportFormat, export_with};
+use pupman::finding::FindingKind;
+use pupman::fix;
+use pupman::fs::backup;
+use pupman::fs::writer::write_atomic;
+use pupman::health::HealthStatus;
+use pupman::history::FindingHistory;
+use pupman::linux::{command, lxc_running, lxc_start_logged, lxc_stop};
 use pupman::metadata::Metadata;
+use pupman::metrics::check_with_metrics;
+use pupman::report::{ReportFormat, report_with};
+use pupman::server;
+use pupman::settings::{Retention, Settings, Theme};
+use pupman::triage::triage_with;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -12,6 +31,104 @@ struct Cli {
     /// Sets a custom lxc config directory
     #[arg(short = 'c', long, value_name = "DIR")]
     lxc_config: Option<PathBuf>,
+    /// Sets a custom settings file
+    #[arg(long, value_name = "FILE")]
+    settings: Option<PathBuf>,
+    /// Inspects host files copied into DIR, e.g. DIR/etc/subuid, without changing anything
+    #[arg(long, value_name = "DIR")]
+    root_prefix: Option<PathBuf>,
+    /// Reads and writes subordinate uids in FILE instead of /etc/subuid
+    #[arg(long, value_name = "FILE")]
+    subuid_path: Option<PathBuf>,
+    /// Reads and writes subordinate gids in FILE instead of /etc/subgid
+    #[arg(long, value_name = "FILE")]
+    subgid_path: Option<PathBuf>,
+    /// Also audits the Incus containers of this host, read through `incus config show`
+    #[arg(long)]
+    incus: bool,
+    /// Skips stat-ing, watching and checking container rootfs directories
+    #[arg(long)]
+    no_rootfs_checks: bool,
+    /// Draws the TUI in THEME instead of the one from the settings, e.g. mono for terminals
+    /// without colors or emoji
+    #[arg(long, value_enum, value_name = "THEME")]
+    theme: Option<Theme>,
+    /// Prints findings instead of starting the TUI. Exits with 1 if any are bad
+    #[arg(long)]
+    check: bool,
+    /// Records every key press, file read and evaluation of the session to FILE, e.g. to attach to
+    /// a bug report
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+    /// Replays a session recorded with --record. Point --root-prefix at the files it was recorded
+    /// against for rootfs details
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints findings instead of starting the TUI, like --check. Exits with 1 if any are bad
+    Check {
+        /// Also writes the findings as Prometheus metrics to FILE, for node_exporter's textfile
+        /// collector
+        #[arg(long, value_name = "FILE")]
+        textfile: Option<PathBuf>,
+    },
+    /// Applies the default fix for a finding without starting the TUI
+    Fix {
+        /// The stable id of the finding to fix
+        #[arg(long, value_name = "ID")]
+        finding_id: String,
+        /// Apply the fix without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Afterwards, run the follow-up steps which only read state, like `pct config`
+        #[arg(long)]
+        run_safe_steps: bool,
+        /// Only print the diff of the files the fix would write
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Works out why an unprivileged container won't start and walks through fixing the most
+    /// likely cause
+    Triage {
+        /// The id of the container
+        vmid: u32,
+        /// Only correlate findings, without trying to start the container
+        #[arg(long)]
+        no_start: bool,
+        /// Try starting the container and apply the fix without asking for confirmation. A container
+        /// which starts is stopped again
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Prints all current findings along with when each was first and last seen
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+    },
+    /// Prints a report of the host mappings, containers and findings with their explanations, to
+    /// share when asking for help
+    Report {
+        #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+    },
+    /// Keeps watching the host like the TUI does, serving what it finds to other programs
+    Serve {
+        /// Serves findings, container details and fixes over JSON-RPC at POST /rpc
+        #[arg(long)]
+        api: bool,
+        /// The loopback address and port to listen on
+        #[arg(long, value_name = "ADDR", default_value = server::DEFAULT_LISTEN)]
+        listen: SocketAddr,
+        /// Reads the bearer token clients must send from FILE, which is created with a new token
+        /// if it is missing [default: ~/.config/pupman/api-token]
+        #[arg(long, value_name = "FILE")]
+        token_file: Option<PathBuf>,
+    },
 }
 
 fn main() -> color_eyre::Result<()> {
@@ -22,12 +139,364 @@ fn main() -> color_eyre::Result<()> {
     info!("Starting pupman...");
 
     let cli = Cli::parse();
+    let mut settings = match cli.settings {
+        Some(path) => Settings::load(&path)?,
+        None => Settings::load_default(),
+    };
+
+    if let Some(theme) = cli.theme {
+        settings.override_theme(theme);
+    }
+
+    let log_entries = settings.retention().log_entries;
+
+    // Setting the depth drops what was logged so far, so it's left alone unless it changes
+    if log_entries != Retention::DEFAULT.log_entries {
+        tui_logger::set_buffer_depth(log_entries);
+    }
+
+    // Before anything runs pveversion or zfs
+    command::set_policy(settings.command_policy());
 
     info!("Collecting system metadata...");
 
-    let md = Metadata::collect(cli.lxc_config).wrap_err("Failed to collect system metadata")?;
+    let mut md = match cli.root_prefix {
+        Some(root_prefix) => Metadata::with_root_prefix(root_prefix, cli.lxc_config),
+        None if cfg!(not(target_os = "linux")) => bail!(
+            "pupman manages LXC containers on Linux hosts and cannot inspect this {} system. \
+             Use --root-prefix <DIR> to view configs copied from a host instead.",
+            std::env::consts::OS
+        ),
+        None => Metadata::collect(cli.lxc_config),
+    }
+    .wrap_err("Failed to collect system metadata")?;
+
+    md.skip_rootfs = cli.no_rootfs_checks;
+    // Incus can only be asked about this host, not about copied files
+    md.incus = cli.incus && !md.is_viewer_only();
+    // Used as is, even with --root-prefix
+    if let Some(path) = cli.subuid_path {
+        md.subuid_path = path;
+    }
+    if let Some(path) = cli.subgid_path {
+        md.subgid_path = path;
+    }
+
+    match cli.command {
+        Some(Command::Fix {
+            finding_id,
+            yes,
+            run_safe_steps,
+            dry_run,
+        }) => return run_fix(&md, settings, &finding_id, yes, run_safe_steps, dry_run),
+        Some(Command::Triage { vmid, no_start, yes }) => return run_triage(&md, settings, vmid, no_start, yes),
+        Some(Command::Export { format }) => return run_export(&md, settings, format),
+        Some(Command::Report { format }) => return run_report(&md, settings, format),
+        Some(Command::Check { textfile }) => return run_check(&md, settings, textfile),
+        Some(Command::Serve {
+            api,
+            listen,
+            token_file,
+        }) => return run_serve(md, settings, api, listen, token_file),
+        None if cli.check => return run_check(&md, settings, None),
+        None => {},
+    }
+
+    // Read before the terminal is taken over, so a bad recording is reported where it can be seen
+    let replay = cli
+        .replay
+        .map(|path| read_recording(&path).wrap_err_with(|| format!("Failed to read recording {}", path.display())))
+        .transpose()?;
+    let mut app = App::new(md, settings)?;
+
+    if let Some(path) = cli.record {
+        app.record_to(&path)
+            .wrap_err_with(|| format!("Failed to create recording {}", path.display()))?;
+    }
+
+    if let Some(recording) = replay {
+        app.replay(recording);
+    }
+
     let terminal = ratatui::init();
-    let result = App::new(md).run(terminal);
+
+    // Pasted blocks arrive as a single event rather than one key press per character. The terminal
+    // is restored below even when the terminal refuses
+    let result = execute!(std::io::stdout(), EnableBracketedPaste, EnableMouseCapture)
+        .map_err(Into::into)
+        .and_then(|()| app.run(terminal));
+
+    let _ = execute!(std::io::stdout(), DisableBracketedPaste, DisableMouseCapture);
     ratatui::restore();
     result
 }
+
+fn run_check(md: &Metadata, settings: Settings, textfile: Option<PathBuf>) -> color_eyre::Result<()> {
+    let (health, findings, metrics, errors) = check_with_metrics(md, settings);
+
+    for err in errors {
+        eprintln!("{err:?}");
+    }
+
+    // Renamed into place, so the collector never scrapes a half written file
+    if let Some(path) = textfile {
+        write_atomic(&path, &metrics).wrap_err_with(|| format!("Failed to write metrics to {}", path.display()))?;
+    }
+
+    for finding in findings.iter().filter(|f| f.kind != FindingKind::Good) {
+        let kind = match finding.kind {
+            FindingKind::Bad => "BAD ",
+            FindingKind::Info => "INFO",
+            _ => "WARN",
+        };
+
+        println!("{kind} {finding}  [{}]", finding.id());
+    }
+
+    println!("{health}");
+
+    if health.status == HealthStatus::Fail {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn run_serve(
+    md: Metadata,
+    settings: Settings,
+    api: bool,
+    listen: SocketAddr,
+    token_file: Option<PathBuf>,
+) -> color_eyre::Result<()> {
+    if !api {
+        bail!("Nothing to serve, pass --api to serve the JSON-RPC API");
+    }
+
+    // The token guards applying fixes as root, it shouldn't cross the network in the clear
+    if !listen.ip().is_loopback() {
+        bail!("The API only listens on loopback addresses, not {listen}");
+    }
+
+    let Some(token_path) = token_file.or_else(server::default_token_path) else {
+        bail!("No config directory to keep the API token in, pass --token-file");
+    };
+    let token = server::load_or_create_token(&token_path)?;
+    let listener = TcpListener::bind(listen).wrap_err_with(|| format!("Failed to listen on {listen}"))?;
+    let mut app = App::with_event_handler(md, settings, EventHandler::without_terminal())?;
+
+    app.initialize()?;
+    server::listen(listener, token, app.event_sender());
+    eprintln!(
+        "Serving the API at http://{listen}/rpc, with the token in {}",
+        token_path.display()
+    );
+
+    while app.is_running() {
+        app.handle_events()?;
+    }
+
+    Ok(())
+}
+
+fn run_export(md: &Metadata, settings: Settings, format: ExportFormat) -> color_eyre::Result<()> {
+    let history = if md.is_viewer_only() {
+        FindingHistory::default()
+    } else {
+        FindingHistory::load_default()
+    };
+
+    let (report, errors) = export_with(md, settings, &history, format);
+
+    for err in errors {
+        eprintln!("{err:?}");
+    }
+
+    print!("{report}");
+
+    Ok(())
+}
+
+fn run_report(md: &Metadata, settings: Settings, format: ReportFormat) -> color_eyre::Result<()> {
+    let (report, errors) = report_with(md, settings, format);
+
+    for err in errors {
+        eprintln!("{err:?}");
+    }
+
+    print!("{report}");
+
+    Ok(())
+}
+
+fn run_fix(
+    md: &Metadata,
+    settings: Settings,
+    finding_id: &str,
+    yes: bool,
+    run_safe_steps: bool,
+    dry_run: bool,
+) -> color_eyre::Result<()> {
+    let (finding, fix, follow_up) = fix::locate(md, settings, finding_id)?;
+
+    println!("{}: {finding}", finding.id());
+    println!("{}", fix.description());
+    println!();
+
+    for write in fix.pending_writes(md)? {
+        print!("{}", write.diff());
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    if !yes && !confirm("Apply this fix?")? {
+        bail!("Fix was not applied");
+    }
+
+    fix.apply(md)?;
+
+    println!("Fix applied");
+
+    if let Some(backup) = backup::last_backup() {
+        println!("The previous version was kept at {}", backup.path.display());
+    }
+
+    println!();
+    println!("Next steps:");
+
+    for step in &follow_up {
+        match step.command_line() {
+            Some(command_line) => println!("  - {}: {command_line}", step.description),
+            None => println!("  - {}", step.description),
+        }
+    }
+
+    if run_safe_steps {
+        let mut failed = 0;
+
+        // The fix is already written, so a failing step shouldn't hide the ones after it
+        for step in follow_up.iter().filter(|step| step.safe && step.command.is_some()) {
+            println!("$ {}", step.command_line().unwrap_or_default());
+
+            match step.run() {
+                Ok(output) if output.is_empty() => {},
+                Ok(output) => println!("{output}"),
+                Err(err) => {
+                    eprintln!("{err}");
+                    failed += 1;
+                },
+            }
+        }
+
+        if failed > 0 {
+            bail!("The fix was applied, but {failed} of the next steps failed");
+        }
+    }
+
+    Ok(())
+}
+
+fn confirm(question: &str) -> color_eyre::Result<bool> {
+    print!("{question} [y/N] ");
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+
+    std::io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Starts container `vmid` with LXC's debug log, unless it is already running or the user would
+/// rather not, and returns the log.
+fn attempt_start(vmid: u32, yes: bool) -> color_eyre::Result<Option<String>> {
+    let name = vmid.to_string();
+
+    if lxc_running(&name).unwrap_or(false) {
+        println!("Container {vmid} is already running, so it won't be started");
+        return Ok(None);
+    }
+
+    if !yes
+        && !confirm(&format!(
+            "Try starting container {vmid} with debug logging to see why it fails? If it starts, it is stopped again"
+        ))?
+    {
+        return Ok(None);
+    }
+
+    let log = tempfile::NamedTempFile::new()?;
+    let started = lxc_start_logged(&name, log.path()).wrap_err("Failed to run lxc-start")?;
+
+    // Triage only wants to see the start, not leave a container running behind the user's back
+    if started {
+        match lxc_stop(&name) {
+            Ok(()) => println!("Container {vmid} started fine, so it was stopped again"),
+            Err(err) => eprintln!("Container {vmid} started, but stopping it again failed: {err}"),
+        }
+    }
+
+    Ok(Some(std::fs::read_to_string(log.path())?))
+}
+
+fn run_triage(md: &Metadata, settings: Settings, vmid: u32, no_start: bool, yes: bool) -> color_eyre::Result<()> {
+    // Files inspected through --root-prefix don't belong to containers on this host
+    let start_log = if no_start || md.is_viewer_only() {
+        None
+    } else {
+        attempt_start(vmid, yes)?
+    };
+    let (triage, errors) = triage_with(md, settings.clone(), vmid, start_log.as_deref())?;
+
+    for err in errors {
+        eprintln!("{err:?}");
+    }
+
+    if !triage.start_errors.is_empty() {
+        println!("LXC failed to start container {vmid}:");
+
+        for error in &triage.start_errors {
+            println!("  {error}");
+        }
+
+        println!();
+    }
+
+    let Some(first) = triage.suspects.first() else {
+        println!("Nothing pupman checks for is wrong with container {vmid}");
+        return Ok(());
+    };
+
+    println!("Problems, most likely cause first:");
+
+    for suspect in &triage.suspects {
+        let marker = if suspect.matches_start { "*" } else { " " };
+
+        println!("{marker} {}  [{}]", suspect.finding, suspect.finding.id());
+    }
+
+    if triage.suspects.iter().any(|suspect| suspect.matches_start) {
+        println!("  (* matches the errors of the start attempt)");
+    }
+
+    println!();
+    println!("{}", first.finding);
+    println!();
+    println!("{}", first.explanation);
+    println!();
+
+    let Some(suspect) = triage.next_fix() else {
+        println!("None of these can be fixed automatically. Follow the explanation above, or generate matching");
+        println!("idmaps, subordinate ids and rootfs ownership with the idmap wizard (g) in the TUI.");
+        return Ok(());
+    };
+
+    if suspect.finding.id() != first.finding.id() {
+        println!("The most likely problem with an automated fix is: {}", suspect.finding);
+        println!();
+    }
+
+    run_fix(md, settings, &suspect.finding.id(), yes, true, false)
+}
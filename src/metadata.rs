@@ -1,12 +1,71 @@
 use std::path::{Path, PathBuf};
 
 use color_eyre::eyre::eyre;
+use log::{info, warn};
+use nix::unistd::getuid;
 
+use crate::fs::subid::{ETC_SUBGID, ETC_SUBUID, SubID};
+use crate::linux::passwd::{ETC_GROUP, ETC_PASSWD};
+use crate::proxmox::dialect::Dialect;
+use crate::proxmox::storage::{PVE_STORAGE_CFG, StorageConfig};
+use crate::proxmox::version::PveVersion;
+
+/// Where pmxcfs mounts the cluster's configs, which only exists on PVE hosts.
+const PVE_DIR: &str = "/etc/pve";
 const PVE_CONF_DIR: &str = "/etc/pve/lxc";
+/// Where PVE writes the LXC config it generates for each container start.
+const LXC_RUNTIME_DIR: &str = "/var/lib/lxc";
+/// Host-wide defaults `lxc-create` copies into every new container config.
+const LXC_DEFAULT_CONF: &str = "/etc/lxc/default.conf";
+const PROC_HOSTNAME: &str = "/proc/sys/kernel/hostname";
+const PROC_OSRELEASE: &str = "/proc/sys/kernel/osrelease";
+const ETC_HOSTNAME: &str = "/etc/hostname";
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Metadata {
     pub lxc_config_dir: PathBuf,
+    pub subuid_path: PathBuf,
+    pub subgid_path: PathBuf,
+    pub passwd_path: PathBuf,
+    pub group_path: PathBuf,
+    pub lxc_default_config: PathBuf,
+    /// Set when inspecting files copied from a host rather than the running system. Nothing is
+    /// written and rootfs directories are not looked at in this viewer-only mode.
+    pub root_prefix: Option<PathBuf>,
+    /// Set by `--incus` to also audit the Incus containers of this host.
+    pub incus: bool,
+    /// Set by `--no-rootfs-checks` for hosts where stat-ing rootfs directories is slow or pointless.
+    pub skip_rootfs: bool,
+    /// The storages rootfs volumes are resolved against.
+    pub storage: StorageConfig,
+    /// Whether this is a Proxmox VE host, or the files were copied from one.
+    pub is_pve: bool,
+    /// The installed PVE version, if it could be detected.
+    pub pve_version: Option<PveVersion>,
+    pub hostname: Option<String>,
+    /// The release of the running kernel, e.g. `6.8.12-1-pve`.
+    pub kernel: Option<String>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            lxc_config_dir: PathBuf::from(PVE_CONF_DIR),
+            subuid_path: PathBuf::from(ETC_SUBUID),
+            subgid_path: PathBuf::from(ETC_SUBGID),
+            passwd_path: PathBuf::from(ETC_PASSWD),
+            group_path: PathBuf::from(ETC_GROUP),
+            lxc_default_config: PathBuf::from(LXC_DEFAULT_CONF),
+            root_prefix: None,
+            skip_rootfs: false,
+            incus: false,
+            storage: StorageConfig::default(),
+            is_pve: false,
+            pve_version: None,
+            hostname: None,
+            kernel: None,
+        }
+    }
 }
 
 impl Metadata {
@@ -21,6 +80,172 @@ impl Metadata {
             ));
         };
 
-        Ok(Metadata { lxc_config_dir })
+        let detected = PveVersion::detect();
+        // pveversion may be missing from sudo's PATH, pmxcfs is there either way
+        let is_pve = detected.is_ok() || Path::new(PVE_DIR).is_dir();
+
+        match &detected {
+            Ok(version) => info!("Detected PVE {version}"),
+            Err(err) if is_pve => warn!("Assuming the default PVE version: {err:?}"),
+            Err(_) => info!("Not a PVE host, reading the configs as plain LXC ones"),
+        }
+
+        Ok(Metadata {
+            lxc_config_dir,
+            storage: load_storage(Path::new(PVE_STORAGE_CFG)),
+            is_pve,
+            pve_version: detected.ok(),
+            hostname: read_trimmed(Path::new(PROC_HOSTNAME)),
+            kernel: read_trimmed(Path::new(PROC_OSRELEASE)),
+            ..Metadata::default()
+        })
+    }
+
+    /// Reads every host file relative to `root_prefix`, e.g. `<root_prefix>/etc/subuid`. An explicit
+    /// `lxc_config_dir` is used as is.
+    pub fn with_root_prefix(root_prefix: PathBuf, lxc_config_dir: Option<PathBuf>) -> color_eyre::Result<Self> {
+        let prefixed = |path: &str| root_prefix.join(path.trim_start_matches('/'));
+        let lxc_config_dir = lxc_config_dir.unwrap_or_else(|| prefixed(PVE_CONF_DIR));
+
+        if !lxc_config_dir.is_dir() {
+            return Err(eyre!(
+                "LXC configuration directory {} not found. Please specify a custom directory with the -c option.",
+                lxc_config_dir.display()
+            ));
+        }
+
+        Ok(Metadata {
+            lxc_config_dir,
+            subuid_path: prefixed(ETC_SUBUID),
+            subgid_path: prefixed(ETC_SUBGID),
+            passwd_path: prefixed(ETC_PASSWD),
+            group_path: prefixed(ETC_GROUP),
+            lxc_default_config: prefixed(LXC_DEFAULT_CONF),
+            storage: load_storage(&prefixed(PVE_STORAGE_CFG)),
+            is_pve: prefixed(PVE_DIR).is_dir(),
+            hostname: read_trimmed(&prefixed(ETC_HOSTNAME)),
+            root_prefix: Some(root_prefix),
+            skip_rootfs: false,
+            incus: false,
+            // Copied files don't say which version they came from, or which kernel ran them
+            pve_version: None,
+            kernel: None,
+        })
+    }
+
+    /// The uid of whoever runs pupman, the user behind `sudo` rather than root. Copied files
+    /// belong to another host, where the uid means nothing.
+    pub fn operator_uid(&self) -> Option<u32> {
+        if self.is_viewer_only() {
+            return None;
+        }
+
+        let sudo_uid = std::env::var("SUDO_UID").ok().and_then(|uid| uid.parse().ok());
+
+        Some(sudo_uid.unwrap_or_else(|| getuid().as_raw()))
+    }
+
+    /// Re-reads storage.cfg, which may have changed since startup.
+    pub fn reload_storage(&mut self) {
+        let path = match &self.root_prefix {
+            Some(root_prefix) => root_prefix.join(PVE_STORAGE_CFG.trim_start_matches('/')),
+            None => PathBuf::from(PVE_STORAGE_CFG),
+        };
+
+        self.storage = load_storage(&path);
+    }
+
+    /// Whether PVE has started the container before. The LXC config PVE generates on every start
+    /// stays behind after the container stops.
+    pub fn was_started(&self, vmid: &str) -> bool {
+        let runtime_dir = match &self.root_prefix {
+            Some(root_prefix) => root_prefix.join(LXC_RUNTIME_DIR.trim_start_matches('/')),
+            None => PathBuf::from(LXC_RUNTIME_DIR),
+        };
+
+        runtime_dir.join(vmid).join("config").exists()
+    }
+
+    /// The detected PVE version for showing, `None` on hosts without PVE.
+    pub fn pve_version_name(&self) -> Option<String> {
+        match self.pve_version {
+            Some(version) => Some(version.to_string()),
+            None => self.is_pve.then(|| "unknown version".to_string()),
+        }
+    }
+
+    /// The config dialect of the detected PVE version, or PVE 8's when it is unknown.
+    pub fn dialect(&self) -> Dialect {
+        self.pve_version.map(Dialect::for_version).unwrap_or_default()
+    }
+
+    pub fn is_viewer_only(&self) -> bool {
+        self.root_prefix.is_some()
+    }
+
+    /// Whether rootfs directories may be looked at on this system at all.
+    pub fn inspects_rootfs(&self) -> bool {
+        !self.is_viewer_only() && !self.skip_rootfs
     }
+
+    pub fn subid_path(&self, sub_id: SubID) -> &Path {
+        match sub_id {
+            SubID::UID => &self.subuid_path,
+            SubID::GID => &self.subgid_path,
+        }
+    }
+
+    /// Maps a path read from this system back to the subordinate id file it is, if any.
+    pub fn subid_for_path(&self, path: &Path) -> Option<SubID> {
+        [SubID::UID, SubID::GID]
+            .into_iter()
+            .find(|sub_id| self.subid_path(*sub_id) == path)
+    }
+}
+
+/// The content of a single line file such as /etc/hostname, if it has any.
+fn read_trimmed(path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let content = content.trim();
+
+    (!content.is_empty()).then(|| content.to_string())
+}
+
+/// A storage.cfg which can't be read only breaks rootfs resolution, so the defaults are used instead.
+fn load_storage(path: &Path) -> StorageConfig {
+    StorageConfig::load(path).unwrap_or_else(|err| {
+        warn!("Using default storages: {err:?}");
+        StorageConfig::default()
+    })
+}
+
+#[test]
+fn test_with_root_prefix() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    assert!(Metadata::with_root_prefix(dir.path().to_path_buf(), None).is_err());
+
+    std::fs::create_dir_all(dir.path().join("etc/pve/lxc"))?;
+    std::fs::write(dir.path().join("etc/hostname"), "pve1\n")?;
+
+    let md = Metadata::with_root_prefix(dir.path().to_path_buf(), None)?;
+
+    assert!(md.is_viewer_only());
+    assert_eq!(md.lxc_config_dir, dir.path().join("etc/pve/lxc"));
+    assert_eq!(md.subid_for_path(&dir.path().join("etc/subgid")), Some(SubID::GID));
+    assert_eq!(md.subid_for_path(Path::new(ETC_SUBGID)), None);
+    assert_eq!(md.lxc_default_config, dir.path().join("etc/lxc/default.conf"));
+    assert!(md.is_pve);
+    assert_eq!(md.pve_version_name().as_deref(), Some("unknown version"));
+    assert_eq!(md.hostname.as_deref(), Some("pve1"));
+    assert_eq!(md.kernel, None);
+
+    // Plain LXC configs copied without anything of PVE's
+    let other = tempfile::tempdir()?;
+    let md = Metadata::with_root_prefix(other.path().to_path_buf(), Some(dir.path().join("etc/pve/lxc")))?;
+
+    assert!(!md.is_pve);
+    assert_eq!(md.pve_version_name(), None);
+
+    Ok(())
 }
@@ -0,0 +1,175 @@
+//! Findings as Prometheus textfile metrics, for node_exporter's textfile collector. Running
+//! `pupman check --textfile` from cron keeps them current without a long-running daemon.
+
+use std::fmt::Write;
+
+use chrono::Utc;
+
+use crate::app::state::{State, finding_vmid};
+use crate::check::Check;
+use crate::finding::{Finding, FindingKind};
+use crate::health::{Health, HealthStatus};
+use crate::metadata::Metadata;
+use crate::settings::Settings;
+
+const KINDS: [(FindingKind, &str); 4] = [
+    (FindingKind::Bad, "bad"),
+    (FindingKind::Warning, "warning"),
+    (FindingKind::Info, "info"),
+    (FindingKind::Good, "good"),
+];
+
+fn status_value(status: HealthStatus) -> u8 {
+    match status {
+        HealthStatus::Ok => 0,
+        HealthStatus::Warn => 1,
+        HealthStatus::Fail => 2,
+    }
+}
+
+fn metric_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+}
+
+/// Renders the evaluated `state` in the Prometheus text format. `timestamp` is when the checks
+/// ran, in seconds since the epoch.
+fn render(state: &State, health: &Health, timestamp: i64) -> String {
+    let mut out = String::new();
+    let findings = &state.findings;
+
+    metric_header(&mut out, "pupman_status", "Overall status, 0 is ok, 1 warn and 2 fail.");
+    let _ = writeln!(out, "pupman_status {}", status_value(health.status));
+
+    metric_header(&mut out, "pupman_findings", "Current findings by kind.");
+    for (kind, name) in KINDS {
+        let count = findings.iter().filter(|f| f.kind == kind).count();
+
+        let _ = writeln!(out, "pupman_findings{{kind=\"{name}\"}} {count}");
+    }
+
+    metric_header(
+        &mut out,
+        "pupman_check_problems",
+        "Current bad and warning findings by check, 0 for disabled checks.",
+    );
+    for check in Check::ALL {
+        let count = findings
+            .iter()
+            .filter(|f| f.check == check && f.kind.is_problem())
+            .count();
+
+        let _ = writeln!(out, "pupman_check_problems{{check=\"{}\"}} {count}", check.id());
+    }
+
+    metric_header(&mut out, "pupman_containers", "Container configs which were loaded.");
+    let _ = writeln!(out, "pupman_containers {}", health.containers);
+
+    metric_header(&mut out, "pupman_load_errors", "Files which could not be loaded.");
+    let _ = writeln!(out, "pupman_load_errors {}", health.load_errors);
+
+    let containers: Vec<_> = state
+        .lxc_configs
+        .iter()
+        .filter_map(|(filename, config)| {
+            let vmid: u32 = filename.strip_suffix(".conf")?.parse().ok()?;
+
+            Some((
+                vmid,
+                state
+                    .dialect
+                    .is_unprivileged(&config.section(None), &state.default_idmaps),
+            ))
+        })
+        .collect();
+    let container_findings = |vmid: u32, kind: FindingKind| {
+        findings
+            .iter()
+            .filter(|f| f.kind == kind && finding_vmid(&state.lxc_configs, f) == Some(vmid))
+            .count()
+    };
+
+    metric_header(
+        &mut out,
+        "pupman_container_unprivileged",
+        "Whether the container is unprivileged.",
+    );
+    for (vmid, unprivileged) in &containers {
+        let _ = writeln!(
+            out,
+            "pupman_container_unprivileged{{vmid=\"{vmid}\"}} {}",
+            u8::from(*unprivileged)
+        );
+    }
+
+    metric_header(
+        &mut out,
+        "pupman_container_findings",
+        "Current bad and warning findings of the container.",
+    );
+    for (vmid, _) in &containers {
+        for (kind, name) in &KINDS[..2] {
+            let _ = writeln!(
+                out,
+                "pupman_container_findings{{vmid=\"{vmid}\",kind=\"{name}\"}} {}",
+                container_findings(*vmid, *kind)
+            );
+        }
+    }
+
+    metric_header(
+        &mut out,
+        "pupman_last_run_timestamp_seconds",
+        "When the checks last ran, in seconds since the epoch.",
+    );
+    let _ = writeln!(out, "pupman_last_run_timestamp_seconds {timestamp}");
+
+    out
+}
+
+/// Runs all checks enabled in `settings` once, like [`check_with`](crate::health::check_with), and
+/// also renders the result as textfile metrics.
+pub fn check_with_metrics(
+    metadata: &Metadata,
+    settings: Settings,
+) -> (Health, Vec<Finding>, String, Vec<color_eyre::Report>) {
+    let (state, errors) = State::collect(metadata, settings);
+    let health = Health::from_state(&state, errors.len());
+    let metrics = render(&state, &health, Utc::now().timestamp());
+
+    (health, state.findings, metrics, errors)
+}
+
+#[test]
+fn test_render() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    let mut state = State::default();
+
+    state.load_config(Path::new("/etc/pve/lxc/100.conf"), "unprivileged: 1\n")?;
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), "arch: amd64\n")?;
+    state.evaluate_findings();
+
+    let health = Health::from_state(&state, 0);
+    let metrics = render(&state, &health, 1700000000);
+    let lines: Vec<_> = metrics.lines().collect();
+
+    assert!(lines.contains(&"pupman_status 2"));
+    assert!(lines.contains(&"pupman_findings{kind=\"bad\"} 2"));
+    assert!(lines.contains(&"pupman_check_problems{check=\"idmap-present\"} 2"));
+    assert!(lines.contains(&"pupman_containers 2"));
+    assert!(lines.contains(&"pupman_container_unprivileged{vmid=\"100\"} 1"));
+    assert!(lines.contains(&"pupman_container_unprivileged{vmid=\"101\"} 0"));
+    assert!(lines.contains(&"pupman_container_findings{vmid=\"100\",kind=\"bad\"} 2"));
+    assert!(lines.contains(&"pupman_container_findings{vmid=\"101\",kind=\"bad\"} 0"));
+    assert!(lines.contains(&"pupman_last_run_timestamp_seconds 1700000000"));
+    // Every sample belongs to a declared metric
+    assert!(lines.iter().filter(|line| !line.starts_with('#')).all(|line| {
+        lines
+            .iter()
+            .filter_map(|l| l.strip_prefix("# TYPE "))
+            .any(|declared| line.starts_with(declared.trim_end_matches(" gauge")))
+    }));
+
+    Ok(())
+}
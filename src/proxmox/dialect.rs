@@ -0,0 +1,181 @@
+//! Container config differences between PVE major versions. Everything which depends on the PVE
+//! version goes through [`Dialect`], so checks don't need to know which version they run against.
+
+use crate::lxc::config::ConfigFormat;
+use crate::lxc::idmap::ConfigIdMap;
+use crate::lxc::section::SectionView;
+use crate::proxmox::version::PveVersion;
+
+/// A config key which the dialect no longer supports.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeprecatedKey {
+    /// The key, or a key prefix when it ends with a `.`.
+    pub key: &'static str,
+    pub replacement: &'static str,
+    /// Why the key no longer works.
+    pub reason: &'static str,
+}
+
+impl DeprecatedKey {
+    fn matches(&self, key: &str) -> bool {
+        if self.key.ends_with('.') {
+            key.starts_with(self.key)
+        } else {
+            key == self.key
+        }
+    }
+
+    /// What `key` should be written as instead.
+    pub fn replace(&self, key: &str) -> String {
+        match key.strip_prefix(self.key) {
+            Some(rest) if self.key.ends_with('.') => format!("{}{rest}", self.replacement),
+            _ => self.replacement.to_string(),
+        }
+    }
+}
+
+/// Keys LXC 3.0 dropped in favour of their LXC 2.1 names. Every supported PVE version ships LXC 3 or
+/// later, which refuses to start containers using them.
+const LEGACY_LXC_KEYS: &[DeprecatedKey] = &[
+    DeprecatedKey {
+        key: "lxc.id_map",
+        replacement: "lxc.idmap",
+        reason: "renamed in LXC 2.1 and removed in LXC 3.0",
+    },
+    DeprecatedKey {
+        key: "lxc.aa_profile",
+        replacement: "lxc.apparmor.profile",
+        reason: "renamed in LXC 2.1 and removed in LXC 3.0",
+    },
+    DeprecatedKey {
+        key: "lxc.mount",
+        replacement: "lxc.mount.fstab",
+        reason: "renamed in LXC 2.1 and removed in LXC 3.0",
+    },
+    DeprecatedKey {
+        key: "lxc.rootfs",
+        replacement: "lxc.rootfs.path",
+        reason: "renamed in LXC 2.1 and removed in LXC 3.0",
+    },
+    DeprecatedKey {
+        key: "lxc.utsname",
+        replacement: "lxc.uts.name",
+        reason: "renamed in LXC 2.1 and removed in LXC 3.0",
+    },
+];
+
+/// PVE 9 only boots with the unified cgroup hierarchy, so cgroup v1 settings are ignored.
+const CGROUP_V1_KEYS: &[DeprecatedKey] = &[DeprecatedKey {
+    key: "lxc.cgroup.",
+    replacement: "lxc.cgroup2.",
+    reason: "cgroup v1 is no longer supported since PVE 9",
+}];
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Dialect {
+    /// PVE 8 and earlier.
+    #[default]
+    Pve8,
+    /// PVE 9 and later.
+    Pve9,
+}
+
+impl Dialect {
+    pub fn for_version(version: PveVersion) -> Self {
+        if version.major >= 9 {
+            Dialect::Pve9
+        } else {
+            Dialect::Pve8
+        }
+    }
+
+    /// Whether a container is unprivileged. PVE accepts any of its boolean spellings. `default_idmaps`
+    /// are those of /etc/lxc/default.conf, which upstream LXC configs without idmaps of their own
+    /// inherit.
+    pub fn is_unprivileged(self, section: &SectionView, default_idmaps: &[ConfigIdMap]) -> bool {
+        // Upstream LXC has no such key, a container is unprivileged once it maps its ids
+        if section.format() == ConfigFormat::Lxc {
+            return section.has_lxc_idmap() || !default_idmaps.is_empty();
+        }
+
+        // `pct` writes `unprivileged: 1` explicitly, so a config without the key is privileged
+        matches!(
+            section.get_unprivileged().map(str::trim),
+            Some("1" | "yes" | "on" | "true")
+        )
+    }
+
+    pub fn deprecated_keys(self) -> impl Iterator<Item = &'static DeprecatedKey> {
+        let cgroup_v1: &[_] = match self {
+            Dialect::Pve8 => &[],
+            Dialect::Pve9 => CGROUP_V1_KEYS,
+        };
+
+        LEGACY_LXC_KEYS.iter().chain(cgroup_v1)
+    }
+
+    /// Whether `key` is no longer supported, and what to use instead.
+    pub fn deprecated(self, key: &str) -> Option<&'static DeprecatedKey> {
+        self.deprecated_keys().find(|deprecated| deprecated.matches(key))
+    }
+}
+
+#[test]
+fn test_pve8_dialect() -> color_eyre::Result<()> {
+    let dialect = Dialect::for_version(PveVersion { major: 8, minor: 4 });
+    let config: crate::lxc::config::Config = "unprivileged: yes\nlxc.cgroup.devices.allow: c 10:200 rwm".parse()?;
+
+    assert_eq!(dialect, Dialect::Pve8);
+    assert!(dialect.is_unprivileged(&config.section(None), &[]));
+    assert!(!dialect.is_unprivileged(&"arch: amd64".parse::<crate::lxc::config::Config>()?.section(None), &[]));
+    // Upstream LXC configs are unprivileged by their own idmaps or those of default.conf
+    assert!(
+        dialect.is_unprivileged(
+            &"lxc.idmap = u 0 100000 65536"
+                .parse::<crate::lxc::config::Config>()?
+                .section(None),
+            &[]
+        )
+    );
+    let plain: crate::lxc::config::Config = "lxc.arch = amd64".parse()?;
+    let defaults = crate::lxc::idmap::config_idmaps(&"lxc.idmap = u 0 100000 65536".parse()?);
+
+    assert!(!dialect.is_unprivileged(&plain.section(None), &[]));
+    assert!(dialect.is_unprivileged(&plain.section(None), &defaults));
+    // PVE configs ignore default.conf
+    assert!(!dialect.is_unprivileged(
+        &"arch: amd64".parse::<crate::lxc::config::Config>()?.section(None),
+        &defaults
+    ));
+    assert_eq!(
+        dialect.deprecated("lxc.id_map").map(|d| d.replacement),
+        Some("lxc.idmap")
+    );
+    assert_eq!(dialect.deprecated("lxc.cgroup.devices.allow"), None);
+    assert_eq!(dialect.deprecated("lxc.mount.entry"), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_pve9_dialect() -> color_eyre::Result<()> {
+    let dialect = Dialect::for_version(PveVersion { major: 9, minor: 0 });
+    let config: crate::lxc::config::Config = "unprivileged: 0".parse()?;
+
+    assert_eq!(dialect, Dialect::Pve9);
+    assert!(!dialect.is_unprivileged(&config.section(None), &[]));
+    assert_eq!(
+        dialect.deprecated("lxc.cgroup.devices.allow").map(|d| d.replacement),
+        Some("lxc.cgroup2.")
+    );
+    assert_eq!(
+        dialect
+            .deprecated("lxc.cgroup.devices.allow")
+            .map(|d| d.replace("lxc.cgroup.devices.allow")),
+        Some("lxc.cgroup2.devices.allow".to_string())
+    );
+    assert_eq!(dialect.deprecated("lxc.cgroup2.devices.allow"), None);
+    assert!(dialect.deprecated("lxc.utsname").is_some());
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! PVE's `features` key, which allows a container things it can't do by default.
+//!
+//! ```text
+//! features: keyctl=1,nesting=1,mount=nfs;cifs
+//! ```
+
+use super::property_string::{PropertyString, PropertyStringError};
+
+/// Filesystems the kernel lets a user namespace mount. An unprivileged container can't mount
+/// anything else, like nfs or ext4, whatever `mount=` allows it.
+pub const USERNS_MOUNTABLE: &[&str] = &[
+    "binder", "cgroup", "cgroup2", "devpts", "fuse", "mqueue", "overlay", "proc", "ramfs", "sysfs", "tmpfs",
+];
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Features {
+    pub fuse: bool,
+    /// Allows the keyctl() syscall, which only unprivileged containers are denied.
+    pub keyctl: bool,
+    pub mknod: bool,
+    pub nesting: bool,
+    /// The filesystem types of `mount=nfs;cifs`.
+    pub mount: Vec<String>,
+}
+
+impl Features {
+    /// Parses a `features` value. Features PVE doesn't know are ignored, the schema check reports
+    /// them.
+    pub fn parse(value: &str) -> Result<Self, PropertyStringError> {
+        let mut features = Features::default();
+
+        for property in PropertyString::parse(value)?.properties {
+            let enabled = matches!(property.value.trim(), "1" | "yes" | "on" | "true");
+
+            match property.key {
+                Some("fuse") => features.fuse = enabled,
+                Some("keyctl") => features.keyctl = enabled,
+                Some("mknod") => features.mknod = enabled,
+                Some("nesting") => features.nesting = enabled,
+                Some("mount") => {
+                    features.mount = property
+                        .value
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|fs_type| !fs_type.is_empty())
+                        .map(String::from)
+                        .collect();
+                },
+                _ => {},
+            }
+        }
+
+        Ok(features)
+    }
+
+    /// The `mount=` types the kernel refuses to mount inside a user namespace.
+    pub fn unmountable_types(&self) -> impl Iterator<Item = &str> {
+        self.mount
+            .iter()
+            .map(String::as_str)
+            .filter(|fs_type| !USERNS_MOUNTABLE.contains(fs_type))
+    }
+}
+
+#[test]
+fn test_parse_features() {
+    let features = Features::parse("nesting=1,keyctl=0,mount=nfs; tmpfs;cifs,fuse=on").expect("valid features");
+
+    assert!(features.nesting);
+    assert!(!features.keyctl);
+    assert!(features.fuse);
+    assert!(!features.mknod);
+    assert_eq!(features.mount, ["nfs", "tmpfs", "cifs"]);
+    assert_eq!(features.unmountable_types().collect::<Vec<_>>(), ["nfs", "cifs"]);
+    assert_eq!(Features::parse(""), Ok(Features::default()));
+    assert!(Features::parse("mount=\"nfs").is_err());
+}
@@ -0,0 +1,8 @@
+//! Parsers for Proxmox VE's own configuration files, and what differs between its versions.
+
+pub mod dialect;
+pub mod features;
+pub mod property_string;
+pub mod schema;
+pub mod storage;
+pub mod version;
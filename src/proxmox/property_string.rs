@@ -0,0 +1,156 @@
+//! PVE's property strings, the comma separated `key=value` lists of `rootfs`, `mpN`, `netN` and
+//! the like. A value containing a comma has to be double quoted, with `\"` and `\\` escaping quotes
+//! and backslashes inside the quotes.
+//!
+//! ```text
+//! mp0: /tank/media,mp="/srv/a,b",backup=1
+//! ```
+
+use std::borrow::Cow;
+
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum PropertyStringError {
+    #[error("a quote is never closed")]
+    UnterminatedQuote,
+    #[error("{0:?} has no key before its =")]
+    EmptyKey(String),
+    #[error("{0:?} has a quote inside its value, which must be quoted as a whole")]
+    StrayQuote(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Property<'v> {
+    /// `None` for a value given without its key, which PVE assigns to the default key.
+    pub key: Option<&'v str>,
+    /// The value with its quotes and escapes resolved.
+    pub value: Cow<'v, str>,
+}
+
+/// The properties of a property string, in order.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PropertyString<'v> {
+    pub properties: Vec<Property<'v>>,
+}
+
+impl<'v> PropertyString<'v> {
+    pub fn parse(value: &'v str) -> Result<Self, PropertyStringError> {
+        let properties = split(value)?
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .map(parse_property)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { properties })
+    }
+
+    /// The value of `key`, if it is set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|property| property.key == Some(key))
+            .map(|property| &*property.value)
+    }
+
+    /// The value given without a key, such as the volume of `local-zfs:subvol-100-disk-0,size=8G`.
+    pub fn positional(&self) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|property| property.key.is_none())
+            .map(|property| &*property.value)
+    }
+}
+
+/// Splits `value` at the commas outside of quotes, without resolving the quotes.
+pub fn split(value: &str) -> Result<Vec<&str>, PropertyStringError> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+
+    if quoted {
+        return Err(PropertyStringError::UnterminatedQuote);
+    }
+
+    parts.push(&value[start..]);
+
+    Ok(parts)
+}
+
+fn parse_property(part: &str) -> Result<Property<'_>, PropertyStringError> {
+    // A quoted value may contain a =, so a key is only looked for before any quote
+    let (key, value) = match part.split_once('=') {
+        Some((key, value)) if !key.contains('"') => (Some(key), value),
+        _ => (None, part),
+    };
+
+    if key.is_some_and(str::is_empty) {
+        return Err(PropertyStringError::EmptyKey(part.to_string()));
+    }
+
+    let value = match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        Some(inner) if inner.contains('\\') => Cow::Owned(unescape(inner)),
+        Some(inner) => Cow::Borrowed(inner),
+        None if value.contains('"') => return Err(PropertyStringError::StrayQuote(part.to_string())),
+        None => Cow::Borrowed(value),
+    };
+
+    Ok(Property { key, value })
+}
+
+fn unescape(inner: &str) -> String {
+    let mut value = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next()),
+            c => value.push(c),
+        }
+    }
+
+    value
+}
+
+#[test]
+fn test_parse_property_string() {
+    let mount = PropertyString::parse(r#"/tank/media,mp="/srv/a,b",backup=1"#).expect("valid");
+
+    assert_eq!(mount.positional(), Some("/tank/media"));
+    assert_eq!(mount.get("mp"), Some("/srv/a,b"));
+    assert_eq!(mount.get("backup"), Some("1"));
+    assert_eq!(mount.get("size"), None);
+
+    let escaped = PropertyString::parse(r#"name=eth0,tag="say \"hi\", \\o/""#).expect("valid");
+
+    assert_eq!(escaped.get("tag"), Some(r#"say "hi", \o/"#));
+    // Empty parts are skipped, like PVE does
+    assert_eq!(PropertyString::parse("a=1,,b=2").expect("valid").properties.len(), 2);
+    assert_eq!(PropertyString::parse("").expect("valid"), PropertyString::default());
+    assert_eq!(
+        PropertyString::parse(r#"mp="/srv"#),
+        Err(PropertyStringError::UnterminatedQuote)
+    );
+    assert_eq!(
+        PropertyString::parse("=1"),
+        Err(PropertyStringError::EmptyKey("=1".into()))
+    );
+    assert_eq!(
+        PropertyString::parse(r#"mp=/srv/"a""#),
+        Err(PropertyStringError::StrayQuote(r#"mp=/srv/"a""#.into()))
+    );
+}
@@ -0,0 +1,273 @@
+//! The keys PVE reads from a container config and what their values look like, as `pct.conf(5)`
+//! documents them. PVE ignores keys it doesn't know, so a typo like `unprivilegd: 1` silently
+//! leaves a container privileged. Raw `lxc.*` keys are passed on to LXC and aren't described here.
+
+use super::property_string::PropertyString;
+
+/// What a value must look like.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValueKind {
+    /// Any of PVE's boolean spellings.
+    Boolean,
+    /// A whole number within `min..=max`.
+    Integer(u64, u64),
+    /// A number which may have a fraction, like `cpulimit: 1.5`.
+    Decimal,
+    Enum(&'static [&'static str]),
+    /// Comma separated `key=value` pairs. The first may leave out its key when the value has a
+    /// default one.
+    PropertyString {
+        default_key: Option<&'static str>,
+    },
+    /// A storage volume like `local-zfs:subvol-100-disk-0` or an absolute host path, followed by
+    /// properties.
+    Volume,
+    Text,
+}
+
+impl ValueKind {
+    /// What a value of this kind looks like, for explanations.
+    pub fn expected(self) -> String {
+        match self {
+            ValueKind::Boolean => "a boolean such as 0 or 1".to_string(),
+            ValueKind::Integer(min, u64::MAX) => format!("a whole number of at least {min}"),
+            ValueKind::Integer(min, max) => format!("a whole number from {min} to {max}"),
+            ValueKind::Decimal => "a number".to_string(),
+            ValueKind::Enum(values) => format!("one of {}", values.join(", ")),
+            ValueKind::PropertyString { .. } => "comma separated key=value pairs".to_string(),
+            ValueKind::Volume => "a storage volume or absolute path, then key=value pairs".to_string(),
+            ValueKind::Text => "any text".to_string(),
+        }
+    }
+
+    fn accepts(self, value: &str) -> bool {
+        let value = value.trim();
+
+        match self {
+            ValueKind::Boolean => matches!(value, "0" | "1" | "no" | "yes" | "off" | "on" | "false" | "true"),
+            ValueKind::Integer(min, max) => value.parse().is_ok_and(|n: u64| (min..=max).contains(&n)),
+            ValueKind::Decimal => value.parse().is_ok_and(|n: f64| n.is_finite() && n >= 0.),
+            ValueKind::Enum(values) => values.contains(&value),
+            ValueKind::PropertyString { default_key } => PropertyString::parse(value).is_ok_and(|properties| {
+                properties
+                    .properties
+                    .iter()
+                    .enumerate()
+                    .all(|(i, property)| property.key.is_some() || (i == 0 && default_key.is_some()))
+            }),
+            ValueKind::Volume => PropertyString::parse(value).is_ok_and(|properties| {
+                let volume = properties.get("volume").or_else(|| properties.positional());
+
+                volume.is_some_and(|volume| {
+                    volume.starts_with('/')
+                        || volume
+                            .split_once(':')
+                            .is_some_and(|(storage, volume)| !storage.is_empty() && !volume.is_empty())
+                }) && properties
+                    .properties
+                    .iter()
+                    .skip(1)
+                    .all(|property| property.key.is_some())
+            }),
+            ValueKind::Text => true,
+        }
+    }
+}
+
+/// A key PVE knows.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KeySchema {
+    pub key: &'static str,
+    /// Whether the key is numbered, like `net0` or `mp12`.
+    pub indexed: bool,
+    pub kind: ValueKind,
+}
+
+const fn key(key: &'static str, kind: ValueKind) -> KeySchema {
+    KeySchema {
+        key,
+        indexed: false,
+        kind,
+    }
+}
+
+const fn indexed(key: &'static str, kind: ValueKind) -> KeySchema {
+    KeySchema {
+        key,
+        indexed: true,
+        kind,
+    }
+}
+
+const PROPERTIES: ValueKind = ValueKind::PropertyString { default_key: None };
+
+const KEYS: &[KeySchema] = &[
+    key(
+        "arch",
+        ValueKind::Enum(&["amd64", "i386", "arm64", "armhf", "riscv32", "riscv64"]),
+    ),
+    key("cmode", ValueKind::Enum(&["shell", "console", "tty"])),
+    key("console", ValueKind::Boolean),
+    key("cores", ValueKind::Integer(1, 8192)),
+    key("cpulimit", ValueKind::Decimal),
+    key("cpuunits", ValueKind::Integer(0, 500000)),
+    key("debug", ValueKind::Boolean),
+    key("description", ValueKind::Text),
+    indexed(
+        "dev",
+        ValueKind::PropertyString {
+            default_key: Some("path"),
+        },
+    ),
+    key("digest", ValueKind::Text),
+    key("entrypoint", ValueKind::Text),
+    key("env", ValueKind::Text),
+    key("features", PROPERTIES),
+    key("hookscript", ValueKind::Text),
+    key("hostname", ValueKind::Text),
+    key(
+        "lock",
+        ValueKind::Enum(&[
+            "backup",
+            "create",
+            "destroyed",
+            "disk",
+            "fstrim",
+            "migrate",
+            "mounted",
+            "rollback",
+            "snapshot",
+            "snapshot-delete",
+        ]),
+    ),
+    key("memory", ValueKind::Integer(16, u64::MAX)),
+    indexed("mp", ValueKind::Volume),
+    key("nameserver", ValueKind::Text),
+    indexed("net", PROPERTIES),
+    key("onboot", ValueKind::Boolean),
+    key(
+        "ostype",
+        ValueKind::Enum(&[
+            "debian",
+            "devuan",
+            "ubuntu",
+            "centos",
+            "fedora",
+            "opensuse",
+            "archlinux",
+            "alpine",
+            "gentoo",
+            "nixos",
+            "unmanaged",
+        ]),
+    ),
+    key("parent", ValueKind::Text),
+    key("protection", ValueKind::Boolean),
+    key("rootfs", ValueKind::Volume),
+    key("searchdomain", ValueKind::Text),
+    key("snaptime", ValueKind::Integer(0, u64::MAX)),
+    key(
+        "startup",
+        ValueKind::PropertyString {
+            default_key: Some("order"),
+        },
+    ),
+    key("swap", ValueKind::Integer(0, u64::MAX)),
+    key("tags", ValueKind::Text),
+    key("template", ValueKind::Boolean),
+    key("timezone", ValueKind::Text),
+    key("tty", ValueKind::Integer(0, 6)),
+    key("unprivileged", ValueKind::Boolean),
+    indexed("unused", ValueKind::Text),
+];
+
+/// Why a config line doesn't fit the schema.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SchemaError {
+    UnknownKey,
+    /// The value isn't what the key expects.
+    Malformed(ValueKind),
+}
+
+/// The schema of `key`, numbered keys like `net0` included.
+pub fn key_schema(key: &str) -> Option<&'static KeySchema> {
+    let name = key.trim_end_matches(|c: char| c.is_ascii_digit());
+    let indexed = name.len() < key.len();
+
+    KEYS.iter()
+        .find(|schema| schema.key == name && schema.indexed == indexed)
+}
+
+/// Checks a PVE config line. Raw `lxc.*` keys are always accepted.
+pub fn validate(key: &str, value: &str) -> Result<(), SchemaError> {
+    if key.starts_with("lxc.") {
+        return Ok(());
+    }
+
+    let schema = key_schema(key).ok_or(SchemaError::UnknownKey)?;
+
+    if schema.kind.accepts(value) {
+        Ok(())
+    } else {
+        Err(SchemaError::Malformed(schema.kind))
+    }
+}
+
+/// The known key `key` was most likely meant to be, if it is only a typo or two away from one.
+pub fn closest_key(key: &str) -> Option<String> {
+    let digits = key.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let name = &key[..key.len() - digits.len()];
+
+    KEYS.iter()
+        .filter(|schema| schema.indexed != digits.is_empty())
+        .map(|schema| (edit_distance(name, schema.key), schema.key))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, closest)| format!("{closest}{digits}"))
+}
+
+/// Levenshtein distance, counting inserted, removed and replaced characters alike.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != *cb);
+
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[test]
+fn test_validate() {
+    assert_eq!(validate("unprivileged", "1"), Ok(()));
+    assert_eq!(validate("unprivilegd", "1"), Err(SchemaError::UnknownKey));
+    assert_eq!(closest_key("unprivilegd").as_deref(), Some("unprivileged"));
+    assert_eq!(closest_key("nett0").as_deref(), Some("net0"));
+    assert_eq!(closest_key("something"), None);
+    assert_eq!(validate("lxc.idmap", "u 0 100000 65536"), Ok(()));
+    assert_eq!(validate("memory", "512"), Ok(()));
+    assert!(validate("memory", "512M").is_err());
+    assert!(validate("tty", "7").is_err());
+    assert_eq!(validate("cpulimit", "1.5"), Ok(()));
+    assert_eq!(validate("net0", "name=eth0,bridge=vmbr0,ip=dhcp,type=veth"), Ok(()));
+    assert!(validate("net0", "eth0,bridge=vmbr0").is_err());
+    assert_eq!(validate("dev0", "/dev/dri/renderD128,gid=104"), Ok(()));
+    assert_eq!(validate("rootfs", "local-zfs:subvol-100-disk-0,size=8G"), Ok(()));
+    assert_eq!(validate("mp0", "/mnt/data,mp=/data"), Ok(()));
+    assert!(validate("rootfs", "subvol-100-disk-0").is_err());
+    assert_eq!(validate("mp1", r#"/mnt/data,mp="/srv/a,b""#), Ok(()));
+    assert!(validate("mp1", r#"/mnt/data,mp="/srv"#).is_err());
+    // Only numbered keys take a number
+    assert_eq!(validate("net", "name=eth0"), Err(SchemaError::UnknownKey));
+    assert_eq!(validate("memory0", "512"), Err(SchemaError::UnknownKey));
+}
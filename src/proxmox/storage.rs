@@ -0,0 +1,270 @@
+//! `/etc/pve/storage.cfg`, which maps storage ids such as `local-zfs` to where their volumes live.
+//!
+//! ```text
+//! zfspool: local-zfs
+//!         pool rpool/data
+//!         content images,rootdir
+//! ```
+
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{WrapErr, eyre};
+
+pub const PVE_STORAGE_CFG: &str = "/etc/pve/storage.cfg";
+
+/// Where LXC mounts the rootfs of running containers.
+const LXC_ROOTFS_MOUNT_PATH: &str = "/var/lib/lxc";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StorageKind {
+    Dir {
+        path: PathBuf,
+    },
+    Btrfs {
+        path: PathBuf,
+    },
+    ZfsPool {
+        pool: String,
+        mountpoint: Option<PathBuf>,
+    },
+    LvmThin {
+        vgname: String,
+        thinpool: String,
+    },
+    /// Any other storage type, by name.
+    Other(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Storage {
+    pub id: String,
+    pub kind: StorageKind,
+}
+
+/// Where a storage volume can be inspected on the host.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VolumePath {
+    /// The backing ZFS dataset, e.g. `rpool/data/subvol-100-disk-0`.
+    pub dataset: Option<String>,
+    pub mountpoint: PathBuf,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StorageConfig {
+    pub storages: Vec<Storage>,
+}
+
+impl Default for StorageConfig {
+    /// The storages a fresh PVE install starts out with, for hosts without a storage.cfg.
+    fn default() -> Self {
+        Self {
+            storages: vec![
+                Storage {
+                    id: "local".to_string(),
+                    kind: StorageKind::Dir {
+                        path: PathBuf::from("/var/lib/vz"),
+                    },
+                },
+                Storage {
+                    id: "local-zfs".to_string(),
+                    kind: StorageKind::ZfsPool {
+                        pool: "rpool/data".to_string(),
+                        mountpoint: None,
+                    },
+                },
+            ],
+        }
+    }
+}
+
+impl StorageConfig {
+    /// Loads `path`, falling back to [`StorageConfig::default`] when it doesn't exist.
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        match read_to_string(path) {
+            Ok(content) => content
+                .parse()
+                .wrap_err_with(|| format!("Failed to parse {}", path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).wrap_err_with(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Storage> {
+        self.storages.iter().find(|storage| storage.id == id)
+    }
+
+    /// Resolves a volume such as `subvol-100-disk-0` on `storage_id` to where it can be inspected.
+    /// Directory-like volumes resolve to themselves. Images and block devices only have a filesystem
+    /// to inspect while the container runs and LXC has it mounted.
+    pub fn resolve_volume(&self, storage_id: &str, volume_id: &str) -> color_eyre::Result<VolumePath> {
+        let storage = self
+            .get(storage_id)
+            .ok_or_else(|| eyre!("storage {storage_id} is not defined in storage.cfg"))?;
+        let (vmid, name) = match volume_id.split_once('/') {
+            Some((vmid, name)) => (vmid, name),
+            None => (volume_id.split('-').nth(1).unwrap_or_default(), volume_id),
+        };
+
+        if vmid.is_empty() || !vmid.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(eyre!("unrecognized volume {volume_id}"));
+        }
+
+        let running_mount = || VolumePath {
+            dataset: None,
+            mountpoint: Path::new(LXC_ROOTFS_MOUNT_PATH).join(vmid).join("rootfs"),
+        };
+
+        Ok(match &storage.kind {
+            StorageKind::Dir { path } | StorageKind::Btrfs { path } if name.starts_with("subvol-") => VolumePath {
+                dataset: None,
+                mountpoint: path.join("images").join(vmid).join(name),
+            },
+            StorageKind::ZfsPool { pool, mountpoint } if name.starts_with("subvol-") => VolumePath {
+                dataset: Some(format!("{pool}/{name}")),
+                mountpoint: mountpoint
+                    .clone()
+                    .unwrap_or_else(|| Path::new("/").join(pool))
+                    .join(name),
+            },
+            StorageKind::Dir { .. } | StorageKind::Btrfs { .. } | StorageKind::ZfsPool { .. } => running_mount(),
+            StorageKind::LvmThin { .. } => running_mount(),
+            StorageKind::Other(kind) => return Err(eyre!("unsupported storage type {kind} of {storage_id}")),
+        })
+    }
+}
+
+/// The `key value` lines of a storage definition.
+type Properties<'a> = Vec<(&'a str, &'a str)>;
+
+impl std::str::FromStr for StorageConfig {
+    type Err = color_eyre::Report;
+
+    fn from_str(content: &str) -> color_eyre::Result<Self> {
+        // Each storage is a `type: id` header followed by indented `key value` properties
+        let mut sections: Vec<(&str, &str, Properties)> = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if !line.starts_with(char::is_whitespace) {
+                let (kind, id) = trimmed
+                    .split_once(':')
+                    .ok_or_else(|| eyre!("line {}: expected `type: id`, found {trimmed}", i + 1))?;
+
+                sections.push((kind.trim(), id.trim(), Vec::new()));
+                continue;
+            }
+
+            let Some((_, _, properties)) = sections.last_mut() else {
+                return Err(eyre!("line {}: property outside of a storage definition", i + 1));
+            };
+
+            properties.push(trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, "")));
+        }
+
+        let storages = sections
+            .into_iter()
+            .map(|(kind, id, properties)| {
+                let property = |key: &str| {
+                    properties
+                        .iter()
+                        .find(|(k, _)| *k == key)
+                        .map(|(_, value)| value.trim().to_string())
+                };
+                let required = |key: &str| property(key).ok_or_else(|| eyre!("storage {id} has no {key} property"));
+                let kind = match kind {
+                    "dir" => StorageKind::Dir {
+                        path: required("path")?.into(),
+                    },
+                    "btrfs" => StorageKind::Btrfs {
+                        path: required("path")?.into(),
+                    },
+                    "zfspool" => StorageKind::ZfsPool {
+                        pool: required("pool")?,
+                        mountpoint: property("mountpoint").map(PathBuf::from),
+                    },
+                    "lvmthin" => StorageKind::LvmThin {
+                        vgname: required("vgname")?,
+                        thinpool: required("thinpool")?,
+                    },
+                    other => StorageKind::Other(other.to_string()),
+                };
+
+                Ok(Storage {
+                    id: id.to_string(),
+                    kind,
+                })
+            })
+            .collect::<color_eyre::Result<_>>()?;
+
+        Ok(StorageConfig { storages })
+    }
+}
+
+#[test]
+fn test_parse_and_resolve() -> color_eyre::Result<()> {
+    let config: StorageConfig = "dir: local\n\
+        \tpath /var/lib/vz\n\
+        \tcontent iso,vztmpl,backup\n\
+        \n\
+        zfspool: local-zfs\n\
+        \tpool rpool/data\n\
+        \tsparse\n\
+        \tcontent images,rootdir\n\
+        \n\
+        zfspool: tank\n\
+        \tpool tank/ct\n\
+        \tmountpoint /mnt/tank/ct\n\
+        \n\
+        lvmthin: local-lvm\n\
+        \tthinpool data\n\
+        \tvgname pve\n\
+        \n\
+        btrfs: fast\n\
+        \tpath /mnt/fast\n\
+        \n\
+        nfs: backups\n\
+        \tserver 10.0.0.2\n"
+        .parse()?;
+
+    assert_eq!(config.storages.len(), 6);
+    assert_eq!(
+        config.resolve_volume("local-zfs", "subvol-100-disk-0")?,
+        VolumePath {
+            dataset: Some("rpool/data/subvol-100-disk-0".into()),
+            mountpoint: "/rpool/data/subvol-100-disk-0".into(),
+        }
+    );
+    assert_eq!(
+        config.resolve_volume("tank", "subvol-101-disk-0")?.mountpoint,
+        PathBuf::from("/mnt/tank/ct/subvol-101-disk-0")
+    );
+    assert_eq!(
+        config.resolve_volume("local", "subvol-102-disk-0")?.mountpoint,
+        PathBuf::from("/var/lib/vz/images/102/subvol-102-disk-0")
+    );
+    assert_eq!(
+        config.resolve_volume("local", "103/vm-103-disk-0.raw")?.mountpoint,
+        PathBuf::from("/var/lib/lxc/103/rootfs")
+    );
+    assert_eq!(
+        config.resolve_volume("local-lvm", "vm-104-disk-0")?.mountpoint,
+        PathBuf::from("/var/lib/lxc/104/rootfs")
+    );
+    assert_eq!(
+        config.resolve_volume("fast", "105/subvol-105-disk-0")?.mountpoint,
+        PathBuf::from("/mnt/fast/images/105/subvol-105-disk-0")
+    );
+    assert!(config.resolve_volume("backups", "vm-106-disk-0").is_err());
+    assert!(config.resolve_volume("missing", "vm-107-disk-0").is_err());
+    assert!(config.resolve_volume("local", "disk").is_err());
+    assert!("dir: local\n\tcontent iso\n".parse::<StorageConfig>().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! Detects the installed Proxmox VE version, as reported by `pveversion`.
+
+use std::fmt::{self, Display};
+use std::process::Command;
+use std::str::FromStr;
+
+use color_eyre::eyre::{WrapErr, eyre};
+
+use crate::linux::command;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct PveVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl PveVersion {
+    /// Runs `pveversion`, which prints e.g. `pve-manager/8.2.4/faa83925c9641325 (running kernel: 6.8.12-1-pve)`.
+    pub fn detect() -> color_eyre::Result<Self> {
+        let output = command::query(&mut Command::new("pveversion")).wrap_err("Failed to execute pveversion")?;
+
+        if !output.status.success() {
+            return Err(eyre!("pveversion command failed"));
+        }
+
+        std::str::from_utf8(&output.stdout)
+            .wrap_err("Failed to parse pveversion output")?
+            .parse()
+    }
+}
+
+impl FromStr for PveVersion {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> color_eyre::Result<Self> {
+        let version = s
+            .trim()
+            .strip_prefix("pve-manager/")
+            .and_then(|rest| rest.split('/').next())
+            .ok_or_else(|| eyre!("unrecognized pveversion output {s:?}"))?;
+        let mut parts = version.split(['.', '-']).map(str::parse::<u32>);
+
+        match (parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor))) => Ok(Self { major, minor }),
+            _ => Err(eyre!("unrecognized PVE version {version}")),
+        }
+    }
+}
+
+impl Display for PveVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[test]
+fn test_parse_pveversion() -> color_eyre::Result<()> {
+    let version: PveVersion = "pve-manager/8.2.4/faa83925c9641325 (running kernel: 6.8.12-1-pve)\n".parse()?;
+
+    assert_eq!(version, PveVersion { major: 8, minor: 2 });
+    assert_eq!(version.to_string(), "8.2");
+    assert_eq!("pve-manager/9.0.3/8b1e1a43".parse::<PveVersion>()?.major, 9);
+    assert!("proxmox-backup-server 3.2".parse::<PveVersion>().is_err());
+
+    Ok(())
+}
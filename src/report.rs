@@ -0,0 +1,374 @@
+//! A report of the host mappings, containers and findings, to share when asking for help, like in a
+//! forum post.
+
+use std::fmt::Write;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+
+use crate::app::state::State;
+use crate::finding::FindingKind;
+use crate::fs::subid::SubID;
+use crate::metadata::Metadata;
+use crate::settings::Settings;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Html => "html",
+        }
+    }
+}
+
+/// A part of the report, which each format writes its own way.
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(u8, String),
+    Paragraph(String),
+    Table(&'static [&'static str], Vec<Vec<String>>),
+    /// Lines quoted from files as they are.
+    Code(Vec<String>),
+}
+
+/// The report of `state` as it was at `now`.
+pub fn report(state: &State, metadata: &Metadata, format: ReportFormat, now: DateTime<Utc>) -> String {
+    let blocks = report_blocks(state, metadata, now);
+
+    match format {
+        ReportFormat::Markdown => markdown(&blocks),
+        ReportFormat::Html => html(&blocks),
+    }
+}
+
+/// Evaluates all findings once and reports on them. Files which failed to load are left out and
+/// their errors returned.
+pub fn report_with(metadata: &Metadata, settings: Settings, format: ReportFormat) -> (String, Vec<color_eyre::Report>) {
+    let (state, errors) = State::collect(metadata, settings);
+
+    (report(&state, metadata, format, Utc::now()), errors)
+}
+
+fn report_blocks(state: &State, metadata: &Metadata, now: DateTime<Utc>) -> Vec<Block> {
+    let mut blocks = vec![
+        Block::Heading(1, "pupman report".to_string()),
+        Block::Paragraph(format!(
+            "Generated by pupman {} on {}, {}.",
+            env!("CARGO_PKG_VERSION"),
+            now.format("%Y-%m-%d %H:%M UTC"),
+            match metadata.pve_version_name() {
+                Some(version) => format!("on Proxmox VE {version}"),
+                None => "on a host without Proxmox VE".to_string(),
+            }
+        )),
+        Block::Heading(2, "Host mappings".to_string()),
+    ];
+
+    for (sub_id, entries, invalid) in [
+        (
+            SubID::UID,
+            &state.host_mapping.subuid,
+            &state.host_mapping.subuid_invalid,
+        ),
+        (
+            SubID::GID,
+            &state.host_mapping.subgid,
+            &state.host_mapping.subgid_invalid,
+        ),
+    ] {
+        blocks.push(Block::Heading(3, sub_id.path().to_string()));
+
+        if entries.is_empty() {
+            blocks.push(Block::Paragraph("No entries.".to_string()));
+        } else {
+            blocks.push(Block::Table(
+                &["Owner", "First id", "Count", "Last id"],
+                entries
+                    .iter()
+                    .map(|entry| {
+                        vec![
+                            entry.host_user_id.to_string(),
+                            entry.host_sub_id.to_string(),
+                            entry.host_sub_id_count.to_string(),
+                            (u64::from(entry.host_sub_id) + u64::from(entry.host_sub_id_count))
+                                .saturating_sub(1)
+                                .to_string(),
+                        ]
+                    })
+                    .collect(),
+            ));
+        }
+
+        if !invalid.is_empty() {
+            blocks.push(Block::Paragraph("Lines which couldn't be read:".to_string()));
+            blocks.push(Block::Code(invalid.iter().map(|line| line.line.to_string()).collect()));
+        }
+    }
+
+    blocks.push(Block::Heading(2, "Containers".to_string()));
+
+    if state.lxc_configs.is_empty() {
+        blocks.push(Block::Paragraph("No container configs were found.".to_string()));
+    }
+
+    for (filename, config) in &state.lxc_configs {
+        let Some(detail) = state.container_detail(filename) else {
+            continue;
+        };
+        let unprivileged = state
+            .dialect
+            .is_unprivileged(&config.section(None), &state.default_idmaps);
+
+        blocks.push(Block::Heading(
+            3,
+            format!("Container {} ({filename})", filename.trim_end_matches(".conf")),
+        ));
+        blocks.push(Block::Paragraph(
+            if unprivileged { "Unprivileged." } else { "Privileged." }.to_string(),
+        ));
+
+        if detail.idmaps.is_empty() {
+            blocks.push(Block::Paragraph("No lxc.idmap entries.".to_string()));
+        } else {
+            blocks.push(Block::Code(
+                detail
+                    .idmaps
+                    .iter()
+                    .map(|idmap| format!("lxc.idmap: {}", idmap.value))
+                    .collect(),
+            ));
+        }
+
+        let (uid, gid) = detail.expected_rootfs_owner();
+        let expected = match (unprivileged, uid, gid) {
+            (true, Some(uid), Some(gid)) => format!(", it should be owned by {uid}:{gid}"),
+            _ => String::new(),
+        };
+
+        blocks.push(Block::Paragraph(match detail.rootfs {
+            None => "No rootfs is set.".to_string(),
+            Some((value, None)) => format!("The rootfs {value} wasn't looked at{expected}."),
+            Some((value, Some((location, rootfs)))) => format!(
+                "The rootfs {value} at {} is owned by {}:{} with mode {:o}{expected}.",
+                location.mountpoint.display(),
+                rootfs.uid(),
+                rootfs.gid(),
+                rootfs.permissions().mode() & 0o7777
+            ),
+        }));
+    }
+
+    let (passed, problems): (Vec<_>, Vec<_>) = state
+        .findings
+        .iter()
+        .partition(|finding| finding.kind == FindingKind::Good);
+
+    blocks.push(Block::Heading(2, "Findings".to_string()));
+
+    if problems.is_empty() {
+        blocks.push(Block::Paragraph("Nothing was found.".to_string()));
+    }
+
+    for finding in problems {
+        let explanation = state.explain(finding, &metadata.lxc_config_dir);
+
+        blocks.push(Block::Heading(
+            3,
+            format!("{}: {}", finding.kind.label(), finding.message),
+        ));
+        blocks.push(Block::Paragraph(format!("Id: {}", finding.id())));
+        blocks.extend(explanation.paragraphs.into_iter().map(Block::Paragraph));
+
+        for excerpt in explanation.offending {
+            blocks.push(Block::Paragraph(format!("In {}:", excerpt.source)));
+            blocks.push(Block::Code(excerpt.lines));
+        }
+
+        if let Some(suggested) = explanation.suggested {
+            blocks.push(Block::Paragraph(format!("Suggested, in {}:", suggested.source)));
+            blocks.push(Block::Code(suggested.lines));
+        }
+
+        if let Some(fix) = finding.fix {
+            blocks.push(Block::Paragraph(format!("Fix: {}", fix.description())));
+        }
+    }
+
+    if !passed.is_empty() {
+        blocks.push(Block::Heading(2, "Passed".to_string()));
+        blocks.push(Block::Table(
+            &["Check", "Result"],
+            passed
+                .iter()
+                .map(|finding| vec![finding.check.name().to_string(), finding.message.to_string()])
+                .collect(),
+        ));
+    }
+
+    if !state.skipped_checks.is_empty() {
+        blocks.push(Block::Heading(2, "Not checked".to_string()));
+        blocks.push(Block::Table(
+            &["Container", "Check", "Why"],
+            state
+                .skipped_checks
+                .iter()
+                .map(|skipped| {
+                    vec![
+                        skipped.filename.to_string(),
+                        skipped.check.name().to_string(),
+                        skipped.reason.description().to_string(),
+                    ]
+                })
+                .collect(),
+        ));
+    }
+
+    blocks
+}
+
+fn markdown(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    let cell = |text: &str| text.replace('|', "\\|");
+
+    for block in blocks {
+        match block {
+            Block::Heading(level, text) => {
+                let _ = writeln!(out, "{} {text}", "#".repeat(usize::from(*level)));
+            },
+            Block::Paragraph(text) => {
+                let _ = writeln!(out, "{text}");
+            },
+            Block::Table(header, rows) => {
+                let _ = writeln!(out, "| {} |", header.join(" | "));
+                let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+
+                for row in rows {
+                    let cells: Vec<_> = row.iter().map(|text| cell(text)).collect();
+                    let _ = writeln!(out, "| {} |", cells.join(" | "));
+                }
+            },
+            Block::Code(lines) => {
+                let _ = writeln!(out, "```\n{}\n```", lines.join("\n"));
+            },
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html(blocks: &[Block]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>pupman report</title>\n</head>\n<body>\n",
+    );
+
+    for block in blocks {
+        match block {
+            Block::Heading(level, text) => {
+                let _ = writeln!(out, "<h{level}>{}</h{level}>", escape_html(text));
+            },
+            Block::Paragraph(text) => {
+                let _ = writeln!(out, "<p>{}</p>", escape_html(text));
+            },
+            Block::Table(header, rows) => {
+                out.push_str("<table>\n<tr>");
+
+                for text in *header {
+                    let _ = write!(out, "<th>{}</th>", escape_html(text));
+                }
+
+                out.push_str("</tr>\n");
+
+                for row in rows {
+                    out.push_str("<tr>");
+
+                    for text in row {
+                        let _ = write!(out, "<td>{}</td>", escape_html(text));
+                    }
+
+                    out.push_str("</tr>\n");
+                }
+
+                out.push_str("</table>\n");
+            },
+            Block::Code(lines) => {
+                let _ = writeln!(out, "<pre>{}</pre>", escape_html(&lines.join("\n")));
+            },
+        }
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[test]
+fn test_report() -> color_eyre::Result<()> {
+    use std::path::Path;
+
+    let mut state = State::default();
+    let metadata = Metadata::default();
+    let now = DateTime::from_timestamp(1_700_000_000, 0).expect("valid timestamp");
+
+    state.load_subid("root:100000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 200000 65536\n",
+    )?;
+    state.evaluate_findings();
+
+    let markdown = report(&state, &metadata, ReportFormat::Markdown, now);
+
+    assert!(markdown.starts_with("# pupman report\n\nGenerated by pupman"));
+    assert!(markdown.contains("### /etc/subuid\n\n| Owner | First id | Count | Last id |\n|---|---|---|---|\n"));
+    assert!(markdown.contains("| root | 100000 | 65536 | 165535 |\n"));
+    assert!(markdown.contains(
+        "### Container 100 (100.conf)\n\nUnprivileged.\n\n```\nlxc.idmap: u 0 100000 65536\n\
+         lxc.idmap: g 0 200000 65536\n```\n"
+    ));
+    assert!(markdown.contains("No rootfs is set."));
+
+    // The gid map reaches past root's subgid range
+    let finding = state
+        .findings
+        .iter()
+        .find(|finding| finding.kind != FindingKind::Good)
+        .expect("a problem");
+
+    assert!(markdown.contains(&format!("Id: {}", finding.id())));
+
+    let html = report(&state, &metadata, ReportFormat::Html, now);
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<h3>Container 100 (100.conf)</h3>\n<p>Unprivileged.</p>\n"));
+    assert!(html.contains("<tr><td>root</td><td>100000</td><td>65536</td><td>165535</td></tr>"));
+    assert!(html.ends_with("</body>\n</html>\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_report_escapes() {
+    let blocks = [
+        Block::Paragraph("<b> & \"quotes\"".to_string()),
+        Block::Table(&["Owner"], vec![vec!["a|b".to_string()]]),
+    ];
+
+    assert_eq!(markdown(&blocks), "<b> & \"quotes\"\n\n| Owner |\n|---|\n| a\\|b |\n\n");
+    assert!(html(&blocks).contains("<p>&lt;b&gt; &amp; &quot;quotes&quot;</p>\n<table>\n<tr><th>Owner</th></tr>\n"));
+}
@@ -0,0 +1,453 @@
+//! `pupman serve --api`: the findings, container details and fixes of a running app over JSON-RPC,
+//! for a web frontend. Calls are `POST /rpc` on a loopback address, with the token from the token
+//! file as a bearer token. Each connection carries a single request.
+//!
+//! Methods:
+//! - `findings.list`: the current findings, as `pupman export --format json` lists them, and
+//!   whether files are still being read.
+//! - `containers.get` with `vmid`: what the detail page shows about a container.
+//! - `fixes.apply` with `finding_id`: applies the default fix of a finding, like `pupman fix --yes`.
+
+use std::fs::{self, OpenOptions, read_to_string};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use color_eyre::eyre::{WrapErr, bail, eyre};
+use log::{info, warn};
+
+use self::rpc::{INTERNAL_ERROR, RpcCall, RpcError, RpcRequest, response};
+use crate::app::event::{AppEvent, Event};
+
+pub mod rpc;
+
+/// Where the API listens unless told otherwise.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7483";
+/// Requests with a longer body are refused, none of the methods take more than an id.
+const MAX_BODY: usize = 64 * 1024;
+/// Request lines and headers are read up to this many bytes, since they are read before the token
+/// is checked.
+const MAX_HEAD: u64 = 16 * 1024;
+const MAX_HEADER_LINES: usize = 64;
+/// Connections past this many are turned away, each one is handled on a thread of its own.
+const MAX_CONNECTIONS: usize = 16;
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a client may take to take in the response.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the app may take to answer, e.g. while a fix runs `usermod`.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub fn default_token_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("pupman").join("api-token"))
+}
+
+/// Reads the API token from `path`, or creates the file with a new random token if it is missing.
+/// A token file other users can read is refused, since the token allows applying fixes as root.
+pub fn load_or_create_token(path: &Path) -> color_eyre::Result<String> {
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                bail!(
+                    "{} can be read by other users, restrict it with chmod 600",
+                    path.display()
+                );
+            }
+
+            let token = read_to_string(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+            let token = token.trim();
+
+            if token.is_empty() {
+                bail!("{} holds no token", path.display());
+            }
+
+            Ok(token.to_string())
+        },
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let mut bytes = [0; 32];
+
+            fs::File::open("/dev/urandom")
+                .and_then(|mut random| random.read_exact(&mut bytes))
+                .wrap_err("Failed to generate an API token")?;
+
+            let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+            }
+
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{token}"))
+                .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+            info!("Created API token in {}", path.display());
+
+            Ok(token)
+        },
+        Err(err) => Err(err).wrap_err_with(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Accepts API calls on `listener` in the background and hands them to the app through `events`.
+pub fn listen(listener: TcpListener, token: String, events: Sender<Event>) {
+    let open = Arc::new(AtomicUsize::new(0));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept an API connection: {err}");
+                    continue;
+                },
+            };
+
+            if let Err(err) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+                warn!("Failed to set up an API connection: {err}");
+                continue;
+            }
+
+            if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                open.fetch_sub(1, Ordering::SeqCst);
+                warn!("Turned away an API connection, {MAX_CONNECTIONS} are open already");
+
+                let _ = write_response(&mut stream, "503 Service Unavailable", "Retry-After: 1\r\n", "");
+                continue;
+            }
+
+            let token = token.clone();
+            let events = events.clone();
+            let open = Arc::clone(&open);
+
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(stream, &token, &events) {
+                    warn!("API connection failed: {err:#}");
+                }
+
+                open.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+}
+
+/// A parsed HTTP request, up to its body.
+struct HttpRequest {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    content_length: Option<usize>,
+}
+
+fn read_head(reader: &mut impl BufRead) -> color_eyre::Result<HttpRequest> {
+    let mut reader = reader.take(MAX_HEAD);
+    let mut line = String::new();
+
+    read_head_line(&mut reader, &mut line)?;
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(eyre!("Malformed request line {line:?}"));
+    };
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        authorization: None,
+        content_length: None,
+    };
+
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        read_head_line(&mut reader, &mut line)?;
+
+        let header = line.trim_end();
+
+        if header.is_empty() {
+            return Ok(request);
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(eyre!("Malformed header {header:?}"));
+        };
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("authorization") {
+            request.authorization = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            request.content_length = Some(value.parse().wrap_err("Malformed Content-Length")?);
+        }
+    }
+
+    Err(eyre!("Too many headers"))
+}
+
+/// Reads a line of the request head into `line`. A line cut off by [`MAX_HEAD`] or by the client
+/// hanging up fails.
+fn read_head_line(reader: &mut impl BufRead, line: &mut String) -> color_eyre::Result<()> {
+    reader.read_line(line)?;
+
+    if !line.ends_with('\n') {
+        return Err(eyre!("Request head is longer than {MAX_HEAD} bytes or cut off"));
+    }
+
+    Ok(())
+}
+
+/// Compares in constant time, so the token can't be guessed a byte at a time from response times.
+fn token_matches(authorization: Option<&str>, token: &str) -> bool {
+    let Some(given) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, extra_headers: &str, body: &str) -> std::io::Result<()> {
+    let content_type = if body.is_empty() {
+        ""
+    } else {
+        "Content-Type: application/json\r\n"
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n{content_type}{extra_headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn handle_connection(mut stream: TcpStream, token: &str, events: &Sender<Event>) -> color_eyre::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let head = read_head(&mut reader)?;
+
+    if head.path != "/rpc" {
+        return Ok(write_response(&mut stream, "404 Not Found", "", "")?);
+    }
+
+    if head.method != "POST" {
+        return Ok(write_response(
+            &mut stream,
+            "405 Method Not Allowed",
+            "Allow: POST\r\n",
+            "",
+        )?);
+    }
+
+    if !token_matches(head.authorization.as_deref(), token) {
+        return Ok(write_response(
+            &mut stream,
+            "401 Unauthorized",
+            "WWW-Authenticate: Bearer\r\n",
+            "",
+        )?);
+    }
+
+    let length = match head.content_length {
+        Some(length) if length <= MAX_BODY => length,
+        Some(_) => return Ok(write_response(&mut stream, "413 Content Too Large", "", "")?),
+        None => return Ok(write_response(&mut stream, "411 Length Required", "", "")?),
+    };
+    let mut body = vec![0; length];
+
+    reader.read_exact(&mut body)?;
+
+    let Ok(body) = String::from_utf8(body) else {
+        return Ok(write_response(&mut stream, "400 Bad Request", "", "")?);
+    };
+    let (id, result) = match RpcRequest::parse(&body) {
+        Ok(request) => {
+            let id = request.id.clone();
+            let (reply, replies) = mpsc::channel();
+
+            events
+                .send(Event::App(AppEvent::Rpc(RpcCall { request, reply })))
+                .map_err(|_| eyre!("pupman is shutting down"))?;
+
+            let result = replies
+                .recv_timeout(REPLY_TIMEOUT)
+                .unwrap_or_else(|_| Err(RpcError::new(INTERNAL_ERROR, "pupman didn't answer in time")));
+
+            // Notifications are carried out, but not answered
+            let Some(id) = id else {
+                return Ok(write_response(&mut stream, "204 No Content", "", "")?);
+            };
+
+            (id, result)
+        },
+        Err((id, err)) => (id, Err(err)),
+    };
+
+    Ok(write_response(&mut stream, "200 OK", "", &response(&id, result))?)
+}
+
+#[test]
+fn test_token_file() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("pupman/api-token");
+    let token = load_or_create_token(&path)?;
+
+    assert_eq!(token.len(), 64);
+    assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+    assert_eq!(load_or_create_token(&path)?, token);
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+
+    assert!(load_or_create_token(&path).is_err());
+    assert!(token_matches(Some(&format!("Bearer {token}")), &token));
+    assert!(!token_matches(Some(&format!("Bearer {}", &token[1..])), &token));
+    assert!(!token_matches(Some(&token), &token));
+    assert!(!token_matches(None, &token));
+
+    Ok(())
+}
+
+#[test]
+fn test_read_head_limits() {
+    let head = |request: String| read_head(&mut BufReader::new(request.as_bytes())).map(|head| head.path);
+
+    assert_eq!(
+        head("POST /rpc HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}".into()).ok(),
+        Some("/rpc".into())
+    );
+    assert!(head(format!("POST /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD as usize))).is_err());
+    assert!(
+        head(format!(
+            "POST /rpc HTTP/1.1\r\n{}\r\n",
+            "X-A: b\r\n".repeat(MAX_HEADER_LINES)
+        ))
+        .is_err()
+    );
+    // The client hung up halfway through
+    assert!(head("POST /rpc HTTP/1.1\r\nHost: localh".into()).is_err());
+}
+
+#[test]
+fn test_serve_api() -> color_eyre::Result<()> {
+    use crate::app::App;
+    use crate::app::event::EventHandler;
+    use crate::metadata::Metadata;
+    use crate::settings::Settings;
+    use std::time::Instant;
+
+    /// How long reading the files, or any single call, may take before the test fails.
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    let root = tempfile::tempdir()?;
+    let lxc_dir = root.path().join("etc/pve/lxc");
+
+    fs::create_dir_all(&lxc_dir)?;
+    fs::write(root.path().join("etc/subuid"), " root:100000:65536\n")?;
+    fs::write(root.path().join("etc/subgid"), "root:100000:65536\n")?;
+    fs::write(
+        lxc_dir.join("100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+
+    let metadata = Metadata::with_root_prefix(root.path().to_path_buf(), None)?;
+    let mut app = App::with_event_handler(metadata, Settings::default(), EventHandler::without_terminal())?;
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    app.initialize()?;
+    listen(listener, "secret".into(), app.event_sender());
+
+    let call = |authorization: &'static str, body: &'static str| {
+        thread::spawn(move || -> std::io::Result<String> {
+            let mut stream = TcpStream::connect(addr)?;
+            let mut response = String::new();
+
+            write!(
+                stream,
+                "POST /rpc HTTP/1.1\r\nHost: localhost\r\nAuthorization: {authorization}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )?;
+            stream.read_to_string(&mut response)?;
+
+            Ok(response)
+        })
+    };
+    let wait = |app: &mut App, client: thread::JoinHandle<std::io::Result<String>>| -> color_eyre::Result<String> {
+        let deadline = Instant::now() + TIMEOUT;
+
+        while !client.is_finished() {
+            assert!(Instant::now() < deadline, "The call wasn't answered in time");
+            app.handle_events_timeout(Duration::from_millis(10))?;
+        }
+
+        Ok(client.join().expect("client doesn't panic")?)
+    };
+    let deadline = Instant::now() + TIMEOUT;
+
+    // Let the files be read first
+    while app.findings().is_empty() {
+        assert!(Instant::now() < deadline, "The files weren't read in time");
+        app.handle_events_timeout(Duration::from_millis(10))?;
+    }
+
+    let denied = wait(&mut app, call("Bearer wrong", "{}"))?;
+
+    assert!(denied.starts_with("HTTP/1.1 401"), "{denied}");
+
+    let listed = wait(
+        &mut app,
+        call("Bearer secret", r#"{"jsonrpc":"2.0","method":"findings.list","id":1}"#),
+    )?;
+
+    assert!(listed.starts_with("HTTP/1.1 200"), "{listed}");
+    assert!(listed.contains("\"check\":\"subid-formatting\""), "{listed}");
+    assert!(listed.ends_with(",\"id\":1}"), "{listed}");
+
+    let detail = wait(
+        &mut app,
+        call(
+            "Bearer secret",
+            r#"{"jsonrpc":"2.0","method":"containers.get","params":{"vmid":100},"id":2}"#,
+        ),
+    )?;
+
+    assert!(detail.contains("\"value\":\"u 0 100000 65536\""), "{detail}");
+
+    let missing = wait(
+        &mut app,
+        call(
+            "Bearer secret",
+            r#"{"jsonrpc":"2.0","method":"containers.get","params":{"vmid":101},"id":3}"#,
+        ),
+    )?;
+
+    assert!(missing.contains("\"code\":-32000"), "{missing}");
+
+    // Files copied with --root-prefix are never written to
+    let refused = wait(
+        &mut app,
+        call(
+            "Bearer secret",
+            r#"{"jsonrpc":"2.0","method":"fixes.apply","params":{"finding_id":"subid-formatting:subuid/root"},"id":4}"#,
+        ),
+    )?;
+
+    assert!(refused.contains("--root-prefix"), "{refused}");
+    assert_eq!(
+        fs::read_to_string(root.path().join("etc/subuid"))?,
+        " root:100000:65536\n"
+    );
+
+    let unknown = wait(
+        &mut app,
+        call("Bearer secret", r#"{"jsonrpc":"2.0","method":"nope","id":5}"#),
+    )?;
+
+    assert!(unknown.contains("\"code\":-32601"), "{unknown}");
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+//! JSON-RPC 2.0 framing of API calls. The methods themselves run on the app's event loop, so they
+//! see the same state the TUI would.
+
+use std::sync::mpsc::Sender;
+
+use serde_json::{Map, Value};
+
+use crate::export::json_string;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The call was understood but couldn't be carried out, like a fix which failed to apply.
+pub const CALL_FAILED: i64 = -32000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcRequest {
+    pub method: String,
+    /// An empty object when the request has none.
+    pub params: Value,
+    /// `None` for notifications, which are carried out without a response.
+    pub id: Option<Value>,
+}
+
+impl RpcRequest {
+    /// Parses the body of a request. A request which can't be made sense of fails with the id to
+    /// answer with, `null` unless one could be read.
+    pub fn parse(body: &str) -> Result<Self, (Value, RpcError)> {
+        let value: Value =
+            serde_json::from_str(body).map_err(|err| (Value::Null, RpcError::new(PARSE_ERROR, err.to_string())))?;
+
+        if matches!(value, Value::Array(_)) {
+            return Err((Value::Null, RpcError::new(INVALID_REQUEST, "Batches are not supported")));
+        }
+
+        let id = value.get("id").cloned();
+        let invalid = |message: &str| {
+            (
+                id.clone().unwrap_or(Value::Null),
+                RpcError::new(INVALID_REQUEST, message),
+            )
+        };
+
+        if !matches!(id, None | Some(Value::Null | Value::Number(_) | Value::String(_))) {
+            return Err((
+                Value::Null,
+                RpcError::new(INVALID_REQUEST, "id must be a number or string"),
+            ));
+        }
+
+        if value.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            return Err(invalid("jsonrpc must be \"2.0\""));
+        }
+
+        let Some(method) = value.get("method").and_then(Value::as_str) else {
+            return Err(invalid("method must be a string"));
+        };
+        let params = match value.get("params") {
+            None => Value::Object(Map::new()),
+            Some(params @ Value::Object(_)) => params.clone(),
+            Some(_) => return Err(invalid("params must be an object")),
+        };
+
+        Ok(RpcRequest {
+            method: method.to_string(),
+            params,
+            id,
+        })
+    }
+
+    /// A string parameter, failing with an error to send back when it is missing.
+    pub fn str_param(&self, name: &str) -> Result<&str, RpcError> {
+        self.params
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("params.{name} must be a string")))
+    }
+
+    /// A parameter holding an id like a vmid, failing with an error to send back when it is missing.
+    pub fn u32_param(&self, name: &str) -> Result<u32, RpcError> {
+        self.params
+            .get(name)
+            .and_then(Value::as_u64)
+            .and_then(|id| u32::try_from(id).ok())
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("params.{name} must be a whole number")))
+    }
+}
+
+/// A request handed to the app, which sends the JSON of its result back through `reply`.
+#[derive(Clone, Debug)]
+pub struct RpcCall {
+    pub request: RpcRequest,
+    pub reply: Sender<Result<String, RpcError>>,
+}
+
+/// The response to the request with `id`, around `result` which is already JSON.
+pub fn response(id: &Value, result: Result<String, RpcError>) -> String {
+    match result {
+        Ok(result) => format!("{{\"jsonrpc\":\"2.0\",\"result\":{result},\"id\":{id}}}"),
+        Err(err) => format!(
+            "{{\"jsonrpc\":\"2.0\",\"error\":{{\"code\":{},\"message\":{}}},\"id\":{id}}}",
+            err.code,
+            json_string(&err.message)
+        ),
+    }
+}
+
+#[test]
+fn test_parse_request() {
+    let request = RpcRequest::parse(r#"{"jsonrpc":"2.0","method":"containers.get","params":{"vmid":100},"id":"a"}"#)
+        .expect("valid request");
+
+    assert_eq!(request.method, "containers.get");
+    assert_eq!(request.u32_param("vmid"), Ok(100));
+    assert_eq!(request.str_param("vmid").unwrap_err().code, INVALID_PARAMS);
+    assert_eq!(request.id, Some(Value::String("a".into())));
+
+    let notification = RpcRequest::parse(r#"{"jsonrpc":"2.0","method":"findings.list"}"#).expect("valid request");
+
+    assert_eq!(notification.id, None);
+    assert_eq!(notification.params, Value::Object(Map::new()));
+
+    for (body, code) in [
+        ("{", PARSE_ERROR),
+        ("[]", INVALID_REQUEST),
+        (r#"{"jsonrpc":"1.0","method":"findings.list","id":1}"#, INVALID_REQUEST),
+        (r#"{"jsonrpc":"2.0","id":1}"#, INVALID_REQUEST),
+        (
+            r#"{"jsonrpc":"2.0","method":"findings.list","params":[1],"id":1}"#,
+            INVALID_REQUEST,
+        ),
+        (r#"{"jsonrpc":"2.0","method":"findings.list","id":{}}"#, INVALID_REQUEST),
+    ] {
+        assert_eq!(RpcRequest::parse(body).unwrap_err().1.code, code, "{body}");
+    }
+
+    // Nesting is limited, so a hostile request can't exhaust the stack
+    assert_eq!(RpcRequest::parse(&"[".repeat(100_000)).unwrap_err().1.code, PARSE_ERROR);
+
+    let (id, err) = RpcRequest::parse(r#"{"jsonrpc":"2.0","id":7}"#).unwrap_err();
+
+    assert_eq!(
+        response(&id, Err(err)),
+        "{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32600,\"message\":\"method must be a string\"},\"id\":7}"
+    );
+    assert_eq!(
+        response(&Value::from(7), Ok("[]".into())),
+        "{\"jsonrpc\":\"2.0\",\"result\":[],\"id\":7}"
+    );
+}
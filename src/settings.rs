@@ -0,0 +1,663 @@
+//! User settings, persisted in `$XDG_CONFIG_HOME/pupman/pupman.conf`.
+//!
+//! The file uses the same `key: value` format as container configs, so it is read and written
+//! through [`Config`] which keeps any comments the user added.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use color_eyre::eyre::WrapErr;
+use log::warn;
+
+use crate::check::Check;
+use crate::fs::subid::SubID;
+use crate::fs::writer::write_atomic;
+use crate::linux::command::CommandPolicy;
+use crate::lxc::config::Config;
+
+const DISABLED_CHECKS: &str = "disabled_checks";
+const INSPECT_ROOTFS: &str = "inspect_rootfs";
+const CHECK_SNAPSHOTS: &str = "check_snapshots";
+const APPLY_MODE: &str = "apply_mode";
+const SORT_ORDER: &str = "sort_order";
+const MAPPING_INTENTS: &str = "mapping_intents";
+const THEME: &str = "theme";
+/// Seconds an external command may take. Only ever set by hand.
+const COMMAND_TIMEOUT: &str = "command_timeout";
+/// How often a hung query like `zfs get` is tried again. Only ever set by hand.
+const COMMAND_RETRIES: &str = "command_retries";
+/// Log lines kept for the logs page. Only ever set by hand.
+const LOG_ENTRIES: &str = "log_entries";
+/// Findings remembered in the finding history. Only ever set by hand.
+const HISTORY_ENTRIES: &str = "history_entries";
+/// Days a finding which went away is remembered for. Only ever set by hand.
+const HISTORY_MAX_AGE: &str = "history_max_age";
+/// Milliseconds file changes are held back for, so a burst of them is read once. Only ever set by
+/// hand.
+const EVENT_DEBOUNCE: &str = "event_debounce";
+
+/// How long file changes are held back for by default. Long enough for an editor to finish saving,
+/// which renames, creates and writes the file in quick succession.
+pub const DEFAULT_EVENT_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// How changes to container configs are written.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ApplyMode {
+    /// Edit the files in the LXC config directory in place.
+    #[default]
+    Direct,
+    /// Go through `pct set <vmid>` where it supports the key, so changes flow through PVE's
+    /// validation and cluster syncing.
+    Pct,
+}
+
+impl ApplyMode {
+    pub fn id(self) -> &'static str {
+        match self {
+            ApplyMode::Direct => "direct",
+            ApplyMode::Pct => "pct",
+        }
+    }
+}
+
+/// How the findings list is ordered. Findings which compare equal are listed most severe first.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SortOrder {
+    #[default]
+    Severity,
+    /// By container id, with host-wide findings first.
+    Container,
+    /// By check, in the order checks are listed in.
+    Check,
+    /// Most recently first seen first.
+    FirstSeen,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 4] = [
+        SortOrder::Severity,
+        SortOrder::Container,
+        SortOrder::Check,
+        SortOrder::FirstSeen,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            SortOrder::Severity => "severity",
+            SortOrder::Container => "container",
+            SortOrder::Check => "check",
+            SortOrder::FirstSeen => "first-seen",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortOrder::Severity => "severity",
+            SortOrder::Container => "container",
+            SortOrder::Check => "check",
+            SortOrder::FirstSeen => "recency",
+        }
+    }
+
+    /// The order after this one, wrapping around.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|order| *order == self).unwrap_or_default();
+
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// The colors and symbols the TUI is drawn with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Theme {
+    /// For terminals with a dark background.
+    #[default]
+    Default,
+    /// For terminals with a light background.
+    Light,
+    /// No colors and only ASCII symbols, for terminals without 256 colors or emoji.
+    Mono,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Default, Theme::Light, Theme::Mono];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Theme::Default => "default",
+            Theme::Light => "light",
+            Theme::Mono => "mono",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Default => "dark terminals",
+            Theme::Light => "light terminals",
+            Theme::Mono => "no colors or emoji",
+        }
+    }
+
+    /// The theme after this one, wrapping around.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|theme| *theme == self).unwrap_or_default();
+
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// How much of the logs and finding history is kept, so a long running session or a host whose
+/// findings churn doesn't grow them forever.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Retention {
+    pub log_entries: usize,
+    /// Findings still present are remembered past this many.
+    pub history_entries: usize,
+    /// How long a finding which went away is remembered for.
+    pub history_max_age: Duration,
+}
+
+impl Retention {
+    pub const DEFAULT: Retention = Retention {
+        // What tui-logger keeps by default
+        log_entries: 10_000,
+        history_entries: 5_000,
+        history_max_age: Duration::from_secs(90 * 24 * 60 * 60),
+    };
+}
+
+/// Which of a container's ids its idmaps are meant to map onto a range of their own.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MappingIntent {
+    /// uids and gids are mapped alike.
+    #[default]
+    Both,
+    /// Only the uid idmaps are generated, gid idmaps are left as they are.
+    UidOnly,
+    /// Only the gid idmaps are generated, e.g. to pass a host group through, and uid idmaps are
+    /// left as they are.
+    GidOnly,
+}
+
+impl MappingIntent {
+    pub const ALL: [MappingIntent; 3] = [MappingIntent::Both, MappingIntent::UidOnly, MappingIntent::GidOnly];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            MappingIntent::Both => "both",
+            MappingIntent::UidOnly => "uid-only",
+            MappingIntent::GidOnly => "gid-only",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MappingIntent::Both => "uids and gids",
+            MappingIntent::UidOnly => "uids only",
+            MappingIntent::GidOnly => "gids only",
+        }
+    }
+
+    /// The kinds of ids which are mapped on purpose.
+    pub fn kinds(self) -> &'static [SubID] {
+        match self {
+            MappingIntent::Both => &[SubID::UID, SubID::GID],
+            MappingIntent::UidOnly => &[SubID::UID],
+            MappingIntent::GidOnly => &[SubID::GID],
+        }
+    }
+
+    /// The intent after this one, wrapping around.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|intent| *intent == self).unwrap_or_default();
+
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Where the settings are saved to, if anywhere.
+    path: Option<PathBuf>,
+    config: Config,
+    disabled_checks: BTreeSet<Check>,
+    /// Whether rootfs directories are stat-ed and watched at all.
+    inspect_rootfs: bool,
+    /// Whether the idmaps of snapshot sections are checked too, not only the live config's.
+    check_snapshots: bool,
+    apply_mode: ApplyMode,
+    sort_order: SortOrder,
+    /// Containers by id which only map one kind of ids on purpose. Others map [`MappingIntent::Both`].
+    mapping_intents: BTreeMap<String, MappingIntent>,
+    command_policy: CommandPolicy,
+    retention: Retention,
+    /// How long changes to the same file are coalesced for before it is read. Zero reads every
+    /// change right away.
+    event_debounce: Duration,
+    theme: Theme,
+    /// The theme picked on the command line, which is used over the saved one but not saved.
+    theme_override: Option<Theme>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            path: None,
+            config: Config::from_str("").expect("empty config is valid"),
+            disabled_checks: BTreeSet::new(),
+            inspect_rootfs: true,
+            check_snapshots: false,
+            apply_mode: ApplyMode::Direct,
+            sort_order: SortOrder::Severity,
+            mapping_intents: BTreeMap::new(),
+            command_policy: CommandPolicy::DEFAULT,
+            retention: Retention::DEFAULT,
+            event_debounce: DEFAULT_EVENT_DEBOUNCE,
+            theme: Theme::Default,
+            theme_override: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("pupman").join("pupman.conf"))
+    }
+
+    /// Loads settings from `path`. A missing file yields default settings which will be saved there.
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        let content = match read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).wrap_err_with(|| format!("Failed to read {}", path.display())),
+        };
+        let mut settings = Self::from_str(&content)?;
+
+        settings.path = Some(path.to_path_buf());
+
+        Ok(settings)
+    }
+
+    /// Loads settings from the default location, falling back to defaults on any error.
+    pub fn load_default() -> Self {
+        let Some(path) = Self::default_path() else {
+            return Self::default();
+        };
+
+        Self::load(&path).unwrap_or_else(|err| {
+            warn!("Failed to load settings, using defaults: {err:?}");
+            Self::default()
+        })
+    }
+
+    pub fn save(&mut self) -> color_eyre::Result<()> {
+        let disabled = self
+            .disabled_checks
+            .iter()
+            .map(|c| c.id())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut section = self.config.section_mut(None);
+
+        section.set(DISABLED_CHECKS, &disabled);
+
+        if self.inspect_rootfs {
+            section.remove_all(INSPECT_ROOTFS);
+        } else {
+            section.set(INSPECT_ROOTFS, "0");
+        }
+
+        if self.check_snapshots {
+            section.set(CHECK_SNAPSHOTS, "1");
+        } else {
+            section.remove_all(CHECK_SNAPSHOTS);
+        }
+
+        match self.apply_mode {
+            ApplyMode::Direct => section.remove_all(APPLY_MODE),
+            mode => section.set(APPLY_MODE, mode.id()),
+        }
+
+        match self.sort_order {
+            SortOrder::Severity => section.remove_all(SORT_ORDER),
+            order => section.set(SORT_ORDER, order.id()),
+        }
+
+        match self.theme {
+            Theme::Default => section.remove_all(THEME),
+            theme => section.set(THEME, theme.id()),
+        }
+
+        let intents = self
+            .mapping_intents
+            .iter()
+            .map(|(vmid, intent)| format!("{vmid}={}", intent.id()))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if intents.is_empty() {
+            section.remove_all(MAPPING_INTENTS);
+        } else {
+            section.set(MAPPING_INTENTS, &intents);
+        }
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        write_atomic(path, &format!("{}\n", self.config))
+    }
+
+    pub fn is_enabled(&self, check: Check) -> bool {
+        !self.disabled_checks.contains(&check)
+    }
+
+    pub fn inspect_rootfs(&self) -> bool {
+        self.inspect_rootfs
+    }
+
+    pub fn set_inspect_rootfs(&mut self, inspect_rootfs: bool) {
+        self.inspect_rootfs = inspect_rootfs;
+    }
+
+    pub fn check_snapshots(&self) -> bool {
+        self.check_snapshots
+    }
+
+    pub fn set_check_snapshots(&mut self, check_snapshots: bool) {
+        self.check_snapshots = check_snapshots;
+    }
+
+    pub fn apply_mode(&self) -> ApplyMode {
+        self.apply_mode
+    }
+
+    pub fn set_apply_mode(&mut self, apply_mode: ApplyMode) {
+        self.apply_mode = apply_mode;
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort_order
+    }
+
+    pub fn set_sort_order(&mut self, sort_order: SortOrder) {
+        self.sort_order = sort_order;
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme_override.unwrap_or(self.theme)
+    }
+
+    /// Picks the theme to save, which also drops any theme picked on the command line.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.theme_override = None;
+    }
+
+    /// Uses `theme` for this session only.
+    pub fn override_theme(&mut self, theme: Theme) {
+        self.theme_override = Some(theme);
+    }
+
+    pub fn theme_overridden(&self) -> bool {
+        self.theme_override.is_some()
+    }
+
+    pub fn command_policy(&self) -> CommandPolicy {
+        self.command_policy
+    }
+
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    pub fn event_debounce(&self) -> Duration {
+        self.event_debounce
+    }
+
+    pub fn mapping_intent(&self, vmid: &str) -> MappingIntent {
+        self.mapping_intents.get(vmid).copied().unwrap_or_default()
+    }
+
+    pub fn set_mapping_intent(&mut self, vmid: &str, intent: MappingIntent) {
+        if intent == MappingIntent::Both {
+            self.mapping_intents.remove(vmid);
+        } else {
+            self.mapping_intents.insert(vmid.to_string(), intent);
+        }
+    }
+
+    pub fn set_enabled(&mut self, check: Check, enabled: bool) {
+        if enabled {
+            self.disabled_checks.remove(&check);
+        } else {
+            self.disabled_checks.insert(check);
+        }
+    }
+}
+
+impl FromStr for Settings {
+    type Err = color_eyre::Report;
+
+    fn from_str(content: &str) -> color_eyre::Result<Self> {
+        let config = Config::from_str(content)?;
+        let mut disabled_checks = BTreeSet::new();
+
+        for id in config
+            .section(None)
+            .get_all(DISABLED_CHECKS)
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+        {
+            match id.parse() {
+                Ok(check) => {
+                    disabled_checks.insert(check);
+                },
+                Err(err) => warn!("Ignoring disabled check: {err}"),
+            }
+        }
+
+        let inspect_rootfs = config.section(None).get(INSPECT_ROOTFS) != Some("0");
+        let check_snapshots = config.section(None).get(CHECK_SNAPSHOTS) == Some("1");
+        let apply_mode = match config.section(None).get(APPLY_MODE) {
+            None | Some("direct") => ApplyMode::Direct,
+            Some("pct") => ApplyMode::Pct,
+            Some(mode) => {
+                warn!("Ignoring unknown apply mode {mode}");
+                ApplyMode::Direct
+            },
+        };
+
+        let sort_order = match config.section(None).get(SORT_ORDER) {
+            None => SortOrder::Severity,
+            Some(id) => SortOrder::ALL
+                .into_iter()
+                .find(|order| order.id() == id)
+                .unwrap_or_else(|| {
+                    warn!("Ignoring unknown sort order {id}");
+                    SortOrder::Severity
+                }),
+        };
+
+        let theme = match config.section(None).get(THEME) {
+            None => Theme::Default,
+            Some(id) => Theme::ALL
+                .into_iter()
+                .find(|theme| theme.id() == id)
+                .unwrap_or_else(|| {
+                    warn!("Ignoring unknown theme {id}");
+                    Theme::Default
+                }),
+        };
+
+        let mut mapping_intents = BTreeMap::new();
+
+        for entry in config
+            .section(None)
+            .get_all(MAPPING_INTENTS)
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let intent = entry.split_once('=').and_then(|(vmid, id)| {
+                let intent = MappingIntent::ALL.into_iter().find(|intent| intent.id() == id.trim())?;
+
+                Some((vmid.trim().to_string(), intent))
+            });
+
+            match intent {
+                Some((vmid, intent)) => {
+                    mapping_intents.insert(vmid, intent);
+                },
+                None => warn!("Ignoring mapping intent {entry}"),
+            }
+        }
+
+        let mut command_policy = CommandPolicy::DEFAULT;
+
+        if let Some(value) = config.section(None).get(COMMAND_TIMEOUT) {
+            match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => command_policy.timeout = Duration::from_secs(secs),
+                _ => warn!("Ignoring command timeout {value}"),
+            }
+        }
+
+        if let Some(value) = config.section(None).get(COMMAND_RETRIES) {
+            match value.parse() {
+                Ok(retries) => command_policy.retries = retries,
+                Err(_) => warn!("Ignoring command retries {value}"),
+            }
+        }
+
+        let mut retention = Retention::DEFAULT;
+
+        for (key, entries) in [
+            (LOG_ENTRIES, &mut retention.log_entries),
+            (HISTORY_ENTRIES, &mut retention.history_entries),
+        ] {
+            if let Some(value) = config.section(None).get(key) {
+                match value.parse() {
+                    Ok(value) if value > 0 => *entries = value,
+                    _ => warn!("Ignoring {key} {value}"),
+                }
+            }
+        }
+
+        if let Some(value) = config.section(None).get(HISTORY_MAX_AGE) {
+            match value.parse::<u64>() {
+                Ok(days) if days > 0 => retention.history_max_age = Duration::from_secs(days * 24 * 60 * 60),
+                _ => warn!("Ignoring history max age {value}"),
+            }
+        }
+
+        let event_debounce = match config.section(None).get(EVENT_DEBOUNCE) {
+            None => DEFAULT_EVENT_DEBOUNCE,
+            Some(value) => value.parse().map(Duration::from_millis).unwrap_or_else(|_| {
+                warn!("Ignoring event debounce {value}");
+                DEFAULT_EVENT_DEBOUNCE
+            }),
+        };
+
+        Ok(Self {
+            path: None,
+            config,
+            disabled_checks,
+            inspect_rootfs,
+            check_snapshots,
+            apply_mode,
+            sort_order,
+            mapping_intents,
+            command_policy,
+            retention,
+            event_debounce,
+            theme,
+            theme_override: None,
+        })
+    }
+}
+
+#[test]
+fn test_settings_load_save() -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("nested").join("pupman.conf");
+
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&path, "# my settings\ndisabled_checks: rootfs-ownership, bogus\n")?;
+
+    let mut settings = Settings::load(&path)?;
+
+    assert!(!settings.is_enabled(Check::RootfsOwnership));
+    assert!(settings.is_enabled(Check::SubidDuplicates));
+    assert!(settings.inspect_rootfs());
+
+    settings.set_enabled(Check::RootfsOwnership, true);
+    settings.set_enabled(Check::SubidFormatting, false);
+    settings.save()?;
+
+    assert_eq!(
+        read_to_string(&path)?,
+        "# my settings\ndisabled_checks: subid-formatting\n"
+    );
+
+    settings.set_inspect_rootfs(false);
+    settings.set_check_snapshots(true);
+    settings.set_apply_mode(ApplyMode::Pct);
+    settings.set_sort_order(SortOrder::Severity.next());
+    settings.set_mapping_intent("101", MappingIntent::GidOnly);
+    settings.set_mapping_intent("100", MappingIntent::UidOnly);
+    settings.set_mapping_intent("100", MappingIntent::Both);
+    settings.set_theme(Theme::Light);
+    settings.override_theme(Theme::Mono);
+    settings.save()?;
+
+    assert!(read_to_string(&path)?.contains("mapping_intents: 101=gid-only\n"));
+    assert!(read_to_string(&path)?.contains("theme: light\n"));
+    assert_eq!(settings.theme(), Theme::Mono);
+
+    let settings = Settings::load(&path)?;
+
+    assert!(!settings.inspect_rootfs());
+    assert!(settings.check_snapshots());
+    assert_eq!(settings.apply_mode(), ApplyMode::Pct);
+    assert_eq!(settings.sort_order(), SortOrder::Container);
+    assert_eq!(SortOrder::FirstSeen.next(), SortOrder::Severity);
+    assert_eq!(settings.mapping_intent("101"), MappingIntent::GidOnly);
+    assert_eq!(settings.mapping_intent("100"), MappingIntent::Both);
+    assert_eq!(settings.theme(), Theme::Light);
+    assert_eq!(settings.command_policy(), CommandPolicy::DEFAULT);
+
+    let settings: Settings = "command_timeout: 30\ncommand_retries: 0\n".parse()?;
+
+    assert_eq!(settings.command_policy().timeout, Duration::from_secs(30));
+    assert_eq!(settings.command_policy().retries, 0);
+    assert_eq!(settings.retention(), Retention::DEFAULT);
+
+    let settings: Settings = "log_entries: 500\nhistory_entries: 0\nhistory_max_age: 7\n".parse()?;
+
+    assert_eq!(settings.retention().log_entries, 500);
+    assert_eq!(settings.retention().history_entries, Retention::DEFAULT.history_entries);
+    assert_eq!(
+        settings.retention().history_max_age,
+        Duration::from_secs(7 * 24 * 60 * 60)
+    );
+    assert_eq!(settings.event_debounce(), DEFAULT_EVENT_DEBOUNCE);
+
+    let settings: Settings = "event_debounce: 0
+"
+    .parse()?;
+
+    assert_eq!(settings.event_debounce(), Duration::ZERO);
+
+    Ok(())
+}
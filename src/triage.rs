@@ -0,0 +1,257 @@
+//! Why won't this unprivileged container start? Correlates the errors LXC logs while starting a
+//! container with the findings about it, so the most likely cause can be fixed first.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+
+use crate::app::state::explain::Explanation;
+use crate::app::state::{State, finding_vmid};
+use crate::check::Check;
+use crate::finding::Finding;
+use crate::metadata::Metadata;
+use crate::settings::Settings;
+
+/// Fragments of LXC start errors and the checks whose findings usually cause them.
+const SIGNATURES: &[(&str, &[Check])] = &[
+    (
+        "newuidmap",
+        &[
+            Check::IdmapHostRange,
+            Check::IdmapCoverage,
+            Check::SubidDuplicates,
+            Check::SubidSymmetry,
+        ],
+    ),
+    (
+        "newgidmap",
+        &[
+            Check::IdmapHostRange,
+            Check::IdmapCoverage,
+            Check::SubidDuplicates,
+            Check::SubidSymmetry,
+        ],
+    ),
+    ("lxc_map_ids", &[Check::IdmapHostRange, Check::IdmapCoverage]),
+    (
+        "idmap",
+        &[Check::IdmapPresent, Check::IdmapCoverage, Check::IdRangeValues],
+    ),
+    ("Read-only file system", &[Check::RootfsWritable]),
+    ("Permission denied", &[Check::RootfsOwnership, Check::MountOwnership]),
+    (
+        "Operation not permitted",
+        &[Check::RootfsOwnership, Check::MountOwnership],
+    ),
+    ("Failed to mount", &[Check::MountOwnership, Check::RootfsOwnership]),
+    ("rootfs", &[Check::RootfsOwnership, Check::RootfsWritable]),
+];
+
+/// A finding which might keep the container from starting.
+#[derive(Clone, Debug)]
+pub struct Suspect {
+    pub finding: Finding,
+    /// Whether the errors of the start attempt point at the finding's check.
+    pub matches_start: bool,
+    /// The finding explained like the TUI's explain popup does.
+    pub explanation: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Triage {
+    pub vmid: u32,
+    /// The errors LXC logged while trying to start the container, if it was tried.
+    pub start_errors: Vec<String>,
+    /// Bad and warning findings about the container or the host's subordinate ids, the most likely
+    /// cause of a failed start first.
+    pub suspects: Vec<Suspect>,
+}
+
+impl Triage {
+    /// The suspect to deal with first, which is the most likely one with an automated fix.
+    pub fn next_fix(&self) -> Option<&Suspect> {
+        self.suspects.iter().find(|suspect| suspect.finding.fix.is_some())
+    }
+}
+
+/// The error messages of an LXC log, without the timestamp and source location prefix.
+pub fn log_errors(log: &str) -> Vec<String> {
+    log.lines()
+        .filter_map(|line| line.split_once(" ERROR "))
+        .map(|(_, rest)| {
+            let rest = rest.trim();
+
+            // `conf - conf.c:lxc_map_ids:3704 - newuidmap failed ...`
+            rest.splitn(3, " - ").nth(2).unwrap_or(rest).to_string()
+        })
+        .collect()
+}
+
+/// The checks whose findings could explain `errors`, most specific first.
+fn suspected_checks(errors: &[String]) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    for (fragment, fragment_checks) in SIGNATURES {
+        if errors.iter().any(|error| error.contains(fragment)) {
+            for check in *fragment_checks {
+                if !checks.contains(check) {
+                    checks.push(*check);
+                }
+            }
+        }
+    }
+
+    checks
+}
+
+fn render_explanation(explanation: &Explanation) -> String {
+    let mut out = explanation.paragraphs.join("\n\n");
+
+    for excerpt in &explanation.offending {
+        let _ = write!(out, "\n\n{}:", excerpt.source);
+
+        for line in &excerpt.lines {
+            let _ = write!(out, "\n    {line}");
+        }
+    }
+
+    if let Some(excerpt) = &explanation.suggested {
+        let _ = write!(out, "\n\nInstead, in {}:", excerpt.source);
+
+        for line in &excerpt.lines {
+            let _ = write!(out, "\n    {line}");
+        }
+    }
+
+    out
+}
+
+/// Ranks the problems of container `vmid` in `state` by how likely they are to keep it from
+/// starting, given the LXC log of an attempt to start it.
+fn diagnose(state: &State, vmid: u32, start_log: Option<&str>, config_dir: &Path) -> Triage {
+    let start_errors = start_log.map(log_errors).unwrap_or_default();
+    let suspected = suspected_checks(&start_errors);
+    let mut suspects: Vec<_> = state
+        .findings
+        .iter()
+        .filter(|finding| finding.kind.is_problem())
+        .filter(|finding| match finding_vmid(&state.lxc_configs, finding) {
+            Some(finding_vmid) => finding_vmid == vmid,
+            // Problems with the host's subordinate ids affect every container
+            None => !finding.host_mapping_highlights.is_empty(),
+        })
+        .map(|finding| Suspect {
+            finding: finding.clone(),
+            matches_start: suspected.contains(&finding.check),
+            explanation: render_explanation(&state.explain(finding, config_dir)),
+        })
+        .collect();
+
+    suspects.sort_by_key(|suspect| {
+        let rank = suspected
+            .iter()
+            .position(|check| *check == suspect.finding.check)
+            .unwrap_or(suspected.len());
+
+        (rank, suspect.finding.kind.sort_order())
+    });
+
+    Triage {
+        vmid,
+        start_errors,
+        suspects,
+    }
+}
+
+/// Runs every check, regardless of which are disabled in `settings`, and triages container `vmid`.
+/// `start_log` is LXC's log of an attempt to start it. Files which failed to load are skipped and
+/// their errors returned.
+pub fn triage_with(
+    metadata: &Metadata,
+    mut settings: Settings,
+    vmid: u32,
+    start_log: Option<&str>,
+) -> color_eyre::Result<(Triage, Vec<color_eyre::Report>)> {
+    for check in Check::ALL {
+        settings.set_enabled(check, true);
+    }
+
+    let (state, errors) = State::collect(metadata, settings);
+
+    if !state.lxc_configs.contains_key(format!("{vmid}.conf").as_str()) {
+        return Err(eyre!(
+            "There is no config for container {vmid} in {}",
+            metadata.lxc_config_dir.display()
+        ));
+    }
+
+    Ok((diagnose(&state, vmid, start_log, &metadata.lxc_config_dir), errors))
+}
+
+#[test]
+fn test_log_errors() {
+    let log = "lxc-start 100 20250101120000.123 INFO     confile - confile.c:set_config_idmaps:2273 - Read uid map\n\
+               lxc-start 100 20250101120000.456 ERROR    conf - conf.c:lxc_map_ids:3704 - newuidmap failed to write mapping \"newuidmap: uid range [0-65536) -> [100000-165536) not allowed\"\n\
+               lxc-start 100 20250101120000.789 ERROR    start - start.c:lxc_spawn:1791 - Failed to set up id mapping.\n";
+    let errors = log_errors(log);
+
+    assert_eq!(
+        errors,
+        [
+            "newuidmap failed to write mapping \"newuidmap: uid range [0-65536) -> [100000-165536) not allowed\"",
+            "Failed to set up id mapping.",
+        ]
+    );
+    assert_eq!(
+        suspected_checks(&errors)[..3],
+        [Check::IdmapHostRange, Check::IdmapCoverage, Check::SubidDuplicates]
+    );
+}
+
+#[test]
+fn test_diagnose() -> color_eyre::Result<()> {
+    use crate::fs::subid::SubID;
+
+    let mut state = State::default();
+
+    state.load_subid("root:100000:65536\nroot:150000:65536\n", SubID::UID)?;
+    state.load_subid("root:100000:65536\n", SubID::GID)?;
+    state.load_config(
+        Path::new("/etc/pve/lxc/100.conf"),
+        "unprivileged: 1\nlxc.idmap: u 0 300000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+    state.load_config(Path::new("/etc/pve/lxc/101.conf"), "unprivileged: 1\n")?;
+    state.evaluate_findings();
+
+    let log = "lxc-start 100 20250101120000.456 ERROR    conf - conf.c:lxc_map_ids:3704 - newuidmap failed to write mapping\n";
+    let triage = diagnose(&state, 100, Some(log), Path::new("/etc/pve/lxc"));
+
+    assert!(!triage.suspects.is_empty());
+    // The idmap outside of root's range is what the start error points at
+    assert_eq!(triage.suspects[0].finding.check, Check::IdmapHostRange);
+    assert!(triage.suspects[0].matches_start);
+    assert!(triage.suspects[0].explanation.contains("lxc.idmap: u 0 300000 65536"));
+    // 101's missing idmaps are someone else's problem, the host's duplicate root isn't
+    assert!(
+        triage
+            .suspects
+            .iter()
+            .all(|suspect| suspect.finding.check != Check::IdmapPresent)
+    );
+    assert!(
+        triage
+            .suspects
+            .iter()
+            .any(|suspect| suspect.finding.check == Check::SubidDuplicates)
+    );
+
+    // Without a start attempt the most severe findings come first
+    let triage = diagnose(&state, 100, None, Path::new("/etc/pve/lxc"));
+
+    assert!(triage.start_errors.is_empty());
+    assert!(triage.suspects.iter().all(|suspect| !suspect.matches_start));
+    assert_eq!(triage.suspects[0].finding.kind, crate::finding::FindingKind::Bad);
+
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! Runs the app against a fake root tree and checks that file changes travel from the monitor
+//! through the reader into the findings.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use pupman::app::App;
+use pupman::app::event::EventHandler;
+use pupman::check::Check;
+use pupman::finding::{Finding, FindingKind};
+use pupman::metadata::Metadata;
+use pupman::settings::Settings;
+use tempfile::TempDir;
+
+/// How long a change may take to show up in the findings before the test fails.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SUBID: &str = "root:100000:65536\n";
+const MAPPED_CONFIG: &str = "unprivileged: 1\nlxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n";
+
+/// A temp directory laid out like the parts of a PVE host pupman reads.
+struct FakeRoot {
+    dir: TempDir,
+}
+
+impl FakeRoot {
+    fn new() -> color_eyre::Result<Self> {
+        let dir = tempfile::tempdir()?;
+
+        fs::create_dir_all(dir.path().join("etc/pve/lxc"))?;
+        fs::write(dir.path().join("etc/subuid"), SUBID)?;
+        fs::write(dir.path().join("etc/subgid"), SUBID)?;
+
+        Ok(Self { dir })
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.dir.path().join(path)
+    }
+
+    fn write(&self, path: &str, content: &str) -> color_eyre::Result<()> {
+        Ok(fs::write(self.path(path), content)?)
+    }
+
+    fn app(&self) -> color_eyre::Result<App> {
+        let metadata = Metadata::with_root_prefix(self.dir.path().to_path_buf(), None)?;
+        let mut app = App::with_event_handler(metadata, Settings::default(), EventHandler::without_terminal())?;
+
+        app.initialize()?;
+
+        Ok(app)
+    }
+}
+
+/// Handles events until the findings satisfy `done`, failing after [`SETTLE_TIMEOUT`].
+fn settle(app: &mut App, what: &str, done: impl Fn(&[Finding]) -> bool) -> color_eyre::Result<()> {
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+
+    while !done(app.findings()) {
+        if Instant::now() > deadline {
+            panic!("Timed out waiting for {what}, findings: {:#?}", app.findings());
+        }
+
+        app.handle_events_timeout(Duration::from_millis(50))?;
+    }
+
+    Ok(())
+}
+
+fn bad_findings_of(findings: &[Finding], check: Check) -> usize {
+    findings
+        .iter()
+        .filter(|f| f.check == check && f.kind == FindingKind::Bad)
+        .count()
+}
+
+fn mentions_config(findings: &[Finding], filename: &str) -> bool {
+    findings.iter().any(|f| {
+        f.lxc_config_mapping_highlights
+            .iter()
+            .any(|(highlighted, _)| highlighted == filename)
+    })
+}
+
+#[test]
+fn test_config_edits_update_findings() -> color_eyre::Result<()> {
+    let root = FakeRoot::new()?;
+
+    root.write("etc/pve/lxc/100.conf", "unprivileged: 1\n")?;
+
+    let mut app = root.app()?;
+
+    settle(&mut app, "missing idmaps to be flagged", |findings| {
+        bad_findings_of(findings, Check::IdmapPresent) == 2
+    })?;
+
+    root.write("etc/pve/lxc/100.conf", MAPPED_CONFIG)?;
+
+    settle(&mut app, "added idmaps to clear the findings", |findings| {
+        bad_findings_of(findings, Check::IdmapPresent) == 0 && !findings.is_empty()
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_include_edits_update_findings() -> color_eyre::Result<()> {
+    let root = FakeRoot::new()?;
+
+    fs::create_dir_all(root.path("etc/lxc"))?;
+    root.write("etc/lxc/common.conf", "")?;
+    // Include paths are those of the copied host, so they are read from under the root prefix
+    root.write(
+        "etc/pve/lxc/100.conf",
+        "unprivileged: 1\nlxc.include: /etc/lxc/common.conf\n",
+    )?;
+
+    let mut app = root.app()?;
+
+    settle(&mut app, "missing idmaps to be flagged", |findings| {
+        bad_findings_of(findings, Check::IdmapPresent) == 2
+    })?;
+
+    root.write(
+        "etc/lxc/common.conf",
+        "lxc.idmap: u 0 100000 65536\nlxc.idmap: g 0 100000 65536\n",
+    )?;
+
+    settle(
+        &mut app,
+        "idmaps added to the include to clear the findings",
+        |findings| bad_findings_of(findings, Check::IdmapPresent) == 0 && !findings.is_empty(),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_new_and_removed_configs() -> color_eyre::Result<()> {
+    let root = FakeRoot::new()?;
+
+    root.write("etc/pve/lxc/100.conf", MAPPED_CONFIG)?;
+
+    let mut app = root.app()?;
+
+    settle(&mut app, "the initial config to load", |findings| !findings.is_empty())?;
+
+    root.write("etc/pve/lxc/101.conf", "unprivileged: 1\n")?;
+
+    settle(&mut app, "the new config to be flagged", |findings| {
+        mentions_config(findings, "101.conf")
+    })?;
+
+    fs::remove_file(root.path("etc/pve/lxc/101.conf"))?;
+
+    settle(&mut app, "the removed config's findings to go", |findings| {
+        !mentions_config(findings, "101.conf")
+    })?;
+
+    // Files other than container configs are ignored
+    root.write("etc/pve/lxc/notes.txt", "unprivileged: 1\n")?;
+    root.write("etc/pve/lxc/102.conf", "unprivileged: 1\n")?;
+
+    settle(&mut app, "the config written after the ignored file", |findings| {
+        mentions_config(findings, "102.conf")
+    })?;
+
+    assert!(!mentions_config(app.findings(), "notes.txt"));
+
+    Ok(())
+}
+
+#[test]
+fn test_subid_edits_update_findings() -> color_eyre::Result<()> {
+    let root = FakeRoot::new()?;
+
+    root.write("etc/pve/lxc/100.conf", MAPPED_CONFIG)?;
+
+    let mut app = root.app()?;
+
+    settle(&mut app, "the idmaps to fit the subuid ranges", |findings| {
+        !findings.is_empty() && bad_findings_of(findings, Check::IdmapHostRange) == 0
+    })?;
+
+    root.write("etc/subuid", "root:200000:65536\n")?;
+
+    settle(&mut app, "the uid idmap to fall outside of /etc/subuid", |findings| {
+        bad_findings_of(findings, Check::IdmapHostRange) == 1
+    })?;
+
+    root.write("etc/subgid", "root:200000:65536\n")?;
+
+    settle(&mut app, "the gid idmap to fall outside of /etc/subgid", |findings| {
+        bad_findings_of(findings, Check::IdmapHostRange) == 2
+    })?;
+
+    root.write("etc/subuid", SUBID)?;
+    root.write("etc/subgid", SUBID)?;
+
+    settle(&mut app, "the restored ranges to clear the findings", |findings| {
+        bad_findings_of(findings, Check::IdmapHostRange) == 0
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_config_dir_remount() -> color_eyre::Result<()> {
+    let root = FakeRoot::new()?;
+
+    root.write("etc/pve/lxc/100.conf", MAPPED_CONFIG)?;
+
+    let mut app = root.app()?;
+
+    settle(&mut app, "the initial config to load", |findings| !findings.is_empty())?;
+
+    // Like pmxcfs going away while pve-cluster restarts
+    fs::remove_dir_all(root.path("etc/pve/lxc"))?;
+
+    settle(&mut app, "the config directory to be missed", |findings| {
+        findings.iter().any(|f| f.check == Check::ConfigDirAvailable)
+    })?;
+
+    fs::create_dir_all(root.path("etc/pve/lxc"))?;
+    root.write("etc/pve/lxc/101.conf", "unprivileged: 1\n")?;
+
+    settle(&mut app, "the config directory to be read again", |findings| {
+        !findings.iter().any(|f| f.check == Check::ConfigDirAvailable) && mentions_config(findings, "101.conf")
+    })?;
+
+    // Watched again, so edits show up
+    root.write("etc/pve/lxc/101.conf", MAPPED_CONFIG)?;
+
+    settle(&mut app, "edits after the remount to show up", |findings| {
+        !mentions_config(findings, "101.conf")
+    })?;
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_subgid_at_startup() -> color_eyre::Result<()> {
+    let root = FakeRoot::new()?;
+
+    // Used to fail watching it and bring the whole app down
+    fs::remove_file(root.path("etc/subgid"))?;
+    root.write("etc/pve/lxc/100.conf", MAPPED_CONFIG)?;
+
+    let mut app = root.app()?;
+
+    settle(
+        &mut app,
+        "the findings to be evaluated without /etc/subgid",
+        |findings| !findings.is_empty(),
+    )?;
+
+    // The config directory is still watched
+    root.write("etc/pve/lxc/101.conf", "unprivileged: 1\n")?;
+
+    settle(&mut app, "the new config to be flagged", |findings| {
+        mentions_config(findings, "101.conf")
+    })?;
+    assert_eq!(bad_findings_of(app.findings(), Check::SubidFiles), 1);
+
+    // Noticed once it is created after all
+    root.write("etc/subgid", "root:100000:65536\n")?;
+
+    settle(&mut app, "the created /etc/subgid to be read", |findings| {
+        bad_findings_of(findings, Check::SubidFiles) == 0
+    })?;
+
+    Ok(())
+}